@@ -0,0 +1,143 @@
+// 处理器和协议的基准测试：经 VirtualFs（WinFsp 和 FUSE 挂载使用的同一层）读写文件、查询属性和列目录。
+// 存储分别为 mem:// 后端，只测处理器本身的开销；以及本机的内存中 httpfs 服务器，加上协议和传输的开销。
+//
+//     cargo bench -p crv-virtual-disk --bench handler
+
+mod support;
+
+use std::time::Duration;
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use crv_virtual_disk::{
+	backend::{self, Remote},
+	vfs::VirtualFs,
+	HttpFsHandler,
+};
+
+use support::MemoryServer;
+
+const FILE_SIZE: usize = 16 * 1024 * 1024;
+// 每次读写的长度，与 Windows 的缓存管理器常用的请求大小相同
+const BLOCK_SIZE: usize = 64 * 1024;
+// 随机读写的偏移按页对齐
+const ALIGN: usize = 4096;
+const DIRECTORY_ENTRIES: usize = 1000;
+
+// 属性缓存关闭，每次查询都到达存储
+fn handler(url: &str) -> HttpFsHandler {
+	HttpFsHandler::new(
+		backend::open(&Remote::new(url)).unwrap(),
+		false,
+		Duration::ZERO,
+	)
+}
+
+fn targets() -> Vec<(&'static str, HttpFsHandler)> {
+	let server = MemoryServer::start();
+	vec![("mem", handler("mem://")), ("http", handler(server.url()))]
+}
+
+// 固定种子的 xorshift，每次运行的偏移序列相同
+struct Offsets(u64);
+
+impl Offsets {
+	fn new() -> Self {
+		Self(0x9e37_79b9_7f4a_7c15)
+	}
+
+	fn next(&mut self) -> u64 {
+		self.0 ^= self.0 << 13;
+		self.0 ^= self.0 >> 7;
+		self.0 ^= self.0 << 17;
+		self.0 % ((FILE_SIZE - BLOCK_SIZE) / ALIGN) as u64 * ALIGN as u64
+	}
+}
+
+fn data_file(fs: &HttpFsHandler) {
+	fs.create("data.bin", false).unwrap();
+	fs.write("data.bin", 0, &vec![0x5a; FILE_SIZE]).unwrap();
+}
+
+fn read(c: &mut Criterion) {
+	let mut group = c.benchmark_group("read");
+	group.throughput(Throughput::Bytes(BLOCK_SIZE as u64));
+	for (name, fs) in targets() {
+		data_file(&fs);
+		let mut buffer = vec![0; BLOCK_SIZE];
+		let mut offset = 0;
+		group.bench_function(BenchmarkId::new("sequential", name), |b| {
+			b.iter(|| {
+				fs.read_into("data.bin", offset, &mut buffer).unwrap();
+				offset = (offset + BLOCK_SIZE as u64) % FILE_SIZE as u64;
+			})
+		});
+		let mut offsets = Offsets::new();
+		group.bench_function(BenchmarkId::new("random", name), |b| {
+			b.iter(|| {
+				fs.read_into("data.bin", offsets.next(), &mut buffer)
+					.unwrap()
+			})
+		});
+	}
+	group.finish();
+}
+
+fn write(c: &mut Criterion) {
+	let mut group = c.benchmark_group("write");
+	group.throughput(Throughput::Bytes(BLOCK_SIZE as u64));
+	let block = vec![0xa5; BLOCK_SIZE];
+	for (name, fs) in targets() {
+		data_file(&fs);
+		let mut offset = 0;
+		group.bench_function(BenchmarkId::new("sequential", name), |b| {
+			b.iter(|| {
+				fs.write("data.bin", offset, &block).unwrap();
+				offset = (offset + BLOCK_SIZE as u64) % FILE_SIZE as u64;
+			})
+		});
+		let mut offsets = Offsets::new();
+		group.bench_function(BenchmarkId::new("random", name), |b| {
+			b.iter(|| fs.write("data.bin", offsets.next(), &block).unwrap())
+		});
+	}
+	group.finish();
+}
+
+fn metadata(c: &mut Criterion) {
+	let mut group = c.benchmark_group("metadata");
+	for (name, fs) in targets() {
+		data_file(&fs);
+		group.throughput(Throughput::Elements(1));
+		group.bench_function(BenchmarkId::new("stat", name), |b| {
+			b.iter(|| fs.stat("data.bin").unwrap())
+		});
+		// 一次创建和一次删除
+		group.throughput(Throughput::Elements(2));
+		group.bench_function(BenchmarkId::new("create_remove", name), |b| {
+			b.iter(|| {
+				fs.create("empty.txt", false).unwrap();
+				fs.remove("empty.txt").unwrap();
+			})
+		});
+	}
+	group.finish();
+}
+
+fn list(c: &mut Criterion) {
+	let mut group = c.benchmark_group("list");
+	group.throughput(Throughput::Elements(DIRECTORY_ENTRIES as u64));
+	for (name, fs) in targets() {
+		fs.create("dir", true).unwrap();
+		for index in 0..DIRECTORY_ENTRIES {
+			fs.create(&format!("dir/file{:04}.txt", index), false)
+				.unwrap();
+		}
+		group.bench_function(BenchmarkId::new("directory", name), |b| {
+			b.iter(|| assert_eq!(fs.list("dir").unwrap().len(), DIRECTORY_ENTRIES))
+		});
+	}
+	group.finish();
+}
+
+criterion_group!(benches, read, write, metadata, list);
+criterion_main!(benches);
@@ -0,0 +1,161 @@
+// 端到端的基准测试：以 Dokan 把内存中的 httpfs 服务器挂载到 HTTPFS_BENCH_MOUNT（默认 R:\），
+// 经 Windows 的文件 API 测量顺序和随机读写的吞吐和延迟、元数据操作和列目录的速度。需要安装 Dokan 驱动。
+//
+//     cargo bench -p crv-virtual-disk --bench mount
+
+mod support;
+
+use std::{
+	env,
+	fs::{self, File, OpenOptions},
+	io::{Read, Seek, SeekFrom, Write},
+	path::Path,
+	process, thread,
+	time::{Duration, Instant},
+};
+
+use crv_virtual_disk::{backend::Remote, Mount, MountHandle};
+
+use support::MemoryServer;
+
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+const BLOCK_SIZE: usize = 64 * 1024;
+const ALIGN: usize = 4096;
+const RANDOM_OPERATIONS: usize = 2000;
+const METADATA_FILES: usize = 500;
+const DIRECTORY_ENTRIES: usize = 1000;
+const LIST_ROUNDS: usize = 20;
+
+// 运行 work 并输出每秒的操作数、每个操作的平均时间，bytes 不为 0 时还输出吞吐
+fn measure(name: &str, operations: usize, bytes: usize, work: impl FnOnce()) {
+	let start = Instant::now();
+	work();
+	let elapsed = start.elapsed().as_secs_f64();
+	let mut line = format!(
+		"{:<24}{:>12.0} ops/s{:>10.1} us/op",
+		name,
+		operations as f64 / elapsed,
+		elapsed * 1e6 / operations as f64
+	);
+	if bytes > 0 {
+		line += &format!("{:>10.1} MiB/s", bytes as f64 / elapsed / (1024.0 * 1024.0));
+	}
+	println!("{}", line);
+}
+
+// 固定种子的 xorshift，每次运行的偏移序列相同
+fn offsets() -> impl Iterator<Item = u64> {
+	let mut state = 0x9e37_79b9_7f4a_7c15_u64;
+	std::iter::repeat_with(move || {
+		state ^= state << 13;
+		state ^= state >> 7;
+		state ^= state << 17;
+		state % ((FILE_SIZE - BLOCK_SIZE) / ALIGN) as u64 * ALIGN as u64
+	})
+}
+
+fn run(root: &Path) -> std::io::Result<()> {
+	let path = root.join("data.bin");
+	let mut block = vec![0x5a; BLOCK_SIZE];
+	let blocks = FILE_SIZE / BLOCK_SIZE;
+
+	let mut file = File::create(&path)?;
+	measure("sequential write", blocks, FILE_SIZE, || {
+		for _ in 0..blocks {
+			file.write_all(&block).unwrap();
+		}
+	});
+	// 关闭时提交暂存的内容
+	measure("close (commit)", 1, FILE_SIZE, || drop(file));
+
+	let mut file = File::open(&path)?;
+	measure("sequential read", blocks, FILE_SIZE, || {
+		for _ in 0..blocks {
+			file.read_exact(&mut block).unwrap();
+		}
+	});
+	measure(
+		"random read",
+		RANDOM_OPERATIONS,
+		RANDOM_OPERATIONS * BLOCK_SIZE,
+		|| {
+			for offset in offsets().take(RANDOM_OPERATIONS) {
+				file.seek(SeekFrom::Start(offset)).unwrap();
+				file.read_exact(&mut block).unwrap();
+			}
+		},
+	);
+	drop(file);
+
+	let mut file = OpenOptions::new().write(true).open(&path)?;
+	measure(
+		"random write",
+		RANDOM_OPERATIONS,
+		RANDOM_OPERATIONS * BLOCK_SIZE,
+		|| {
+			for offset in offsets().take(RANDOM_OPERATIONS) {
+				file.seek(SeekFrom::Start(offset)).unwrap();
+				file.write_all(&block).unwrap();
+			}
+		},
+	);
+	drop(file);
+
+	// 每个文件一次创建、一次查询和一次删除
+	let metadata = root.join("metadata");
+	fs::create_dir(&metadata)?;
+	measure("create/stat/delete", METADATA_FILES * 3, 0, || {
+		for index in 0..METADATA_FILES {
+			let path = metadata.join(format!("file{:04}.txt", index));
+			File::create(&path).unwrap();
+			fs::metadata(&path).unwrap();
+			fs::remove_file(&path).unwrap();
+		}
+	});
+
+	let directory = root.join("dir");
+	fs::create_dir(&directory)?;
+	for index in 0..DIRECTORY_ENTRIES {
+		File::create(directory.join(format!("file{:04}.txt", index)))?;
+	}
+	measure("list (entries)", DIRECTORY_ENTRIES * LIST_ROUNDS, 0, || {
+		for _ in 0..LIST_ROUNDS {
+			assert_eq!(fs::read_dir(&directory).unwrap().count(), DIRECTORY_ENTRIES);
+		}
+	});
+	Ok(())
+}
+
+fn main() {
+	let mount_point = env::var("HTTPFS_BENCH_MOUNT").unwrap_or_else(|_| "R:\\".to_string());
+	let server = MemoryServer::start();
+	let mut mount = Mount::new(Remote::new(server.url()), mount_point.clone());
+	// 服务器没有变更通知
+	mount.events = false;
+
+	dokan::init();
+	let handle = MountHandle::spawn(mount, |_| {});
+	while !handle.is_mounted() {
+		if handle.is_finished() {
+			let error = handle
+				.wait()
+				.err()
+				.map_or("the mount ended".to_string(), |e| e.to_string());
+			eprintln!("mounting {} failed: {}", mount_point, error);
+			dokan::shutdown();
+			process::exit(1);
+		}
+		thread::sleep(Duration::from_millis(50));
+	}
+	println!("mounted {} at {}", server.url(), mount_point);
+
+	let result = run(Path::new(&mount_point));
+	if let Err(e) = handle.unmount_and_wait() {
+		eprintln!("unmounting {} failed: {}", mount_point, e);
+	}
+	dokan::shutdown();
+	if let Err(e) = result {
+		eprintln!("the benchmark failed: {}", e);
+		process::exit(1);
+	}
+}
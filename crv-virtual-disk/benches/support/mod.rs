@@ -0,0 +1,230 @@
+// 基准测试共用的内存中的 httpfs 服务器：文件保存在 mem:// 后端中，只实现客户端挂载和读写用到的路由，
+// 每个连接一个线程并保持连接。测得的是客户端和协议的开销，不受服务器磁盘的影响
+
+#![allow(dead_code)]
+
+use std::{
+	collections::HashMap,
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{TcpListener, TcpStream},
+	sync::Arc,
+	thread,
+};
+
+use crv_virtual_disk::{
+	backend::{self, Remote},
+	error::RemoteError,
+	StorageBackend, TimesUpdate,
+};
+use percent_encoding::percent_decode_str;
+use serde_json::{json, Value};
+
+// 服务器声明支持的功能，其余功能客户端改用通用的做法
+const FEATURES: [&str; 3] = ["ranges", "paging", "times"];
+
+pub struct MemoryServer {
+	url: String,
+}
+
+impl MemoryServer {
+	/// 在本机的随机端口上启动服务器，进程结束时停止。
+	pub fn start() -> Self {
+		let backend: Arc<dyn StorageBackend> =
+			Arc::from(backend::open(&Remote::new("mem://")).unwrap());
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let url = format!("http://{}", listener.local_addr().unwrap());
+		thread::spawn(move || {
+			for stream in listener.incoming().flatten() {
+				let backend = backend.clone();
+				thread::spawn(move || {
+					let _ = serve(stream, backend.as_ref());
+				});
+			}
+		});
+		Self { url }
+	}
+
+	pub fn url(&self) -> &str {
+		&self.url
+	}
+}
+
+struct Request {
+	method: String,
+	route: String,
+	path: String,
+	query: HashMap<String, String>,
+	body: Vec<u8>,
+}
+
+impl Request {
+	fn param<T: std::str::FromStr>(&self, name: &str) -> Option<T> {
+		self.query.get(name).and_then(|value| value.parse().ok())
+	}
+
+	fn json(&self) -> Value {
+		serde_json::from_slice(&self.body).unwrap_or_default()
+	}
+}
+
+fn decode(text: &str) -> String {
+	percent_decode_str(&text.replace('+', " "))
+		.decode_utf8_lossy()
+		.into_owned()
+}
+
+// 读取一个请求，连接关闭时返回 None
+fn read_request(reader: &mut BufReader<TcpStream>) -> io::Result<Option<Request>> {
+	let mut line = String::new();
+	if reader.read_line(&mut line)? == 0 {
+		return Ok(None);
+	}
+	let mut parts = line.split_whitespace();
+	let method = parts.next().unwrap_or_default().to_string();
+	let target = parts.next().unwrap_or_default();
+	let mut length = 0;
+	loop {
+		let mut header = String::new();
+		reader.read_line(&mut header)?;
+		let header = header.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = header.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				length = value.trim().parse().unwrap_or(0);
+			}
+		}
+	}
+	let mut body = vec![0; length];
+	reader.read_exact(&mut body)?;
+
+	let (path, query) = target.split_once('?').unwrap_or((target, ""));
+	let (route, path) = path
+		.trim_start_matches('/')
+		.split_once('/')
+		.unwrap_or((path.trim_start_matches('/'), ""));
+	let path = match decode(path) {
+		path if path.is_empty() || path == "$ROOT" => ".".to_string(),
+		path => path,
+	};
+	let query = query
+		.split('&')
+		.filter_map(|pair| pair.split_once('='))
+		.map(|(name, value)| (decode(name), decode(value)))
+		.collect();
+	Ok(Some(Request {
+		method,
+		route: route.to_string(),
+		path,
+		query,
+		body,
+	}))
+}
+
+fn serve(stream: TcpStream, backend: &dyn StorageBackend) -> io::Result<()> {
+	stream.set_nodelay(true)?;
+	let mut writer = stream.try_clone()?;
+	let mut reader = BufReader::new(stream);
+	while let Some(request) = read_request(&mut reader)? {
+		let (status, content_type, body) = match handle(&request, backend) {
+			Ok(Reply::Empty) => (204, "text/plain", Vec::new()),
+			Ok(Reply::Json(value)) => (200, "application/json", value.to_string().into_bytes()),
+			Ok(Reply::Bytes(data)) => (200, "application/octet-stream", data),
+			Err(e) => {
+				let status = match e.code() {
+					Some("not_found" | "parent_not_found") => 404,
+					Some("already_exists" | "directory_not_empty" | "type_mismatch") => 409,
+					_ => 400,
+				};
+				let error = json!({ "code": e.code().unwrap_or("internal_error"), "message": e.to_string() });
+				(status, "application/json", error.to_string().into_bytes())
+			}
+		};
+		write!(
+			writer,
+			"HTTP/1.1 {} Status\r\nContent-Type: {}\r\nContent-Length: {}\r\n\r\n",
+			status,
+			content_type,
+			body.len()
+		)?;
+		writer.write_all(&body)?;
+	}
+	Ok(())
+}
+
+enum Reply {
+	Empty,
+	Json(Value),
+	Bytes(Vec<u8>),
+}
+
+fn not_found(request: &Request) -> RemoteError {
+	RemoteError::backend(
+		"not_found",
+		format!("no route for {} /{}", request.method, request.route),
+	)
+}
+
+fn handle(request: &Request, backend: &dyn StorageBackend) -> Result<Reply, RemoteError> {
+	let path = request.path.as_str();
+	let flag = |name: &str| request.param::<bool>(name).unwrap_or(false);
+	match (request.method.as_str(), request.route.as_str()) {
+		("GET", "capabilities") => Ok(Reply::Json(json!({
+			"protocol_version": 1,
+			"min_protocol_version": 1,
+			"features": FEATURES,
+		}))),
+		("GET", "info") => Ok(Reply::Json(json!(backend.stat(path)?))),
+		("GET", "list") => {
+			let page = backend.list_page(path, request.query.get("cursor").map(String::as_str))?;
+			Ok(Reply::Json(
+				json!({ "items": page.items, "next_cursor": page.next_cursor }),
+			))
+		}
+		("GET", "read") => {
+			let offset = request.param("offset").unwrap_or(0);
+			let length = request.param("length").unwrap_or(usize::MAX);
+			Ok(Reply::Bytes(backend.read(path, offset, length)?))
+		}
+		("POST", "write") if flag("atomic") => {
+			backend.commit(path, &request.body).map(|()| Reply::Empty)
+		}
+		("POST", "write") => backend
+			.write(path, request.param("offset").unwrap_or(0), &request.body)
+			.map(|()| Reply::Empty),
+		("PUT", "create") => backend
+			.create(path, flag("is_directory"))
+			.map(|()| Reply::Empty),
+		("DELETE", "delete") => backend.delete(path, flag("dry_run")).map(|()| Reply::Empty),
+		("POST", "move") => {
+			let new_path = request.json()["new_path"]
+				.as_str()
+				.unwrap_or_default()
+				.to_string();
+			backend
+				.rename(path, &new_path, flag("replace"))
+				.map(|()| Reply::Empty)
+		}
+		("POST", "truncate") => {
+			let size = request.json()["size"].as_u64().unwrap_or(0);
+			backend.truncate(path, size).map(|()| Reply::Empty)
+		}
+		("POST", "times") => {
+			let times = request.json();
+			let times = TimesUpdate {
+				created: times["created"].as_u64(),
+				accessed: times["accessed"].as_u64(),
+				modified: times["modified"].as_u64(),
+			};
+			backend.set_times(path, &times).map(|()| Reply::Empty)
+		}
+		("GET", "space") => {
+			let space = backend.space()?;
+			Ok(Reply::Json(
+				json!({ "total": space.total, "available": space.available }),
+			))
+		}
+		_ => Err(not_found(request)),
+	}
+}
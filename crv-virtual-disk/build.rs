@@ -0,0 +1,10 @@
+fn main() {
+	// gRPC 后端的客户端代码由 httpfs 服务器的协议定义生成；使用自带的 protoc，不需要另外安装
+	let proto = "../httpfs-server/proto";
+	println!("cargo:rerun-if-changed={}/httpfs.proto", proto);
+	std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+	tonic_build::configure()
+		.build_server(false)
+		.compile_protos(&[format!("{}/httpfs.proto", proto)], &[proto])
+		.unwrap();
+}
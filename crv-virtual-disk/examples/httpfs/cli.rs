@@ -0,0 +1,533 @@
+use std::{net::SocketAddr, path::PathBuf};
+
+use clap::{Args, Parser, Subcommand};
+
+use crv_virtual_disk::{
+	attr_cache::Eviction,
+	compression::Compression,
+	events::ShutdownPolicy,
+	http2::Http2,
+	image::{cache::CacheMode, NewFormat},
+	names::NameEscaping,
+	Driver,
+};
+
+use crate::logging::LogFormat;
+
+#[derive(Debug, Parser)]
+#[command(
+	name = "httpfs",
+	author,
+	about = "Mount a share of an HTTP storage server as a Dokan file system."
+)]
+pub struct Cli {
+	#[command(subcommand)]
+	pub command: Command,
+	/// Log output format; the level is set with the HTTPFS_LOG environment variable (e.g. `debug` traces every file system operation).
+	#[arg(long, global = true, value_enum, default_value = "text")]
+	pub log_format: LogFormat,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+	/// Mount a share (or every profile with --all) and serve it until Ctrl-C or `httpfs unmount`.
+	Mount(MountArgs),
+	/// Register a Windows service that mounts the share at boot, then start it (one service per mount with --all).
+	InstallService(MountArgs),
+	/// Stop and remove the service installed for a mount point.
+	UninstallService {
+		/// Drive letter or mount point given to `install-service`.
+		mount_point: String,
+	},
+	/// Unmount a file system mounted by httpfs.
+	Unmount {
+		/// Drive letter or mount point, e.g. `M` or `M:\`.
+		mount_point: String,
+		/// Refuse new opens, commit staged writes and wait for open files to close (up to --timeout) before removing the drive, instead of an unmount that can wait forever.
+		#[arg(long)]
+		force: bool,
+		/// Seconds to wait for open files to close with --force.
+		#[arg(long, value_name = "SECONDS", default_value_t = 10, requires = "force")]
+		timeout: u64,
+	},
+	/// Show running httpfs mounts on this machine.
+	Status {
+		/// Only show this mount (drive letter or mount point).
+		mount_point: Option<String>,
+		/// Also list the files each mount has open, with the process holding them, to find what keeps a drive busy before unmounting.
+		#[arg(long)]
+		open_files: bool,
+	},
+	/// List saves replayed from the journal that conflicted with changes made in storage meanwhile, and the conflict copies that hold them.
+	Conflicts {
+		/// Drive letter or mount point (defaults to every running mount).
+		mount_point: Option<String>,
+	},
+	/// Show running mounts in the notification area with their health, recent errors and transfer rates.
+	Tray,
+	/// Inspect or clear the attribute cache of a running mount.
+	Cache {
+		#[command(subcommand)]
+		command: CacheCommand,
+	},
+	/// Sign in to the --oauth-issuer provider and save the sign-in for later mounts (run it as the service account for install-service).
+	Login {
+		#[command(flatten)]
+		remote: RemoteArgs,
+	},
+	/// Forget the saved sign-in to the --oauth-issuer provider.
+	Logout {
+		#[command(flatten)]
+		remote: RemoteArgs,
+	},
+	/// Keep server URLs, tokens and encryption passphrases in the system keyring instead of command lines and the mounts file.
+	Credentials {
+		#[command(subcommand)]
+		command: CredentialsCommand,
+	},
+	/// Search the share recursively for names matching PATTERN.
+	Search {
+		#[command(flatten)]
+		remote: RemoteArgs,
+		/// Case-insensitive wildcard pattern (`*` and `?`).
+		pattern: String,
+	},
+	/// Compare files in a local directory with the server's checksums and report differences.
+	Verify {
+		#[command(flatten)]
+		remote: RemoteArgs,
+		/// Local copy to verify.
+		local_dir: String,
+		/// Share directory that LOCAL_DIR mirrors (defaults to the share root).
+		#[arg(long = "remote", value_name = "PATH", default_value = "")]
+		remote_dir: String,
+	},
+	/// Inspect and restore entries in the server's trash.
+	Trash {
+		#[command(flatten)]
+		remote: RemoteArgs,
+		#[command(subcommand)]
+		command: TrashCommand,
+	},
+	/// Show the changes recorded in the server's audit log and check that the log has not been altered.
+	Audit {
+		#[command(flatten)]
+		remote: RemoteArgs,
+		/// Only changes to PATH or below it (including renames to it).
+		#[arg(long, value_name = "PATH")]
+		path: Option<String>,
+		/// Only changes made on behalf of USER.
+		#[arg(long, value_name = "USER")]
+		user: Option<String>,
+		/// Only changes from the last DAYS days.
+		#[arg(long, value_name = "DAYS")]
+		days: Option<u64>,
+		/// Show at most the N most recent changes [default: 1000].
+		#[arg(long, value_name = "N")]
+		limit: Option<usize>,
+	},
+	/// List the partitions of a VHD, VHDX, QCOW2 or raw disk image with their types and file systems.
+	Partitions {
+		/// Disk image file.
+		image: PathBuf,
+	},
+	/// Export a disk image over the NBD protocol so Linux hosts can attach it (e.g. `nbd-client -N NAME HOST /dev/nbd0`).
+	NbdServe {
+		/// VHD, VHDX, QCOW2 or raw disk image: a local file, or a path in the share when --url or --profile is given.
+		image: String,
+		#[command(flatten)]
+		remote: RemoteArgs,
+		/// Address to accept NBD connections on.
+		#[arg(long, value_name = "ADDRESS", default_value = "127.0.0.1:10809")]
+		listen: SocketAddr,
+		/// Name of the export [default: the image file name]; clients asking for the default export get it as well.
+		#[arg(long, value_name = "NAME")]
+		export_name: Option<String>,
+		/// Accept writes; IMAGE must be a local snapshot delta created with `disk-snapshot create`, so the image below it stays unchanged.
+		#[arg(long)]
+		writable: bool,
+	},
+	/// Create, list, merge and discard copy-on-write snapshots of disk images.
+	DiskSnapshot {
+		#[command(subcommand)]
+		command: DiskSnapshotCommand,
+	},
+	/// Create empty disk images and change the size of existing ones.
+	DiskImage {
+		#[command(subcommand)]
+		command: DiskImageCommand,
+	},
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CacheCommand {
+	/// Drop all cached attributes so the next lookups go to the server.
+	Purge {
+		/// Drive letter or mount point (defaults to every running mount).
+		mount_point: Option<String>,
+	},
+	/// Show the hit ratio, the number and memory of cached entries against the limits, and how many were evicted.
+	Stats {
+		/// Drive letter or mount point (defaults to every running mount).
+		mount_point: Option<String>,
+	},
+	/// Free the local copies of files that were downloaded into --offline-cache, turning them back into placeholders fetched on the next read; pinned files are kept.
+	Dehydrate {
+		/// File or directory in the share, e.g. /videos.
+		path: String,
+		/// Drive letter or mount point (defaults to every running mount).
+		mount_point: Option<String>,
+	},
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TrashCommand {
+	/// List deleted entries, most recent first.
+	List,
+	/// Move a deleted entry back into the share.
+	Restore {
+		/// Entry ID shown by `trash list`.
+		id: String,
+		/// Restore to PATH instead of the original location.
+		#[arg(long, value_name = "PATH")]
+		to: Option<String>,
+	},
+}
+
+#[derive(Debug, Subcommand)]
+pub enum CredentialsCommand {
+	/// Save secrets under NAME, asking for each one on the console; fields not asked for keep their saved value.
+	Set {
+		name: String,
+		/// Ask for the server URL (it may contain a user name and password).
+		#[arg(long)]
+		url: bool,
+		/// Ask for the access token.
+		#[arg(long)]
+		token: bool,
+		/// Ask for the client-side encryption passphrase.
+		#[arg(long)]
+		passphrase: bool,
+	},
+	/// List the saved credentials and which fields they hold, without showing the secrets.
+	List,
+	/// Delete the saved credential NAME.
+	Remove { name: String },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DiskSnapshotCommand {
+	/// Create the empty delta DELTA on top of IMAGE; IMAGE must not be modified afterwards, writes go to DELTA.
+	Create {
+		/// VHD, VHDX, QCOW2 or raw disk image, or another delta.
+		image: PathBuf,
+		/// New delta file.
+		delta: PathBuf,
+	},
+	/// Show the deltas below DELTA down to the base image, with the amount of data each one changes.
+	List { delta: PathBuf },
+	/// Write the changes kept in DELTA into the layer below it (another delta or a raw image), then delete DELTA.
+	Merge { delta: PathBuf },
+	/// Delete DELTA and the changes kept in it, going back to the layer below it.
+	Discard { delta: PathBuf },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DiskImageCommand {
+	/// Create an empty disk image of the given size; no blocks are allocated until they are written.
+	Create {
+		/// New image file.
+		image: PathBuf,
+		/// Size of the virtual disk in bytes, or with a K, M, G or T suffix (e.g. 100G).
+		#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+		size: u64,
+		/// Dynamic VHDX, dynamic VHD, QCOW2, or a sparse raw image.
+		#[arg(long, value_enum, default_value = "vhdx")]
+		format: NewFormat,
+		/// Allocation unit: a VHDX block from 1M to 256M [default: 32M], a VHD block from 512K to 256M [default: 2M] or a QCOW2 cluster from 512 to 2M [default: 64K]; powers of two.
+		#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+		block_size: Option<u64>,
+	},
+	/// Convert a disk image to raw, VHD, VHDX or QCOW2; blocks that are all zero stay unallocated in the new image.
+	Convert {
+		/// Image in any supported format; differencing disks, backing files and snapshot deltas are flattened.
+		source: PathBuf,
+		/// New image file.
+		target: PathBuf,
+		/// Format of the new image [default: from the extension of TARGET: .vhdx, .vhd, .qcow2, or .img/.raw/.bin for raw].
+		#[arg(long, value_enum)]
+		format: Option<NewFormat>,
+		/// Allocation unit of the new image, as for `disk-image create`.
+		#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+		block_size: Option<u64>,
+	},
+	/// Grow or shrink a dynamic VHDX or raw image; shrink the partitions inside it first, data beyond the new end is lost.
+	Resize {
+		image: PathBuf,
+		/// New size of the virtual disk in bytes, or with a K, M, G or T suffix.
+		#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+		size: u64,
+	},
+}
+
+// 访问服务器所需的参数，所有与服务器通信的子命令共用；未给出的值可以来自 mounts.toml 中的配置
+#[derive(Debug, Args)]
+pub struct RemoteArgs {
+	/// HTTP storage server URL (e.g., http://localhost:8080), grpc(s)://HOST:PORT for the same server over gRPC, s3://BUCKET[/PREFIX] for an S3-compatible bucket, dav(s)://[USER:PASSWORD@]HOST/PATH for a WebDAV directory, sftp://[USER@]HOST[:PORT]/PATH for a directory on an SSH host, file:///PATH for a local directory, mem:// for a RAM disk, or zip:///PATH or iso:///PATH for a read-only ZIP archive or ISO 9660/UDF image.
+	#[arg(short = 'u', long = "url", value_name = "SERVER_URL")]
+	pub server_url: Option<String>,
+	/// Name of the server share to use (defaults to the server's default share).
+	#[arg(short, long, value_name = "SHARE")]
+	pub share: Option<String>,
+	/// Access token sent to the server as a bearer credential.
+	#[arg(long, value_name = "TOKEN")]
+	pub token: Option<String>,
+	/// Take the server URL, token and encryption passphrase from the credential NAME saved with `credentials set` in the system keyring; --url and --token given as well take precedence.
+	#[arg(long, value_name = "NAME")]
+	pub credential: Option<String>,
+	/// Sign in with this OpenID Connect provider (e.g. https://login.microsoftonline.com/TENANT/v2.0) using the device code flow and send its access tokens instead of --token; the refresh token is kept in the Windows Credential Manager (httpfs servers only).
+	#[arg(
+		long,
+		value_name = "URL",
+		requires = "oauth_client_id",
+		conflicts_with = "token"
+	)]
+	pub oauth_issuer: Option<String>,
+	/// Client ID of httpfs registered as a public client with the --oauth-issuer provider.
+	#[arg(long, value_name = "ID")]
+	pub oauth_client_id: Option<String>,
+	/// Scopes requested when signing in with --oauth-issuer [default: openid offline_access].
+	#[arg(long, value_name = "SCOPES")]
+	pub oauth_scope: Option<String>,
+	/// Transfer compression for large writes and server responses [default: zstd].
+	#[arg(long, value_name = "none|gzip|zstd")]
+	pub compression: Option<Compression>,
+	/// HTTP/2 use towards an httpfs server: auto negotiates it over HTTPS, prior-knowledge speaks it without negotiation (also over plain HTTP), off keeps to HTTP/1.1 [default: auto].
+	#[arg(long, value_name = "auto|prior-knowledge|off")]
+	pub http2: Option<Http2>,
+	/// Experimental: talk to an https:// httpfs server over HTTP/3 (QUIC), falling back to TCP for a while whenever QUIC gets no answer. Needs a build with the http3 feature and a server with http3 enabled.
+	#[arg(long)]
+	pub http3: bool,
+	/// S3 endpoint for s3:// URLs, e.g. http://localhost:9000 for MinIO [default: https://s3.REGION.amazonaws.com].
+	#[arg(long, value_name = "URL")]
+	pub s3_endpoint: Option<String>,
+	/// Region used to sign S3 requests [default: AWS_REGION, or us-east-1].
+	#[arg(long, value_name = "REGION")]
+	pub s3_region: Option<String>,
+	/// Address the bucket as ENDPOINT/BUCKET instead of BUCKET.ENDPOINT (usually needed for MinIO).
+	#[arg(long)]
+	pub s3_path_style: bool,
+	/// Profile of the AWS credentials file to sign S3 requests with [default: environment variables, then AWS_PROFILE or default, then the EC2 instance role].
+	#[arg(long, value_name = "NAME")]
+	pub aws_profile: Option<String>,
+	/// Private key file for sftp:// URLs [default: ssh-agent, then ~/.ssh/id_ed25519, id_ecdsa and id_rsa].
+	#[arg(long, value_name = "FILE")]
+	pub ssh_key: Option<String>,
+	/// Trust the SSH server whose host key has this fingerprint (SHA256:...) instead of checking ~/.ssh/known_hosts.
+	#[arg(long, value_name = "FINGERPRINT")]
+	pub ssh_host_key: Option<String>,
+	/// Capacity of a mem:// volume in bytes, or with a K, M, G or T suffix (e.g. 2G) [default: unlimited].
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	pub mem_capacity: Option<u64>,
+	/// Keep at most this much file content of a mem:// volume in memory and move the least recently used blocks to a temporary file beyond it, in bytes or with a K, M, G or T suffix (e.g. 512M) [default: everything stays in memory].
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	pub mem_limit: Option<u64>,
+	/// Branch, tag or commit of a git:// repository to mount (anything git rev-parse accepts) [default: HEAD].
+	#[arg(long, value_name = "REF")]
+	pub git_ref: Option<String>,
+	/// Partition of a vdisk:// image to mount, numbered from 1 as in its partition table [default: the first partition with a readable file system].
+	#[arg(long, value_name = "N")]
+	pub partition: Option<usize>,
+	/// Cache this much of a vdisk:// image or an nbd-serve image in memory, in bytes or with a K, M, G or T suffix (e.g. 256M), to speed up random I/O on remote images [default: no cache].
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	pub block_cache: Option<u64>,
+	/// Write changes through to the image at once, or keep them in the block cache until a flush [default: write-through].
+	#[arg(long, value_enum, value_name = "MODE")]
+	pub block_cache_mode: Option<CacheMode>,
+	/// Seconds between writes of changed blocks to the image with --block-cache-mode write-back [default: 5].
+	#[arg(long, value_name = "SECONDS")]
+	pub flush_interval: Option<u64>,
+	/// Writable upper layer of an overlay mount (e.g. file:///C:/changes or mem://): all changes go here, while --url and the --lower layers are only read.
+	#[arg(long, value_name = "URL")]
+	pub upper: Option<String>,
+	/// Additional read-only layer below --url in an overlay mount; may be repeated, upper layers first.
+	#[arg(long, value_name = "URL")]
+	pub lower: Vec<String>,
+	/// Encrypt file content on the client before it reaches the storage; the key is derived from the passphrase in HTTPFS_PASSPHRASE or asked on the console.
+	#[arg(long)]
+	pub encrypt: bool,
+	/// Derive the encryption key from this file (at least 32 bytes) instead of a passphrase; implies --encrypt.
+	#[arg(long, value_name = "FILE")]
+	pub key_file: Option<String>,
+	/// Also encrypt file and directory names (implies --encrypt); only takes effect when the storage is first encrypted.
+	#[arg(long)]
+	pub encrypt_names: bool,
+	/// Store files zstd-compressed in 64 KiB chunks; already compressed formats (zip, jpg, mp4...) are kept as they are.
+	#[arg(long)]
+	pub compress_files: bool,
+	/// Further file extension to store uncompressed with --compress-files; may be repeated or comma-separated.
+	#[arg(long, value_name = "EXT", value_delimiter = ',')]
+	pub compress_skip: Vec<String>,
+	/// Split files into content-defined chunks stored once by hash, sharing identical data across files.
+	#[arg(long)]
+	pub dedup: bool,
+	/// When a large file saved as a whole already exists on an httpfs server, upload only the blocks that changed and let the server copy the rest from the old file.
+	#[arg(long)]
+	pub delta_sync: bool,
+	/// Hide directory entries this share's token gives no right to open, as Windows Server access-based enumeration does, so users only see what they can actually open (httpfs servers only).
+	#[arg(long)]
+	pub access_based_enumeration: bool,
+	/// Let an httpfs server whose share is also kept in an object store redirect large reads to short-lived signed URLs, fetching the data from the object store directly (httpfs servers only).
+	#[arg(long)]
+	pub direct_reads: bool,
+	/// Show remote names Windows cannot use (reserved device names like CON, trailing dots or spaces, <>:"\|?* and control characters): hash writes each offending character as #XXXX, private-use maps it to U+F000 plus its code as WSL and Cygwin do [default: none].
+	#[arg(long, value_enum, value_name = "MODE")]
+	pub escape_names: Option<NameEscaping>,
+	/// Take the options not given on the command line from this profile of the mounts file.
+	#[arg(short, long, value_name = "NAME")]
+	pub profile: Option<String>,
+	/// Mounts file with named profiles (defaults to %APPDATA%\httpfs\mounts.toml).
+	#[arg(long, value_name = "FILE")]
+	pub mounts_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct MountArgs {
+	#[command(flatten)]
+	pub remote: RemoteArgs,
+	/// Drive letter (e.g. M:\), `auto` for the first free drive letter, or an empty directory on an NTFS volume.
+	#[arg(short, long, value_name = "MOUNT_POINT")]
+	pub mount_point: Option<String>,
+	/// Mount every profile of the mounts file; other options apply to all of them.
+	#[arg(long, conflicts_with_all = ["profile", "server_url", "mount_point", "service"])]
+	pub all: bool,
+	/// Expose previous file versions kept by the server under a read-only \.snapshots directory.
+	#[arg(long)]
+	pub snapshots: bool,
+	/// How long file attributes seen in directory listings are reused before asking the server again (0 disables) [default: 2].
+	#[arg(long, value_name = "SECONDS")]
+	pub attr_cache_ttl: Option<u64>,
+	/// Keep at most this many entries in the attribute cache [default: no limit].
+	#[arg(long, value_name = "N")]
+	pub attr_cache_max_entries: Option<usize>,
+	/// Keep at most this much memory of attributes in the cache, in bytes or with a K, M, G or T suffix [default: no limit].
+	#[arg(long, value_name = "SIZE", value_parser = parse_size)]
+	pub attr_cache_max_bytes: Option<u64>,
+	/// Which entries make room when the attribute cache is full: least recently used, least frequently used, or only expired ones (new entries are then not cached) [default: lru].
+	#[arg(long, value_enum, value_name = "POLICY")]
+	pub attr_cache_eviction: Option<Eviction>,
+	/// Never evict or expire cached attributes of paths matching this pattern (e.g. /projects/**); they are only dropped on local changes or server notifications. May be repeated or comma-separated.
+	#[arg(long, value_name = "PATTERN", value_delimiter = ',')]
+	pub attr_cache_pin: Vec<String>,
+	/// Do not subscribe to the server's change notifications.
+	#[arg(long)]
+	pub no_events: bool,
+	/// What to do when the server announces a shutdown: unmount shortly before it, or keep the mount and resume afterwards [default: unmount].
+	#[arg(long, value_enum, value_name = "ACTION")]
+	pub on_shutdown_notice: Option<ShutdownPolicy>,
+	/// File system driver: Dokan; WinFsp for machines where the Dokan driver cannot be installed (needs --features winfsp); or ProjFS to project the share into the MOUNT_POINT directory, fetching files on first access and keeping local changes local (needs --features projfs). Only Dokan offers snapshots, alternate data streams and change notifications [default: dokan].
+	#[arg(long, value_enum, value_name = "DRIVER")]
+	pub driver: Option<Driver>,
+	/// TOML file of [[rule]] tables that allow or deny reading, writing, deleting or executing files by path pattern, process name or user SID; checked when a file is opened (Dokan only).
+	#[arg(long, value_name = "FILE")]
+	pub access_rules: Option<PathBuf>,
+	/// TOML file mapping Windows user SIDs to server user names (a [users] table and an optional default); requests for files a user opened carry that user, so the server applies their share permissions. Disables the attribute cache (Dokan and httpfs servers only).
+	#[arg(long, value_name = "FILE")]
+	pub user_map: Option<PathBuf>,
+	/// Send writes of this process (executable name such as sqlservr.exe, wildcards allowed) straight to storage instead of staging new and overwritten files locally until they are closed; may be repeated or comma-separated (Dokan only).
+	#[arg(long, value_name = "PROCESS", value_delimiter = ',')]
+	pub write_through: Vec<String>,
+	/// Always ask storage for the attributes of files opened by this process instead of using the attribute cache; may be repeated or comma-separated (Dokan only).
+	#[arg(long, value_name = "PROCESS", value_delimiter = ',')]
+	pub no_attr_cache_for: Vec<String>,
+	/// Directory for a write-ahead journal of staged saves: every write to a new or overwritten file is written to disk here before it is acknowledged, and saves not committed because the client crashed or storage failed are replayed on the next mount.
+	#[arg(long, value_name = "DIR")]
+	pub journal: Option<PathBuf>,
+	/// Upload files in the background with this many parallel workers once they are closed, instead of making each close wait for its upload; uploads of the same file stay in order (Dokan only).
+	#[arg(long, value_name = "N")]
+	pub upload_workers: Option<usize>,
+	/// Commit closed small files together in one request to an httpfs server instead of one request per file, for copying many tiny files (Dokan only).
+	#[arg(long)]
+	pub pack_small_files: bool,
+	/// Take leases from an httpfs server on opened paths so their cached attributes stay valid until they change or another client opens them for writing, instead of expiring after --attr-cache-ttl.
+	#[arg(long)]
+	pub leases: bool,
+	/// Directory for local copies of pinned paths, so they stay readable while the server is unreachable (Dokan only).
+	#[arg(long, value_name = "DIR")]
+	pub offline_cache: Option<PathBuf>,
+	/// Always keep this file or directory of the share on this device: it is downloaded into --offline-cache and kept up to date; may be repeated or comma-separated.
+	#[arg(
+		long,
+		value_name = "PATH",
+		value_delimiter = ',',
+		requires = "offline_cache"
+	)]
+	pub pin: Vec<String>,
+	/// Detect Office-style saves (write a temporary file, rename the original away, rename the temporary file over it, delete the original) and commit them as one atomic replace with a single version entry, using the default temporary file patterns (*.tmp).
+	#[arg(long)]
+	pub atomic_saves: bool,
+	/// Name pattern of temporary files written during a save, such as ~$*.tmp (wildcards allowed); implies --atomic-saves and replaces the default patterns; may be repeated or comma-separated.
+	#[arg(long, value_name = "PATTERN", value_delimiter = ',')]
+	pub atomic_save_pattern: Vec<String>,
+	/// Force a single thread.
+	#[arg(short = 't', long)]
+	pub single_thread: bool,
+	/// Enable Dokan's debug output.
+	#[arg(short, long)]
+	pub dokan_debug: bool,
+	/// Longest time Dokan waits for a single operation before unmounting the volume [default: 15].
+	#[arg(long, value_name = "SECONDS")]
+	pub dokan_timeout: Option<u64>,
+	/// Allocation unit size reported for the volume; a multiple of the sector size.
+	#[arg(long, value_name = "BYTES")]
+	pub allocation_unit_size: Option<u32>,
+	/// Sector size reported for the volume; a power of two from 512 to 4096 [default: 512].
+	#[arg(long, value_name = "BYTES")]
+	pub sector_size: Option<u32>,
+	/// Mount the volume read-only.
+	#[arg(long)]
+	pub write_protect: bool,
+	/// Register the volume with the Windows mount manager (visible to all sessions).
+	#[arg(long)]
+	pub mount_manager: bool,
+	/// Report the volume as a removable drive.
+	#[arg(long)]
+	pub removable: bool,
+	/// Present the mount as a network drive (requires the Dokan network provider).
+	#[arg(long, conflicts_with = "mount_manager")]
+	pub network: bool,
+	/// UNC name for the network redirector, e.g. \\server\share [default with --network: \\httpfs\SHARE].
+	#[arg(long, value_name = "NAME")]
+	pub unc_name: Option<String>,
+	/// Serve Prometheus metrics of the mount at http://ADDR/metrics (e.g., 127.0.0.1:9101).
+	#[arg(long, value_name = "ADDR")]
+	pub metrics_addr: Option<SocketAddr>,
+	/// Run under the Windows service control manager (set by `install-service`).
+	#[arg(long, hide = true)]
+	pub service: bool,
+}
+
+// 字节数，可以带二进制单位后缀 K、M、G、T（可选的 B 或 iB），如 512M、2GiB
+pub fn parse_size(text: &str) -> Result<u64, String> {
+	let text = text.trim();
+	let upper = text.to_ascii_uppercase();
+	let digits = upper.trim_end_matches("IB").trim_end_matches('B');
+	let (number, shift) = match digits.chars().last() {
+		Some('K') => (&digits[..digits.len() - 1], 10),
+		Some('M') => (&digits[..digits.len() - 1], 20),
+		Some('G') => (&digits[..digits.len() - 1], 30),
+		Some('T') => (&digits[..digits.len() - 1], 40),
+		_ => (digits, 0),
+	};
+	number
+		.trim()
+		.parse::<u64>()
+		.ok()
+		.and_then(|number| number.checked_mul(1 << shift))
+		.ok_or_else(|| {
+			format!(
+				"invalid size '{}'; use a number of bytes or a K, M, G or T suffix",
+				text
+			)
+		})
+}
@@ -0,0 +1,36 @@
+use std::io;
+
+use clap::ValueEnum;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt::format::FmtSpan, EnvFilter};
+
+// 日志级别和过滤规则的环境变量，语法与 RUST_LOG 相同
+const LOG_ENV: &str = "HTTPFS_LOG";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum LogFormat {
+	Text,
+	Json,
+}
+
+// 初始化输出到标准错误的日志，默认级别为 info。每个 Dokan 回调在 debug 级别有一个 span（路径、偏移、长度和结果状态），
+// 启用时在 span 结束时输出一行并附带耗时
+pub fn init(format: LogFormat, ansi: bool) {
+	let filter = EnvFilter::builder()
+		.with_default_directive(LevelFilter::INFO.into())
+		.with_env_var(LOG_ENV)
+		.from_env_lossy();
+	let builder = tracing_subscriber::fmt()
+		.with_env_filter(filter)
+		.with_span_events(FmtSpan::CLOSE)
+		.with_target(false)
+		.with_writer(io::stderr);
+	match format {
+		LogFormat::Text => builder.with_ansi(ansi).init(),
+		LogFormat::Json => builder
+			.json()
+			.with_current_span(true)
+			.with_span_list(false)
+			.init(),
+	}
+}
@@ -0,0 +1,760 @@
+mod cli;
+mod logging;
+mod mounts;
+mod service;
+mod tray;
+
+#[cfg(all(unix, feature = "fuse"))]
+use std::thread;
+use std::{
+	net::TcpListener,
+	path::Path,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use clap::{Parser, ValueEnum};
+use crv_virtual_disk::{
+	access::Right, attr_cache::CacheStats, auth::OAuth, backend, control, credentials::Credentials,
+	error, image, metrics::MetricsSnapshot, nbd, open_files::OpenFile, verify, AuditFilter, Mount,
+	MountEvent, MountHandle,
+};
+#[cfg(all(unix, feature = "fuse"))]
+use crv_virtual_disk::{fuse, mount::connect};
+use dokan::{init, shutdown, unmount};
+use tracing::error;
+use widestring::U16CString;
+
+use crate::cli::{
+	CacheCommand, Cli, Command, CredentialsCommand, DiskImageCommand, DiskSnapshotCommand,
+	TrashCommand,
+};
+
+// 在控制台中挂载，每个挂载在单独的线程中运行，按下 Ctrl-C 时全部卸载
+fn mount_interactive(mounts: Vec<Mount>) -> Result<(), Box<dyn std::error::Error>> {
+	init();
+	let count = mounts.len();
+	let handles: Arc<Vec<MountHandle>> = Arc::new(
+		mounts
+			.into_iter()
+			.map(|mount| {
+				let mount_point = mount.mount_point.clone();
+				MountHandle::spawn(mount, move |event| {
+					if event == MountEvent::Mounted {
+						println!("\nHTTP file system is mounted, press Ctrl-C or run `httpfs unmount {}` to unmount.", mount_point);
+					}
+				})
+			})
+			.collect(),
+	);
+	let handlers = handles.clone();
+	ctrlc::set_handler(move || {
+		for handle in handlers.iter().filter(|handle| handle.is_mounted()) {
+			if handle.unmount() {
+				println!("File system on {} will unmount...", handle.mount_point())
+			} else {
+				error!(mount_point = %handle.mount_point(), "failed to unmount file system");
+			}
+		}
+	})
+	.expect("failed to set Ctrl-C handler");
+
+	let failures: Vec<String> = handles
+		.iter()
+		.filter_map(|handle| {
+			handle
+				.wait()
+				.err()
+				.map(|e| format!("{}: {}", handle.mount_point(), e))
+		})
+		.collect();
+	shutdown();
+
+	match failures.len() {
+		0 => Ok(()),
+		1 => Err(failures[0].clone().into()),
+		_ => {
+			for failure in &failures {
+				error!("{}", failure);
+			}
+			Err(format!("{} of {} mounts failed", failures.len(), count).into())
+		}
+	}
+}
+
+// 在 Linux 和 macOS 上以 FUSE 挂载，每个挂载在单独的线程中运行，用 fusermount -u 或 umount 卸载。
+// 只使用存储后端：没有变更通知、控制管道和指标，--dokan-* 参数被忽略
+#[cfg(all(unix, feature = "fuse"))]
+fn mount_fuse(mounts: Vec<Mount>) -> Result<(), Box<dyn std::error::Error>> {
+	let mut threads = Vec::new();
+	for args in mounts {
+		let handler = connect(
+			&args.remote,
+			false,
+			Duration::from_secs(args.attr_cache_ttl),
+		)?;
+		println!("HTTP File System");
+		println!("  Server: {}", args.remote.server_url);
+		println!("  Mount:  {}", args.mount_point);
+		threads.push(thread::spawn(move || {
+			fuse::mount(handler, Path::new(&args.mount_point))
+		}));
+	}
+	for thread in threads {
+		thread.join().unwrap()?;
+	}
+	Ok(())
+}
+
+// 要管理的挂载：指定挂载点时只有它，否则为本机上所有运行中的挂载
+fn control_targets(mount_point: Option<&str>) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+	let ids = match mount_point {
+		Some(mount_point) => vec![control::mount_id(mount_point)],
+		None => control::running_mounts()?,
+	};
+	if ids.is_empty() {
+		return Err("no httpfs mount is running".into());
+	}
+	Ok(ids)
+}
+
+// 向运行中的挂载发送控制命令，挂载不存在时给出可读的错误
+fn control_send(
+	id: &str,
+	request: &control::Request,
+) -> Result<control::Response, Box<dyn std::error::Error>> {
+	match control::send(id, request) {
+		Ok(control::Response::Error { message }) => Err(format!("{}: {}", id, message).into()),
+		Ok(response) => Ok(response),
+		Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+			Err(format!("no httpfs mount is running on {}", id).into())
+		}
+		Err(e) => Err(format!("{}: {}", id, e).into()),
+	}
+}
+
+fn print_cache_stats(id: &str, stats: &CacheStats) {
+	let lookups = stats.hits + stats.misses;
+	let ratio = if lookups == 0 {
+		0.0
+	} else {
+		stats.hits as f64 * 100.0 / lookups as f64
+	};
+	println!(
+		"{}\t{} entries\tttl {}s\t{} hits, {} misses ({:.1}% hit ratio)",
+		id, stats.entries, stats.ttl_secs, stats.hits, stats.misses, ratio
+	);
+	let limit = |max: Option<String>| max.map_or_else(String::new, |max| format!(" of {}", max));
+	println!(
+		"\toccupancy {}{} entries, {}{}\t{} pinned\t{} evicted ({})",
+		stats.entries,
+		limit(stats.max_entries.map(|max| max.to_string())),
+		human_bytes(stats.bytes),
+		limit(stats.max_bytes.map(human_bytes)),
+		stats.pinned,
+		stats.evictions,
+		stats
+			.eviction
+			.to_possible_value()
+			.map_or_else(String::new, |value| value.get_name().to_string())
+	);
+}
+
+// 挂载以来的传输量、每种回调的次数、速率、错误数和平均耗时，以及最近的错误
+fn print_metrics(metrics: &MetricsSnapshot) {
+	println!(
+		"  transfer\tread {}, written {}",
+		human_bytes(metrics.bytes_read),
+		human_bytes(metrics.bytes_written)
+	);
+	let uptime = metrics.uptime_secs.max(1.0);
+	for (op, stats) in &metrics.ops {
+		println!(
+			"  {:<24}{:>10} ops {:>9.1}/s {:>8} errors  avg {:.1} ms",
+			op,
+			stats.count,
+			stats.count as f64 / uptime,
+			stats.errors,
+			stats.mean_latency().as_secs_f64() * 1000.0
+		);
+	}
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs());
+	for recent in &metrics.recent_errors {
+		println!(
+			"  error\t{} ago\t{}\t{} ({:#010x})",
+			humantime_secs(now.saturating_sub(recent.unix_secs)),
+			recent.op,
+			error::describe_status(recent.status),
+			recent.status
+		);
+	}
+}
+
+fn print_open_files(files: &[OpenFile]) {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs());
+	for file in files {
+		let access: Vec<&str> = file
+			.access
+			.iter()
+			.map(|right| match right {
+				Right::Read => "read",
+				Right::Write => "write",
+				Right::Delete => "delete",
+				Right::Execute => "execute",
+			})
+			.collect();
+		println!(
+			"  open\t{}\t{} ({})\t{}\tfor {}\tread {}, written {}",
+			file.path,
+			file.process.as_deref().unwrap_or("?"),
+			file.pid,
+			if access.is_empty() {
+				"attributes".to_string()
+			} else {
+				access.join("+")
+			},
+			humantime_secs(now.saturating_sub(file.opened_unix_secs)),
+			human_bytes(file.bytes_read),
+			human_bytes(file.bytes_written)
+		);
+	}
+}
+
+// credentials set 在控制台询问的一项，不能为空
+fn ask_secret(prompt: &str, echo: bool) -> Result<String, Box<dyn std::error::Error>> {
+	match backend::read_console(prompt, echo) {
+		Some(value) if !value.is_empty() => Ok(value),
+		Some(_) => Err("the value must not be empty".into()),
+		None => Err("credentials set needs a console".into()),
+	}
+}
+
+fn human_bytes(bytes: u64) -> String {
+	const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+	let mut value = bytes as f64;
+	let mut unit = 0;
+	while value >= 1024.0 && unit < UNITS.len() - 1 {
+		value /= 1024.0;
+		unit += 1;
+	}
+	if unit == 0 {
+		format!("{} B", bytes)
+	} else {
+		format!("{:.1} {}", value, UNITS[unit])
+	}
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let cli = Cli::parse();
+	// 服务的输出写入事件日志，不使用终端颜色
+	let service = matches!(&cli.command, Command::Mount(args) if args.service);
+	logging::init(cli.log_format, !service);
+
+	match cli.command {
+		Command::Mount(args) => {
+			let mut mounts = mounts::resolve_mounts(&args)?;
+			if args.service {
+				return Ok(service::run(mounts.remove(0))?);
+			}
+			#[cfg(all(unix, feature = "fuse"))]
+			return mount_fuse(mounts);
+			#[cfg(not(all(unix, feature = "fuse")))]
+			mount_interactive(mounts)
+		}
+		Command::InstallService(args) => {
+			for mount in mounts::resolve_mounts(&args)? {
+				let name = service::install(&mount)?;
+				println!(
+					"Installed and started service {}; it mounts {} at boot.",
+					name, mount.mount_point
+				);
+			}
+			Ok(())
+		}
+		Command::UninstallService { mount_point } => {
+			let name = service::uninstall(&mount_point)?;
+			println!("Stopped and removed service {}.", name);
+			Ok(())
+		}
+		Command::Unmount {
+			mount_point,
+			force,
+			timeout,
+		} => {
+			let request = if force {
+				control::Request::ForceUnmount {
+					timeout_secs: timeout,
+				}
+			} else {
+				control::Request::Unmount
+			};
+			match control_send(&control::mount_id(&mount_point), &request) {
+				Ok(_) => println!("File system on {} will unmount...", mount_point),
+				// 不是由 httpfs 挂载（或来自旧版本）时直接请求 Dokan 卸载
+				Err(e) => {
+					if !unmount(&U16CString::from_str(&mount_point)?) {
+						return Err(e);
+					}
+					println!("File system on {} will unmount...", mount_point);
+				}
+			}
+			Ok(())
+		}
+		Command::Status {
+			mount_point,
+			open_files,
+		} => {
+			for id in control_targets(mount_point.as_deref())? {
+				if let control::Response::Status(status) =
+					control_send(&id, &control::Request::Status)?
+				{
+					let server = match &status.share {
+						Some(share) => format!("{} (share {})", status.server, share),
+						None => status.server.clone(),
+					};
+					println!(
+						"{}\t{}\tup {}\tevents {}\t{} open files",
+						status.mount_point,
+						server,
+						humantime_secs(status.uptime_secs),
+						if status.events { "on" } else { "off" },
+						status.open_files
+					);
+					if status.conflicts > 0 {
+						println!(
+							"  {} conflict copies, see `httpfs conflicts`",
+							status.conflicts
+						);
+					}
+					print_cache_stats("  cache", &status.cache);
+					print_metrics(&status.metrics);
+				}
+				if open_files {
+					if let control::Response::OpenFiles(files) =
+						control_send(&id, &control::Request::OpenFiles)?
+					{
+						print_open_files(&files);
+					}
+				}
+			}
+			Ok(())
+		}
+		Command::Conflicts { mount_point } => {
+			for id in control_targets(mount_point.as_deref())? {
+				if let control::Response::Conflicts(conflicts) =
+					control_send(&id, &control::Request::Conflicts)?
+				{
+					for conflict in &conflicts {
+						println!(
+							"{}\t{}\tkept as {}\t{}",
+							id,
+							conflict.path,
+							conflict.copy,
+							httpdate::fmt_http_date(
+								UNIX_EPOCH + Duration::from_secs(conflict.detected)
+							)
+						);
+					}
+				}
+			}
+			Ok(())
+		}
+		Command::Tray => Ok(tray::run()?),
+		Command::Cache { command } => {
+			let (mount_point, request) = match &command {
+				CacheCommand::Purge { mount_point } => (mount_point, control::Request::CachePurge),
+				CacheCommand::Stats { mount_point } => (mount_point, control::Request::CacheStats),
+				CacheCommand::Dehydrate { path, mount_point } => (
+					mount_point,
+					control::Request::Dehydrate { path: path.clone() },
+				),
+			};
+			for id in control_targets(mount_point.as_deref())? {
+				match control_send(&id, &request)? {
+					control::Response::Purged { entries } => {
+						println!("{}\tpurged {} entries", id, entries)
+					}
+					control::Response::Cache(stats) => print_cache_stats(&id, &stats),
+					control::Response::Dehydrated(freed) => println!(
+						"{}\tfreed {} files, {}",
+						id,
+						freed.files,
+						human_bytes(freed.bytes)
+					),
+					_ => {}
+				}
+			}
+			Ok(())
+		}
+		Command::Login { remote } => {
+			let settings = mounts::resolve_remote(&remote)?
+				.oauth
+				.ok_or("login requires --oauth-issuer")?;
+			OAuth::shared(&settings).sign_in()?;
+			println!("Signed in to {}.", settings.issuer);
+			Ok(())
+		}
+		Command::Logout { remote } => {
+			let settings = mounts::resolve_remote(&remote)?
+				.oauth
+				.ok_or("logout requires --oauth-issuer")?;
+			OAuth::shared(&settings).sign_out()?;
+			println!("Signed out of {}.", settings.issuer);
+			Ok(())
+		}
+		Command::Credentials { command } => match command {
+			CredentialsCommand::Set {
+				name,
+				url,
+				token,
+				passphrase,
+			} => {
+				if !(url || token || passphrase) {
+					return Err("give --url, --token or --passphrase".into());
+				}
+				let mut credentials = Credentials::load(&name)?.unwrap_or_default();
+				if url {
+					credentials.url = Some(ask_secret("Server URL: ", true)?);
+				}
+				if token {
+					credentials.token = Some(ask_secret("Access token: ", false)?);
+				}
+				if passphrase {
+					let value = ask_secret("Encryption passphrase: ", false)?;
+					if ask_secret("Repeat the passphrase: ", false)? != value {
+						return Err("the passphrases do not match".into());
+					}
+					credentials.passphrase = Some(value);
+				}
+				credentials.save(&name)?;
+				println!("Saved credential '{}'.", name);
+				Ok(())
+			}
+			CredentialsCommand::List => {
+				for name in Credentials::names()? {
+					let fields = match Credentials::load(&name) {
+						Ok(Some(credentials)) => {
+							let fields: Vec<&str> = [
+								("url", credentials.url.is_some()),
+								("token", credentials.token.is_some()),
+								("passphrase", credentials.passphrase.is_some()),
+							]
+							.into_iter()
+							.filter_map(|(field, set)| set.then_some(field))
+							.collect();
+							fields.join(", ")
+						}
+						Ok(None) => continue,
+						Err(e) => e.to_string(),
+					};
+					println!("{}\t{}", name, fields);
+				}
+				Ok(())
+			}
+			CredentialsCommand::Remove { name } => {
+				Credentials::remove(&name)?;
+				Ok(())
+			}
+		},
+		Command::Search { remote, pattern } => {
+			let backend = backend::open(&mounts::resolve_remote(&remote)?)?;
+			let response = backend.search(".", &pattern, true)?;
+			for hit in &response.hits {
+				println!("{}", hit.path);
+			}
+			if response.truncated {
+				eprintln!("(more results were omitted)");
+			}
+			Ok(())
+		}
+		Command::Verify {
+			remote,
+			local_dir,
+			remote_dir,
+		} => {
+			let backend = backend::open(&mounts::resolve_remote(&remote)?)?;
+			let divergent = verify::verify(backend.as_ref(), Path::new(&local_dir), &remote_dir)?;
+			if divergent > 0 {
+				return Err(format!("{} file(s) differ from the server", divergent).into());
+			}
+			println!("All files match the server.");
+			Ok(())
+		}
+		Command::Trash { remote, command } => {
+			let backend = backend::open(&mounts::resolve_remote(&remote)?)?;
+			match command {
+				TrashCommand::Restore { id, to } => {
+					let restored = backend.restore_trash(&id, to.as_deref())?;
+					println!("Restored {} to {}", id, restored.path);
+				}
+				TrashCommand::List => {
+					for entry in backend.list_trash()? {
+						let deleted = UNIX_EPOCH + Duration::from_secs(entry.deleted);
+						println!(
+							"{}\t{}\t{}\t{}{}",
+							entry.id,
+							httpdate::fmt_http_date(deleted),
+							entry.size,
+							entry.path,
+							if entry.is_directory { "/" } else { "" }
+						);
+					}
+				}
+			}
+			Ok(())
+		}
+		Command::Audit {
+			remote,
+			path,
+			user,
+			days,
+			limit,
+		} => {
+			let backend = backend::open(&mounts::resolve_remote(&remote)?)?;
+			let now = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |since| since.as_secs());
+			let since = days.map(|days| now.saturating_sub(days * 24 * 60 * 60));
+			let response = backend.audit(&AuditFilter {
+				path,
+				user,
+				since,
+				limit,
+			})?;
+			for entry in &response.entries {
+				let time = UNIX_EPOCH + Duration::from_secs(entry.time);
+				let target = match &entry.new_path {
+					Some(new_path) => format!("{} -> {}", entry.path, new_path),
+					None => entry.path.clone(),
+				};
+				println!(
+					"{}\t{}\t{}\t{}\t{}",
+					entry.seq,
+					httpdate::fmt_http_date(time),
+					entry
+						.user
+						.as_deref()
+						.or(entry.client.as_deref())
+						.unwrap_or("-"),
+					entry.action,
+					target
+				);
+			}
+			match response.broken_at {
+				Some(line) => Err(format!("the audit log was altered at line {}", line).into()),
+				None => {
+					eprintln!("Hash chain verified, head {}", response.head);
+					Ok(())
+				}
+			}
+		}
+		Command::Partitions { image } => {
+			let device = image::open(&image)?;
+			let partitions = image::partitions(device.as_ref())?;
+			if partitions.is_empty() {
+				let file_system =
+					image::file_system(device.as_ref())?.unwrap_or("no known file system");
+				println!(
+					"No partition table; the whole disk ({}) is one volume: {}",
+					human_bytes(device.size()),
+					file_system
+				);
+			}
+			for partition in &partitions {
+				// 超出磁盘末尾的分区读不到引导扇区，不显示文件系统
+				let volume = image::Slice::new(device.clone(), partition.offset, partition.size);
+				let file_system = image::file_system(&volume).ok().flatten().unwrap_or("-");
+				println!(
+					"{}\t{}\t{}\t{}\t{}\t{}",
+					partition.number,
+					partition.offset,
+					human_bytes(partition.size),
+					partition.kind,
+					file_system,
+					partition.name
+				);
+			}
+			Ok(())
+		}
+		Command::NbdServe {
+			image,
+			remote,
+			listen,
+			export_name,
+			writable,
+		} => {
+			// 给出服务器时映像是共享中的文件，否则是本地文件；只有本地的快照差异文件可以写入
+			let (device, partition, cache) =
+				if remote.server_url.is_some() || remote.profile.is_some() {
+					if writable {
+						return Err("--writable only applies to local snapshot deltas".into());
+					}
+					let remote = mounts::resolve_remote(&remote)?;
+					(
+						image::open_in(backend::open(&remote)?.into(), &image)?,
+						remote.partition,
+						remote.block_cache,
+					)
+				} else if writable {
+					(
+						image::open_writable(Path::new(&image))?,
+						remote.partition,
+						mounts::block_cache(&remote)?,
+					)
+				} else {
+					(
+						image::open(Path::new(&image))?,
+						remote.partition,
+						mounts::block_cache(&remote)?,
+					)
+				};
+			let device = match partition {
+				Some(number) => image::open_partition(device, number)?,
+				None => device,
+			};
+			let device = match cache {
+				Some(cache) => image::cache::wrap(device, &cache),
+				None => device,
+			};
+			let name = export_name.unwrap_or_else(|| {
+				image
+					.rsplit(['/', '\\'])
+					.next()
+					.unwrap_or_default()
+					.to_string()
+			});
+			let listener = TcpListener::bind(listen)?;
+			let access = if device.writable() {
+				"read-write"
+			} else {
+				"read-only"
+			};
+			println!(
+				"Exporting {} ({}) {} as NBD export '{}' on {}; press Ctrl-C to stop.",
+				image,
+				human_bytes(device.size()),
+				access,
+				name,
+				listener.local_addr()?
+			);
+			Ok(nbd::serve(
+				listener,
+				Arc::new(nbd::Export { name, device }),
+			)?)
+		}
+		Command::DiskSnapshot { command } => {
+			match command {
+				DiskSnapshotCommand::Create { image, delta } => {
+					image::cow::create(&delta, &image)?;
+					println!(
+						"Created {} on top of {}; {} must not be modified while the snapshot is in use.",
+						delta.display(),
+						image.display(),
+						image.display()
+					);
+				}
+				DiskSnapshotCommand::List { delta } => {
+					let (layers, base) = image::cow::chain(&delta)?;
+					for layer in &layers {
+						println!(
+							"{}\t{} changed",
+							layer.path.display(),
+							human_bytes(layer.changed)
+						);
+					}
+					println!("{}\tbase image", base.display());
+				}
+				DiskSnapshotCommand::Merge { delta } => {
+					let lower = image::cow::merge(&delta)?;
+					println!(
+						"Merged {} into {} and deleted it.",
+						delta.display(),
+						lower.display()
+					);
+				}
+				DiskSnapshotCommand::Discard { delta } => {
+					image::cow::discard(&delta)?;
+					println!("Discarded {}.", delta.display());
+				}
+			}
+			Ok(())
+		}
+		Command::DiskImage { command } => {
+			match command {
+				DiskImageCommand::Create {
+					image,
+					size,
+					format,
+					block_size,
+				} => {
+					image::create(&image, format, size, block_size)?;
+					println!("Created {} ({}).", image.display(), human_bytes(size));
+				}
+				DiskImageCommand::Resize { image, size } => {
+					let old_size = image::resize(&image, size)?;
+					println!(
+						"Resized {} from {} to {}.",
+						image.display(),
+						human_bytes(old_size),
+						human_bytes(size)
+					);
+				}
+				DiskImageCommand::Convert {
+					source,
+					target,
+					format,
+					block_size,
+				} => {
+					let format = format
+						.or_else(|| image::NewFormat::from_path(&target))
+						.ok_or_else(|| {
+							format!(
+								"cannot tell the format of {} from its extension; use --format",
+								target.display()
+							)
+						})?;
+					// 进度每变化 1% 在同一行更新一次
+					let mut shown = None;
+					image::convert(&source, &target, format, block_size, |done, total| {
+						let percent = done * 100 / total;
+						if shown != Some(percent) {
+							shown = Some(percent);
+							eprint!(
+								"\rConverting: {}% ({} of {})",
+								percent,
+								human_bytes(done),
+								human_bytes(total)
+							);
+						}
+					})?;
+					eprintln!();
+					println!(
+						"Converted {} to {} ({} on disk).",
+						source.display(),
+						target.display(),
+						human_bytes(std::fs::metadata(&target)?.len())
+					);
+				}
+			}
+			Ok(())
+		}
+	}
+}
+
+// 以 1d2h3m4s 的形式显示运行时间
+fn humantime_secs(secs: u64) -> String {
+	let (days, hours, minutes, seconds) =
+		(secs / 86400, secs / 3600 % 24, secs / 60 % 60, secs % 60);
+	let mut out = String::new();
+	for (value, unit) in [(days, "d"), (hours, "h"), (minutes, "m")] {
+		if value > 0 || !out.is_empty() {
+			out.push_str(&format!("{}{}", value, unit));
+		}
+	}
+	out.push_str(&format!("{}s", seconds));
+	out
+}
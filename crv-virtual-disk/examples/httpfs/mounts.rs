@@ -0,0 +1,573 @@
+use std::{
+	collections::BTreeMap,
+	env,
+	error::Error,
+	fs,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	time::Duration,
+};
+
+use crv_virtual_disk::{
+	atomic_save,
+	attr_cache::{CacheLimits, Eviction},
+	auth::OAuthSettings,
+	backend::Remote,
+	check_token,
+	compression::Compression,
+	credentials::Credentials,
+	events::ShutdownPolicy,
+	hooks::Hooks,
+	http2::Http2,
+	image::cache::{CacheMode, CacheSettings},
+	mount::DEFAULT_ATTR_CACHE_TTL,
+	mount_point,
+	names::NameEscaping,
+	policy::{CachePolicy, ProcessPolicies, ProcessPolicy},
+	Driver, Mount, MountConfig,
+};
+use serde::Deserialize;
+
+use crate::cli::{parse_size, MountArgs, RemoteArgs};
+
+const DEFAULT_FLUSH_INTERVAL: u64 = 5;
+
+// 网络驱动器未指定 UNC 名称时使用 \\httpfs\<共享>
+const DEFAULT_UNC_SERVER: &str = "httpfs";
+
+// mounts.toml 中的一个挂载配置，对应 mount 的同名参数，命令行给出的值优先
+//
+// [mounts.work]
+// url = "http://files.example.com:8080"
+// share = "team"
+// mount_point = "W:\\"
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+	url: Option<String>,
+	share: Option<String>,
+	token: Option<String>,
+	credential: Option<String>,
+	oauth_issuer: Option<String>,
+	oauth_client_id: Option<String>,
+	oauth_scope: Option<String>,
+	compression: Option<String>,
+	http2: Option<String>,
+	#[serde(default)]
+	http3: bool,
+	s3_endpoint: Option<String>,
+	s3_region: Option<String>,
+	#[serde(default)]
+	s3_path_style: bool,
+	aws_profile: Option<String>,
+	ssh_key: Option<String>,
+	ssh_host_key: Option<String>,
+	mem_capacity: Option<String>,
+	mem_limit: Option<String>,
+	git_ref: Option<String>,
+	partition: Option<usize>,
+	block_cache: Option<String>,
+	block_cache_mode: Option<CacheMode>,
+	flush_interval: Option<u64>,
+	upper: Option<String>,
+	#[serde(default)]
+	lower: Vec<String>,
+	#[serde(default)]
+	encrypt: bool,
+	key_file: Option<String>,
+	#[serde(default)]
+	encrypt_names: bool,
+	#[serde(default)]
+	compress_files: bool,
+	#[serde(default)]
+	compress_skip: Vec<String>,
+	#[serde(default)]
+	dedup: bool,
+	#[serde(default)]
+	delta_sync: bool,
+	#[serde(default)]
+	access_based_enumeration: bool,
+	#[serde(default)]
+	direct_reads: bool,
+	escape_names: Option<NameEscaping>,
+	mount_point: Option<String>,
+	attr_cache_ttl: Option<u64>,
+	attr_cache_max_entries: Option<usize>,
+	attr_cache_max_bytes: Option<String>,
+	attr_cache_eviction: Option<Eviction>,
+	#[serde(default)]
+	attr_cache_pin: Vec<String>,
+	#[serde(default)]
+	snapshots: bool,
+	events: Option<bool>,
+	on_shutdown_notice: Option<ShutdownPolicy>,
+	#[serde(default)]
+	single_thread: bool,
+	#[serde(default)]
+	dokan_debug: bool,
+	dokan_timeout: Option<u64>,
+	allocation_unit_size: Option<u32>,
+	sector_size: Option<u32>,
+	#[serde(default)]
+	write_protect: bool,
+	#[serde(default)]
+	mount_manager: bool,
+	#[serde(default)]
+	removable: bool,
+	#[serde(default)]
+	network: bool,
+	unc_name: Option<String>,
+	metrics_addr: Option<SocketAddr>,
+	driver: Option<Driver>,
+	access_rules: Option<PathBuf>,
+	user_map: Option<PathBuf>,
+	#[serde(default)]
+	write_through: Vec<String>,
+	#[serde(default)]
+	no_attr_cache_for: Vec<String>,
+	journal: Option<PathBuf>,
+	upload_workers: Option<usize>,
+	#[serde(default)]
+	pack_small_files: bool,
+	#[serde(default)]
+	leases: bool,
+	offline_cache: Option<PathBuf>,
+	#[serde(default)]
+	pin: Vec<String>,
+	#[serde(default)]
+	atomic_saves: bool,
+	#[serde(default)]
+	atomic_save_patterns: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct MountsFile {
+	#[serde(default)]
+	mounts: BTreeMap<String, Profile>,
+}
+
+// 未指定 --mounts-file 时使用 %APPDATA%\httpfs\mounts.toml
+fn default_path() -> PathBuf {
+	env::var_os("APPDATA")
+		.map(PathBuf::from)
+		.unwrap_or_default()
+		.join("httpfs")
+		.join("mounts.toml")
+}
+
+fn load(path: Option<&Path>) -> Result<BTreeMap<String, Profile>, Box<dyn Error>> {
+	let path = path.map_or_else(default_path, Path::to_path_buf);
+	let text =
+		fs::read_to_string(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+	let file: MountsFile =
+		toml::from_str(&text).map_err(|e| format!("invalid {}: {}", path.display(), e))?;
+	Ok(file.mounts)
+}
+
+// --profile 指定的配置，未指定时所有值都来自命令行
+fn profile(args: &RemoteArgs) -> Result<Profile, Box<dyn Error>> {
+	let Some(name) = &args.profile else {
+		return Ok(Profile::default());
+	};
+	load(args.mounts_file.as_deref())?
+		.remove(name)
+		.ok_or_else(|| format!("no profile named '{}' in the mounts file", name).into())
+}
+
+// 去掉 URL 结尾的 /，mem:// 这样只有协议的 URL 保持不变
+fn trim_url(url: &str) -> String {
+	match url.trim_end_matches('/') {
+		trimmed if trimmed.ends_with(':') => url.to_string(),
+		trimmed => trimmed.to_string(),
+	}
+}
+
+// 命令行参数和配置合并后访问服务器所需的设置
+fn remote(args: &RemoteArgs, profile: &Profile) -> Result<Remote, Box<dyn Error>> {
+	let credential = args
+		.credential
+		.clone()
+		.or_else(|| profile.credential.clone());
+	let stored = match &credential {
+		Some(name) => Credentials::load(name)?.ok_or_else(|| {
+			format!(
+				"no saved credential named '{}'; save it with `httpfs credentials set`",
+				name
+			)
+		})?,
+		None => Credentials::default(),
+	};
+	let server_url = args
+		.server_url
+		.as_ref()
+		.or(profile.url.as_ref())
+		.or(stored.url.as_ref())
+		.ok_or("--url is required unless the profile or --credential sets url")?;
+	let compression = match (args.compression, &profile.compression) {
+		(Some(compression), _) => compression,
+		(None, Some(name)) => name.parse()?,
+		(None, None) => Compression::Zstd,
+	};
+	let http2 = match (args.http2, &profile.http2) {
+		(Some(http2), _) => http2,
+		(None, Some(name)) => name.parse()?,
+		(None, None) => Http2::Auto,
+	};
+	let upper = args
+		.upper
+		.as_ref()
+		.or(profile.upper.as_ref())
+		.map(|url| trim_url(url));
+	let lower: Vec<String> = if args.lower.is_empty() {
+		&profile.lower
+	} else {
+		&args.lower
+	}
+	.iter()
+	.map(|url| trim_url(url))
+	.collect();
+	if upper.is_none() && !lower.is_empty() {
+		return Err("--lower requires --upper".into());
+	}
+	let key_file = args.key_file.clone().or_else(|| profile.key_file.clone());
+	let encrypt_names = args.encrypt_names || profile.encrypt_names;
+	let encrypt = args.encrypt || profile.encrypt || key_file.is_some() || encrypt_names;
+	let token = args
+		.token
+		.clone()
+		.or_else(|| profile.token.clone())
+		.or(stored.token);
+	let oauth = match args.oauth_issuer.as_ref().or(profile.oauth_issuer.as_ref()) {
+		Some(issuer) => Some(OAuthSettings {
+			issuer: trim_url(issuer),
+			client_id: args
+				.oauth_client_id
+				.clone()
+				.or_else(|| profile.oauth_client_id.clone())
+				.ok_or("--oauth-issuer requires --oauth-client-id")?,
+			scope: args
+				.oauth_scope
+				.clone()
+				.or_else(|| profile.oauth_scope.clone()),
+		}),
+		None => None,
+	};
+	if oauth.is_some() && token.is_some() {
+		return Err("--token cannot be combined with --oauth-issuer".into());
+	}
+	if let Some(token) = &token {
+		check_token(token)?;
+	}
+	Ok(Remote {
+		server_url: trim_url(server_url),
+		share: args.share.clone().or_else(|| profile.share.clone()),
+		token,
+		oauth,
+		credential,
+		compression,
+		http2,
+		http3: args.http3 || profile.http3,
+		s3_endpoint: args
+			.s3_endpoint
+			.clone()
+			.or_else(|| profile.s3_endpoint.clone()),
+		s3_region: args.s3_region.clone().or_else(|| profile.s3_region.clone()),
+		s3_path_style: args.s3_path_style || profile.s3_path_style,
+		aws_profile: args
+			.aws_profile
+			.clone()
+			.or_else(|| profile.aws_profile.clone()),
+		ssh_key: args.ssh_key.clone().or_else(|| profile.ssh_key.clone()),
+		ssh_host_key: args
+			.ssh_host_key
+			.clone()
+			.or_else(|| profile.ssh_host_key.clone()),
+		mem_capacity: match args.mem_capacity {
+			Some(capacity) => Some(capacity),
+			None => profile
+				.mem_capacity
+				.as_deref()
+				.map(parse_size)
+				.transpose()?,
+		},
+		mem_limit: match args.mem_limit {
+			Some(limit) => Some(limit),
+			None => profile.mem_limit.as_deref().map(parse_size).transpose()?,
+		},
+		git_ref: args.git_ref.clone().or_else(|| profile.git_ref.clone()),
+		partition: args.partition.or(profile.partition),
+		block_cache: cache_settings(args, profile)?,
+		upper,
+		lower,
+		encrypt,
+		key_file,
+		encrypt_names,
+		compress_files: args.compress_files || profile.compress_files,
+		compress_skip: if args.compress_skip.is_empty() {
+			&profile.compress_skip
+		} else {
+			&args.compress_skip
+		}
+		.clone(),
+		dedup: args.dedup || profile.dedup,
+		delta_sync: args.delta_sync || profile.delta_sync,
+		access_based_enumeration: args.access_based_enumeration || profile.access_based_enumeration,
+		direct_reads: args.direct_reads || profile.direct_reads,
+		escape_names: args
+			.escape_names
+			.or(profile.escape_names)
+			.unwrap_or_default(),
+	})
+}
+
+// 命令行参数和配置合并后一个挂载的全部设置
+fn mount(args: &MountArgs, profile: &Profile) -> Result<Mount, Box<dyn Error>> {
+	let mount_point = args
+		.mount_point
+		.as_ref()
+		.or(profile.mount_point.as_ref())
+		.ok_or("--mount-point is required unless the profile sets mount_point")?;
+	let remote = remote(&args.remote, profile)?;
+	let driver = args.driver.or(profile.driver).unwrap_or_default();
+	match driver {
+		Driver::Winfsp if !cfg!(feature = "winfsp") => {
+			return Err(
+				"this httpfs was built without WinFsp support; rebuild it with --features winfsp"
+					.into(),
+			)
+		}
+		Driver::Projfs if !cfg!(feature = "projfs") => {
+			return Err(
+				"this httpfs was built without ProjFS support; rebuild it with --features projfs"
+					.into(),
+			)
+		}
+		_ => {}
+	}
+	let network = args.network || profile.network;
+	let mut dokan = MountConfig::builder()
+		.single_thread(args.single_thread || profile.single_thread)
+		.debug(args.dokan_debug || profile.dokan_debug)
+		.write_protect(args.write_protect || profile.write_protect)
+		.mount_manager(args.mount_manager || profile.mount_manager)
+		.removable(args.removable || profile.removable)
+		.network(network);
+	if let Some(secs) = args.dokan_timeout.or(profile.dokan_timeout) {
+		dokan = dokan.timeout(Duration::from_secs(secs));
+	}
+	if let Some(bytes) = args.allocation_unit_size.or(profile.allocation_unit_size) {
+		dokan = dokan.allocation_unit_size(bytes);
+	}
+	if let Some(bytes) = args.sector_size.or(profile.sector_size) {
+		dokan = dokan.sector_size(bytes);
+	}
+	match args.unc_name.as_ref().or(profile.unc_name.as_ref()) {
+		Some(name) => dokan = dokan.unc_name(name.as_str()),
+		None if network => {
+			let share = remote.share.as_deref().unwrap_or("default");
+			dokan = dokan.unc_name(format!(r"\\{}\{}", DEFAULT_UNC_SERVER, share));
+		}
+		None => {}
+	}
+	Ok(Mount {
+		remote,
+		mount_point: mount_point.clone(),
+		snapshots: args.snapshots || profile.snapshots,
+		attr_cache_ttl: args
+			.attr_cache_ttl
+			.or(profile.attr_cache_ttl)
+			.unwrap_or(DEFAULT_ATTR_CACHE_TTL),
+		attr_cache_limits: CacheLimits {
+			max_entries: args
+				.attr_cache_max_entries
+				.or(profile.attr_cache_max_entries),
+			max_bytes: match args.attr_cache_max_bytes {
+				Some(bytes) => Some(bytes),
+				None => profile
+					.attr_cache_max_bytes
+					.as_deref()
+					.map(parse_size)
+					.transpose()?,
+			},
+			eviction: args
+				.attr_cache_eviction
+				.or(profile.attr_cache_eviction)
+				.unwrap_or_default(),
+			pinned: if args.attr_cache_pin.is_empty() {
+				&profile.attr_cache_pin
+			} else {
+				&args.attr_cache_pin
+			}
+			.clone(),
+		},
+		events: !args.no_events && profile.events.unwrap_or(true),
+		on_shutdown_notice: args
+			.on_shutdown_notice
+			.or(profile.on_shutdown_notice)
+			.unwrap_or_default(),
+		dokan: dokan.build()?,
+		metrics_addr: args.metrics_addr.or(profile.metrics_addr),
+		driver,
+		service: args.service,
+		hooks: Hooks::new(),
+		access_rules: args
+			.access_rules
+			.clone()
+			.or_else(|| profile.access_rules.clone()),
+		user_map: args.user_map.clone().or_else(|| profile.user_map.clone()),
+		process_policies: process_policies(
+			if args.write_through.is_empty() {
+				&profile.write_through
+			} else {
+				&args.write_through
+			},
+			if args.no_attr_cache_for.is_empty() {
+				&profile.no_attr_cache_for
+			} else {
+				&args.no_attr_cache_for
+			},
+		),
+		journal: args.journal.clone().or_else(|| profile.journal.clone()),
+		upload_workers: args.upload_workers.or(profile.upload_workers).unwrap_or(0),
+		pack_small_files: args.pack_small_files || profile.pack_small_files,
+		leases: args.leases || profile.leases,
+		offline_cache: args
+			.offline_cache
+			.clone()
+			.or_else(|| profile.offline_cache.clone()),
+		pin: if args.pin.is_empty() {
+			&profile.pin
+		} else {
+			&args.pin
+		}
+		.clone(),
+		atomic_save_patterns: atomic_save_patterns(args, profile),
+	})
+}
+
+// 给出了模式时使用这些模式，只打开了 atomic_saves 时使用默认的模式
+fn atomic_save_patterns(args: &MountArgs, profile: &Profile) -> Vec<String> {
+	let patterns = if args.atomic_save_pattern.is_empty() {
+		&profile.atomic_save_patterns
+	} else {
+		&args.atomic_save_pattern
+	};
+	if !patterns.is_empty() {
+		patterns.clone()
+	} else if args.atomic_saves || profile.atomic_saves {
+		atomic_save::DEFAULT_PATTERNS
+			.iter()
+			.map(|pattern| pattern.to_string())
+			.collect()
+	} else {
+		Vec::new()
+	}
+}
+
+// 每个进程名一条策略，按第一次出现的顺序
+fn process_policies(write_through: &[String], no_attr_cache_for: &[String]) -> ProcessPolicies {
+	let mut policies = Vec::new();
+	for name in write_through {
+		cache_policy(&mut policies, name).write_back = false;
+	}
+	for name in no_attr_cache_for {
+		cache_policy(&mut policies, name).attr_cache = false;
+	}
+	ProcessPolicies::new(policies)
+}
+
+fn cache_policy<'a>(policies: &'a mut Vec<ProcessPolicy>, name: &str) -> &'a mut CachePolicy {
+	let index = match policies
+		.iter()
+		.position(|policy| policy.process.eq_ignore_ascii_case(name))
+	{
+		Some(index) => index,
+		None => {
+			policies.push(ProcessPolicy {
+				process: name.to_string(),
+				cache: CachePolicy::default(),
+			});
+			policies.len() - 1
+		}
+	};
+	&mut policies[index].cache
+}
+
+// search、verify、trash 等子命令访问的服务器
+pub fn resolve_remote(args: &RemoteArgs) -> Result<Remote, Box<dyn Error>> {
+	remote(args, &profile(args)?)
+}
+
+// 块缓存的设置，没有给出缓存大小时为 None
+fn cache_settings(
+	args: &RemoteArgs,
+	profile: &Profile,
+) -> Result<Option<CacheSettings>, Box<dyn Error>> {
+	let size = match args.block_cache {
+		Some(size) => size,
+		None => match profile.block_cache.as_deref().map(parse_size).transpose()? {
+			Some(size) => size,
+			None => return Ok(None),
+		},
+	};
+	Ok(Some(CacheSettings {
+		size,
+		mode: args
+			.block_cache_mode
+			.or(profile.block_cache_mode)
+			.unwrap_or_default(),
+		flush_interval: Duration::from_secs(
+			args.flush_interval
+				.or(profile.flush_interval)
+				.unwrap_or(DEFAULT_FLUSH_INTERVAL),
+		),
+	}))
+}
+
+// 不经过服务器打开本地映像时（nbd-serve）的块缓存设置
+pub fn block_cache(args: &RemoteArgs) -> Result<Option<CacheSettings>, Box<dyn Error>> {
+	cache_settings(args, &profile(args)?)
+}
+
+// mount 和 install-service 要挂载的文件系统：--all 时为 mounts.toml 中的每个配置（命令行上的选项应用于全部）
+pub fn resolve_mounts(args: &MountArgs) -> Result<Vec<Mount>, Box<dyn Error>> {
+	let mut mounts = if args.all {
+		let profiles = load(args.remote.mounts_file.as_deref())?;
+		if profiles.is_empty() {
+			return Err("the mounts file does not define any mounts".into());
+		}
+		profiles
+			.iter()
+			.map(|(name, profile)| {
+				mount(args, profile).map_err(|e| format!("profile '{}': {}", name, e).into())
+			})
+			.collect::<Result<Vec<_>, Box<dyn Error>>>()?
+	} else {
+		vec![mount(args, &profile(&args.remote)?)?]
+	};
+	assign_drive_letters(&mut mounts)?;
+	Ok(mounts)
+}
+
+// 把挂载点为 auto 的挂载依次分配到空闲的盘符，跳过同一批挂载中已指定的盘符；
+// 服务在安装时分配，之后每次启动使用同一盘符
+fn assign_drive_letters(mounts: &mut [Mount]) -> Result<(), Box<dyn Error>> {
+	let taken: Vec<char> = mounts
+		.iter()
+		.filter_map(|mount| mount_point::drive_letter(&mount.mount_point))
+		.collect();
+	let mut free = mount_point::free_drive_letters()
+		.into_iter()
+		.filter(|letter| !taken.contains(letter));
+	for mount in mounts
+		.iter_mut()
+		.filter(|mount| mount.mount_point.eq_ignore_ascii_case(mount_point::AUTO))
+	{
+		let letter = free
+			.next()
+			.ok_or("no free drive letter for --mount-point auto")?;
+		mount.mount_point = format!("{}:\\", letter);
+	}
+	Ok(())
+}
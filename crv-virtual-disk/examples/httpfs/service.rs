@@ -0,0 +1,431 @@
+use std::{
+	env,
+	io::{self, BufRead, BufReader, Read},
+	mem, ptr,
+	sync::{
+		atomic::{AtomicBool, AtomicPtr, Ordering},
+		Arc, Mutex, OnceLock,
+	},
+	thread,
+};
+
+use tracing::error;
+use widestring::U16CString;
+use winapi::{
+	shared::{
+		minwindef::{DWORD, LPVOID, TRUE},
+		winerror::{
+			ERROR_CALL_NOT_IMPLEMENTED, ERROR_FAILED_SERVICE_CONTROLLER_CONNECT,
+			ERROR_SERVICE_NOT_ACTIVE, ERROR_SERVICE_SPECIFIC_ERROR, NO_ERROR,
+		},
+	},
+	um::{
+		fileapi::ReadFile,
+		handleapi::CloseHandle,
+		namedpipeapi::CreatePipe,
+		processenv::SetStdHandle,
+		winbase::{RegisterEventSourceW, ReportEventW, STD_ERROR_HANDLE, STD_OUTPUT_HANDLE},
+		winnt::{
+			DELETE, EVENTLOG_ERROR_TYPE, EVENTLOG_INFORMATION_TYPE, EVENTLOG_WARNING_TYPE, HANDLE,
+			LPWSTR, SERVICE_AUTO_START, SERVICE_ERROR_NORMAL, SERVICE_WIN32_OWN_PROCESS,
+		},
+		winsvc::{
+			ChangeServiceConfig2W, CloseServiceHandle, ControlService, CreateServiceW,
+			DeleteService, OpenSCManagerW, OpenServiceW, RegisterServiceCtrlHandlerExW,
+			SetServiceStatus, StartServiceCtrlDispatcherW, StartServiceW, SC_ACTION,
+			SC_ACTION_RESTART, SC_HANDLE, SC_MANAGER_CONNECT, SC_MANAGER_CREATE_SERVICE,
+			SERVICE_ACCEPT_SHUTDOWN, SERVICE_ACCEPT_STOP, SERVICE_CHANGE_CONFIG,
+			SERVICE_CONFIG_DESCRIPTION, SERVICE_CONFIG_FAILURE_ACTIONS,
+			SERVICE_CONFIG_FAILURE_ACTIONS_FLAG, SERVICE_CONTROL_INTERROGATE,
+			SERVICE_CONTROL_SHUTDOWN, SERVICE_CONTROL_STOP, SERVICE_DESCRIPTIONW,
+			SERVICE_FAILURE_ACTIONSW, SERVICE_FAILURE_ACTIONS_FLAG, SERVICE_QUERY_STATUS,
+			SERVICE_RUNNING, SERVICE_START, SERVICE_START_PENDING, SERVICE_STATUS,
+			SERVICE_STATUS_HANDLE__, SERVICE_STOP, SERVICE_STOPPED, SERVICE_STOP_PENDING,
+			SERVICE_TABLE_ENTRYW,
+		},
+	},
+};
+
+use crv_virtual_disk::{
+	control,
+	mount::{self, request_unmount, Driver, Mount},
+};
+
+const DESCRIPTION: &str = "Mounts a share of an HTTP storage server as a Dokan file system.";
+
+// 服务因错误退出后 SCM 依次等待这些时间（毫秒）再重新启动，一天内没有再失败则重新计数
+const RESTART_DELAYS: [DWORD; 3] = [5_000, 30_000, 60_000];
+const RESET_PERIOD_SECS: DWORD = 24 * 60 * 60;
+
+// 每个挂载点一个服务，名称与控制管道使用相同的标识
+pub fn service_name(mount_point: &str) -> String {
+	format!("httpfs-{}", control::mount_id(mount_point))
+}
+
+fn wide(s: &str) -> io::Result<U16CString> {
+	U16CString::from_str(s).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+fn check(ok: i32) -> io::Result<()> {
+	if ok != 0 {
+		Ok(())
+	} else {
+		Err(io::Error::last_os_error())
+	}
+}
+
+// 按 Windows 命令行的解析规则给参数加引号：引号前的反斜杠需要加倍，结尾的反斜杠（如 M:\）也一样
+fn quote_arg(arg: &str) -> String {
+	if !arg.is_empty() && !arg.contains([' ', '\t', '"']) {
+		return arg.to_string();
+	}
+	let mut quoted = String::from('"');
+	let mut backslashes = 0;
+	for c in arg.chars() {
+		match c {
+			'\\' => backslashes += 1,
+			'"' => {
+				quoted.push_str(&"\\".repeat(backslashes + 1));
+				backslashes = 0;
+			}
+			_ => backslashes = 0,
+		}
+		quoted.push(c);
+	}
+	quoted.push_str(&"\\".repeat(backslashes));
+	quoted.push('"');
+	quoted
+}
+
+// 服务控制管理器和服务的句柄，离开作用域时关闭
+struct ScHandle(SC_HANDLE);
+
+impl ScHandle {
+	fn new(handle: SC_HANDLE) -> io::Result<Self> {
+		if handle.is_null() {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(Self(handle))
+		}
+	}
+
+	fn manager(access: DWORD) -> io::Result<Self> {
+		Self::new(unsafe { OpenSCManagerW(ptr::null(), ptr::null(), access) })
+	}
+
+	fn configure<T>(&self, level: DWORD, info: &mut T) -> io::Result<()> {
+		check(unsafe { ChangeServiceConfig2W(self.0, level, (info as *mut T).cast()) })
+	}
+}
+
+impl Drop for ScHandle {
+	fn drop(&mut self) {
+		unsafe { CloseServiceHandle(self.0) };
+	}
+}
+
+// 注册开机自动启动的服务并立即启动，返回服务名称。服务以 LocalSystem 运行，命令行中保存挂载参数（包括访问令牌）
+pub fn install(args: &Mount) -> io::Result<String> {
+	let name = service_name(&args.mount_point);
+	let exe = env::current_exe()?;
+	let command_line = std::iter::once(exe.to_string_lossy().into_owned())
+		.chain(args.to_args())
+		.map(|arg| quote_arg(&arg))
+		.collect::<Vec<_>>()
+		.join(" ");
+
+	let manager = ScHandle::manager(SC_MANAGER_CREATE_SERVICE)?;
+	let service = ScHandle::new(unsafe {
+		CreateServiceW(
+			manager.0,
+			wide(&name)?.as_ptr(),
+			wide(&format!("HTTP File System ({})", args.mount_point))?.as_ptr(),
+			SERVICE_CHANGE_CONFIG | SERVICE_START,
+			SERVICE_WIN32_OWN_PROCESS,
+			SERVICE_AUTO_START,
+			SERVICE_ERROR_NORMAL,
+			wide(&command_line)?.as_ptr(),
+			ptr::null(),
+			ptr::null_mut(),
+			ptr::null(),
+			ptr::null(),
+			ptr::null(),
+		)
+	})?;
+
+	let mut description = wide(DESCRIPTION)?;
+	service.configure(
+		SERVICE_CONFIG_DESCRIPTION,
+		&mut SERVICE_DESCRIPTIONW {
+			lpDescription: description.as_mut_ptr(),
+		},
+	)?;
+	let mut actions = RESTART_DELAYS.map(|delay| SC_ACTION {
+		Type: SC_ACTION_RESTART,
+		Delay: delay,
+	});
+	service.configure(
+		SERVICE_CONFIG_FAILURE_ACTIONS,
+		&mut SERVICE_FAILURE_ACTIONSW {
+			dwResetPeriod: RESET_PERIOD_SECS,
+			lpRebootMsg: ptr::null_mut(),
+			lpCommand: ptr::null_mut(),
+			cActions: actions.len() as DWORD,
+			lpsaActions: actions.as_mut_ptr(),
+		},
+	)?;
+	// 挂载失败时服务以错误码正常退出而不是崩溃，也要按上面的设置重新启动
+	service.configure(
+		SERVICE_CONFIG_FAILURE_ACTIONS_FLAG,
+		&mut SERVICE_FAILURE_ACTIONS_FLAG {
+			fFailureActionsOnNonCrashFailures: TRUE,
+		},
+	)?;
+
+	check(unsafe { StartServiceW(service.0, 0, ptr::null_mut()) })?;
+	Ok(name)
+}
+
+// 停止并删除挂载点对应的服务，返回服务名称
+pub fn uninstall(mount_point: &str) -> io::Result<String> {
+	let name = service_name(mount_point);
+	let manager = ScHandle::manager(SC_MANAGER_CONNECT)?;
+	let service = ScHandle::new(unsafe {
+		OpenServiceW(
+			manager.0,
+			wide(&name)?.as_ptr(),
+			SERVICE_STOP | SERVICE_QUERY_STATUS | DELETE,
+		)
+	})?;
+	let mut status: SERVICE_STATUS = unsafe { mem::zeroed() };
+	if unsafe { ControlService(service.0, SERVICE_CONTROL_STOP, &mut status) } == 0 {
+		let error = io::Error::last_os_error();
+		if error.raw_os_error() != Some(ERROR_SERVICE_NOT_ACTIVE as i32) {
+			return Err(error);
+		}
+	}
+	check(unsafe { DeleteService(service.0) })?;
+	Ok(name)
+}
+
+// ServiceMain 和控制处理函数没有用户参数，通过静态变量取得挂载参数和运行状态
+static ARGS: Mutex<Option<Mount>> = Mutex::new(None);
+static SERVICE: OnceLock<Service> = OnceLock::new();
+
+struct Service {
+	status: AtomicPtr<SERVICE_STATUS_HANDLE__>,
+	mount_point: U16CString,
+	driver: Driver,
+	stop_events: Arc<AtomicBool>,
+}
+
+impl Service {
+	fn report(&self, state: DWORD, exit_code: DWORD) {
+		let accepted = if state == SERVICE_RUNNING {
+			SERVICE_ACCEPT_STOP | SERVICE_ACCEPT_SHUTDOWN
+		} else {
+			0
+		};
+		let mut status = SERVICE_STATUS {
+			dwServiceType: SERVICE_WIN32_OWN_PROCESS,
+			dwCurrentState: state,
+			dwControlsAccepted: accepted,
+			dwWin32ExitCode: if exit_code == 0 {
+				NO_ERROR
+			} else {
+				ERROR_SERVICE_SPECIFIC_ERROR
+			},
+			dwServiceSpecificExitCode: exit_code,
+			dwCheckPoint: 0,
+			dwWaitHint: if state == SERVICE_RUNNING || state == SERVICE_STOPPED {
+				0
+			} else {
+				30_000
+			},
+		};
+		unsafe { SetServiceStatus(self.status.load(Ordering::Relaxed).cast(), &mut status) };
+	}
+}
+
+// 由服务控制管理器启动（mount --service）时进入，直到服务停止才返回
+pub fn run(args: Mount) -> io::Result<()> {
+	let name = wide(&service_name(&args.mount_point))?;
+	*ARGS.lock().unwrap() = Some(args);
+	let table = [
+		SERVICE_TABLE_ENTRYW {
+			lpServiceName: name.as_ptr(),
+			lpServiceProc: Some(service_main),
+		},
+		SERVICE_TABLE_ENTRYW {
+			lpServiceName: ptr::null(),
+			lpServiceProc: None,
+		},
+	];
+	if unsafe { StartServiceCtrlDispatcherW(table.as_ptr()) } != 0 {
+		return Ok(());
+	}
+	let error = io::Error::last_os_error();
+	if error.raw_os_error() == Some(ERROR_FAILED_SERVICE_CONTROLLER_CONNECT as i32) {
+		return Err(
+			io::Error::other("--service is used by the service control manager; run `httpfs install-service` instead"),
+		);
+	}
+	Err(error)
+}
+
+unsafe extern "system" fn service_main(_argc: DWORD, _argv: *mut LPWSTR) {
+	let Some(args) = ARGS.lock().unwrap().take() else {
+		return;
+	};
+	let name = service_name(&args.mount_point);
+	// 重定向失败时没有其他地方可以记录日志，仍然继续挂载
+	let _ = redirect_output(&name);
+	let (Ok(wide_name), Ok(mount_point)) = (wide(&name), wide(&args.mount_point)) else {
+		return;
+	};
+	let service = SERVICE.get_or_init(|| Service {
+		status: AtomicPtr::new(ptr::null_mut()),
+		mount_point,
+		driver: args.driver,
+		stop_events: Arc::new(AtomicBool::new(false)),
+	});
+	let status =
+		RegisterServiceCtrlHandlerExW(wide_name.as_ptr(), Some(control_handler), ptr::null_mut());
+	if status.is_null() {
+		error!(error = %io::Error::last_os_error(), "service: cannot register control handler");
+		return;
+	}
+	service.status.store(status.cast(), Ordering::Relaxed);
+	service.report(SERVICE_START_PENDING, 0);
+
+	dokan::init();
+	let result = mount::mount(&args, service.stop_events.clone(), Arc::default(), || {
+		service.report(SERVICE_RUNNING, 0)
+	});
+	dokan::shutdown();
+	match result {
+		Ok(()) => service.report(SERVICE_STOPPED, 0),
+		// 以服务特定错误码退出，SCM 按失败操作稍后重新启动服务
+		Err(e) => {
+			error!(error = %e, "service: mount failed");
+			service.report(SERVICE_STOPPED, 1);
+		}
+	}
+}
+
+unsafe extern "system" fn control_handler(
+	control: DWORD,
+	_event_type: DWORD,
+	_event_data: LPVOID,
+	_context: LPVOID,
+) -> DWORD {
+	let Some(service) = SERVICE.get() else {
+		return ERROR_CALL_NOT_IMPLEMENTED;
+	};
+	match control {
+		SERVICE_CONTROL_STOP | SERVICE_CONTROL_SHUTDOWN => {
+			service.report(SERVICE_STOP_PENDING, 0);
+			if !request_unmount(&service.mount_point, service.driver, &service.stop_events) {
+				error!("service: failed to unmount file system");
+			}
+			NO_ERROR
+		}
+		SERVICE_CONTROL_INTERROGATE => NO_ERROR,
+		_ => ERROR_CALL_NOT_IMPLEMENTED,
+	}
+}
+
+// 服务没有控制台，把 stdout 和 stderr 重定向到管道，由后台线程逐行写入 Windows 事件日志（应用程序日志，来源为服务名称），
+// 事件类型取自日志行的级别
+fn redirect_output(source: &str) -> io::Result<()> {
+	let event_log = unsafe { RegisterEventSourceW(ptr::null(), wide(source)?.as_ptr()) };
+	if event_log.is_null() {
+		return Err(io::Error::last_os_error());
+	}
+	let (mut read, mut write) = (ptr::null_mut(), ptr::null_mut());
+	check(unsafe { CreatePipe(&mut read, &mut write, ptr::null_mut(), 0) })?;
+	check(unsafe { SetStdHandle(STD_OUTPUT_HANDLE, write) })?;
+	check(unsafe { SetStdHandle(STD_ERROR_HANDLE, write) })?;
+
+	let reader = OutputPipe(read);
+	let event_log = EventLog(event_log);
+	thread::spawn(move || {
+		for line in BufReader::new(reader).lines() {
+			let Ok(line) = line else {
+				break;
+			};
+			event_log.report(&line);
+		}
+	});
+	Ok(())
+}
+
+// 只在读取日志的线程中使用的句柄
+struct OutputPipe(HANDLE);
+struct EventLog(HANDLE);
+
+unsafe impl Send for OutputPipe {}
+unsafe impl Send for EventLog {}
+
+impl Read for OutputPipe {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut read: DWORD = 0;
+		let len = buf.len().min(DWORD::MAX as usize) as DWORD;
+		if unsafe {
+			ReadFile(
+				self.0,
+				buf.as_mut_ptr().cast(),
+				len,
+				&mut read,
+				ptr::null_mut(),
+			)
+		} != 0
+		{
+			Ok(read as usize)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+}
+
+impl Drop for OutputPipe {
+	fn drop(&mut self) {
+		unsafe { CloseHandle(self.0) };
+	}
+}
+
+// 文本格式的日志行为“时间 级别 ...”，JSON 格式的级别在 level 字段中；其他输出（如 println）没有级别
+fn log_level(line: &str) -> Option<&str> {
+	if line.starts_with('{') {
+		let start = line.find(r#""level":""#)? + r#""level":""#.len();
+		return line[start..].split('"').next();
+	}
+	line.split_whitespace().nth(1)
+}
+
+impl EventLog {
+	fn report(&self, line: &str) {
+		let kind = match log_level(line) {
+			Some("ERROR") => EVENTLOG_ERROR_TYPE,
+			Some("WARN") => EVENTLOG_WARNING_TYPE,
+			_ => EVENTLOG_INFORMATION_TYPE,
+		};
+		let Ok(message) = U16CString::from_str(line) else {
+			return;
+		};
+		let mut strings = [message.as_ptr()];
+		unsafe {
+			ReportEventW(
+				self.0,
+				kind,
+				0,
+				0,
+				ptr::null_mut(),
+				1,
+				0,
+				strings.as_mut_ptr(),
+				ptr::null_mut(),
+			)
+		};
+	}
+}
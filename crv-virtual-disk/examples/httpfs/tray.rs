@@ -0,0 +1,568 @@
+use std::{
+	cell::RefCell,
+	collections::BTreeMap,
+	io, mem, ptr,
+	sync::atomic::{AtomicU32, Ordering},
+	time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use widestring::U16CString;
+use winapi::{
+	shared::{
+		minwindef::{DWORD, LPARAM, LRESULT, UINT, WPARAM},
+		ntdef::NTSTATUS,
+		ntstatus::{STATUS_BAD_NETWORK_NAME, STATUS_IO_TIMEOUT, STATUS_UNEXPECTED_NETWORK_ERROR},
+		windef::{HMENU, HWND, POINT},
+	},
+	um::{
+		libloaderapi::GetModuleHandleW,
+		shellapi::{
+			ShellExecuteW, Shell_NotifyIconW, NIF_ICON, NIF_INFO, NIF_MESSAGE, NIF_TIP, NIIF_INFO,
+			NIIF_WARNING, NIM_ADD, NIM_DELETE, NIM_MODIFY, NOTIFYICONDATAW,
+		},
+		wincon::{FreeConsole, GetConsoleProcessList},
+		winuser::{
+			AppendMenuW, CreatePopupMenu, CreateWindowExW, DefWindowProcW, DestroyMenu,
+			DestroyWindow, DispatchMessageW, GetCursorPos, GetMessageW, LoadIconW, MessageBoxW,
+			PostMessageW, PostQuitMessage, RegisterClassW, RegisterWindowMessageW,
+			SetForegroundWindow, SetTimer, TrackPopupMenu, TranslateMessage, IDI_APPLICATION,
+			IDI_ERROR, IDI_WARNING, IDYES, MB_ICONERROR, MB_ICONWARNING, MB_OK, MB_YESNO,
+			MF_GRAYED, MF_POPUP, MF_SEPARATOR, MF_STRING, MSG, SW_SHOWNORMAL, TPM_RETURNCMD,
+			TPM_RIGHTBUTTON, WM_APP, WM_DESTROY, WM_LBUTTONUP, WM_NULL, WM_RBUTTONUP, WM_TIMER,
+			WNDCLASSW,
+		},
+	},
+};
+
+use crv_virtual_disk::{
+	control::{self, Request, Response, Status},
+	error::describe_status,
+};
+
+use crate::{control_send, human_bytes, humantime_secs};
+
+// 轮询运行中挂载的间隔（毫秒）
+const REFRESH_MS: UINT = 2000;
+
+// 这段时间内出现过错误的挂载显示为有错误；最近一次错误是网络故障且发生在 OFFLINE_SECS 内时显示为无法连接服务器
+const ERROR_WINDOW_SECS: u64 = 5 * 60;
+const OFFLINE_SECS: u64 = 60;
+
+// 菜单中每个挂载列出的最近错误条数
+const MENU_ERRORS: usize = 5;
+
+const WM_TRAY: UINT = WM_APP + 1;
+const TIMER_ID: usize = 1;
+
+// 菜单命令：挂载的操作为 ID_MOUNT_BASE + 序号 * ACTIONS + 操作
+const ID_EXIT: usize = 1;
+const ID_MOUNT_BASE: usize = 100;
+const ACTIONS: usize = 3;
+const ACTION_OPEN: usize = 0;
+const ACTION_PURGE: usize = 1;
+const ACTION_UNMOUNT: usize = 2;
+
+// 资源管理器重启后广播的消息，收到后需要重新添加图标
+static TASKBAR_CREATED: AtomicU32 = AtomicU32::new(0);
+
+thread_local! {
+	static TRAY: RefCell<Option<Tray>> = const { RefCell::new(None) };
+}
+
+// 按严重程度排序，图标显示所有挂载中最严重的一种
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Health {
+	Healthy,
+	Errors,
+	Offline,
+	NotResponding,
+}
+
+impl Health {
+	fn label(self) -> &'static str {
+		match self {
+			Health::Healthy => "healthy",
+			Health::Errors => "recent errors",
+			Health::Offline => "server unreachable",
+			Health::NotResponding => "not responding",
+		}
+	}
+}
+
+fn is_network(status: u32) -> bool {
+	matches!(
+		status as NTSTATUS,
+		STATUS_UNEXPECTED_NETWORK_ERROR | STATUS_IO_TIMEOUT | STATUS_BAD_NETWORK_NAME
+	)
+}
+
+fn health(status: &Status, now: u64) -> Health {
+	match status.metrics.recent_errors.first() {
+		Some(latest)
+			if is_network(latest.status) && now.saturating_sub(latest.unix_secs) < OFFLINE_SECS =>
+		{
+			Health::Offline
+		}
+		Some(latest) if now.saturating_sub(latest.unix_secs) < ERROR_WINDOW_SECS => Health::Errors,
+		_ => Health::Healthy,
+	}
+}
+
+fn unix_now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs())
+}
+
+// 一个运行中的挂载在最近一次轮询时的状态
+struct MountView {
+	id: String,
+	// 控制管道没有响应时为错误信息
+	status: Result<Status, String>,
+	health: Health,
+	read_rate: f64,
+	write_rate: f64,
+}
+
+impl MountView {
+	fn mount_point(&self) -> &str {
+		match &self.status {
+			Ok(status) => &status.mount_point,
+			Err(_) => &self.id,
+		}
+	}
+
+	fn rates(&self) -> String {
+		format!(
+			"read {}/s, write {}/s",
+			human_bytes(self.read_rate as u64),
+			human_bytes(self.write_rate as u64)
+		)
+	}
+
+	// 状态变差时气泡通知的内容
+	fn problem(&self) -> String {
+		match &self.status {
+			Ok(status) => match status.metrics.recent_errors.first() {
+				Some(latest) => format!("{}: {}", latest.op, describe_status(latest.status)),
+				None => String::new(),
+			},
+			Err(message) => message.clone(),
+		}
+	}
+}
+
+struct Tray {
+	window: HWND,
+	mounts: Vec<MountView>,
+	// 上一次轮询时每个挂载的时间和传输量，用于计算速率
+	previous: BTreeMap<String, (Instant, u64, u64)>,
+}
+
+impl Tray {
+	fn poll(&mut self) {
+		let now = unix_now();
+		let polled = Instant::now();
+		let mut previous = BTreeMap::new();
+		let mut mounts = Vec::new();
+		for id in control::running_mounts().unwrap_or_default() {
+			let status = match control::send(&id, &Request::Status) {
+				Ok(Response::Status(status)) => Ok(status),
+				Ok(Response::Error { message }) => Err(message),
+				Ok(_) => Err("unexpected response".to_string()),
+				Err(e) => Err(e.to_string()),
+			};
+			let (mut read_rate, mut write_rate) = (0.0, 0.0);
+			let health = match &status {
+				Ok(status) => {
+					let metrics = &status.metrics;
+					if let Some((at, read, written)) = self.previous.get(&id) {
+						let secs = polled.duration_since(*at).as_secs_f64().max(0.001);
+						read_rate = metrics.bytes_read.saturating_sub(*read) as f64 / secs;
+						write_rate = metrics.bytes_written.saturating_sub(*written) as f64 / secs;
+					}
+					previous.insert(
+						id.clone(),
+						(polled, metrics.bytes_read, metrics.bytes_written),
+					);
+					health(status, now)
+				}
+				Err(_) => Health::NotResponding,
+			};
+			mounts.push(MountView {
+				id,
+				status,
+				health,
+				read_rate,
+				write_rate,
+			});
+		}
+
+		// 挂载状态变差或挂载消失时通知用户
+		for mount in &mounts {
+			let before = self
+				.mounts
+				.iter()
+				.find(|old| old.id == mount.id)
+				.map_or(Health::Healthy, |old| old.health);
+			if mount.health > before {
+				self.balloon(
+					&format!("{} - {}", mount.mount_point(), mount.health.label()),
+					&mount.problem(),
+					NIIF_WARNING,
+				);
+			}
+		}
+		for old in &self.mounts {
+			if !mounts.iter().any(|mount| mount.id == old.id) {
+				self.balloon(
+					old.mount_point(),
+					"The drive is no longer mounted.",
+					NIIF_INFO,
+				);
+			}
+		}
+		self.mounts = mounts;
+		self.previous = previous;
+	}
+
+	fn icon_data(&self) -> NOTIFYICONDATAW {
+		let mut data: NOTIFYICONDATAW = unsafe { mem::zeroed() };
+		data.cbSize = mem::size_of::<NOTIFYICONDATAW>() as DWORD;
+		data.hWnd = self.window;
+		data.uID = 1;
+		data
+	}
+
+	fn tooltip(&self) -> String {
+		let mut tip = String::from("httpfs");
+		if self.mounts.is_empty() {
+			tip.push_str("\nNo mounts running");
+		}
+		for mount in &self.mounts {
+			tip.push_str(&format!(
+				"\n{} {}",
+				mount.mount_point(),
+				mount.health.label()
+			));
+		}
+		tip
+	}
+
+	// 更新图标和提示文字；message 为 NIM_ADD 时重新添加图标
+	fn show(&self, message: DWORD) {
+		let icon = match self.mounts.iter().map(|mount| mount.health).max() {
+			None | Some(Health::Healthy) => IDI_APPLICATION,
+			Some(Health::Errors) => IDI_WARNING,
+			Some(_) => IDI_ERROR,
+		};
+		let mut data = self.icon_data();
+		data.uFlags = NIF_ICON | NIF_TIP | NIF_MESSAGE;
+		data.uCallbackMessage = WM_TRAY;
+		data.hIcon = unsafe { LoadIconW(ptr::null_mut(), icon) };
+		copy_wide(&mut data.szTip, &self.tooltip());
+		unsafe { Shell_NotifyIconW(message, &mut data) };
+	}
+
+	fn balloon(&self, title: &str, text: &str, flags: DWORD) {
+		let mut data = self.icon_data();
+		data.uFlags = NIF_INFO;
+		data.dwInfoFlags = flags;
+		copy_wide(&mut data.szInfoTitle, title);
+		copy_wide(&mut data.szInfo, text);
+		unsafe { Shell_NotifyIconW(NIM_MODIFY, &mut data) };
+	}
+
+	fn menu(&self) -> HMENU {
+		let now = unix_now();
+		let menu = unsafe { CreatePopupMenu() };
+		if self.mounts.is_empty() {
+			append(menu, MF_STRING | MF_GRAYED, 0, "No mounts running");
+		}
+		for (index, mount) in self.mounts.iter().enumerate() {
+			let submenu = unsafe { CreatePopupMenu() };
+			match &mount.status {
+				Ok(status) => {
+					let server = match &status.share {
+						Some(share) => format!("{} (share {})", status.server, share),
+						None => status.server.clone(),
+					};
+					append(submenu, MF_STRING | MF_GRAYED, 0, &server);
+					append(
+						submenu,
+						MF_STRING | MF_GRAYED,
+						0,
+						&format!("up {}", humantime_secs(status.uptime_secs)),
+					);
+					append(submenu, MF_STRING | MF_GRAYED, 0, &mount.rates());
+				}
+				Err(message) => append(submenu, MF_STRING | MF_GRAYED, 0, message),
+			}
+			append(submenu, MF_SEPARATOR, 0, "");
+			let base = ID_MOUNT_BASE + index * ACTIONS;
+			append(submenu, MF_STRING, base + ACTION_OPEN, "Open");
+			append(submenu, MF_STRING, base + ACTION_PURGE, "Purge cache");
+			append(submenu, MF_STRING, base + ACTION_UNMOUNT, "Unmount");
+			if let Ok(status) = &mount.status {
+				if !status.metrics.recent_errors.is_empty() {
+					append(submenu, MF_SEPARATOR, 0, "");
+					append(submenu, MF_STRING | MF_GRAYED, 0, "Recent errors");
+				}
+				for recent in status.metrics.recent_errors.iter().take(MENU_ERRORS) {
+					let line = format!(
+						"{} ago\t{}: {}",
+						humantime_secs(now.saturating_sub(recent.unix_secs)),
+						recent.op,
+						describe_status(recent.status)
+					);
+					append(submenu, MF_STRING | MF_GRAYED, 0, &line);
+				}
+			}
+			append(
+				menu,
+				MF_POPUP,
+				submenu as usize,
+				&format!("{}\t{}", mount.mount_point(), mount.health.label()),
+			);
+		}
+		append(menu, MF_SEPARATOR, 0, "");
+		append(menu, MF_STRING, ID_EXIT, "Exit");
+		menu
+	}
+}
+
+// 截断到缓冲区长度并以 0 结尾
+fn copy_wide(buffer: &mut [u16], text: &str) {
+	let len = buffer.len() - 1;
+	let mut end = 0;
+	for (slot, unit) in buffer[..len].iter_mut().zip(text.encode_utf16()) {
+		*slot = unit;
+		end += 1;
+	}
+	buffer[end] = 0;
+}
+
+// 菜单文字中的 & 表示快捷键，需要转义
+fn append(menu: HMENU, flags: UINT, id: usize, text: &str) {
+	let text = U16CString::from_str(text.replace('&', "&&")).unwrap_or_default();
+	unsafe { AppendMenuW(menu, flags, id, text.as_ptr()) };
+}
+
+fn wide(s: &str) -> U16CString {
+	U16CString::from_str(s).unwrap_or_default()
+}
+
+// 窗口过程可能在菜单或对话框的消息循环中重入，此时跳过而不是重复借用状态
+fn with_tray<T>(f: impl FnOnce(&mut Tray) -> T) -> Option<T> {
+	TRAY.with(|tray| tray.try_borrow_mut().ok()?.as_mut().map(f))
+}
+
+fn alert(window: HWND, text: &str, flags: UINT) -> i32 {
+	let (text, caption) = (wide(text), wide("httpfs"));
+	unsafe { MessageBoxW(window, text.as_ptr(), caption.as_ptr(), flags) }
+}
+
+fn show_menu(window: HWND) {
+	// 菜单打开期间定时器仍会刷新状态，先记下菜单对应的挂载
+	let Some((menu, targets)) = with_tray(|tray| {
+		let targets: Vec<(String, String)> = tray
+			.mounts
+			.iter()
+			.map(|mount| (mount.id.clone(), mount.mount_point().to_string()))
+			.collect();
+		(tray.menu(), targets)
+	}) else {
+		return;
+	};
+	let mut cursor = POINT { x: 0, y: 0 };
+	let command = unsafe {
+		GetCursorPos(&mut cursor);
+		// 不先切到前台时，点击菜单以外的地方菜单不会关闭
+		SetForegroundWindow(window);
+		let command = TrackPopupMenu(
+			menu,
+			TPM_RETURNCMD | TPM_RIGHTBUTTON,
+			cursor.x,
+			cursor.y,
+			0,
+			window,
+			ptr::null(),
+		);
+		PostMessageW(window, WM_NULL, 0, 0);
+		DestroyMenu(menu);
+		command as usize
+	};
+	match command {
+		0 => {}
+		ID_EXIT => unsafe {
+			DestroyWindow(window);
+		},
+		command => {
+			let (index, action) = (
+				(command - ID_MOUNT_BASE) / ACTIONS,
+				(command - ID_MOUNT_BASE) % ACTIONS,
+			);
+			if let Some((id, mount_point)) = targets.get(index) {
+				run_action(window, id, mount_point, action);
+			}
+		}
+	}
+}
+
+fn run_action(window: HWND, id: &str, mount_point: &str, action: usize) {
+	match action {
+		ACTION_OPEN => {
+			let (verb, path) = (wide("open"), wide(mount_point));
+			let result = unsafe {
+				ShellExecuteW(
+					window,
+					verb.as_ptr(),
+					path.as_ptr(),
+					ptr::null(),
+					ptr::null(),
+					SW_SHOWNORMAL,
+				)
+			};
+			// 返回值不大于 32 表示失败
+			if result as usize <= 32 {
+				alert(
+					window,
+					&format!(
+						"Cannot open {}: {}",
+						mount_point,
+						io::Error::last_os_error()
+					),
+					MB_OK | MB_ICONERROR,
+				);
+			}
+		}
+		ACTION_PURGE => match control_send(id, &Request::CachePurge) {
+			Ok(Response::Purged { entries }) => {
+				with_tray(|tray| {
+					tray.balloon(
+						mount_point,
+						&format!("Purged {} cached entries.", entries),
+						NIIF_INFO,
+					)
+				});
+			}
+			Ok(_) => {}
+			Err(e) => {
+				alert(window, &e.to_string(), MB_OK | MB_ICONERROR);
+			}
+		},
+		ACTION_UNMOUNT => {
+			let question = format!(
+				"Unmount {}?\n\nPrograms with files open on it may lose unsaved changes.",
+				mount_point
+			);
+			if alert(window, &question, MB_YESNO | MB_ICONWARNING) != IDYES {
+				return;
+			}
+			if let Err(e) = control_send(id, &Request::Unmount) {
+				alert(window, &e.to_string(), MB_OK | MB_ICONERROR);
+			}
+		}
+		_ => {}
+	}
+}
+
+fn refresh() {
+	with_tray(|tray| {
+		tray.poll();
+		tray.show(NIM_MODIFY);
+	});
+}
+
+unsafe extern "system" fn window_proc(
+	window: HWND,
+	message: UINT,
+	wparam: WPARAM,
+	lparam: LPARAM,
+) -> LRESULT {
+	match message {
+		WM_TIMER => refresh(),
+		WM_TRAY => {
+			if matches!(lparam as UINT, WM_LBUTTONUP | WM_RBUTTONUP) {
+				show_menu(window);
+			}
+		}
+		WM_DESTROY => {
+			with_tray(|tray| {
+				let mut data = tray.icon_data();
+				Shell_NotifyIconW(NIM_DELETE, &mut data);
+			});
+			PostQuitMessage(0);
+		}
+		message if message != 0 && message == TASKBAR_CREATED.load(Ordering::Relaxed) => {
+			with_tray(|tray| tray.show(NIM_ADD));
+		}
+		_ => return DefWindowProcW(window, message, wparam, lparam),
+	}
+	0
+}
+
+// 从资源管理器或启动文件夹运行时不保留控制台窗口；在已有的命令提示符中运行时控制台属于其他进程，不受影响
+fn hide_console() {
+	let mut processes: [DWORD; 2] = [0; 2];
+	unsafe {
+		if GetConsoleProcessList(processes.as_mut_ptr(), processes.len() as DWORD) == 1 {
+			FreeConsole();
+		}
+	}
+}
+
+// 在通知区域显示本机所有运行中挂载的状态，直到从菜单退出
+pub fn run() -> io::Result<()> {
+	hide_console();
+	let class = wide("httpfs-tray");
+	unsafe {
+		let instance = GetModuleHandleW(ptr::null());
+		let mut class_info: WNDCLASSW = mem::zeroed();
+		class_info.lpfnWndProc = Some(window_proc);
+		class_info.hInstance = instance;
+		class_info.lpszClassName = class.as_ptr();
+		if RegisterClassW(&class_info) == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		// 不显示的顶层窗口，只用于接收图标的消息；仅用于消息的窗口收不到 TaskbarCreated 广播
+		let window = CreateWindowExW(
+			0,
+			class.as_ptr(),
+			class.as_ptr(),
+			0,
+			0,
+			0,
+			0,
+			0,
+			ptr::null_mut(),
+			ptr::null_mut(),
+			instance,
+			ptr::null_mut(),
+		);
+		if window.is_null() {
+			return Err(io::Error::last_os_error());
+		}
+		TASKBAR_CREATED.store(
+			RegisterWindowMessageW(wide("TaskbarCreated").as_ptr()),
+			Ordering::Relaxed,
+		);
+
+		let mut tray = Tray {
+			window,
+			mounts: Vec::new(),
+			previous: BTreeMap::new(),
+		};
+		tray.poll();
+		tray.show(NIM_ADD);
+		TRAY.with(|cell| *cell.borrow_mut() = Some(tray));
+		SetTimer(window, TIMER_ID, REFRESH_MS, None);
+
+		let mut message: MSG = mem::zeroed();
+		while GetMessageW(&mut message, ptr::null_mut(), 0, 0) > 0 {
+			TranslateMessage(&message);
+			DispatchMessageW(&message);
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,202 @@
+use std::{fs, io, path::Path};
+
+use serde::{Deserialize, Serialize};
+use winapi::um::winnt::{
+	ACCESS_MASK, DELETE, FILE_APPEND_DATA, FILE_EXECUTE, FILE_READ_DATA, FILE_WRITE_ATTRIBUTES,
+	FILE_WRITE_DATA, FILE_WRITE_EA, GENERIC_ALL, GENERIC_EXECUTE, GENERIC_READ, GENERIC_WRITE,
+	MAXIMUM_ALLOWED, WRITE_DAC, WRITE_OWNER,
+};
+
+use crate::requester::Requester;
+
+/// 访问规则控制的操作。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Right {
+	Read,
+	Write,
+	Delete,
+	Execute,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+	Allow,
+	Deny,
+}
+
+/// 一条访问规则。`path` 为共享内路径的通配符：`*` 和 `?` 不跨越 `/`，`**` 匹配任意层目录；
+/// 以 `/` 开头的模式从共享根目录匹配，否则（如 `*.exe`）匹配任意目录下的条目。
+/// 设置了 `process`（可执行文件名，可以带通配符）或 `user`（SID）时只对这样的请求者生效。大小写不敏感。
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+	pub path: String,
+	pub action: Action,
+	// 未给出时规则适用于所有操作
+	#[serde(default)]
+	pub access: Vec<Right>,
+	pub process: Option<String>,
+	pub user: Option<String>,
+}
+
+impl Rule {
+	fn applies(&self, path: &str, right: Right, requester: &Requester) -> bool {
+		(self.access.is_empty() || self.access.contains(&right))
+			&& path_matches(&self.path, path)
+			&& self.process.as_ref().map_or(true, |pattern| {
+				requester
+					.process_name
+					.as_deref()
+					.is_some_and(|name| name_matches(pattern, name))
+			}) && self.user.as_ref().map_or(true, |sid| {
+			requester
+				.user_sid
+				.as_deref()
+				.is_some_and(|user| user.eq_ignore_ascii_case(sid))
+		})
+	}
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RulesFile {
+	#[serde(default)]
+	rule: Vec<Rule>,
+}
+
+/// 打开文件时检查的访问规则：对请求的每种操作，按顺序第一条适用的规则决定允许还是拒绝，没有适用的规则时允许。
+///
+/// ```toml
+/// [[rule]]
+/// path = "*.exe"
+/// access = ["execute"]
+/// action = "deny"
+///
+/// [[rule]]
+/// path = "/releases/**"
+/// access = ["write", "delete"]
+/// process = "robocopy.exe"
+/// action = "allow"
+///
+/// [[rule]]
+/// path = "/releases/**"
+/// access = ["write", "delete"]
+/// action = "deny"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct AccessRules {
+	rules: Vec<Rule>,
+}
+
+impl AccessRules {
+	pub fn new(rules: Vec<Rule>) -> Self {
+		Self { rules }
+	}
+
+	/// 读取 TOML 格式的规则文件，每条规则为一个 `[[rule]]` 表。
+	pub fn load(path: &Path) -> io::Result<Self> {
+		let text = fs::read_to_string(path)?;
+		let file: RulesFile = toml::from_str(&text).map_err(|e| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("invalid access rules {}: {}", path.display(), e),
+			)
+		})?;
+		Ok(Self::new(file.rule))
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.rules.is_empty()
+	}
+
+	/// 请求者能否对 `path` 执行所有 `rights`；返回第一个被拒绝的操作。
+	pub fn check(&self, path: &str, rights: &[Right], requester: &Requester) -> Result<(), Right> {
+		for &right in rights {
+			let rule = self
+				.rules
+				.iter()
+				.find(|rule| rule.applies(path, right, requester));
+			if rule.is_some_and(|rule| rule.action == Action::Deny) {
+				return Err(right);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// 打开文件时请求的操作：`desired_access` 中的读、写、删除和执行权限，以及会创建或覆盖文件的打开方式（写）和关闭时删除（删除）。
+pub fn requested_rights(
+	desired_access: ACCESS_MASK,
+	creates: bool,
+	delete_on_close: bool,
+) -> Vec<Right> {
+	let mut rights = Vec::new();
+	let all = desired_access & (GENERIC_ALL | MAXIMUM_ALLOWED) != 0;
+	if all || desired_access & (GENERIC_READ | FILE_READ_DATA) != 0 {
+		rights.push(Right::Read);
+	}
+	if all
+		|| creates
+		|| desired_access
+			& (GENERIC_WRITE
+				| FILE_WRITE_DATA
+				| FILE_APPEND_DATA
+				| FILE_WRITE_EA
+				| FILE_WRITE_ATTRIBUTES
+				| WRITE_DAC | WRITE_OWNER)
+			!= 0
+	{
+		rights.push(Right::Write);
+	}
+	if all || delete_on_close || desired_access & DELETE != 0 {
+		rights.push(Right::Delete);
+	}
+	if all || desired_access & (GENERIC_EXECUTE | FILE_EXECUTE) != 0 {
+		rights.push(Right::Execute);
+	}
+	rights
+}
+
+// 进程名等不含路径的名称是否匹配通配符，`*` 和 `?` 匹配任意字符，大小写不敏感。
+pub(crate) fn name_matches(pattern: &str, name: &str) -> bool {
+	glob_match(&chars(pattern), &chars(name), false)
+}
+
+fn chars(text: &str) -> Vec<char> {
+	text.chars().flat_map(char::to_lowercase).collect()
+}
+
+// 共享内路径（以 / 分隔，根目录为 "."）是否匹配规则的模式
+pub(crate) fn path_matches(pattern: &str, path: &str) -> bool {
+	let path = if path == "." { "" } else { path };
+	match pattern.strip_prefix('/') {
+		Some(anchored) => glob_match(&chars(anchored), &chars(path), true),
+		None => {
+			glob_match(&chars(&format!("**/{}", pattern)), &chars(path), true)
+				|| glob_match(&chars(pattern), &chars(path), true)
+		}
+	}
+}
+
+// 通配符匹配；in_path 为 true 时 * 和 ? 不匹配 /，** 匹配任意字符，**/ 还可以匹配空串
+fn glob_match(pattern: &[char], text: &[char], in_path: bool) -> bool {
+	match pattern {
+		[] => text.is_empty(),
+		['*', '*', '/', rest @ ..] if in_path => (0..=text.len()).any(|skip| {
+			(skip == 0 || text[skip - 1] == '/') && glob_match(rest, &text[skip..], in_path)
+		}),
+		['*', '*', rest @ ..] if in_path => {
+			(0..=text.len()).any(|skip| glob_match(rest, &text[skip..], in_path))
+		}
+		['*', rest @ ..] => (0..=text.len())
+			.take_while(|&skip| skip == 0 || !in_path || text[skip - 1] != '/')
+			.any(|skip| glob_match(rest, &text[skip..], in_path)),
+		['?', rest @ ..] => {
+			text.first().is_some_and(|&c| !in_path || c != '/')
+				&& glob_match(rest, &text[1..], in_path)
+		}
+		[c, rest @ ..] => text.first() == Some(c) && glob_match(rest, &text[1..], in_path),
+	}
+}
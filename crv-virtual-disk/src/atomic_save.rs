@@ -0,0 +1,724 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Condvar, Mutex},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{debug, error, warn};
+
+use crate::{
+	access, backend::BatchFile, error::RemoteError, identity, vfs, ChecksumResponse, ListPage,
+	RemoteFileInfo, RestoreResponse, SearchResponse, SpaceResponse, StorageBackend, TimesUpdate,
+	TrashEntry, VersionInfo, XattrEntry,
+};
+
+/// 未设置时视为保存过程中临时文件的名称：Word 的 `~WRL0001.tmp`、PowerPoint 的 `ppt1A2B.tmp` 等。
+pub const DEFAULT_PATTERNS: &[&str] = &["*.tmp"];
+
+// 临时文件和改名的原文件最多在本地保留这么久，之后按原来的操作发送到存储
+const WINDOW: Duration = Duration::from_secs(10);
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs())
+}
+
+fn base_name(path: &str) -> &str {
+	path.rsplit('/').next().unwrap_or(path)
+}
+
+fn parent(path: &str) -> &str {
+	path.rsplit_once('/').map_or(".", |(parent, _)| parent)
+}
+
+fn not_found(path: &str) -> RemoteError {
+	RemoteError::backend("not_found", format!("{} not found", path))
+}
+
+fn renamed(info: &RemoteFileInfo, path: &str) -> RemoteFileInfo {
+	RemoteFileInfo {
+		name: base_name(path).to_string(),
+		..info.clone()
+	}
+}
+
+// 只在本地的临时文件：新建后的内容和时间戳
+struct Held {
+	data: Vec<u8>,
+	info: RemoteFileInfo,
+	times: TimesUpdate,
+	since: Instant,
+	// 创建者代表的用户，到期后以该用户的身份提交
+	user: Option<String>,
+}
+
+impl Held {
+	fn new(path: &str) -> Self {
+		let now = now_secs();
+		Self {
+			data: Vec::new(),
+			info: RemoteFileInfo {
+				name: base_name(path).to_string(),
+				is_directory: false,
+				size: 0,
+				created: now,
+				modified: now,
+				accessed: now,
+				stored_size: None,
+				allocated_size: None,
+			},
+			times: TimesUpdate::default(),
+			since: Instant::now(),
+			user: identity::current_user(),
+		}
+	}
+
+	fn changed(&mut self) {
+		self.info.size = self.data.len() as u64;
+		self.info.modified = now_secs();
+	}
+}
+
+// 原文件被改名为临时名称：存储中仍在原路径 target，本地显示为临时名称。replaced 表示原路径已被新内容原子替换，
+// 改名前的内容只在存储的历史版本中
+struct Backup {
+	target: String,
+	info: RemoteFileInfo,
+	replaced: bool,
+	since: Instant,
+	user: Option<String>,
+}
+
+#[derive(Default)]
+struct State {
+	held: HashMap<String, Held>,
+	backups: HashMap<String, Backup>,
+	closed: bool,
+}
+
+impl State {
+	// 被改名为临时名称、还没有被替换的原文件
+	fn is_hidden(&self, path: &str) -> bool {
+		self.backups
+			.values()
+			.any(|backup| !backup.replaced && backup.target == path)
+	}
+
+	fn backup_of(&self, target: &str) -> Option<String> {
+		self.backups
+			.iter()
+			.find(|(_, backup)| !backup.replaced && backup.target == target)
+			.map(|(path, _)| path.clone())
+	}
+
+	fn involves(&self, path: &str) -> bool {
+		self.held.contains_key(path) || self.backups.contains_key(path) || self.is_hidden(path)
+	}
+}
+
+struct Shared {
+	inner: Arc<dyn StorageBackend>,
+	patterns: Vec<String>,
+	state: Mutex<State>,
+	closed: Condvar,
+}
+
+/// 识别 Office 等程序“写临时文件 + 改名 + 删除”的保存方式，把它转换为一次原子替换。
+///
+/// Word 保存 `doc.docx` 时先写入 `~WRL0001.tmp`，把 `doc.docx` 改名为 `~WRL0002.tmp`，再把 `~WRL0001.tmp` 改名为 `doc.docx`，
+/// 最后删除 `~WRL0002.tmp`。直接发送到存储时这是三次上传或改名，原文件的历史版本随改名移到被删除的备份上。
+/// 名称匹配 `patterns` 的新文件先只保留在本地，原文件改名为这样的名称时存储中的原文件不动；临时文件随后被改名为原文件时，
+/// 以一次原子写入替换原文件（存储为它保存一个历史版本），改名后的原文件被删除时不再请求存储。
+/// 在 [`WINDOW`] 内没有完成这个过程的操作按原样发送到存储；释放时发送所有未完成的操作。
+pub(crate) struct AtomicSaveBackend {
+	shared: Arc<Shared>,
+	flusher: Option<JoinHandle<()>>,
+}
+
+impl AtomicSaveBackend {
+	pub(crate) fn new(inner: Arc<dyn StorageBackend>, patterns: Vec<String>) -> Self {
+		let shared = Arc::new(Shared {
+			inner,
+			patterns,
+			state: Mutex::new(State::default()),
+			closed: Condvar::new(),
+		});
+		let flusher = {
+			let shared = shared.clone();
+			thread::Builder::new()
+				.name("atomic-save".to_string())
+				.spawn(move || shared.run())
+				.expect("cannot spawn atomic save flusher")
+		};
+		Self {
+			shared,
+			flusher: Some(flusher),
+		}
+	}
+}
+
+impl Shared {
+	fn is_temporary(&self, path: &str) -> bool {
+		let name = base_name(path);
+		self.patterns
+			.iter()
+			.any(|pattern| access::name_matches(pattern, name))
+	}
+
+	// 定期把超过时间的临时文件和改名发送到存储
+	fn run(&self) {
+		let mut state = self.state.lock().unwrap();
+		while !state.closed {
+			state = self
+				.closed
+				.wait_timeout(state, Duration::from_secs(1))
+				.unwrap()
+				.0;
+			let held: Vec<String> = state
+				.held
+				.iter()
+				.filter(|(_, held)| held.since.elapsed() >= WINDOW)
+				.map(|(path, _)| path.clone())
+				.collect();
+			let backups: Vec<String> = state
+				.backups
+				.iter()
+				.filter(|(_, backup)| backup.since.elapsed() >= WINDOW)
+				.map(|(path, _)| path.clone())
+				.collect();
+			if held.is_empty() && backups.is_empty() {
+				continue;
+			}
+			drop(state);
+			for path in held.iter().chain(&backups) {
+				self.flush(path);
+			}
+			state = self.state.lock().unwrap();
+		}
+	}
+
+	// 把 path 相关的本地状态按原来的操作发送到存储
+	fn flush(&self, path: &str) {
+		let (held, backup) = {
+			let mut state = self.state.lock().unwrap();
+			let key = state.backup_of(path).unwrap_or_else(|| path.to_string());
+			(
+				state.held.remove(path),
+				state.backups.remove(&key).map(|backup| (key, backup)),
+			)
+		};
+		if let Some(held) = held {
+			if let Err(e) =
+				identity::with_user(held.user.as_deref(), || self.materialize(path, &held))
+			{
+				error!(path = %path, error = %e, "committing a held temporary file failed");
+			}
+		}
+		if let Some((path, backup)) = backup {
+			if let Err(e) = identity::with_user(backup.user.as_deref(), || {
+				self.restore_backup(&path, &backup)
+			}) {
+				error!(path = %path, target = %backup.target, error = %e, "renaming the saved file failed");
+			}
+		}
+	}
+
+	fn flush_all(&self) {
+		let paths: Vec<String> = {
+			let state = self.state.lock().unwrap();
+			state
+				.held
+				.keys()
+				.chain(state.backups.keys())
+				.cloned()
+				.collect()
+		};
+		for path in paths {
+			self.flush(&path);
+		}
+	}
+
+	fn materialize(&self, path: &str, held: &Held) -> Result<(), RemoteError> {
+		self.inner.commit(path, &held.data)?;
+		if !held.times.is_empty() {
+			self.inner.set_times(path, &held.times)?;
+		}
+		Ok(())
+	}
+
+	// 原文件没有被替换时在存储中完成改名；已被替换时从历史版本恢复改名前的内容
+	fn restore_backup(&self, path: &str, backup: &Backup) -> Result<(), RemoteError> {
+		if !backup.replaced {
+			return self.inner.rename(&backup.target, path, false);
+		}
+		let version = self
+			.inner
+			.list_versions(&backup.target)?
+			.into_iter()
+			.max_by(|a, b| (a.modified, &a.id).cmp(&(b.modified, &b.id)))
+			.ok_or_else(|| {
+				RemoteError::backend(
+					"not_found",
+					format!("no saved version of {}", backup.target),
+				)
+			})?;
+		let data =
+			self.inner
+				.read_version(&backup.target, &version.id, 0, version.size as usize)?;
+		self.inner.commit(path, &data)
+	}
+
+	// 临时文件改名为 new_path：以一次原子写入提交到新路径
+	fn commit_held(
+		&self,
+		old_path: &str,
+		new_path: &str,
+		replace: bool,
+	) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let backup = state.backup_of(new_path);
+		if backup.is_none() {
+			drop(state);
+			match self.inner.stat(new_path) {
+				Ok(info) if info.is_directory => {
+					return Err(RemoteError::backend(
+						"type_mismatch",
+						format!("{} is a directory", new_path),
+					))
+				}
+				Ok(_) if !replace => {
+					return Err(RemoteError::backend(
+						"already_exists",
+						format!("{} already exists", new_path),
+					))
+				}
+				Ok(_) => {}
+				Err(e) if e.code() == Some("not_found") => {}
+				Err(e) => return Err(e),
+			}
+			state = self.state.lock().unwrap();
+		}
+		let Some(held) = state.held.remove(old_path) else {
+			return Err(not_found(old_path));
+		};
+		drop(state);
+		if let Err(e) = self.materialize(new_path, &held) {
+			self.state
+				.lock()
+				.unwrap()
+				.held
+				.insert(old_path.to_string(), held);
+			return Err(e);
+		}
+		if let Some(backup) = &backup {
+			if let Some(backup) = self.state.lock().unwrap().backups.get_mut(backup) {
+				backup.replaced = true;
+			}
+			debug!(path = %new_path, temporary = %old_path, "atomic save detected");
+		}
+		Ok(())
+	}
+}
+
+impl Drop for AtomicSaveBackend {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().closed = true;
+		self.shared.closed.notify_all();
+		if let Some(flusher) = self.flusher.take() {
+			let _ = flusher.join();
+		}
+		self.shared.flush_all();
+	}
+}
+
+impl AtomicSaveBackend {
+	// 对 path 做其他操作前先把它的本地状态发送到存储
+	fn settle(&self, path: &str) {
+		if self.shared.state.lock().unwrap().involves(path) {
+			self.shared.flush(path);
+		}
+	}
+
+	fn with_held<T>(&self, path: &str, apply: impl FnOnce(&mut Held) -> T) -> Option<T> {
+		self.shared
+			.state
+			.lock()
+			.unwrap()
+			.held
+			.get_mut(path)
+			.map(apply)
+	}
+}
+
+impl StorageBackend for AtomicSaveBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		{
+			let state = self.shared.state.lock().unwrap();
+			if let Some(held) = state.held.get(path) {
+				return Ok(held.info.clone());
+			}
+			if let Some(backup) = state.backups.get(path) {
+				return Ok(backup.info.clone());
+			}
+			if state.is_hidden(path) {
+				return Err(not_found(path));
+			}
+		}
+		self.shared.inner.stat(path)
+	}
+
+	// 最后一页中加入本地的临时文件，去掉被改名的原文件
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let mut page = self.shared.inner.list_page(path, cursor)?;
+		let state = self.shared.state.lock().unwrap();
+		page.items.retain(|item| {
+			let child = vfs::child_path(path, &item.name);
+			!state.is_hidden(&child)
+				&& !state.held.contains_key(&child)
+				&& !state.backups.contains_key(&child)
+		});
+		if page.next_cursor.is_none() {
+			page.items.extend(
+				state
+					.held
+					.iter()
+					.filter(|(child, _)| parent(child) == path)
+					.map(|(_, held)| held.info.clone()),
+			);
+			page.items.extend(
+				state
+					.backups
+					.iter()
+					.filter(|(child, _)| parent(child) == path)
+					.map(|(_, backup)| backup.info.clone()),
+			);
+		}
+		Ok(page)
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let target = {
+			let state = self.shared.state.lock().unwrap();
+			if let Some(held) = state.held.get(path) {
+				let start = (offset as usize).min(held.data.len());
+				let end = start.saturating_add(length).min(held.data.len());
+				return Ok(held.data[start..end].to_vec());
+			}
+			if state.is_hidden(path) {
+				return Err(not_found(path));
+			}
+			state
+				.backups
+				.get(path)
+				.map(|backup| (backup.target.clone(), backup.replaced))
+		};
+		match target {
+			Some((target, false)) => self.shared.inner.read(&target, offset, length),
+			Some((_, true)) => {
+				self.settle(path);
+				self.shared.inner.read(path, offset, length)
+			}
+			None => self.shared.inner.read(path, offset, length),
+		}
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		let written = self.with_held(path, |held| {
+			let end = offset as usize + data.len();
+			if held.data.len() < end {
+				held.data.resize(end, 0);
+			}
+			held.data[offset as usize..end].copy_from_slice(data);
+			held.changed();
+		});
+		if written.is_some() {
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.write(path, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let committed = self.with_held(path, |held| {
+			held.data = data.to_vec();
+			held.changed();
+		});
+		if committed.is_some() {
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.commit(path, data)
+	}
+
+	// 名称匹配的新文件先只在本地创建
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		if !is_directory && self.shared.is_temporary(path) {
+			let mut state = self.shared.state.lock().unwrap();
+			if state.involves(path) {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", path),
+				));
+			}
+			state.held.insert(path.to_string(), Held::new(path));
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		{
+			let mut state = self.shared.state.lock().unwrap();
+			if state.held.contains_key(path) {
+				if !dry_run {
+					state.held.remove(path);
+				}
+				return Ok(());
+			}
+			if state.is_hidden(path) {
+				return Err(not_found(path));
+			}
+			if let Some(backup) = state.backups.get(path) {
+				// 已被替换的原文件只在历史版本中，删除时无需请求存储；没有被替换时删除存储中的原文件
+				let target = (!backup.replaced).then(|| backup.target.clone());
+				if dry_run {
+					drop(state);
+					return match target {
+						Some(target) => self.shared.inner.delete(&target, true),
+						None => Ok(()),
+					};
+				}
+				state.backups.remove(path);
+				drop(state);
+				return match target {
+					Some(target) => self.shared.inner.delete(&target, false),
+					None => Ok(()),
+				};
+			}
+		}
+		self.shared.inner.delete(path, dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let mut state = self.shared.state.lock().unwrap();
+		// 临时文件改名为另一个临时名称
+		if state.held.contains_key(old_path)
+			&& self.shared.is_temporary(new_path)
+			&& !state.involves(new_path)
+		{
+			let mut held = state.held.remove(old_path).expect("checked above");
+			held.info.name = base_name(new_path).to_string();
+			state.held.insert(new_path.to_string(), held);
+			return Ok(());
+		}
+		// 临时文件改名为原文件：一次原子写入
+		if state.held.contains_key(old_path)
+			&& !state.held.contains_key(new_path)
+			&& !state.backups.contains_key(new_path)
+		{
+			drop(state);
+			return self.shared.commit_held(old_path, new_path, replace);
+		}
+		// 改名后的原文件改回原来的名称
+		if state
+			.backups
+			.get(old_path)
+			.is_some_and(|backup| !backup.replaced && backup.target == new_path)
+		{
+			state.backups.remove(old_path);
+			return Ok(());
+		}
+		// 原文件改名为临时名称：存储中的原文件暂时不动
+		if self.shared.is_temporary(new_path)
+			&& !self.shared.is_temporary(old_path)
+			&& !state.involves(old_path)
+			&& !state.involves(new_path)
+		{
+			drop(state);
+			let info = self.shared.inner.stat(old_path)?;
+			if !info.is_directory {
+				let exists = match self.shared.inner.stat(new_path) {
+					Ok(_) => true,
+					Err(e) if e.code() == Some("not_found") => false,
+					Err(e) => return Err(e),
+				};
+				if !exists {
+					let backup = Backup {
+						target: old_path.to_string(),
+						info: renamed(&info, new_path),
+						replaced: false,
+						since: Instant::now(),
+						user: identity::current_user(),
+					};
+					self.shared
+						.state
+						.lock()
+						.unwrap()
+						.backups
+						.insert(new_path.to_string(), backup);
+					return Ok(());
+				}
+			}
+			return self.shared.inner.rename(old_path, new_path, replace);
+		}
+		let involved = state.involves(old_path) || state.involves(new_path);
+		drop(state);
+		if involved {
+			warn!(
+				path = %old_path,
+				new_path = %new_path,
+				"unexpected rename during a save, sending pending operations to storage"
+			);
+			self.shared.flush(old_path);
+			self.shared.flush(new_path);
+		}
+		self.shared.inner.rename(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let truncated = self.with_held(path, |held| {
+			held.data.resize(size as usize, 0);
+			held.changed();
+		});
+		if truncated.is_some() {
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		let set = self.with_held(path, |held| {
+			held.times.merge(*times);
+			held.info.created = times.created.unwrap_or(held.info.created);
+			held.info.accessed = times.accessed.unwrap_or(held.info.accessed);
+			held.info.modified = times.modified.unwrap_or(held.info.modified);
+		});
+		if set.is_some() {
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.set_times(path, times)
+	}
+
+	// 本地的临时文件单独处理，其余文件仍然一起提交
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		let held: Vec<bool> = {
+			let state = self.shared.state.lock().unwrap();
+			files
+				.iter()
+				.map(|file| state.held.contains_key(file.path))
+				.collect()
+		};
+		let others: Vec<BatchFile> = files
+			.iter()
+			.zip(&held)
+			.filter(|(_, held)| !**held)
+			.map(|(file, _)| *file)
+			.collect();
+		for file in &others {
+			self.settle(file.path);
+		}
+		let mut results = self.shared.inner.commit_batch(&others)?.into_iter();
+		Ok(files
+			.iter()
+			.zip(held)
+			.map(|(file, held)| {
+				if held {
+					self.commit(file.path, file.data)
+						.and_then(|()| self.set_times(file.path, &file.times))
+				} else {
+					results.next().unwrap_or_else(|| {
+						Err(RemoteError::backend("io_error", "missing batch result"))
+					})
+				}
+			})
+			.collect())
+	}
+
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		let zeroed = self.with_held(path, |held| {
+			let start = (offset as usize).min(held.data.len());
+			let end = (offset.saturating_add(length) as usize).min(held.data.len());
+			held.data[start..end].fill(0);
+			held.changed();
+		});
+		if zeroed.is_some() {
+			return Ok(());
+		}
+		self.settle(path);
+		self.shared.inner.zero_range(path, offset, length)
+	}
+
+	fn read_only(&self) -> bool {
+		self.shared.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.shared.inner.space()
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		self.shared.inner.search(path, pattern, recursive)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		self.settle(path);
+		self.shared.inner.list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		self.settle(path);
+		self.shared.inner.get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.settle(path);
+		self.shared.inner.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.settle(path);
+		self.shared.inner.delete_xattr(path, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		self.settle(path);
+		self.shared.inner.list_versions(path)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		self.settle(path);
+		self.shared.inner.read_version(path, id, offset, length)
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		self.shared.inner.list_trash()
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		self.shared.inner.restore_trash(id, path)
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		self.settle(path);
+		self.shared.inner.checksum(path)
+	}
+}
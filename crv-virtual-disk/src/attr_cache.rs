@@ -0,0 +1,306 @@
+use std::{
+	collections::{HashMap, HashSet},
+	mem,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{access, RemoteFileInfo};
+
+// 没有设置条目数上限时，缓存的条目超过该数量时清理已过期的条目
+const CLEANUP_THRESHOLD: usize = 10_000;
+
+// 超过上限时一次换出到上限的这个比例以下，避免之后每次插入都要换出
+const EVICT_TO_PERCENT: usize = 90;
+
+// 每个条目除路径和名称外大约占用的内存
+const ENTRY_OVERHEAD: u64 = (mem::size_of::<Entry>() + 64) as u64;
+
+/// 缓存满时选择换出哪些条目。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Eviction {
+	/// 最久未使用的条目
+	#[default]
+	Lru,
+	/// 命中次数最少的条目，次数相同时最久未使用的
+	Lfu,
+	/// 只清理过期的条目，仍然满时不再缓存新条目
+	TtlOnly,
+}
+
+/// 缓存的容量和换出方式。`pinned` 中的路径模式（与访问规则相同的写法，如 `/projects/**`）匹配的条目不会被换出，
+/// 也不会过期，只在本客户端修改或服务器推送变化时失效。
+#[derive(Debug, Clone, Default)]
+pub struct CacheLimits {
+	pub max_entries: Option<usize>,
+	pub max_bytes: Option<u64>,
+	pub eviction: Eviction,
+	pub pinned: Vec<String>,
+}
+
+impl CacheLimits {
+	fn is_pinned(&self, path: &str) -> bool {
+		self.pinned
+			.iter()
+			.any(|pattern| access::path_matches(pattern, path))
+	}
+
+	fn is_over(&self, entries: usize, bytes: u64) -> bool {
+		self.max_entries.is_some_and(|max| entries > max)
+			|| self.max_bytes.is_some_and(|max| bytes > max)
+	}
+}
+
+struct Entry {
+	info: RemoteFileInfo,
+	stored: Instant,
+	// 最近一次使用时的序号
+	used: u64,
+	hits: u64,
+	bytes: u64,
+	pinned: bool,
+}
+
+#[derive(Default)]
+struct Entries {
+	map: HashMap<String, Entry>,
+	bytes: u64,
+	clock: u64,
+}
+
+impl Entries {
+	fn remove(&mut self, path: &str) {
+		if let Some(entry) = self.map.remove(path) {
+			self.bytes -= entry.bytes;
+		}
+	}
+
+	fn retain(&mut self, mut keep: impl FnMut(&str, &Entry) -> bool) {
+		let bytes = &mut self.bytes;
+		self.map.retain(|path, entry| {
+			let kept = keep(path, entry);
+			if !kept {
+				*bytes -= entry.bytes;
+			}
+			kept
+		});
+	}
+}
+
+// 远程文件信息的短期缓存。资源管理器列出目录后会逐个打开条目查询属性，
+// 列目录时预先填充的信息让这些查询无需再请求 /info。本客户端的修改和服务器推送的变化会使相关条目失效，
+// 其他客户端造成的变化在未订阅事件时最多延迟 ttl 才能看到
+pub struct AttrCache {
+	ttl: Duration,
+	limits: Mutex<CacheLimits>,
+	entries: Mutex<Entries>,
+	// 持有服务器租约的路径，条目不过期，直到租约被召回
+	leased: Mutex<HashSet<String>>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+	evictions: AtomicU64,
+}
+
+// 通过控制管道查询的缓存统计；较早版本的挂载没有容量相关的字段
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CacheStats {
+	pub entries: usize,
+	pub ttl_secs: u64,
+	pub hits: u64,
+	pub misses: u64,
+	#[serde(default)]
+	pub bytes: u64,
+	#[serde(default)]
+	pub pinned: usize,
+	#[serde(default)]
+	pub evictions: u64,
+	#[serde(default)]
+	pub max_entries: Option<usize>,
+	#[serde(default)]
+	pub max_bytes: Option<u64>,
+	#[serde(default)]
+	pub eviction: Eviction,
+}
+
+impl AttrCache {
+	// ttl 为 0 时不缓存
+	pub fn new(ttl: Duration) -> Self {
+		Self {
+			ttl,
+			limits: Mutex::new(CacheLimits::default()),
+			entries: Mutex::new(Entries::default()),
+			leased: Mutex::new(HashSet::new()),
+			hits: AtomicU64::new(0),
+			misses: AtomicU64::new(0),
+			evictions: AtomicU64::new(0),
+		}
+	}
+
+	// 更换容量和换出方式，已缓存的条目按新的设置重新检查
+	pub fn set_limits(&self, limits: CacheLimits) {
+		let mut current = self.limits.lock().unwrap();
+		let mut entries = self.entries.lock().unwrap();
+		for (path, entry) in entries.map.iter_mut() {
+			entry.pinned = limits.is_pinned(path);
+		}
+		if limits.is_over(entries.map.len(), entries.bytes) {
+			self.evict(&mut entries, &limits, (0, 0));
+		}
+		*current = limits;
+	}
+
+	pub fn get(&self, path: &str) -> Option<RemoteFileInfo> {
+		let mut entries = self.entries.lock().unwrap();
+		entries.clock += 1;
+		let clock = entries.clock;
+		let leased = self.leased.lock().unwrap().contains(path);
+		let info = entries
+			.map
+			.get_mut(path)
+			.filter(|entry| entry.pinned || leased || entry.stored.elapsed() < self.ttl)
+			.map(|entry| {
+				entry.used = clock;
+				entry.hits += 1;
+				entry.info.clone()
+			});
+		let counter = if info.is_some() {
+			&self.hits
+		} else {
+			&self.misses
+		};
+		counter.fetch_add(1, Ordering::Relaxed);
+		info
+	}
+
+	pub fn insert(&self, path: String, info: RemoteFileInfo) {
+		if self.ttl.is_zero() {
+			return;
+		}
+		let limits = self.limits.lock().unwrap();
+		let mut entries = self.entries.lock().unwrap();
+		entries.remove(&path);
+		let bytes = (path.len() * 2 + info.name.len()) as u64 + ENTRY_OVERHEAD;
+		if limits.max_entries.is_none() && entries.map.len() >= CLEANUP_THRESHOLD {
+			entries.retain(|_, entry| entry.pinned || entry.stored.elapsed() < self.ttl);
+		}
+		let pinned = limits.is_pinned(&path);
+		if !pinned && limits.is_over(entries.map.len() + 1, entries.bytes + bytes) {
+			self.evict(&mut entries, &limits, (1, bytes));
+			// 只按过期清理时，清理后仍然满就不缓存这个条目
+			if limits.eviction == Eviction::TtlOnly
+				&& limits.is_over(entries.map.len() + 1, entries.bytes + bytes)
+			{
+				return;
+			}
+		}
+		entries.clock += 1;
+		let used = entries.clock;
+		entries.bytes += bytes;
+		entries.map.insert(
+			path,
+			Entry {
+				info,
+				stored: Instant::now(),
+				used,
+				hits: 0,
+				bytes,
+				pinned,
+			},
+		);
+	}
+
+	// 超过上限时先清理过期的条目，再按换出方式换出未固定的条目，直到加上将要插入的条目（incoming 为条目数和字节数）后不超过上限的九成
+	fn evict(&self, entries: &mut Entries, limits: &CacheLimits, incoming: (usize, u64)) {
+		let before = entries.map.len();
+		entries.retain(|_, entry| entry.pinned || entry.stored.elapsed() < self.ttl);
+		if limits.eviction != Eviction::TtlOnly {
+			let target_entries = limits.max_entries.map(|max| max * EVICT_TO_PERCENT / 100);
+			let target_bytes = limits
+				.max_bytes
+				.map(|max| max * EVICT_TO_PERCENT as u64 / 100);
+			let mut candidates: Vec<(u64, u64, &String)> = entries
+				.map
+				.iter()
+				.filter(|(_, entry)| !entry.pinned)
+				.map(|(path, entry)| match limits.eviction {
+					Eviction::Lfu => (entry.hits, entry.used, path),
+					_ => (entry.used, 0, path),
+				})
+				.collect();
+			candidates.sort_unstable();
+			let (mut count, mut bytes) =
+				(entries.map.len() + incoming.0, entries.bytes + incoming.1);
+			let mut victims = Vec::new();
+			for (_, _, path) in candidates {
+				if target_entries.is_none_or(|target| count <= target)
+					&& target_bytes.is_none_or(|target| bytes <= target)
+				{
+					break;
+				}
+				count -= 1;
+				bytes -= entries.map[path].bytes;
+				victims.push(path.clone());
+			}
+			for path in victims {
+				entries.remove(&path);
+			}
+		}
+		self.evictions
+			.fetch_add((before - entries.map.len()) as u64, Ordering::Relaxed);
+	}
+
+	// 使 path 及其下所有条目失效（目录被删除或移动时其中的条目也随之变化），同时使父目录失效
+	pub fn invalidate(&self, path: &str) {
+		let mut entries = self.entries.lock().unwrap();
+		if entries.map.is_empty() {
+			return;
+		}
+		let prefix = format!("{}/", path);
+		entries.retain(|key, _| key != path && !key.starts_with(&prefix));
+		if let Some((parent, _)) = path.rsplit_once('/') {
+			entries.remove(parent);
+		}
+	}
+
+	// 取得租约的路径的条目不再过期，见 Leases
+	pub fn lease(&self, path: &str) {
+		self.leased.lock().unwrap().insert(path.to_string());
+	}
+
+	pub fn unlease(&self, path: &str) {
+		self.leased.lock().unwrap().remove(path);
+	}
+
+	// 清空缓存，返回清除的条目数
+	pub fn clear(&self) -> usize {
+		let mut entries = self.entries.lock().unwrap();
+		let count = entries.map.len();
+		entries.map.clear();
+		entries.bytes = 0;
+		count
+	}
+
+	pub fn stats(&self) -> CacheStats {
+		let limits = self.limits.lock().unwrap();
+		let entries = self.entries.lock().unwrap();
+		CacheStats {
+			entries: entries.map.len(),
+			ttl_secs: self.ttl.as_secs(),
+			hits: self.hits.load(Ordering::Relaxed),
+			misses: self.misses.load(Ordering::Relaxed),
+			bytes: entries.bytes,
+			pinned: entries.map.values().filter(|entry| entry.pinned).count(),
+			evictions: self.evictions.load(Ordering::Relaxed),
+			max_entries: limits.max_entries,
+			max_bytes: limits.max_bytes,
+			eviction: limits.eviction,
+		}
+	}
+}
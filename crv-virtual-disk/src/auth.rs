@@ -0,0 +1,338 @@
+use std::{
+	io::{self, IsTerminal},
+	sync::{Arc, Mutex},
+	thread,
+	time::{Duration, Instant},
+};
+
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use serde::Deserialize;
+use tracing::{debug, warn};
+
+use crate::{
+	credentials::{delete_secret, read_secret, write_secret},
+	error::{CheckStatus, RemoteError},
+};
+
+// 访问令牌在到期前这么久就刷新，避免请求途中过期
+const EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+// 令牌响应没有给出 expires_in 时假定的有效期（秒）
+const DEFAULT_LIFETIME: u64 = 3600;
+// 设备码授权没有给出轮询间隔时使用的间隔（秒），服务器要求放慢时每次加 5 秒
+const DEFAULT_POLL_INTERVAL: u64 = 5;
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// 通过 OpenID Connect 身份提供方登录的设置：`issuer` 为提供方的地址（如 `https://login.microsoftonline.com/TENANT/v2.0`），
+/// 授权和令牌端点从 `{issuer}/.well-known/openid-configuration` 取得；`client_id` 为在提供方注册的公共客户端，
+/// `scope` 未设置时请求 `openid offline_access`。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OAuthSettings {
+	pub issuer: String,
+	pub client_id: String,
+	pub scope: Option<String>,
+}
+
+impl OAuthSettings {
+	fn scope(&self) -> &str {
+		self.scope.as_deref().unwrap_or("openid offline_access")
+	}
+
+	// 刷新令牌在 Windows 凭据管理器中的目标名称
+	fn credential_target(&self) -> String {
+		format!(
+			"httpfs:oauth:{}:{}",
+			self.issuer.trim_end_matches('/'),
+			self.client_id
+		)
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct Discovery {
+	device_authorization_endpoint: Option<String>,
+	token_endpoint: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct DeviceAuthorization {
+	device_code: String,
+	user_code: String,
+	// 部分提供方（如 Google）使用 verification_url
+	#[serde(alias = "verification_url")]
+	verification_uri: String,
+	expires_in: u64,
+	interval: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+	access_token: String,
+	expires_in: Option<u64>,
+	refresh_token: Option<String>,
+}
+
+// 令牌端点的错误响应（RFC 6749 第 5.2 节）
+#[derive(Debug, Deserialize)]
+struct TokenError {
+	error: String,
+	error_description: Option<String>,
+}
+
+struct Tokens {
+	access: String,
+	expires: Instant,
+	refresh: Option<String>,
+}
+
+impl Tokens {
+	fn is_fresh(&self) -> bool {
+		self.expires
+			.checked_duration_since(Instant::now())
+			.is_some_and(|left| left > EXPIRY_MARGIN)
+	}
+}
+
+#[derive(Default)]
+struct State {
+	token_endpoint: Option<String>,
+	device_endpoint: Option<String>,
+	tokens: Option<Tokens>,
+}
+
+fn unauthorized(message: impl Into<String>) -> RemoteError {
+	RemoteError::backend("unauthorized", message)
+}
+
+/// OAuth 2.0 登录：第一次以设备码流程登录（在控制台显示网址和代码，在任意设备的浏览器中确认），
+/// 之后用刷新令牌取得新的访问令牌；刷新令牌保存在当前用户的 Windows 凭据管理器中，下次挂载无需再次确认。
+/// 访问令牌在到期前自动刷新，作为 bearer 凭据随每个请求发送，代替静态的 `--token`。
+pub struct OAuth {
+	settings: OAuthSettings,
+	client: Client,
+	// 刷新期间持有，同时到期的请求只刷新一次
+	state: Mutex<State>,
+}
+
+impl OAuth {
+	/// 进程内同一设置共用的登录状态，挂载的后端、事件订阅和命令行共用同一组令牌。
+	pub fn shared(settings: &OAuthSettings) -> Arc<Self> {
+		static SHARED: Mutex<Vec<Arc<OAuth>>> = Mutex::new(Vec::new());
+		let mut shared = SHARED.lock().unwrap();
+		if let Some(oauth) = shared.iter().find(|oauth| oauth.settings == *settings) {
+			return oauth.clone();
+		}
+		let oauth = Arc::new(Self {
+			settings: settings.clone(),
+			client: Client::builder()
+				.timeout(Duration::from_secs(30))
+				.build()
+				.unwrap(),
+			state: Mutex::new(State::default()),
+		});
+		shared.push(oauth.clone());
+		oauth
+	}
+
+	/// 确保已经登录：先用保存的刷新令牌，失败或没有时在控制台进行设备码登录；
+	/// 没有控制台（如以服务运行）时返回错误，需要先以同一账户运行 `httpfs login`。
+	pub fn sign_in(&self) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		if state.tokens.as_ref().is_some_and(Tokens::is_fresh) {
+			return Ok(());
+		}
+		let target = self.settings.credential_target();
+		let refresh = state
+			.tokens
+			.as_ref()
+			.and_then(|tokens| tokens.refresh.clone())
+			.or_else(|| read_secret(&target));
+		if let Some(refresh) = refresh {
+			match self.refresh(&mut state, &refresh) {
+				Ok(()) => return Ok(()),
+				Err(e) => {
+					warn!(issuer = %self.settings.issuer, error = %e, "the saved sign-in is no longer valid")
+				}
+			}
+		}
+		if !io::stdin().is_terminal() {
+			return Err(unauthorized(format!(
+				"not signed in to {}; run `httpfs login` as this account first",
+				self.settings.issuer
+			)));
+		}
+		self.device_code(&mut state)
+	}
+
+	/// 忘记保存的登录，之后需要重新确认。
+	pub fn sign_out(&self) -> io::Result<()> {
+		self.state.lock().unwrap().tokens = None;
+		delete_secret(&self.settings.credential_target())
+	}
+
+	/// 当前的访问令牌，快到期时先刷新。刷新令牌失效后改用凭据管理器中保存的，
+	/// 挂载运行期间重新 `httpfs login` 即可恢复。
+	pub fn access_token(&self) -> Result<String, RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		if let Some(tokens) = state.tokens.as_ref().filter(|tokens| tokens.is_fresh()) {
+			return Ok(tokens.access.clone());
+		}
+		let current = state
+			.tokens
+			.as_ref()
+			.and_then(|tokens| tokens.refresh.clone());
+		let result = match &current {
+			Some(refresh) => self.refresh(&mut state, refresh),
+			None => Err(unauthorized(format!(
+				"not signed in to {}",
+				self.settings.issuer
+			))),
+		};
+		if let Err(e) = result {
+			match read_secret(&self.settings.credential_target()) {
+				Some(saved) if Some(&saved) != current.as_ref() => {
+					self.refresh(&mut state, &saved)?
+				}
+				_ => return Err(e),
+			}
+		}
+		Ok(state.tokens.as_ref().unwrap().access.clone())
+	}
+
+	// 给请求加上访问令牌；取不到令牌时照常发送，由服务器拒绝
+	pub(crate) fn authorize(&self, request: RequestBuilder) -> RequestBuilder {
+		match self.access_token() {
+			Ok(token) => request.bearer_auth(token),
+			Err(e) => {
+				warn!(issuer = %self.settings.issuer, error = %e, "cannot get an access token");
+				request
+			}
+		}
+	}
+
+	// 从提供方的 OpenID 配置取得令牌和设备授权端点
+	fn discover(&self, state: &mut State) -> Result<(), RemoteError> {
+		if state.token_endpoint.is_some() {
+			return Ok(());
+		}
+		let url = format!(
+			"{}/.well-known/openid-configuration",
+			self.settings.issuer.trim_end_matches('/')
+		);
+		let discovery: Discovery = self.client.get(&url).send()?.check_status()?.json()?;
+		state.token_endpoint = Some(discovery.token_endpoint);
+		state.device_endpoint = discovery.device_authorization_endpoint;
+		Ok(())
+	}
+
+	fn refresh(&self, state: &mut State, refresh: &str) -> Result<(), RemoteError> {
+		self.discover(state)?;
+		let response = self
+			.client
+			.post(state.token_endpoint.as_deref().unwrap())
+			.form(&[
+				("grant_type", "refresh_token"),
+				("refresh_token", refresh),
+				("client_id", self.settings.client_id.as_str()),
+			])
+			.send()?;
+		let tokens = match token_response(response)? {
+			Ok(tokens) => tokens,
+			Err(error) => return Err(unauthorized(describe(&error))),
+		};
+		debug!(issuer = %self.settings.issuer, "refreshed the access token");
+		self.store(state, tokens, Some(refresh));
+		Ok(())
+	}
+
+	// RFC 8628 的设备码流程：显示网址和代码，按提供方给出的间隔轮询直到用户确认、拒绝或代码过期
+	fn device_code(&self, state: &mut State) -> Result<(), RemoteError> {
+		self.discover(state)?;
+		let Some(endpoint) = state.device_endpoint.clone() else {
+			return Err(unauthorized(format!(
+				"{} does not support the device code flow",
+				self.settings.issuer
+			)));
+		};
+		let authorization: DeviceAuthorization = self
+			.client
+			.post(&endpoint)
+			.form(&[
+				("client_id", self.settings.client_id.as_str()),
+				("scope", self.settings.scope()),
+			])
+			.send()?
+			.check_status()?
+			.json()?;
+		eprintln!(
+			"To sign in, open {} and enter the code {}",
+			authorization.verification_uri, authorization.user_code
+		);
+		let deadline = Instant::now() + Duration::from_secs(authorization.expires_in);
+		let mut interval = authorization.interval.unwrap_or(DEFAULT_POLL_INTERVAL);
+		while Instant::now() < deadline {
+			thread::sleep(Duration::from_secs(interval));
+			let response = self
+				.client
+				.post(state.token_endpoint.as_deref().unwrap())
+				.form(&[
+					("grant_type", DEVICE_CODE_GRANT),
+					("device_code", authorization.device_code.as_str()),
+					("client_id", self.settings.client_id.as_str()),
+				])
+				.send()?;
+			match token_response(response)? {
+				Ok(tokens) => {
+					self.store(state, tokens, None);
+					eprintln!("Signed in to {}.", self.settings.issuer);
+					return Ok(());
+				}
+				Err(error) if error.error == "authorization_pending" => {}
+				Err(error) if error.error == "slow_down" => interval += 5,
+				Err(error) => return Err(unauthorized(describe(&error))),
+			}
+		}
+		Err(unauthorized(
+			"the sign-in code expired before it was confirmed",
+		))
+	}
+
+	// 记下新的令牌，刷新令牌另存到凭据管理器；提供方没有轮换刷新令牌时继续使用原来的
+	fn store(&self, state: &mut State, response: TokenResponse, previous_refresh: Option<&str>) {
+		let refresh = response
+			.refresh_token
+			.or_else(|| previous_refresh.map(str::to_string));
+		if let Some(refresh) = &refresh {
+			if let Err(e) = write_secret(&self.settings.credential_target(), refresh) {
+				warn!(
+					issuer = %self.settings.issuer,
+					error = %e,
+					"cannot save the sign-in to the Credential Manager, it only lasts until unmounted"
+				);
+			}
+		}
+		state.tokens = Some(Tokens {
+			access: response.access_token,
+			expires: Instant::now()
+				+ Duration::from_secs(response.expires_in.unwrap_or(DEFAULT_LIFETIME)),
+			refresh,
+		});
+	}
+}
+
+// 令牌端点成功时返回令牌，按 RFC 6749 返回错误体时返回该错误
+fn token_response(response: Response) -> Result<Result<TokenResponse, TokenError>, RemoteError> {
+	if response.status().is_success() {
+		return Ok(Ok(response.json()?));
+	}
+	match response.json::<TokenError>() {
+		Ok(error) => Ok(Err(error)),
+		Err(e) => Err(e.into()),
+	}
+}
+
+fn describe(error: &TokenError) -> String {
+	match &error.error_description {
+		Some(description) => format!("sign-in failed: {} ({})", error.error, description),
+		None => format!("sign-in failed: {}", error.error),
+	}
+}
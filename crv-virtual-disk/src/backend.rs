@@ -0,0 +1,682 @@
+mod compressed;
+mod dedup;
+mod direct;
+mod disk;
+mod encrypted;
+mod escaped;
+mod git;
+mod grpc;
+mod http;
+mod iso;
+mod local;
+mod memory;
+pub mod mock;
+mod overlay;
+mod s3;
+mod sftp;
+#[cfg(test)]
+mod tests;
+mod webdav;
+mod zip;
+
+use std::{
+	env,
+	error::Error,
+	fs::File,
+	io::{self, BufRead, IsTerminal, Read, Seek, SeekFrom},
+	path::PathBuf,
+};
+
+use percent_encoding::percent_decode_str;
+use sha2::{Digest, Sha256};
+use winapi::um::{
+	consoleapi::{GetConsoleMode, SetConsoleMode},
+	processenv::GetStdHandle,
+	winbase::STD_INPUT_HANDLE,
+	wincon::ENABLE_ECHO_INPUT,
+};
+
+use self::{
+	compressed::CompressedBackend,
+	dedup::{ChunkStore, DedupBackend},
+	disk::DiskBackend,
+	encrypted::{EncryptedBackend, KeySource},
+	escaped::EscapedBackend,
+	git::GitBackend,
+	grpc::GrpcBackend,
+	http::HttpBackend,
+	iso::IsoBackend,
+	local::LocalBackend,
+	memory::MemoryBackend,
+	overlay::OverlayBackend,
+	s3::S3Backend,
+	sftp::SftpBackend,
+	webdav::WebDavBackend,
+	zip::ZipBackend,
+};
+use crate::{
+	auth::{OAuth, OAuthSettings},
+	capabilities::Capabilities,
+	compression::Compression,
+	credentials::Credentials,
+	error::RemoteError,
+	http2::Http2,
+	image::cache::CacheSettings,
+	names::NameEscaping,
+	AuditFilter, AuditResponse, ChecksumResponse, ListPage, RemoteFileInfo, RestoreResponse,
+	SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+// 默认的 checksum 每次读取的长度
+const CHECKSUM_READ_SIZE: usize = 4 * 1024 * 1024;
+// 默认的 zero_range 每次写入的长度
+const ZERO_WRITE_SIZE: u64 = 4 * 1024 * 1024;
+
+/// 批量提交中的一个文件：整体替换为 `data`，之后设置 `times` 中给出的时间戳。
+#[derive(Debug, Clone, Copy)]
+pub struct BatchFile<'a> {
+	pub path: &'a str,
+	pub data: &'a [u8],
+	pub times: TimesUpdate,
+}
+
+fn directory_info(name: &str, modified: u64) -> RemoteFileInfo {
+	RemoteFileInfo {
+		name: name.to_string(),
+		is_directory: true,
+		size: 0,
+		created: modified,
+		modified,
+		accessed: modified,
+		stored_size: None,
+		allocated_size: None,
+	}
+}
+
+fn file_info(name: &str, size: u64, modified: u64) -> RemoteFileInfo {
+	RemoteFileInfo {
+		name: name.to_string(),
+		is_directory: false,
+		size,
+		created: modified,
+		modified,
+		accessed: modified,
+		stored_size: None,
+		allocated_size: None,
+	}
+}
+
+// 路径的最后一段
+fn base_name(path: &str) -> &str {
+	path.rsplit('/').next().unwrap_or(path)
+}
+
+// RFC 3986 的 URI 编码：只保留非保留字符，keep_slash 时路径中的 / 保持不变
+fn uri_encode(text: &str, keep_slash: bool) -> String {
+	let mut encoded = String::with_capacity(text.len());
+	for byte in text.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+				encoded.push(byte as char)
+			}
+			b'/' if keep_slash => encoded.push('/'),
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
+
+/// httpfs API 的 URL 中的路径：根目录为 `$ROOT`，其余路径逐段编码，名称中的空格、`#`、`?`、`%` 等不会改变 URL 的含义。
+pub(crate) fn encode_api_path(path: &str) -> String {
+	if path == "." {
+		"$ROOT".to_string()
+	} else {
+		uri_encode(path, true)
+	}
+}
+
+// 公历日期换算为 1970-01-01 以来的天数
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+	let year = if month <= 2 { year - 1 } else { year };
+	let era = year.div_euclid(400);
+	let yoe = year.rem_euclid(400);
+	let mp = if month > 2 { month - 3 } else { month + 9 };
+	let doy = (153 * mp + 2) / 5 + day - 1;
+	let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+	era * 146097 + doe - 719468
+}
+
+// 1970-01-01 以来的天数换算为公历日期
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	(yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+// 解析 ISO 8601 格式的 UTC 时间（Unix 秒），如 2024-01-02T03:04:05.000Z
+fn parse_timestamp(text: &str) -> Option<u64> {
+	let field = |range: std::ops::Range<usize>| text.get(range)?.parse::<i64>().ok();
+	let days = days_from_civil(field(0..4)?, field(5..7)?, field(8..10)?);
+	let secs = days * 86400 + field(11..13)? * 3600 + field(14..16)? * 60 + field(17..19)?;
+	u64::try_from(secs).ok()
+}
+
+// file:///C:/data、zip:///D:/archive.zip 等 URL 中的本地路径；Windows 路径前的 / 被去掉
+fn url_path(server_url: &str) -> PathBuf {
+	let location = server_url.split_once("://").map_or("", |(_, rest)| rest);
+	let location = percent_decode_str(location).decode_utf8_lossy();
+	match location.strip_prefix('/') {
+		Some(rest) if rest.get(1..2) == Some(":") => PathBuf::from(rest),
+		_ => PathBuf::from(location.as_ref()),
+	}
+}
+
+// 只读后端对修改操作返回的错误
+fn write_protected(path: &str) -> RemoteError {
+	RemoteError::backend(
+		"read_only",
+		format!("cannot modify {}: the volume is read-only", path),
+	)
+}
+
+// 映像和压缩包格式中的小端字段，调用方负责检查长度
+pub fn u16_at(data: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes([data[offset], data[offset + 1]])
+}
+
+pub fn u32_at(data: &[u8], offset: usize) -> u32 {
+	u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+pub fn u64_at(data: &[u8], offset: usize) -> u64 {
+	u64::from_le_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+fn read_at(file: &mut File, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+	let mut data = vec![0; length];
+	file.seek(SeekFrom::Start(offset))?;
+	file.read_exact(&mut data)?;
+	Ok(data)
+}
+
+/// 读到 `buffer` 填满或读到末尾，返回读到的字节数。
+pub fn read_full(reader: &mut impl Read, buffer: &mut [u8]) -> io::Result<usize> {
+	let mut filled = 0;
+	while filled < buffer.len() {
+		match reader.read(&mut buffer[filled..]) {
+			Ok(0) => break,
+			Ok(read) => filled += read,
+			Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(filled)
+}
+
+// 映像或压缩包的结构损坏
+pub fn invalid(message: impl Into<String>) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// 在控制台读取一行输入，`echo` 为 false 时不回显；没有控制台（如以服务运行）时返回 None。
+pub fn read_console(prompt: &str, echo: bool) -> Option<String> {
+	if !io::stdin().is_terminal() {
+		return None;
+	}
+	eprint!("{}", prompt);
+	let handle = unsafe { GetStdHandle(STD_INPUT_HANDLE) };
+	let mut mode = 0;
+	let hidden = !echo && unsafe { GetConsoleMode(handle, &mut mode) } != 0;
+	if hidden {
+		unsafe { SetConsoleMode(handle, mode & !ENABLE_ECHO_INPUT) };
+	}
+	let mut line = String::new();
+	let result = io::stdin().lock().read_line(&mut line);
+	if hidden {
+		unsafe { SetConsoleMode(handle, mode) };
+		eprintln!();
+	}
+	result.ok()?;
+	Some(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+fn parse_xml(text: &str) -> Result<roxmltree::Document<'_>, RemoteError> {
+	roxmltree::Document::parse(text).map_err(|e| {
+		RemoteError::backend("invalid_response", format!("invalid XML response: {}", e))
+	})
+}
+
+// 按本地名称查找子元素的文本，忽略命名空间
+fn child_text<'a>(node: roxmltree::Node<'a, '_>, name: &str) -> Option<&'a str> {
+	node.children()
+		.find(|child| child.has_tag_name(name))
+		.and_then(|child| child.text())
+}
+
+/// 挂载的文件所在的存储。路径使用 `/` 分隔，共享根目录为 `"."`；
+/// 属性缓存、暂存内容的提交和传输统计由 [`HttpFsHandler`](crate::HttpFsHandler) 负责，后端只执行单个操作。
+/// 失败时的错误类别与 httpfs 服务器相同（`not_found`、`already_exists`、`directory_not_empty` 等），
+/// 可选的操作默认返回 `not_supported` 或空结果。
+pub trait StorageBackend: Send + Sync {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError>;
+
+	// 获取一页目录内容，cursor 为上一页返回的 next_cursor
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError>;
+
+	// 读到文件末尾时返回的数据可以短于 length
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError>;
+
+	// 读到调用者的缓冲区，返回读到的字节数，读到文件末尾时可以少于 buffer 的长度。
+	// 能把内容直接读进缓冲区的存储覆盖这个方法，省去每次读取分配的 Vec
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		let data = self.read(path, offset, buffer.len())?;
+		let len = data.len().min(buffer.len());
+		buffer[..len].copy_from_slice(&data[..len]);
+		Ok(len)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError>;
+
+	// 用 data 整体替换文件内容，其他客户端不会看到写了一半的文件
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError>;
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError>;
+
+	// 非递归删除，非空目录返回 directory_not_empty；dry_run 时只检查能否删除
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError>;
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError>;
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError>;
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError>;
+
+	// 提交多个文件，按顺序返回每个文件的结果，一个文件失败不影响其他文件；外层的错误表示整批都没有结果。
+	// 能在一个请求中提交多个文件的存储覆盖这个方法，其他存储依次提交
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		Ok(commit_each(self, files))
+	}
+
+	// 把 [offset, offset + length) 置为 0，超出末尾时扩展文件，结果与写入同样长的 0 相同；
+	// 支持稀疏文件的存储不为这段分配空间，其他存储默认写入 0
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		write_zeros(self, path, offset, length)
+	}
+
+	// 只读的后端以写保护方式挂载，修改操作返回 read_only
+	fn read_only(&self) -> bool {
+		false
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		Err(RemoteError::unsupported("space"))
+	}
+
+	// 按文件名通配符搜索 path 下的条目
+	fn search(
+		&self,
+		_path: &str,
+		_pattern: &str,
+		_recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		Err(RemoteError::unsupported("search"))
+	}
+
+	fn list_xattrs(&self, _path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		Ok(Vec::new())
+	}
+
+	// 属性不存在时返回 None
+	fn get_xattr(&self, _path: &str, _name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		Ok(None)
+	}
+
+	fn put_xattr(&self, _path: &str, _name: &str, _value: &[u8]) -> Result<(), RemoteError> {
+		Err(RemoteError::unsupported("extended attributes"))
+	}
+
+	fn delete_xattr(&self, _path: &str, _name: &str) -> Result<(), RemoteError> {
+		Err(RemoteError::unsupported("extended attributes"))
+	}
+
+	// 文件的历史版本，新版本在前
+	fn list_versions(&self, _path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		Ok(Vec::new())
+	}
+
+	fn read_version(
+		&self,
+		_path: &str,
+		_id: &str,
+		_offset: u64,
+		_length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		Err(RemoteError::unsupported("file versions"))
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		Err(RemoteError::unsupported("trash"))
+	}
+
+	// 把回收站条目恢复到原路径，指定 path 时恢复到该路径
+	fn restore_trash(
+		&self,
+		_id: &str,
+		_path: Option<&str>,
+	) -> Result<RestoreResponse, RemoteError> {
+		Err(RemoteError::unsupported("trash"))
+	}
+
+	// 服务器记录的修改历史，按时间顺序
+	fn audit(&self, _filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		Err(RemoteError::unsupported("audit log"))
+	}
+
+	// 文件内容的 sha256；不能在存储端计算时读取整个文件
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		read_checksum(self, path)
+	}
+}
+
+// StorageBackend 各方法的默认做法，覆盖了这些方法的后端在存储端不支持时也可以使用
+
+// 依次提交每个文件
+fn commit_each<B: StorageBackend + ?Sized>(
+	backend: &B,
+	files: &[BatchFile],
+) -> Vec<Result<(), RemoteError>> {
+	files
+		.iter()
+		.map(|file| {
+			backend.commit(file.path, file.data)?;
+			if !file.times.is_empty() {
+				backend.set_times(file.path, &file.times)?;
+			}
+			Ok(())
+		})
+		.collect()
+}
+
+fn write_zeros<B: StorageBackend + ?Sized>(
+	backend: &B,
+	path: &str,
+	offset: u64,
+	length: u64,
+) -> Result<(), RemoteError> {
+	let zeros = vec![0; ZERO_WRITE_SIZE.min(length) as usize];
+	let mut done = 0;
+	while done < length {
+		let chunk = (length - done).min(ZERO_WRITE_SIZE);
+		backend.write(path, offset + done, &zeros[..chunk as usize])?;
+		done += chunk;
+	}
+	Ok(())
+}
+
+// 读取整个文件计算 sha256
+fn read_checksum<B: StorageBackend + ?Sized>(
+	backend: &B,
+	path: &str,
+) -> Result<ChecksumResponse, RemoteError> {
+	let mut hasher = Sha256::new();
+	let mut offset = 0;
+	loop {
+		let data = backend.read(path, offset, CHECKSUM_READ_SIZE)?;
+		hasher.update(&data);
+		offset += data.len() as u64;
+		if data.len() < CHECKSUM_READ_SIZE {
+			break;
+		}
+	}
+	Ok(ChecksumResponse {
+		digest: hex::encode(hasher.finalize()),
+	})
+}
+
+/// 访问存储所需的设置，[`open`] 按 `server_url` 的协议选择后端。
+#[derive(Debug, Clone)]
+pub struct Remote {
+	pub server_url: String,
+	pub share: Option<String>,
+	pub token: Option<String>,
+	// 通过身份提供方登录取得随请求发送的令牌，代替 token，只用于 httpfs 服务器
+	pub oauth: Option<OAuthSettings>,
+	// 地址、令牌和加密口令取自密钥环中这个名称的设置；server_url 和 token 已经合并，这里记下名称供服务命令行和口令使用
+	pub credential: Option<String>,
+	pub compression: Compression,
+	// 与 httpfs 服务器之间的 HTTP 版本，只用于 httpfs 服务器
+	pub http2: Http2,
+	// 实验性的 HTTP/3，QUIC 不通时回退到 TCP，只用于 https:// 的 httpfs 服务器
+	pub http3: bool,
+	pub s3_endpoint: Option<String>,
+	pub s3_region: Option<String>,
+	pub s3_path_style: bool,
+	pub aws_profile: Option<String>,
+	pub ssh_key: Option<String>,
+	pub ssh_host_key: Option<String>,
+	pub mem_capacity: Option<u64>,
+	// mem:// 卷在内存中保存的内容上限，超出的部分移到临时文件
+	pub mem_limit: Option<u64>,
+	// git:// 挂载的分支、标签或提交
+	pub git_ref: Option<String>,
+	// vdisk:// 挂载的分区序号
+	pub partition: Option<usize>,
+	// 磁盘映像的块缓存
+	pub block_cache: Option<CacheSettings>,
+	// 叠加挂载的可写上层和 server_url 之下的其他只读层
+	pub upper: Option<String>,
+	pub lower: Vec<String>,
+	// 客户端加密：key_file 未设置时使用口令
+	pub encrypt: bool,
+	pub key_file: Option<String>,
+	pub encrypt_names: bool,
+	// 压缩存储文件内容，compress_skip 为另外不压缩的扩展名
+	pub compress_files: bool,
+	pub compress_skip: Vec<String>,
+	// 文件内容按内容定义的分块去重存储
+	pub dedup: bool,
+	// 提交修改过的大文件时只上传与 httpfs 服务器上的内容不同的部分
+	pub delta_sync: bool,
+	// 列目录和搜索时由 httpfs 服务器去掉无权打开的条目
+	pub access_based_enumeration: bool,
+	// httpfs 服务器允许时，大文件的读取跟随签名 URL 直接从对象存储取得
+	pub direct_reads: bool,
+	// Windows 不能使用的远程名称的转义方式
+	pub escape_names: NameEscaping,
+}
+
+impl Remote {
+	/// 以默认设置访问 `server_url`：默认共享，没有令牌，zstd 传输压缩，不加密、不压缩存储也不去重。
+	pub fn new(server_url: impl Into<String>) -> Self {
+		Self {
+			server_url: server_url.into(),
+			share: None,
+			token: None,
+			oauth: None,
+			credential: None,
+			compression: Compression::Zstd,
+			http2: Http2::Auto,
+			http3: false,
+			s3_endpoint: None,
+			s3_region: None,
+			s3_path_style: false,
+			aws_profile: None,
+			ssh_key: None,
+			ssh_host_key: None,
+			mem_capacity: None,
+			mem_limit: None,
+			git_ref: None,
+			partition: None,
+			block_cache: None,
+			upper: None,
+			lower: Vec::new(),
+			encrypt: false,
+			key_file: None,
+			encrypt_names: false,
+			compress_files: false,
+			compress_skip: Vec::new(),
+			dedup: false,
+			delta_sync: false,
+			access_based_enumeration: false,
+			direct_reads: false,
+			escape_names: NameEscaping::None,
+		}
+	}
+
+	/// URL 的协议，决定使用哪种存储后端。
+	pub fn scheme(&self) -> String {
+		self.server_url
+			.split_once("://")
+			.map_or_else(String::new, |(scheme, _)| scheme.to_ascii_lowercase())
+	}
+
+	/// 是否为 httpfs 服务器，只有它提供变更通知。
+	pub fn is_httpfs(&self) -> bool {
+		matches!(self.scheme().as_str(), "http" | "https" | "grpc" | "grpcs")
+	}
+
+	/// httpfs 服务器的 HTTP API 地址：`grpc://` 和 `grpcs://` 的服务器在同一端口上也提供 HTTP API。
+	pub fn rest_url(&self) -> String {
+		match self.server_url.split_once("://") {
+			Some((scheme, rest)) if scheme.eq_ignore_ascii_case("grpc") => {
+				format!("http://{}", rest)
+			}
+			Some((scheme, rest)) if scheme.eq_ignore_ascii_case("grpcs") => {
+				format!("https://{}", rest)
+			}
+			_ => self.server_url.clone(),
+		}
+	}
+
+	/// 指定共享时通过 `/share/{name}` 前缀访问。
+	pub fn base_url(&self) -> String {
+		match &self.share {
+			Some(share) => format!("{}/share/{}", self.rest_url(), share),
+			None => self.rest_url(),
+		}
+	}
+}
+
+// 按 URL 的协议选择后端：http(s):// 为 httpfs 服务器，grpc(s):// 为 httpfs 服务器的 gRPC 服务，s3://bucket/prefix 为 S3 兼容存储，
+// dav(s)://host/path 为 WebDAV 服务器，sftp://user@host/path 为 SSH 服务器上的目录，
+// file:///path 为本地目录，mem:// 为内存盘，
+// zip:///path、iso:///path、git:///path 和 vdisk:///path 为只读挂载的 ZIP 文件、光盘映像、git 仓库中的提交和虚拟磁盘中的卷
+fn open_url(remote: &Remote) -> Result<Box<dyn StorageBackend>, Box<dyn Error>> {
+	match remote.scheme().as_str() {
+		"http" | "https" => Ok(Box::new(HttpBackend::open(remote)?)),
+		"grpc" | "grpcs" => Ok(Box::new(GrpcBackend::new(remote)?)),
+		"s3" => Ok(Box::new(S3Backend::new(remote)?)),
+		"dav" | "davs" => Ok(Box::new(WebDavBackend::new(remote)?)),
+		"sftp" => Ok(Box::new(SftpBackend::new(remote)?)),
+		"file" => Ok(Box::new(LocalBackend::new(remote)?)),
+		"mem" => Ok(Box::new(MemoryBackend::new(remote)?)),
+		"zip" => Ok(Box::new(ZipBackend::new(remote)?)),
+		"iso" => Ok(Box::new(IsoBackend::new(remote)?)),
+		"git" => Ok(Box::new(GitBackend::new(remote)?)),
+		"vdisk" => Ok(Box::new(DiskBackend::new(remote)?)),
+		scheme => Err(format!(
+			"unsupported URL scheme '{}' in {}",
+			scheme, remote.server_url
+		)
+		.into()),
+	}
+}
+
+// 叠加挂载：--url 和 --lower 依次为只读的下层。
+// --share 和 --token 只用于 --url，其他各层只使用各自 URL 中的设置
+fn open_overlay(
+	remote: &Remote,
+	upper_url: &str,
+) -> Result<Box<dyn StorageBackend>, Box<dyn Error>> {
+	let layer = |server_url: &str| Remote {
+		server_url: server_url.to_string(),
+		share: None,
+		token: None,
+		oauth: None,
+		credential: None,
+		upper: None,
+		lower: Vec::new(),
+		..remote.clone()
+	};
+	let upper = open_url(&layer(upper_url))?;
+	if upper.read_only() {
+		return Err(format!("the upper layer {} is read-only", upper_url).into());
+	}
+	let mut lowers = vec![open_url(&Remote {
+		upper: None,
+		lower: Vec::new(),
+		..remote.clone()
+	})?];
+	for lower_url in &remote.lower {
+		lowers.push(open_url(&layer(lower_url))?);
+	}
+	Ok(Box::new(OverlayBackend::new(upper, lowers)))
+}
+
+// 密钥环中保存的加密口令；设置了 credential 但已经删除时报错，不要退回到控制台询问
+fn stored_passphrase(remote: &Remote) -> Result<Option<String>, Box<dyn Error>> {
+	match &remote.credential {
+		Some(name) => Ok(Credentials::load(name)?
+			.ok_or_else(|| format!("no saved credential named '{}'", name))?
+			.passphrase),
+		None => Ok(None),
+	}
+}
+
+/// 打开挂载的存储：设置了 `upper` 时为叠加挂载；启用加密时在其上加密全部内容，
+/// 口令来自 `HTTPFS_PASSPHRASE`、`credential` 保存的设置或在控制台询问。去重在加密之前进行，压缩又在去重之前进行；
+/// 去重直接位于 httpfs 服务器之上时块保存在服务器的块存储中，否则块经过下面各层保存在后端中。
+pub fn open(remote: &Remote) -> Result<Box<dyn StorageBackend>, Box<dyn Error>> {
+	// 挂载前登录，之后的请求只需刷新令牌，不会在文件系统的回调中等待用户确认
+	if let Some(settings) = &remote.oauth {
+		OAuth::shared(settings).sign_in()?;
+	}
+	let mut backend = match &remote.upper {
+		Some(upper_url) => open_overlay(remote, upper_url)?,
+		None => open_url(remote)?,
+	};
+	if remote.encrypt {
+		let key = match &remote.key_file {
+			Some(path) => KeySource::KeyFile(path.into()),
+			None => KeySource::Passphrase(match env::var("HTTPFS_PASSPHRASE") {
+				Ok(passphrase) => Some(passphrase),
+				Err(_) => stored_passphrase(remote)?,
+			}),
+		};
+		backend = Box::new(EncryptedBackend::open(backend, &key, remote.encrypt_names)?);
+	}
+	if remote.dedup {
+		// 服务器没有块存储时块保存在后端中
+		let server = (remote.is_httpfs() && remote.upper.is_none() && !remote.encrypt)
+			.then(|| HttpBackend::new(remote))
+			.transpose()?
+			.filter(|http| http.supports("chunks"))
+			.map(|http| Box::new(http) as Box<dyn ChunkStore>);
+		backend = Box::new(DedupBackend::new(backend, server));
+	}
+	if remote.compress_files {
+		backend = Box::new(CompressedBackend::new(backend, &remote.compress_skip));
+	}
+	// 在最外层转义，加密和压缩的是远程的原始名称
+	if remote.escape_names != NameEscaping::None {
+		backend = Box::new(EscapedBackend::new(backend, remote.escape_names));
+	}
+	Ok(backend)
+}
+
+/// httpfs 服务器的共享支持的功能，服务器无法访问或不是 httpfs 服务器时返回 None。
+pub fn capabilities(remote: &Remote) -> Option<Capabilities> {
+	remote
+		.is_httpfs()
+		.then(|| HttpBackend::new(remote).ok()?.capabilities())
+		.flatten()
+}
@@ -0,0 +1,658 @@
+use std::collections::HashSet;
+
+use super::{u32_at, u64_at, StorageBackend};
+use crate::{
+	error::RemoteError, AuditFilter, AuditResponse, ListPage, RemoteFileInfo, RestoreResponse,
+	SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+// 压缩存储的文件格式：开头的标识，之后是各块压缩后的数据，然后是块索引，最后是固定长度的尾部。
+// 尾部依次为索引的位置、块数、原始长度（均为 u64）和尾部标识；索引中每块为位置（u64）和长度（u32），
+// 长度的最高位表示该块没有压缩，长度为 0 表示全零的块。不符合此格式的文件按原样读写
+const MAGIC: &[u8; 8] = b"HFSZ\x00\x01\r\n";
+const TRAILER_MAGIC: &[u8; 8] = b"HFSZIDX1";
+const HEADER_SIZE: u64 = MAGIC.len() as u64;
+const TRAILER_SIZE: u64 = 32;
+const ENTRY_SIZE: u64 = 12;
+const RAW_FLAG: u32 = 1 << 31;
+
+// 每块的原始长度，块单独压缩，读写只涉及所在的块
+const CHUNK_SIZE: u64 = 64 * 1024;
+const LEVEL: i32 = 3;
+
+// 改写的块追加在索引之前，原来的数据成为空洞；空洞超过有效数据且不少于 1 MiB 时重写整个文件
+const COMPACT_MIN_GARBAGE: u64 = 1024 * 1024;
+
+// 默认不压缩的扩展名：本身已经压缩的格式
+const INCOMPRESSIBLE: &[&str] = &[
+	"7z", "aac", "avi", "br", "bz2", "cab", "docx", "flac", "gif", "gz", "heic", "jar", "jpeg",
+	"jpg", "lz4", "m4a", "mkv", "mov", "mp3", "mp4", "ogg", "png", "pptx", "rar", "tgz", "webm",
+	"webp", "xlsx", "xz", "zip", "zst",
+];
+
+fn corrupt(path: &str) -> RemoteError {
+	RemoteError::backend(
+		"corrupt_data",
+		format!("the compressed content of {} is damaged", path),
+	)
+}
+
+#[derive(Clone, Copy)]
+struct Chunk {
+	offset: u64,
+	length: u32,
+}
+
+impl Chunk {
+	const ZERO: Chunk = Chunk {
+		offset: 0,
+		length: 0,
+	};
+
+	fn stored_length(&self) -> u64 {
+		(self.length & !RAW_FLAG) as u64
+	}
+}
+
+// 压缩文件的索引
+struct Layout {
+	size: u64,
+	index_offset: u64,
+	chunks: Vec<Chunk>,
+}
+
+impl Layout {
+	fn empty() -> Self {
+		Self {
+			size: 0,
+			index_offset: HEADER_SIZE,
+			chunks: Vec::new(),
+		}
+	}
+
+	// 第 index 块的原始长度
+	fn chunk_length(&self, index: usize) -> usize {
+		(self.size - index as u64 * CHUNK_SIZE).min(CHUNK_SIZE) as usize
+	}
+
+	fn live(&self) -> u64 {
+		self.chunks.iter().map(Chunk::stored_length).sum()
+	}
+
+	// 已被替换的块占用的空间
+	fn garbage(&self) -> u64 {
+		(self.index_offset - HEADER_SIZE).saturating_sub(self.live())
+	}
+
+	// 索引和尾部
+	fn tail(&self) -> Vec<u8> {
+		let mut tail =
+			Vec::with_capacity(self.chunks.len() * ENTRY_SIZE as usize + TRAILER_SIZE as usize);
+		for chunk in &self.chunks {
+			tail.extend(chunk.offset.to_le_bytes());
+			tail.extend(chunk.length.to_le_bytes());
+		}
+		tail.extend(self.index_offset.to_le_bytes());
+		tail.extend((self.chunks.len() as u64).to_le_bytes());
+		tail.extend(self.size.to_le_bytes());
+		tail.extend(TRAILER_MAGIC);
+		tail
+	}
+}
+
+// 尾部给出的原始长度和索引位置；结构不一致时不是压缩文件
+fn parse_trailer(stored: u64, trailer: &[u8]) -> Option<(u64, u64, u64)> {
+	if trailer.len() != TRAILER_SIZE as usize || &trailer[24..] != TRAILER_MAGIC {
+		return None;
+	}
+	let (index_offset, count, size) = (u64_at(trailer, 0), u64_at(trailer, 8), u64_at(trailer, 16));
+	let end = count
+		.checked_mul(ENTRY_SIZE)?
+		.checked_add(index_offset)?
+		.checked_add(TRAILER_SIZE)?;
+	(count == size.div_ceil(CHUNK_SIZE) && index_offset >= HEADER_SIZE && end == stored)
+		.then_some((size, index_offset, count))
+}
+
+// 压缩后的块和索引中的长度；全零的块不保存，压缩不能变小时保存原始数据
+fn pack(data: &[u8]) -> (Vec<u8>, u32) {
+	if data.iter().all(|&byte| byte == 0) {
+		return (Vec::new(), 0);
+	}
+	match zstd::bulk::compress(data, LEVEL) {
+		Ok(compressed) if compressed.len() < data.len() => {
+			let length = compressed.len() as u32;
+			(compressed, length)
+		}
+		_ => (data.to_vec(), data.len() as u32 | RAW_FLAG),
+	}
+}
+
+// 在任意后端之上以 zstd 压缩文件内容：每 64 KiB 一块，文件末尾的索引记录各块的位置，读取时透明解压。
+// 已经压缩的格式和 --compress-skip 给出的扩展名保持原样，不是由本后端写入的文件也按原样读写。
+// 文件信息中的 size 为原始长度，stored_size 为存储中的长度
+pub struct CompressedBackend<B: StorageBackend + ?Sized = dyn StorageBackend> {
+	inner: Box<B>,
+	skip: HashSet<String>,
+}
+
+impl<B: StorageBackend + ?Sized> CompressedBackend<B> {
+	pub fn new(inner: Box<B>, skip: &[String]) -> Self {
+		let skip = INCOMPRESSIBLE
+			.iter()
+			.map(|extension| extension.to_string())
+			.chain(
+				skip.iter()
+					.map(|extension| extension.trim_start_matches('.').to_ascii_lowercase()),
+			)
+			.collect();
+		Self { inner, skip }
+	}
+
+	// 新写入的内容是否压缩，只取决于扩展名
+	fn compressible(&self, path: &str) -> bool {
+		let name = path.rsplit('/').next().unwrap_or(path);
+		match name.rsplit_once('.') {
+			Some((_, extension)) => !self.skip.contains(&extension.to_ascii_lowercase()),
+			None => true,
+		}
+	}
+
+	// 压缩文件的原始长度；read 读取存储中的内容
+	fn logical_size(
+		&self,
+		stored: u64,
+		read: impl Fn(u64, usize) -> Result<Vec<u8>, RemoteError>,
+	) -> Result<Option<u64>, RemoteError> {
+		if stored < HEADER_SIZE + TRAILER_SIZE {
+			return Ok(None);
+		}
+		let trailer = read(stored - TRAILER_SIZE, TRAILER_SIZE as usize)?;
+		Ok(parse_trailer(stored, &trailer).map(|(size, _, _)| size))
+	}
+
+	fn layout(
+		&self,
+		path: &str,
+		stored: u64,
+		read: &dyn Fn(u64, usize) -> Result<Vec<u8>, RemoteError>,
+	) -> Result<Option<Layout>, RemoteError> {
+		if stored < HEADER_SIZE + TRAILER_SIZE {
+			return Ok(None);
+		}
+		let Some((size, index_offset, count)) =
+			parse_trailer(stored, &read(stored - TRAILER_SIZE, TRAILER_SIZE as usize)?)
+		else {
+			return Ok(None);
+		};
+		let index = read(index_offset, (count * ENTRY_SIZE) as usize)?;
+		if index.len() as u64 != count * ENTRY_SIZE {
+			return Err(corrupt(path));
+		}
+		let chunks: Vec<Chunk> = index
+			.chunks(ENTRY_SIZE as usize)
+			.map(|entry| Chunk {
+				offset: u64_at(entry, 0),
+				length: u32_at(entry, 8),
+			})
+			.collect();
+		if chunks.iter().any(|chunk| {
+			chunk.stored_length() > 0
+				&& (chunk.offset < HEADER_SIZE
+					|| chunk.offset + chunk.stored_length() > index_offset)
+		}) {
+			return Err(corrupt(path));
+		}
+		Ok(Some(Layout {
+			size,
+			index_offset,
+			chunks,
+		}))
+	}
+
+	// 当前内容的索引，文件不是压缩格式时为 None
+	fn current_layout(&self, path: &str) -> Result<(RemoteFileInfo, Option<Layout>), RemoteError> {
+		let info = self.inner.stat(path)?;
+		if info.is_directory {
+			return Ok((info, None));
+		}
+		let layout = self.layout(path, info.size, &|offset, length| {
+			self.inner.read(path, offset, length)
+		})?;
+		Ok((info, layout))
+	}
+
+	// 读取 first 开始的若干块并解压，每块补足到原始长度
+	fn read_chunks(
+		&self,
+		path: &str,
+		layout: &Layout,
+		first: usize,
+		last: usize,
+		read: &dyn Fn(u64, usize) -> Result<Vec<u8>, RemoteError>,
+	) -> Result<Vec<u8>, RemoteError> {
+		// 存储中相邻或间隔不大的块一次读出
+		let mut stored: Vec<Chunk> = layout.chunks[first..=last]
+			.iter()
+			.copied()
+			.filter(|chunk| chunk.length != 0)
+			.collect();
+		stored.sort_by_key(|chunk| chunk.offset);
+		let mut ranges: Vec<(u64, u64)> = Vec::new();
+		for chunk in stored {
+			let end = chunk.offset + chunk.stored_length();
+			match ranges.last_mut() {
+				Some(range) if chunk.offset <= range.1 + CHUNK_SIZE => range.1 = range.1.max(end),
+				_ => ranges.push((chunk.offset, end)),
+			}
+		}
+		let mut spans = Vec::with_capacity(ranges.len());
+		for (start, end) in ranges {
+			let data = read(start, (end - start) as usize)?;
+			if data.len() as u64 != end - start {
+				return Err(corrupt(path));
+			}
+			spans.push((start, data));
+		}
+		let mut plain = Vec::with_capacity((last - first + 1) * CHUNK_SIZE as usize);
+		for index in first..=last {
+			let chunk = layout.chunks[index];
+			let length = layout.chunk_length(index);
+			let start = plain.len();
+			let span = spans
+				.iter()
+				.rfind(|(start, _)| *start <= chunk.offset)
+				.filter(|_| chunk.length != 0);
+			if let Some((span_start, data)) = span {
+				let begin = (chunk.offset - span_start) as usize;
+				let stored = &data[begin..begin + chunk.stored_length() as usize];
+				if chunk.length & RAW_FLAG != 0 {
+					plain.extend(stored);
+				} else {
+					plain.extend(
+						zstd::bulk::decompress(stored, CHUNK_SIZE as usize)
+							.map_err(|_| corrupt(path))?,
+					);
+				}
+			}
+			plain.resize(start + length, 0);
+		}
+		Ok(plain)
+	}
+
+	fn read_layout(
+		&self,
+		path: &str,
+		layout: &Layout,
+		offset: u64,
+		length: usize,
+		read: &dyn Fn(u64, usize) -> Result<Vec<u8>, RemoteError>,
+	) -> Result<Vec<u8>, RemoteError> {
+		if offset >= layout.size || length == 0 {
+			return Ok(Vec::new());
+		}
+		let end = (offset + length as u64).min(layout.size);
+		let first = (offset / CHUNK_SIZE) as usize;
+		let last = ((end - 1) / CHUNK_SIZE) as usize;
+		let mut plain = self.read_chunks(path, layout, first, last, read)?;
+		plain.truncate((end - first as u64 * CHUNK_SIZE) as usize);
+		plain.drain(..(offset - first as u64 * CHUNK_SIZE) as usize);
+		Ok(plain)
+	}
+
+	// 把新的块数据、索引和尾部写在原来的索引处，去掉其后剩余的内容；new 时先写入文件开头的标识
+	fn store(
+		&self,
+		path: &str,
+		layout: &Layout,
+		blobs: Vec<u8>,
+		write_at: u64,
+		stored: u64,
+		new: bool,
+	) -> Result<(), RemoteError> {
+		let (position, mut data) = if new {
+			(0, MAGIC.to_vec())
+		} else {
+			(write_at, Vec::new())
+		};
+		data.extend(blobs);
+		data.extend(layout.tail());
+		self.inner.write(path, position, &data)?;
+		if position + (data.len() as u64) < stored {
+			self.inner.truncate(path, position + data.len() as u64)?;
+		}
+		let garbage = layout.garbage();
+		if garbage > COMPACT_MIN_GARBAGE && garbage > layout.live() {
+			self.compact(path, layout)?;
+		}
+		Ok(())
+	}
+
+	// 去掉被替换的块，整体提交
+	fn compact(&self, path: &str, layout: &Layout) -> Result<(), RemoteError> {
+		let old = self.inner.read(path, 0, layout.index_offset as usize)?;
+		if old.len() as u64 != layout.index_offset {
+			return Err(corrupt(path));
+		}
+		let mut compacted = Layout {
+			size: layout.size,
+			index_offset: HEADER_SIZE,
+			chunks: Vec::with_capacity(layout.chunks.len()),
+		};
+		let mut data = MAGIC.to_vec();
+		for chunk in &layout.chunks {
+			if chunk.length == 0 {
+				compacted.chunks.push(Chunk::ZERO);
+				continue;
+			}
+			compacted.chunks.push(Chunk {
+				offset: data.len() as u64,
+				length: chunk.length,
+			});
+			data.extend(
+				&old[chunk.offset as usize..(chunk.offset + chunk.stored_length()) as usize],
+			);
+		}
+		compacted.index_offset = data.len() as u64;
+		data.extend(compacted.tail());
+		self.inner.commit(path, &data)
+	}
+
+	// 重新压缩写入涉及的块，追加在索引之前
+	fn write_chunks(
+		&self,
+		path: &str,
+		mut layout: Layout,
+		stored: u64,
+		offset: u64,
+		data: &[u8],
+		new: bool,
+	) -> Result<(), RemoteError> {
+		let end = offset + data.len() as u64;
+		let write_at = layout.index_offset;
+		layout.size = layout.size.max(end);
+		layout
+			.chunks
+			.resize(layout.size.div_ceil(CHUNK_SIZE) as usize, Chunk::ZERO);
+		let first = (offset / CHUNK_SIZE) as usize;
+		let last = ((end - 1) / CHUNK_SIZE) as usize;
+		let start = first as u64 * CHUNK_SIZE;
+		let mut plain =
+			vec![0; (last as u64 * CHUNK_SIZE - start) as usize + layout.chunk_length(last)];
+		// 只有首尾两块可能没有被完全覆盖，需要读出原来的内容；新增的块原来的内容为零
+		for index in [first, last] {
+			let chunk_start = index as u64 * CHUNK_SIZE;
+			if offset > chunk_start || end < chunk_start + layout.chunk_length(index) as u64 {
+				let old = self.read_chunks(path, &layout, index, index, &|offset, length| {
+					self.inner.read(path, offset, length)
+				})?;
+				let position = (chunk_start - start) as usize;
+				plain[position..position + old.len()].copy_from_slice(&old);
+			}
+		}
+		plain[(offset - start) as usize..(end - start) as usize].copy_from_slice(data);
+		let mut blobs = Vec::new();
+		for (i, chunk) in plain.chunks(CHUNK_SIZE as usize).enumerate() {
+			let (blob, length) = pack(chunk);
+			layout.chunks[first + i] = if length == 0 {
+				Chunk::ZERO
+			} else {
+				Chunk {
+					offset: layout.index_offset,
+					length,
+				}
+			};
+			layout.index_offset += blob.len() as u64;
+			blobs.extend(blob);
+		}
+		self.store(path, &layout, blobs, write_at, stored, new)
+	}
+
+	// 整个文件的压缩格式，不能变小时返回 None
+	fn pack_file(data: &[u8]) -> Option<Vec<u8>> {
+		let mut layout = Layout::empty();
+		layout.size = data.len() as u64;
+		let mut packed = MAGIC.to_vec();
+		for chunk in data.chunks(CHUNK_SIZE as usize) {
+			let (blob, length) = pack(chunk);
+			layout.chunks.push(if length == 0 {
+				Chunk::ZERO
+			} else {
+				Chunk {
+					offset: packed.len() as u64,
+					length,
+				}
+			});
+			packed.extend(blob);
+		}
+		layout.index_offset = packed.len() as u64;
+		packed.extend(layout.tail());
+		(packed.len() < data.len()).then_some(packed)
+	}
+
+	// 列表和搜索结果中的文件：压缩文件换算为原始长度
+	fn logical_info(
+		&self,
+		path: &str,
+		mut info: RemoteFileInfo,
+	) -> Result<RemoteFileInfo, RemoteError> {
+		if info.is_directory {
+			return Ok(info);
+		}
+		if let Some(size) = self.logical_size(info.size, |offset, length| {
+			self.inner.read(path, offset, length)
+		})? {
+			info.stored_size = Some(info.size);
+			info.size = size;
+			info.allocated_size = None;
+		}
+		Ok(info)
+	}
+
+	// 列出后被删除等读取失败的条目保留存储中的信息
+	fn listed_info(&self, path: &str, info: RemoteFileInfo) -> RemoteFileInfo {
+		self.logical_info(path, info.clone()).unwrap_or(info)
+	}
+}
+
+impl<B: StorageBackend + ?Sized> StorageBackend for CompressedBackend<B> {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let info = self.inner.stat(path)?;
+		self.logical_info(path, info)
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let page = self.inner.list_page(path, cursor)?;
+		let items = page
+			.items
+			.into_iter()
+			.map(|info| {
+				let child = if path == "." {
+					info.name.clone()
+				} else {
+					format!("{}/{}", path, info.name)
+				};
+				self.listed_info(&child, info)
+			})
+			.collect();
+		Ok(ListPage {
+			items,
+			next_cursor: page.next_cursor,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		match self.current_layout(path)? {
+			(_, Some(layout)) => {
+				self.read_layout(path, &layout, offset, length, &|offset, length| {
+					self.inner.read(path, offset, length)
+				})
+			}
+			(_, None) => self.inner.read(path, offset, length),
+		}
+	}
+
+	// 空文件首次写入时按扩展名决定是否压缩，之后保持原来的格式
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		if data.is_empty() {
+			return self.inner.write(path, offset, data);
+		}
+		match self.current_layout(path)? {
+			(info, Some(layout)) => self.write_chunks(path, layout, info.size, offset, data, false),
+			(info, None) if info.size == 0 && !info.is_directory && self.compressible(path) => {
+				self.write_chunks(path, Layout::empty(), 0, offset, data, true)
+			}
+			(_, None) => self.inner.write(path, offset, data),
+		}
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		match Some(data)
+			.filter(|_| self.compressible(path))
+			.and_then(Self::pack_file)
+		{
+			Some(packed) => self.inner.commit(path, &packed),
+			None => self.inner.commit(path, data),
+		}
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.inner.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.inner.delete(path, dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.inner.rename(old_path, new_path, replace)
+	}
+
+	// 缩短时重新压缩新的最后一块，加长时只在索引中添加全零的块
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let (info, layout) = self.current_layout(path)?;
+		let Some(mut layout) = layout.filter(|_| size > 0) else {
+			return self.inner.truncate(path, size);
+		};
+		if size == layout.size {
+			return Ok(());
+		}
+		let write_at = layout.index_offset;
+		let mut blobs = Vec::new();
+		let count = size.div_ceil(CHUNK_SIZE) as usize;
+		let last = count - 1;
+		let tail = (size - last as u64 * CHUNK_SIZE) as usize;
+		if size < layout.size && tail < layout.chunk_length(last) && layout.chunks[last].length != 0
+		{
+			let mut plain = self.read_chunks(path, &layout, last, last, &|offset, length| {
+				self.inner.read(path, offset, length)
+			})?;
+			plain.truncate(tail);
+			let (blob, length) = pack(&plain);
+			layout.chunks[last] = if length == 0 {
+				Chunk::ZERO
+			} else {
+				Chunk {
+					offset: layout.index_offset,
+					length,
+				}
+			};
+			layout.index_offset += blob.len() as u64;
+			blobs = blob;
+		}
+		layout.chunks.resize(count, Chunk::ZERO);
+		layout.size = size;
+		self.store(path, &layout, blobs, write_at, info.size, false)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.inner.set_times(path, times)
+	}
+
+	fn read_only(&self) -> bool {
+		self.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.inner.space()
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		let mut response = self.inner.search(path, pattern, recursive)?;
+		for hit in &mut response.hits {
+			hit.info = self.listed_info(&hit.path, hit.info.clone());
+		}
+		Ok(response)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		self.inner.list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		self.inner.get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.inner.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.inner.delete_xattr(path, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		let mut versions = self.inner.list_versions(path)?;
+		for version in &mut versions {
+			let id = version.id.clone();
+			if let Some(size) = self.logical_size(version.size, |offset, length| {
+				self.inner.read_version(path, &id, offset, length)
+			})? {
+				version.size = size;
+			}
+		}
+		Ok(versions)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let stored = self
+			.inner
+			.list_versions(path)?
+			.into_iter()
+			.find(|version| version.id == id)
+			.map(|version| version.size);
+		let read = |offset, length| self.inner.read_version(path, id, offset, length);
+		match stored
+			.map(|stored| self.layout(path, stored, &read))
+			.transpose()?
+			.flatten()
+		{
+			Some(layout) => self.read_layout(path, &layout, offset, length, &read),
+			None => read(offset, length),
+		}
+	}
+
+	// 回收站中的大小为存储中的长度
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		self.inner.list_trash()
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		self.inner.restore_trash(id, path)
+	}
+
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		self.inner.audit(filter)
+	}
+}
@@ -0,0 +1,721 @@
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use super::StorageBackend;
+use crate::{
+	error::RemoteError, AuditFilter, AuditResponse, ListPage, RemoteFileInfo, RestoreResponse,
+	SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+// FastCDC 分块的最小、平均和最大长度。达到平均长度之前使用位数更多的掩码，之后使用位数更少的掩码，
+// 使块长集中在平均长度附近
+const MIN_CHUNK: usize = 16 * 1024;
+const AVG_CHUNK: usize = 64 * 1024;
+const MAX_CHUNK: usize = 256 * 1024;
+const MASK_SMALL: u64 = high_bits(18);
+const MASK_LARGE: u64 = high_bits(14);
+
+// gear 哈希的随机表，由固定种子的 splitmix64 生成，所有客户端切出的边界因此相同
+const GEAR: [u64; 256] = gear_table();
+
+// 清单文件的开头；size 紧随其后，列目录时只需读出开头就能得到文件长度
+const MANIFEST_PREFIX: &[u8] = b"{\"httpfs_manifest\":1,\"size\":";
+const MANIFEST_HEAD_SIZE: usize = 64;
+
+// 没有服务器的块存储时，块保存在后端根目录下的该目录中，挂载后不可见
+const CHUNK_DIR: &str = ".httpfs-chunkstore";
+
+// 缓存的清单数超过该值时整体清空；最近读取的块另外保留若干个
+const MAX_CACHED_MANIFESTS: usize = 256;
+const CACHED_CHUNKS: usize = 32;
+
+const fn high_bits(count: u32) -> u64 {
+	!0 << (64 - count)
+}
+
+const fn gear_table() -> [u64; 256] {
+	let mut table = [0; 256];
+	let mut state: u64 = 0x6874_7470_6673_6364;
+	let mut i = 0;
+	while i < table.len() {
+		state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = state;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		table[i] = z ^ (z >> 31);
+		i += 1;
+	}
+	table
+}
+
+// data 开头一块的长度
+fn cut_point(data: &[u8]) -> usize {
+	if data.len() <= MIN_CHUNK {
+		return data.len();
+	}
+	let normal = AVG_CHUNK.min(data.len());
+	let end = MAX_CHUNK.min(data.len());
+	let mut hash = 0u64;
+	for (i, &byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK) {
+		hash = (hash << 1).wrapping_add(GEAR[byte as usize]);
+		let mask = if i < normal { MASK_SMALL } else { MASK_LARGE };
+		if hash & mask == 0 {
+			return i + 1;
+		}
+	}
+	end
+}
+
+// 按内容切分：边界只取决于附近的数据，插入或删除几个字节只影响前后一两块
+fn split(mut data: &[u8]) -> Vec<&[u8]> {
+	let mut pieces = Vec::new();
+	while !data.is_empty() {
+		let (piece, rest) = data.split_at(cut_point(data));
+		pieces.push(piece);
+		data = rest;
+	}
+	pieces
+}
+
+fn sha256_hex(data: &[u8]) -> String {
+	hex::encode(Sha256::digest(data))
+}
+
+fn is_hash(text: &str) -> bool {
+	text.len() == 64 && text.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn corrupt(path: &str) -> RemoteError {
+	RemoteError::backend(
+		"corrupt_data",
+		format!("the chunk manifest of {} is damaged", path),
+	)
+}
+
+// 按 sha256 寻址的块存储，httpfs 服务器通过 /chunks 接口提供
+pub trait ChunkStore: Send + Sync {
+	fn put_chunk(&self, hash: &str, data: &[u8]) -> Result<(), RemoteError>;
+
+	// 块不存在时返回 None
+	fn get_chunk(&self, hash: &str) -> Result<Option<Vec<u8>>, RemoteError>;
+
+	// 给出的哈希中存储里还没有的块
+	fn missing_chunks(&self, hashes: &[String]) -> Result<Vec<String>, RemoteError>;
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ChunkRef {
+	// None 表示全零的块，不保存
+	hash: Option<String>,
+	size: u64,
+}
+
+// 去重文件在后端中保存的内容：文件长度和依次拼接的块。字段顺序固定，MANIFEST_PREFIX 依赖于此
+#[derive(Clone, Serialize, Deserialize)]
+struct Manifest {
+	httpfs_manifest: u32,
+	size: u64,
+	chunks: Vec<ChunkRef>,
+}
+
+impl Manifest {
+	fn empty() -> Self {
+		Self {
+			httpfs_manifest: 1,
+			size: 0,
+			chunks: Vec::new(),
+		}
+	}
+
+	// 不是清单格式时返回 None
+	fn parse(path: &str, data: &[u8]) -> Result<Option<Self>, RemoteError> {
+		if !data.starts_with(MANIFEST_PREFIX) {
+			return Ok(None);
+		}
+		let manifest: Self = serde_json::from_slice(data).map_err(|_| corrupt(path))?;
+		let valid = manifest.httpfs_manifest == 1
+			&& manifest.chunks.iter().all(|chunk| {
+				chunk.size > 0
+					&& chunk.size <= MAX_CHUNK as u64
+					&& chunk.hash.as_deref().is_none_or(is_hash)
+			}) && manifest.chunks.iter().map(|chunk| chunk.size).sum::<u64>()
+			== manifest.size;
+		if !valid {
+			return Err(corrupt(path));
+		}
+		Ok(Some(manifest))
+	}
+
+	// 各块在文件中的结束位置
+	fn ends(&self) -> Vec<u64> {
+		self.chunks
+			.iter()
+			.scan(0, |end, chunk| {
+				*end += chunk.size;
+				Some(*end)
+			})
+			.collect()
+	}
+
+	// 在末尾加上全零的块
+	fn extend_zeros(&mut self, size: u64) {
+		while self.size < size {
+			let length = (size - self.size).min(MAX_CHUNK as u64);
+			self.chunks.push(ChunkRef {
+				hash: None,
+				size: length,
+			});
+			self.size += length;
+		}
+	}
+}
+
+// 清单开头给出的文件长度
+fn logical_size(head: &[u8]) -> Option<u64> {
+	let digits = head.strip_prefix(MANIFEST_PREFIX)?;
+	let digits = &digits[..digits.iter().position(|&b| b == b',')?];
+	std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+// 读取清单时后端中的长度和修改时间，两者都不变时缓存有效
+struct CachedManifest {
+	stored: u64,
+	modified: u64,
+	manifest: Arc<Manifest>,
+}
+
+// 在任意后端之上按内容去重：文件以 FastCDC 切成平均 64 KiB 的块，块按 sha256 只保存一份，
+// 后端中的文件只是引用这些块的 JSON 清单。相同的数据在不同文件和同一文件的不同位置之间共享，
+// 服务器为清单保存的历史版本和回收站条目也只占清单的大小。
+// 直接位于 httpfs 服务器之上时块保存在服务器的块存储中，否则保存在后端根目录下的隐藏目录中。
+// 不是由本后端写入的文件按原样读写
+pub struct DedupBackend<B: StorageBackend + ?Sized = dyn StorageBackend> {
+	inner: Box<B>,
+	server: Option<Box<dyn ChunkStore>>,
+	manifests: Mutex<HashMap<String, CachedManifest>>,
+	chunks: Mutex<VecDeque<(String, Arc<Vec<u8>>)>>,
+}
+
+impl<B: StorageBackend + ?Sized> DedupBackend<B> {
+	pub fn new(inner: Box<B>, server: Option<Box<dyn ChunkStore>>) -> Self {
+		Self {
+			inner,
+			server,
+			manifests: Mutex::new(HashMap::new()),
+			chunks: Mutex::new(VecDeque::new()),
+		}
+	}
+
+	// 块目录不能通过挂载访问
+	fn check_path(path: &str) -> Result<(), RemoteError> {
+		if path
+			.split('/')
+			.next()
+			.is_some_and(|first| first.eq_ignore_ascii_case(CHUNK_DIR))
+		{
+			return Err(RemoteError::backend(
+				"invalid_name",
+				format!("{} is reserved for deduplicated chunks", CHUNK_DIR),
+			));
+		}
+		Ok(())
+	}
+
+	fn chunk_path(hash: &str) -> String {
+		format!("{}/{}/{}", CHUNK_DIR, &hash[..2], hash)
+	}
+
+	fn put_chunk(&self, hash: &str, data: &[u8]) -> Result<(), RemoteError> {
+		if let Some(server) = &self.server {
+			return server.put_chunk(hash, data);
+		}
+		let path = Self::chunk_path(hash);
+		match self.inner.commit(&path, data) {
+			Err(e) if matches!(e.code(), Some("not_found" | "parent_not_found")) => {
+				for dir in [
+					CHUNK_DIR.to_string(),
+					format!("{}/{}", CHUNK_DIR, &hash[..2]),
+				] {
+					match self.inner.create(&dir, true) {
+						Err(e) if e.code() != Some("already_exists") => return Err(e),
+						_ => {}
+					}
+				}
+				self.inner.commit(&path, data)
+			}
+			result => result,
+		}
+	}
+
+	fn missing_chunks(&self, hashes: &[String]) -> Result<Vec<String>, RemoteError> {
+		if let Some(server) = &self.server {
+			return server.missing_chunks(hashes);
+		}
+		let mut missing = Vec::new();
+		for hash in hashes {
+			match self.inner.stat(&Self::chunk_path(hash)) {
+				Ok(_) => {}
+				Err(e) if e.code() == Some("not_found") => missing.push(hash.clone()),
+				Err(e) => return Err(e),
+			}
+		}
+		Ok(missing)
+	}
+
+	// 读取并校验一块，最近读取的块保留在内存中
+	fn get_chunk(&self, path: &str, hash: &str) -> Result<Arc<Vec<u8>>, RemoteError> {
+		if let Some((_, data)) = self
+			.chunks
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|(cached, _)| cached == hash)
+		{
+			return Ok(data.clone());
+		}
+		let data = match &self.server {
+			Some(server) => server.get_chunk(hash)?,
+			None => match self.inner.read(&Self::chunk_path(hash), 0, MAX_CHUNK) {
+				Ok(data) => Some(data),
+				Err(e) if e.code() == Some("not_found") => None,
+				Err(e) => return Err(e),
+			},
+		};
+		let Some(data) = data else {
+			return Err(RemoteError::backend(
+				"corrupt_data",
+				format!("chunk {} of {} is missing", hash, path),
+			));
+		};
+		if sha256_hex(&data) != hash {
+			return Err(RemoteError::backend(
+				"checksum_mismatch",
+				format!("chunk {} of {} is damaged", hash, path),
+			));
+		}
+		let data = Arc::new(data);
+		let mut chunks = self.chunks.lock().unwrap();
+		if chunks.len() >= CACHED_CHUNKS {
+			chunks.pop_front();
+		}
+		chunks.push_back((hash.to_string(), data.clone()));
+		Ok(data)
+	}
+
+	// 第 first 到 last 块拼接后的内容
+	fn load_chunks(
+		&self,
+		path: &str,
+		manifest: &Manifest,
+		first: usize,
+		last: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let mut data = Vec::new();
+		for chunk in &manifest.chunks[first..=last] {
+			match &chunk.hash {
+				Some(hash) => {
+					let content = self.get_chunk(path, hash)?;
+					if content.len() as u64 != chunk.size {
+						return Err(corrupt(path));
+					}
+					data.extend_from_slice(&content);
+				}
+				None => data.resize(data.len() + chunk.size as usize, 0),
+			}
+		}
+		Ok(data)
+	}
+
+	// 切分数据并上传存储中还没有的块，返回对应的清单项
+	fn store_chunks(&self, data: &[u8]) -> Result<Vec<ChunkRef>, RemoteError> {
+		let pieces = split(data);
+		let chunks: Vec<ChunkRef> = pieces
+			.iter()
+			.map(|piece| ChunkRef {
+				hash: (!piece.iter().all(|&byte| byte == 0)).then(|| sha256_hex(piece)),
+				size: piece.len() as u64,
+			})
+			.collect();
+		let mut hashes: Vec<String> = chunks
+			.iter()
+			.filter_map(|chunk| chunk.hash.clone())
+			.collect();
+		hashes.sort_unstable();
+		hashes.dedup();
+		let mut missing: HashSet<String> = self.missing_chunks(&hashes)?.into_iter().collect();
+		for (piece, chunk) in pieces.iter().zip(&chunks) {
+			if let Some(hash) = &chunk.hash {
+				if missing.remove(hash) {
+					self.put_chunk(hash, piece)?;
+				}
+			}
+		}
+		Ok(chunks)
+	}
+
+	// 块全部上传之后再整体替换清单
+	fn save(&self, path: &str, manifest: Manifest) -> Result<(), RemoteError> {
+		let data = serde_json::to_vec(&manifest)
+			.map_err(|e| RemoteError::backend("invalid_input", e.to_string()))?;
+		self.inner.commit(path, &data)?;
+		let info = self.inner.stat(path)?;
+		self.cache(path, &info, Arc::new(manifest));
+		Ok(())
+	}
+
+	fn cache(&self, path: &str, info: &RemoteFileInfo, manifest: Arc<Manifest>) {
+		let mut manifests = self.manifests.lock().unwrap();
+		if manifests.len() >= MAX_CACHED_MANIFESTS {
+			manifests.clear();
+		}
+		manifests.insert(
+			path.to_string(),
+			CachedManifest {
+				stored: info.size,
+				modified: info.modified,
+				manifest,
+			},
+		);
+	}
+
+	fn forget(&self, path: &str) {
+		let prefix = format!("{}/", path);
+		self.manifests
+			.lock()
+			.unwrap()
+			.retain(|cached, _| cached != path && !cached.starts_with(&prefix));
+	}
+
+	// 文件当前的清单，不是去重格式时为 None。后端中的长度和修改时间不变时使用缓存的清单
+	fn current(&self, path: &str) -> Result<(RemoteFileInfo, Option<Arc<Manifest>>), RemoteError> {
+		Self::check_path(path)?;
+		let info = self.inner.stat(path)?;
+		if info.is_directory || info.size < MANIFEST_PREFIX.len() as u64 {
+			return Ok((info, None));
+		}
+		if let Some(cached) = self.manifests.lock().unwrap().get(path) {
+			if cached.stored == info.size && cached.modified == info.modified {
+				return Ok((info, Some(cached.manifest.clone())));
+			}
+		}
+		if self.inner.read(path, 0, MANIFEST_PREFIX.len())? != MANIFEST_PREFIX {
+			return Ok((info, None));
+		}
+		let data = self.inner.read(path, 0, info.size as usize)?;
+		let Some(manifest) = Manifest::parse(path, &data)? else {
+			return Ok((info, None));
+		};
+		let manifest = Arc::new(manifest);
+		self.cache(path, &info, manifest.clone());
+		Ok((info, Some(manifest)))
+	}
+
+	fn read_manifest(
+		&self,
+		path: &str,
+		manifest: &Manifest,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		if offset >= manifest.size || length == 0 {
+			return Ok(Vec::new());
+		}
+		let end = (offset + length as u64).min(manifest.size);
+		let ends = manifest.ends();
+		let first = ends.partition_point(|&chunk_end| chunk_end <= offset);
+		let last = ends.partition_point(|&chunk_end| chunk_end < end);
+		let start = if first == 0 { 0 } else { ends[first - 1] };
+		let mut data = self.load_chunks(path, manifest, first, last)?;
+		data.truncate((end - start) as usize);
+		data.drain(..(offset - start) as usize);
+		Ok(data)
+	}
+
+	// 重新切分写入涉及的块。写入位置正好在一块开头时前一块也一起切分，
+	// 这样依次追加写入的文件与整体提交的文件切出相同的块
+	fn write_manifest(
+		&self,
+		path: &str,
+		mut manifest: Manifest,
+		offset: u64,
+		data: &[u8],
+	) -> Result<(), RemoteError> {
+		let end = offset + data.len() as u64;
+		manifest.extend_zeros(end);
+		let ends = manifest.ends();
+		let mut first = ends.partition_point(|&chunk_end| chunk_end <= offset);
+		let last = ends.partition_point(|&chunk_end| chunk_end < end);
+		if first > 0 && ends[first - 1] == offset {
+			first -= 1;
+		}
+		let start = if first == 0 { 0 } else { ends[first - 1] };
+		let mut region = self.load_chunks(path, &manifest, first, last)?;
+		region[(offset - start) as usize..(end - start) as usize].copy_from_slice(data);
+		let chunks = self.store_chunks(&region)?;
+		manifest.chunks.splice(first..=last, chunks);
+		self.save(path, manifest)
+	}
+
+	// 列表和搜索结果中的文件：清单换算为文件长度
+	fn logical_info(
+		&self,
+		path: &str,
+		mut info: RemoteFileInfo,
+	) -> Result<RemoteFileInfo, RemoteError> {
+		if info.is_directory || info.size < MANIFEST_PREFIX.len() as u64 {
+			return Ok(info);
+		}
+		if let Some(size) = logical_size(&self.inner.read(path, 0, MANIFEST_HEAD_SIZE)?) {
+			info.size = size;
+		}
+		Ok(info)
+	}
+
+	// 列出后被删除等读取失败的条目保留存储中的信息
+	fn listed_info(&self, path: &str, info: RemoteFileInfo) -> RemoteFileInfo {
+		self.logical_info(path, info.clone()).unwrap_or(info)
+	}
+}
+
+impl<B: StorageBackend + ?Sized> StorageBackend for DedupBackend<B> {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		Self::check_path(path)?;
+		let info = self.inner.stat(path)?;
+		self.logical_info(path, info)
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		Self::check_path(path)?;
+		let page = self.inner.list_page(path, cursor)?;
+		let items = page
+			.items
+			.into_iter()
+			.filter(|info| path != "." || !info.name.eq_ignore_ascii_case(CHUNK_DIR))
+			.map(|info| {
+				let child = if path == "." {
+					info.name.clone()
+				} else {
+					format!("{}/{}", path, info.name)
+				};
+				self.listed_info(&child, info)
+			})
+			.collect();
+		Ok(ListPage {
+			items,
+			next_cursor: page.next_cursor,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		match self.current(path)? {
+			(_, Some(manifest)) => self.read_manifest(path, &manifest, offset, length),
+			(_, None) => self.inner.read(path, offset, length),
+		}
+	}
+
+	// 空文件首次写入时改为清单，之后保持原来的格式
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		if data.is_empty() {
+			Self::check_path(path)?;
+			return self.inner.write(path, offset, data);
+		}
+		match self.current(path)? {
+			(_, Some(manifest)) => self.write_manifest(path, (*manifest).clone(), offset, data),
+			(info, None) if info.size == 0 && !info.is_directory => {
+				self.write_manifest(path, Manifest::empty(), offset, data)
+			}
+			(_, None) => self.inner.write(path, offset, data),
+		}
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		if data.is_empty() {
+			self.forget(path);
+			return self.inner.commit(path, data);
+		}
+		let chunks = self.store_chunks(data)?;
+		self.save(
+			path,
+			Manifest {
+				size: data.len() as u64,
+				chunks,
+				..Manifest::empty()
+			},
+		)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		self.inner.create(path, is_directory)
+	}
+
+	// 块在服务器回收（/chunks/gc）之前一直保留
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		self.inner.delete(path, dry_run)?;
+		if !dry_run {
+			self.forget(path);
+		}
+		Ok(())
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		Self::check_path(old_path)?;
+		Self::check_path(new_path)?;
+		self.inner.rename(old_path, new_path, replace)?;
+		self.forget(old_path);
+		self.forget(new_path);
+		Ok(())
+	}
+
+	// 缩短时重新切分新的最后一块，加长时只在清单中添加全零的块
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let (_, manifest) = self.current(path)?;
+		let Some(manifest) = manifest.filter(|_| size > 0) else {
+			self.forget(path);
+			return self.inner.truncate(path, size);
+		};
+		if size == manifest.size {
+			return Ok(());
+		}
+		let mut manifest = (*manifest).clone();
+		if size > manifest.size {
+			manifest.extend_zeros(size);
+			return self.save(path, manifest);
+		}
+		let ends = manifest.ends();
+		let last = ends.partition_point(|&chunk_end| chunk_end < size);
+		let start = if last == 0 { 0 } else { ends[last - 1] };
+		let mut tail = Vec::new();
+		if ends[last] > size {
+			tail = self.load_chunks(path, &manifest, last, last)?;
+			tail.truncate((size - start) as usize);
+		}
+		manifest.chunks.truncate(last + 1);
+		if !tail.is_empty() {
+			let chunks = self.store_chunks(&tail)?;
+			manifest.chunks.splice(last.., chunks);
+		}
+		manifest.size = size;
+		self.save(path, manifest)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		self.inner.set_times(path, times)
+	}
+
+	fn read_only(&self) -> bool {
+		self.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.inner.space()
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		Self::check_path(path)?;
+		let mut response = self.inner.search(path, pattern, recursive)?;
+		response
+			.hits
+			.retain(|hit| Self::check_path(&hit.path).is_ok());
+		for hit in &mut response.hits {
+			hit.info = self.listed_info(&hit.path, hit.info.clone());
+		}
+		Ok(response)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		Self::check_path(path)?;
+		self.inner.list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		Self::check_path(path)?;
+		self.inner.get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		self.inner.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		Self::check_path(path)?;
+		self.inner.delete_xattr(path, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		Self::check_path(path)?;
+		let mut versions = self.inner.list_versions(path)?;
+		for version in &mut versions {
+			if version.size >= MANIFEST_PREFIX.len() as u64 {
+				if let Some(size) = logical_size(&self.inner.read_version(
+					path,
+					&version.id,
+					0,
+					MANIFEST_HEAD_SIZE,
+				)?) {
+					version.size = size;
+				}
+			}
+		}
+		Ok(versions)
+	}
+
+	// 历史版本是当时的清单，引用的块同样保留在块存储中
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		Self::check_path(path)?;
+		if self
+			.inner
+			.read_version(path, id, 0, MANIFEST_PREFIX.len())?
+			!= MANIFEST_PREFIX
+		{
+			return self.inner.read_version(path, id, offset, length);
+		}
+		let stored = self
+			.inner
+			.list_versions(path)?
+			.into_iter()
+			.find(|version| version.id == id)
+			.map_or(0, |version| version.size);
+		let data = self.inner.read_version(path, id, 0, stored as usize)?;
+		match Manifest::parse(path, &data)? {
+			Some(manifest) => self.read_manifest(path, &manifest, offset, length),
+			None => self.inner.read_version(path, id, offset, length),
+		}
+	}
+
+	// 回收站中的大小为清单的长度
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		self.inner.list_trash()
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		if let Some(path) = path {
+			Self::check_path(path)?;
+		}
+		self.inner.restore_trash(id, path)
+	}
+
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		self.inner.audit(filter)
+	}
+}
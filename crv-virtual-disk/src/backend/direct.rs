@@ -0,0 +1,116 @@
+use std::{
+	collections::HashMap,
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use reqwest::{
+	blocking::{Client, Response},
+	header::{LOCATION, RANGE},
+	StatusCode,
+};
+use tracing::debug;
+
+use crate::error::{CheckStatus, RemoteError, SendRetrying};
+
+// 服务器随重定向给出的签名 URL 有效期（秒）
+const EXPIRES_HEADER: &str = "x-httpfs-url-expires-in";
+// 在到期前这么久就不再使用缓存的 URL，避免请求途中过期
+const EXPIRY_MARGIN: Duration = Duration::from_secs(10);
+// 缓存的 URL 超过该数量时整体清空
+const MAX_CACHED_URLS: usize = 1000;
+
+struct SignedUrl {
+	url: String,
+	expires: Instant,
+}
+
+/// 跟随 `/read` 返回的签名 URL 直接从对象存储读取，URL 按路径缓存到快过期为止，
+/// 同一文件之后的读取不再经过服务器。签名 URL 用单独的客户端请求，不带服务器的令牌。
+pub(super) struct DirectReads {
+	client: Client,
+	urls: Mutex<HashMap<String, SignedUrl>>,
+}
+
+impl DirectReads {
+	pub fn new() -> Self {
+		Self {
+			client: Client::builder()
+				.timeout(Duration::from_secs(30))
+				.build()
+				.unwrap(),
+			urls: Mutex::new(HashMap::new()),
+		}
+	}
+
+	// 缓存中仍然有效的 URL
+	pub fn cached(&self, path: &str) -> Option<String> {
+		let urls = self.urls.lock().unwrap();
+		urls.get(path)
+			.filter(|signed| signed.expires > Instant::now())
+			.map(|signed| signed.url.clone())
+	}
+
+	// 记下服务器重定向给出的 URL，返回该 URL；没有 Location 时返回 None
+	pub fn remember(&self, path: &str, response: &Response) -> Option<String> {
+		let url = response.headers().get(LOCATION)?.to_str().ok()?.to_string();
+		let lifetime = response
+			.headers()
+			.get(EXPIRES_HEADER)
+			.and_then(|value| value.to_str().ok())
+			.and_then(|value| value.parse().ok())
+			.map(Duration::from_secs)
+			.unwrap_or_default();
+		if let Some(lifetime) = lifetime.checked_sub(EXPIRY_MARGIN) {
+			let mut urls = self.urls.lock().unwrap();
+			if urls.len() >= MAX_CACHED_URLS {
+				urls.clear();
+			}
+			urls.insert(
+				path.to_string(),
+				SignedUrl {
+					url: url.clone(),
+					expires: Instant::now() + lifetime,
+				},
+			);
+		}
+		Some(url)
+	}
+
+	// 用 Range 请求读取对象的一段；读取位置超出文件末尾时对象存储返回 416，与服务器一样返回空内容
+	pub fn read(&self, url: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		if length == 0 {
+			return Ok(Vec::new());
+		}
+		let range = format!("bytes={}-{}", offset, offset + length as u64 - 1);
+		let response = self.client.get(url).header(RANGE, range).send_retrying()?;
+		if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+			return Ok(Vec::new());
+		}
+		let response = response.check_status()?;
+		// 不支持 Range 的对象存储返回完整内容
+		let full = response.status() == StatusCode::OK;
+		let data = response.bytes()?;
+		if full {
+			let start = (offset as usize).min(data.len());
+			let end = start.saturating_add(length).min(data.len());
+			return Ok(data[start..end].to_vec());
+		}
+		Ok(data.to_vec())
+	}
+
+	// 文件修改、删除或移动后丢弃它（和目录下所有文件）的 URL，之后的读取重新询问服务器
+	pub fn forget(&self, path: &str) {
+		let mut urls = self.urls.lock().unwrap();
+		let before = urls.len();
+		urls.retain(|cached, _| {
+			cached != path
+				&& !cached
+					.strip_prefix(path)
+					.is_some_and(|rest| rest.starts_with('/'))
+		});
+		if urls.len() != before {
+			debug!(path = %path, "dropped cached signed URLs");
+		}
+	}
+}
@@ -0,0 +1,189 @@
+use std::{
+	collections::HashMap,
+	error::Error,
+	io,
+	path::Path,
+	sync::{Arc, Mutex},
+};
+
+use super::{url_path, write_protected, StorageBackend};
+use crate::{
+	backend::Remote,
+	error::RemoteError,
+	image::{self, cache::CacheSettings, Entry, FileSystem},
+	ListPage, RemoteFileInfo, TimesUpdate,
+};
+
+fn not_found(path: &str) -> RemoteError {
+	RemoteError::backend("not_found", format!("{} is not on the volume", path))
+}
+
+fn image_error(path: &str, error: io::Error) -> RemoteError {
+	RemoteError::backend("image_error", format!("{}: {}", path, error))
+}
+
+fn info(name: &str, entry: &Entry) -> RemoteFileInfo {
+	RemoteFileInfo {
+		name: name.to_string(),
+		is_directory: entry.is_directory,
+		size: entry.size,
+		created: entry.created,
+		modified: entry.modified,
+		accessed: entry.accessed,
+		stored_size: None,
+		allocated_size: None,
+	}
+}
+
+// 只读挂载虚拟磁盘映像中的一个卷：vdisk:///D:/vm/disk.vhdx，格式按内容识别（VHD、VHDX、QCOW2 或原始映像），
+// --partition 选择分区（默认为第一个能识别文件系统的分区），--block-cache 在映像之上加块缓存。
+// 目录在第一次访问时读取并缓存，名称与 Windows 一样不区分大小写
+pub struct DiskBackend {
+	fs: Box<dyn FileSystem>,
+	// 已读取的目录，键为目录在卷中的实际路径，根目录为 "."
+	directories: Mutex<HashMap<String, Arc<Vec<Entry>>>>,
+}
+
+impl DiskBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to vdisk:// URLs".into());
+		}
+		let path = url_path(&remote.server_url);
+		Ok(
+			Self::open(&path, remote.partition, remote.block_cache.as_ref())
+				.map_err(|e| format!("cannot open {}: {}", path.display(), e))?,
+		)
+	}
+
+	pub fn open(
+		path: &Path,
+		partition: Option<usize>,
+		cache: Option<&CacheSettings>,
+	) -> io::Result<Self> {
+		let mut device = image::open(path)?;
+		if let Some(cache) = cache {
+			device = image::cache::wrap(device, cache);
+		}
+		Ok(Self {
+			fs: image::open_volume(device, partition)?,
+			directories: Mutex::new(HashMap::new()),
+		})
+	}
+
+	fn children(&self, key: &str, directory: &Entry) -> Result<Arc<Vec<Entry>>, RemoteError> {
+		if let Some(children) = self.directories.lock().unwrap().get(key) {
+			return Ok(children.clone());
+		}
+		let children = Arc::new(self.fs.list(directory).map_err(|e| image_error(key, e))?);
+		self.directories
+			.lock()
+			.unwrap()
+			.insert(key.to_string(), children.clone());
+		Ok(children)
+	}
+
+	// 从根目录逐级查找，返回条目和它在卷中的实际路径；名称完全相同的优先，其次是只有大小写不同的
+	fn lookup(&self, path: &str) -> Result<(String, Entry), RemoteError> {
+		let mut entry = self.fs.root();
+		let mut key = ".".to_string();
+		if path == "." {
+			return Ok((key, entry));
+		}
+		for name in path.split('/') {
+			if !entry.is_directory {
+				return Err(not_found(path));
+			}
+			let children = self.children(&key, &entry)?;
+			let lowercase = name.to_lowercase();
+			entry = children
+				.iter()
+				.find(|child| child.name == name)
+				.or_else(|| {
+					children
+						.iter()
+						.find(|child| child.name.to_lowercase() == lowercase)
+				})
+				.cloned()
+				.ok_or_else(|| not_found(path))?;
+			key = if key == "." {
+				entry.name.clone()
+			} else {
+				format!("{}/{}", key, entry.name)
+			};
+		}
+		Ok((key, entry))
+	}
+}
+
+impl StorageBackend for DiskBackend {
+	fn read_only(&self) -> bool {
+		true
+	}
+
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let (_, entry) = self.lookup(path)?;
+		// 根目录在文件系统中没有名称
+		Ok(info(if path == "." { path } else { &entry.name }, &entry))
+	}
+
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let (key, directory) = self.lookup(path)?;
+		if !directory.is_directory {
+			return Err(RemoteError::backend(
+				"not_a_directory",
+				format!("{} is not a directory", path),
+			));
+		}
+		let items = self
+			.children(&key, &directory)?
+			.iter()
+			.map(|entry| info(&entry.name, entry))
+			.collect();
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let (_, file) = self.lookup(path)?;
+		if file.is_directory {
+			return Err(RemoteError::backend(
+				"is_a_directory",
+				format!("{} is a directory", path),
+			));
+		}
+		self.fs
+			.read(&file, offset, length)
+			.map_err(|e| image_error(path, e))
+	}
+
+	fn write(&self, path: &str, _offset: u64, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn commit(&self, path: &str, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn create(&self, path: &str, _is_directory: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn delete(&self, path: &str, _dry_run: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn rename(&self, old_path: &str, _new_path: &str, _replace: bool) -> Result<(), RemoteError> {
+		Err(write_protected(old_path))
+	}
+
+	fn truncate(&self, path: &str, _size: u64) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn set_times(&self, path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+}
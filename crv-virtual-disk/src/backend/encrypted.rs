@@ -0,0 +1,766 @@
+use std::{error::Error, fs, path::PathBuf};
+
+use aes_gcm::{
+	aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+	Aes256Gcm, Nonce,
+};
+use argon2::{Algorithm, Argon2, Params, Version};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+
+use super::{base_name, read_console, StorageBackend};
+use crate::{
+	error::RemoteError, AuditFilter, AuditResponse, ListPage, RemoteFileInfo, RestoreResponse,
+	SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+// 明文按 64 KiB 分块，每块用独立的随机 nonce 加密并带认证标签
+const CHUNK_SIZE: u64 = 64 * 1024;
+const NONCE_SIZE: u64 = 12;
+const TAG_SIZE: u64 = 16;
+const STORED_CHUNK_SIZE: u64 = NONCE_SIZE + CHUNK_SIZE + TAG_SIZE;
+
+// 加密文件的开头：格式标识和随机的文件标识。文件标识和块序号作为附加数据参与认证，
+// 块不能在文件之间或文件内部调换。空文件不加密，保存为 0 字节
+const MAGIC: &[u8; 4] = b"HFE1";
+const FILE_ID_SIZE: usize = 16;
+const HEADER_SIZE: u64 = MAGIC.len() as u64 + FILE_ID_SIZE as u64;
+
+// 存储根目录下记录密钥派生参数的文件，不出现在目录列表中
+const VOLUME_FILE: &str = ".httpfs-encryption";
+const VOLUME_FILE_MAX_SIZE: usize = 64 * 1024;
+const FORMAT_VERSION: u32 = 1;
+const SALT_SIZE: usize = 16;
+// 用内容密钥加密的固定文本，打开时用来检查口令或密钥文件
+const CHECK_TEXT: &[u8] = b"httpfs encryption check";
+
+// 口令的 Argon2id 参数：64 MiB 内存，3 轮，单线程
+const ARGON2_MEMORY_KIB: u32 = 64 * 1024;
+const ARGON2_ITERATIONS: u32 = 3;
+const ARGON2_PARALLELISM: u32 = 1;
+const MIN_KEY_FILE_SIZE: usize = 32;
+
+// 多数存储的单个名称长度上限，加密后的名称更长时返回 invalid_name
+const MAX_NAME_LENGTH: usize = 255;
+// 不区分大小写的存储也能保存的 base32 字母表（小写，不带填充）
+const BASE32: &[u8; 32] = b"abcdefghijklmnopqrstuvwxyz234567";
+
+// 扩展文件时每次写入的零的长度
+const EXTEND_STEP: u64 = 16 * CHUNK_SIZE;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// 密钥的来源：口令（None 时在控制台询问）或密钥文件
+pub enum KeySource {
+	Passphrase(Option<String>),
+	KeyFile(PathBuf),
+}
+
+#[derive(Serialize, Deserialize)]
+struct Argon2Settings {
+	memory_kib: u32,
+	iterations: u32,
+	parallelism: u32,
+}
+
+// .httpfs-encryption 的内容；kdf 为 argon2id（口令）或 keyfile
+#[derive(Serialize, Deserialize)]
+struct VolumeFile {
+	version: u32,
+	kdf: String,
+	salt: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	argon2: Option<Argon2Settings>,
+	names: bool,
+	check: String,
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> [u8; 32] {
+	let mut mac =
+		<HmacSha256 as Mac>::new_from_slice(key).expect("HMAC accepts keys of any length");
+	mac.update(data);
+	mac.finalize().into_bytes().into()
+}
+
+fn random_bytes<const N: usize>() -> [u8; N] {
+	let mut bytes = [0; N];
+	OsRng.fill_bytes(&mut bytes);
+	bytes
+}
+
+fn base32_encode(data: &[u8]) -> String {
+	let mut encoded = String::with_capacity((data.len() * 8).div_ceil(5));
+	let (mut bits, mut count) = (0u32, 0);
+	for &byte in data {
+		bits = (bits << 8) | byte as u32;
+		count += 8;
+		while count >= 5 {
+			count -= 5;
+			encoded.push(BASE32[((bits >> count) & 31) as usize] as char);
+		}
+	}
+	if count > 0 {
+		encoded.push(BASE32[((bits << (5 - count)) & 31) as usize] as char);
+	}
+	encoded
+}
+
+fn base32_decode(text: &str) -> Option<Vec<u8>> {
+	let mut data = Vec::with_capacity(text.len() * 5 / 8);
+	let (mut bits, mut count) = (0u32, 0);
+	for byte in text.bytes() {
+		bits = (bits << 5) | BASE32.iter().position(|&c| c == byte)? as u32;
+		count += 5;
+		if count >= 8 {
+			count -= 8;
+			data.push((bits >> count) as u8);
+		}
+	}
+	Some(data)
+}
+
+// 存储中的文件长度对应的明文长度
+fn plain_size(stored: u64) -> u64 {
+	let Some(body) = stored.checked_sub(HEADER_SIZE) else {
+		return 0;
+	};
+	let rest = body % STORED_CHUNK_SIZE;
+	body / STORED_CHUNK_SIZE * CHUNK_SIZE + rest.saturating_sub(NONCE_SIZE + TAG_SIZE)
+}
+
+// 第 index 块在存储中的位置
+fn stored_offset(index: u64) -> u64 {
+	HEADER_SIZE + index * STORED_CHUNK_SIZE
+}
+
+fn corrupt(path: &str) -> RemoteError {
+	RemoteError::backend(
+		"decryption_failed",
+		format!("cannot decrypt {}: wrong key or corrupted data", path),
+	)
+}
+
+// 口令来自 HTTPFS_PASSPHRASE 或控制台；首次加密时在控制台输入两次
+fn passphrase(given: &Option<String>, creating: bool) -> Result<String, Box<dyn Error>> {
+	if let Some(passphrase) = given {
+		return Ok(passphrase.clone());
+	}
+	let passphrase = read_console("Encryption passphrase: ", false)
+		.ok_or("set HTTPFS_PASSPHRASE or use --key-file when there is no console")?;
+	if passphrase.is_empty() {
+		return Err("the encryption passphrase must not be empty".into());
+	}
+	if creating
+		&& read_console("Repeat the passphrase: ", false).as_deref() != Some(passphrase.as_str())
+	{
+		return Err("the passphrases do not match".into());
+	}
+	Ok(passphrase)
+}
+
+// 由口令或密钥文件得到主密钥
+fn master_key(
+	volume: &VolumeFile,
+	key: &KeySource,
+	creating: bool,
+) -> Result<[u8; 32], Box<dyn Error>> {
+	let salt = hex::decode(&volume.salt).map_err(|_| format!("invalid salt in {}", VOLUME_FILE))?;
+	match (volume.kdf.as_str(), key) {
+		("argon2id", KeySource::Passphrase(given)) => {
+			let settings = volume
+				.argon2
+				.as_ref()
+				.ok_or_else(|| format!("{} has no Argon2 parameters", VOLUME_FILE))?;
+			let params = Params::new(
+				settings.memory_kib,
+				settings.iterations,
+				settings.parallelism,
+				Some(32),
+			)
+			.map_err(|e| e.to_string())?;
+			let mut master = [0; 32];
+			Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+				.hash_password_into(passphrase(given, creating)?.as_bytes(), &salt, &mut master)
+				.map_err(|e| e.to_string())?;
+			Ok(master)
+		}
+		("keyfile", KeySource::KeyFile(path)) => {
+			let content = fs::read(path)
+				.map_err(|e| format!("cannot read key file {}: {}", path.display(), e))?;
+			if content.len() < MIN_KEY_FILE_SIZE {
+				return Err(format!(
+					"the key file {} must be at least {} bytes",
+					path.display(),
+					MIN_KEY_FILE_SIZE
+				)
+				.into());
+			}
+			Ok(hmac(&salt, &content))
+		}
+		("argon2id", KeySource::KeyFile(_)) => {
+			Err("the volume is encrypted with a passphrase, not a key file".into())
+		}
+		("keyfile", KeySource::Passphrase(_)) => {
+			Err("the volume is encrypted with a key file; use --key-file".into())
+		}
+		(kdf, _) => Err(format!("unknown key derivation '{}' in {}", kdf, VOLUME_FILE).into()),
+	}
+}
+
+// 由主密钥派生的子密钥
+struct Keys {
+	content: Aes256Gcm,
+	names: Aes256Gcm,
+	// 由名称计算 nonce 的 HMAC 密钥：相同的名称总是加密为相同的结果，才能按名称查找
+	name_nonce: [u8; 32],
+}
+
+impl Keys {
+	fn new(master: &[u8; 32]) -> Self {
+		Self {
+			content: Aes256Gcm::new(&hmac(master, b"httpfs content").into()),
+			names: Aes256Gcm::new(&hmac(master, b"httpfs names").into()),
+			name_nonce: hmac(master, b"httpfs name nonce"),
+		}
+	}
+
+	// 随机 nonce 加上密文和标签
+	fn seal(&self, data: &[u8], aad: &[u8]) -> Vec<u8> {
+		let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+		let mut sealed = nonce.to_vec();
+		sealed.extend(
+			self.content
+				.encrypt(&nonce, Payload { msg: data, aad })
+				.expect("AES-GCM input is within its size limit"),
+		);
+		sealed
+	}
+
+	fn open(&self, sealed: &[u8], aad: &[u8]) -> Option<Vec<u8>> {
+		if sealed.len() < (NONCE_SIZE + TAG_SIZE) as usize {
+			return None;
+		}
+		let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE as usize);
+		self.content
+			.decrypt(
+				Nonce::from_slice(nonce),
+				Payload {
+					msg: ciphertext,
+					aad,
+				},
+			)
+			.ok()
+	}
+}
+
+// 块的附加数据：文件标识和块序号
+fn chunk_aad(file_id: &[u8], index: u64) -> Vec<u8> {
+	let mut aad = file_id.to_vec();
+	aad.extend(index.to_le_bytes());
+	aad
+}
+
+// 在任意后端之上透明地加密文件内容（AES-256-GCM，每 64 KiB 一块），可选地加密文件名，
+// 存储端只能看到密文。密钥由口令（Argon2id）或密钥文件派生，参数保存在存储根目录的 .httpfs-encryption 中
+pub struct EncryptedBackend<B: StorageBackend + ?Sized = dyn StorageBackend> {
+	inner: Box<B>,
+	keys: Keys,
+	encrypt_names: bool,
+}
+
+impl<B: StorageBackend + ?Sized> EncryptedBackend<B> {
+	// 打开已加密的存储；存储为空时创建 .httpfs-encryption，否则检查密钥是否正确。
+	// 是否加密文件名在首次加密时决定
+	pub fn open(
+		inner: Box<B>,
+		key: &KeySource,
+		encrypt_names: bool,
+	) -> Result<Self, Box<dyn Error>> {
+		let existing = match inner.read(VOLUME_FILE, 0, VOLUME_FILE_MAX_SIZE) {
+			Ok(data) => Some(
+				serde_json::from_slice::<VolumeFile>(&data)
+					.map_err(|e| format!("invalid {}: {}", VOLUME_FILE, e))?,
+			),
+			Err(e) if e.code() == Some("not_found") => None,
+			Err(e) => return Err(e.into()),
+		};
+		let Some(volume) = existing else {
+			return Self::create(inner, key, encrypt_names);
+		};
+		if volume.version != FORMAT_VERSION {
+			return Err(format!(
+				"unsupported encryption format version {} in {}",
+				volume.version, VOLUME_FILE
+			)
+			.into());
+		}
+		if encrypt_names && !volume.names {
+			return Err("the volume was encrypted without --encrypt-names; file names cannot be encrypted afterwards".into());
+		}
+		let keys = Keys::new(&master_key(&volume, key, false)?);
+		let check = hex::decode(&volume.check)
+			.map_err(|_| format!("invalid check value in {}", VOLUME_FILE))?;
+		if keys.open(&check, MAGIC).as_deref() != Some(CHECK_TEXT) {
+			return Err("wrong encryption passphrase or key file".into());
+		}
+		Ok(Self {
+			inner,
+			keys,
+			encrypt_names: volume.names,
+		})
+	}
+
+	// 只在空的存储上开始加密，已有的明文文件不会被加密
+	fn create(inner: Box<B>, key: &KeySource, encrypt_names: bool) -> Result<Self, Box<dyn Error>> {
+		if !inner.list_page(".", None)?.items.is_empty() {
+			return Err(format!(
+				"the storage is not empty and has no {}; encryption can only be enabled on an empty volume",
+				VOLUME_FILE
+			)
+			.into());
+		}
+		let mut volume = VolumeFile {
+			version: FORMAT_VERSION,
+			kdf: String::new(),
+			salt: hex::encode(random_bytes::<SALT_SIZE>()),
+			argon2: None,
+			names: encrypt_names,
+			check: String::new(),
+		};
+		match key {
+			KeySource::Passphrase(_) => {
+				volume.kdf = "argon2id".to_string();
+				volume.argon2 = Some(Argon2Settings {
+					memory_kib: ARGON2_MEMORY_KIB,
+					iterations: ARGON2_ITERATIONS,
+					parallelism: ARGON2_PARALLELISM,
+				});
+			}
+			KeySource::KeyFile(_) => volume.kdf = "keyfile".to_string(),
+		}
+		let keys = Keys::new(&master_key(&volume, key, true)?);
+		volume.check = hex::encode(keys.seal(CHECK_TEXT, MAGIC));
+		inner.commit(VOLUME_FILE, &serde_json::to_vec_pretty(&volume)?)?;
+		Ok(Self {
+			inner,
+			keys,
+			encrypt_names,
+		})
+	}
+
+	// 确定性加密的名称：nonce 由名称的 HMAC 得到，结果为 nonce 和密文的 base32
+	fn encrypt_name(&self, name: &str) -> Result<String, RemoteError> {
+		let nonce = hmac(&self.keys.name_nonce, name.as_bytes());
+		let nonce = Nonce::from_slice(&nonce[..NONCE_SIZE as usize]);
+		let mut sealed = nonce.to_vec();
+		sealed.extend(
+			self.keys
+				.names
+				.encrypt(nonce, name.as_bytes())
+				.expect("AES-GCM input is within its size limit"),
+		);
+		let encoded = base32_encode(&sealed);
+		if encoded.len() > MAX_NAME_LENGTH {
+			return Err(RemoteError::backend(
+				"invalid_name",
+				format!("{} is too long to be encrypted", name),
+			));
+		}
+		Ok(encoded)
+	}
+
+	// 不是本卷加密的名称（如其他程序放入的文件）返回 None
+	fn decrypt_name(&self, encoded: &str) -> Option<String> {
+		let sealed = base32_decode(encoded)?;
+		if sealed.len() < (NONCE_SIZE + TAG_SIZE) as usize {
+			return None;
+		}
+		let (nonce, ciphertext) = sealed.split_at(NONCE_SIZE as usize);
+		String::from_utf8(
+			self.keys
+				.names
+				.decrypt(Nonce::from_slice(nonce), ciphertext)
+				.ok()?,
+		)
+		.ok()
+	}
+
+	// 内层存储中的路径
+	fn inner_path(&self, path: &str) -> Result<String, RemoteError> {
+		if !self.encrypt_names {
+			if path.eq_ignore_ascii_case(VOLUME_FILE) {
+				return Err(RemoteError::backend(
+					"invalid_name",
+					format!("{} is reserved for the encryption settings", VOLUME_FILE),
+				));
+			}
+			return Ok(path.to_string());
+		}
+		let components = path
+			.split('/')
+			.map(|component| match component {
+				"." | ".." | "" => Ok(component.to_string()),
+				name => self.encrypt_name(name),
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(components.join("/"))
+	}
+
+	// 内层存储路径对应的明文路径，不能解密时返回 None
+	fn plain_path(&self, inner: &str) -> Option<String> {
+		if !self.encrypt_names || inner == "." {
+			return Some(inner.to_string());
+		}
+		let components = inner
+			.split('/')
+			.map(|component| self.decrypt_name(component))
+			.collect::<Option<Vec<_>>>()?;
+		Some(components.join("/"))
+	}
+
+	// 目录列表中的条目：隐藏 .httpfs-encryption 和不能解密的名称，文件大小换算为明文长度
+	fn plain_info(&self, at_root: bool, mut info: RemoteFileInfo) -> Option<RemoteFileInfo> {
+		if at_root && info.name == VOLUME_FILE {
+			return None;
+		}
+		if self.encrypt_names {
+			info.name = self.decrypt_name(&info.name)?;
+		}
+		if !info.is_directory {
+			info.size = plain_size(info.size);
+		}
+		Some(info)
+	}
+
+	fn file_id(&self, path: &str, data: &[u8]) -> Result<Vec<u8>, RemoteError> {
+		match data.get(..HEADER_SIZE as usize) {
+			Some(header) if header.starts_with(MAGIC) => Ok(header[MAGIC.len()..].to_vec()),
+			_ => Err(corrupt(path)),
+		}
+	}
+
+	// 解密 offset 开始的 length 字节明文；read 读取存储中的内容，用于当前内容和历史版本
+	fn read_with(
+		&self,
+		path: &str,
+		offset: u64,
+		length: usize,
+		read: impl Fn(u64, usize) -> Result<Vec<u8>, RemoteError>,
+	) -> Result<Vec<u8>, RemoteError> {
+		if length == 0 {
+			return Ok(Vec::new());
+		}
+		let first = offset / CHUNK_SIZE;
+		let count = ((offset + length as u64 - 1) / CHUNK_SIZE - first + 1) as usize;
+		let (file_id, body) = if first == 0 {
+			let mut data = read(0, HEADER_SIZE as usize + count * STORED_CHUNK_SIZE as usize)?;
+			if data.is_empty() {
+				return Ok(Vec::new());
+			}
+			let file_id = self.file_id(path, &data)?;
+			(file_id, data.split_off(HEADER_SIZE as usize))
+		} else {
+			let body = read(stored_offset(first), count * STORED_CHUNK_SIZE as usize)?;
+			if body.is_empty() {
+				return Ok(Vec::new());
+			}
+			(self.file_id(path, &read(0, HEADER_SIZE as usize)?)?, body)
+		};
+		let mut plain = Vec::with_capacity(body.len());
+		for (i, sealed) in body.chunks(STORED_CHUNK_SIZE as usize).enumerate() {
+			plain.extend(
+				self.keys
+					.open(sealed, &chunk_aad(&file_id, first + i as u64))
+					.ok_or_else(|| corrupt(path))?,
+			);
+		}
+		plain.drain(..((offset - first * CHUNK_SIZE) as usize).min(plain.len()));
+		plain.truncate(length);
+		Ok(plain)
+	}
+
+	fn read_inner(&self, inner: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.read_with(inner, offset, length, |offset, length| {
+			self.inner.read(inner, offset, length)
+		})
+	}
+
+	fn plain_len(&self, inner: &str) -> Result<u64, RemoteError> {
+		Ok(plain_size(self.inner.stat(inner)?.size))
+	}
+
+	// 整个文件的存储内容
+	fn seal_file(&self, data: &[u8]) -> Vec<u8> {
+		if data.is_empty() {
+			return Vec::new();
+		}
+		let file_id = random_bytes::<FILE_ID_SIZE>();
+		let mut stored = MAGIC.to_vec();
+		stored.extend(file_id);
+		for (index, chunk) in data.chunks(CHUNK_SIZE as usize).enumerate() {
+			stored.extend(self.keys.seal(chunk, &chunk_aad(&file_id, index as u64)));
+		}
+		stored
+	}
+
+	// 重新加密写入涉及的块：从 offset 和原长度中较小者所在的块开始，到写入结束所在的块
+	fn write_inner(&self, inner: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		if data.is_empty() {
+			return Ok(());
+		}
+		let size = self.plain_len(inner)?;
+		let first = offset.min(size) / CHUNK_SIZE;
+		let start = first * CHUNK_SIZE;
+		let end = offset + data.len() as u64;
+		let old_end = size.min(end.div_ceil(CHUNK_SIZE) * CHUNK_SIZE);
+		let mut plain = if old_end > start {
+			self.read_inner(inner, start, (old_end - start) as usize)?
+		} else {
+			Vec::new()
+		};
+		plain.resize(plain.len().max((end - start) as usize), 0);
+		plain[(offset - start) as usize..(end - start) as usize].copy_from_slice(data);
+		if size == 0 {
+			return self.inner.write(inner, 0, &self.seal_file(&plain));
+		}
+		let file_id = self.file_id(inner, &self.inner.read(inner, 0, HEADER_SIZE as usize)?)?;
+		let mut stored = Vec::with_capacity(
+			plain.len().div_ceil(CHUNK_SIZE as usize) * STORED_CHUNK_SIZE as usize,
+		);
+		for (i, chunk) in plain.chunks(CHUNK_SIZE as usize).enumerate() {
+			stored.extend(
+				self.keys
+					.seal(chunk, &chunk_aad(&file_id, first + i as u64)),
+			);
+		}
+		self.inner.write(inner, stored_offset(first), &stored)
+	}
+}
+
+impl<B: StorageBackend + ?Sized> StorageBackend for EncryptedBackend<B> {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let mut info = self.inner.stat(&self.inner_path(path)?)?;
+		if self.encrypt_names && path != "." {
+			info.name = base_name(path).to_string();
+		}
+		if !info.is_directory {
+			info.size = plain_size(info.size);
+		}
+		Ok(info)
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let page = self.inner.list_page(&self.inner_path(path)?, cursor)?;
+		Ok(ListPage {
+			items: page
+				.items
+				.into_iter()
+				.filter_map(|info| self.plain_info(path == ".", info))
+				.collect(),
+			next_cursor: page.next_cursor,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.read_inner(&self.inner_path(path)?, offset, length)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.write_inner(&self.inner_path(path)?, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.inner
+			.commit(&self.inner_path(path)?, &self.seal_file(data))
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.inner.create(&self.inner_path(path)?, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.inner.delete(&self.inner_path(path)?, dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.inner.rename(
+			&self.inner_path(old_path)?,
+			&self.inner_path(new_path)?,
+			replace,
+		)
+	}
+
+	// 缩短时重新加密新的最后一块，加长时分段写入零
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let inner = self.inner_path(path)?;
+		let old_size = self.plain_len(&inner)?;
+		if size == 0 {
+			return self.inner.truncate(&inner, 0);
+		}
+		if size > old_size {
+			let mut position = old_size;
+			while position < size {
+				let step = (size - position).min(EXTEND_STEP);
+				self.write_inner(&inner, position, &vec![0; step as usize])?;
+				position += step;
+			}
+			return Ok(());
+		}
+		if size == old_size {
+			return Ok(());
+		}
+		let last = (size - 1) / CHUNK_SIZE;
+		let tail = self.read_inner(
+			&inner,
+			last * CHUNK_SIZE,
+			(size - last * CHUNK_SIZE) as usize,
+		)?;
+		let file_id = self.file_id(&inner, &self.inner.read(&inner, 0, HEADER_SIZE as usize)?)?;
+		self.inner.truncate(&inner, stored_offset(last))?;
+		self.inner.write(
+			&inner,
+			stored_offset(last),
+			&self.keys.seal(&tail, &chunk_aad(&file_id, last)),
+		)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.inner.set_times(&self.inner_path(path)?, times)
+	}
+
+	fn read_only(&self) -> bool {
+		self.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.inner.space()
+	}
+
+	// 加密的名称不能按通配符匹配
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		if self.encrypt_names {
+			return Err(RemoteError::unsupported("search with encrypted file names"));
+		}
+		let mut response = self
+			.inner
+			.search(&self.inner_path(path)?, pattern, recursive)?;
+		response.hits.retain_mut(|hit| {
+			if hit.path.eq_ignore_ascii_case(VOLUME_FILE) {
+				return false;
+			}
+			if !hit.info.is_directory {
+				hit.info.size = plain_size(hit.info.size);
+			}
+			true
+		});
+		Ok(response)
+	}
+
+	// 属性名不加密，值整体加密，附加数据为属性名
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		let mut entries = self.inner.list_xattrs(&self.inner_path(path)?)?;
+		for entry in &mut entries {
+			entry.size = entry.size.saturating_sub(NONCE_SIZE + TAG_SIZE);
+		}
+		Ok(entries)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		let Some(sealed) = self.inner.get_xattr(&self.inner_path(path)?, name)? else {
+			return Ok(None);
+		};
+		self.keys
+			.open(&sealed, name.as_bytes())
+			.map(Some)
+			.ok_or_else(|| corrupt(path))
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.inner.put_xattr(
+			&self.inner_path(path)?,
+			name,
+			&self.keys.seal(value, name.as_bytes()),
+		)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.inner.delete_xattr(&self.inner_path(path)?, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		let mut versions = self.inner.list_versions(&self.inner_path(path)?)?;
+		for version in &mut versions {
+			version.size = plain_size(version.size);
+		}
+		Ok(versions)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let inner = self.inner_path(path)?;
+		self.read_with(path, offset, length, |offset, length| {
+			self.inner.read_version(&inner, id, offset, length)
+		})
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		let entries = self.inner.list_trash()?;
+		Ok(entries
+			.into_iter()
+			.filter_map(|mut entry| {
+				entry.path = self.plain_path(&entry.path)?;
+				if !entry.is_directory {
+					entry.size = plain_size(entry.size);
+				}
+				Some(entry)
+			})
+			.collect())
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		let inner = path.map(|path| self.inner_path(path)).transpose()?;
+		let mut response = self.inner.restore_trash(id, inner.as_deref())?;
+		if let Some(path) = self.plain_path(&response.path) {
+			response.path = path;
+		}
+		Ok(response)
+	}
+
+	// 不能解密的路径（如其他程序放入的文件）按存储中的名称显示
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		let inner = AuditFilter {
+			path: filter
+				.path
+				.as_deref()
+				.map(|path| self.inner_path(path))
+				.transpose()?,
+			user: filter.user.clone(),
+			..*filter
+		};
+		let mut response = self.inner.audit(&inner)?;
+		for entry in &mut response.entries {
+			if let Some(path) = self.plain_path(&entry.path) {
+				entry.path = path;
+			}
+			if let Some(path) = entry
+				.new_path
+				.as_deref()
+				.and_then(|path| self.plain_path(path))
+			{
+				entry.new_path = Some(path);
+			}
+		}
+		Ok(response)
+	}
+}
@@ -0,0 +1,202 @@
+use super::{BatchFile, StorageBackend};
+use crate::{
+	error::RemoteError, names::NameEscaping, AuditFilter, AuditResponse, ChecksumResponse,
+	ListPage, RemoteFileInfo, RestoreResponse, SearchResponse, SpaceResponse, TimesUpdate,
+	TrashEntry, VersionInfo, XattrEntry,
+};
+
+// 在任意后端之上转义 Windows 不能使用的名称：目录列表、搜索结果、回收站和审计日志中的名称按 escaping 转义，
+// 收到的路径逐段还原后交给内层后端，远程文件因此能在挂载中显示、打开和原样改名
+pub struct EscapedBackend<B: StorageBackend + ?Sized = dyn StorageBackend> {
+	inner: Box<B>,
+	escaping: NameEscaping,
+}
+
+impl<B: StorageBackend + ?Sized> EscapedBackend<B> {
+	pub fn new(inner: Box<B>, escaping: NameEscaping) -> Self {
+		Self { inner, escaping }
+	}
+
+	// 内层存储中的路径
+	fn inner_path(&self, path: &str) -> String {
+		self.escaping.unescape_path(path)
+	}
+
+	fn escape_info(&self, mut info: RemoteFileInfo) -> RemoteFileInfo {
+		info.name = self.escaping.escape(&info.name).into_owned();
+		info
+	}
+}
+
+impl<B: StorageBackend + ?Sized> StorageBackend for EscapedBackend<B> {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		Ok(self.escape_info(self.inner.stat(&self.inner_path(path))?))
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let page = self.inner.list_page(&self.inner_path(path), cursor)?;
+		Ok(ListPage {
+			items: page
+				.items
+				.into_iter()
+				.map(|info| self.escape_info(info))
+				.collect(),
+			next_cursor: page.next_cursor,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.inner.read(&self.inner_path(path), offset, length)
+	}
+
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		self.inner.read_into(&self.inner_path(path), offset, buffer)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.inner.write(&self.inner_path(path), offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.inner.commit(&self.inner_path(path), data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.inner.create(&self.inner_path(path), is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.inner.delete(&self.inner_path(path), dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.inner.rename(
+			&self.inner_path(old_path),
+			&self.inner_path(new_path),
+			replace,
+		)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.inner.truncate(&self.inner_path(path), size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.inner.set_times(&self.inner_path(path), times)
+	}
+
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		let paths: Vec<String> = files
+			.iter()
+			.map(|file| self.inner_path(file.path))
+			.collect();
+		let files: Vec<BatchFile> = files
+			.iter()
+			.zip(&paths)
+			.map(|(file, path)| BatchFile { path, ..*file })
+			.collect();
+		self.inner.commit_batch(&files)
+	}
+
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		self.inner
+			.zero_range(&self.inner_path(path), offset, length)
+	}
+
+	fn read_only(&self) -> bool {
+		self.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.inner.space()
+	}
+
+	// 通配符中的 * 和 ? 不会被转义，模式的其余部分与名称同样还原
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		let mut response = self.inner.search(
+			&self.inner_path(path),
+			&self.escaping.unescape(pattern),
+			recursive,
+		)?;
+		for hit in &mut response.hits {
+			hit.path = self.escaping.escape_path(&hit.path);
+			hit.info.name = self.escaping.escape(&hit.info.name).into_owned();
+		}
+		Ok(response)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		self.inner.list_xattrs(&self.inner_path(path))
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		self.inner.get_xattr(&self.inner_path(path), name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.inner.put_xattr(&self.inner_path(path), name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.inner.delete_xattr(&self.inner_path(path), name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		self.inner.list_versions(&self.inner_path(path))
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		self.inner
+			.read_version(&self.inner_path(path), id, offset, length)
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		let mut entries = self.inner.list_trash()?;
+		for entry in &mut entries {
+			entry.path = self.escaping.escape_path(&entry.path);
+		}
+		Ok(entries)
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		let inner = path.map(|path| self.inner_path(path));
+		let mut response = self.inner.restore_trash(id, inner.as_deref())?;
+		response.path = self.escaping.escape_path(&response.path);
+		Ok(response)
+	}
+
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		let inner = AuditFilter {
+			path: filter.path.as_deref().map(|path| self.inner_path(path)),
+			user: filter.user.clone(),
+			..*filter
+		};
+		let mut response = self.inner.audit(&inner)?;
+		for entry in &mut response.entries {
+			entry.path = self.escaping.escape_path(&entry.path);
+			entry.new_path = entry
+				.new_path
+				.as_deref()
+				.map(|path| self.escaping.escape_path(path));
+		}
+		Ok(response)
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		self.inner.checksum(&self.inner_path(path))
+	}
+}
@@ -0,0 +1,232 @@
+use std::{
+	collections::VecDeque,
+	error::Error,
+	path::Path,
+	sync::{Arc, Mutex},
+};
+
+use git2::{ObjectType, Oid, Repository, TreeEntry};
+
+use super::{base_name, directory_info, file_info, url_path, write_protected, StorageBackend};
+use crate::{backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, TimesUpdate};
+
+// 最近读取的几个文件的内容保留在内存中，分段读取同一个文件时不必每次重新解压；过大的文件不缓存
+const CACHED_BLOBS: usize = 4;
+const MAX_CACHED_BLOB: usize = 64 * 1024 * 1024;
+
+// 路径在提交中对应的对象
+enum Node {
+	Tree(Oid),
+	Blob(Oid, u64),
+	// 子模块只记录了另一个仓库中的提交，显示为空目录
+	Submodule,
+}
+
+fn not_found(path: &str) -> RemoteError {
+	RemoteError::backend("not_found", format!("{} is not in the commit", path))
+}
+
+fn repository_error(path: &str, error: git2::Error) -> RemoteError {
+	RemoteError::backend("repository_error", format!("{}: {}", path, error.message()))
+}
+
+// 只读挂载 git 仓库中的一个提交：git:///D:/repos/project.git，--git-ref 选择分支、标签或提交（默认 HEAD）。
+// 挂载时只解析出提交的根目录，目录在访问时才从树对象中查找，文件内容在读取时才加载。
+// git 不记录文件的时间，所有条目都使用提交时间；符号链接显示为内容是目标路径的文件
+pub struct GitBackend {
+	// git2 的仓库对象不能在线程间共享
+	repo: Mutex<Repository>,
+	tree: Oid,
+	time: u64,
+	blobs: Mutex<VecDeque<(Oid, Arc<Vec<u8>>)>>,
+}
+
+impl GitBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to git:// URLs".into());
+		}
+		let path = url_path(&remote.server_url);
+		let reference = remote.git_ref.as_deref().unwrap_or("HEAD");
+		Ok(Self::open(&path, reference).map_err(|e| {
+			format!(
+				"cannot open {} at {}: {}",
+				path.display(),
+				reference,
+				e.message()
+			)
+		})?)
+	}
+
+	// 普通仓库和裸仓库都可以；reference 可以是 git rev-parse 接受的任何形式，如 main、v1.0、HEAD~3 或提交 ID
+	pub fn open(path: &Path, reference: &str) -> Result<Self, git2::Error> {
+		let repo = Repository::open(path)?;
+		let (tree, time) = {
+			let commit = repo.revparse_single(reference)?.peel_to_commit()?;
+			(commit.tree_id(), commit.time().seconds().max(0) as u64)
+		};
+		Ok(Self {
+			repo: Mutex::new(repo),
+			tree,
+			time,
+			blobs: Mutex::new(VecDeque::new()),
+		})
+	}
+
+	fn node(repo: &Repository, path: &str, entry: &TreeEntry) -> Result<Node, RemoteError> {
+		match entry.kind() {
+			Some(ObjectType::Tree) => Ok(Node::Tree(entry.id())),
+			Some(ObjectType::Blob) => {
+				// 只读对象头得到大小，不解压内容
+				let (size, _) = repo
+					.odb()
+					.and_then(|odb| odb.read_header(entry.id()))
+					.map_err(|e| repository_error(path, e))?;
+				Ok(Node::Blob(entry.id(), size as u64))
+			}
+			_ => Ok(Node::Submodule),
+		}
+	}
+
+	// 从根目录逐级查找，名称区分大小写
+	fn lookup(&self, repo: &Repository, path: &str) -> Result<Node, RemoteError> {
+		let mut node = Node::Tree(self.tree);
+		if path == "." {
+			return Ok(node);
+		}
+		for name in path.split('/') {
+			let Node::Tree(id) = node else {
+				return Err(not_found(path));
+			};
+			let tree = repo.find_tree(id).map_err(|e| repository_error(path, e))?;
+			let entry = tree.get_name(name).ok_or_else(|| not_found(path))?;
+			node = Self::node(repo, path, &entry)?;
+		}
+		Ok(node)
+	}
+
+	fn info(&self, name: &str, node: &Node) -> RemoteFileInfo {
+		match node {
+			Node::Blob(_, size) => file_info(name, *size, self.time),
+			Node::Tree(_) | Node::Submodule => directory_info(name, self.time),
+		}
+	}
+
+	fn blob(&self, repo: &Repository, path: &str, id: Oid) -> Result<Arc<Vec<u8>>, RemoteError> {
+		if let Some((_, content)) = self
+			.blobs
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|(cached, _)| *cached == id)
+		{
+			return Ok(content.clone());
+		}
+		let content = Arc::new(
+			repo.find_blob(id)
+				.map_err(|e| repository_error(path, e))?
+				.content()
+				.to_vec(),
+		);
+		if content.len() <= MAX_CACHED_BLOB {
+			let mut blobs = self.blobs.lock().unwrap();
+			if blobs.len() >= CACHED_BLOBS {
+				blobs.pop_front();
+			}
+			blobs.push_back((id, content.clone()));
+		}
+		Ok(content)
+	}
+}
+
+impl StorageBackend for GitBackend {
+	fn read_only(&self) -> bool {
+		true
+	}
+
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let repo = self.repo.lock().unwrap();
+		let node = self.lookup(&repo, path)?;
+		Ok(self.info(base_name(path), &node))
+	}
+
+	// 名称不是 UTF-8 的条目无法通过路径访问，不列出
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let repo = self.repo.lock().unwrap();
+		let id = match self.lookup(&repo, path)? {
+			Node::Tree(id) => id,
+			Node::Submodule => {
+				return Ok(ListPage {
+					items: Vec::new(),
+					next_cursor: None,
+				})
+			}
+			Node::Blob(..) => {
+				return Err(RemoteError::backend(
+					"not_a_directory",
+					format!("{} is not a directory", path),
+				))
+			}
+		};
+		let tree = repo.find_tree(id).map_err(|e| repository_error(path, e))?;
+		let mut items = Vec::with_capacity(tree.len());
+		for entry in tree.iter() {
+			let Some(name) = entry.name() else {
+				continue;
+			};
+			let node = Self::node(&repo, path, &entry)?;
+			items.push(self.info(name, &node));
+		}
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let repo = self.repo.lock().unwrap();
+		let Node::Blob(id, size) = self.lookup(&repo, path)? else {
+			return Err(RemoteError::backend(
+				"is_a_directory",
+				format!("{} is a directory", path),
+			));
+		};
+		if offset >= size || length == 0 {
+			return Ok(Vec::new());
+		}
+		let content = self.blob(&repo, path, id)?;
+		let end = (content.len() as u64).min(offset.saturating_add(length as u64));
+		Ok(content
+			.get(offset as usize..end as usize)
+			.unwrap_or_default()
+			.to_vec())
+	}
+
+	fn write(&self, path: &str, _offset: u64, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn commit(&self, path: &str, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn create(&self, path: &str, _is_directory: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn delete(&self, path: &str, _dry_run: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn rename(&self, old_path: &str, _new_path: &str, _replace: bool) -> Result<(), RemoteError> {
+		Err(write_protected(old_path))
+	}
+
+	fn truncate(&self, path: &str, _size: u64) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn set_times(&self, path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+}
@@ -0,0 +1,363 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
+use reqwest::StatusCode;
+use tokio::runtime::{Builder, Runtime};
+use tonic::{
+	metadata::MetadataValue,
+	transport::{Channel, ClientTlsConfig, Endpoint},
+	Code, Request, Status,
+};
+use tracing::debug;
+
+use super::{http::HttpBackend, BatchFile, StorageBackend};
+use crate::{
+	auth::OAuth,
+	backend::Remote,
+	error::{ApiError, RemoteError},
+	identity, AuditFilter, AuditResponse, ChecksumResponse, ListPage, RemoteFileInfo,
+	RestoreResponse, SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo,
+	XattrEntry,
+};
+
+mod proto {
+	tonic::include_proto!("httpfs.v1");
+}
+
+use proto::{storage_client::StorageClient, ListRequest, ReadRequest, StatRequest, WriteRequest};
+
+// 元数据的键必须是小写
+const SHARE_METADATA: &str = "x-httpfs-share";
+const USER_METADATA: &str = "x-httpfs-user";
+// 不超过该大小的提交以一个原子的 Write 调用完成，更大的文件经 HTTP API 分块上传
+const ATOMIC_WRITE_LIMIT: usize = 8 * 1024 * 1024;
+// 单个消息的上限，要容纳最大的一次读取和原子写入
+const MAX_MESSAGE_SIZE: usize = 64 * 1024 * 1024;
+// 列目录时每页请求的条目数
+const LIST_PAGE_SIZE: u32 = 1000;
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+// 根目录在协议中为空路径
+fn proto_path(path: &str) -> String {
+	if path == "." {
+		String::new()
+	} else {
+		path.to_string()
+	}
+}
+
+fn file_info(info: proto::FileInfo) -> RemoteFileInfo {
+	RemoteFileInfo {
+		name: info.name,
+		is_directory: info.is_directory,
+		size: info.size,
+		created: info.created,
+		modified: info.modified,
+		accessed: info.accessed,
+		stored_size: None,
+		allocated_size: info.allocated_size,
+	}
+}
+
+// 与服务器的映射相反，只用于显示
+fn http_status(code: Code) -> StatusCode {
+	match code {
+		Code::InvalidArgument => StatusCode::BAD_REQUEST,
+		Code::Unauthenticated => StatusCode::UNAUTHORIZED,
+		Code::PermissionDenied => StatusCode::FORBIDDEN,
+		Code::NotFound => StatusCode::NOT_FOUND,
+		Code::AlreadyExists => StatusCode::CONFLICT,
+		Code::FailedPrecondition => StatusCode::PRECONDITION_FAILED,
+		Code::ResourceExhausted => StatusCode::TOO_MANY_REQUESTS,
+		Code::Unavailable => StatusCode::SERVICE_UNAVAILABLE,
+		Code::Unimplemented => StatusCode::NOT_IMPLEMENTED,
+		_ => StatusCode::INTERNAL_SERVER_ERROR,
+	}
+}
+
+// 服务器把 HTTP API 的 JSON 错误体放在 details 中，错误类别与 HTTP API 相同；
+// 没有 details 的是连接或协议层面的失败
+fn remote_error(status: Status) -> RemoteError {
+	match serde_json::from_slice::<ApiError>(status.details()) {
+		Ok(error) => RemoteError::Api {
+			status: http_status(status.code()),
+			error,
+			request_id: None,
+		},
+		Err(_) => match status.code() {
+			Code::Unavailable | Code::DeadlineExceeded | Code::Cancelled | Code::Unknown => {
+				RemoteError::backend("connection_lost", status.message().to_string())
+			}
+			Code::Unimplemented => RemoteError::backend(
+				"not_supported",
+				format!("the server does not offer gRPC: {}", status.message()),
+			),
+			_ => RemoteError::backend(
+				"invalid_response",
+				format!("{:?}: {}", status.code(), status.message()),
+			),
+		},
+	}
+}
+
+/// httpfs 服务器的 gRPC 服务（`grpc://` 为明文 HTTP/2，`grpcs://` 为 TLS）：列目录、属性和读写这些最频繁的调用
+/// 以二进制消息在一个 HTTP/2 连接上复用；协议中没有的操作和大文件的分块上传经同一端口上的 HTTP API 完成。
+pub struct GrpcBackend {
+	// 后端的方法在 Dokan 的线程中同步调用，在这个运行时上等待异步的调用完成
+	runtime: Runtime,
+	client: StorageClient<Channel>,
+	share: Option<String>,
+	token: Option<String>,
+	oauth: Option<Arc<OAuth>>,
+	// 列目录时请求服务器去掉无权打开的条目
+	accessible_only: bool,
+	// 跟随签名 URL 时读写都经过 HTTP API
+	direct_reads: bool,
+	delta_sync: bool,
+	rest: HttpBackend,
+}
+
+impl GrpcBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn std::error::Error>> {
+		let runtime = Builder::new_multi_thread()
+			.worker_threads(2)
+			.thread_name("httpfs-grpc")
+			.enable_all()
+			.build()?;
+		let address = remote.rest_url();
+		let mut endpoint = Endpoint::from_shared(address.clone())?
+			.connect_timeout(Duration::from_secs(10))
+			.timeout(Duration::from_secs(30))
+			.tcp_keepalive(Some(KEEP_ALIVE_INTERVAL))
+			.http2_keep_alive_interval(KEEP_ALIVE_INTERVAL)
+			.http2_adaptive_window(true);
+		if address.starts_with("https://") {
+			endpoint = endpoint.tls_config(ClientTlsConfig::new().with_native_roots())?;
+		}
+		// 第一次调用时才连接，服务器暂时不可用不影响挂载
+		let channel = {
+			let _guard = runtime.enter();
+			endpoint.connect_lazy()
+		};
+		Ok(Self {
+			runtime,
+			client: StorageClient::new(channel)
+				.max_decoding_message_size(MAX_MESSAGE_SIZE)
+				.max_encoding_message_size(MAX_MESSAGE_SIZE),
+			share: remote.share.clone(),
+			token: remote.token.clone(),
+			oauth: remote.oauth.as_ref().map(OAuth::shared),
+			accessible_only: remote.access_based_enumeration,
+			direct_reads: remote.direct_reads,
+			delta_sync: remote.delta_sync,
+			rest: HttpBackend::new(remote)?,
+		})
+	}
+
+	// 调用带上共享、访问令牌和当前线程代表的用户，见 identity::with_user
+	fn request<T>(&self, message: T) -> Result<Request<T>, RemoteError> {
+		let mut request = Request::new(message);
+		let metadata = request.metadata_mut();
+		let invalid = |what: &str| {
+			RemoteError::backend(
+				"invalid_input",
+				format!("the {} cannot be sent as gRPC metadata", what),
+			)
+		};
+		if let Some(share) = &self.share {
+			metadata.insert(
+				SHARE_METADATA,
+				share.parse().map_err(|_| invalid("share name"))?,
+			);
+		}
+		let token = match &self.oauth {
+			Some(oauth) => Some(oauth.access_token()?),
+			None => self.token.clone(),
+		};
+		if let Some(token) = token {
+			let mut value: MetadataValue<_> = format!("Bearer {}", token)
+				.parse()
+				.map_err(|_| invalid("token"))?;
+			value.set_sensitive(true);
+			metadata.insert("authorization", value);
+		}
+		if let Some(user) = identity::current_user() {
+			metadata.insert(
+				USER_METADATA,
+				user.parse().map_err(|_| invalid("user name"))?,
+			);
+		}
+		Ok(request)
+	}
+
+	fn call<R>(
+		&self,
+		future: impl Future<Output = Result<tonic::Response<R>, Status>>,
+	) -> Result<R, RemoteError> {
+		self.runtime
+			.block_on(future)
+			.map(tonic::Response::into_inner)
+			.map_err(remote_error)
+	}
+}
+
+impl StorageBackend for GrpcBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let request = self.request(StatRequest {
+			path: proto_path(path),
+		})?;
+		Ok(file_info(self.call(self.client.clone().stat(request))?))
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let request = self.request(ListRequest {
+			path: proto_path(path),
+			cursor: cursor.map(str::to_string),
+			limit: Some(LIST_PAGE_SIZE),
+			accessible_only: self.accessible_only,
+		})?;
+		let page = self.call(self.client.clone().list(request))?;
+		Ok(ListPage {
+			items: page.items.into_iter().map(file_info).collect(),
+			next_cursor: page.next_cursor,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		if self.direct_reads {
+			return self.rest.read(path, offset, length);
+		}
+		let request = self.request(ReadRequest {
+			path: proto_path(path),
+			offset,
+			length: Some(length as u64),
+			version: None,
+		})?;
+		Ok(self.call(self.client.clone().read(request))?.data)
+	}
+
+	// 跟随签名 URL 读取时，修改要经过 HttpBackend 才会丢弃缓存的 URL
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		if self.direct_reads {
+			return self.rest.write(path, offset, data);
+		}
+		let request = self.request(WriteRequest {
+			path: proto_path(path),
+			data: data.to_vec(),
+			offset,
+			..Default::default()
+		})?;
+		self.call(self.client.clone().write(request))?;
+		Ok(())
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		if data.len() > ATOMIC_WRITE_LIMIT || self.delta_sync || self.direct_reads {
+			return self.rest.commit(path, data);
+		}
+		let request = self.request(WriteRequest {
+			path: proto_path(path),
+			data: data.to_vec(),
+			atomic: true,
+			..Default::default()
+		})?;
+		self.call(self.client.clone().write(request))?;
+		debug!(path = %path, size = data.len(), "commit over gRPC");
+		Ok(())
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.rest.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.rest.delete(path, dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.rest.rename(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.rest.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.rest.set_times(path, times)
+	}
+
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		self.rest.commit_batch(files)
+	}
+
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		self.rest.zero_range(path, offset, length)
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.rest.space()
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		self.rest.search(path, pattern, recursive)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		self.rest.list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		self.rest.get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.rest.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.rest.delete_xattr(path, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		self.rest.list_versions(path)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let request = self.request(ReadRequest {
+			path: proto_path(path),
+			offset,
+			length: Some(length as u64),
+			version: Some(id.to_string()),
+		})?;
+		Ok(self.call(self.client.clone().read(request))?.data)
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		self.rest.list_trash()
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		self.rest.restore_trash(id, path)
+	}
+
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		self.rest.audit(filter)
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		self.rest.checksum(path)
+	}
+}
@@ -0,0 +1,888 @@
+use std::{
+	error::Error,
+	sync::{Arc, Mutex, OnceLock},
+	time::{Duration, Instant},
+};
+
+use reqwest::{
+	blocking::{multipart, Client, RequestBuilder},
+	redirect::Policy,
+	IntoUrl, Method, StatusCode,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::{debug, error, info, warn};
+
+use super::{
+	commit_each, dedup::ChunkStore, direct::DirectReads, encode_api_path, read_checksum, read_full,
+	write_zeros, BatchFile, StorageBackend,
+};
+use crate::{
+	auth::OAuth,
+	auth_headers,
+	backend::Remote,
+	buffers::PooledBuffer,
+	capabilities::Capabilities,
+	compression::Compression,
+	delta::{self, Signature},
+	error::{ApiError, CheckStatus, RemoteError},
+	http3::{Http3, SendVia},
+	identity::{self, USER_HEADER},
+	AuditFilter, AuditResponse, ChecksumResponse, ListPage, RemoteFileInfo, RestoreResponse,
+	SearchResponse, SpaceResponse, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+// 超过该大小的提交改用可续传的分块上传
+const CHUNKED_UPLOAD_THRESHOLD: usize = 8 * 1024 * 1024;
+const UPLOAD_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+const UPLOAD_RETRIES: usize = 3;
+
+// 启用增量上传时，不小于该大小的提交先比较服务器上的块签名
+const DELTA_MIN_SIZE: usize = 1024 * 1024;
+// 增量上传的块大小按文件大小选择，使签名不超过约 4096 块
+const DELTA_MAX_BLOCKS: usize = 4096;
+const DELTA_MIN_BLOCK_SIZE: usize = 4 * 1024;
+const DELTA_MAX_BLOCK_SIZE: usize = 1024 * 1024;
+// 每次 /upload/:session/copy 请求的区间数
+const COPY_BATCH: usize = 1000;
+
+// 列目录时每页请求的条目数
+const LIST_PAGE_SIZE: usize = 1000;
+
+// 每次 /chunks/exists 查询的哈希数，与服务器的上限一致
+const CHUNK_EXISTS_BATCH: usize = 1000;
+
+// 查询 /capabilities 失败后再次查询的间隔，期间按服务器支持所有功能处理
+const CAPABILITIES_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Deserialize)]
+struct UploadStartResponse {
+	session: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct UploadStatusResponse {
+	received: Vec<(u64, u64)>,
+}
+
+// POST /batch 中一个文件的结果，失败时带有单独提交时的状态码和错误体
+#[derive(Debug, Deserialize)]
+struct BatchResult {
+	status: Option<u16>,
+	error: Option<ApiError>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChunksExistResponse {
+	missing: Vec<String>,
+}
+
+// 根目录使用特殊标识符
+fn api_path(path: &str) -> &str {
+	if path == "." {
+		"$ROOT"
+	} else {
+		path
+	}
+}
+
+// httpfs 服务器的 HTTP API
+pub struct HttpBackend {
+	base_url: String,
+	client: Client,
+	compression: Compression,
+	// 修改过的大文件只上传与服务器上的内容不同的部分
+	delta_sync: bool,
+	// 列目录和搜索时请求服务器去掉无权打开的条目
+	accessible_only: bool,
+	// 通过身份提供方登录时每个请求带上当前的访问令牌
+	oauth: Option<Arc<OAuth>>,
+	// 服务器允许时大文件从对象存储的签名 URL 直接读取
+	direct_reads: Option<DirectReads>,
+	// 实验性的 HTTP/3，QUIC 不通时回退到 client
+	http3: Option<Http3>,
+	// 服务器支持的功能，第一次用到时查询
+	capabilities: OnceLock<Capabilities>,
+	// 上一次查询失败的时间
+	capabilities_failed: Mutex<Option<Instant>>,
+}
+
+impl HttpBackend {
+	pub fn new(remote: &Remote) -> Result<Self, RemoteError> {
+		let compression = remote.compression;
+		let headers = auth_headers(remote.token.as_deref())?;
+		// 关闭压缩时也不再通过 Accept-Encoding 请求压缩的响应
+		let builder = || {
+			Client::builder()
+				.timeout(Duration::from_secs(30))
+				.default_headers(headers.clone())
+				.gzip(compression != Compression::None)
+				.zstd(compression != Compression::None)
+				// 签名 URL 由 direct_reads 自己跟随并缓存，不能带着令牌发往对象存储
+				.redirect(if remote.direct_reads {
+					Policy::none()
+				} else {
+					Policy::default()
+				})
+		};
+		// HTTP/3 不可用时照常挂载，只使用 TCP
+		let http3 = remote
+			.http3
+			.then(|| Http3::new(&remote.rest_url(), builder()))
+			.and_then(|http3| {
+				http3
+					.map_err(|e| warn!(error = %e, "HTTP/3 is not available, using TCP"))
+					.ok()
+			});
+		Ok(Self {
+			base_url: remote.base_url(),
+			client: remote.http2.configure(builder()).build().unwrap(),
+			http3,
+			compression,
+			delta_sync: remote.delta_sync,
+			accessible_only: remote.access_based_enumeration,
+			oauth: remote.oauth.as_ref().map(OAuth::shared),
+			direct_reads: remote.direct_reads.then(DirectReads::new),
+			capabilities: OnceLock::new(),
+			capabilities_failed: Mutex::new(None),
+		})
+	}
+
+	// 挂载时立即查询服务器支持的功能，服务器要求更新的客户端时挂载失败；
+	// 服务器暂时无法访问时照常挂载，之后再查询
+	pub fn open(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		let backend = Self::new(remote)?;
+		if let Some(capabilities) = backend.capabilities() {
+			capabilities.check()?;
+			info!(
+				protocol_version = capabilities.protocol_version,
+				features = ?capabilities.features,
+				"server capabilities"
+			);
+		}
+		Ok(backend)
+	}
+
+	// 服务器的 /capabilities，没有该路由的旧版服务器只支持基本的操作；查询失败时返回 None
+	pub fn capabilities(&self) -> Option<Capabilities> {
+		self.discovered().cloned()
+	}
+
+	fn discovered(&self) -> Option<&Capabilities> {
+		if let Some(capabilities) = self.capabilities.get() {
+			return Some(capabilities);
+		}
+		let mut failed = self.capabilities_failed.lock().unwrap();
+		if failed.is_some_and(|at| at.elapsed() < CAPABILITIES_RETRY) {
+			return None;
+		}
+		let result = self
+			.request(Method::GET, format!("{}/capabilities", self.base_url))
+			.send_via(&self.http3)
+			.and_then(CheckStatus::check_status);
+		let capabilities = match result {
+			Ok(response) => response.json::<Capabilities>().map_err(RemoteError::from),
+			Err(e) if e.code() == Some("not_found") => {
+				warn!("the server does not report its capabilities, using only basic operations");
+				Ok(Capabilities::legacy())
+			}
+			Err(e) => Err(e),
+		};
+		match capabilities {
+			Ok(capabilities) => {
+				*failed = None;
+				Some(self.capabilities.get_or_init(|| capabilities))
+			}
+			Err(e) => {
+				warn!(error = %e, "querying the server capabilities failed");
+				*failed = Some(Instant::now());
+				None
+			}
+		}
+	}
+
+	// 服务器是否支持该功能；还不知道时按支持处理，失败的请求照常报告错误
+	pub fn supports(&self, feature: &str) -> bool {
+		self.discovered()
+			.map_or(true, |capabilities| capabilities.supports(feature))
+	}
+
+	// 修改过的路径不再使用缓存的签名 URL
+	fn forget_signed(&self, path: &str) {
+		if let Some(direct) = &self.direct_reads {
+			direct.forget(path);
+		}
+	}
+
+	// 请求服务器重定向到签名 URL 并从那里读取；服务器直接返回内容时返回 None。
+	// 对象存储读取失败时丢弃该 URL，由调用者改为从服务器读取
+	fn read_direct(
+		&self,
+		direct: &DirectReads,
+		url: &str,
+		path: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Option<Vec<u8>>, RemoteError> {
+		if let Some(signed) = direct.cached(path) {
+			match direct.read(&signed, offset, length) {
+				Ok(data) => return Ok(Some(data)),
+				Err(e) => {
+					debug!(path = %path, error = %e, "the cached signed URL failed, asking the server again");
+					direct.forget(path);
+				}
+			}
+		}
+		let response = self
+			.request(Method::GET, url)
+			.query(&[
+				("offset", offset.to_string()),
+				("length", length.to_string()),
+				("redirect", "true".to_string()),
+			])
+			.send_via(&self.http3)?;
+		if response.status() != StatusCode::TEMPORARY_REDIRECT {
+			return Ok(Some(response.check_status()?.bytes()?.to_vec()));
+		}
+		let Some(signed) = direct.remember(path, &response) else {
+			return Ok(None);
+		};
+		match direct.read(&signed, offset, length) {
+			Ok(data) => Ok(Some(data)),
+			Err(e) => {
+				warn!(path = %path, error = %e, "reading from the object store failed, reading through the server");
+				direct.forget(path);
+				Ok(None)
+			}
+		}
+	}
+
+	// 请求带上访问令牌和当前线程代表的用户，见 identity::with_user
+	fn request(&self, method: Method, url: impl IntoUrl) -> RequestBuilder {
+		let mut request = self.client.request(method, url);
+		if let Some(oauth) = &self.oauth {
+			request = oauth.authorize(request);
+		}
+		match identity::current_user() {
+			Some(user) => request.header(USER_HEADER, user),
+			None => request,
+		}
+	}
+
+	fn commit_atomic(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let url = format!("{}/write/{}", self.base_url, encode_api_path(path));
+		let request = self
+			.request(Method::POST, &url)
+			.query(&[("atomic", "true")]);
+		self.compression
+			.body(request, path, data)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	// rsync 式的增量上传：按服务器上现有文件的块签名找出新内容中没有变化的块，由服务器从原文件复制，
+	// 只上传其余部分，最后同样校验整体 sha256 后原子替换。文件不存在或节省不到一成时返回 false，由调用者上传完整内容
+	fn upload_delta(&self, path: &str, data: &[u8]) -> Result<bool, RemoteError> {
+		let block_size = (data.len() / DELTA_MAX_BLOCKS)
+			.next_power_of_two()
+			.clamp(DELTA_MIN_BLOCK_SIZE, DELTA_MAX_BLOCK_SIZE);
+		let signature = match self
+			.request(
+				Method::GET,
+				format!("{}/signature/{}", self.base_url, encode_api_path(path)),
+			)
+			.query(&[("block_size", block_size.to_string())])
+			.send_via(&self.http3)?
+			.check_status()
+		{
+			Ok(response) => response.json::<Signature>()?,
+			Err(e) if e.code() == Some("not_found") => return Ok(false),
+			Err(e) => return Err(e),
+		};
+		let delta = delta::diff(&signature, data);
+		let uploaded = delta.literal_len();
+		if uploaded * 10 > data.len() as u64 * 9 {
+			return Ok(false);
+		}
+
+		let session = self
+			.request(Method::POST, format!("{}/upload/start", self.base_url))
+			.json(&serde_json::json!({ "path": path, "size": data.len() as u64 }))
+			.send_via(&self.http3)?
+			.check_status()?
+			.json::<UploadStartResponse>()?
+			.session;
+		let session_url = format!("{}/upload/{}", self.base_url, session);
+		let result = (|| -> Result<(), RemoteError> {
+			for batch in delta.copies.chunks(COPY_BATCH) {
+				let ranges: Vec<_> = batch
+					.iter()
+					.map(|range| serde_json::json!({ "source": range.source, "offset": range.offset, "length": range.length }))
+					.collect();
+				self.request(Method::POST, format!("{}/copy", session_url))
+					.json(&serde_json::json!({ "ranges": ranges }))
+					.send_via(&self.http3)?
+					.check_status()?;
+			}
+			for &(start, end) in &delta.literals {
+				for (index, chunk) in data[start as usize..end as usize]
+					.chunks(UPLOAD_CHUNK_SIZE)
+					.enumerate()
+				{
+					let offset = start + (index * UPLOAD_CHUNK_SIZE) as u64;
+					let request = self
+						.request(Method::PUT, format!("{}/chunk", session_url))
+						.query(&[
+							("offset", offset.to_string()),
+							("sha256", hex::encode(Sha256::digest(chunk))),
+						]);
+					self.compression
+						.body(request, path, chunk)
+						.send_via(&self.http3)?
+						.check_status()?;
+				}
+			}
+			// 复制期间服务器上的文件被修改时整体校验失败，会话随之删除
+			self.request(Method::POST, format!("{}/commit", session_url))
+				.json(&serde_json::json!({ "sha256": hex::encode(Sha256::digest(data)) }))
+				.send_via(&self.http3)?
+				.check_status()?;
+			Ok(())
+		})();
+		if let Err(e) = result {
+			let _ = self.request(Method::DELETE, &session_url).send();
+			return Err(e);
+		}
+		debug!(path = %path, size = data.len(), uploaded, "upload_delta");
+		Ok(true)
+	}
+
+	// 通过分块上传会话提交完整内容：每个分块附带 sha256，提交时校验整体 sha256，
+	// 失败的分块在下一轮根据服务器返回的已接收区间补传
+	fn upload_chunked(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let session = self
+			.request(Method::POST, format!("{}/upload/start", self.base_url))
+			.json(&serde_json::json!({ "path": path, "size": data.len() as u64 }))
+			.send_via(&self.http3)?
+			.check_status()?
+			.json::<UploadStartResponse>()?
+			.session;
+		let session_url = format!("{}/upload/{}", self.base_url, session);
+		let file_sha256 = hex::encode(Sha256::digest(data));
+
+		let mut received = Vec::new();
+		let mut attempt = 0;
+		loop {
+			for (index, chunk) in data.chunks(UPLOAD_CHUNK_SIZE).enumerate() {
+				let start = (index * UPLOAD_CHUNK_SIZE) as u64;
+				let end = start + chunk.len() as u64;
+				if received.iter().any(|&(s, e)| s <= start && end <= e) {
+					continue;
+				}
+				let request = self
+					.request(Method::PUT, format!("{}/chunk", session_url))
+					.query(&[
+						("offset", start.to_string()),
+						("sha256", hex::encode(Sha256::digest(chunk))),
+					]);
+				let result = self
+					.compression
+					.body(request, path, chunk)
+					.send_via(&self.http3)
+					.and_then(CheckStatus::check_status);
+				if let Err(e) = result {
+					error!(path = %path, offset = start, error = %e, "upload_chunked: chunk failed");
+				}
+			}
+
+			let response = self
+				.request(Method::POST, format!("{}/commit", session_url))
+				.json(&serde_json::json!({ "sha256": file_sha256 }))
+				.send_via(&self.http3)?;
+			// 409 表示仍有缺失的分块，查询已接收区间后补传
+			if response.status() != reqwest::StatusCode::CONFLICT || attempt == UPLOAD_RETRIES {
+				if response.status() == reqwest::StatusCode::CONFLICT {
+					let _ = self.request(Method::DELETE, &session_url).send();
+				}
+				response.check_status()?;
+				return Ok(());
+			}
+			attempt += 1;
+			received = self
+				.request(Method::GET, &session_url)
+				.send_via(&self.http3)?
+				.check_status()?
+				.json::<UploadStatusResponse>()?
+				.received;
+		}
+	}
+}
+
+impl StorageBackend for HttpBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let url = format!("{}/info/{}", self.base_url, encode_api_path(path));
+		let response = self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<RemoteFileInfo>()?)
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let url = format!("{}/list/{}", self.base_url, encode_api_path(path));
+		let mut request = self
+			.request(Method::GET, &url)
+			.query(&[("limit", LIST_PAGE_SIZE.to_string())]);
+		if let Some(cursor) = cursor {
+			request = request.query(&[("cursor", cursor)]);
+		}
+		if self.accessible_only {
+			request = request.query(&[("accessible", "true")]);
+		}
+		let response = request.send_via(&self.http3)?.check_status()?;
+		Ok(response.json::<ListPage>()?)
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let url = format!("{}/read/{}", self.base_url, encode_api_path(path));
+		if let Some(direct) = self
+			.direct_reads
+			.as_ref()
+			.filter(|_| self.supports("direct_reads"))
+		{
+			if let Some(data) = self.read_direct(direct, &url, path, offset, length)? {
+				return Ok(data);
+			}
+		}
+		// 不支持按区间读取的服务器返回整个文件
+		if !self.supports("ranges") {
+			let data = self
+				.request(Method::GET, &url)
+				.send_via(&self.http3)?
+				.check_status()?
+				.bytes()?;
+			let start = usize::try_from(offset)
+				.unwrap_or(usize::MAX)
+				.min(data.len());
+			return Ok(data[start..start.saturating_add(length).min(data.len())].to_vec());
+		}
+		let response = self
+			.request(Method::GET, &url)
+			.query(&[
+				("offset", offset.to_string()),
+				("length", length.to_string()),
+			])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.bytes()?.to_vec())
+	}
+
+	// 响应体直接读进调用者的缓冲区；转向对象存储的读取和不支持按区间读取的服务器仍然经过 read
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		if !self.supports("ranges") || self.direct_reads.is_some() && self.supports("direct_reads")
+		{
+			let data = self.read(path, offset, buffer.len())?;
+			let len = data.len().min(buffer.len());
+			buffer[..len].copy_from_slice(&data[..len]);
+			return Ok(len);
+		}
+		let url = format!("{}/read/{}", self.base_url, encode_api_path(path));
+		let mut response = self
+			.request(Method::GET, &url)
+			.query(&[
+				("offset", offset.to_string()),
+				("length", buffer.len().to_string()),
+			])
+			.send_via(&self.http3)?
+			.check_status()?;
+		read_full(&mut response, buffer).map_err(|e| {
+			RemoteError::backend("connection_lost", format!("reading {} failed: {}", path, e))
+		})
+	}
+
+	// 超过服务器单次写入上限的内容分成多个请求
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		let url = format!("{}/write/{}", self.base_url, encode_api_path(path));
+		let max_write_size = self
+			.discovered()
+			.and_then(|capabilities| capabilities.max_write_size)
+			.unwrap_or(usize::MAX);
+		self.forget_signed(path);
+		let mut done = 0;
+		for chunk in data.chunks(max_write_size.max(1)) {
+			let request = self
+				.request(Method::POST, &url)
+				.query(&[("offset", (offset + done).to_string())]);
+			self.compression
+				.body(request, path, chunk)
+				.send_via(&self.http3)?
+				.check_status()?;
+			done += chunk.len() as u64;
+		}
+		Ok(())
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.forget_signed(path);
+		if self.delta_sync && data.len() >= DELTA_MIN_SIZE && self.supports("delta") {
+			match self.upload_delta(path, data) {
+				Ok(true) => return Ok(()),
+				Ok(false) => {}
+				Err(e) => {
+					warn!(path = %path, error = %e, "upload_delta failed, uploading the whole file")
+				}
+			}
+		}
+		if data.len() > CHUNKED_UPLOAD_THRESHOLD && self.supports("chunked_upload") {
+			self.upload_chunked(path, data)
+		} else {
+			self.commit_atomic(path, data)
+		}
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let url = format!("{}/create/{}", self.base_url, encode_api_path(path));
+		self.request(Method::PUT, &url)
+			.query(&[("is_directory", is_directory.to_string())])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	// 服务器对非空目录返回 409
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let url = format!("{}/delete/{}", self.base_url, encode_api_path(path));
+		if !dry_run {
+			self.forget_signed(path);
+		}
+		self.request(Method::DELETE, &url)
+			.query(&[("dry_run", dry_run.to_string())])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let url = format!("{}/move/{}", self.base_url, encode_api_path(old_path));
+		self.forget_signed(old_path);
+		self.forget_signed(new_path);
+		self.request(Method::POST, &url)
+			.query(&[("replace", replace.to_string())])
+			.json(&serde_json::json!({ "new_path": api_path(new_path) }))
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let url = format!("{}/truncate/{}", self.base_url, encode_api_path(path));
+		self.forget_signed(path);
+		self.request(Method::POST, &url)
+			.json(&serde_json::json!({ "size": size }))
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	// 服务器在支持的文件系统上打洞
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		if !self.supports("sparse") {
+			return write_zeros(self, path, offset, length);
+		}
+		let url = format!("{}/zero/{}", self.base_url, encode_api_path(path));
+		self.forget_signed(path);
+		self.request(Method::POST, &url)
+			.json(&serde_json::json!({ "offset": offset, "length": length }))
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	// 不能设置时间戳的服务器保留它自己的时间
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		if !self.supports("times") {
+			return Ok(());
+		}
+		let url = format!("{}/times/{}", self.base_url, encode_api_path(path));
+		self.request(Method::POST, &url)
+			.json(times)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	// 一个 multipart 请求：第一部分为清单，之后按清单顺序每个文件一部分
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		if !self.supports("batch") {
+			return Ok(commit_each(self, files));
+		}
+		let manifest: Vec<_> = files
+			.iter()
+			.map(|file| {
+				let mut entry = serde_json::to_value(file.times).unwrap_or_default();
+				entry["path"] = api_path(file.path).into();
+				entry
+			})
+			.collect();
+		let mut form = multipart::Form::new().text(
+			"manifest",
+			serde_json::json!({ "files": manifest }).to_string(),
+		);
+		for file in files {
+			self.forget_signed(file.path);
+			form = form.part("file", multipart::Part::bytes(file.data.to_vec()));
+		}
+		let response = self
+			.request(Method::POST, format!("{}/batch", self.base_url))
+			.multipart(form)
+			.send_via(&self.http3)?
+			.check_status()?;
+		let request_id = response
+			.headers()
+			.get("x-request-id")
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string);
+		let results = response.json::<Vec<BatchResult>>()?;
+		if results.len() != files.len() {
+			return Err(RemoteError::backend(
+				"corrupt_data",
+				format!(
+					"batch returned {} results for {} files",
+					results.len(),
+					files.len()
+				),
+			));
+		}
+		debug!(files = files.len(), "commit_batch");
+		Ok(results
+			.into_iter()
+			.map(|result| match result.error {
+				None => Ok(()),
+				Some(error) => Err(RemoteError::Api {
+					status: result
+						.status
+						.and_then(|status| StatusCode::from_u16(status).ok())
+						.unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
+					error,
+					request_id: request_id.clone(),
+				}),
+			})
+			.collect())
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		let url = format!("{}/space", self.base_url);
+		let response = self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<SpaceResponse>()?)
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		if !self.supports("search") {
+			return Err(RemoteError::unsupported("search"));
+		}
+		let mut request = self
+			.request(Method::GET, format!("{}/search", self.base_url))
+			.query(&[
+				("q", pattern),
+				("path", api_path(path)),
+				("recursive", &recursive.to_string()),
+			]);
+		if self.accessible_only {
+			request = request.query(&[("accessible", "true")]);
+		}
+		let response = request.send_via(&self.http3)?.check_status()?;
+		Ok(response.json::<SearchResponse>()?)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		if !self.supports("xattrs") {
+			return Ok(Vec::new());
+		}
+		let url = format!("{}/xattr/{}", self.base_url, encode_api_path(path));
+		Ok(self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()?
+			.json::<Vec<XattrEntry>>()?)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		if !self.supports("xattrs") {
+			return Ok(None);
+		}
+		let url = format!("{}/xattr/{}", self.base_url, encode_api_path(path));
+		match self
+			.request(Method::GET, &url)
+			.query(&[("name", name)])
+			.send_via(&self.http3)?
+			.check_status()
+		{
+			Ok(response) => Ok(Some(response.bytes()?.to_vec())),
+			Err(e) if e.code() == Some("xattr_not_found") => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		if !self.supports("xattrs") {
+			return Err(RemoteError::unsupported("extended attributes"));
+		}
+		let url = format!("{}/xattr/{}", self.base_url, encode_api_path(path));
+		self.request(Method::PUT, &url)
+			.query(&[("name", name)])
+			.body(value.to_vec())
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		if !self.supports("xattrs") {
+			return Err(RemoteError::unsupported("extended attributes"));
+		}
+		let url = format!("{}/xattr/{}", self.base_url, encode_api_path(path));
+		self.request(Method::DELETE, &url)
+			.query(&[("name", name)])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		if !self.supports("versions") {
+			return Ok(Vec::new());
+		}
+		let url = format!("{}/versions/{}", self.base_url, encode_api_path(path));
+		let response = self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<Vec<VersionInfo>>()?)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		if !self.supports("versions") {
+			return Err(RemoteError::unsupported("file versions"));
+		}
+		let url = format!("{}/read/{}", self.base_url, encode_api_path(path));
+		let response = self
+			.request(Method::GET, &url)
+			.query(&[
+				("version", id.to_string()),
+				("offset", offset.to_string()),
+				("length", length.to_string()),
+			])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.bytes()?.to_vec())
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		if !self.supports("trash") {
+			return Err(RemoteError::unsupported("trash"));
+		}
+		let url = format!("{}/trash/list", self.base_url);
+		let response = self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<Vec<TrashEntry>>()?)
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		if !self.supports("trash") {
+			return Err(RemoteError::unsupported("trash"));
+		}
+		let url = format!("{}/trash/restore", self.base_url);
+		let response = self
+			.request(Method::POST, &url)
+			.json(&serde_json::json!({ "id": id, "path": path }))
+			.send_via(&self.http3)?
+			.check_status()?;
+		let restored = response.json::<RestoreResponse>()?;
+		self.forget_signed(&restored.path);
+		Ok(restored)
+	}
+
+	fn audit(&self, filter: &AuditFilter) -> Result<AuditResponse, RemoteError> {
+		if !self.supports("audit") {
+			return Err(RemoteError::unsupported("audit log"));
+		}
+		let url = format!("{}/audit", self.base_url);
+		let response = self
+			.request(Method::GET, &url)
+			.query(filter)
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<AuditResponse>()?)
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		if !self.supports("checksum") {
+			return read_checksum(self, path);
+		}
+		let url = format!("{}/checksum/{}", self.base_url, encode_api_path(path));
+		let response = self
+			.request(Method::GET, &url)
+			.query(&[("algo", "sha256")])
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(response.json::<ChecksumResponse>()?)
+	}
+}
+
+// 服务器的去重块存储
+impl ChunkStore for HttpBackend {
+	fn put_chunk(&self, hash: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let url = format!("{}/chunks/{}", self.base_url, hash);
+		self.request(Method::PUT, &url)
+			.body(PooledBuffer::copy_from(data).into_bytes())
+			.send_via(&self.http3)?
+			.check_status()?;
+		Ok(())
+	}
+
+	fn get_chunk(&self, hash: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		let url = format!("{}/chunks/{}", self.base_url, hash);
+		match self
+			.request(Method::GET, &url)
+			.send_via(&self.http3)?
+			.check_status()
+		{
+			Ok(response) => Ok(Some(response.bytes()?.to_vec())),
+			Err(e) if e.code() == Some("chunk_not_found") => Ok(None),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn missing_chunks(&self, hashes: &[String]) -> Result<Vec<String>, RemoteError> {
+		let mut missing = Vec::new();
+		for batch in hashes.chunks(CHUNK_EXISTS_BATCH) {
+			let response = self
+				.request(Method::POST, format!("{}/chunks/exists", self.base_url))
+				.json(&serde_json::json!({ "hashes": batch }))
+				.send_via(&self.http3)?
+				.check_status()?;
+			missing.extend(response.json::<ChunksExistResponse>()?.missing);
+		}
+		Ok(missing)
+	}
+}
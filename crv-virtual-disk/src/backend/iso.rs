@@ -0,0 +1,1047 @@
+use std::{
+	collections::{HashMap, HashSet},
+	error::Error,
+	fs::File,
+	io,
+	path::Path,
+	sync::Mutex,
+};
+
+use tracing::warn;
+
+use super::{
+	base_name, days_from_civil, invalid, read_at, u16_at, u32_at, u64_at, url_path,
+	write_protected, StorageBackend,
+};
+use crate::{backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, TimesUpdate};
+
+const SECTOR: u64 = 2048;
+// 卷描述符（以及 UDF 的卷识别序列）从第 16 个扇区开始
+const FIRST_DESCRIPTOR: u64 = 16;
+const MAX_DESCRIPTORS: u64 = 64;
+// SUSP 延续区和 UDF 分配描述符延续的层数上限，防止损坏的映像造成死循环
+const MAX_CONTINUATIONS: usize = 16;
+// 目录内容的大小上限，超过时映像按损坏处理
+const MAX_DIRECTORY_SIZE: u64 = 64 * 1024 * 1024;
+// El Torito 启动映像所在的虚拟目录，与 7-Zip 相同
+const BOOT_DIRECTORY: &str = "[BOOT]";
+
+// UDF 描述符标签
+const TAG_ANCHOR: u16 = 2;
+const TAG_PARTITION: u16 = 5;
+const TAG_LOGICAL_VOLUME: u16 = 6;
+const TAG_TERMINATING: u16 = 8;
+const TAG_FILE_SET: u16 = 256;
+const TAG_FILE_IDENTIFIER: u16 = 257;
+const TAG_ALLOCATION_EXTENT: u16 = 258;
+const TAG_FILE_ENTRY: u16 = 261;
+const TAG_EXTENDED_FILE_ENTRY: u16 = 266;
+
+// 文件内容在映像中的一段；position 为 None 的段没有记录数据，读出为 0
+struct Extent {
+	position: Option<u64>,
+	length: u64,
+}
+
+enum Content {
+	Extents(Vec<Extent>),
+	// UDF 中直接保存在文件项里的小文件
+	Inline(Vec<u8>),
+}
+
+struct Node {
+	is_directory: bool,
+	size: u64,
+	created: u64,
+	modified: u64,
+	accessed: u64,
+	content: Content,
+}
+
+impl Node {
+	fn directory(created: u64, modified: u64, accessed: u64) -> Self {
+		Self {
+			is_directory: true,
+			size: 0,
+			created,
+			modified,
+			accessed,
+			content: Content::Extents(Vec::new()),
+		}
+	}
+
+	fn info(&self, name: &str) -> RemoteFileInfo {
+		RemoteFileInfo {
+			name: name.to_string(),
+			is_directory: self.is_directory,
+			size: if self.is_directory { 0 } else { self.size },
+			created: self.created,
+			modified: self.modified,
+			accessed: self.accessed,
+			stored_size: None,
+			allocated_size: None,
+		}
+	}
+}
+
+// 读取内容中 [offset, end) 的部分，超出记录的段时返回的数据较短
+fn read_content(file: &mut File, content: &Content, offset: u64, end: u64) -> io::Result<Vec<u8>> {
+	let extents = match content {
+		Content::Inline(data) => {
+			let len = data.len();
+			return Ok(data[(offset as usize).min(len)..(end as usize).min(len)].to_vec());
+		}
+		Content::Extents(extents) => extents,
+	};
+	let mut data = Vec::with_capacity(end.saturating_sub(offset) as usize);
+	let mut start = 0;
+	for extent in extents {
+		let stop = start + extent.length;
+		let (from, to) = (offset.max(start), end.min(stop));
+		if from < to {
+			match extent.position {
+				Some(position) => data.extend(read_at(
+					file,
+					position + from - start,
+					(to - from) as usize,
+				)?),
+				None => data.resize(data.len() + (to - from) as usize, 0),
+			}
+		}
+		start = stop;
+		if start >= end {
+			break;
+		}
+	}
+	Ok(data)
+}
+
+// 映像中的目录树，目录路径到直接成员名称的映射中根目录为 "."
+struct Tree {
+	nodes: HashMap<String, Node>,
+	children: HashMap<String, Vec<String>>,
+}
+
+impl Tree {
+	fn new(root: Node) -> Self {
+		Self {
+			nodes: HashMap::from([(".".to_string(), root)]),
+			children: HashMap::from([(".".to_string(), Vec::new())]),
+		}
+	}
+
+	// 同一目录中重名的条目只保留第一个；返回新条目的路径
+	fn insert(&mut self, parent: &str, name: &str, node: Node) -> Option<String> {
+		if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+			return None;
+		}
+		let path = if parent == "." {
+			name.to_string()
+		} else {
+			format!("{}/{}", parent, name)
+		};
+		if self.nodes.contains_key(&path) {
+			return None;
+		}
+		self.children.get_mut(parent)?.push(name.to_string());
+		if node.is_directory {
+			self.children.insert(path.clone(), Vec::new());
+		}
+		self.nodes.insert(path.clone(), node);
+		Some(path)
+	}
+}
+
+#[derive(Default)]
+struct Descriptors {
+	primary: Option<Vec<u8>>,
+	joliet: Option<Vec<u8>>,
+	boot_catalog: Option<u32>,
+	udf: bool,
+}
+
+// 读取 ISO 9660 卷描述符和其后的 UDF 卷识别序列
+fn read_descriptors(file: &mut File, image_len: u64) -> io::Result<Descriptors> {
+	let mut found = Descriptors::default();
+	for sector in FIRST_DESCRIPTOR..FIRST_DESCRIPTOR + MAX_DESCRIPTORS {
+		if (sector + 1) * SECTOR > image_len {
+			break;
+		}
+		let data = read_at(file, sector * SECTOR, SECTOR as usize)?;
+		let identifier: [u8; 5] = data[1..6].try_into().unwrap();
+		match (&identifier, data[0]) {
+			(b"CD001", 0) if &data[7..30] == b"EL TORITO SPECIFICATION" => {
+				found.boot_catalog = Some(u32_at(&data, 0x47))
+			}
+			(b"CD001", 1) if found.primary.is_none() => found.primary = Some(data),
+			// Joliet 的补充卷描述符以 UCS-2 转义序列标识
+			(b"CD001", 2)
+				if matches!(&data[88..91], b"%/@" | b"%/C" | b"%/E") && found.joliet.is_none() =>
+			{
+				found.joliet = Some(data)
+			}
+			(b"NSR02" | b"NSR03", _) => found.udf = true,
+			(b"CD001" | b"BEA01" | b"TEA01" | b"BOOT2", _) => {}
+			_ => break,
+		}
+	}
+	Ok(found)
+}
+
+// 目录记录中的 7 字节时间：1900 年起的年份、月、日、时、分、秒和以 15 分钟为单位的时区
+fn record_time(stamp: &[u8]) -> u64 {
+	let days = days_from_civil(1900 + stamp[0] as i64, stamp[1] as i64, stamp[2] as i64);
+	let secs = days * 86400 + stamp[3] as i64 * 3600 + stamp[4] as i64 * 60 + stamp[5] as i64
+		- stamp[6] as i8 as i64 * 900;
+	u64::try_from(secs).unwrap_or(0)
+}
+
+// Rock Ridge 长格式的 17 字节时间：YYYYMMDDHHMMSScc 十六个数字和时区
+fn long_time(stamp: &[u8]) -> u64 {
+	let field = |range: std::ops::Range<usize>| {
+		std::str::from_utf8(&stamp[range]).ok()?.parse::<i64>().ok()
+	};
+	let parsed = (|| {
+		let days = days_from_civil(field(0..4)?, field(4..6)?, field(6..8)?);
+		Some(
+			days * 86400 + field(8..10)? * 3600 + field(10..12)? * 60 + field(12..14)?
+				- stamp[16] as i8 as i64 * 900,
+		)
+	})();
+	parsed
+		.and_then(|secs| u64::try_from(secs).ok())
+		.unwrap_or(0)
+}
+
+// 从系统使用区中取得的 Rock Ridge 信息
+#[derive(Default)]
+struct SystemUse {
+	name: Option<Vec<u8>>,
+	created: Option<u64>,
+	modified: Option<u64>,
+	accessed: Option<u64>,
+	// CL：深层目录被移到别处，记录指向它的实际位置
+	child: Option<u32>,
+	// RE：被移动的目录在 rr_moved 中的原记录
+	relocated: bool,
+}
+
+struct DirectoryRecord {
+	name: String,
+	node: Node,
+	// 子目录内容的位置和大小
+	directory: Option<(u32, u32)>,
+}
+
+struct Iso9660<'a> {
+	file: &'a mut File,
+	image_len: u64,
+	joliet: bool,
+	// SUSP 项前跳过的字节数；None 表示没有 Rock Ridge 扩展
+	rock_ridge: Option<usize>,
+}
+
+impl Iso9660<'_> {
+	fn read(&mut self, offset: u64, length: u64) -> io::Result<Vec<u8>> {
+		if offset
+			.checked_add(length)
+			.is_none_or(|end| end > self.image_len)
+		{
+			return Err(invalid("a directory lies outside the image"));
+		}
+		read_at(self.file, offset, length as usize)
+	}
+
+	// 目录记录的名称：Joliet 为 UCS-2，否则去掉文件的版本号 ;1 和没有扩展名时结尾的 .
+	fn plain_name(&self, raw: &[u8], is_directory: bool) -> String {
+		let name: String = if self.joliet {
+			char::decode_utf16(
+				raw.chunks_exact(2)
+					.map(|pair| u16::from_be_bytes([pair[0], pair[1]])),
+			)
+			.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+			.collect()
+		} else {
+			String::from_utf8_lossy(raw).into_owned()
+		};
+		if is_directory {
+			return name;
+		}
+		let name = name.split_once(';').map_or(name.as_str(), |(name, _)| name);
+		name.strip_suffix('.').unwrap_or(name).to_string()
+	}
+
+	fn parse_system_use(
+		&mut self,
+		mut area: &[u8],
+		found: &mut SystemUse,
+		depth: usize,
+	) -> io::Result<()> {
+		let mut continuation = None;
+		while area.len() >= 4 {
+			let len = area[2] as usize;
+			if len < 4 || len > area.len() {
+				break;
+			}
+			let (entry, rest) = area.split_at(len);
+			area = rest;
+			match &entry[..2] {
+				// NM 的标志位 1、2 表示当前目录和上级目录
+				b"NM" if len >= 5 && entry[4] & 0b110 == 0 => found
+					.name
+					.get_or_insert_with(Vec::new)
+					.extend_from_slice(&entry[5..]),
+				b"TF" if len >= 5 => {
+					let flags = entry[4];
+					let size = if flags & 0x80 != 0 { 17 } else { 7 };
+					let mut stamps = entry[5..].chunks_exact(size).map(|stamp| {
+						if size == 17 {
+							long_time(stamp)
+						} else {
+							record_time(stamp)
+						}
+					});
+					for (bit, target) in [
+						(0x01, &mut found.created),
+						(0x02, &mut found.modified),
+						(0x04, &mut found.accessed),
+					] {
+						if flags & bit != 0 {
+							*target = stamps.next();
+						}
+					}
+				}
+				b"CL" if len >= 12 => found.child = Some(u32_at(entry, 4)),
+				b"RE" => found.relocated = true,
+				b"CE" if len >= 28 => {
+					continuation = Some((
+						u32_at(entry, 4) as u64,
+						u32_at(entry, 12) as u64,
+						u32_at(entry, 20) as u64,
+					))
+				}
+				b"ST" => break,
+				_ => {}
+			}
+		}
+		if let Some((block, offset, length)) = continuation {
+			if depth < MAX_CONTINUATIONS && length <= SECTOR {
+				let data = self.read(block * SECTOR + offset, length)?;
+				self.parse_system_use(&data, found, depth + 1)?;
+			}
+		}
+		Ok(())
+	}
+
+	fn directory(&mut self, lba: u32, size: u32) -> io::Result<Vec<DirectoryRecord>> {
+		let data = self.read(lba as u64 * SECTOR, size as u64)?;
+		let mut records = Vec::new();
+		// 超过 4 GiB 的文件由连续的同名记录组成，除最后一个外都带有多段标志
+		let mut multi_extent: Option<DirectoryRecord> = None;
+		let mut position = 0;
+		while position < data.len() {
+			let len = data[position] as usize;
+			// 记录不跨扇区，扇区剩余部分以 0 填充
+			if len == 0 {
+				position = (position / SECTOR as usize + 1) * SECTOR as usize;
+				continue;
+			}
+			if len < 34 || position + len > data.len() {
+				break;
+			}
+			let record = &data[position..position + len];
+			position += len;
+			let name_len = record[32] as usize;
+			if 33 + name_len > len || (name_len == 1 && record[33] <= 1) {
+				continue;
+			}
+			let flags = record[25];
+			let mut system_use = SystemUse::default();
+			if let Some(skip) = self.rock_ridge {
+				let start = 33 + name_len + (1 - name_len % 2) + skip;
+				if start < len {
+					self.parse_system_use(&record[start..], &mut system_use, 0)?;
+				}
+			}
+			if system_use.relocated {
+				continue;
+			}
+			let is_directory = flags & 0x02 != 0 || system_use.child.is_some();
+			let name = match system_use.name.take() {
+				Some(name) => String::from_utf8_lossy(&name).into_owned(),
+				None => self.plain_name(&record[33..33 + name_len], is_directory),
+			};
+			let extent = Extent {
+				position: Some(u32_at(record, 2) as u64 * SECTOR),
+				length: u32_at(record, 10) as u64,
+			};
+			match &mut multi_extent {
+				Some(previous) if previous.name == name => {
+					previous.node.size += extent.length;
+					if let Content::Extents(extents) = &mut previous.node.content {
+						extents.push(extent);
+					}
+				}
+				_ => {
+					records.extend(multi_extent.take());
+					let recorded = record_time(&record[18..25]);
+					let modified = system_use.modified.unwrap_or(recorded);
+					let created = system_use.created.unwrap_or(modified);
+					let accessed = system_use.accessed.unwrap_or(modified);
+					let (node, directory) = if is_directory {
+						let location = match system_use.child {
+							// 被移动的目录的大小在其 . 记录中
+							Some(lba) => (lba, u32_at(&self.read(lba as u64 * SECTOR, 34)?, 10)),
+							None => (u32_at(record, 2), u32_at(record, 10)),
+						};
+						(Node::directory(created, modified, accessed), Some(location))
+					} else {
+						let node = Node {
+							is_directory: false,
+							size: extent.length,
+							created,
+							modified,
+							accessed,
+							content: Content::Extents(vec![extent]),
+						};
+						(node, None)
+					};
+					multi_extent = Some(DirectoryRecord {
+						name,
+						node,
+						directory,
+					});
+				}
+			}
+			if flags & 0x80 == 0 {
+				records.extend(multi_extent.take());
+			}
+		}
+		records.extend(multi_extent);
+		Ok(records)
+	}
+}
+
+// 根目录 . 记录的系统使用区以 SP 项开始时映像带有 Rock Ridge 扩展，返回其中的跳过字节数
+fn detect_rock_ridge(file: &mut File, root: &[u8], image_len: u64) -> io::Result<Option<usize>> {
+	let lba = u32_at(root, 2) as u64;
+	if (lba + 1) * SECTOR > image_len {
+		return Ok(None);
+	}
+	let sector = read_at(file, lba * SECTOR, SECTOR as usize)?;
+	let len = sector[0] as usize;
+	if len < 34 {
+		return Ok(None);
+	}
+	let name_len = sector[32] as usize;
+	let area = sector
+		.get(33 + name_len + (1 - name_len % 2)..len)
+		.unwrap_or_default();
+	Ok(
+		(area.len() >= 7 && &area[..2] == b"SP" && area[4..6] == [0xbe, 0xef])
+			.then(|| area[6] as usize),
+	)
+}
+
+// 有 Rock Ridge 时使用主卷描述符中的 POSIX 名称，否则优先使用 Joliet 的长文件名
+fn read_iso9660(file: &mut File, image_len: u64, descriptors: &Descriptors) -> io::Result<Tree> {
+	let primary = descriptors
+		.primary
+		.as_ref()
+		.ok_or_else(|| invalid("no ISO 9660 or UDF file system"))?;
+	let rock_ridge = detect_rock_ridge(file, &primary[156..190], image_len)?;
+	let (descriptor, joliet) = match (&descriptors.joliet, rock_ridge) {
+		(Some(joliet), None) => (joliet, true),
+		_ => (primary, false),
+	};
+	let root = &descriptor[156..190];
+	let time = record_time(&root[18..25]);
+	let mut tree = Tree::new(Node::directory(time, time, time));
+	let mut parser = Iso9660 {
+		file,
+		image_len,
+		joliet,
+		rock_ridge,
+	};
+	let mut pending = vec![(".".to_string(), u32_at(root, 2), u32_at(root, 10))];
+	let mut visited = HashSet::new();
+	while let Some((path, lba, size)) = pending.pop() {
+		if !visited.insert(lba) || size as u64 > MAX_DIRECTORY_SIZE {
+			continue;
+		}
+		for record in parser.directory(lba, size)? {
+			if let Some(child) = tree.insert(&path, &record.name, record.node) {
+				if let Some((lba, size)) = record.directory {
+					pending.push((child, lba, size));
+				}
+			}
+		}
+	}
+	Ok(tree)
+}
+
+// UDF 时间戳：类型和以分钟为单位的时区、年、月、日、时、分、秒
+fn udf_time(stamp: &[u8]) -> u64 {
+	let zone = (u16_at(stamp, 0) & 0x0fff) as i64;
+	let zone = match zone {
+		// -2047 表示未指定时区
+		0x801 => 0,
+		0x800.. => zone - 0x1000,
+		_ => zone,
+	};
+	let days = days_from_civil(
+		u16_at(stamp, 2) as i16 as i64,
+		stamp[4] as i64,
+		stamp[5] as i64,
+	);
+	let secs =
+		days * 86400 + stamp[6] as i64 * 3600 + stamp[7] as i64 * 60 + stamp[8] as i64 - zone * 60;
+	u64::try_from(secs).unwrap_or(0)
+}
+
+// OSTA 压缩 Unicode：首字节 8 表示每个字符一个字节，16 表示 UCS-2
+fn decode_cs0(raw: &[u8]) -> String {
+	match raw.split_first() {
+		Some((8 | 254, rest)) => rest.iter().map(|&byte| byte as char).collect(),
+		Some((16 | 255, rest)) => char::decode_utf16(
+			rest.chunks_exact(2)
+				.map(|pair| u16::from_be_bytes([pair[0], pair[1]])),
+		)
+		.map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+		.collect(),
+		_ => String::new(),
+	}
+}
+
+// 逻辑卷中分区引用号对应的分区
+enum Partition {
+	// 物理分区的起始扇区
+	Physical(u64),
+	// UDF 2.50 的元数据分区，逻辑块按元数据文件的各段映射到映像中
+	Metadata(Vec<Extent>),
+	Unsupported(String),
+}
+
+struct Udf<'a> {
+	file: &'a mut File,
+	image_len: u64,
+	partitions: Vec<Partition>,
+}
+
+impl<'a> Udf<'a> {
+	// 从锚点卷描述符指针找到卷描述符序列，返回文件集描述符所在的分区和逻辑块
+	fn open(file: &'a mut File, image_len: u64) -> io::Result<(Self, (u16, u32))> {
+		let last = image_len / SECTOR;
+		let anchor = [256, last.saturating_sub(257), last.saturating_sub(1)]
+			.into_iter()
+			.filter(|&sector| sector >= 256 && (sector + 1) * SECTOR <= image_len)
+			.map(|sector| read_at(file, sector * SECTOR, SECTOR as usize))
+			.find(|data| {
+				data.as_ref()
+					.map_or(true, |data| u16_at(data, 0) == TAG_ANCHOR)
+			})
+			.ok_or_else(|| invalid("no UDF anchor volume descriptor"))??;
+		let (length, location) = (u32_at(&anchor, 16) as u64, u32_at(&anchor, 20) as u64);
+
+		let mut partition_starts = HashMap::new();
+		let mut logical_volume = None;
+		for sector in location..location + (length / SECTOR).min(MAX_DESCRIPTORS) {
+			if (sector + 1) * SECTOR > image_len {
+				break;
+			}
+			let data = read_at(file, sector * SECTOR, SECTOR as usize)?;
+			match u16_at(&data, 0) {
+				TAG_PARTITION => {
+					partition_starts.insert(u16_at(&data, 22), u32_at(&data, 188) as u64);
+				}
+				TAG_LOGICAL_VOLUME => logical_volume = Some(data),
+				TAG_TERMINATING => break,
+				_ => {}
+			}
+		}
+		let volume = logical_volume.ok_or_else(|| invalid("no UDF logical volume descriptor"))?;
+		if u32_at(&volume, 212) as u64 != SECTOR {
+			return Err(invalid("unsupported UDF logical block size"));
+		}
+		let file_set = (u16_at(&volume, 256), u32_at(&volume, 252));
+
+		// 分区映射：类型 1 为物理分区，类型 2 以标识符区分元数据、虚拟和可备用分区
+		let physical = |number: u16| {
+			partition_starts
+				.get(&number)
+				.copied()
+				.ok_or_else(|| invalid(format!("UDF partition {} is missing", number)))
+		};
+		let mut maps = Vec::new();
+		let (mut position, count) = (440, u32_at(&volume, 268));
+		for _ in 0..count {
+			let Some(&map_len) = volume.get(position + 1) else {
+				break;
+			};
+			let map = volume
+				.get(position..position + map_len as usize)
+				.ok_or_else(|| invalid("corrupt UDF partition map"))?;
+			position += map_len as usize;
+			maps.push(match map[0] {
+				1 if map.len() >= 6 => (Partition::Physical(physical(u16_at(map, 4))?), None),
+				2 if map.len() >= 44 && map[5..].starts_with(b"*UDF Metadata Partition") => (
+					Partition::Physical(physical(u16_at(map, 38))?),
+					Some(u32_at(map, 40)),
+				),
+				2 if map.len() >= 28 => {
+					let identifier = String::from_utf8_lossy(&map[5..28])
+						.trim_end_matches('\0')
+						.to_string();
+					(Partition::Unsupported(identifier), None)
+				}
+				_ => return Err(invalid("corrupt UDF partition map")),
+			});
+		}
+		let metadata_files: Vec<Option<u32>> = maps.iter().map(|(_, metadata)| *metadata).collect();
+		let mut udf = Self {
+			file,
+			image_len,
+			partitions: maps.into_iter().map(|(partition, _)| partition).collect(),
+		};
+		// 元数据文件本身记录在对应的物理分区中
+		for (index, metadata) in metadata_files.into_iter().enumerate() {
+			if let Some(location) = metadata {
+				let node = udf
+					.file_entry(index as u16, location)?
+					.ok_or_else(|| invalid("corrupt UDF metadata file"))?;
+				let Content::Extents(extents) = node.content else {
+					return Err(invalid("corrupt UDF metadata file"));
+				};
+				udf.partitions[index] = Partition::Metadata(extents);
+			}
+		}
+		Ok((udf, file_set))
+	}
+
+	// 分区中从逻辑块 block 开始的 length 字节在映像中的位置
+	fn resolve(&self, partition: u16, block: u32, length: u64) -> io::Result<Vec<Extent>> {
+		match self.partitions.get(partition as usize) {
+			Some(Partition::Physical(start)) => Ok(vec![Extent {
+				position: Some((start + block as u64) * SECTOR),
+				length,
+			}]),
+			Some(Partition::Metadata(extents)) => {
+				let (offset, end) = (block as u64 * SECTOR, block as u64 * SECTOR + length);
+				let mut pieces = Vec::new();
+				let mut start = 0;
+				for extent in extents {
+					let stop = start + extent.length;
+					let (from, to) = (offset.max(start), end.min(stop));
+					if from < to {
+						pieces.push(Extent {
+							position: extent.position.map(|position| position + from - start),
+							length: to - from,
+						});
+					}
+					start = stop;
+				}
+				Ok(pieces)
+			}
+			Some(Partition::Unsupported(kind)) => {
+				Err(invalid(format!("unsupported UDF partition type {}", kind)))
+			}
+			None => Err(invalid(format!("UDF partition {} is missing", partition))),
+		}
+	}
+
+	fn read_block(&mut self, partition: u16, block: u32) -> io::Result<Vec<u8>> {
+		let extents = self.resolve(partition, block, SECTOR)?;
+		match extents.first() {
+			Some(Extent {
+				position: Some(position),
+				..
+			}) if position + SECTOR <= self.image_len => read_at(self.file, *position, SECTOR as usize),
+			_ => Err(invalid("a UDF block lies outside the image")),
+		}
+	}
+
+	// 分配描述符：short_ad 位于文件项所在的分区，long_ad 和 ext_ad 自带分区引用号
+	fn allocation(
+		&mut self,
+		partition: u16,
+		kind: u16,
+		descriptors: &[u8],
+		depth: usize,
+	) -> io::Result<Vec<Extent>> {
+		let size = match kind {
+			0 => 8,
+			1 => 16,
+			2 => 20,
+			_ => return Err(invalid("unknown UDF allocation descriptor type")),
+		};
+		let mut extents = Vec::new();
+		for descriptor in descriptors.chunks_exact(size) {
+			let raw_length = u32_at(descriptor, 0);
+			let length = (raw_length & 0x3fff_ffff) as u64;
+			if length == 0 {
+				break;
+			}
+			let (block, target) = match kind {
+				0 => (u32_at(descriptor, 4), partition),
+				1 => (u32_at(descriptor, 4), u16_at(descriptor, 8)),
+				_ => (u32_at(descriptor, 12), u16_at(descriptor, 16)),
+			};
+			match raw_length >> 30 {
+				0 => extents.extend(self.resolve(target, block, length)?),
+				// 其余的分配描述符在另一个块中
+				3 if depth < MAX_CONTINUATIONS => {
+					let data = self.read_block(target, block)?;
+					if u16_at(&data, 0) != TAG_ALLOCATION_EXTENT {
+						return Err(invalid("corrupt UDF allocation extent"));
+					}
+					let next = data
+						.get(24..24 + u32_at(&data, 20) as usize)
+						.ok_or_else(|| invalid("corrupt UDF allocation extent"))?;
+					extents.extend(self.allocation(partition, kind, next, depth + 1)?);
+				}
+				3 => break,
+				// 已分配未记录或未分配的段
+				_ => extents.push(Extent {
+					position: None,
+					length,
+				}),
+			}
+		}
+		Ok(extents)
+	}
+
+	// 读取文件项或扩展文件项；符号链接、设备等其他类型返回 None
+	fn file_entry(&mut self, partition: u16, block: u32) -> io::Result<Option<Node>> {
+		let data = self.read_block(partition, block)?;
+		let (accessed_at, modified_at, created_at, lengths_at) = match u16_at(&data, 0) {
+			TAG_FILE_ENTRY => (72, 84, None, 168),
+			TAG_EXTENDED_FILE_ENTRY => (80, 92, Some(104), 208),
+			_ => return Err(invalid("corrupt UDF file entry")),
+		};
+		let is_directory = match data[27] {
+			4 => true,
+			0 | 5 => false,
+			_ => return Ok(None),
+		};
+		let kind = u16_at(&data, 34) & 0x7;
+		let size = u64_at(&data, 56);
+		let start = lengths_at + 8 + u32_at(&data, lengths_at) as usize;
+		let descriptors = data
+			.get(start..start + u32_at(&data, lengths_at + 4) as usize)
+			.ok_or_else(|| invalid("corrupt UDF file entry"))?;
+		let content = match kind {
+			3 => Content::Inline(descriptors[..descriptors.len().min(size as usize)].to_vec()),
+			_ => Content::Extents(self.allocation(partition, kind, descriptors, 0)?),
+		};
+		let modified = udf_time(&data[modified_at..]);
+		Ok(Some(Node {
+			is_directory,
+			size,
+			created: created_at.map_or(modified, |at| udf_time(&data[at..])),
+			modified,
+			accessed: udf_time(&data[accessed_at..]),
+			content,
+		}))
+	}
+
+	// 目录内容中的文件标识描述符，跳过已删除的条目和上级目录；返回名称和文件项的位置
+	fn directory(&mut self, node: &Node) -> io::Result<Vec<(String, u16, u32)>> {
+		if node.size > MAX_DIRECTORY_SIZE {
+			return Err(invalid("a UDF directory is too large"));
+		}
+		let data = read_content(self.file, &node.content, 0, node.size)?;
+		let mut entries = Vec::new();
+		let mut position = 0;
+		while position + 38 <= data.len() {
+			let descriptor = &data[position..];
+			if u16_at(descriptor, 0) != TAG_FILE_IDENTIFIER {
+				break;
+			}
+			let name_len = descriptor[19] as usize;
+			let name_start = 38 + u16_at(descriptor, 36) as usize;
+			if name_start + name_len > descriptor.len() {
+				break;
+			}
+			position += (name_start + name_len + 3) & !3;
+			if descriptor[18] & 0x0c == 0 {
+				entries.push((
+					decode_cs0(&descriptor[name_start..name_start + name_len]),
+					u16_at(descriptor, 28),
+					u32_at(descriptor, 24),
+				));
+			}
+		}
+		Ok(entries)
+	}
+}
+
+fn read_udf(file: &mut File, image_len: u64) -> io::Result<Tree> {
+	let (mut udf, (partition, block)) = Udf::open(file, image_len)?;
+	let file_set = udf.read_block(partition, block)?;
+	if u16_at(&file_set, 0) != TAG_FILE_SET {
+		return Err(invalid("no UDF file set descriptor"));
+	}
+	let root_location = (u16_at(&file_set, 408), u32_at(&file_set, 404));
+	let root = udf
+		.file_entry(root_location.0, root_location.1)?
+		.filter(|node| node.is_directory)
+		.ok_or_else(|| invalid("the UDF root is not a directory"))?;
+	let mut tree = Tree::new(Node::directory(root.created, root.modified, root.accessed));
+	let mut pending = vec![(".".to_string(), root)];
+	let mut visited = HashSet::from([root_location]);
+	while let Some((path, directory)) = pending.pop() {
+		for (name, partition, block) in udf.directory(&directory)? {
+			let Some(node) = udf.file_entry(partition, block)? else {
+				continue;
+			};
+			if !node.is_directory {
+				tree.insert(&path, &name, node);
+			} else if visited.insert((partition, block)) {
+				if let Some(child) = tree.insert(
+					&path,
+					&name,
+					Node::directory(node.created, node.modified, node.accessed),
+				) {
+					pending.push((child, node));
+				}
+			}
+		}
+	}
+	Ok(tree)
+}
+
+// 无模拟的启动项只记录加载的虚拟扇区数（EFI 映像常为 1），映像是 FAT 卷时按引导扇区中的总扇区数计算大小
+fn fat_size(sector: &[u8]) -> Option<u64> {
+	if sector[510..512] != [0x55, 0xaa] || !matches!(u16_at(sector, 11), 512 | 1024 | 2048 | 4096) {
+		return None;
+	}
+	let sectors = match u16_at(sector, 19) {
+		0 => u32_at(sector, 32) as u64,
+		count => count as u64,
+	};
+	Some(u16_at(sector, 11) as u64 * sectors)
+}
+
+// El Torito 启动目录中的各个启动映像，名称包含平台和模拟方式
+fn read_boot_images(
+	file: &mut File,
+	image_len: u64,
+	catalog: u32,
+) -> io::Result<Vec<(String, Node)>> {
+	let data = read_at(file, catalog as u64 * SECTOR, SECTOR as usize)?;
+	if data[0] != 1 || data[30..32] != [0x55, 0xaa] {
+		return Err(invalid("corrupt El Torito boot catalog"));
+	}
+	// 验证项之后是默认启动项，然后是各个节头和其中的启动项
+	let mut entries = vec![(data[1], &data[32..64])];
+	let mut platform = data[1];
+	for slot in data[64..].chunks_exact(32) {
+		match slot[0] {
+			0x90 | 0x91 => platform = slot[1],
+			0x88 | 0x00 if u32_at(slot, 8) != 0 => entries.push((platform, slot)),
+			0x44 => {}
+			_ => break,
+		}
+	}
+
+	let mut images = Vec::new();
+	for (platform, entry) in entries {
+		let position = u32_at(entry, 8) as u64 * SECTOR;
+		if position + 512 > image_len {
+			continue;
+		}
+		let first = read_at(file, position, 512)?;
+		let (media, size) = match entry[1] & 0x0f {
+			1 => ("1.2M", 1_228_800),
+			2 => ("1.44M", 1_474_560),
+			3 => ("2.88M", 2_949_120),
+			// 硬盘模拟：映像到第一个分区的末尾为止
+			4 => (
+				"HardDisk",
+				(u32_at(&first, 454) as u64 + u32_at(&first, 458) as u64) * 512,
+			),
+			_ => (
+				"NoEmul",
+				fat_size(&first).unwrap_or(u16_at(entry, 6) as u64 * 512),
+			),
+		};
+		let platform = match platform {
+			0 => "x86".to_string(),
+			1 => "PowerPC".to_string(),
+			2 => "Mac".to_string(),
+			0xef => "EFI".to_string(),
+			other => format!("{:02X}", other),
+		};
+		let size = size.min(image_len - position);
+		let node = Node {
+			is_directory: false,
+			size,
+			created: 0,
+			modified: 0,
+			accessed: 0,
+			content: Content::Extents(vec![Extent {
+				position: Some(position),
+				length: size,
+			}]),
+		};
+		images.push((format!("Boot-{}-{}", platform, media), node));
+	}
+	Ok(images)
+}
+
+// 以只读卷挂载的光盘映像：同时带有 UDF 时使用 UDF（可以表示超过 4 GiB 的文件），
+// 否则读取 ISO 9660 及其 Rock Ridge 或 Joliet 扩展；El Torito 启动映像作为 [BOOT] 目录下的文件。
+// 打开时建立整个目录树，读取时直接读映像中对应的段
+pub struct IsoBackend {
+	file: Mutex<File>,
+	tree: Tree,
+}
+
+impl IsoBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to iso:// URLs".into());
+		}
+		let image = url_path(&remote.server_url);
+		Ok(Self::open(&image).map_err(|e| format!("cannot open {}: {}", image.display(), e))?)
+	}
+
+	pub fn open(image: &Path) -> io::Result<Self> {
+		let mut file = File::open(image)?;
+		let image_len = file.metadata()?.len();
+		let descriptors = read_descriptors(&mut file, image_len)?;
+		let mut tree = if descriptors.udf {
+			match read_udf(&mut file, image_len) {
+				Err(e) if descriptors.primary.is_some() => {
+					warn!(
+						"cannot read the UDF file system of {}, using ISO 9660 instead: {}",
+						image.display(),
+						e
+					);
+					read_iso9660(&mut file, image_len, &descriptors)?
+				}
+				result => result?,
+			}
+		} else {
+			read_iso9660(&mut file, image_len, &descriptors)?
+		};
+
+		if let Some(catalog) = descriptors.boot_catalog {
+			match read_boot_images(&mut file, image_len, catalog) {
+				Ok(images) if !images.is_empty() => {
+					let root = &tree.nodes["."];
+					let directory = Node::directory(root.created, root.modified, root.accessed);
+					if tree.insert(".", BOOT_DIRECTORY, directory).is_some() {
+						for (name, node) in images {
+							let mut unique = format!("{}.img", name);
+							for index in 2.. {
+								if !tree
+									.nodes
+									.contains_key(&format!("{}/{}", BOOT_DIRECTORY, unique))
+								{
+									break;
+								}
+								unique = format!("{}-{}.img", name, index);
+							}
+							tree.insert(BOOT_DIRECTORY, &unique, node);
+						}
+					}
+				}
+				Ok(_) => {}
+				Err(e) => warn!("ignoring the boot catalog of {}: {}", image.display(), e),
+			}
+		}
+		for names in tree.children.values_mut() {
+			names.sort();
+		}
+		Ok(Self {
+			file: Mutex::new(file),
+			tree,
+		})
+	}
+
+	fn node(&self, path: &str) -> Result<&Node, RemoteError> {
+		self.tree.nodes.get(path).ok_or_else(|| {
+			RemoteError::backend("not_found", format!("{} is not in the image", path))
+		})
+	}
+}
+
+impl StorageBackend for IsoBackend {
+	fn read_only(&self) -> bool {
+		true
+	}
+
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		Ok(self.node(path)?.info(base_name(path)))
+	}
+
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		if !self.node(path)?.is_directory {
+			return Err(RemoteError::backend(
+				"not_a_directory",
+				format!("{} is not a directory", path),
+			));
+		}
+		let items = self.tree.children[path]
+			.iter()
+			.map(|name| {
+				let child = if path == "." {
+					name.clone()
+				} else {
+					format!("{}/{}", path, name)
+				};
+				self.tree.nodes[&child].info(name)
+			})
+			.collect();
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let node = self.node(path)?;
+		if node.is_directory {
+			return Err(RemoteError::backend(
+				"is_a_directory",
+				format!("{} is a directory", path),
+			));
+		}
+		let end = node.size.min(offset.saturating_add(length as u64));
+		if offset >= end {
+			return Ok(Vec::new());
+		}
+		let mut file = self.file.lock().unwrap();
+		read_content(&mut file, &node.content, offset, end)
+			.map_err(|e| RemoteError::backend("image_error", format!("{}: {}", path, e)))
+	}
+
+	fn write(&self, path: &str, _offset: u64, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn commit(&self, path: &str, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn create(&self, path: &str, _is_directory: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn delete(&self, path: &str, _dry_run: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn rename(&self, old_path: &str, _new_path: &str, _replace: bool) -> Result<(), RemoteError> {
+		Err(write_protected(old_path))
+	}
+
+	fn truncate(&self, path: &str, _size: u64) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn set_times(&self, path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+}
@@ -0,0 +1,311 @@
+use std::{
+	error::Error,
+	fs::{self, FileTimes, Metadata, OpenOptions},
+	io::{self, ErrorKind, Read, Seek, SeekFrom, Write},
+	path::{Component, Path, PathBuf},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{base_name, read_full, url_path, StorageBackend};
+use crate::{
+	backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate,
+};
+
+fn to_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+fn from_secs(secs: u64) -> SystemTime {
+	UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+fn io_error(e: io::Error, path: &str) -> RemoteError {
+	let code = match e.kind() {
+		ErrorKind::NotFound => "not_found",
+		ErrorKind::AlreadyExists => "already_exists",
+		ErrorKind::PermissionDenied => "permission_denied",
+		ErrorKind::DirectoryNotEmpty => "directory_not_empty",
+		ErrorKind::NotADirectory => "not_a_directory",
+		ErrorKind::IsADirectory => "is_a_directory",
+		ErrorKind::StorageFull => "disk_full",
+		ErrorKind::InvalidFilename => "invalid_name",
+		_ => "io_error",
+	};
+	RemoteError::backend(code, format!("{}: {}", path, e))
+}
+
+fn metadata_info(name: &str, metadata: &Metadata) -> RemoteFileInfo {
+	let modified = metadata.modified().map(to_secs).unwrap_or(0);
+	RemoteFileInfo {
+		name: name.to_string(),
+		is_directory: metadata.is_dir(),
+		size: if metadata.is_dir() { 0 } else { metadata.len() },
+		created: metadata.created().map(to_secs).unwrap_or(modified),
+		modified,
+		accessed: metadata.accessed().map(to_secs).unwrap_or(modified),
+		stored_size: None,
+		allocated_size: None,
+	}
+}
+
+// 只为修改时间戳打开文件或目录
+fn open_for_times(path: &Path) -> io::Result<fs::File> {
+	let mut options = OpenOptions::new();
+	#[cfg(windows)]
+	{
+		use std::os::windows::fs::OpenOptionsExt;
+
+		// FILE_WRITE_ATTRIBUTES；FILE_FLAG_BACKUP_SEMANTICS 用于打开目录
+		options.access_mode(0x100).custom_flags(0x0200_0000);
+	}
+	#[cfg(not(windows))]
+	options.read(true);
+	options.open(path)
+}
+
+fn is_empty_dir(path: &Path) -> io::Result<bool> {
+	Ok(fs::read_dir(path)?.next().is_none())
+}
+
+// 本地目录的直通映射，与 memfs/mirror 示例相同，行为最接近真实文件系统：
+// 作为 StorageBackend 的参考实现用于一致性测试，也可以作为叠加挂载的下层。
+// 提交先写入同目录下的临时文件再重命名，符号链接按其目标处理
+pub struct LocalBackend {
+	root: PathBuf,
+}
+
+impl LocalBackend {
+	// file:///C:/data 或 file:///home/user/data
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to file:// URLs".into());
+		}
+		let location = url_path(&remote.server_url);
+		Ok(Self::open(&location)
+			.map_err(|e| format!("cannot use {} as the root: {}", location.display(), e))?)
+	}
+
+	pub fn open(root: &Path) -> io::Result<Self> {
+		let root = fs::canonicalize(root)?;
+		if !root.is_dir() {
+			return Err(io::Error::new(ErrorKind::NotADirectory, "not a directory"));
+		}
+		Ok(Self { root })
+	}
+
+	// 只接受普通的路径分量，不能通过 .. 或绝对路径访问根目录之外
+	fn local_path(&self, path: &str) -> Result<PathBuf, RemoteError> {
+		if path == "." {
+			return Ok(self.root.clone());
+		}
+		let relative = Path::new(path);
+		if !relative
+			.components()
+			.all(|component| matches!(component, Component::Normal(_)))
+		{
+			return Err(RemoteError::backend(
+				"invalid_name",
+				format!("{} is not a valid path", path),
+			));
+		}
+		Ok(self.root.join(relative))
+	}
+
+	fn parent_exists(&self, path: &str) -> bool {
+		let parent = path.rsplit_once('/').map_or(".", |(parent, _)| parent);
+		self.local_path(parent).is_ok_and(|parent| parent.is_dir())
+	}
+}
+
+impl StorageBackend for LocalBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let metadata = fs::metadata(self.local_path(path)?).map_err(|e| io_error(e, path))?;
+		Ok(metadata_info(base_name(path), &metadata))
+	}
+
+	// 一次返回整个目录，按名称排序；指向不存在目标的符号链接被跳过
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let entries = fs::read_dir(self.local_path(path)?).map_err(|e| io_error(e, path))?;
+		let mut items = Vec::new();
+		for entry in entries {
+			let entry = entry.map_err(|e| io_error(e, path))?;
+			if let Ok(metadata) = fs::metadata(entry.path()) {
+				items.push(metadata_info(
+					&entry.file_name().to_string_lossy(),
+					&metadata,
+				));
+			}
+		}
+		items.sort_by(|a, b| a.name.cmp(&b.name));
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let mut file = fs::File::open(self.local_path(path)?).map_err(|e| io_error(e, path))?;
+		file.seek(SeekFrom::Start(offset))
+			.map_err(|e| io_error(e, path))?;
+		let mut data = Vec::with_capacity(length);
+		file.take(length as u64)
+			.read_to_end(&mut data)
+			.map_err(|e| io_error(e, path))?;
+		Ok(data)
+	}
+
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		let mut file = fs::File::open(self.local_path(path)?).map_err(|e| io_error(e, path))?;
+		file.seek(SeekFrom::Start(offset))
+			.map_err(|e| io_error(e, path))?;
+		read_full(&mut file, buffer).map_err(|e| io_error(e, path))
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		let mut file = OpenOptions::new()
+			.write(true)
+			.open(self.local_path(path)?)
+			.map_err(|e| io_error(e, path))?;
+		file.seek(SeekFrom::Start(offset))
+			.map_err(|e| io_error(e, path))?;
+		file.write_all(data).map_err(|e| io_error(e, path))
+	}
+
+	// 重命名在两个平台上都会替换已有的文件
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let target = self.local_path(path)?;
+		let temp = target.with_file_name(format!(".{}.httpfs-tmp", base_name(path)));
+		let result = fs::write(&temp, data).and_then(|()| fs::rename(&temp, &target));
+		if result.is_err() {
+			let _ = fs::remove_file(&temp);
+		}
+		result.map_err(|e| io_error(e, path))
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let local = self.local_path(path)?;
+		let result = if is_directory {
+			fs::create_dir(&local)
+		} else {
+			OpenOptions::new()
+				.write(true)
+				.create_new(true)
+				.open(&local)
+				.map(drop)
+		};
+		match result {
+			Err(e) if e.kind() == ErrorKind::NotFound && !self.parent_exists(path) => {
+				Err(RemoteError::backend(
+					"parent_not_found",
+					format!("the parent of {} does not exist", path),
+				))
+			}
+			result => result.map_err(|e| io_error(e, path)),
+		}
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let local = self.local_path(path)?;
+		let is_directory = fs::symlink_metadata(&local)
+			.map_err(|e| io_error(e, path))?
+			.is_dir();
+		if is_directory && !is_empty_dir(&local).map_err(|e| io_error(e, path))? {
+			return Err(RemoteError::backend(
+				"directory_not_empty",
+				format!("{} is not empty", path),
+			));
+		}
+		if dry_run {
+			return Ok(());
+		}
+		let result = if is_directory {
+			fs::remove_dir(&local)
+		} else {
+			fs::remove_file(&local)
+		};
+		result.map_err(|e| io_error(e, path))
+	}
+
+	// Windows 上的重命名不能替换目录，replace 时先删除空的目标目录
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let source = self.local_path(old_path)?;
+		let target = self.local_path(new_path)?;
+		let source_is_directory = fs::symlink_metadata(&source)
+			.map_err(|e| io_error(e, old_path))?
+			.is_dir();
+		if source_is_directory && new_path.starts_with(&format!("{}/", old_path)) {
+			return Err(RemoteError::backend(
+				"move_into_self",
+				format!("cannot move {} into itself", old_path),
+			));
+		}
+		if let Ok(metadata) = fs::symlink_metadata(&target) {
+			if !replace {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", new_path),
+				));
+			}
+			if metadata.is_dir() != source_is_directory {
+				return Err(RemoteError::backend(
+					"type_mismatch",
+					format!("{} is of a different type", new_path),
+				));
+			}
+			if metadata.is_dir() {
+				if !is_empty_dir(&target).map_err(|e| io_error(e, new_path))? {
+					return Err(RemoteError::backend(
+						"directory_not_empty",
+						format!("{} is not empty", new_path),
+					));
+				}
+				fs::remove_dir(&target).map_err(|e| io_error(e, new_path))?;
+			}
+		} else if !self.parent_exists(new_path) {
+			return Err(RemoteError::backend(
+				"parent_not_found",
+				format!("the parent of {} does not exist", new_path),
+			));
+		}
+		fs::rename(&source, &target).map_err(|e| io_error(e, old_path))
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let file = OpenOptions::new()
+			.write(true)
+			.open(self.local_path(path)?)
+			.map_err(|e| io_error(e, path))?;
+		file.set_len(size).map_err(|e| io_error(e, path))
+	}
+
+	// 创建时间只能在 Windows 上设置
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		let mut file_times = FileTimes::new();
+		if let Some(accessed) = times.accessed {
+			file_times = file_times.set_accessed(from_secs(accessed));
+		}
+		if let Some(modified) = times.modified {
+			file_times = file_times.set_modified(from_secs(modified));
+		}
+		#[cfg(windows)]
+		{
+			use std::os::windows::fs::FileTimesExt;
+
+			if let Some(created) = times.created {
+				file_times = file_times.set_created(from_secs(created));
+			}
+		}
+		let file = open_for_times(&self.local_path(path)?).map_err(|e| io_error(e, path))?;
+		file.set_times(file_times).map_err(|e| io_error(e, path))
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		let stats = fs4::statvfs(&self.root).map_err(|e| io_error(e, "."))?;
+		Ok(SpaceResponse {
+			total: stats.total_space(),
+			available: stats.available_space(),
+		})
+	}
+}
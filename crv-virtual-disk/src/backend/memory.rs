@@ -0,0 +1,819 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	env,
+	error::Error,
+	fs::{File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	process,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Mutex,
+	},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::{info, warn};
+
+use super::{base_name, StorageBackend};
+use crate::{
+	backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate,
+	XattrEntry,
+};
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+fn not_found(path: &str) -> RemoteError {
+	RemoteError::backend("not_found", format!("{} does not exist", path))
+}
+
+fn parent(path: &str) -> &str {
+	path.rsplit_once('/').map_or(".", |(parent, _)| parent)
+}
+
+// 直接成员的键以 prefix 开头且其后不再含 /
+fn child_prefix(path: &str) -> String {
+	if path == "." {
+		String::new()
+	} else {
+		format!("{}/", path)
+	}
+}
+
+// 文件内容按块保存，没有写入过或被置 0 的块不占用内存，读取时为 0
+const BLOCK_SIZE: u64 = 64 * 1024;
+
+// [offset, offset + length) 按块拆分：块序号、块内偏移、在这段中的位置和长度
+fn pieces(offset: u64, length: u64) -> impl Iterator<Item = (u64, usize, u64, usize)> {
+	let mut done = 0;
+	std::iter::from_fn(move || {
+		if done >= length {
+			return None;
+		}
+		let position = offset + done;
+		let within = position % BLOCK_SIZE;
+		let piece_length = (BLOCK_SIZE - within).min(length - done);
+		let piece = (
+			position / BLOCK_SIZE,
+			within as usize,
+			done,
+			piece_length as usize,
+		);
+		done += piece_length;
+		Some(piece)
+	})
+}
+
+// 长度为 size 的文件中，indices 这些块占用的字节数：末尾不足一块的部分不计入
+fn stored_len(size: u64, indices: impl Iterator<Item = u64>) -> u64 {
+	indices
+		.map(|index| BLOCK_SIZE.min(size.saturating_sub(index * BLOCK_SIZE)))
+		.sum()
+}
+
+fn is_zero(data: &[u8]) -> bool {
+	data.iter().all(|&b| b == 0)
+}
+
+fn io_error(e: io::Error) -> RemoteError {
+	RemoteError::backend(
+		"io_error",
+		format!("the spill file of the memory volume: {}", e),
+	)
+}
+
+// 内容移出内存的块所在的临时文件，关闭时删除；释放的位置留给之后移出的块
+struct Spill {
+	file: File,
+	free: Vec<u64>,
+	end: u64,
+}
+
+impl Spill {
+	fn create() -> io::Result<Self> {
+		static COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let path = env::temp_dir().join(format!(
+			"httpfs-mem-{}-{}.tmp",
+			process::id(),
+			COUNTER.fetch_add(1, Ordering::SeqCst)
+		));
+		let mut options = OpenOptions::new();
+		options.read(true).write(true).create_new(true);
+		#[cfg(windows)]
+		{
+			use std::os::windows::fs::OpenOptionsExt;
+
+			// FILE_ATTRIBUTE_TEMPORARY 尽量留在系统缓存中，FILE_FLAG_DELETE_ON_CLOSE 关闭时删除
+			options.attributes(0x100).custom_flags(0x0400_0000);
+		}
+		let file = options.open(&path)?;
+		#[cfg(not(windows))]
+		std::fs::remove_file(&path)?;
+		info!(path = %path.display(), "mem: moving blocks that are not in use to a temporary file");
+		Ok(Self {
+			file,
+			free: Vec::new(),
+			end: 0,
+		})
+	}
+
+	fn write(&mut self, data: &[u8]) -> io::Result<u64> {
+		let offset = self.free.pop().unwrap_or(self.end);
+		self.file.seek(SeekFrom::Start(offset))?;
+		self.file.write_all(data)?;
+		self.end = self.end.max(offset + BLOCK_SIZE);
+		Ok(offset)
+	}
+
+	fn read(&mut self, offset: u64) -> io::Result<Vec<u8>> {
+		let mut data = vec![0; BLOCK_SIZE as usize];
+		self.file.seek(SeekFrom::Start(offset))?;
+		self.file.read_exact(&mut data)?;
+		Ok(data)
+	}
+}
+
+// 块在内存中（带最近一次使用的序号）或在临时文件中的位置
+enum Place {
+	Memory { data: Vec<u8>, used: u64 },
+	Spilled(u64),
+}
+
+// 所有文件的块。设置了内存上限时，内存中的块超出上限后最久未使用的块被移到临时文件，
+// 再次读写时移回内存
+struct Blocks {
+	places: HashMap<u64, Place>,
+	next_id: u64,
+	// 内存中的块，键为最近一次使用的序号
+	recent: BTreeMap<u64, u64>,
+	clock: u64,
+	limit: Option<u64>,
+	spill: Option<Spill>,
+}
+
+impl Blocks {
+	fn tick(&mut self) -> u64 {
+		self.clock += 1;
+		self.clock
+	}
+
+	// 不足一块的内容补 0
+	fn insert(&mut self, mut data: Vec<u8>) -> u64 {
+		data.resize(BLOCK_SIZE as usize, 0);
+		let (id, used) = (self.next_id, self.tick());
+		self.next_id += 1;
+		self.recent.insert(used, id);
+		self.places.insert(id, Place::Memory { data, used });
+		id
+	}
+
+	// 块的内容，在临时文件中时先读回内存
+	fn data(&mut self, id: u64) -> io::Result<&mut Vec<u8>> {
+		let now = self.tick();
+		let place = self.places.get_mut(&id).unwrap();
+		match place {
+			Place::Memory { used, .. } => {
+				self.recent.remove(used);
+				*used = now;
+			}
+			Place::Spilled(offset) => {
+				let spill = self.spill.as_mut().unwrap();
+				let data = spill.read(*offset)?;
+				spill.free.push(*offset);
+				*place = Place::Memory { data, used: now };
+			}
+		}
+		self.recent.insert(now, id);
+		match place {
+			Place::Memory { data, .. } => Ok(data),
+			Place::Spilled(_) => unreachable!(),
+		}
+	}
+
+	fn remove(&mut self, id: u64) {
+		match self.places.remove(&id) {
+			Some(Place::Memory { used, .. }) => {
+				self.recent.remove(&used);
+			}
+			Some(Place::Spilled(offset)) => self.spill.as_mut().unwrap().free.push(offset),
+			None => {}
+		}
+	}
+
+	fn spill_over(&mut self) -> io::Result<()> {
+		let Some(limit) = self.limit else {
+			return Ok(());
+		};
+		while self.recent.len() as u64 * BLOCK_SIZE > limit {
+			let (&used, &id) = self.recent.first_key_value().unwrap();
+			if self.spill.is_none() {
+				self.spill = Some(Spill::create()?);
+			}
+			let spill = self.spill.as_mut().unwrap();
+			let Some(Place::Memory { data, .. }) = self.places.get(&id) else {
+				unreachable!()
+			};
+			let offset = spill.write(data)?;
+			self.recent.remove(&used);
+			self.places.insert(id, Place::Spilled(offset));
+		}
+		Ok(())
+	}
+
+	// 每次修改后执行；移出失败时内存暂时超出上限，下一次修改时再试
+	fn enforce_limit(&mut self) {
+		if let Err(e) = self.spill_over() {
+			warn!(error = %e, "mem: cannot move blocks out of memory");
+		}
+	}
+}
+
+struct Node {
+	is_directory: bool,
+	size: u64,
+	// 块序号到块的映射，没有的块全为 0
+	blocks: BTreeMap<u64, u64>,
+	created: u64,
+	modified: u64,
+	accessed: u64,
+	xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Node {
+	fn new(is_directory: bool) -> Self {
+		let now = now();
+		Self {
+			is_directory,
+			size: 0,
+			blocks: BTreeMap::new(),
+			created: now,
+			modified: now,
+			accessed: now,
+			xattrs: BTreeMap::new(),
+		}
+	}
+
+	// 有空洞的文件给出实际占用的字节数
+	fn info(&self, name: &str) -> RemoteFileInfo {
+		let stored = self.stored();
+		RemoteFileInfo {
+			name: name.to_string(),
+			is_directory: self.is_directory,
+			size: self.size,
+			created: self.created,
+			modified: self.modified,
+			accessed: self.accessed,
+			stored_size: None,
+			allocated_size: (stored < self.size).then_some(stored),
+		}
+	}
+
+	fn stored(&self) -> u64 {
+		stored_len(self.size, self.blocks.keys().copied())
+	}
+
+	// 计入容量的字节数：文件内容中已分配的块和扩展属性的值
+	fn usage(&self) -> u64 {
+		self.stored()
+			+ self
+				.xattrs
+				.values()
+				.map(|value| value.len() as u64)
+				.sum::<u64>()
+	}
+}
+
+fn file_mut<'a>(
+	nodes: &'a mut BTreeMap<String, Node>,
+	path: &str,
+) -> Result<&'a mut Node, RemoteError> {
+	match nodes.get_mut(path) {
+		Some(node) if node.is_directory => Err(RemoteError::backend(
+			"is_a_directory",
+			format!("{} is a directory", path),
+		)),
+		Some(node) => Ok(node),
+		None => Err(not_found(path)),
+	}
+}
+
+struct State {
+	// 键为条目路径，根目录为 "."
+	nodes: BTreeMap<String, Node>,
+	used: u64,
+	blocks: Blocks,
+}
+
+impl State {
+	fn node(&self, path: &str) -> Result<&Node, RemoteError> {
+		self.nodes.get(path).ok_or_else(|| not_found(path))
+	}
+
+	fn children<'a>(&'a self, path: &str) -> impl Iterator<Item = (&'a String, &'a Node)> + 'a {
+		let prefix = child_prefix(path);
+		let len = prefix.len();
+		self.nodes
+			.range(prefix.clone()..)
+			.take_while(move |(key, _)| key.starts_with(&prefix))
+			.filter(move |(key, _)| key.as_str() != "." && !key[len..].contains('/'))
+	}
+
+	fn has_children(&self, path: &str) -> bool {
+		self.children(path).next().is_some()
+	}
+
+	fn check_parent(&self, path: &str) -> Result<(), RemoteError> {
+		match self.nodes.get(parent(path)) {
+			Some(node) if node.is_directory => Ok(()),
+			Some(_) => Err(RemoteError::backend(
+				"not_a_directory",
+				format!("the parent of {} is not a directory", path),
+			)),
+			None => Err(RemoteError::backend(
+				"parent_not_found",
+				format!("the parent of {} does not exist", path),
+			)),
+		}
+	}
+
+	// 文件占用的字节数从 old 变为 new 后的总用量；增长超出容量时返回 disk_full
+	fn used_after(&self, old: u64, new: u64, capacity: Option<u64>) -> Result<u64, RemoteError> {
+		let used = self.used - old + new;
+		if let Some(capacity) = capacity {
+			if new > old && used > capacity {
+				return Err(RemoteError::backend(
+					"disk_full",
+					format!(
+						"the volume is full ({} of {} bytes used)",
+						self.used, capacity
+					),
+				));
+			}
+		}
+		Ok(used)
+	}
+
+	// 删除条目时释放它的块
+	fn remove(&mut self, path: &str) {
+		let node = self.nodes.remove(path).unwrap();
+		self.used -= node.usage();
+		for id in node.blocks.into_values() {
+			self.blocks.remove(id);
+		}
+	}
+
+	fn read(&mut self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let node = file_mut(&mut self.nodes, path)?;
+		node.accessed = now();
+		let start = offset.min(node.size);
+		let end = start.saturating_add(length as u64).min(node.size);
+		let mut data = vec![0; (end - start) as usize];
+		for (index, within, at, length) in pieces(start, end - start) {
+			if let Some(&id) = node.blocks.get(&index) {
+				let block = self.blocks.data(id).map_err(io_error)?;
+				data[at as usize..at as usize + length]
+					.copy_from_slice(&block[within..within + length]);
+			}
+		}
+		self.blocks.enforce_limit();
+		Ok(data)
+	}
+
+	// 写入部分全为 0 的块没有分配时保持不分配
+	fn write(
+		&mut self,
+		path: &str,
+		offset: u64,
+		data: &[u8],
+		capacity: Option<u64>,
+	) -> Result<(), RemoteError> {
+		let node = file_mut(&mut self.nodes, path)?;
+		let size = node.size.max(offset + data.len() as u64);
+		let added: Vec<u64> = pieces(offset, data.len() as u64)
+			.filter(|&(index, _, at, length)| {
+				!node.blocks.contains_key(&index)
+					&& !is_zero(&data[at as usize..at as usize + length])
+			})
+			.map(|(index, ..)| index)
+			.collect();
+		let (old, new) = (
+			node.usage(),
+			node.usage() - node.stored()
+				+ stored_len(size, node.blocks.keys().copied().chain(added)),
+		);
+		self.used = self.used_after(old, new, capacity)?;
+
+		let node = self.nodes.get_mut(path).unwrap();
+		for (index, within, at, length) in pieces(offset, data.len() as u64) {
+			let part = &data[at as usize..at as usize + length];
+			match node.blocks.get(&index) {
+				Some(&id) => self.blocks.data(id).map_err(io_error)?[within..within + length]
+					.copy_from_slice(part),
+				None if is_zero(part) => {}
+				None => {
+					let mut block = vec![0; within + length];
+					block[within..].copy_from_slice(part);
+					node.blocks.insert(index, self.blocks.insert(block));
+				}
+			}
+		}
+		node.size = size;
+		node.modified = now();
+		self.blocks.enforce_limit();
+		Ok(())
+	}
+
+	// 整块被置 0 的块被释放，其余只把这一段改写为 0
+	fn zero(
+		&mut self,
+		path: &str,
+		offset: u64,
+		length: u64,
+		capacity: Option<u64>,
+	) -> Result<(), RemoteError> {
+		let node = file_mut(&mut self.nodes, path)?;
+		let end = offset
+			.checked_add(length)
+			.ok_or_else(|| RemoteError::backend("invalid_input", "the range is too large"))?;
+		let size = node.size.max(end);
+		let freed: Vec<u64> = pieces(offset, end.min(node.size).saturating_sub(offset))
+			.filter(|&(index, _, _, length)| {
+				length as u64 == BLOCK_SIZE && node.blocks.contains_key(&index)
+			})
+			.map(|(index, ..)| index)
+			.collect();
+		let (old, new) = (
+			node.usage(),
+			node.usage() - node.stored()
+				+ stored_len(
+					size,
+					node.blocks
+						.keys()
+						.copied()
+						.filter(|index| !freed.contains(index)),
+				),
+		);
+		self.used = self.used_after(old, new, capacity)?;
+
+		let node = self.nodes.get_mut(path).unwrap();
+		for (index, within, _, length) in pieces(offset, end.min(node.size).saturating_sub(offset))
+		{
+			let Some(&id) = node.blocks.get(&index) else {
+				continue;
+			};
+			if length as u64 == BLOCK_SIZE {
+				node.blocks.remove(&index);
+				self.blocks.remove(id);
+			} else {
+				self.blocks.data(id).map_err(io_error)?[within..within + length].fill(0);
+			}
+		}
+		node.size = size;
+		node.modified = now();
+		self.blocks.enforce_limit();
+		Ok(())
+	}
+
+	// 缩短时释放末尾之后的块，并把最后一块中末尾之后的部分置 0，再次增长时读出 0
+	fn truncate(
+		&mut self,
+		path: &str,
+		size: u64,
+		capacity: Option<u64>,
+	) -> Result<(), RemoteError> {
+		let node = file_mut(&mut self.nodes, path)?;
+		let (old, new) = (
+			node.usage(),
+			node.usage() - node.stored()
+				+ stored_len(
+					size,
+					node.blocks
+						.range(..size.div_ceil(BLOCK_SIZE))
+						.map(|(&index, _)| index),
+				),
+		);
+		self.used = self.used_after(old, new, capacity)?;
+
+		let node = self.nodes.get_mut(path).unwrap();
+		for (_, id) in node.blocks.split_off(&size.div_ceil(BLOCK_SIZE)) {
+			self.blocks.remove(id);
+		}
+		if size < node.size && !size.is_multiple_of(BLOCK_SIZE) {
+			if let Some(&id) = node.blocks.get(&(size / BLOCK_SIZE)) {
+				self.blocks.data(id).map_err(io_error)?[(size % BLOCK_SIZE) as usize..].fill(0);
+			}
+		}
+		node.size = size;
+		node.modified = now();
+		self.blocks.enforce_limit();
+		Ok(())
+	}
+
+	// 以 data 替换整个文件，全为 0 的块不分配
+	fn replace(
+		&mut self,
+		path: &str,
+		data: &[u8],
+		capacity: Option<u64>,
+	) -> Result<(), RemoteError> {
+		let node = file_mut(&mut self.nodes, path)?;
+		let chunks = data
+			.chunks(BLOCK_SIZE as usize)
+			.enumerate()
+			.filter(|(_, chunk)| !is_zero(chunk));
+		let (old, new) = (
+			node.usage(),
+			node.usage() - node.stored()
+				+ stored_len(
+					data.len() as u64,
+					chunks.clone().map(|(index, _)| index as u64),
+				),
+		);
+		self.used = self.used_after(old, new, capacity)?;
+
+		let node = self.nodes.get_mut(path).unwrap();
+		for (_, id) in std::mem::take(&mut node.blocks) {
+			self.blocks.remove(id);
+		}
+		for (index, chunk) in chunks {
+			node.blocks
+				.insert(index as u64, self.blocks.insert(chunk.to_vec()));
+		}
+		node.size = data.len() as u64;
+		node.modified = now();
+		self.blocks.enforce_limit();
+		Ok(())
+	}
+}
+
+// 内容保存在内存中的卷，卸载后内容丢失：用作内存盘，也是测试中行为检查的基础。
+// 文件按块分配，没有写入过的部分不占用内存也不计入容量。设置了容量时已分配的内容和扩展属性的总大小
+// 不能超过它，超出的写入返回 disk_full；设置了内存上限时，超出上限的部分移到临时文件中
+pub struct MemoryBackend {
+	state: Mutex<State>,
+	capacity: Option<u64>,
+}
+
+impl MemoryBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to mem:// URLs".into());
+		}
+		Ok(Self::with_capacity(remote.mem_capacity).with_memory_limit(remote.mem_limit))
+	}
+
+	pub fn with_capacity(capacity: Option<u64>) -> Self {
+		Self {
+			state: Mutex::new(State {
+				nodes: BTreeMap::from([(".".to_string(), Node::new(true))]),
+				used: 0,
+				blocks: Blocks {
+					places: HashMap::new(),
+					next_id: 0,
+					recent: BTreeMap::new(),
+					clock: 0,
+					limit: None,
+					spill: None,
+				},
+			}),
+			capacity,
+		}
+	}
+
+	// 内存中最多保存 limit 字节的文件内容，最久未使用的块移到临时文件
+	pub fn with_memory_limit(self, limit: Option<u64>) -> Self {
+		self.state.lock().unwrap().blocks.limit = limit;
+		self
+	}
+}
+
+impl StorageBackend for MemoryBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		let state = self.state.lock().unwrap();
+		Ok(state.node(path)?.info(base_name(path)))
+	}
+
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let state = self.state.lock().unwrap();
+		if !state.node(path)?.is_directory {
+			return Err(RemoteError::backend(
+				"not_a_directory",
+				format!("{} is not a directory", path),
+			));
+		}
+		let items = state
+			.children(path)
+			.map(|(key, node)| node.info(base_name(key)))
+			.collect();
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.state.lock().unwrap().read(path, offset, length)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.state
+			.lock()
+			.unwrap()
+			.write(path, offset, data, self.capacity)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		if !state.nodes.contains_key(path) {
+			state.check_parent(path)?;
+			state.nodes.insert(path.to_string(), Node::new(false));
+		}
+		state.replace(path, data, self.capacity)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		if state.nodes.contains_key(path) {
+			return Err(RemoteError::backend(
+				"already_exists",
+				format!("{} already exists", path),
+			));
+		}
+		state.check_parent(path)?;
+		state
+			.nodes
+			.insert(path.to_string(), Node::new(is_directory));
+		Ok(())
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let node = state.node(path)?;
+		if path == "." {
+			return Err(RemoteError::backend(
+				"invalid_input",
+				"the root directory cannot be deleted",
+			));
+		}
+		if node.is_directory && state.has_children(path) {
+			return Err(RemoteError::backend(
+				"directory_not_empty",
+				format!("{} is not empty", path),
+			));
+		}
+		if !dry_run {
+			state.remove(path);
+		}
+		Ok(())
+	}
+
+	// 目录连同其下的所有条目一起改名
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let source_is_directory = state.node(old_path)?.is_directory;
+		if source_is_directory && new_path.starts_with(&format!("{}/", old_path)) {
+			return Err(RemoteError::backend(
+				"move_into_self",
+				format!("cannot move {} into itself", old_path),
+			));
+		}
+		if let Some(target) = state.nodes.get(new_path) {
+			if !replace {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", new_path),
+				));
+			}
+			if target.is_directory != source_is_directory {
+				return Err(RemoteError::backend(
+					"type_mismatch",
+					format!("{} is of a different type", new_path),
+				));
+			}
+			if target.is_directory && state.has_children(new_path) {
+				return Err(RemoteError::backend(
+					"directory_not_empty",
+					format!("{} is not empty", new_path),
+				));
+			}
+			state.remove(new_path);
+		} else {
+			state.check_parent(new_path)?;
+		}
+		let prefix = format!("{}/", old_path);
+		let moved: Vec<String> = state
+			.nodes
+			.range(prefix.clone()..)
+			.take_while(|(key, _)| key.starts_with(&prefix))
+			.map(|(key, _)| key.clone())
+			.collect();
+		let node = state.nodes.remove(old_path).unwrap();
+		state.nodes.insert(new_path.to_string(), node);
+		for key in moved {
+			let node = state.nodes.remove(&key).unwrap();
+			state
+				.nodes
+				.insert(format!("{}/{}", new_path, &key[prefix.len()..]), node);
+		}
+		Ok(())
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.state
+			.lock()
+			.unwrap()
+			.truncate(path, size, self.capacity)
+	}
+
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		self.state
+			.lock()
+			.unwrap()
+			.zero(path, offset, length, self.capacity)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let node = state.nodes.get_mut(path).ok_or_else(|| not_found(path))?;
+		node.created = times.created.unwrap_or(node.created);
+		node.accessed = times.accessed.unwrap_or(node.accessed);
+		node.modified = times.modified.unwrap_or(node.modified);
+		Ok(())
+	}
+
+	// 没有设置容量时由处理器使用默认值
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		let capacity = self
+			.capacity
+			.ok_or_else(|| RemoteError::unsupported("space"))?;
+		let state = self.state.lock().unwrap();
+		Ok(SpaceResponse {
+			total: capacity,
+			available: capacity.saturating_sub(state.used),
+		})
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		let state = self.state.lock().unwrap();
+		Ok(state
+			.node(path)?
+			.xattrs
+			.iter()
+			.map(|(name, value)| XattrEntry {
+				name: name.clone(),
+				size: value.len() as u64,
+			})
+			.collect())
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		let state = self.state.lock().unwrap();
+		Ok(state.node(path)?.xattrs.get(name).cloned())
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let old = state
+			.node(path)?
+			.xattrs
+			.get(name)
+			.map_or(0, |old| old.len() as u64);
+		let used = state.used - old + value.len() as u64;
+		if let Some(capacity) = self.capacity {
+			if used > state.used && used > capacity {
+				return Err(RemoteError::backend(
+					"disk_full",
+					format!(
+						"the volume is full ({} of {} bytes used)",
+						state.used, capacity
+					),
+				));
+			}
+		}
+		state
+			.nodes
+			.get_mut(path)
+			.unwrap()
+			.xattrs
+			.insert(name.to_string(), value.to_vec());
+		state.used = used;
+		Ok(())
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		let mut state = self.state.lock().unwrap();
+		let node = state.nodes.get_mut(path).ok_or_else(|| not_found(path))?;
+		let value = node.xattrs.remove(name).ok_or_else(|| {
+			RemoteError::backend(
+				"xattr_not_found",
+				format!("{} has no attribute {}", path, name),
+			)
+		})?;
+		state.used -= value.len() as u64;
+		Ok(())
+	}
+}
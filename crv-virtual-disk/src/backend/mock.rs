@@ -0,0 +1,230 @@
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	thread,
+	time::Duration,
+};
+
+use super::{memory::MemoryBackend, StorageBackend};
+use crate::{error::RemoteError, ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate};
+
+/// [`MockBackend`] 记录和注入故障的操作。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Operation {
+	Stat,
+	List,
+	Read,
+	Write,
+	Commit,
+	Create,
+	Delete,
+	Rename,
+	Truncate,
+	SetTimes,
+	Space,
+}
+
+#[derive(Debug, Clone)]
+enum Effect {
+	Fail(&'static str),
+	Delay(Duration),
+	ShortRead(usize),
+}
+
+/// 注入的一个故障：某个操作失败、延迟或（读取时）只返回部分数据。默认对所有路径、每次调用都生效。
+#[derive(Debug, Clone)]
+pub struct Fault {
+	operation: Operation,
+	effect: Effect,
+	path: Option<String>,
+	// 还会生效的次数，None 为不限
+	remaining: Option<usize>,
+}
+
+impl Fault {
+	/// `operation` 以错误类别 `code`（`not_found`、`connection_lost`、`disk_full` 等）失败。
+	pub fn fail(operation: Operation, code: &'static str) -> Self {
+		Self::new(operation, Effect::Fail(code))
+	}
+
+	/// `operation` 等待 `delay` 后照常执行。
+	pub fn delay(operation: Operation, delay: Duration) -> Self {
+		Self::new(operation, Effect::Delay(delay))
+	}
+
+	/// 读取最多返回 `length` 字节，像连接中途断开的响应一样短于请求的长度。
+	pub fn short_read(length: usize) -> Self {
+		Self::new(Operation::Read, Effect::ShortRead(length))
+	}
+
+	fn new(operation: Operation, effect: Effect) -> Self {
+		Self {
+			operation,
+			effect,
+			path: None,
+			remaining: None,
+		}
+	}
+
+	/// 只对 `path` 生效；重命名按原路径匹配。
+	pub fn on(mut self, path: &str) -> Self {
+		self.path = Some(path.to_string());
+		self
+	}
+
+	/// 只生效 `times` 次，之后操作照常执行，用于检查重试。
+	pub fn times(mut self, times: usize) -> Self {
+		self.remaining = Some(times);
+		self
+	}
+}
+
+#[derive(Default)]
+struct Script {
+	faults: Vec<Fault>,
+	calls: HashMap<Operation, usize>,
+}
+
+/// 测试用的后端：内容保存在内存盘中，按注入的 [`Fault`] 返回指定的错误、延迟或部分读取，并记录每种操作的调用次数，
+/// 不需要网络就能确定地检查处理器的重试、缓存和错误映射。克隆的实例共享内容和脚本，
+/// 可以把一个交给 [`HttpFsHandler`](crate::HttpFsHandler)，用另一个注入故障和检查调用次数。
+#[derive(Clone)]
+pub struct MockBackend {
+	inner: Arc<MemoryBackend>,
+	script: Arc<Mutex<Script>>,
+}
+
+impl Default for MockBackend {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl MockBackend {
+	pub fn new() -> Self {
+		Self {
+			inner: Arc::new(MemoryBackend::with_capacity(None)),
+			script: Arc::default(),
+		}
+	}
+
+	/// 追加故障。一次调用只产生第一个匹配且还有剩余次数的故障。
+	pub fn inject(&self, fault: Fault) {
+		self.script.lock().unwrap().faults.push(fault);
+	}
+
+	/// 移除所有故障，调用次数保留。
+	pub fn clear_faults(&self) {
+		self.script.lock().unwrap().faults.clear();
+	}
+
+	/// `operation` 至今被调用的次数，包括注入故障的调用。
+	pub fn calls(&self, operation: Operation) -> usize {
+		self.script
+			.lock()
+			.unwrap()
+			.calls
+			.get(&operation)
+			.copied()
+			.unwrap_or(0)
+	}
+
+	pub fn reset_calls(&self) {
+		self.script.lock().unwrap().calls.clear();
+	}
+
+	// 记录调用并产生匹配的故障：失败时返回错误，延迟在锁外等待，部分读取返回读取长度的上限
+	fn enter(&self, operation: Operation, path: &str) -> Result<Option<usize>, RemoteError> {
+		let effect = {
+			let mut script = self.script.lock().unwrap();
+			*script.calls.entry(operation).or_default() += 1;
+			let fault = script.faults.iter_mut().find(|fault| {
+				fault.operation == operation
+					&& fault.path.as_deref().is_none_or(|only| only == path)
+					&& fault.remaining != Some(0)
+			});
+			match fault {
+				Some(fault) => {
+					if let Some(remaining) = &mut fault.remaining {
+						*remaining -= 1;
+					}
+					Some(fault.effect.clone())
+				}
+				None => None,
+			}
+		};
+		match effect {
+			Some(Effect::Fail(code)) => Err(RemoteError::backend(
+				code,
+				format!("injected {} for {:?} on {}", code, operation, path),
+			)),
+			Some(Effect::Delay(delay)) => {
+				thread::sleep(delay);
+				Ok(None)
+			}
+			Some(Effect::ShortRead(length)) => Ok(Some(length)),
+			None => Ok(None),
+		}
+	}
+}
+
+impl StorageBackend for MockBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		self.enter(Operation::Stat, path)?;
+		self.inner.stat(path)
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		self.enter(Operation::List, path)?;
+		self.inner.list_page(path, cursor)
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let limit = self.enter(Operation::Read, path)?;
+		self.inner.read(
+			path,
+			offset,
+			limit.map_or(length, |limit| length.min(limit)),
+		)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.enter(Operation::Write, path)?;
+		self.inner.write(path, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.enter(Operation::Commit, path)?;
+		self.inner.commit(path, data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.enter(Operation::Create, path)?;
+		self.inner.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.enter(Operation::Delete, path)?;
+		self.inner.delete(path, dry_run)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.enter(Operation::Rename, old_path)?;
+		self.inner.rename(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.enter(Operation::Truncate, path)?;
+		self.inner.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.enter(Operation::SetTimes, path)?;
+		self.inner.set_times(path, times)
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.enter(Operation::Space, ".")?;
+		self.inner.space()
+	}
+}
@@ -0,0 +1,487 @@
+use std::collections::{BTreeMap, HashSet};
+
+use super::{base_name, StorageBackend, CHECKSUM_READ_SIZE};
+use crate::{
+	error::RemoteError, ChecksumResponse, ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate,
+	XattrEntry,
+};
+
+// 上层中的标记文件（与 aufs 相同）：.wh.<名称> 表示下层中的同名条目已被删除，
+// 目录中的 .wh..wh..opq 表示不再显示下层中该目录的内容。标记不出现在目录列表中，也不能被创建
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_MARKER: &str = ".wh..wh..opq";
+
+fn parent(path: &str) -> &str {
+	path.rsplit_once('/').map_or(".", |(parent, _)| parent)
+}
+
+fn child_path(parent: &str, name: &str) -> String {
+	if parent == "." {
+		name.to_string()
+	} else {
+		format!("{}/{}", parent, name)
+	}
+}
+
+fn whiteout_path(path: &str) -> String {
+	child_path(
+		parent(path),
+		&format!("{}{}", WHITEOUT_PREFIX, base_name(path)),
+	)
+}
+
+fn not_found(path: &str) -> RemoteError {
+	RemoteError::backend("not_found", format!("{} does not exist", path))
+}
+
+// 条目不存在的各种错误
+fn is_missing(e: &RemoteError) -> bool {
+	matches!(
+		e.code(),
+		Some("not_found" | "parent_not_found" | "not_a_directory")
+	)
+}
+
+fn exists(backend: &dyn StorageBackend, path: &str) -> Result<bool, RemoteError> {
+	match backend.stat(path) {
+		Ok(_) => Ok(true),
+		Err(e) if is_missing(&e) => Ok(false),
+		Err(e) => Err(e),
+	}
+}
+
+fn list_all(backend: &dyn StorageBackend, path: &str) -> Result<Vec<RemoteFileInfo>, RemoteError> {
+	let mut items = Vec::new();
+	let mut cursor = None;
+	loop {
+		let page = backend.list_page(path, cursor.as_deref())?;
+		items.extend(page.items);
+		match page.next_cursor {
+			Some(next) => cursor = Some(next),
+			None => return Ok(items),
+		}
+	}
+}
+
+fn read_all(backend: &dyn StorageBackend, path: &str) -> Result<Vec<u8>, RemoteError> {
+	let mut data = Vec::new();
+	loop {
+		let chunk = backend.read(path, data.len() as u64, CHECKSUM_READ_SIZE)?;
+		data.extend_from_slice(&chunk);
+		if chunk.len() < CHECKSUM_READ_SIZE {
+			return Ok(data);
+		}
+	}
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Layer {
+	Upper,
+	Lower(usize),
+}
+
+// 叠加挂载：可写的上层覆盖在一个或多个只读的下层之上，靠上的层中的同名条目优先。
+// 所有修改都在上层中进行：修改下层中的文件前先把它（和上级目录）复制到上层，
+// 删除下层中的条目时在上层中留下删除标记。下层永远不会被写入，可以是只读的后端或共享的服务器
+pub struct OverlayBackend {
+	upper: Box<dyn StorageBackend>,
+	// 靠上的层在前
+	lowers: Vec<Box<dyn StorageBackend>>,
+}
+
+impl OverlayBackend {
+	pub fn new(upper: Box<dyn StorageBackend>, lowers: Vec<Box<dyn StorageBackend>>) -> Self {
+		Self { upper, lowers }
+	}
+
+	fn layer(&self, layer: Layer) -> &dyn StorageBackend {
+		match layer {
+			Layer::Upper => self.upper.as_ref(),
+			Layer::Lower(index) => self.lowers[index].as_ref(),
+		}
+	}
+
+	// 下层中的 path 是否被遮住：它本身或某个上级目录有删除标记，或某个上级目录在上层中是不透明的
+	fn hidden(&self, path: &str) -> Result<bool, RemoteError> {
+		let mut current = String::from(".");
+		for name in path.split('/') {
+			if current != "." && exists(self.upper.as_ref(), &child_path(&current, OPAQUE_MARKER))?
+			{
+				return Ok(true);
+			}
+			current = child_path(&current, name);
+			if exists(self.upper.as_ref(), &whiteout_path(&current))? {
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+	// 合并后的视图中 path 所在的层
+	fn locate(&self, path: &str) -> Result<(Layer, RemoteFileInfo), RemoteError> {
+		if base_name(path).starts_with(WHITEOUT_PREFIX) {
+			return Err(not_found(path));
+		}
+		match self.upper.stat(path) {
+			Ok(info) => return Ok((Layer::Upper, info)),
+			Err(e) if is_missing(&e) => {}
+			Err(e) => return Err(e),
+		}
+		if path == "." || self.hidden(path)? {
+			return Err(not_found(path));
+		}
+		for (index, lower) in self.lowers.iter().enumerate() {
+			match lower.stat(path) {
+				Ok(info) => return Ok((Layer::Lower(index), info)),
+				Err(e) if is_missing(&e) => {}
+				Err(e) => return Err(e),
+			}
+		}
+		Err(not_found(path))
+	}
+
+	// 不考虑删除标记时，是否有某个下层包含 path
+	fn in_lowers(&self, path: &str) -> Result<bool, RemoteError> {
+		for lower in &self.lowers {
+			if exists(lower.as_ref(), path)? {
+				return Ok(true);
+			}
+		}
+		Ok(false)
+	}
+
+	// 合并后的目录内容：同名的条目取靠上的层，去掉有删除标记的条目，不透明的目录只取上层
+	fn list_merged(&self, path: &str) -> Result<Vec<RemoteFileInfo>, RemoteError> {
+		let (layer, info) = self.locate(path)?;
+		if !info.is_directory {
+			return Err(RemoteError::backend(
+				"not_a_directory",
+				format!("{} is not a directory", path),
+			));
+		}
+		let mut items = BTreeMap::new();
+		let mut whiteouts = HashSet::new();
+		let mut opaque = false;
+		if layer == Layer::Upper {
+			for item in list_all(self.upper.as_ref(), path)? {
+				if item.name == OPAQUE_MARKER {
+					opaque = true;
+				} else if let Some(name) = item.name.strip_prefix(WHITEOUT_PREFIX) {
+					whiteouts.insert(name.to_string());
+				} else {
+					items.insert(item.name.clone(), item);
+				}
+			}
+			opaque = opaque || (path != "." && self.hidden(path)?);
+		}
+		if !opaque {
+			for lower in &self.lowers {
+				let listed = match list_all(lower.as_ref(), path) {
+					Ok(listed) => listed,
+					Err(e) if is_missing(&e) => continue,
+					Err(e) => return Err(e),
+				};
+				for item in listed {
+					if !item.name.starts_with(WHITEOUT_PREFIX) && !whiteouts.contains(&item.name) {
+						items.entry(item.name.clone()).or_insert(item);
+					}
+				}
+			}
+		}
+		Ok(items.into_values().collect())
+	}
+
+	// 标记文件的名称不能用于普通条目
+	fn check_name(path: &str) -> Result<(), RemoteError> {
+		if base_name(path).starts_with(WHITEOUT_PREFIX) {
+			return Err(RemoteError::backend(
+				"invalid_name",
+				format!("{} is reserved for the overlay", path),
+			));
+		}
+		Ok(())
+	}
+
+	fn check_parent(&self, path: &str) -> Result<(), RemoteError> {
+		match self.locate(parent(path)) {
+			Ok((_, info)) if info.is_directory => Ok(()),
+			Ok(_) => Err(RemoteError::backend(
+				"not_a_directory",
+				format!("the parent of {} is not a directory", path),
+			)),
+			Err(e) if is_missing(&e) => Err(RemoteError::backend(
+				"parent_not_found",
+				format!("the parent of {} does not exist", path),
+			)),
+			Err(e) => Err(e),
+		}
+	}
+
+	// 尽量保留复制到上层的条目的时间戳，上层不支持时忽略
+	fn keep_times(&self, path: &str, info: &RemoteFileInfo) {
+		let times = TimesUpdate {
+			created: Some(info.created),
+			accessed: Some(info.accessed),
+			modified: Some(info.modified),
+		};
+		let _ = self.upper.set_times(path, &times);
+	}
+
+	// 在上层中创建 dir 及其上级目录（不复制目录内容），已存在的跳过
+	fn copy_up_directories(&self, dir: &str) -> Result<(), RemoteError> {
+		if dir == "." {
+			return Ok(());
+		}
+		let mut current = String::from(".");
+		for name in dir.split('/') {
+			current = child_path(&current, name);
+			let (layer, info) = self.locate(&current)?;
+			if !info.is_directory {
+				return Err(RemoteError::backend(
+					"not_a_directory",
+					format!("{} is not a directory", current),
+				));
+			}
+			if layer != Layer::Upper {
+				self.upper.create(&current, true)?;
+				self.keep_times(&current, &info);
+			}
+		}
+		Ok(())
+	}
+
+	// 修改前把 path 复制到上层，文件连同内容一起复制；扩展属性和时间戳在上层支持时保留
+	fn copy_up(&self, path: &str) -> Result<(), RemoteError> {
+		let (layer, info) = self.locate(path)?;
+		if layer == Layer::Upper {
+			return Ok(());
+		}
+		if info.is_directory {
+			return self.copy_up_directories(path);
+		}
+		self.copy_up_directories(parent(path))?;
+		let lower = self.layer(layer);
+		self.upper.commit(path, &read_all(lower, path)?)?;
+		for xattr in lower.list_xattrs(path).unwrap_or_default() {
+			if let Ok(Some(value)) = lower.get_xattr(path, &xattr.name) {
+				let _ = self.upper.put_xattr(path, &xattr.name, &value);
+			}
+		}
+		self.keep_times(path, &info);
+		Ok(())
+	}
+
+	// 上层中新放入 path 之后：去掉它的删除标记；新的目录遮住下层中的同名目录
+	fn cover(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let whiteout = whiteout_path(path);
+		if exists(self.upper.as_ref(), &whiteout)? {
+			self.upper.delete(&whiteout, false)?;
+		}
+		if is_directory && self.in_lowers(path)? {
+			self.upper.commit(&child_path(path, OPAQUE_MARKER), b"")?;
+		}
+		Ok(())
+	}
+
+	// 把合并后的 from 整个复制到上层的 to
+	fn copy_tree(&self, from: &str, to: &str) -> Result<(), RemoteError> {
+		let (layer, info) = self.locate(from)?;
+		if info.is_directory {
+			self.upper.create(to, true)?;
+			for item in self.list_merged(from)? {
+				self.copy_tree(&child_path(from, &item.name), &child_path(to, &item.name))?;
+			}
+		} else {
+			self.upper.commit(to, &read_all(self.layer(layer), from)?)?;
+		}
+		self.keep_times(to, &info);
+		Ok(())
+	}
+
+	fn remove_tree(&self, path: &str) -> Result<(), RemoteError> {
+		if self.locate(path)?.1.is_directory {
+			for item in self.list_merged(path)? {
+				self.remove_tree(&child_path(path, &item.name))?;
+			}
+		}
+		self.delete(path, false)
+	}
+}
+
+impl StorageBackend for OverlayBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		Ok(self.locate(path)?.1)
+	}
+
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		Ok(ListPage {
+			items: self.list_merged(path)?,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let (layer, _) = self.locate(path)?;
+		self.layer(layer).read(path, offset, length)
+	}
+
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		let (layer, _) = self.locate(path)?;
+		self.layer(layer).read_into(path, offset, buffer)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.copy_up(path)?;
+		self.upper.write(path, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		Self::check_name(path)?;
+		if exists(self.upper.as_ref(), path)? {
+			return self.upper.commit(path, data);
+		}
+		self.check_parent(path)?;
+		self.copy_up_directories(parent(path))?;
+		self.upper.commit(path, data)?;
+		self.cover(path, false)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		Self::check_name(path)?;
+		match self.locate(path) {
+			Ok(_) => {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", path),
+				))
+			}
+			Err(e) if is_missing(&e) => {}
+			Err(e) => return Err(e),
+		}
+		self.check_parent(path)?;
+		self.copy_up_directories(parent(path))?;
+		self.upper.create(path, is_directory)?;
+		self.cover(path, is_directory)
+	}
+
+	// 删除上层中的条目，下层中还有同名条目时留下删除标记
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		if path == "." {
+			return Err(RemoteError::backend(
+				"invalid_input",
+				"the root directory cannot be deleted",
+			));
+		}
+		let (layer, info) = self.locate(path)?;
+		if info.is_directory && !self.list_merged(path)?.is_empty() {
+			return Err(RemoteError::backend(
+				"directory_not_empty",
+				format!("{} is not empty", path),
+			));
+		}
+		if dry_run {
+			return Ok(());
+		}
+		if layer == Layer::Upper {
+			if info.is_directory {
+				for marker in list_all(self.upper.as_ref(), path)? {
+					self.upper.delete(&child_path(path, &marker.name), false)?;
+				}
+			}
+			self.upper.delete(path, false)?;
+		}
+		if self.in_lowers(path)? {
+			self.copy_up_directories(parent(path))?;
+			self.upper.commit(&whiteout_path(path), b"")?;
+		}
+		Ok(())
+	}
+
+	// 只在上层中的条目直接改名；包含下层内容的目录复制到上层的新位置后删除原位置
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		Self::check_name(new_path)?;
+		let (layer, source) = self.locate(old_path)?;
+		if source.is_directory && new_path.starts_with(&format!("{}/", old_path)) {
+			return Err(RemoteError::backend(
+				"move_into_self",
+				format!("cannot move {} into itself", old_path),
+			));
+		}
+		match self.locate(new_path) {
+			Ok((_, target)) => {
+				if !replace {
+					return Err(RemoteError::backend(
+						"already_exists",
+						format!("{} already exists", new_path),
+					));
+				}
+				if target.is_directory != source.is_directory {
+					return Err(RemoteError::backend(
+						"type_mismatch",
+						format!("{} is of a different type", new_path),
+					));
+				}
+				if target.is_directory && !self.list_merged(new_path)?.is_empty() {
+					return Err(RemoteError::backend(
+						"directory_not_empty",
+						format!("{} is not empty", new_path),
+					));
+				}
+				self.delete(new_path, false)?;
+			}
+			Err(e) if is_missing(&e) => self.check_parent(new_path)?,
+			Err(e) => return Err(e),
+		}
+		self.copy_up_directories(parent(new_path))?;
+		let in_lowers = self.in_lowers(old_path)?;
+		if layer == Layer::Upper && !(source.is_directory && in_lowers) {
+			self.upper.rename(old_path, new_path, false)?;
+			if in_lowers {
+				self.upper.commit(&whiteout_path(old_path), b"")?;
+			}
+		} else {
+			self.copy_tree(old_path, new_path)?;
+			self.remove_tree(old_path)?;
+		}
+		self.cover(new_path, source.is_directory)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.copy_up(path)?;
+		self.upper.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.copy_up(path)?;
+		self.upper.set_times(path, times)
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.upper.space()
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		let (layer, _) = self.locate(path)?;
+		self.layer(layer).list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		let (layer, _) = self.locate(path)?;
+		self.layer(layer).get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.copy_up(path)?;
+		self.upper.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.copy_up(path)?;
+		self.upper.delete_xattr(path, name)
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		let (layer, _) = self.locate(path)?;
+		self.layer(layer).checksum(path)
+	}
+}
@@ -0,0 +1,916 @@
+use std::{
+	collections::BTreeMap,
+	env,
+	error::Error,
+	fs,
+	path::PathBuf,
+	sync::Mutex,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use hmac::{Hmac, Mac};
+use reqwest::{
+	blocking::{Client, Response},
+	header::{
+		HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_LENGTH, LAST_MODIFIED, RANGE,
+	},
+	Method, StatusCode, Url,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use tracing::error;
+
+use super::{
+	base_name, child_text, civil_from_days, directory_info, file_info, parse_timestamp, parse_xml,
+	uri_encode, StorageBackend,
+};
+use crate::{
+	backend::Remote,
+	error::{ApiError, CheckStatus, RemoteError, SendRetrying},
+	ListPage, RemoteFileInfo, TimesUpdate,
+};
+
+// 列目录时每页请求的条目数，也是 S3 允许的最大值
+const LIST_PAGE_SIZE: usize = 1000;
+
+// 超过该大小的内容改用分段上传，每段失败时单独重试
+const MULTIPART_THRESHOLD: usize = 16 * 1024 * 1024;
+const PART_SIZE: usize = 8 * 1024 * 1024;
+const PART_RETRIES: usize = 3;
+
+// CopyObject 单次能复制的最大对象，更大的对象按段复制
+const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+const COPY_PART_SIZE: u64 = 512 * 1024 * 1024;
+
+// EC2 实例元数据服务，在实例上运行时从中获取实例角色的临时凭据
+const IMDS_URL: &str = "http://169.254.169.254";
+const IMDS_TIMEOUT: Duration = Duration::from_secs(1);
+const IMDS_TOKEN_HEADER: &str = "x-aws-ec2-metadata-token";
+
+// 临时凭据在过期前这么多秒重新获取
+const CREDENTIAL_REFRESH_SECS: u64 = 5 * 60;
+
+type HmacSha256 = Hmac<Sha256>;
+
+#[derive(Debug, Clone)]
+struct Credentials {
+	access_key_id: String,
+	secret_access_key: String,
+	session_token: Option<String>,
+	// 临时凭据的过期时间（Unix 秒）
+	expires: Option<u64>,
+}
+
+// 实例元数据服务返回的凭据
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InstanceCredentials {
+	access_key_id: String,
+	secret_access_key: String,
+	token: String,
+	expiration: String,
+}
+
+// 按顺序查找凭据：--aws-profile 指定的配置，环境变量 AWS_ACCESS_KEY_ID 和 AWS_SECRET_ACCESS_KEY，
+// 共享凭据文件中 AWS_PROFILE（默认 default）的配置，最后是 EC2 实例角色；实例角色的凭据过期前自动更新
+struct CredentialChain {
+	current: Mutex<Credentials>,
+	from_instance: bool,
+}
+
+impl CredentialChain {
+	fn resolve(profile: Option<&str>) -> Result<Self, Box<dyn Error>> {
+		let fixed = |credentials| Self {
+			current: Mutex::new(credentials),
+			from_instance: false,
+		};
+		if let Some(profile) = profile {
+			return profile_credentials(profile).map(fixed).ok_or_else(|| {
+				format!(
+					"no credentials for profile '{}' in the AWS credentials file",
+					profile
+				)
+				.into()
+			});
+		}
+		if let (Ok(access_key_id), Ok(secret_access_key)) = (
+			env::var("AWS_ACCESS_KEY_ID"),
+			env::var("AWS_SECRET_ACCESS_KEY"),
+		) {
+			return Ok(fixed(Credentials {
+				access_key_id,
+				secret_access_key,
+				session_token: env::var("AWS_SESSION_TOKEN").ok(),
+				expires: None,
+			}));
+		}
+		let profile = env::var("AWS_PROFILE").unwrap_or_else(|_| "default".to_string());
+		if let Some(credentials) = profile_credentials(&profile) {
+			return Ok(fixed(credentials));
+		}
+		match instance_credentials() {
+			Ok(credentials) => Ok(Self {
+				current: Mutex::new(credentials),
+				from_instance: true,
+			}),
+			Err(_) => Err("no S3 credentials found: set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY, add them to the AWS credentials file or run on an instance with an IAM role".into()),
+		}
+	}
+
+	fn get(&self) -> Result<Credentials, RemoteError> {
+		let mut current = self.current.lock().unwrap();
+		if self.from_instance
+			&& current
+				.expires
+				.is_some_and(|expires| expires < unix_now() + CREDENTIAL_REFRESH_SECS)
+		{
+			*current = instance_credentials()?;
+		}
+		Ok(current.clone())
+	}
+}
+
+fn unix_now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs())
+}
+
+// AWS_SHARED_CREDENTIALS_FILE，默认为用户目录下的 .aws\credentials
+fn credentials_file() -> Option<PathBuf> {
+	if let Some(path) = env::var_os("AWS_SHARED_CREDENTIALS_FILE") {
+		return Some(PathBuf::from(path));
+	}
+	let home = env::var_os("USERPROFILE").or_else(|| env::var_os("HOME"))?;
+	Some(PathBuf::from(home).join(".aws").join("credentials"))
+}
+
+// 共享凭据文件是 INI 格式，每个配置一节
+fn profile_credentials(profile: &str) -> Option<Credentials> {
+	let text = fs::read_to_string(credentials_file()?).ok()?;
+	let mut values = BTreeMap::new();
+	let mut in_profile = false;
+	for line in text.lines().map(str::trim) {
+		if line.starts_with(['#', ';']) {
+			continue;
+		}
+		if let Some(name) = line
+			.strip_prefix('[')
+			.and_then(|line| line.strip_suffix(']'))
+		{
+			in_profile = name.trim() == profile;
+		} else if let Some((key, value)) = line.split_once('=').filter(|_| in_profile) {
+			values.insert(key.trim().to_string(), value.trim().to_string());
+		}
+	}
+	Some(Credentials {
+		access_key_id: values.remove("aws_access_key_id")?,
+		secret_access_key: values.remove("aws_secret_access_key")?,
+		session_token: values.remove("aws_session_token"),
+		expires: None,
+	})
+}
+
+// 通过 IMDSv2 获取实例角色的临时凭据：先申请会话令牌，再查询角色名和凭据
+fn instance_credentials() -> Result<Credentials, RemoteError> {
+	let client = Client::builder().timeout(IMDS_TIMEOUT).build()?;
+	let token = client
+		.put(format!("{}/latest/api/token", IMDS_URL))
+		.header("x-aws-ec2-metadata-token-ttl-seconds", "21600")
+		.send()?
+		.check_status()?
+		.text()?;
+	let url = format!("{}/latest/meta-data/iam/security-credentials/", IMDS_URL);
+	let roles = client
+		.get(&url)
+		.header(IMDS_TOKEN_HEADER, &token)
+		.send()?
+		.check_status()?
+		.text()?;
+	let role = roles
+		.lines()
+		.next()
+		.filter(|role| !role.is_empty())
+		.ok_or_else(|| RemoteError::backend("not_found", "the instance has no IAM role"))?;
+	let credentials = client
+		.get(format!("{}{}", url, role))
+		.header(IMDS_TOKEN_HEADER, &token)
+		.send()?
+		.check_status()?
+		.json::<InstanceCredentials>()?;
+	Ok(Credentials {
+		access_key_id: credentials.access_key_id,
+		secret_access_key: credentials.secret_access_key,
+		session_token: Some(credentials.token),
+		expires: parse_timestamp(&credentials.expiration),
+	})
+}
+
+// 签名使用的日期（20240102）和时间（20240102T030405Z）
+fn amz_date(secs: u64) -> (String, String) {
+	let (year, month, day) = civil_from_days((secs / 86400) as i64);
+	let date = format!("{:04}{:02}{:02}", year, month, day);
+	let time = secs % 86400;
+	let timestamp = format!(
+		"{}T{:02}{:02}{:02}Z",
+		date,
+		time / 3600,
+		time / 60 % 60,
+		time % 60
+	);
+	(date, timestamp)
+}
+
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+	let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+	mac.update(data.as_bytes());
+	mac.finalize().into_bytes().to_vec()
+}
+
+// S3 的错误代码换成与 httpfs 服务器相同的错误类别，HEAD 请求的错误没有响应体，按状态码判断
+fn error_category(code: &str, status: StatusCode) -> String {
+	match code {
+		"NoSuchKey" | "NoSuchUpload" => "not_found",
+		"NoSuchBucket" => "share_not_found",
+		"SlowDown" | "RequestLimitExceeded" => "rate_limited",
+		"EntityTooLarge" => "file_too_large",
+		"InvalidRange" => "invalid_range",
+		"" => match status {
+			StatusCode::NOT_FOUND => "not_found",
+			StatusCode::SERVICE_UNAVAILABLE => "rate_limited",
+			StatusCode::RANGE_NOT_SATISFIABLE => "invalid_range",
+			_ => "http_error",
+		},
+		code => code,
+	}
+	.to_string()
+}
+
+// S3 错误响应的 XML 体：<Error><Code>NoSuchKey</Code><Message>...</Message></Error>
+fn api_error(status: StatusCode, body: &str, request_id: Option<String>) -> Option<RemoteError> {
+	let (code, message) = match roxmltree::Document::parse(body) {
+		Ok(doc) if doc.root_element().has_tag_name("Error") => {
+			let root = doc.root_element();
+			(
+				child_text(root, "Code").unwrap_or_default().to_string(),
+				child_text(root, "Message").unwrap_or_default().to_string(),
+			)
+		}
+		// 复制和完成分段上传在成功的状态码下也可能返回错误体
+		_ if status.is_success() => return None,
+		_ => (
+			String::new(),
+			status.canonical_reason().unwrap_or("error").to_string(),
+		),
+	};
+	Some(RemoteError::Api {
+		status,
+		error: ApiError {
+			code: error_category(&code, status),
+			message,
+			os_error: None,
+		},
+		request_id,
+	})
+}
+
+fn check(response: Response) -> Result<Response, RemoteError> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+	let request_id = response
+		.headers()
+		.get("x-amz-request-id")
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string);
+	let body = response.text().unwrap_or_default();
+	Err(api_error(status, &body, request_id).expect("failed responses always produce an error"))
+}
+
+// 读取成功响应的 XML 体，体中是错误时返回该错误
+fn check_body(response: Response) -> Result<String, RemoteError> {
+	let status = response.status();
+	let body = response.text()?;
+	match api_error(status, &body, None) {
+		Some(e) => Err(e),
+		None => Ok(body),
+	}
+}
+
+struct Object {
+	key: String,
+	size: u64,
+	modified: u64,
+}
+
+// ListObjectsV2 的一页：使用分隔符时子目录作为公共前缀返回
+struct ObjectPage {
+	objects: Vec<Object>,
+	prefixes: Vec<String>,
+	next_token: Option<String>,
+}
+
+// S3 或兼容的对象存储（如 MinIO）中的一个桶，可以限定在某个键前缀下。
+// 对象键中的 / 分隔目录；目录是以 / 结尾的空对象，或者只是其他键的公共前缀。
+// 对象不能原地修改，write 和 truncate 读出整个对象修改后重新上传，目录重命名逐个复制其中的对象；
+// 时间戳由存储决定，不能设置
+pub struct S3Backend {
+	client: Client,
+	// scheme://host[:port]，虚拟主机风格时 host 以桶名开头
+	origin: String,
+	// 签名使用的 Host 请求头
+	host: String,
+	// 路径风格时为 /bucket，虚拟主机风格时为空
+	base_path: String,
+	bucket: String,
+	// 共享根目录对应的键前缀，为空或以 / 结尾
+	prefix: String,
+	region: String,
+	credentials: CredentialChain,
+}
+
+impl S3Backend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to s3:// URLs; put the key prefix in the URL instead".into());
+		}
+		let location = remote
+			.server_url
+			.split_once("://")
+			.map_or("", |(_, rest)| rest);
+		let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+		if bucket.is_empty() {
+			return Err(format!(
+				"{} does not name a bucket; use s3://BUCKET[/PREFIX]",
+				remote.server_url
+			)
+			.into());
+		}
+		let prefix = prefix.trim_matches('/');
+		let prefix = if prefix.is_empty() {
+			String::new()
+		} else {
+			format!("{}/", prefix)
+		};
+
+		let region = remote
+			.s3_region
+			.clone()
+			.or_else(|| env::var("AWS_REGION").ok())
+			.or_else(|| env::var("AWS_DEFAULT_REGION").ok())
+			.unwrap_or_else(|| "us-east-1".to_string());
+		let endpoint = match &remote.s3_endpoint {
+			Some(endpoint) => endpoint.clone(),
+			None => format!("https://s3.{}.amazonaws.com", region),
+		};
+		let endpoint = Url::parse(&endpoint)
+			.map_err(|e| format!("invalid S3 endpoint {}: {}", endpoint, e))?;
+		let host = endpoint
+			.host_str()
+			.ok_or_else(|| format!("S3 endpoint {} has no host", endpoint))?;
+		let host = match endpoint.port() {
+			Some(port) => format!("{}:{}", host, port),
+			None => host.to_string(),
+		};
+		// 桶名中的 . 会使虚拟主机风格的证书校验失败，总是使用路径风格
+		let (host, base_path) = if remote.s3_path_style || bucket.contains('.') {
+			(host, format!("/{}", bucket))
+		} else {
+			(format!("{}.{}", bucket, host), String::new())
+		};
+
+		Ok(Self {
+			client: Client::builder().timeout(Duration::from_secs(30)).build()?,
+			origin: format!("{}://{}", endpoint.scheme(), host),
+			host,
+			base_path,
+			bucket: bucket.to_string(),
+			prefix,
+			region,
+			credentials: CredentialChain::resolve(remote.aws_profile.as_deref())?,
+		})
+	}
+
+	// 文件对应的对象键
+	fn key(&self, path: &str) -> String {
+		format!("{}{}", self.prefix, path)
+	}
+
+	// 目录下的条目共有的键前缀，根目录为共享的前缀
+	fn dir_prefix(&self, path: &str) -> String {
+		if path == "." {
+			self.prefix.clone()
+		} else {
+			format!("{}{}/", self.prefix, path)
+		}
+	}
+
+	// 发送经过 SigV4 签名的请求；key 为空时请求桶本身
+	fn send(
+		&self,
+		method: Method,
+		key: &str,
+		query: &[(&str, &str)],
+		mut headers: HeaderMap,
+		body: Vec<u8>,
+	) -> Result<Response, RemoteError> {
+		let credentials = self.credentials.get()?;
+		let mut query: Vec<(String, String)> = query
+			.iter()
+			.map(|(name, value)| (uri_encode(name, false), uri_encode(value, false)))
+			.collect();
+		query.sort();
+		let query = query
+			.iter()
+			.map(|(name, value)| format!("{}={}", name, value))
+			.collect::<Vec<_>>()
+			.join("&");
+		let path = format!("{}/{}", self.base_path, uri_encode(key, true));
+		let url = if query.is_empty() {
+			format!("{}{}", self.origin, path)
+		} else {
+			format!("{}{}?{}", self.origin, path, query)
+		};
+
+		let (date, timestamp) = amz_date(unix_now());
+		let payload_hash = hex::encode(Sha256::digest(&body));
+		let mut insert = |name: &'static str, value: &str| {
+			headers.insert(
+				HeaderName::from_static(name),
+				HeaderValue::from_str(value).unwrap(),
+			);
+		};
+		insert("x-amz-date", &timestamp);
+		insert("x-amz-content-sha256", &payload_hash);
+		if let Some(token) = &credentials.session_token {
+			insert("x-amz-security-token", token);
+		}
+
+		// 签名覆盖 Host 和所有显式设置的请求头
+		let mut signed: Vec<(String, String)> = headers
+			.iter()
+			.map(|(name, value)| {
+				(
+					name.as_str().to_string(),
+					value.to_str().unwrap_or_default().trim().to_string(),
+				)
+			})
+			.collect();
+		signed.push(("host".to_string(), self.host.clone()));
+		signed.sort();
+		let canonical_headers: String = signed
+			.iter()
+			.map(|(name, value)| format!("{}:{}\n", name, value))
+			.collect();
+		let signed_headers = signed
+			.iter()
+			.map(|(name, _)| name.as_str())
+			.collect::<Vec<_>>()
+			.join(";");
+		let canonical_request = format!(
+			"{}\n{}\n{}\n{}\n{}\n{}",
+			method, path, query, canonical_headers, signed_headers, payload_hash
+		);
+		let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+		let string_to_sign = format!(
+			"AWS4-HMAC-SHA256\n{}\n{}\n{}",
+			timestamp,
+			scope,
+			hex::encode(Sha256::digest(canonical_request.as_bytes()))
+		);
+		let mut signing_key = hmac(
+			format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+			&date,
+		);
+		for part in [self.region.as_str(), "s3", "aws4_request"] {
+			signing_key = hmac(&signing_key, part);
+		}
+		let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+		let mut authorization = HeaderValue::from_str(&format!(
+			"AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+			credentials.access_key_id, scope, signed_headers, signature
+		))
+		.unwrap();
+		authorization.set_sensitive(true);
+		headers.insert(AUTHORIZATION, authorization);
+
+		let response = self
+			.client
+			.request(method, url)
+			.headers(headers)
+			.body(body)
+			.send_retrying()?;
+		check(response)
+	}
+
+	fn head_object(&self, key: &str) -> Result<(u64, u64), RemoteError> {
+		let response = self.send(Method::HEAD, key, &[], HeaderMap::new(), Vec::new())?;
+		let header = |name| {
+			response
+				.headers()
+				.get(name)
+				.and_then(|value: &HeaderValue| value.to_str().ok())
+		};
+		let size = header(CONTENT_LENGTH)
+			.and_then(|value| value.parse().ok())
+			.unwrap_or(0);
+		let modified = header(LAST_MODIFIED)
+			.and_then(|value| httpdate::parse_http_date(value).ok())
+			.and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+			.map_or(0, |since| since.as_secs());
+		Ok((size, modified))
+	}
+
+	fn get_object(&self, key: &str) -> Result<Vec<u8>, RemoteError> {
+		let response = self.send(Method::GET, key, &[], HeaderMap::new(), Vec::new())?;
+		Ok(response.bytes()?.to_vec())
+	}
+
+	fn put_object(&self, key: &str, data: &[u8]) -> Result<(), RemoteError> {
+		if data.len() > MULTIPART_THRESHOLD {
+			return self.put_multipart(key, data);
+		}
+		self.send(Method::PUT, key, &[], HeaderMap::new(), data.to_vec())?;
+		Ok(())
+	}
+
+	fn delete_object(&self, key: &str) -> Result<(), RemoteError> {
+		self.send(Method::DELETE, key, &[], HeaderMap::new(), Vec::new())?;
+		Ok(())
+	}
+
+	// 分段上传或复制：parts 个分段依次由 part 上传并返回 ETag，失败时放弃整个上传，已上传的分段不会留在桶中
+	fn multipart(
+		&self,
+		key: &str,
+		parts: usize,
+		mut part: impl FnMut(&str, usize) -> Result<String, RemoteError>,
+	) -> Result<(), RemoteError> {
+		let body = check_body(self.send(
+			Method::POST,
+			key,
+			&[("uploads", "")],
+			HeaderMap::new(),
+			Vec::new(),
+		)?)?;
+		let doc = parse_xml(&body)?;
+		let upload_id = child_text(doc.root_element(), "UploadId")
+			.ok_or_else(|| {
+				RemoteError::backend("invalid_response", "S3 did not return an upload ID")
+			})?
+			.to_string();
+
+		let result = (1..=parts)
+			.map(|number| part(&upload_id, number))
+			.collect::<Result<Vec<_>, _>>()
+			.and_then(|etags| {
+				let mut complete = String::from("<CompleteMultipartUpload>");
+				for (index, etag) in etags.iter().enumerate() {
+					complete.push_str(&format!(
+						"<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+						index + 1,
+						etag
+					));
+				}
+				complete.push_str("</CompleteMultipartUpload>");
+				check_body(self.send(
+					Method::POST,
+					key,
+					&[("uploadId", &upload_id)],
+					HeaderMap::new(),
+					complete.into_bytes(),
+				)?)
+				.map(drop)
+			});
+		if result.is_err() {
+			let _ = self.send(
+				Method::DELETE,
+				key,
+				&[("uploadId", &upload_id)],
+				HeaderMap::new(),
+				Vec::new(),
+			);
+		}
+		result
+	}
+
+	fn put_multipart(&self, key: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let chunks: Vec<&[u8]> = data.chunks(PART_SIZE).collect();
+		self.multipart(key, chunks.len(), |upload_id, number| {
+			let part_number = number.to_string();
+			let mut attempt = 0;
+			loop {
+				let query = [
+					("partNumber", part_number.as_str()),
+					("uploadId", upload_id),
+				];
+				match self.send(
+					Method::PUT,
+					key,
+					&query,
+					HeaderMap::new(),
+					chunks[number - 1].to_vec(),
+				) {
+					Ok(response) => {
+						let etag = response
+							.headers()
+							.get("etag")
+							.and_then(|value| value.to_str().ok());
+						return etag.map(str::to_string).ok_or_else(|| {
+							RemoteError::backend(
+								"invalid_response",
+								"S3 did not return a part ETag",
+							)
+						});
+					}
+					Err(e) if attempt < PART_RETRIES => {
+						error!(key = %key, part = number, error = %e, "put_multipart: part failed");
+						attempt += 1;
+					}
+					Err(e) => return Err(e),
+				}
+			}
+		})
+	}
+
+	// 服务器端复制对象，不经过客户端传输内容
+	fn copy_object(&self, source: &str, target: &str, size: u64) -> Result<(), RemoteError> {
+		let copy_source =
+			HeaderValue::from_str(&format!("/{}/{}", self.bucket, uri_encode(source, true)))
+				.unwrap();
+		let mut headers = HeaderMap::new();
+		headers.insert("x-amz-copy-source", copy_source);
+		if size <= MAX_COPY_SIZE {
+			check_body(self.send(Method::PUT, target, &[], headers, Vec::new())?)?;
+			return Ok(());
+		}
+		let parts = size.div_ceil(COPY_PART_SIZE) as usize;
+		self.multipart(target, parts, |upload_id, number| {
+			let start = (number as u64 - 1) * COPY_PART_SIZE;
+			let end = (start + COPY_PART_SIZE).min(size) - 1;
+			let mut headers = headers.clone();
+			headers.insert(
+				"x-amz-copy-source-range",
+				HeaderValue::from_str(&format!("bytes={}-{}", start, end)).unwrap(),
+			);
+			let part_number = number.to_string();
+			let query = [
+				("partNumber", part_number.as_str()),
+				("uploadId", upload_id),
+			];
+			let body = check_body(self.send(Method::PUT, target, &query, headers, Vec::new())?)?;
+			let doc = parse_xml(&body)?;
+			child_text(doc.root_element(), "ETag")
+				.map(str::to_string)
+				.ok_or_else(|| {
+					RemoteError::backend("invalid_response", "S3 did not return a part ETag")
+				})
+		})
+	}
+
+	// 列出 prefix 下的对象；delimiter 为 true 时只列出一层，更深的键合并为公共前缀
+	fn list_objects(
+		&self,
+		prefix: &str,
+		delimiter: bool,
+		max_keys: usize,
+		token: Option<&str>,
+	) -> Result<ObjectPage, RemoteError> {
+		let max_keys = max_keys.to_string();
+		let mut query = vec![
+			("list-type", "2"),
+			("prefix", prefix),
+			("max-keys", max_keys.as_str()),
+		];
+		if delimiter {
+			query.push(("delimiter", "/"));
+		}
+		if let Some(token) = token {
+			query.push(("continuation-token", token));
+		}
+		let body = check_body(self.send(Method::GET, "", &query, HeaderMap::new(), Vec::new())?)?;
+		let doc = parse_xml(&body)?;
+		let root = doc.root_element();
+		let mut page = ObjectPage {
+			objects: Vec::new(),
+			prefixes: Vec::new(),
+			next_token: None,
+		};
+		for node in root.children().filter(|node| node.is_element()) {
+			if node.has_tag_name("Contents") {
+				page.objects.push(Object {
+					key: child_text(node, "Key").unwrap_or_default().to_string(),
+					size: child_text(node, "Size")
+						.and_then(|size| size.parse().ok())
+						.unwrap_or(0),
+					modified: child_text(node, "LastModified")
+						.and_then(parse_timestamp)
+						.unwrap_or(0),
+				});
+			} else if node.has_tag_name("CommonPrefixes") {
+				page.prefixes
+					.extend(child_text(node, "Prefix").map(str::to_string));
+			}
+		}
+		if child_text(root, "IsTruncated") == Some("true") {
+			page.next_token = child_text(root, "NextContinuationToken").map(str::to_string);
+		}
+		Ok(page)
+	}
+
+	// 目录下所有层级的对象，包括目录本身的空对象
+	fn list_all(&self, prefix: &str) -> Result<Vec<Object>, RemoteError> {
+		let mut objects = Vec::new();
+		let mut token = None;
+		loop {
+			let page = self.list_objects(prefix, false, LIST_PAGE_SIZE, token.as_deref())?;
+			objects.extend(page.objects);
+			token = match page.next_token {
+				Some(next) => Some(next),
+				None => return Ok(objects),
+			};
+		}
+	}
+
+	// 读出整个对象，修改后重新上传
+	fn rewrite(&self, path: &str, edit: impl FnOnce(&mut Vec<u8>)) -> Result<(), RemoteError> {
+		let key = self.key(path);
+		let mut data = self.get_object(&key)?;
+		edit(&mut data);
+		self.put_object(&key, &data)
+	}
+}
+
+impl StorageBackend for S3Backend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		if path == "." {
+			return Ok(directory_info(".", 0));
+		}
+		match self.head_object(&self.key(path)) {
+			Ok((size, modified)) => return Ok(file_info(base_name(path), size, modified)),
+			Err(e) if e.code() == Some("not_found") => {}
+			Err(e) => return Err(e),
+		}
+		// 目录存在时它的空对象（如果有）排在前缀下的第一个；只是公共前缀的目录没有时间戳
+		let prefix = self.dir_prefix(path);
+		let page = self.list_objects(&prefix, true, 1, None)?;
+		if page.objects.is_empty() && page.prefixes.is_empty() {
+			return Err(RemoteError::backend(
+				"not_found",
+				format!("{} does not exist", path),
+			));
+		}
+		let modified = page
+			.objects
+			.first()
+			.filter(|object| object.key == prefix)
+			.map_or(0, |object| object.modified);
+		Ok(directory_info(base_name(path), modified))
+	}
+
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let prefix = self.dir_prefix(path);
+		let page = self.list_objects(&prefix, true, LIST_PAGE_SIZE, cursor)?;
+		let mut items = Vec::new();
+		for common in &page.prefixes {
+			let name = common[prefix.len()..].trim_end_matches('/');
+			if !name.is_empty() {
+				items.push(directory_info(name, 0));
+			}
+		}
+		for object in &page.objects {
+			if object.key != prefix {
+				items.push(file_info(
+					&object.key[prefix.len()..],
+					object.size,
+					object.modified,
+				));
+			}
+		}
+		Ok(ListPage {
+			items,
+			next_cursor: page.next_token,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		if length == 0 {
+			return Ok(Vec::new());
+		}
+		let mut headers = HeaderMap::new();
+		let range = format!("bytes={}-{}", offset, offset + length as u64 - 1);
+		headers.insert(RANGE, HeaderValue::from_str(&range).unwrap());
+		match self.send(Method::GET, &self.key(path), &[], headers, Vec::new()) {
+			Ok(response) => Ok(response.bytes()?.to_vec()),
+			// 从文件末尾之后开始读取
+			Err(e) if e.code() == Some("invalid_range") => Ok(Vec::new()),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.rewrite(path, |content| {
+			let start = offset as usize;
+			let end = start + data.len();
+			if content.len() < end {
+				content.resize(end, 0);
+			}
+			content[start..end].copy_from_slice(data);
+		})
+	}
+
+	// 单个 PUT 或分段上传在完成前对其他客户端都不可见
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.put_object(&self.key(path), data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let key = if is_directory {
+			self.dir_prefix(path)
+		} else {
+			self.key(path)
+		};
+		self.put_object(&key, &[])
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let key = self.key(path);
+		match self.head_object(&key) {
+			Ok(_) if dry_run => return Ok(()),
+			Ok(_) => return self.delete_object(&key),
+			Err(e) if e.code() == Some("not_found") => {}
+			Err(e) => return Err(e),
+		}
+		// 目录中只剩自己的空对象时才能删除
+		let prefix = self.dir_prefix(path);
+		let page = self.list_objects(&prefix, false, 2, None)?;
+		if page.objects.is_empty() {
+			return Err(RemoteError::backend(
+				"not_found",
+				format!("{} does not exist", path),
+			));
+		}
+		if page.objects.iter().any(|object| object.key != prefix) {
+			return Err(RemoteError::backend(
+				"directory_not_empty",
+				format!("{} is not empty", path),
+			));
+		}
+		if dry_run {
+			return Ok(());
+		}
+		self.delete_object(&prefix)
+	}
+
+	// 先复制再删除原对象，重命名目录时逐个处理其中的对象，中途失败时新旧位置各有一部分
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let source = self.stat(old_path)?;
+		match self.stat(new_path) {
+			Ok(_) if !replace => {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", new_path),
+				))
+			}
+			Ok(target) if target.is_directory != source.is_directory => {
+				return Err(RemoteError::backend(
+					"type_mismatch",
+					format!("{} is of a different type", new_path),
+				));
+			}
+			Ok(target) if target.is_directory => self.delete(new_path, false)?,
+			Ok(_) => {}
+			Err(e) if e.code() == Some("not_found") => {}
+			Err(e) => return Err(e),
+		}
+
+		if !source.is_directory {
+			let (old_key, new_key) = (self.key(old_path), self.key(new_path));
+			self.copy_object(&old_key, &new_key, source.size)?;
+			return self.delete_object(&old_key);
+		}
+		if new_path.starts_with(&format!("{}/", old_path)) {
+			return Err(RemoteError::backend(
+				"move_into_self",
+				format!("cannot move {} into itself", old_path),
+			));
+		}
+		let (old_prefix, new_prefix) = (self.dir_prefix(old_path), self.dir_prefix(new_path));
+		for object in self.list_all(&old_prefix)? {
+			let new_key = format!("{}{}", new_prefix, &object.key[old_prefix.len()..]);
+			self.copy_object(&object.key, &new_key, object.size)?;
+			self.delete_object(&object.key)?;
+		}
+		Ok(())
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.rewrite(path, |content| content.resize(size as usize, 0))
+	}
+
+	// 对象的修改时间由存储在上传时设置
+	fn set_times(&self, _path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Ok(())
+	}
+}
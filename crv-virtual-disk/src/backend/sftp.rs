@@ -0,0 +1,668 @@
+use std::{
+	env,
+	error::Error,
+	io::{self, IsTerminal, Read, Seek, SeekFrom, Write},
+	net::{TcpStream, ToSocketAddrs},
+	path::{Path, PathBuf},
+	sync::Mutex,
+	time::Duration,
+};
+
+use percent_encoding::percent_decode_str;
+use reqwest::Url;
+use ssh2::{
+	CheckResult, ErrorCode, FileStat, HashType, KeyboardInteractivePrompt, KnownHostFileKind,
+	OpenFlags, OpenType, Prompt, Session, Sftp,
+};
+
+use super::{base_name, read_console, StorageBackend};
+use crate::{
+	backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate,
+};
+
+const DEFAULT_PORT: u16 = 22;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(15);
+// 单个 SSH 操作的超时（毫秒）
+const SESSION_TIMEOUT_MS: u32 = 30_000;
+
+// 未指定 --ssh-key 时依次尝试的私钥文件（~/.ssh 下）
+const DEFAULT_KEYS: [&str; 3] = ["id_ed25519", "id_ecdsa", "id_rsa"];
+
+// SFTP 状态码（draft-ietf-secsh-filexfer）；OpenSSH 使用协议版本 3，多数失败只返回 FAILURE
+const FX_NO_SUCH_FILE: i32 = 2;
+const FX_PERMISSION_DENIED: i32 = 3;
+const FX_OP_UNSUPPORTED: i32 = 8;
+const FX_NO_SUCH_PATH: i32 = 10;
+const FX_FILE_ALREADY_EXISTS: i32 = 11;
+const FX_WRITE_PROTECT: i32 = 12;
+const FX_NO_SPACE_ON_FILESYSTEM: i32 = 14;
+const FX_QUOTA_EXCEEDED: i32 = 15;
+const FX_LOCK_CONFLICT: i32 = 17;
+const FX_DIR_NOT_EMPTY: i32 = 18;
+const FX_NOT_A_DIRECTORY: i32 = 19;
+const FX_INVALID_FILENAME: i32 = 20;
+
+// 表示连接已不可用的 libssh2 错误，遇到时重新连接
+const SSH_ERROR_SOCKET_SEND: i32 = -7;
+const SSH_ERROR_TIMEOUT: i32 = -9;
+const SSH_ERROR_SOCKET_DISCONNECT: i32 = -13;
+const SSH_ERROR_CHANNEL_CLOSED: i32 = -26;
+const SSH_ERROR_SOCKET_RECV: i32 = -43;
+
+fn sftp_error(e: ssh2::Error, path: &str) -> RemoteError {
+	let code = match e.code() {
+		ErrorCode::SFTP(FX_NO_SUCH_FILE | FX_NO_SUCH_PATH) => "not_found",
+		ErrorCode::SFTP(FX_PERMISSION_DENIED | FX_WRITE_PROTECT) => "permission_denied",
+		ErrorCode::SFTP(FX_OP_UNSUPPORTED) => "not_supported",
+		ErrorCode::SFTP(FX_FILE_ALREADY_EXISTS) => "already_exists",
+		ErrorCode::SFTP(FX_NO_SPACE_ON_FILESYSTEM | FX_QUOTA_EXCEEDED) => "disk_full",
+		ErrorCode::SFTP(FX_LOCK_CONFLICT) => "locked",
+		ErrorCode::SFTP(FX_DIR_NOT_EMPTY) => "directory_not_empty",
+		ErrorCode::SFTP(FX_NOT_A_DIRECTORY) => "not_a_directory",
+		ErrorCode::SFTP(FX_INVALID_FILENAME) => "invalid_name",
+		ErrorCode::Session(
+			SSH_ERROR_SOCKET_SEND
+			| SSH_ERROR_TIMEOUT
+			| SSH_ERROR_SOCKET_DISCONNECT
+			| SSH_ERROR_CHANNEL_CLOSED
+			| SSH_ERROR_SOCKET_RECV,
+		) => "connection_lost",
+		_ => "sftp_error",
+	};
+	RemoteError::backend(code, format!("{}: {}", path, e.message()))
+}
+
+// 读写打开的文件时 ssh2 只给出 io::Error，丢失了 SFTP 状态码
+fn io_error(e: io::Error, path: &str) -> RemoteError {
+	let code = match e.kind() {
+		io::ErrorKind::NotFound => "not_found",
+		io::ErrorKind::TimedOut => "connection_lost",
+		_ => "sftp_error",
+	};
+	RemoteError::backend(code, format!("{}: {}", path, e))
+}
+
+fn stat_info(name: &str, stat: &FileStat) -> RemoteFileInfo {
+	let modified = stat.mtime.unwrap_or(0);
+	RemoteFileInfo {
+		name: name.to_string(),
+		is_directory: stat.is_dir(),
+		size: if stat.is_dir() {
+			0
+		} else {
+			stat.size.unwrap_or(0)
+		},
+		created: modified,
+		modified,
+		accessed: stat.atime.unwrap_or(modified),
+		stored_size: None,
+		allocated_size: None,
+	}
+}
+
+fn ssh_dir() -> Option<PathBuf> {
+	let home = env::var_os("USERPROFILE").or_else(|| env::var_os("HOME"))?;
+	Some(PathBuf::from(home).join(".ssh"))
+}
+
+// OpenSSH 显示的主机密钥指纹：SHA256: 加上不带填充的 base64
+fn fingerprint(hash: &[u8]) -> String {
+	const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+	let mut encoded = String::from("SHA256:");
+	for chunk in hash.chunks(3) {
+		let bits = chunk.iter().enumerate().fold(0u32, |bits, (i, &byte)| {
+			bits | ((byte as u32) << (16 - 8 * i))
+		});
+		for i in 0..=chunk.len() {
+			encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+		}
+	}
+	encoded
+}
+
+// 键盘交互认证：不回显的提示先用 URL 中的密码回答，其余在控制台询问；
+// 回答保存在 answers 中，重新连接时不再询问
+struct ConsolePrompt<'a> {
+	password: Option<&'a str>,
+	answers: &'a mut Vec<String>,
+}
+
+impl KeyboardInteractivePrompt for ConsolePrompt<'_> {
+	fn prompt<'b>(
+		&mut self,
+		_username: &str,
+		instructions: &str,
+		prompts: &[Prompt<'b>],
+	) -> Vec<String> {
+		if self.answers.len() == prompts.len() {
+			return self.answers.clone();
+		}
+		if !instructions.is_empty() && io::stdin().is_terminal() {
+			eprintln!("{}", instructions);
+		}
+		let answers: Vec<String> = prompts
+			.iter()
+			.map(|prompt| match (self.password, prompt.echo) {
+				(Some(password), false) => password.to_string(),
+				_ => read_console(&prompt.text, prompt.echo).unwrap_or_default(),
+			})
+			.collect();
+		self.answers.clone_from(&answers);
+		answers
+	}
+}
+
+// SSH 服务器的地址和认证方式
+struct SshTarget {
+	host: String,
+	port: u16,
+	user: String,
+	password: Option<String>,
+	key: Option<PathBuf>,
+	host_key: Option<String>,
+	answers: Mutex<Vec<String>>,
+}
+
+impl SshTarget {
+	fn connect(&self) -> Result<Sftp, String> {
+		let addrs = (self.host.as_str(), self.port)
+			.to_socket_addrs()
+			.map_err(|e| format!("cannot resolve {}: {}", self.host, e))?;
+		let mut last_error = format!("{} has no address", self.host);
+		let stream = addrs
+			.into_iter()
+			.find_map(
+				|addr| match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+					Ok(stream) => Some(stream),
+					Err(e) => {
+						last_error = format!("cannot connect to {}: {}", addr, e);
+						None
+					}
+				},
+			)
+			.ok_or(last_error)?;
+
+		let mut session = Session::new().map_err(|e| e.to_string())?;
+		session.set_tcp_stream(stream);
+		session.set_timeout(SESSION_TIMEOUT_MS);
+		session
+			.handshake()
+			.map_err(|e| format!("SSH handshake with {} failed: {}", self.host, e))?;
+		self.verify_host_key(&session)?;
+		self.authenticate(&session)?;
+		session
+			.sftp()
+			.map_err(|e| format!("{} does not provide SFTP: {}", self.host, e))
+	}
+
+	// 指定 --ssh-host-key 时比较指纹，否则按 ~/.ssh/known_hosts 校验；未知的主机拒绝连接
+	fn verify_host_key(&self, session: &Session) -> Result<(), String> {
+		let (key, _) = session.host_key().ok_or("the server sent no host key")?;
+		let fingerprint = fingerprint(session.host_key_hash(HashType::Sha256).unwrap_or_default());
+		if let Some(expected) = &self.host_key {
+			return if *expected == fingerprint {
+				Ok(())
+			} else {
+				Err(format!(
+					"the host key of {} is {}, not {}",
+					self.host, fingerprint, expected
+				))
+			};
+		}
+		let mut known_hosts = session.known_hosts().map_err(|e| e.to_string())?;
+		if let Some(file) = ssh_dir().map(|dir| dir.join("known_hosts")) {
+			let _ = known_hosts.read_file(&file, KnownHostFileKind::OpenSSH);
+		}
+		match known_hosts.check_port(&self.host, self.port, key) {
+			CheckResult::Match => Ok(()),
+			CheckResult::Mismatch => Err(format!(
+				"the host key of {} ({}) does not match known_hosts; the server may have been replaced",
+				self.host, fingerprint
+			)),
+			CheckResult::NotFound | CheckResult::Failure => Err(format!(
+				"{} is not in known_hosts; connect once with ssh, or pass --ssh-host-key {} after checking the fingerprint",
+				self.host, fingerprint
+			)),
+		}
+	}
+
+	// 依次尝试 ssh-agent、私钥文件、密码和键盘交互，跳过服务器不接受的方式
+	fn authenticate(&self, session: &Session) -> Result<(), String> {
+		let methods = session
+			.auth_methods(&self.user)
+			.map_err(|e| e.to_string())?
+			.to_string();
+		if session.authenticated() {
+			return Ok(());
+		}
+		if methods.contains("publickey") {
+			if self.key.is_none() && session.userauth_agent(&self.user).is_ok() {
+				return Ok(());
+			}
+			let passphrase = env::var("HTTPFS_SSH_PASSPHRASE").ok();
+			for key in self.key_files() {
+				let public_key = key.with_extension("pub");
+				let public_key = public_key.exists().then_some(public_key.as_path());
+				if session
+					.userauth_pubkey_file(&self.user, public_key, &key, passphrase.as_deref())
+					.is_ok()
+				{
+					return Ok(());
+				}
+			}
+		}
+		if let Some(password) = &self.password {
+			if methods.contains("password")
+				&& session.userauth_password(&self.user, password).is_ok()
+			{
+				return Ok(());
+			}
+		}
+		if methods.contains("keyboard-interactive") {
+			let mut answers = self.answers.lock().unwrap();
+			let mut prompt = ConsolePrompt {
+				password: self.password.as_deref(),
+				answers: &mut answers,
+			};
+			if session
+				.userauth_keyboard_interactive(&self.user, &mut prompt)
+				.is_ok()
+			{
+				return Ok(());
+			}
+			answers.clear();
+		}
+		Err(format!(
+			"authentication as {} on {} failed (server accepts: {})",
+			self.user, self.host, methods
+		))
+	}
+
+	fn key_files(&self) -> Vec<PathBuf> {
+		match &self.key {
+			Some(key) => vec![key.clone()],
+			None => ssh_dir()
+				.map(|dir| {
+					DEFAULT_KEYS
+						.iter()
+						.map(|name| dir.join(name))
+						.filter(|key| key.exists())
+						.collect()
+				})
+				.unwrap_or_default(),
+		}
+	}
+}
+
+// 通过 SFTP 访问 SSH 服务器上的一个目录，服务器上不需要运行 httpfs。
+// 所有操作共用一个 SSH 连接，连接断开时重新连接并重试一次。
+// OpenSSH 的 SFTP 重命名不能覆盖已有文件，replace 时先删除目标，不是原子操作；
+// 保存文件时先写入同目录下的临时文件再重命名
+pub struct SftpBackend {
+	target: SshTarget,
+	// 共享根目录在服务器上的绝对路径
+	root: String,
+	connection: Mutex<Option<Sftp>>,
+}
+
+impl SftpBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to sftp:// URLs; put the directory in the URL instead".into());
+		}
+		let url = Url::parse(&remote.server_url)
+			.map_err(|e| format!("invalid SFTP URL {}: {}", remote.server_url, e))?;
+		let host = url
+			.host_str()
+			.ok_or_else(|| format!("{} has no host", remote.server_url))?;
+		let decode = |text: &str| percent_decode_str(text).decode_utf8_lossy().into_owned();
+		let user = match url.username() {
+			"" => env::var("USERNAME").or_else(|_| env::var("USER"))?,
+			user => decode(user),
+		};
+		let target = SshTarget {
+			host: host.trim_matches(['[', ']']).to_string(),
+			port: url.port().unwrap_or(DEFAULT_PORT),
+			user,
+			password: url.password().map(decode),
+			key: remote.ssh_key.as_ref().map(PathBuf::from),
+			host_key: remote.ssh_host_key.clone(),
+			answers: Mutex::new(Vec::new()),
+		};
+
+		// 路径以 /~ 开头或为空时相对于用户的主目录
+		let sftp = target.connect()?;
+		let path = decode(url.path());
+		let path = path.trim_end_matches('/');
+		let root = match path
+			.strip_prefix("/~")
+			.filter(|rest| rest.is_empty() || rest.starts_with('/'))
+		{
+			Some(rest) => format!(
+				"{}{}",
+				sftp.realpath(Path::new("."))?
+					.to_string_lossy()
+					.trim_end_matches('/'),
+				rest
+			),
+			None if path.is_empty() => sftp
+				.realpath(Path::new("."))?
+				.to_string_lossy()
+				.trim_end_matches('/')
+				.to_string(),
+			None => path.to_string(),
+		};
+		if !sftp
+			.stat(Path::new(if root.is_empty() { "/" } else { &root }))?
+			.is_dir()
+		{
+			return Err(format!("{} is not a directory on {}", root, target.host).into());
+		}
+		Ok(Self {
+			target,
+			root,
+			connection: Mutex::new(Some(sftp)),
+		})
+	}
+
+	fn remote_path(&self, path: &str) -> PathBuf {
+		match path {
+			"." if self.root.is_empty() => PathBuf::from("/"),
+			"." => PathBuf::from(&self.root),
+			_ => PathBuf::from(format!("{}/{}", self.root, path)),
+		}
+	}
+
+	// 在当前连接上执行操作；连接已断开时重新连接后重试一次
+	fn with<T>(&self, op: impl Fn(&Sftp) -> Result<T, RemoteError>) -> Result<T, RemoteError> {
+		let mut connection = self.connection.lock().unwrap();
+		let mut retried = false;
+		loop {
+			if connection.is_none() {
+				let sftp = self
+					.target
+					.connect()
+					.map_err(|e| RemoteError::backend("connection_lost", e))?;
+				*connection = Some(sftp);
+			}
+			match op(connection.as_ref().unwrap()) {
+				Err(e) if e.code() == Some("connection_lost") && !retried => {
+					*connection = None;
+					retried = true;
+				}
+				result => return result,
+			}
+		}
+	}
+
+	// 符号链接按其目标显示，目标不存在时跳过
+	fn resolve(&self, sftp: &Sftp, path: &Path, stat: FileStat) -> Option<FileStat> {
+		if stat.file_type().is_symlink() {
+			sftp.stat(path).ok()
+		} else {
+			Some(stat)
+		}
+	}
+
+	fn remove(&self, sftp: &Sftp, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let remote = self.remote_path(path);
+		let result = if is_directory {
+			sftp.rmdir(&remote)
+		} else {
+			sftp.unlink(&remote)
+		};
+		result.map_err(|e| sftp_error(e, path))
+	}
+}
+
+impl StorageBackend for SftpBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		self.with(|sftp| {
+			let stat = sftp
+				.stat(&self.remote_path(path))
+				.map_err(|e| sftp_error(e, path))?;
+			Ok(stat_info(base_name(path), &stat))
+		})
+	}
+
+	// SFTP 的 readdir 一次返回整个目录
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		self.with(|sftp| {
+			let entries = sftp
+				.readdir(self.remote_path(path))
+				.map_err(|e| sftp_error(e, path))?;
+			let items = entries
+				.into_iter()
+				.filter_map(|(entry, stat)| {
+					let stat = self.resolve(sftp, &entry, stat)?;
+					let name = entry.file_name()?.to_string_lossy().into_owned();
+					(stat.is_dir() || stat.is_file()).then(|| stat_info(&name, &stat))
+				})
+				.collect();
+			Ok(ListPage {
+				items,
+				next_cursor: None,
+			})
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.with(|sftp| {
+			let mut file = sftp
+				.open(self.remote_path(path))
+				.map_err(|e| sftp_error(e, path))?;
+			file.seek(SeekFrom::Start(offset))
+				.map_err(|e| io_error(e, path))?;
+			let mut data = Vec::with_capacity(length);
+			file.take(length as u64)
+				.read_to_end(&mut data)
+				.map_err(|e| io_error(e, path))?;
+			Ok(data)
+		})
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.with(|sftp| {
+			let mut file = sftp
+				.open_mode(
+					self.remote_path(path),
+					OpenFlags::WRITE,
+					0o644,
+					OpenType::File,
+				)
+				.map_err(|e| sftp_error(e, path))?;
+			file.seek(SeekFrom::Start(offset))
+				.map_err(|e| io_error(e, path))?;
+			file.write_all(data).map_err(|e| io_error(e, path))
+		})
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		let temp = match path.rsplit_once('/') {
+			Some((parent, name)) => format!("{}/.{}.httpfs-tmp", parent, name),
+			None => format!(".{}.httpfs-tmp", path),
+		};
+		self.with(|sftp| {
+			let mut file = sftp
+				.open_mode(
+					self.remote_path(&temp),
+					OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::TRUNCATE,
+					0o644,
+					OpenType::File,
+				)
+				.map_err(|e| sftp_error(e, path))?;
+			let result = file
+				.write_all(data)
+				.map_err(|e| io_error(e, path))
+				.and_then(|()| {
+					drop(file);
+					if sftp.stat(&self.remote_path(path)).is_ok() {
+						self.remove(sftp, path, false)?;
+					}
+					sftp.rename(&self.remote_path(&temp), &self.remote_path(path), None)
+						.map_err(|e| sftp_error(e, path))
+				});
+			if result.is_err() {
+				let _ = sftp.unlink(&self.remote_path(&temp));
+			}
+			result
+		})
+	}
+
+	// OpenSSH 对已存在的条目和缺少的父目录都只返回 FAILURE 或 NO_SUCH_FILE，失败后查询确定原因
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.with(|sftp| {
+			let remote = self.remote_path(path);
+			let result = if is_directory {
+				sftp.mkdir(&remote, 0o755)
+			} else {
+				sftp.open_mode(
+					&remote,
+					OpenFlags::WRITE | OpenFlags::CREATE | OpenFlags::EXCLUSIVE,
+					0o644,
+					OpenType::File,
+				)
+				.map(drop)
+			};
+			let Err(e) = result else {
+				return Ok(());
+			};
+			if sftp.lstat(&remote).is_ok() {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", path),
+				));
+			}
+			let parent = path.rsplit_once('/').map_or(".", |(parent, _)| parent);
+			if sftp.stat(&self.remote_path(parent)).is_err() {
+				return Err(RemoteError::backend(
+					"parent_not_found",
+					format!("the parent of {} does not exist", path),
+				));
+			}
+			Err(sftp_error(e, path))
+		})
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		self.with(|sftp| {
+			let remote = self.remote_path(path);
+			let is_directory = sftp
+				.lstat(&remote)
+				.map_err(|e| sftp_error(e, path))?
+				.is_dir();
+			if is_directory
+				&& !sftp
+					.readdir(&remote)
+					.map_err(|e| sftp_error(e, path))?
+					.is_empty()
+			{
+				return Err(RemoteError::backend(
+					"directory_not_empty",
+					format!("{} is not empty", path),
+				));
+			}
+			if dry_run {
+				return Ok(());
+			}
+			self.remove(sftp, path, is_directory)
+		})
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.with(|sftp| {
+			let source = sftp
+				.lstat(&self.remote_path(old_path))
+				.map_err(|e| sftp_error(e, old_path))?;
+			if source.is_dir() && new_path.starts_with(&format!("{}/", old_path)) {
+				return Err(RemoteError::backend(
+					"move_into_self",
+					format!("cannot move {} into itself", old_path),
+				));
+			}
+			if let Ok(target) = sftp.lstat(&self.remote_path(new_path)) {
+				if !replace {
+					return Err(RemoteError::backend(
+						"already_exists",
+						format!("{} already exists", new_path),
+					));
+				}
+				if target.is_dir() != source.is_dir() {
+					return Err(RemoteError::backend(
+						"type_mismatch",
+						format!("{} is of a different type", new_path),
+					));
+				}
+				if target.is_dir()
+					&& !sftp
+						.readdir(self.remote_path(new_path))
+						.map_err(|e| sftp_error(e, new_path))?
+						.is_empty()
+				{
+					return Err(RemoteError::backend(
+						"directory_not_empty",
+						format!("{} is not empty", new_path),
+					));
+				}
+				self.remove(sftp, new_path, target.is_dir())?;
+			}
+			sftp.rename(
+				&self.remote_path(old_path),
+				&self.remote_path(new_path),
+				None,
+			)
+			.map_err(|e| sftp_error(e, old_path))
+		})
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let stat = FileStat {
+			size: Some(size),
+			uid: None,
+			gid: None,
+			perm: None,
+			atime: None,
+			mtime: None,
+		};
+		self.with(|sftp| {
+			sftp.setstat(&self.remote_path(path), stat.clone())
+				.map_err(|e| sftp_error(e, path))
+		})
+	}
+
+	// SFTP 只能同时设置访问和修改时间，缺少的一个保持原值；创建时间不能设置
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		if times.accessed.is_none() && times.modified.is_none() {
+			return Ok(());
+		}
+		self.with(|sftp| {
+			let remote = self.remote_path(path);
+			let current = sftp.stat(&remote).map_err(|e| sftp_error(e, path))?;
+			let stat = FileStat {
+				size: None,
+				uid: None,
+				gid: None,
+				perm: None,
+				atime: times.accessed.or(current.atime),
+				mtime: times.modified.or(current.mtime),
+			};
+			sftp.setstat(&remote, stat).map_err(|e| sftp_error(e, path))
+		})
+	}
+
+	// 需要服务器支持 statvfs@openssh.com 扩展
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.with(|sftp| {
+			let mut dir = sftp
+				.opendir(self.remote_path("."))
+				.map_err(|e| sftp_error(e, "."))?;
+			let vfs = dir
+				.statvfs()
+				.map_err(|_| RemoteError::unsupported("space"))?;
+			Ok(SpaceResponse {
+				total: vfs.f_blocks * vfs.f_frsize,
+				available: vfs.f_bavail * vfs.f_frsize,
+			})
+		})
+	}
+}
@@ -0,0 +1,5184 @@
+use std::{
+	collections::HashMap,
+	fs,
+	io::{Read, Write},
+	net::{TcpListener, TcpStream},
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicUsize, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+};
+
+use sha2::{Digest, Sha256};
+
+use super::{
+	compressed::CompressedBackend,
+	dedup::{ChunkStore, DedupBackend},
+	disk::DiskBackend,
+	encrypted::{EncryptedBackend, KeySource},
+	escaped::EscapedBackend,
+	git::GitBackend,
+	iso::IsoBackend,
+	local::LocalBackend,
+	memory::MemoryBackend,
+	overlay::OverlayBackend,
+	zip::ZipBackend,
+	StorageBackend,
+};
+use crate::{
+	conformance::{self, TemporaryMount},
+	TimesUpdate,
+};
+
+// 测试用的临时目录，离开作用域时删除
+struct TempDir(PathBuf);
+
+impl TempDir {
+	fn new() -> Self {
+		static COUNTER: AtomicUsize = AtomicUsize::new(0);
+		let dir = std::env::temp_dir().join(format!(
+			"httpfs-backend-test-{}-{}",
+			std::process::id(),
+			COUNTER.fetch_add(1, Ordering::SeqCst)
+		));
+		fs::create_dir_all(&dir).unwrap();
+		Self(dir)
+	}
+}
+
+impl Drop for TempDir {
+	fn drop(&mut self) {
+		let _ = fs::remove_dir_all(&self.0);
+	}
+}
+
+fn error_code<T: std::fmt::Debug>(result: Result<T, crate::error::RemoteError>) -> String {
+	result
+		.unwrap_err()
+		.code()
+		.unwrap_or("transport")
+		.to_string()
+}
+
+fn names(backend: &dyn StorageBackend, path: &str) -> Vec<String> {
+	let mut names: Vec<String> = backend
+		.list_page(path, None)
+		.unwrap()
+		.items
+		.into_iter()
+		.map(|item| item.name)
+		.collect();
+	names.sort();
+	names
+}
+
+// StorageBackend 的一致性检查：每个可写后端都应通过，失败时的错误类别与 httpfs 服务器相同。
+// 后端必须以空的根目录开始
+fn check_conformance(backend: &dyn StorageBackend) {
+	// 创建和查询
+	backend.create("docs", true).unwrap();
+	backend.create("docs/a.txt", false).unwrap();
+	assert_eq!(error_code(backend.create("docs", true)), "already_exists");
+	assert_eq!(
+		error_code(backend.create("docs/a.txt", false)),
+		"already_exists"
+	);
+	assert_eq!(
+		error_code(backend.create("missing/b.txt", false)),
+		"parent_not_found"
+	);
+	assert_eq!(error_code(backend.stat("nothing")), "not_found");
+	let info = backend.stat("docs").unwrap();
+	assert!(info.is_directory);
+	assert_eq!(info.name, "docs");
+	let info = backend.stat("docs/a.txt").unwrap();
+	assert!(!info.is_directory);
+	assert_eq!((info.name.as_str(), info.size), ("a.txt", 0));
+	assert!(backend.stat(".").unwrap().is_directory);
+	assert_eq!(names(backend, "."), ["docs"]);
+	assert_eq!(names(backend, "docs"), ["a.txt"]);
+
+	// 读写和调整大小
+	backend.write("docs/a.txt", 0, b"hello world").unwrap();
+	backend.write("docs/a.txt", 6, b"there").unwrap();
+	assert_eq!(backend.read("docs/a.txt", 0, 100).unwrap(), b"hello there");
+	assert_eq!(backend.read("docs/a.txt", 6, 3).unwrap(), b"the");
+	assert_eq!(backend.read("docs/a.txt", 50, 10).unwrap(), b"");
+	let mut buffer = [0xff; 8];
+	assert_eq!(backend.read_into("docs/a.txt", 6, &mut buffer).unwrap(), 5);
+	assert_eq!(&buffer[..5], b"there");
+	assert_eq!(backend.read_into("docs/a.txt", 50, &mut buffer).unwrap(), 0);
+	backend.write("docs/a.txt", 13, b"!").unwrap();
+	assert_eq!(
+		backend.read("docs/a.txt", 0, 100).unwrap(),
+		b"hello there\0\0!"
+	);
+	backend.truncate("docs/a.txt", 5).unwrap();
+	assert_eq!(backend.read("docs/a.txt", 0, 100).unwrap(), b"hello");
+	backend.truncate("docs/a.txt", 7).unwrap();
+	assert_eq!(backend.stat("docs/a.txt").unwrap().size, 7);
+	backend.zero_range("docs/a.txt", 1, 2).unwrap();
+	backend.zero_range("docs/a.txt", 6, 4).unwrap();
+	assert_eq!(
+		backend.read("docs/a.txt", 0, 100).unwrap(),
+		b"h\0\0lo\0\0\0\0\0"
+	);
+	assert_eq!(error_code(backend.read("docs/none.txt", 0, 1)), "not_found");
+
+	// 提交整体替换内容，也可以创建新文件
+	backend.commit("docs/a.txt", b"replaced").unwrap();
+	assert_eq!(backend.read("docs/a.txt", 0, 100).unwrap(), b"replaced");
+	backend.commit("docs/new.txt", b"new").unwrap();
+	assert_eq!(names(backend, "docs"), ["a.txt", "new.txt"]);
+	assert_eq!(
+		backend.checksum("docs/a.txt").unwrap().digest,
+		hex::encode(Sha256::digest(b"replaced"))
+	);
+
+	// 不能保存时间戳的后端忽略设置
+	let times = TimesUpdate {
+		modified: Some(1_600_000_000),
+		..Default::default()
+	};
+	backend.set_times("docs/a.txt", &times).unwrap();
+
+	// 删除只删除空目录
+	assert_eq!(
+		error_code(backend.delete("docs", false)),
+		"directory_not_empty"
+	);
+	assert_eq!(
+		error_code(backend.delete("docs", true)),
+		"directory_not_empty"
+	);
+	backend.delete("docs/new.txt", true).unwrap();
+	assert!(backend.stat("docs/new.txt").is_ok());
+	backend.delete("docs/new.txt", false).unwrap();
+	assert_eq!(error_code(backend.stat("docs/new.txt")), "not_found");
+	assert_eq!(
+		error_code(backend.delete("docs/new.txt", false)),
+		"not_found"
+	);
+
+	// 重命名
+	backend.create("docs/b.txt", false).unwrap();
+	assert_eq!(
+		error_code(backend.rename("docs/a.txt", "docs/b.txt", false)),
+		"already_exists"
+	);
+	backend.rename("docs/a.txt", "docs/b.txt", true).unwrap();
+	assert_eq!(backend.read("docs/b.txt", 0, 100).unwrap(), b"replaced");
+	assert_eq!(names(backend, "docs"), ["b.txt"]);
+	backend.create("other", true).unwrap();
+	assert_eq!(
+		error_code(backend.rename("docs/b.txt", "other", true)),
+		"type_mismatch"
+	);
+	assert_eq!(
+		error_code(backend.rename("docs", "docs/inner", false)),
+		"move_into_self"
+	);
+	backend.create("other/x.txt", false).unwrap();
+	assert_eq!(
+		error_code(backend.rename("docs", "other", true)),
+		"directory_not_empty"
+	);
+	backend.delete("other/x.txt", false).unwrap();
+	backend.rename("docs", "other", true).unwrap();
+	assert_eq!(names(backend, "."), ["other"]);
+	backend.rename("other", "moved", false).unwrap();
+	assert_eq!(names(backend, "."), ["moved"]);
+	assert_eq!(backend.read("moved/b.txt", 0, 100).unwrap(), b"replaced");
+	assert_eq!(
+		error_code(backend.rename("nothing", "else", false)),
+		"not_found"
+	);
+}
+
+#[test]
+fn local_backend_conforms() {
+	let dir = TempDir::new();
+	let backend = LocalBackend::open(&dir.0).unwrap();
+	check_conformance(&backend);
+	assert!(dir.0.join("moved").join("b.txt").is_file());
+
+	let times = TimesUpdate {
+		accessed: Some(1_500_000_000),
+		modified: Some(1_600_000_000),
+		..Default::default()
+	};
+	backend.set_times("moved/b.txt", &times).unwrap();
+	let info = backend.stat("moved/b.txt").unwrap();
+	assert_eq!(
+		(info.accessed, info.modified),
+		(1_500_000_000, 1_600_000_000)
+	);
+}
+
+// 经 Dokan 挂载后以 Win32 文件 API 检查，需要安装 Dokan 驱动
+#[test]
+#[ignore]
+fn local_backend_passes_win32_conformance() {
+	let dir = TempDir::new();
+	let mount =
+		TemporaryMount::new(super::Remote::new(format!("file:///{}", dir.0.display()))).unwrap();
+	let failures = conformance::run(mount.root());
+	assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+#[ignore]
+fn memory_backend_passes_win32_conformance() {
+	let mount = TemporaryMount::new(super::Remote::new("mem://")).unwrap();
+	let failures = conformance::run(mount.root());
+	assert!(failures.is_empty(), "{:?}", failures);
+}
+
+#[test]
+fn local_backend_stays_inside_root() {
+	let dir = TempDir::new();
+	fs::create_dir(dir.0.join("root")).unwrap();
+	fs::write(dir.0.join("secret.txt"), b"secret").unwrap();
+	let backend = LocalBackend::open(&dir.0.join("root")).unwrap();
+	assert_eq!(
+		error_code(backend.read("../secret.txt", 0, 10)),
+		"invalid_name"
+	);
+	assert_eq!(error_code(backend.stat("/etc")), "invalid_name");
+}
+
+#[test]
+fn memory_backend_conforms() {
+	let backend = MemoryBackend::with_capacity(None);
+	check_conformance(&backend);
+
+	let times = TimesUpdate {
+		created: Some(1_400_000_000),
+		accessed: Some(1_500_000_000),
+		modified: Some(1_600_000_000),
+	};
+	backend.set_times("moved/b.txt", &times).unwrap();
+	let info = backend.stat("moved/b.txt").unwrap();
+	assert_eq!(
+		(info.created, info.accessed, info.modified),
+		(1_400_000_000, 1_500_000_000, 1_600_000_000)
+	);
+	backend
+		.put_xattr("moved/b.txt", "user.tag", b"blue")
+		.unwrap();
+	assert_eq!(
+		backend
+			.get_xattr("moved/b.txt", "user.tag")
+			.unwrap()
+			.as_deref(),
+		Some(&b"blue"[..])
+	);
+	assert_eq!(backend.list_xattrs("moved/b.txt").unwrap().len(), 1);
+	backend.delete_xattr("moved/b.txt", "user.tag").unwrap();
+	assert_eq!(
+		error_code(backend.delete_xattr("moved/b.txt", "user.tag")),
+		"xattr_not_found"
+	);
+}
+
+#[test]
+fn memory_backend_enforces_capacity() {
+	let backend = MemoryBackend::with_capacity(Some(10));
+	backend.commit("a.bin", &[1; 6]).unwrap();
+	backend.create("b.bin", false).unwrap();
+	assert_eq!(error_code(backend.write("b.bin", 0, &[2; 5])), "disk_full");
+	assert_eq!(backend.stat("b.bin").unwrap().size, 0);
+	backend.write("b.bin", 0, &[2; 4]).unwrap();
+	let space = backend.space().unwrap();
+	assert_eq!((space.total, space.available), (10, 0));
+	assert_eq!(error_code(backend.truncate("a.bin", 7)), "disk_full");
+
+	// 缩小和删除释放容量
+	backend.truncate("a.bin", 2).unwrap();
+	assert_eq!(backend.space().unwrap().available, 4);
+	backend.delete("b.bin", false).unwrap();
+	backend.commit("a.bin", &[3; 10]).unwrap();
+	assert_eq!(backend.space().unwrap().available, 0);
+	backend.rename("a.bin", "c.bin", false).unwrap();
+	assert_eq!(backend.read("c.bin", 8, 10).unwrap(), [3, 3]);
+}
+
+#[test]
+fn memory_backend_spills_to_disk() {
+	const BLOCK: usize = 64 * 1024;
+	let backend = MemoryBackend::with_capacity(None).with_memory_limit(Some(2 * BLOCK as u64));
+	backend.create("disk.img", false).unwrap();
+	let mut expected = noise(7, 8 * BLOCK);
+	backend.write("disk.img", 0, &expected).unwrap();
+	assert_eq!(backend.read("disk.img", 0, 8 * BLOCK).unwrap(), expected);
+	// 第一块已经移到临时文件中
+	backend.write("disk.img", 100, b"changed").unwrap();
+	expected[100..107].copy_from_slice(b"changed");
+	assert_eq!(backend.read("disk.img", 0, 8 * BLOCK).unwrap(), expected);
+
+	// 没有写入过的部分不占空间
+	backend.truncate("disk.img", 100 * BLOCK as u64).unwrap();
+	let info = backend.stat("disk.img").unwrap();
+	assert_eq!(
+		(info.size, info.allocated_size),
+		(100 * BLOCK as u64, Some(8 * BLOCK as u64))
+	);
+	assert_eq!(
+		backend.read("disk.img", 50 * BLOCK as u64, 10).unwrap(),
+		[0; 10]
+	);
+	backend
+		.zero_range("disk.img", 2 * BLOCK as u64, 4 * BLOCK as u64)
+		.unwrap();
+	backend.zero_range("disk.img", 10, 20).unwrap();
+	expected[2 * BLOCK..6 * BLOCK].fill(0);
+	expected[10..30].fill(0);
+	assert_eq!(
+		backend.stat("disk.img").unwrap().allocated_size,
+		Some(4 * BLOCK as u64)
+	);
+	assert_eq!(backend.read("disk.img", 0, 8 * BLOCK).unwrap(), expected);
+
+	// 缩短后再增长的部分为 0
+	backend.truncate("disk.img", BLOCK as u64 + 5).unwrap();
+	backend.truncate("disk.img", 2 * BLOCK as u64).unwrap();
+	let tail = backend.read("disk.img", BLOCK as u64, BLOCK).unwrap();
+	assert_eq!(tail[..5], expected[BLOCK..BLOCK + 5]);
+	assert!(tail[5..].iter().all(|&b| b == 0));
+	backend.commit("copy.img", &expected).unwrap();
+	backend.delete("disk.img", false).unwrap();
+	assert_eq!(backend.read("copy.img", 0, 8 * BLOCK).unwrap(), expected);
+	assert_eq!(
+		backend.stat("copy.img").unwrap().allocated_size,
+		Some(4 * BLOCK as u64)
+	);
+
+	// 容量只计算已分配的块
+	let thin = MemoryBackend::with_capacity(Some(2 * BLOCK as u64)).with_memory_limit(Some(0));
+	thin.create("huge.img", false).unwrap();
+	thin.truncate("huge.img", 1 << 40).unwrap();
+	let data = noise(9, BLOCK);
+	thin.write("huge.img", 1 << 30, &data).unwrap();
+	thin.write("huge.img", 1 << 35, &data).unwrap();
+	assert_eq!(error_code(thin.write("huge.img", 0, b"x")), "disk_full");
+	thin.write("huge.img", 1 << 20, &[0; 100]).unwrap();
+	assert_eq!(thin.read("huge.img", 1 << 30, BLOCK).unwrap(), data);
+	assert_eq!(thin.read("huge.img", 1 << 35, BLOCK).unwrap(), data);
+}
+
+#[test]
+fn overlay_backend_conforms() {
+	let backend = OverlayBackend::new(
+		Box::new(MemoryBackend::with_capacity(None)),
+		vec![Box::new(MemoryBackend::with_capacity(None))],
+	);
+	check_conformance(&backend);
+}
+
+#[test]
+fn overlay_backend_keeps_lower_layers_unchanged() {
+	let dir = TempDir::new();
+	fs::create_dir_all(dir.0.join("lower/docs/deep")).unwrap();
+	fs::write(dir.0.join("lower/docs/a.txt"), b"lower a").unwrap();
+	fs::write(dir.0.join("lower/docs/deep/b.txt"), b"lower b").unwrap();
+	fs::write(dir.0.join("lower/shared.txt"), b"top").unwrap();
+	fs::create_dir(dir.0.join("bottom")).unwrap();
+	fs::write(dir.0.join("bottom/shared.txt"), b"bottom").unwrap();
+	fs::write(dir.0.join("bottom/only.txt"), b"only").unwrap();
+	let lowers: Vec<Box<dyn StorageBackend>> = vec![
+		Box::new(LocalBackend::open(&dir.0.join("lower")).unwrap()),
+		Box::new(LocalBackend::open(&dir.0.join("bottom")).unwrap()),
+	];
+	let backend = OverlayBackend::new(Box::new(MemoryBackend::with_capacity(None)), lowers);
+
+	// 合并的目录内容，靠上的层优先
+	assert_eq!(names(&backend, "."), ["docs", "only.txt", "shared.txt"]);
+	assert_eq!(backend.read("shared.txt", 0, 100).unwrap(), b"top");
+
+	// 写入前复制到上层
+	backend.write("docs/a.txt", 0, b"upper").unwrap();
+	assert_eq!(backend.read("docs/a.txt", 0, 100).unwrap(), b"upper a");
+	backend.truncate("docs/deep/b.txt", 2).unwrap();
+	assert_eq!(backend.read("docs/deep/b.txt", 0, 100).unwrap(), b"lo");
+
+	// 删除下层中的条目留下删除标记，标记不出现在列表中也不能直接访问
+	backend.delete("only.txt", false).unwrap();
+	assert_eq!(error_code(backend.stat("only.txt")), "not_found");
+	assert_eq!(names(&backend, "."), ["docs", "shared.txt"]);
+	assert_eq!(error_code(backend.stat(".wh.only.txt")), "not_found");
+	assert_eq!(error_code(backend.create(".wh.x", false)), "invalid_name");
+	backend.commit("only.txt", b"again").unwrap();
+	assert_eq!(backend.read("only.txt", 0, 100).unwrap(), b"again");
+
+	// 删除后重新创建的目录不显示下层中原来的内容
+	backend.delete("docs/deep/b.txt", false).unwrap();
+	backend.delete("docs/deep", false).unwrap();
+	backend.create("docs/deep", true).unwrap();
+	assert!(names(&backend, "docs/deep").is_empty());
+	assert_eq!(names(&backend, "docs"), ["a.txt", "deep"]);
+
+	// 包含下层内容的目录改名时整个复制到新位置
+	backend.rename("docs", "moved", false).unwrap();
+	assert_eq!(names(&backend, "."), ["moved", "only.txt", "shared.txt"]);
+	assert_eq!(backend.read("moved/a.txt", 0, 100).unwrap(), b"upper a");
+	assert_eq!(error_code(backend.stat("docs/a.txt")), "not_found");
+	backend.create("docs", true).unwrap();
+	assert!(names(&backend, "docs").is_empty());
+
+	assert_eq!(
+		fs::read(dir.0.join("lower/docs/a.txt")).unwrap(),
+		b"lower a"
+	);
+	assert_eq!(
+		fs::read(dir.0.join("lower/docs/deep/b.txt")).unwrap(),
+		b"lower b"
+	);
+	assert_eq!(fs::read(dir.0.join("bottom/only.txt")).unwrap(), b"only");
+}
+
+// 手工构造的 ZIP 文件：成员为 (名称, 压缩方法, 内容)，CRC 不参与读取，填 0
+fn build_zip(members: &[(&str, u16, &[u8])]) -> Vec<u8> {
+	let mut archive = Vec::new();
+	let mut directory = Vec::new();
+	for &(name, method, content) in members {
+		let data = match method {
+			8 => {
+				let mut encoder =
+					flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+				encoder.write_all(content).unwrap();
+				encoder.finish().unwrap()
+			}
+			_ => content.to_vec(),
+		};
+		// 版本、标志、方法、时间（2024-01-02 03:04:06）、CRC、压缩和原始大小、名称和扩展字段长度
+		let mut fields = Vec::new();
+		fields.extend_from_slice(&20u16.to_le_bytes());
+		fields.extend_from_slice(&0x0800u16.to_le_bytes());
+		fields.extend_from_slice(&method.to_le_bytes());
+		fields.extend_from_slice(&(3u16 << 11 | 4 << 5 | 3).to_le_bytes());
+		fields.extend_from_slice(&(44u16 << 9 | 1 << 5 | 2).to_le_bytes());
+		fields.extend_from_slice(&0u32.to_le_bytes());
+		fields.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		fields.extend_from_slice(&(content.len() as u32).to_le_bytes());
+		fields.extend_from_slice(&(name.len() as u16).to_le_bytes());
+		fields.extend_from_slice(&0u16.to_le_bytes());
+
+		let offset = archive.len() as u32;
+		archive.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+		archive.extend_from_slice(&fields);
+		archive.extend_from_slice(name.as_bytes());
+		archive.extend_from_slice(&data);
+
+		directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+		directory.extend_from_slice(&20u16.to_le_bytes());
+		directory.extend_from_slice(&fields);
+		// 注释长度、磁盘号和文件属性
+		directory.extend_from_slice(&[0; 10]);
+		directory.extend_from_slice(&offset.to_le_bytes());
+		directory.extend_from_slice(name.as_bytes());
+	}
+	let offset = archive.len() as u32;
+	archive.extend_from_slice(&directory);
+	archive.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+	archive.extend_from_slice(&[0; 4]);
+	archive.extend_from_slice(&(members.len() as u16).to_le_bytes());
+	archive.extend_from_slice(&(members.len() as u16).to_le_bytes());
+	archive.extend_from_slice(&(directory.len() as u32).to_le_bytes());
+	archive.extend_from_slice(&offset.to_le_bytes());
+	archive.extend_from_slice(&0u16.to_le_bytes());
+	archive
+}
+
+#[test]
+fn compressed_backend_conforms() {
+	let backend = CompressedBackend::new(Box::new(MemoryBackend::with_capacity(None)), &[]);
+	check_conformance(&backend);
+}
+
+#[test]
+fn compressed_backend_stores_chunks() {
+	let dir = TempDir::new();
+	let backend = CompressedBackend::new(
+		Box::new(LocalBackend::open(&dir.0).unwrap()),
+		&["raw".to_string()],
+	);
+	let text: Vec<u8> = b"all work and no play makes jack a dull boy\n"
+		.iter()
+		.copied()
+		.cycle()
+		.take(300_000)
+		.collect();
+
+	// 提交的文件整体压缩，大小为原始长度
+	backend.commit("a.txt", &text).unwrap();
+	let info = backend.stat("a.txt").unwrap();
+	assert_eq!(info.size, text.len() as u64);
+	let stored = fs::metadata(dir.0.join("a.txt")).unwrap().len();
+	assert_eq!(info.stored_size, Some(stored));
+	assert!(stored < text.len() as u64 / 10);
+	assert_eq!(backend.read("a.txt", 0, 400_000).unwrap(), text);
+	assert_eq!(
+		backend.list_page(".", None).unwrap().items[0].size,
+		text.len() as u64
+	);
+
+	// 跨越块的写入、截断和加长
+	let mut expected = text.clone();
+	backend.write("a.txt", 65_000, &[b'x'; 2000]).unwrap();
+	expected[65_000..67_000].fill(b'x');
+	backend.write("a.txt", 310_000, b"end").unwrap();
+	expected.resize(310_000, 0);
+	expected.extend(b"end");
+	assert_eq!(backend.read("a.txt", 0, 400_000).unwrap(), expected);
+	assert_eq!(
+		backend.read("a.txt", 66_000, 10).unwrap(),
+		&expected[66_000..66_010]
+	);
+	backend.truncate("a.txt", 100_000).unwrap();
+	expected.truncate(100_000);
+	backend.truncate("a.txt", 200_000).unwrap();
+	expected.resize(200_000, 0);
+	assert_eq!(backend.read("a.txt", 0, 400_000).unwrap(), expected);
+
+	// 反复改写的块留下的空洞会被回收（不能压缩的内容按原样保存）
+	let mut seed = 1u32;
+	for round in 0..100u64 {
+		let noise: Vec<u8> = (0..65_536)
+			.map(|_| {
+				seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+				(seed >> 16) as u8
+			})
+			.collect();
+		backend.write("a.txt", round % 3 * 65_536, &noise).unwrap();
+		expected[(round % 3 * 65_536) as usize..(round % 3 * 65_536 + 65_536) as usize]
+			.copy_from_slice(&noise);
+	}
+	assert!(fs::metadata(dir.0.join("a.txt")).unwrap().len() < 2 * 1024 * 1024);
+	assert_eq!(backend.read("a.txt", 0, 400_000).unwrap(), expected);
+
+	// 从空文件开始写入的文件同样压缩，不压缩的扩展名和已有的文件保持原样
+	backend.create("b.log", false).unwrap();
+	backend.write("b.log", 0, &text).unwrap();
+	assert!(backend.stat("b.log").unwrap().stored_size.is_some());
+	backend.commit("c.raw", &text).unwrap();
+	backend.commit("d.zip", &text).unwrap();
+	assert_eq!(fs::read(dir.0.join("c.raw")).unwrap(), text);
+	assert_eq!(fs::read(dir.0.join("d.zip")).unwrap(), text);
+	assert_eq!(backend.stat("c.raw").unwrap().stored_size, None);
+	fs::write(dir.0.join("plain.txt"), b"written elsewhere").unwrap();
+	assert_eq!(
+		backend.read("plain.txt", 0, 100).unwrap(),
+		b"written elsewhere"
+	);
+	backend.write("plain.txt", 8, b"ELSEWHERE").unwrap();
+	assert_eq!(
+		fs::read(dir.0.join("plain.txt")).unwrap(),
+		b"written ELSEWHERE"
+	);
+}
+
+// 不能压缩的伪随机数据
+fn noise(mut seed: u32, length: usize) -> Vec<u8> {
+	(0..length)
+		.map(|_| {
+			seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+			(seed >> 16) as u8
+		})
+		.collect()
+}
+
+// 代替服务器 /chunks 接口的内存块存储，记录上传的次数
+#[derive(Default)]
+struct MemoryChunks {
+	chunks: Mutex<HashMap<String, Vec<u8>>>,
+	uploads: AtomicUsize,
+}
+
+impl ChunkStore for Arc<MemoryChunks> {
+	fn put_chunk(&self, hash: &str, data: &[u8]) -> Result<(), crate::error::RemoteError> {
+		self.uploads.fetch_add(1, Ordering::SeqCst);
+		self.chunks
+			.lock()
+			.unwrap()
+			.insert(hash.to_string(), data.to_vec());
+		Ok(())
+	}
+
+	fn get_chunk(&self, hash: &str) -> Result<Option<Vec<u8>>, crate::error::RemoteError> {
+		Ok(self.chunks.lock().unwrap().get(hash).cloned())
+	}
+
+	fn missing_chunks(&self, hashes: &[String]) -> Result<Vec<String>, crate::error::RemoteError> {
+		let chunks = self.chunks.lock().unwrap();
+		Ok(hashes
+			.iter()
+			.filter(|hash| !chunks.contains_key(*hash))
+			.cloned()
+			.collect())
+	}
+}
+
+#[test]
+fn dedup_backend_conforms() {
+	let backend = DedupBackend::new(Box::new(MemoryBackend::with_capacity(None)), None);
+	check_conformance(&backend);
+}
+
+#[test]
+fn dedup_backend_shares_chunks() {
+	let dir = TempDir::new();
+	let store = Arc::new(MemoryChunks::default());
+	let backend = DedupBackend::new(
+		Box::new(LocalBackend::open(&dir.0).unwrap()),
+		Some(Box::new(store.clone())),
+	);
+	let uploads = || store.uploads.load(Ordering::SeqCst);
+	let data = noise(1, 1_000_000);
+
+	// 后端中只保存清单，相同的内容不再上传
+	backend.commit("a.bin", &data).unwrap();
+	let chunks = uploads();
+	assert!((4..=60).contains(&chunks), "{} chunks", chunks);
+	backend.commit("b.bin", &data).unwrap();
+	assert_eq!(uploads(), chunks);
+	assert!(fs::metadata(dir.0.join("b.bin")).unwrap().len() < 10_000);
+	assert_eq!(backend.stat("b.bin").unwrap().size, data.len() as u64);
+	assert_eq!(
+		backend.list_page(".", None).unwrap().items[0].size,
+		data.len() as u64
+	);
+	assert_eq!(backend.read("b.bin", 0, 2_000_000).unwrap(), data);
+	assert_eq!(
+		backend.read("b.bin", 123_456, 100_000).unwrap(),
+		&data[123_456..223_456]
+	);
+
+	// 插入数据后只有附近的块改变
+	let mut inserted = data[..500_000].to_vec();
+	inserted.extend(b"inserted");
+	inserted.extend(&data[500_000..]);
+	backend.commit("c.bin", &inserted).unwrap();
+	assert!(uploads() - chunks <= 3);
+	assert_eq!(backend.read("c.bin", 0, 2_000_000).unwrap(), inserted);
+
+	// 依次追加写入与整体提交切出相同的块
+	backend.create("d.bin", false).unwrap();
+	for piece in data.chunks(100_000) {
+		let offset = backend.stat("d.bin").unwrap().size;
+		backend.write("d.bin", offset, piece).unwrap();
+	}
+	assert_eq!(
+		fs::read(dir.0.join("d.bin")).unwrap(),
+		fs::read(dir.0.join("b.bin")).unwrap()
+	);
+
+	// 改写、截断和加长，全零的部分不保存
+	let mut expected = data.clone();
+	backend.write("a.bin", 300_000, b"changed").unwrap();
+	expected[300_000..300_007].copy_from_slice(b"changed");
+	let before = uploads();
+	backend.truncate("a.bin", 10_000_000).unwrap();
+	expected.resize(10_000_000, 0);
+	backend.write("a.bin", 5_000_000, b"middle").unwrap();
+	expected[5_000_000..5_000_006].copy_from_slice(b"middle");
+	assert!(uploads() - before <= 2);
+	assert_eq!(backend.read("a.bin", 0, 20_000_000).unwrap(), expected);
+	backend.truncate("a.bin", 400_000).unwrap();
+	expected.truncate(400_000);
+	assert_eq!(backend.read("a.bin", 0, 20_000_000).unwrap(), expected);
+	assert_eq!(backend.read("b.bin", 0, 2_000_000).unwrap(), data);
+
+	// 损坏的块被发现，不是清单的文件按原样读写
+	for content in store.chunks.lock().unwrap().values_mut() {
+		content[0] ^= 1;
+	}
+	let backend = DedupBackend::new(
+		Box::new(LocalBackend::open(&dir.0).unwrap()),
+		Some(Box::new(store.clone())),
+	);
+	assert_eq!(
+		error_code(backend.read("b.bin", 0, 100)),
+		"checksum_mismatch"
+	);
+	fs::write(dir.0.join("plain.txt"), b"written elsewhere").unwrap();
+	backend.write("plain.txt", 8, b"ELSEWHERE").unwrap();
+	assert_eq!(
+		fs::read(dir.0.join("plain.txt")).unwrap(),
+		b"written ELSEWHERE"
+	);
+}
+
+#[test]
+fn dedup_backend_keeps_chunks_in_the_backend() {
+	let dir = TempDir::new();
+	let backend = DedupBackend::new(Box::new(LocalBackend::open(&dir.0).unwrap()), None);
+	let data = noise(2, 300_000);
+	backend.commit("a.bin", &data).unwrap();
+	backend.commit("b.bin", &data).unwrap();
+	assert_eq!(backend.read("b.bin", 0, 400_000).unwrap(), data);
+
+	// 两个文件共用同一组块，块目录不可见
+	let stored_bytes: u64 = fs::read_dir(dir.0.join(".httpfs-chunkstore"))
+		.unwrap()
+		.flat_map(|prefix| fs::read_dir(prefix.unwrap().path()).unwrap())
+		.map(|chunk| chunk.unwrap().metadata().unwrap().len())
+		.sum();
+	assert_eq!(stored_bytes, data.len() as u64);
+	assert_eq!(names(&backend, "."), ["a.bin", "b.bin"]);
+	assert_eq!(
+		error_code(backend.stat(".httpfs-chunkstore")),
+		"invalid_name"
+	);
+	assert_eq!(
+		error_code(backend.create(".httpfs-chunkstore/x", false)),
+		"invalid_name"
+	);
+}
+
+// 密钥文件在 dir 中，测试存储在 dir/store 中
+fn encrypted_local(
+	dir: &TempDir,
+	encrypt_names: bool,
+) -> Result<EncryptedBackend, Box<dyn std::error::Error>> {
+	let key_path = dir.0.join("key");
+	if !key_path.exists() {
+		fs::write(&key_path, [7; 32]).unwrap();
+		fs::create_dir(dir.0.join("store")).unwrap();
+	}
+	EncryptedBackend::open(
+		Box::new(LocalBackend::open(&dir.0.join("store")).unwrap()),
+		&KeySource::KeyFile(key_path),
+		encrypt_names,
+	)
+}
+
+// 存储中的全部内容，包括文件名
+fn stored_bytes(path: &std::path::Path) -> Vec<u8> {
+	let mut bytes = Vec::new();
+	for entry in fs::read_dir(path).unwrap() {
+		let entry = entry.unwrap();
+		bytes.extend(entry.file_name().to_string_lossy().as_bytes());
+		if entry.file_type().unwrap().is_dir() {
+			bytes.extend(stored_bytes(&entry.path()));
+		} else {
+			bytes.extend(fs::read(entry.path()).unwrap());
+		}
+	}
+	bytes
+}
+
+#[test]
+fn encrypted_backend_conforms() {
+	let dir = TempDir::new();
+	let backend = encrypted_local(&dir, false).unwrap();
+	check_conformance(&backend);
+	assert!(dir.0.join("store/.httpfs-encryption").is_file());
+	assert_eq!(
+		error_code(backend.stat(".httpfs-encryption")),
+		"invalid_name"
+	);
+
+	let backend = EncryptedBackend::open(
+		Box::new(MemoryBackend::with_capacity(None)),
+		&KeySource::KeyFile(dir.0.join("key")),
+		true,
+	)
+	.unwrap();
+	check_conformance(&backend);
+	backend
+		.put_xattr("moved/b.txt", "user.tag", b"blue")
+		.unwrap();
+	assert_eq!(
+		backend
+			.get_xattr("moved/b.txt", "user.tag")
+			.unwrap()
+			.as_deref(),
+		Some(&b"blue"[..])
+	);
+	assert_eq!(backend.list_xattrs("moved/b.txt").unwrap()[0].size, 4);
+}
+
+#[test]
+fn encrypted_backend_hides_content() {
+	let dir = TempDir::new();
+	let backend = encrypted_local(&dir, false).unwrap();
+
+	// 跨越多个块的写入、覆盖和截断，与明文的结果比较
+	let mut expected: Vec<u8> = (0..200_000u32).map(|i| (i % 251) as u8).collect();
+	backend.create("big.bin", false).unwrap();
+	backend.write("big.bin", 0, &expected).unwrap();
+	backend.write("big.bin", 65_000, &[0xaa; 3000]).unwrap();
+	expected[65_000..68_000].fill(0xaa);
+	backend.write("big.bin", 210_000, b"tail").unwrap();
+	expected.resize(210_000, 0);
+	expected.extend(b"tail");
+	assert_eq!(backend.stat("big.bin").unwrap().size, expected.len() as u64);
+	assert_eq!(backend.read("big.bin", 0, 300_000).unwrap(), expected);
+	assert_eq!(
+		backend.read("big.bin", 131_000, 1000).unwrap(),
+		&expected[131_000..132_000]
+	);
+	backend.truncate("big.bin", 70_000).unwrap();
+	expected.truncate(70_000);
+	assert_eq!(backend.read("big.bin", 0, 300_000).unwrap(), expected);
+	backend.truncate("big.bin", 140_000).unwrap();
+	expected.resize(140_000, 0);
+	assert_eq!(backend.read("big.bin", 0, 300_000).unwrap(), expected);
+
+	backend.commit("notes.txt", b"attack at dawn").unwrap();
+	let stored = stored_bytes(&dir.0.join("store"));
+	assert!(!stored.windows(6).any(|window| window == b"attack"));
+	assert!(!stored
+		.windows(6)
+		.any(|window| window == &expected[1000..1006]));
+
+	// 被改动的密文不能解密
+	let path = dir.0.join("store/notes.txt");
+	let mut data = fs::read(&path).unwrap();
+	let last = data.len() - 1;
+	data[last] ^= 1;
+	fs::write(&path, data).unwrap();
+	assert_eq!(
+		error_code(backend.read("notes.txt", 0, 100)),
+		"decryption_failed"
+	);
+}
+
+#[test]
+fn encrypted_backend_encrypts_names() {
+	let dir = TempDir::new();
+	let backend = encrypted_local(&dir, true).unwrap();
+	backend.create("Secret Plans", true).unwrap();
+	backend
+		.commit("Secret Plans/report.txt", b"report")
+		.unwrap();
+	assert_eq!(names(&backend, "."), ["Secret Plans"]);
+	assert_eq!(names(&backend, "Secret Plans"), ["report.txt"]);
+	assert_eq!(
+		backend.stat("Secret Plans/report.txt").unwrap().name,
+		"report.txt"
+	);
+	assert!(!stored_bytes(&dir.0.join("store"))
+		.windows(6)
+		.any(|window| window == b"Secret" || window == b"report"));
+	assert_eq!(
+		error_code(backend.create(&"x".repeat(200), false)),
+		"invalid_name"
+	);
+	assert_eq!(
+		error_code(backend.search(".", "*.txt", true)),
+		"not_supported"
+	);
+
+	// 其他程序放入的文件不能解密，不出现在列表中
+	fs::write(dir.0.join("store/plain.txt"), b"plain").unwrap();
+	assert_eq!(names(&backend, "."), ["Secret Plans"]);
+
+	// 是否加密文件名由首次加密决定
+	assert!(encrypted_local(&dir, false)
+		.unwrap()
+		.stat("Secret Plans")
+		.is_ok());
+	let plain_dir = TempDir::new();
+	encrypted_local(&plain_dir, false).unwrap();
+	assert!(encrypted_local(&plain_dir, true).is_err());
+}
+
+#[test]
+fn encrypted_backend_checks_the_key() {
+	let dir = TempDir::new();
+	let store = || Box::new(LocalBackend::open(&dir.0).unwrap());
+	let passphrase = |text: &str| KeySource::Passphrase(Some(text.to_string()));
+	let backend = EncryptedBackend::open(store(), &passphrase("correct horse"), false).unwrap();
+	backend.commit("a.txt", b"data").unwrap();
+	assert_eq!(
+		EncryptedBackend::open(store(), &passphrase("correct horse"), false)
+			.unwrap()
+			.read("a.txt", 0, 10)
+			.unwrap(),
+		b"data"
+	);
+	assert!(EncryptedBackend::open(store(), &passphrase("wrong horse"), false).is_err());
+	assert!(
+		EncryptedBackend::open(store(), &KeySource::KeyFile(dir.0.join("a.txt")), false).is_err()
+	);
+
+	// 不在已有明文文件的存储上开始加密
+	let plain = TempDir::new();
+	fs::write(plain.0.join("existing.txt"), b"plain").unwrap();
+	assert!(EncryptedBackend::open(
+		Box::new(LocalBackend::open(&plain.0).unwrap()),
+		&passphrase("pass"),
+		false
+	)
+	.is_err());
+}
+
+#[test]
+fn zip_backend_reads_members() {
+	let dir = TempDir::new();
+	// 超过一个缓存块且不重复的内容，用于检查跨块和倒序的读取
+	let large: Vec<u8> = (0..3_000_000u32)
+		.map(|i| (i.wrapping_mul(2_654_435_761) >> 24) as u8)
+		.collect();
+	let archive = build_zip(&[
+		("readme.txt", 0, b"stored content"),
+		("docs/", 0, b""),
+		("docs/notes.txt", 8, b"deflated notes"),
+		("data/deep/large.bin", 8, &large),
+	]);
+	fs::write(dir.0.join("test.zip"), archive).unwrap();
+	let backend = ZipBackend::open(&dir.0.join("test.zip")).unwrap();
+	assert!(backend.read_only());
+
+	assert_eq!(names(&backend, "."), ["data", "docs", "readme.txt"]);
+	assert_eq!(names(&backend, "data"), ["deep"]);
+	assert!(backend.stat("data/deep").unwrap().is_directory);
+	let info = backend.stat("data/deep/large.bin").unwrap();
+	assert_eq!(
+		(info.name.as_str(), info.size),
+		("large.bin", large.len() as u64)
+	);
+	assert_eq!(backend.stat("readme.txt").unwrap().modified, 1_704_164_646);
+	assert_eq!(error_code(backend.stat("missing.txt")), "not_found");
+	assert_eq!(
+		error_code(backend.list_page("readme.txt", None)),
+		"not_a_directory"
+	);
+
+	assert_eq!(backend.read("readme.txt", 7, 100).unwrap(), b"content");
+	assert_eq!(
+		backend.read("docs/notes.txt", 0, 100).unwrap(),
+		b"deflated notes"
+	);
+	assert_eq!(
+		backend.read("data/deep/large.bin", 2_500_000, 10).unwrap(),
+		&large[2_500_000..2_500_010]
+	);
+	assert_eq!(
+		backend
+			.read("data/deep/large.bin", 1_048_000, 2_000)
+			.unwrap(),
+		&large[1_048_000..1_050_000]
+	);
+	assert_eq!(
+		backend.read("data/deep/large.bin", 2_999_990, 100).unwrap(),
+		&large[2_999_990..]
+	);
+	assert_eq!(
+		backend.read("data/deep/large.bin", 3_000_000, 100).unwrap(),
+		b""
+	);
+	assert_eq!(
+		backend.checksum("data/deep/large.bin").unwrap().digest,
+		hex::encode(Sha256::digest(&large))
+	);
+
+	assert_eq!(
+		error_code(backend.write("readme.txt", 0, b"x")),
+		"read_only"
+	);
+	assert_eq!(error_code(backend.create("new.txt", false)), "read_only");
+	assert_eq!(error_code(backend.delete("readme.txt", false)), "read_only");
+	assert_eq!(
+		error_code(backend.rename("readme.txt", "other.txt", false)),
+		"read_only"
+	);
+}
+
+const SECTOR: usize = 2048;
+
+// 把 data 写入映像的第 sector 个扇区
+fn put_sector(image: &mut [u8], sector: usize, data: &[u8]) {
+	image[sector * SECTOR..sector * SECTOR + data.len()].copy_from_slice(data);
+}
+
+// ISO 9660 目录记录，时间为 2024-01-02 03:04:06 UTC
+fn iso_record(name: &[u8], lba: u32, size: u32, flags: u8, system_use: &[u8]) -> Vec<u8> {
+	let mut record = vec![0; 33];
+	record[2..6].copy_from_slice(&lba.to_le_bytes());
+	record[6..10].copy_from_slice(&lba.to_be_bytes());
+	record[10..14].copy_from_slice(&size.to_le_bytes());
+	record[14..18].copy_from_slice(&size.to_be_bytes());
+	record[18..25].copy_from_slice(&[124, 1, 2, 3, 4, 6, 0]);
+	record[25] = flags;
+	record[32] = name.len() as u8;
+	record.extend_from_slice(name);
+	if name.len().is_multiple_of(2) {
+		record.push(0);
+	}
+	record.extend_from_slice(system_use);
+	if record.len() % 2 == 1 {
+		record.push(0);
+	}
+	record[0] = record.len() as u8;
+	record
+}
+
+fn rock_ridge_name(name: &str) -> Vec<u8> {
+	[&[b'N', b'M', 5 + name.len() as u8, 1, 0], name.as_bytes()].concat()
+}
+
+#[test]
+fn iso_backend_reads_iso9660_images() {
+	let dir = TempDir::new();
+	let mut image = vec![0; 32 * SECTOR];
+	// 主卷描述符、El Torito 启动记录和结束符
+	let root = iso_record(&[0], 21, SECTOR as u32, 0x02, &[]);
+	let mut primary = vec![1];
+	primary.extend_from_slice(b"CD001\x01");
+	primary.resize(156, 0);
+	primary.extend_from_slice(&root);
+	put_sector(&mut image, 16, &primary);
+	let mut boot = vec![0];
+	boot.extend_from_slice(b"CD001\x01EL TORITO SPECIFICATION");
+	boot.resize(0x47, 0);
+	boot.extend_from_slice(&20u32.to_le_bytes());
+	put_sector(&mut image, 17, &boot);
+	put_sector(&mut image, 18, b"\xffCD001\x01");
+
+	// 启动目录：验证项和一个加载 4 个虚拟扇区的无模拟启动项
+	let mut catalog = vec![0; 64];
+	catalog[0] = 1;
+	catalog[30..32].copy_from_slice(&[0x55, 0xaa]);
+	catalog[32] = 0x88;
+	catalog[38..40].copy_from_slice(&4u16.to_le_bytes());
+	catalog[40..44].copy_from_slice(&30u32.to_le_bytes());
+	put_sector(&mut image, 20, &catalog);
+
+	// 根目录的 . 记录带有 SP 项；readme 的名称和修改时间（2020-06-15）来自 Rock Ridge，
+	// BIG.BIN 由两段组成，DOCS 中的文件只有 ISO 9660 名称
+	let timestamp = [b'T', b'F', 12, 1, 0x02, 120, 6, 15, 0, 0, 0, 0];
+	let records = [
+		iso_record(
+			&[0],
+			21,
+			SECTOR as u32,
+			0x02,
+			&[b'S', b'P', 7, 1, 0xbe, 0xef, 0],
+		),
+		iso_record(&[1], 21, SECTOR as u32, 0x02, &[]),
+		iso_record(b"BIG.BIN;1", 25, SECTOR as u32, 0x80, &[]),
+		iso_record(b"BIG.BIN;1", 27, 100, 0, &[]),
+		iso_record(b"DOCS", 22, SECTOR as u32, 0x02, &rock_ridge_name("Docs")),
+		iso_record(
+			b"README.TXT;1",
+			24,
+			12,
+			0,
+			&[rock_ridge_name("readme.txt"), timestamp.to_vec()].concat(),
+		),
+	]
+	.concat();
+	put_sector(&mut image, 21, &records);
+	let records = [
+		iso_record(&[0], 22, SECTOR as u32, 0x02, &[]),
+		iso_record(&[1], 21, SECTOR as u32, 0x02, &[]),
+		iso_record(b"NOTE.TXT;1", 28, 5, 0, &[]),
+	]
+	.concat();
+	put_sector(&mut image, 22, &records);
+
+	put_sector(&mut image, 24, b"read me now!");
+	let big: Vec<u8> = (0..SECTOR + 100).map(|i| (i % 251) as u8).collect();
+	put_sector(&mut image, 25, &big[..SECTOR]);
+	put_sector(&mut image, 27, &big[SECTOR..]);
+	put_sector(&mut image, 28, b"notes");
+	put_sector(&mut image, 30, &[0xb0; SECTOR]);
+	fs::write(dir.0.join("test.iso"), image).unwrap();
+
+	let backend = IsoBackend::open(&dir.0.join("test.iso")).unwrap();
+	assert!(backend.read_only());
+	assert_eq!(
+		names(&backend, "."),
+		["BIG.BIN", "Docs", "[BOOT]", "readme.txt"]
+	);
+	assert_eq!(names(&backend, "Docs"), ["NOTE.TXT"]);
+	assert_eq!(names(&backend, "[BOOT]"), ["Boot-x86-NoEmul.img"]);
+	let info = backend.stat("readme.txt").unwrap();
+	assert_eq!((info.size, info.modified), (12, 1_592_179_200));
+	assert_eq!(
+		backend.stat("Docs/NOTE.TXT").unwrap().modified,
+		1_704_164_646
+	);
+	assert_eq!(backend.read("readme.txt", 5, 100).unwrap(), b"me now!");
+	assert_eq!(backend.read("Docs/NOTE.TXT", 0, 100).unwrap(), b"notes");
+	assert_eq!(backend.stat("BIG.BIN").unwrap().size, big.len() as u64);
+	assert_eq!(backend.read("BIG.BIN", 2040, 20).unwrap(), &big[2040..2060]);
+	assert_eq!(backend.read("BIG.BIN", 0, 10_000).unwrap(), big);
+	assert_eq!(
+		backend
+			.read("[BOOT]/Boot-x86-NoEmul.img", 0, 10_000)
+			.unwrap(),
+		[0xb0; SECTOR]
+	);
+	assert_eq!(error_code(backend.stat("Docs/missing")), "not_found");
+	assert_eq!(error_code(backend.commit("readme.txt", b"x")), "read_only");
+	assert_eq!(error_code(backend.create("Docs/new", true)), "read_only");
+}
+
+// UDF 描述符标签，其余字段由调用方填写
+fn udf_tag(identifier: u16) -> Vec<u8> {
+	let mut block = vec![0; SECTOR];
+	block[0..2].copy_from_slice(&identifier.to_le_bytes());
+	block
+}
+
+// 文件项（tag 261）或扩展文件项（tag 266），修改时间为 2024-01-02 03:04:06 UTC
+fn udf_entry(tag: u16, file_type: u8, allocation: u16, size: u64, descriptors: &[u8]) -> Vec<u8> {
+	let mut block = udf_tag(tag);
+	let (modified_at, lengths_at) = if tag == 266 { (92, 208) } else { (84, 168) };
+	block[27] = file_type;
+	block[34..36].copy_from_slice(&allocation.to_le_bytes());
+	block[56..64].copy_from_slice(&size.to_le_bytes());
+	block[modified_at..modified_at + 2].copy_from_slice(&0x1000u16.to_le_bytes());
+	block[modified_at + 2..modified_at + 4].copy_from_slice(&2024u16.to_le_bytes());
+	block[modified_at + 4..modified_at + 9].copy_from_slice(&[1, 2, 3, 4, 6]);
+	block[lengths_at + 4..lengths_at + 8]
+		.copy_from_slice(&(descriptors.len() as u32).to_le_bytes());
+	block[lengths_at + 8..lengths_at + 8 + descriptors.len()].copy_from_slice(descriptors);
+	block
+}
+
+// 文件标识描述符，名称为空时是上级目录
+fn udf_identifier(name: &str, characteristics: u8, block: u32) -> Vec<u8> {
+	let mut identifier = udf_tag(257);
+	identifier.truncate(38);
+	let name: Vec<u8> = if name.is_empty() {
+		Vec::new()
+	} else {
+		[&[8], name.as_bytes()].concat()
+	};
+	identifier[18] = characteristics;
+	identifier[19] = name.len() as u8;
+	identifier[20..24].copy_from_slice(&(SECTOR as u32).to_le_bytes());
+	identifier[24..28].copy_from_slice(&block.to_le_bytes());
+	identifier.extend_from_slice(&name);
+	identifier.resize((identifier.len() + 3) & !3, 0);
+	identifier
+}
+
+#[test]
+fn iso_backend_reads_udf_images() {
+	let dir = TempDir::new();
+	let mut image = vec![0; 257 * SECTOR];
+	put_sector(&mut image, 16, b"\0BEA01\x01");
+	put_sector(&mut image, 17, b"\0NSR02\x01");
+	put_sector(&mut image, 18, b"\0TEA01\x01");
+
+	// 锚点指向扇区 32 开始的卷描述符序列：分区 0 从扇区 64 开始，逻辑卷有一个类型 1 的分区映射
+	let mut anchor = udf_tag(2);
+	anchor[16..20].copy_from_slice(&(3 * SECTOR as u32).to_le_bytes());
+	anchor[20..24].copy_from_slice(&32u32.to_le_bytes());
+	put_sector(&mut image, 256, &anchor);
+	let mut partition = udf_tag(5);
+	partition[188..192].copy_from_slice(&64u32.to_le_bytes());
+	put_sector(&mut image, 32, &partition);
+	let mut volume = udf_tag(6);
+	volume[212..216].copy_from_slice(&(SECTOR as u32).to_le_bytes());
+	volume[268..272].copy_from_slice(&1u32.to_le_bytes());
+	volume[440..446].copy_from_slice(&[1, 6, 1, 0, 0, 0]);
+	put_sector(&mut image, 33, &volume);
+	put_sector(&mut image, 34, &udf_tag(8));
+
+	// 逻辑块 0 为文件集描述符，根目录在块 1；hello.txt 由一个记录的段和一个未记录的段组成，
+	// sub 为扩展文件项，其中的 inner.txt 内容直接保存在文件项中
+	let mut file_set = udf_tag(256);
+	file_set[404..408].copy_from_slice(&1u32.to_le_bytes());
+	put_sector(&mut image, 64, &file_set);
+	let root = [
+		udf_identifier("", 0x0a, 1),
+		udf_identifier("hello.txt", 0, 2),
+		udf_identifier("sub", 0x02, 3),
+	]
+	.concat();
+	put_sector(
+		&mut image,
+		65,
+		&udf_entry(261, 4, 3, root.len() as u64, &root),
+	);
+	let extents = [
+		4096u32.to_le_bytes(),
+		10u32.to_le_bytes(),
+		(1 << 30 | 904u32).to_le_bytes(),
+		0u32.to_le_bytes(),
+	]
+	.concat();
+	put_sector(&mut image, 66, &udf_entry(261, 5, 0, 5000, &extents));
+	let sub = [
+		udf_identifier("", 0x0a, 1),
+		udf_identifier("inner.txt", 0, 4),
+	]
+	.concat();
+	put_sector(
+		&mut image,
+		67,
+		&udf_entry(266, 4, 3, sub.len() as u64, &sub),
+	);
+	put_sector(&mut image, 68, &udf_entry(261, 5, 3, 4, b"tiny"));
+	let hello: Vec<u8> = (0..4096).map(|i| (i % 253) as u8 + 1).collect();
+	put_sector(&mut image, 74, &hello);
+	fs::write(dir.0.join("test.iso"), image).unwrap();
+
+	let backend = IsoBackend::open(&dir.0.join("test.iso")).unwrap();
+	assert_eq!(names(&backend, "."), ["hello.txt", "sub"]);
+	assert_eq!(names(&backend, "sub"), ["inner.txt"]);
+	let info = backend.stat("hello.txt").unwrap();
+	assert_eq!((info.size, info.modified), (5000, 1_704_164_646));
+	let data = backend.read("hello.txt", 4000, 2000).unwrap();
+	assert_eq!(data.len(), 1000);
+	assert_eq!(&data[..96], &hello[4000..]);
+	assert!(data[96..].iter().all(|&byte| byte == 0));
+	assert_eq!(backend.read("sub/inner.txt", 1, 100).unwrap(), b"iny");
+	assert_eq!(
+		error_code(backend.rename("sub", "other", false)),
+		"read_only"
+	);
+}
+
+#[test]
+fn git_backend_mounts_commits() {
+	let dir = TempDir::new();
+	let repo = git2::Repository::init_bare(dir.0.join("repo.git")).unwrap();
+	let signature = git2::Signature::new(
+		"test",
+		"test@example.com",
+		&git2::Time::new(1_700_000_000, 0),
+	)
+	.unwrap();
+	let large = noise(3, 200_000);
+	// 按 (路径, 内容) 构造一个提交，目录按路径中的 / 生成
+	let commit = |files: &[(&str, &[u8])], parent: Option<git2::Oid>, branch: &str| {
+		fn build(repo: &git2::Repository, files: &[(&str, &[u8])]) -> git2::Oid {
+			let mut builder = repo.treebuilder(None).unwrap();
+			let mut dirs: Vec<&str> = files
+				.iter()
+				.filter_map(|(path, _)| path.split_once('/').map(|(dir, _)| dir))
+				.collect();
+			dirs.dedup();
+			for dir in dirs {
+				let children: Vec<(&str, &[u8])> = files
+					.iter()
+					.filter_map(|(path, data)| {
+						Some((path.strip_prefix(dir)?.strip_prefix('/')?, *data))
+					})
+					.collect();
+				builder
+					.insert(dir, build(repo, &children), 0o040000)
+					.unwrap();
+			}
+			for (path, data) in files.iter().filter(|(path, _)| !path.contains('/')) {
+				builder
+					.insert(path, repo.blob(data).unwrap(), 0o100644)
+					.unwrap();
+			}
+			builder.write().unwrap()
+		}
+		let tree = repo.find_tree(build(&repo, files)).unwrap();
+		let parents: Vec<git2::Commit> = parent
+			.map(|id| repo.find_commit(id).unwrap())
+			.into_iter()
+			.collect();
+		let parents: Vec<&git2::Commit> = parents.iter().collect();
+		repo.commit(
+			Some(&format!("refs/heads/{}", branch)),
+			&signature,
+			&signature,
+			"commit",
+			&tree,
+			&parents,
+		)
+		.unwrap()
+	};
+	let first = commit(
+		&[
+			("readme.txt", b"version 1"),
+			("src/main.rs", b"fn main() {}"),
+			("src/data/large.bin", &large),
+		],
+		None,
+		"main",
+	);
+	commit(
+		&[
+			("readme.txt", b"version 2"),
+			("src/main.rs", b"fn main() {}"),
+		],
+		Some(first),
+		"main",
+	);
+	repo.set_head("refs/heads/main").unwrap();
+
+	// 默认挂载 HEAD
+	let backend = GitBackend::open(&dir.0.join("repo.git"), "HEAD").unwrap();
+	assert!(backend.read_only());
+	assert_eq!(names(&backend, "."), ["readme.txt", "src"]);
+	assert_eq!(names(&backend, "src"), ["main.rs"]);
+	assert_eq!(backend.read("readme.txt", 0, 100).unwrap(), b"version 2");
+	let info = backend.stat("src/main.rs").unwrap();
+	assert_eq!(
+		(info.name.as_str(), info.size, info.modified),
+		("main.rs", 12, 1_700_000_000)
+	);
+	assert!(backend.stat("src").unwrap().is_directory);
+	assert_eq!(error_code(backend.stat("src/data")), "not_found");
+	assert_eq!(error_code(backend.stat("README.TXT")), "not_found");
+	assert_eq!(
+		error_code(backend.list_page("readme.txt", None)),
+		"not_a_directory"
+	);
+	assert_eq!(error_code(backend.read("src", 0, 1)), "is_a_directory");
+	assert_eq!(
+		error_code(backend.write("readme.txt", 0, b"x")),
+		"read_only"
+	);
+	assert_eq!(error_code(backend.create("new.txt", false)), "read_only");
+
+	// 较早的提交，分段读取大文件
+	let backend = GitBackend::open(&dir.0.join("repo.git"), "main~1").unwrap();
+	assert_eq!(backend.read("readme.txt", 0, 100).unwrap(), b"version 1");
+	assert_eq!(
+		backend.stat("src/data/large.bin").unwrap().size,
+		large.len() as u64
+	);
+	assert_eq!(
+		backend
+			.read("src/data/large.bin", 150_000, 100_000)
+			.unwrap(),
+		&large[150_000..]
+	);
+	assert_eq!(
+		backend.read("src/data/large.bin", 0, 10).unwrap(),
+		&large[..10]
+	);
+	let backend = GitBackend::open(&dir.0.join("repo.git"), &first.to_string()).unwrap();
+	assert_eq!(names(&backend, "src"), ["data", "main.rs"]);
+	assert!(GitBackend::open(&dir.0.join("repo.git"), "no-such-branch").is_err());
+}
+
+// 测试用的 FAT 卷：每簇一个扇区，子目录只占一个簇，FAT12 的根目录在固定区域
+struct FatImage {
+	data: Vec<u8>,
+	fat32: bool,
+	reserved: usize,
+	fat_sectors: usize,
+	root: usize,
+	data_start: usize,
+	next: u32,
+}
+
+// 目录项中的时间 2024-03-05 12:34:56
+const FAT_DATE: u16 = (44 << 9) | (3 << 5) | 5;
+const FAT_TIME: u16 = (12 << 11) | (34 << 5) | 28;
+const FAT_TIMESTAMP: u64 = 1_709_642_096;
+
+impl FatImage {
+	fn new(sectors: usize, fat32: bool) -> Self {
+		let (reserved, root_entries) = if fat32 { (32, 0) } else { (1, 64) };
+		let fat_sectors = if fat32 {
+			sectors * 4 / 512 + 1
+		} else {
+			sectors * 3 / 2 / 512 + 1
+		};
+		let root = (reserved + 2 * fat_sectors) * 512;
+		let mut data = vec![0; sectors * 512];
+		data[..11].copy_from_slice(b"\xEB\x3C\x90MSWIN4.1");
+		data[11..13].copy_from_slice(&512u16.to_le_bytes());
+		data[13] = 1;
+		data[14..16].copy_from_slice(&(reserved as u16).to_le_bytes());
+		data[16] = 2;
+		data[17..19].copy_from_slice(&(root_entries as u16).to_le_bytes());
+		data[21] = 0xF8;
+		data[32..36].copy_from_slice(&(sectors as u32).to_le_bytes());
+		if fat32 {
+			data[36..40].copy_from_slice(&(fat_sectors as u32).to_le_bytes());
+			data[44..48].copy_from_slice(&2u32.to_le_bytes());
+		} else {
+			data[22..24].copy_from_slice(&(fat_sectors as u16).to_le_bytes());
+		}
+		data[510..512].copy_from_slice(&[0x55, 0xAA]);
+		let mut image = Self {
+			data,
+			fat32,
+			reserved,
+			fat_sectors,
+			root,
+			data_start: root + root_entries * 32,
+			next: 2,
+		};
+		image.set(0, 0x0FFF_FFF8);
+		image.set(1, 0x0FFF_FFFF);
+		if fat32 {
+			// FAT32 的根目录是第 2 簇
+			image.allocate(&[], 1);
+		}
+		image
+	}
+
+	// 在两份 FAT 中设置一项
+	fn set(&mut self, cluster: u32, value: u32) {
+		for copy in 0..2 {
+			let fat = (self.reserved + copy * self.fat_sectors) * 512;
+			if self.fat32 {
+				let at = fat + cluster as usize * 4;
+				self.data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+			} else {
+				let at = fat + cluster as usize * 3 / 2;
+				let old = u16::from_le_bytes([self.data[at], self.data[at + 1]]);
+				let value = value as u16 & 0x0FFF;
+				let new = if cluster.is_multiple_of(2) {
+					(old & 0xF000) | value
+				} else {
+					(old & 0x000F) | (value << 4)
+				};
+				self.data[at..at + 2].copy_from_slice(&new.to_le_bytes());
+			}
+		}
+	}
+
+	fn cluster(&self, cluster: u32) -> usize {
+		self.data_start + (cluster as usize - 2) * 512
+	}
+
+	// 写入内容并返回起始簇；stride 为 2 时簇之间各空出一个簇，使簇链不连续
+	fn allocate(&mut self, content: &[u8], stride: u32) -> u32 {
+		let clusters: Vec<u32> = (0..content.len().div_ceil(512).max(1) as u32)
+			.map(|index| self.next + index * stride)
+			.collect();
+		self.next = clusters.last().unwrap() + 1;
+		for (index, &cluster) in clusters.iter().enumerate() {
+			self.set(
+				cluster,
+				clusters.get(index + 1).copied().unwrap_or(0x0FFF_FFFF),
+			);
+			let chunk = content.chunks(512).nth(index).unwrap_or_default();
+			let at = self.cluster(cluster);
+			self.data[at..at + chunk.len()].copy_from_slice(chunk);
+		}
+		clusters[0]
+	}
+
+	// 在目录中加入一项，directory 为 None 时是根目录；没有长文件名时短文件名标记为全小写
+	fn add(
+		&mut self,
+		directory: Option<u32>,
+		long_name: Option<&str>,
+		short: &[u8; 11],
+		attributes: u8,
+		content: &[u8],
+		stride: u32,
+	) -> u32 {
+		let cluster = self.allocate(content, stride);
+		let mut entries = Vec::new();
+		if let Some(long_name) = long_name {
+			let checksum = short
+				.iter()
+				.fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b));
+			let units: Vec<u16> = long_name.encode_utf16().chain([0]).collect();
+			let parts = units.len().div_ceil(13);
+			for sequence in (1..=parts).rev() {
+				let mut entry = [0u8; 32];
+				entry[0] = sequence as u8 | if sequence == parts { 0x40 } else { 0 };
+				entry[11] = 0x0F;
+				entry[13] = checksum;
+				for (index, offset) in [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30]
+					.into_iter()
+					.enumerate()
+				{
+					let unit = units
+						.get((sequence - 1) * 13 + index)
+						.copied()
+						.unwrap_or(0xFFFF);
+					entry[offset..offset + 2].copy_from_slice(&unit.to_le_bytes());
+				}
+				entries.extend(entry);
+			}
+		}
+		let mut entry = [0u8; 32];
+		entry[..11].copy_from_slice(short);
+		entry[11] = attributes;
+		entry[12] = if long_name.is_none() { 0x18 } else { 0 };
+		for (offset, value) in [
+			(14, FAT_TIME),
+			(16, FAT_DATE),
+			(18, FAT_DATE),
+			(20, (cluster >> 16) as u16),
+			(22, FAT_TIME),
+			(24, FAT_DATE),
+			(26, cluster as u16),
+		] {
+			entry[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+		}
+		if attributes & 0x10 == 0 {
+			entry[28..32].copy_from_slice(&(content.len() as u32).to_le_bytes());
+		}
+		entries.extend(entry);
+		let mut at = match directory {
+			None if !self.fat32 => self.root,
+			None => self.cluster(2),
+			Some(directory) => self.cluster(directory),
+		};
+		while self.data[at] != 0 {
+			at += 32;
+		}
+		self.data[at..at + entries.len()].copy_from_slice(&entries);
+		cluster
+	}
+}
+
+// exFAT 测试卷：512 字节的扇区和簇，FAT 从第 24 个扇区开始，簇堆从第 64 个扇区开始；
+// 根目录是第 2 簇起的两个簇，经过 FAT 链接
+struct ExfatImage {
+	data: Vec<u8>,
+	next: u32,
+}
+
+impl ExfatImage {
+	fn new(sectors: usize) -> Self {
+		let mut data = vec![0; sectors * 512];
+		data[..11].copy_from_slice(b"\xEB\x76\x90EXFAT   ");
+		data[72..80].copy_from_slice(&(sectors as u64).to_le_bytes());
+		for (offset, value) in [
+			(80, 24u32),
+			(84, 16),
+			(88, 64),
+			(92, sectors as u32 - 64),
+			(96, 2),
+		] {
+			data[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+		}
+		data[104..111].copy_from_slice(&[0, 1, 0, 0, 9, 0, 1]);
+		data[510..512].copy_from_slice(&[0x55, 0xAA]);
+		let mut image = Self { data, next: 2 };
+		image.set(0, 0xFFFF_FFF8);
+		image.set(1, 0xFFFF_FFFF);
+		image.allocate(&[0; 1024], 1, true);
+		image
+	}
+
+	fn set(&mut self, cluster: u32, value: u32) {
+		let at = 24 * 512 + cluster as usize * 4;
+		self.data[at..at + 4].copy_from_slice(&value.to_le_bytes());
+	}
+
+	fn cluster(&self, cluster: u32) -> usize {
+		64 * 512 + (cluster as usize - 2) * 512
+	}
+
+	// 写入内容并返回起始簇；stride 为 2 时簇之间各空出一个簇，linked 为 false 时不写入 FAT
+	fn allocate(&mut self, content: &[u8], stride: u32, linked: bool) -> u32 {
+		let clusters: Vec<u32> = (0..content.len().div_ceil(512).max(1) as u32)
+			.map(|index| self.next + index * stride)
+			.collect();
+		self.next = clusters.last().unwrap() + 1;
+		for (index, &cluster) in clusters.iter().enumerate() {
+			if linked {
+				self.set(
+					cluster,
+					clusters.get(index + 1).copied().unwrap_or(0xFFFF_FFFF),
+				);
+			}
+			let chunk = content.chunks(512).nth(index).unwrap_or_default();
+			let at = self.cluster(cluster);
+			self.data[at..at + chunk.len()].copy_from_slice(chunk);
+		}
+		clusters[0]
+	}
+
+	// 在目录中加入文件项、流扩展项和文件名项，返回起始簇和文件项的位置。stride 为 1 时簇连续且不经过 FAT；
+	// 只有前 valid 字节有效。修改时间是 UTC+8 的本地时间，其余时间没有 UTC 偏移
+	fn add(
+		&mut self,
+		directory: u32,
+		name: &str,
+		attributes: u16,
+		content: &[u8],
+		stride: u32,
+		valid: usize,
+	) -> (u32, usize) {
+		let cluster = self.allocate(content, stride, stride != 1);
+		let units: Vec<u16> = name.encode_utf16().collect();
+		let names = units.len().div_ceil(15);
+		let mut set = vec![0u8; (2 + names) * 32];
+		set[0] = 0x85;
+		set[1] = 1 + names as u8;
+		set[4..6].copy_from_slice(&attributes.to_le_bytes());
+		for (offset, value) in [
+			(8, FAT_TIME),
+			(10, FAT_DATE),
+			(12, FAT_TIME),
+			(14, FAT_DATE),
+			(18, FAT_DATE),
+		] {
+			set[offset..offset + 2].copy_from_slice(&value.to_le_bytes());
+		}
+		set[23] = 0x80 | 32;
+		set[32] = 0xC0;
+		set[33] = if stride == 1 { 3 } else { 1 };
+		set[35] = units.len() as u8;
+		let length = if attributes & 0x10 != 0 {
+			content.len().div_ceil(512).max(1) * 512
+		} else {
+			content.len()
+		};
+		set[40..48].copy_from_slice(&(valid.min(length) as u64).to_le_bytes());
+		set[52..56].copy_from_slice(&cluster.to_le_bytes());
+		set[56..64].copy_from_slice(&(length as u64).to_le_bytes());
+		for (index, unit) in units.iter().enumerate() {
+			let at = 64 + index / 15 * 32;
+			set[at] = 0xC1;
+			set[at + 2 + index % 15 * 2..at + 4 + index % 15 * 2]
+				.copy_from_slice(&unit.to_le_bytes());
+		}
+		let checksum = set
+			.iter()
+			.enumerate()
+			.filter(|&(index, _)| index != 2 && index != 3)
+			.fold(0u16, |sum, (_, &b)| {
+				sum.rotate_right(1).wrapping_add(b as u16)
+			});
+		set[2..4].copy_from_slice(&checksum.to_le_bytes());
+		let mut at = self.cluster(directory);
+		while self.data[at] != 0 {
+			at += 32;
+		}
+		self.data[at..at + set.len()].copy_from_slice(&set);
+		(cluster, at)
+	}
+}
+
+// NTFS 测试卷：512 字节的扇区和簇，1 KiB 的文件记录和索引记录。$MFT 分为两段：
+// 0 到 31 号记录从第 16 簇开始，32 到 47 号记录从第 200 簇开始；数据从第 300 簇开始分配
+struct NtfsImage {
+	data: Vec<u8>,
+	next: u64,
+}
+
+// 2024-03-05 12:34:56 UTC
+const NTFS_TIME: u64 = (FAT_TIMESTAMP + 11_644_473_600) * 10_000_000;
+
+fn utf16le(text: &str) -> Vec<u8> {
+	text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+}
+
+// 更新序列：每 512 字节的最后两字节换成序列号 1，原值存入偏移 usa 处的数组
+fn ntfs_fixup(record: &mut [u8], usa: usize) {
+	record[4..6].copy_from_slice(&(usa as u16).to_le_bytes());
+	let count = record.len() as u16 / 512 + 1;
+	record[6..8].copy_from_slice(&count.to_le_bytes());
+	record[usa..usa + 2].copy_from_slice(&1u16.to_le_bytes());
+	for index in 1..=record.len() / 512 {
+		let at = index * 512 - 2;
+		record.copy_within(at..at + 2, usa + index * 2);
+		record[at..at + 2].copy_from_slice(&1u16.to_le_bytes());
+	}
+}
+
+fn ntfs_resident(kind: u32, name: &str, value: &[u8]) -> Vec<u8> {
+	let name = utf16le(name);
+	let value_offset = (24 + name.len()).next_multiple_of(8);
+	let mut attribute = vec![0; (value_offset + value.len()).next_multiple_of(8)];
+	let length = attribute.len() as u32;
+	attribute[..4].copy_from_slice(&kind.to_le_bytes());
+	attribute[4..8].copy_from_slice(&length.to_le_bytes());
+	attribute[9] = (name.len() / 2) as u8;
+	attribute[10..12].copy_from_slice(&24u16.to_le_bytes());
+	attribute[16..20].copy_from_slice(&(value.len() as u32).to_le_bytes());
+	attribute[20..22].copy_from_slice(&(value_offset as u16).to_le_bytes());
+	attribute[24..24 + name.len()].copy_from_slice(&name);
+	attribute[value_offset..value_offset + value.len()].copy_from_slice(value);
+	attribute
+}
+
+// 非常驻属性：runs 为 (簇数, 起始簇)，没有起始簇的段是稀疏的；start 为这一段的起始簇
+fn ntfs_nonresident(
+	kind: u32,
+	name: &str,
+	flags: u16,
+	start: u64,
+	runs: &[(u64, Option<u64>)],
+	size: u64,
+	initialized: u64,
+) -> Vec<u8> {
+	let minimal = |bytes: [u8; 8], signed: bool| {
+		let mut bytes = bytes.to_vec();
+		while bytes.len() > 1 {
+			let (last, before) = (bytes[bytes.len() - 1], bytes[bytes.len() - 2]);
+			if (last == 0 && (!signed || before & 0x80 == 0))
+				|| (signed && last == 0xFF && before & 0x80 != 0)
+			{
+				bytes.pop();
+			} else {
+				break;
+			}
+		}
+		bytes
+	};
+	let mut list = Vec::new();
+	let mut previous = 0i64;
+	for &(length, lcn) in runs {
+		let length = minimal(length.to_le_bytes(), false);
+		let offset = match lcn {
+			Some(lcn) => {
+				let delta = lcn as i64 - previous;
+				previous = lcn as i64;
+				minimal(delta.to_le_bytes(), true)
+			}
+			None => Vec::new(),
+		};
+		list.push((offset.len() << 4 | length.len()) as u8);
+		list.extend(length);
+		list.extend(offset);
+	}
+	list.push(0);
+	let name = utf16le(name);
+	let runs_offset = (64 + name.len()).next_multiple_of(8);
+	let mut attribute = vec![0; (runs_offset + list.len()).next_multiple_of(8)];
+	let (length, clusters) = (
+		attribute.len() as u32,
+		runs.iter().map(|run| run.0).sum::<u64>(),
+	);
+	attribute[..4].copy_from_slice(&kind.to_le_bytes());
+	attribute[4..8].copy_from_slice(&length.to_le_bytes());
+	attribute[8] = 1;
+	attribute[9] = (name.len() / 2) as u8;
+	attribute[10..12].copy_from_slice(&64u16.to_le_bytes());
+	attribute[12..14].copy_from_slice(&flags.to_le_bytes());
+	for (offset, value) in [
+		(16, start),
+		(24, start + clusters - 1),
+		(40, clusters * 512),
+		(48, size),
+		(56, initialized),
+	] {
+		attribute[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+	}
+	attribute[32..34].copy_from_slice(&(runs_offset as u16).to_le_bytes());
+	attribute[64..64 + name.len()].copy_from_slice(&name);
+	attribute[runs_offset..runs_offset + list.len()].copy_from_slice(&list);
+	attribute
+}
+
+// 标准信息属性：访问时间比其他时间早一小时
+fn ntfs_standard_information() -> Vec<u8> {
+	let mut value = vec![0; 48];
+	for (offset, time) in [
+		(0, NTFS_TIME),
+		(8, NTFS_TIME),
+		(16, NTFS_TIME),
+		(24, NTFS_TIME - 3600 * 10_000_000),
+	] {
+		value[offset..offset + 8].copy_from_slice(&time.to_le_bytes());
+	}
+	ntfs_resident(0x10, "", &value)
+}
+
+// 文件名属性的内容，也是目录索引项的键
+fn ntfs_file_name(name: &str, namespace: u8, directory: bool) -> Vec<u8> {
+	let units = utf16le(name);
+	let mut value = vec![0; 66 + units.len()];
+	value[..8].copy_from_slice(&(5u64 | 1 << 48).to_le_bytes());
+	value[56..60].copy_from_slice(&(if directory { 0x1000_0000u32 } else { 0x20 }).to_le_bytes());
+	value[64] = (units.len() / 2) as u8;
+	value[65] = namespace;
+	value[66..].copy_from_slice(&units);
+	value
+}
+
+// 索引节点：节点头之后是各项 (记录号, 名称, 名称空间, 是否目录)，最后是带有结束标志的空项；
+// subnode 时结束项指向第 0 个索引记录
+fn ntfs_index_node(entries: &[(u64, &str, u8, bool)], subnode: bool) -> Vec<u8> {
+	let mut data = Vec::new();
+	for &(record, name, namespace, directory) in entries {
+		let key = ntfs_file_name(name, namespace, directory);
+		let length = (16 + key.len()).next_multiple_of(8);
+		let mut entry = vec![0; length];
+		entry[..8].copy_from_slice(&(record | 1 << 48).to_le_bytes());
+		entry[8..10].copy_from_slice(&(length as u16).to_le_bytes());
+		entry[10..12].copy_from_slice(&(key.len() as u16).to_le_bytes());
+		entry[16..16 + key.len()].copy_from_slice(&key);
+		data.extend(entry);
+	}
+	let mut last = vec![0; if subnode { 24 } else { 16 }];
+	let length = last.len() as u16;
+	last[8..10].copy_from_slice(&length.to_le_bytes());
+	last[12..14].copy_from_slice(&(if subnode { 3u16 } else { 2 }).to_le_bytes());
+	data.extend(last);
+	let mut node = vec![0; 16];
+	node[..4].copy_from_slice(&16u32.to_le_bytes());
+	node[4..8].copy_from_slice(&(16 + data.len() as u32).to_le_bytes());
+	node[8..12].copy_from_slice(&(16 + data.len() as u32).to_le_bytes());
+	node[12] = subnode as u8;
+	node.extend(data);
+	node
+}
+
+fn ntfs_index_root(node: &[u8]) -> Vec<u8> {
+	let mut value = vec![0; 16];
+	value[..4].copy_from_slice(&0x30u32.to_le_bytes());
+	value[4..8].copy_from_slice(&1u32.to_le_bytes());
+	value[8..12].copy_from_slice(&1024u32.to_le_bytes());
+	value[12] = 2;
+	value.extend(node);
+	ntfs_resident(0x90, "$I30", &value)
+}
+
+// 索引记录：节点头位于第 24 字节，各项从第 64 字节开始
+fn ntfs_index_record(vcn: u64, node: &[u8]) -> Vec<u8> {
+	let mut record = vec![0; 1024];
+	record[..4].copy_from_slice(b"INDX");
+	record[16..24].copy_from_slice(&vcn.to_le_bytes());
+	let entries = &node[16..];
+	record[24..28].copy_from_slice(&40u32.to_le_bytes());
+	record[28..32].copy_from_slice(&(40 + entries.len() as u32).to_le_bytes());
+	record[32..36].copy_from_slice(&1000u32.to_le_bytes());
+	record[64..64 + entries.len()].copy_from_slice(entries);
+	ntfs_fixup(&mut record, 40);
+	record
+}
+
+impl NtfsImage {
+	fn new(sectors: usize) -> Self {
+		let mut data = vec![0; sectors * 512];
+		data[..11].copy_from_slice(b"\xEB\x52\x90NTFS    ");
+		data[11..13].copy_from_slice(&512u16.to_le_bytes());
+		data[13] = 1;
+		data[40..48].copy_from_slice(&(sectors as u64 - 1).to_le_bytes());
+		data[48..56].copy_from_slice(&16u64.to_le_bytes());
+		data[64] = 0xF6;
+		data[68] = 0xF6;
+		data[510..512].copy_from_slice(&[0x55, 0xAA]);
+		Self { data, next: 300 }
+	}
+
+	fn record_offset(number: u64) -> usize {
+		match number {
+			0..32 => 16 * 512 + number as usize * 1024,
+			_ => 200 * 512 + (number as usize - 32) * 1024,
+		}
+	}
+
+	// 写入数据并返回起始簇
+	fn allocate(&mut self, content: &[u8]) -> u64 {
+		let cluster = self.next;
+		self.next += content.len().div_ceil(512).max(1) as u64;
+		let at = cluster as usize * 512;
+		self.data[at..at + content.len()].copy_from_slice(content);
+		cluster
+	}
+
+	// 文件记录：flags 为 1（在用）加 2（目录），base 为扩展记录所属的基本记录
+	fn record(&mut self, number: u64, flags: u16, base: u64, attributes: &[Vec<u8>]) {
+		let mut record = vec![0u8; 1024];
+		record[..4].copy_from_slice(b"FILE");
+		record[16..18].copy_from_slice(&1u16.to_le_bytes());
+		record[20..22].copy_from_slice(&56u16.to_le_bytes());
+		record[22..24].copy_from_slice(&flags.to_le_bytes());
+		let mut at = 56;
+		for attribute in attributes {
+			record[at..at + attribute.len()].copy_from_slice(attribute);
+			at += attribute.len();
+		}
+		record[at..at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+		record[24..28].copy_from_slice(&(at as u32 + 8).to_le_bytes());
+		record[28..32].copy_from_slice(&1024u32.to_le_bytes());
+		record[32..40].copy_from_slice(&(if base == 0 { 0 } else { base | 1 << 48 }).to_le_bytes());
+		ntfs_fixup(&mut record, 48);
+		let offset = Self::record_offset(number);
+		self.data[offset..offset + 1024].copy_from_slice(&record);
+	}
+}
+
+// MBR 分区表中的一项
+fn mbr_partition(disk: &mut [u8], index: usize, kind: u8, start: u32, sectors: u32) {
+	let entry = &mut disk[446 + index * 16..462 + index * 16];
+	entry[4] = kind;
+	entry[8..12].copy_from_slice(&start.to_le_bytes());
+	entry[12..16].copy_from_slice(&sectors.to_le_bytes());
+	disk[510..512].copy_from_slice(&[0x55, 0xAA]);
+}
+
+// GPT 磁盘：在 disk 末尾加上备份分区项和备份头，写入保护性 MBR、主头和主分区项。
+// 每个分区为（序号、类型 GUID、名称、起始扇区、结束扇区）
+fn gpt_disk(disk: &mut Vec<u8>, partitions: &[(usize, &str, &str, u64, u64)]) {
+	let mut entries = vec![0; 128 * 128];
+	for &(number, kind, name, first, last) in partitions {
+		let entry = &mut entries[(number - 1) * 128..number * 128];
+		entry[..16].copy_from_slice(&guid(kind));
+		entry[32..40].copy_from_slice(&first.to_le_bytes());
+		entry[40..48].copy_from_slice(&last.to_le_bytes());
+		for (index, unit) in name.encode_utf16().enumerate() {
+			entry[56 + index * 2..58 + index * 2].copy_from_slice(&unit.to_le_bytes());
+		}
+	}
+	let mut crc = flate2::Crc::new();
+	crc.update(&entries);
+	let entries_crc = crc.sum();
+	disk.resize(disk.len() + 33 * 512, 0);
+	let last = disk.len() as u64 / 512 - 1;
+	mbr_partition(disk, 0, 0xEE, 1, u32::MAX);
+	for (lba, alternate, table) in [(1, last, 2), (last, 1, last - 32)] {
+		let mut header = vec![0; 92];
+		header[..8].copy_from_slice(b"EFI PART");
+		header[8..12].copy_from_slice(&0x0001_0000u32.to_le_bytes());
+		header[12..16].copy_from_slice(&92u32.to_le_bytes());
+		header[24..32].copy_from_slice(&lba.to_le_bytes());
+		header[32..40].copy_from_slice(&alternate.to_le_bytes());
+		header[72..80].copy_from_slice(&table.to_le_bytes());
+		header[80..84].copy_from_slice(&128u32.to_le_bytes());
+		header[84..88].copy_from_slice(&128u32.to_le_bytes());
+		header[88..92].copy_from_slice(&entries_crc.to_le_bytes());
+		let mut crc = flate2::Crc::new();
+		crc.update(&header);
+		header[16..20].copy_from_slice(&crc.sum().to_le_bytes());
+		disk[lba as usize * 512..lba as usize * 512 + 92].copy_from_slice(&header);
+		disk[table as usize * 512..table as usize * 512 + entries.len()].copy_from_slice(&entries);
+	}
+}
+
+// VHD 页脚和动态磁盘头的校验和
+fn vhd_checksum(data: &mut [u8], field: usize) {
+	data[field..field + 4].fill(0);
+	let sum = data.iter().fold(0u32, |sum, &b| sum.wrapping_add(b as u32));
+	data[field..field + 4].copy_from_slice(&(!sum).to_be_bytes());
+}
+
+fn vhd_footer(size: u64, kind: u32, data_offset: u64, unique_id: [u8; 16]) -> Vec<u8> {
+	let mut footer = vec![0; 512];
+	footer[..8].copy_from_slice(b"conectix");
+	footer[12..16].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+	footer[16..24].copy_from_slice(&data_offset.to_be_bytes());
+	footer[40..48].copy_from_slice(&size.to_be_bytes());
+	footer[48..56].copy_from_slice(&size.to_be_bytes());
+	footer[60..64].copy_from_slice(&kind.to_be_bytes());
+	footer[68..84].copy_from_slice(&unique_id);
+	vhd_checksum(&mut footer, 64);
+	footer
+}
+
+// 差异磁盘的父磁盘：相对路径、父磁盘的标识和本磁盘保存了哪些扇区
+type ParentDisk<'a, Id> = Option<(&'a str, Id, &'a dyn Fn(usize) -> bool)>;
+
+// 动态 VHD 只保存含非零数据的块；给出 parent（标识为父磁盘的唯一 ID）时为差异磁盘
+fn vhd_image(
+	disk: &[u8],
+	block_size: usize,
+	unique_id: [u8; 16],
+	parent: ParentDisk<[u8; 16]>,
+) -> Vec<u8> {
+	let blocks = disk.len().div_ceil(block_size);
+	let bitmap_size = (block_size / 512).div_ceil(8).next_multiple_of(512);
+	let kind = if parent.is_some() { 4 } else { 3 };
+	let mut image = vhd_footer(disk.len() as u64, kind, 512, unique_id);
+	let mut header = vec![0; 1024];
+	header[..8].copy_from_slice(b"cxsparse");
+	header[8..16].fill(0xFF);
+	header[16..24].copy_from_slice(&1536u64.to_be_bytes());
+	header[24..28].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+	header[28..32].copy_from_slice(&(blocks as u32).to_be_bytes());
+	header[32..36].copy_from_slice(&(block_size as u32).to_be_bytes());
+	let mut table = vec![0xFFu8; (blocks * 4).next_multiple_of(512)];
+	let mut tail = Vec::new();
+	let data_start = 1536 + table.len();
+	if let Some((name, parent_id, _)) = parent {
+		header[40..56].copy_from_slice(&parent_id);
+		let locator: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+		header[576..580].copy_from_slice(b"W2ru");
+		header[580..584].copy_from_slice(&1u32.to_be_bytes());
+		header[584..588].copy_from_slice(&(locator.len() as u32).to_be_bytes());
+		header[592..600].copy_from_slice(&(data_start as u64).to_be_bytes());
+		tail.extend(locator);
+		tail.resize(512, 0);
+	}
+	for block in 0..blocks {
+		let sectors = block * block_size / 512..((block + 1) * block_size).min(disk.len()) / 512;
+		let present = |sector: usize| match parent {
+			Some((_, _, present)) => present(sector),
+			None => disk[sector * 512..sector * 512 + 512]
+				.iter()
+				.any(|&b| b != 0),
+		};
+		if !sectors.clone().any(present) {
+			continue;
+		}
+		let sector = (data_start + tail.len()) / 512;
+		table[block * 4..block * 4 + 4].copy_from_slice(&(sector as u32).to_be_bytes());
+		let mut bitmap = vec![0; bitmap_size];
+		let mut data = vec![0; block_size];
+		for (index, sector) in sectors.enumerate() {
+			if parent.is_none() || present(sector) {
+				bitmap[index / 8] |= 0x80 >> (index % 8);
+				data[index * 512..index * 512 + 512]
+					.copy_from_slice(&disk[sector * 512..sector * 512 + 512]);
+			}
+		}
+		tail.extend(bitmap);
+		tail.extend(data);
+	}
+	vhd_checksum(&mut header, 36);
+	image.extend(header);
+	image.extend(table);
+	image.extend(tail);
+	image.extend(vhd_footer(disk.len() as u64, kind, 512, unique_id));
+	image
+}
+
+fn crc32c(data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in data {
+		crc ^= byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ 0x82F6_3B78
+			} else {
+				crc >> 1
+			};
+		}
+	}
+	!crc
+}
+
+// 文本形式的 GUID 按 Windows 的方式存储，前三段小端
+fn guid(text: &str) -> [u8; 16] {
+	let bytes = hex::decode(text.replace('-', "")).unwrap();
+	let mut guid: [u8; 16] = bytes.try_into().unwrap();
+	guid[..4].reverse();
+	guid[4..6].reverse();
+	guid[6..8].reverse();
+	guid
+}
+
+// VHDX 映像：1 MiB 的块，元数据区和块分配表各占 1 MiB，之后是数据块。
+// 给出 parent（标识为父磁盘的数据写入 GUID）时为差异磁盘
+fn vhdx_image(disk: &[u8], data_write_guid: &str, parent: ParentDisk<&str>) -> Vec<u8> {
+	const MIB: usize = 1024 * 1024;
+	let blocks = disk.len().div_ceil(MIB);
+	let mut image = vec![0; 3 * MIB];
+	image[..8].copy_from_slice(b"vhdxfile");
+	for (sequence, offset) in [(1u64, 64 * 1024), (2, 128 * 1024)] {
+		let header = &mut image[offset..offset + 4096];
+		header[..4].copy_from_slice(b"head");
+		header[8..16].copy_from_slice(&sequence.to_le_bytes());
+		header[32..48].copy_from_slice(&guid(data_write_guid));
+		header[66..68].copy_from_slice(&1u16.to_le_bytes());
+		let checksum = crc32c(header);
+		header[4..8].copy_from_slice(&checksum.to_le_bytes());
+	}
+	for offset in [192 * 1024, 256 * 1024] {
+		let table = &mut image[offset..offset + 64 * 1024];
+		table[..4].copy_from_slice(b"regi");
+		table[8..12].copy_from_slice(&2u32.to_le_bytes());
+		for (index, (id, start)) in [
+			("2DC27766-F623-4200-9D64-115E9BFD4A08", 2 * MIB),
+			("8B7CA206-4790-4B9A-B8FE-575F050F886E", MIB),
+		]
+		.into_iter()
+		.enumerate()
+		{
+			let entry = &mut table[16 + index * 32..48 + index * 32];
+			entry[..16].copy_from_slice(&guid(id));
+			entry[16..24].copy_from_slice(&(start as u64).to_le_bytes());
+			entry[24..28].copy_from_slice(&(MIB as u32).to_le_bytes());
+			entry[28..32].copy_from_slice(&1u32.to_le_bytes());
+		}
+		let checksum = crc32c(table);
+		table[4..8].copy_from_slice(&checksum.to_le_bytes());
+	}
+
+	let mut items: Vec<(&str, Vec<u8>)> = vec![
+		(
+			"CAA16737-FA36-4D43-B3B6-33F0AA44E76B",
+			[
+				(MIB as u32).to_le_bytes(),
+				(if parent.is_some() { 2u32 } else { 0 }).to_le_bytes(),
+			]
+			.concat(),
+		),
+		(
+			"2FA54224-CD1B-4876-B211-5DBED83BF4B8",
+			(disk.len() as u64).to_le_bytes().to_vec(),
+		),
+		(
+			"8141BF1D-A96F-4709-BA47-F233A8FAAB5F",
+			512u32.to_le_bytes().to_vec(),
+		),
+		(
+			"CDA348C7-445D-4471-9CC9-E9885251C556",
+			512u32.to_le_bytes().to_vec(),
+		),
+	];
+	if let Some((name, linkage, _)) = parent {
+		let pairs = [
+			("parent_linkage", format!("{{{}}}", linkage)),
+			("relative_path", name.to_string()),
+		];
+		let mut locator = guid("B04AEFB7-D19E-4A81-B789-25B8E9445913").to_vec();
+		locator.extend([0, 0, pairs.len() as u8, 0]);
+		let mut strings = Vec::new();
+		let utf16 = |text: &str| {
+			text.encode_utf16()
+				.flat_map(u16::to_le_bytes)
+				.collect::<Vec<u8>>()
+		};
+		for (key, value) in &pairs {
+			let (key, value) = (utf16(key), utf16(value));
+			let base = 20 + pairs.len() * 12 + strings.len();
+			locator.extend((base as u32).to_le_bytes());
+			locator.extend(((base + key.len()) as u32).to_le_bytes());
+			locator.extend((key.len() as u16).to_le_bytes());
+			locator.extend((value.len() as u16).to_le_bytes());
+			strings.extend(key);
+			strings.extend(value);
+		}
+		locator.extend(strings);
+		items.push(("A8D35F2D-B30B-454D-ABF7-D3D84834AB0C", locator));
+	}
+	let metadata = &mut image[MIB..2 * MIB];
+	metadata[..8].copy_from_slice(b"metadata");
+	metadata[10..12].copy_from_slice(&(items.len() as u16).to_le_bytes());
+	let mut offset = 64 * 1024;
+	for (index, (id, value)) in items.iter().enumerate() {
+		let entry = &mut metadata[32 + index * 32..64 + index * 32];
+		entry[..16].copy_from_slice(&guid(id));
+		entry[16..20].copy_from_slice(&(offset as u32).to_le_bytes());
+		entry[20..24].copy_from_slice(&(value.len() as u32).to_le_bytes());
+		entry[24..28].copy_from_slice(&4u32.to_le_bytes());
+		metadata[offset..offset + value.len()].copy_from_slice(value);
+		offset += value.len().next_multiple_of(8);
+	}
+
+	// 块分配表：每 4096 个数据块之后是一个扇区位图块的项
+	let mut bitmap = vec![0u8; MIB];
+	let mut table = vec![0u64; 4097];
+	for (block, entry) in table.iter_mut().enumerate().take(blocks) {
+		let sectors = block * MIB / 512..((block + 1) * MIB).min(disk.len()) / 512;
+		let present = |sector: usize| match parent {
+			Some((_, _, present)) => present(sector),
+			None => true,
+		};
+		let count = sectors.clone().filter(|&sector| present(sector)).count();
+		*entry = match count {
+			0 => 0,
+			_ => {
+				let mut data = vec![0; MIB];
+				for (index, sector) in sectors.clone().enumerate() {
+					if present(sector) {
+						bitmap[sector / 8] |= 1 << (sector % 8);
+						data[index * 512..index * 512 + 512]
+							.copy_from_slice(&disk[sector * 512..sector * 512 + 512]);
+					}
+				}
+				let state = if count == sectors.len() { 6 } else { 7 };
+				image.extend(data);
+				(image.len() - MIB) as u64 | state
+			}
+		};
+	}
+	if parent.is_some() {
+		image.extend(&bitmap);
+		table[4096] = (image.len() - MIB) as u64 | 6;
+	}
+	for (index, entry) in table.iter().enumerate() {
+		image[2 * MIB + index * 8..2 * MIB + index * 8 + 8].copy_from_slice(&entry.to_le_bytes());
+	}
+	image
+}
+
+// 检查测试卷的内容：长文件名、全小写的短文件名、不连续的簇链和子目录
+fn check_fat_volume(backend: &dyn StorageBackend, long: &[u8], fragmented: &[u8], nested: &[u8]) {
+	assert!(backend.read_only());
+	assert_eq!(
+		names(backend, "."),
+		["Long File Name.txt", "Sub Folder", "readme.txt"]
+	);
+	assert_eq!(names(backend, "Sub Folder"), ["nested.bin"]);
+	let info = backend.stat("Long File Name.txt").unwrap();
+	assert_eq!(
+		(info.size, info.modified, info.created, info.accessed),
+		(
+			long.len() as u64,
+			FAT_TIMESTAMP,
+			FAT_TIMESTAMP,
+			1_709_596_800
+		)
+	);
+	assert_eq!(backend.read("Long File Name.txt", 0, 100).unwrap(), long);
+	assert_eq!(backend.read("readme.txt", 0, 10_000).unwrap(), fragmented);
+	assert_eq!(
+		backend.read("readme.txt", 500, 600).unwrap(),
+		&fragmented[500..1100]
+	);
+	assert_eq!(
+		backend
+			.read("SUB FOLDER/Nested.BIN", 1000, 100_000)
+			.unwrap(),
+		&nested[1000..]
+	);
+	assert_eq!(backend.stat("sub folder").unwrap().name, "Sub Folder");
+	assert!(backend.read("readme.txt", 5000, 10).unwrap().is_empty());
+	assert_eq!(error_code(backend.stat("missing.txt")), "not_found");
+	assert_eq!(error_code(backend.stat("readme.txt/inner")), "not_found");
+	assert_eq!(
+		error_code(backend.list_page("readme.txt", None)),
+		"not_a_directory"
+	);
+	assert_eq!(
+		error_code(backend.read("Sub Folder", 0, 1)),
+		"is_a_directory"
+	);
+	assert_eq!(
+		error_code(backend.write("readme.txt", 0, b"x")),
+		"read_only"
+	);
+	assert_eq!(error_code(backend.create("new.txt", false)), "read_only");
+}
+
+#[test]
+fn disk_backend_reads_fat_images() {
+	let dir = TempDir::new();
+	let (long, fragmented, nested) = (b"hello from FAT".to_vec(), noise(1, 1500), noise(2, 3000));
+	let mut volume = FatImage::new(2048, false);
+	volume.add(None, None, b"TESTVOL    ", 0x08, &[], 1);
+	volume.add(
+		None,
+		Some("Long File Name.txt"),
+		b"LONGFI~1TXT",
+		0x20,
+		&long,
+		1,
+	);
+	volume.add(None, None, b"README  TXT", 0x20, &fragmented, 2);
+	let sub = volume.add(None, Some("Sub Folder"), b"SUBFOL~1   ", 0x10, &[], 1);
+	volume.add(
+		Some(sub),
+		Some("nested.bin"),
+		b"NESTED  BIN",
+		0x20,
+		&nested,
+		1,
+	);
+
+	// 分区从第 128 个扇区开始
+	let mut disk = vec![0; 128 * 512];
+	mbr_partition(&mut disk, 1, 0x01, 128, 2048);
+	disk.extend(&volume.data);
+	let fixed = [
+		disk.clone(),
+		vhd_footer(disk.len() as u64, 2, u64::MAX, [1; 16]),
+	]
+	.concat();
+	let images = [
+		("volume.img", volume.data.clone()),
+		("disk.img", disk.clone()),
+		("fixed.vhd", fixed),
+		("dynamic.vhd", vhd_image(&disk, 256 * 1024, [2; 16], None)),
+		(
+			"disk.vhdx",
+			vhdx_image(&disk, "11111111-2222-3333-4444-555555555555", None),
+		),
+	];
+	for (name, image) in images {
+		let path = dir.0.join(name);
+		fs::write(&path, image).unwrap();
+		let backend =
+			DiskBackend::open(&path, None, None).unwrap_or_else(|e| panic!("{}: {}", name, e));
+		check_fat_volume(&backend, &long, &fragmented, &nested);
+	}
+
+	// 分区按分区表中的序号选择
+	let path = dir.0.join("disk.vhdx");
+	check_fat_volume(
+		&DiskBackend::open(&path, Some(2), None).unwrap(),
+		&long,
+		&fragmented,
+		&nested,
+	);
+	assert!(DiskBackend::open(&path, Some(1), None).is_err());
+	assert!(DiskBackend::open(&dir.0.join("volume.img"), Some(2), None).is_err());
+}
+
+#[test]
+fn disk_backend_reads_fat32_in_gpt_disks() {
+	let dir = TempDir::new();
+	let content = noise(3, 5000);
+	let mut volume = FatImage::new(70_000, true);
+	let sub = volume.add(None, Some("Documents"), b"DOCUME~1   ", 0x10, &[], 1);
+	volume.add(
+		Some(sub),
+		Some("report final.pdf"),
+		b"REPORT~1PDF",
+		0x20,
+		&content,
+		2,
+	);
+
+	// 第 2 个分区项从第 2048 个扇区开始
+	let mut disk = vec![0; 2048 * 512];
+	disk.extend(&volume.data);
+	gpt_disk(
+		&mut disk,
+		&[(
+			2,
+			"EBD0A0A2-B9E5-4433-87C0-68B6B72699C7",
+			"Basic data partition",
+			2048,
+			2048 + 70_000 - 1,
+		)],
+	);
+	let path = dir.0.join("gpt.img");
+	fs::write(&path, &disk).unwrap();
+
+	let backend = DiskBackend::open(&path, None, None).unwrap();
+	assert_eq!(names(&backend, "."), ["Documents"]);
+	assert_eq!(
+		backend.stat("documents/Report Final.pdf").unwrap().size,
+		5000
+	);
+	assert_eq!(
+		backend
+			.read("Documents/report final.pdf", 100, 10_000)
+			.unwrap(),
+		&content[100..]
+	);
+	assert!(DiskBackend::open(&path, Some(1), None).is_err());
+	assert!(DiskBackend::open(&path, Some(2), None).is_ok());
+
+	let device = crate::image::open(&path).unwrap();
+	let partitions = crate::image::partitions(device.as_ref()).unwrap();
+	assert_eq!(partitions.len(), 1);
+	assert_eq!(
+		(
+			partitions[0].number,
+			partitions[0].offset,
+			partitions[0].size
+		),
+		(2, 2048 * 512, 70_000 * 512)
+	);
+	assert_eq!(
+		(partitions[0].kind.to_string(), partitions[0].name.as_str()),
+		("Basic data".to_string(), "Basic data partition")
+	);
+
+	// 主 GPT 的分区项损坏时使用磁盘末尾的备份，两份都损坏时无法打开
+	disk[1024 + 200] ^= 1;
+	fs::write(&path, &disk).unwrap();
+	assert_eq!(
+		names(&DiskBackend::open(&path, None, None).unwrap(), "."),
+		["Documents"]
+	);
+	let backup = disk.len() - 512;
+	disk[backup + 30] ^= 1;
+	fs::write(&path, &disk).unwrap();
+	assert!(DiskBackend::open(&path, None, None).is_err());
+}
+
+#[test]
+fn disk_backend_reads_exfat_volumes() {
+	let dir = TempDir::new();
+	let (long, fragmented) = (b"hello from exFAT".to_vec(), noise(6, 1500));
+	// nested.bin 只有前 2000 字节有效，之后的簇中残留的数据读出为 0
+	let mut nested = noise(7, 3000);
+	nested[2000..].fill(0xEE);
+	let mut volume = ExfatImage::new(2048);
+	let at = volume.cluster(2);
+	volume.data[at] = 0x83;
+	volume.add(2, "Long File Name.txt", 0x20, &long, 1, long.len());
+	volume.add(2, "readme.txt", 0x20, &fragmented, 2, fragmented.len());
+	let (_, deleted) = volume.add(2, "deleted.txt", 0x20, b"gone", 1, 4);
+	volume.data[deleted] = 0x05;
+	let (_, damaged) = volume.add(2, "damaged.txt", 0x20, b"bad", 1, 3);
+	volume.data[damaged + 2] ^= 1;
+	let (sub, _) = volume.add(2, "Sub Folder", 0x10, &[], 1, 512);
+	volume.add(sub, "nested.bin", 0x20, &nested, 2, 2000);
+	// 根目录占两个簇，其余的项在第二个簇中
+	for index in 0..4 {
+		volume.add(2, &format!("file {}.txt", index), 0x20, &[], 1, 0);
+	}
+	nested[2000..].fill(0);
+
+	let mut disk = vec![0; 128 * 512];
+	mbr_partition(&mut disk, 0, 0x07, 128, 2048);
+	disk.extend(&volume.data);
+	let path = dir.0.join("exfat.img");
+	fs::write(&path, &disk).unwrap();
+	let backend = DiskBackend::open(&path, None, None).unwrap();
+	let mut expected = vec![
+		"Long File Name.txt".to_string(),
+		"Sub Folder".to_string(),
+		"readme.txt".to_string(),
+	];
+	expected.extend((0..4).map(|index| format!("file {}.txt", index)));
+	expected.sort();
+	assert_eq!(names(&backend, "."), expected);
+	let info = backend.stat("Long File Name.txt").unwrap();
+	assert_eq!(
+		(info.size, info.modified, info.created, info.accessed),
+		(
+			long.len() as u64,
+			FAT_TIMESTAMP - 8 * 3600,
+			FAT_TIMESTAMP,
+			1_709_596_800
+		)
+	);
+	assert_eq!(backend.read("long file name.TXT", 0, 100).unwrap(), long);
+	assert_eq!(
+		backend.read("readme.txt", 500, 600).unwrap(),
+		&fragmented[500..1100]
+	);
+	assert_eq!(names(&backend, "Sub Folder"), ["nested.bin"]);
+	assert_eq!(
+		backend.stat("Sub Folder/nested.bin").unwrap().modified,
+		FAT_TIMESTAMP - 8 * 3600
+	);
+	assert_eq!(
+		backend
+			.read("SUB FOLDER/Nested.BIN", 1000, 100_000)
+			.unwrap(),
+		&nested[1000..]
+	);
+	assert_eq!(
+		backend.read("Sub Folder/nested.bin", 2500, 100).unwrap(),
+		[0; 100]
+	);
+	assert!(backend.read("file 3.txt", 0, 10).unwrap().is_empty());
+	assert_eq!(error_code(backend.stat("deleted.txt")), "not_found");
+	assert_eq!(error_code(backend.stat("damaged.txt")), "not_found");
+	assert_eq!(
+		error_code(backend.write("readme.txt", 0, b"x")),
+		"read_only"
+	);
+	let device = crate::image::open(&path).unwrap();
+	let partition = crate::image::open_partition(device, 1).unwrap();
+	assert_eq!(
+		crate::image::file_system(partition.as_ref()).unwrap(),
+		Some("exFAT")
+	);
+}
+
+#[test]
+fn disk_backend_reads_ntfs_volumes() {
+	let dir = TempDir::new();
+	let mut volume = NtfsImage::new(2048);
+	let mft = ntfs_nonresident(
+		0x80,
+		"",
+		0,
+		0,
+		&[(64, Some(16)), (32, Some(200))],
+		48 * 1024,
+		48 * 1024,
+	);
+	volume.record(0, 1, 0, &[ntfs_standard_information(), mft]);
+	let root = ntfs_index_node(
+		&[
+			(0, "$MFT", 3, false),
+			(24, "Documents", 3, true),
+			(26, "Long File Name.txt", 1, false),
+			(26, "LONGFI~1.TXT", 2, false),
+			(27, "compressed.bin", 3, false),
+			(28, "deleted.txt", 3, false),
+			(25, "hello.txt", 3, false),
+		],
+		false,
+	);
+	volume.record(
+		5,
+		3,
+		0,
+		&[ntfs_standard_information(), ntfs_index_root(&root)],
+	);
+
+	// 常驻的小文件
+	let hello = b"hello from NTFS";
+	volume.record(
+		25,
+		1,
+		0,
+		&[
+			ntfs_standard_information(),
+			ntfs_resident(0x30, "", &ntfs_file_name("hello.txt", 3, false)),
+			ntfs_resident(0x80, "", hello),
+		],
+	);
+	// 不连续、中间稀疏、只初始化了前 2800 字节的文件
+	let mut long = noise(8, 3000);
+	let (first, _, last) = (
+		volume.allocate(&long[..1024]),
+		volume.allocate(&[0xEE; 512]),
+		volume.allocate(&long[2048..]),
+	);
+	long[1024..2048].fill(0);
+	long[2800..].fill(0);
+	let data = ntfs_nonresident(
+		0x80,
+		"",
+		0,
+		0,
+		&[(2, Some(first)), (2, None), (2, Some(last))],
+		3000,
+		2800,
+	);
+	volume.record(26, 1, 0, &[ntfs_standard_information(), data]);
+	let packed = volume.allocate(&[1; 512]);
+	volume.record(
+		27,
+		1,
+		0,
+		&[
+			ntfs_standard_information(),
+			ntfs_nonresident(0x80, "", 1, 0, &[(1, Some(packed))], 100, 100),
+		],
+	);
+	volume.record(
+		28,
+		0,
+		0,
+		&[
+			ntfs_standard_information(),
+			ntfs_resident(0x80, "", b"gone"),
+		],
+	);
+
+	// 目录的项在索引记录中，位图只标记第 0 个索引记录在用
+	let records = [
+		ntfs_index_record(0, &ntfs_index_node(&[(29, "report.pdf", 3, false)], false)),
+		ntfs_index_record(2, &ntfs_index_node(&[(40, "stale.txt", 3, false)], false)),
+	]
+	.concat();
+	let allocation = volume.allocate(&records);
+	let mut bitmap = vec![0; 8];
+	bitmap[0] = 1;
+	volume.record(
+		24,
+		3,
+		0,
+		&[
+			ntfs_standard_information(),
+			ntfs_index_root(&ntfs_index_node(&[], true)),
+			ntfs_nonresident(0xA0, "$I30", 0, 0, &[(4, Some(allocation))], 2048, 2048),
+			ntfs_resident(0xB0, "$I30", &bitmap),
+		],
+	);
+
+	// 数据属性分为两段，分别在两个扩展记录中，由属性列表指向
+	let report = noise(9, 5000);
+	let (head, tail) = (
+		volume.allocate(&report[..2048]),
+		volume.allocate(&report[2048..]),
+	);
+	let mut list = Vec::new();
+	for (kind, start, record) in [(0x10u32, 0u64, 29u64), (0x80, 0, 30), (0x80, 4, 31)] {
+		let mut entry = vec![0; 32];
+		entry[..4].copy_from_slice(&kind.to_le_bytes());
+		entry[4..6].copy_from_slice(&32u16.to_le_bytes());
+		entry[7] = 26;
+		entry[8..16].copy_from_slice(&start.to_le_bytes());
+		entry[16..24].copy_from_slice(&(record | 1 << 48).to_le_bytes());
+		list.extend(entry);
+	}
+	volume.record(
+		29,
+		1,
+		0,
+		&[ntfs_standard_information(), ntfs_resident(0x20, "", &list)],
+	);
+	volume.record(
+		30,
+		1,
+		29,
+		&[ntfs_nonresident(
+			0x80,
+			"",
+			0,
+			0,
+			&[(4, Some(head))],
+			5000,
+			5000,
+		)],
+	);
+	volume.record(
+		31,
+		1,
+		29,
+		&[ntfs_nonresident(0x80, "", 0, 4, &[(6, Some(tail))], 0, 0)],
+	);
+
+	let mut disk = vec![0; 128 * 512];
+	mbr_partition(&mut disk, 0, 0x07, 128, 2048);
+	disk.extend(&volume.data);
+	let path = dir.0.join("ntfs.vhd");
+	fs::write(&path, vhd_image(&disk, 256 * 1024, [4; 16], None)).unwrap();
+	let backend = DiskBackend::open(&path, None, None).unwrap();
+	assert_eq!(
+		names(&backend, "."),
+		[
+			"Documents",
+			"Long File Name.txt",
+			"compressed.bin",
+			"hello.txt"
+		]
+	);
+	assert_eq!(names(&backend, "documents"), ["report.pdf"]);
+	let info = backend.stat("hello.txt").unwrap();
+	assert_eq!(
+		(info.size, info.modified, info.created, info.accessed),
+		(
+			hello.len() as u64,
+			FAT_TIMESTAMP,
+			FAT_TIMESTAMP,
+			FAT_TIMESTAMP - 3600
+		)
+	);
+	assert_eq!(backend.read("HELLO.txt", 6, 100).unwrap(), &hello[6..]);
+	assert_eq!(backend.stat("Long File Name.txt").unwrap().size, 3000);
+	assert_eq!(backend.read("Long File Name.txt", 0, 10_000).unwrap(), long);
+	assert_eq!(
+		backend.read("long file name.txt", 1000, 1900).unwrap(),
+		&long[1000..2900]
+	);
+	assert!(backend.stat("Documents").unwrap().is_directory);
+	assert_eq!(
+		backend.read("Documents/report.pdf", 0, 10_000).unwrap(),
+		report
+	);
+	assert_eq!(
+		backend.read("Documents/report.pdf", 2000, 100).unwrap(),
+		&report[2000..2100]
+	);
+	assert_eq!(
+		error_code(backend.read("compressed.bin", 0, 10)),
+		"image_error"
+	);
+	assert_eq!(error_code(backend.stat("deleted.txt")), "not_found");
+	assert_eq!(error_code(backend.stat("LONGFI~1.TXT")), "not_found");
+	assert_eq!(error_code(backend.stat("$MFT")), "not_found");
+}
+
+#[test]
+fn disk_images_read_logical_partitions() {
+	let dir = TempDir::new();
+	let (long, fragmented, nested) = (
+		b"in a logical partition".to_vec(),
+		noise(4, 1500),
+		noise(5, 3000),
+	);
+	let mut volume = FatImage::new(2048, false);
+	volume.add(
+		None,
+		Some("Long File Name.txt"),
+		b"LONGFI~1TXT",
+		0x20,
+		&long,
+		1,
+	);
+	volume.add(None, None, b"README  TXT", 0x20, &fragmented, 2);
+	let sub = volume.add(None, Some("Sub Folder"), b"SUBFOL~1   ", 0x10, &[], 1);
+	volume.add(
+		Some(sub),
+		Some("nested.bin"),
+		b"NESTED  BIN",
+		0x20,
+		&nested,
+		1,
+	);
+
+	// 主分区 1 没有文件系统；扩展分区从第 200 个扇区开始，其中的两个逻辑分区各由一个扩展引导记录描述，
+	// 第二个逻辑分区（序号 6）是 FAT 卷
+	let mut disk = vec![0; 1000 * 512];
+	mbr_partition(&mut disk, 0, 0x83, 64, 100);
+	mbr_partition(&mut disk, 1, 0x0F, 200, 800 + 2048);
+	let (first, second) = (&mut disk[200 * 512..201 * 512], 300);
+	mbr_partition(first, 0, 0x07, 63, 30);
+	mbr_partition(first, 1, 0x05, second - 200, 2048 + 64);
+	mbr_partition(
+		&mut disk[second as usize * 512..(second as usize + 1) * 512],
+		0,
+		0x01,
+		700,
+		2048,
+	);
+	disk.extend(&volume.data);
+	let path = dir.0.join("logical.img");
+	fs::write(&path, &disk).unwrap();
+
+	let device = crate::image::open(&path).unwrap();
+	let partitions = crate::image::partitions(device.as_ref()).unwrap();
+	let summary = partitions
+		.iter()
+		.map(|partition| {
+			(
+				partition.number,
+				partition.offset / 512,
+				partition.size / 512,
+				partition.kind.to_string(),
+			)
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(
+		summary,
+		[
+			(1, 64, 100, "Linux".to_string()),
+			(5, 263, 30, "NTFS/exFAT".to_string()),
+			(6, 1000, 2048, "FAT12".to_string())
+		]
+	);
+	check_fat_volume(
+		&DiskBackend::open(&path, None, None).unwrap(),
+		&long,
+		&fragmented,
+		&nested,
+	);
+	check_fat_volume(
+		&DiskBackend::open(&path, Some(6), None).unwrap(),
+		&long,
+		&fragmented,
+		&nested,
+	);
+	assert!(DiskBackend::open(&path, Some(7), None).is_err());
+
+	// 指回自身的扩展引导记录不会无限循环
+	mbr_partition(
+		&mut disk[second as usize * 512..(second as usize + 1) * 512],
+		1,
+		0x05,
+		second - 200,
+		1,
+	);
+	fs::write(&path, &disk).unwrap();
+	assert!(crate::image::partitions(crate::image::open(&path).unwrap().as_ref()).is_err());
+}
+
+#[test]
+fn disk_images_read_differencing_chains() {
+	let dir = TempDir::new();
+	let size = 3 * 1024 * 1024 + 512 * 7;
+	let (base, changes) = (noise(4, size), noise(5, size));
+	// 差异磁盘保存每 7 个扇区中的 3 个和一段连续的扇区
+	let present = |sector: usize| sector % 7 < 3 || (4000..4100).contains(&sector);
+	let expected: Vec<u8> = (0..size)
+		.map(|i| {
+			if present(i / 512) {
+				changes[i]
+			} else {
+				base[i]
+			}
+		})
+		.collect();
+	let check = |path: &std::path::Path| {
+		let device = crate::image::open(path).unwrap();
+		assert_eq!(device.size(), size as u64);
+		let mut offset = 0;
+		for length in [1, 511, 513, 100_000, 1_048_576, usize::MAX]
+			.into_iter()
+			.cycle()
+		{
+			let length = length.min(size - offset);
+			let mut buf = vec![0; length];
+			device.read_at(offset as u64, &mut buf).unwrap();
+			assert!(
+				buf == expected[offset..offset + length],
+				"{} bytes at {}",
+				length,
+				offset
+			);
+			offset += length;
+			if offset == size {
+				break;
+			}
+		}
+		let mut buf = vec![0; 2];
+		assert!(device.read_at(size as u64 - 1, &mut buf).is_err());
+	};
+
+	fs::write(
+		dir.0.join("parent.vhd"),
+		vhd_image(&base, 512 * 1024, [7; 16], None),
+	)
+	.unwrap();
+	fs::write(
+		dir.0.join("child.vhd"),
+		vhd_image(
+			&changes,
+			512 * 1024,
+			[8; 16],
+			Some(("parent.vhd", [7; 16], &present)),
+		),
+	)
+	.unwrap();
+	check(&dir.0.join("child.vhd"));
+	fs::write(
+		dir.0.join("stale.vhd"),
+		vhd_image(
+			&changes,
+			512 * 1024,
+			[9; 16],
+			Some(("parent.vhd", [6; 16], &present)),
+		),
+	)
+	.unwrap();
+	assert!(crate::image::open(&dir.0.join("stale.vhd")).is_err());
+
+	let parent_guid = "0F1E2D3C-4B5A-6978-8796-A5B4C3D2E1F0";
+	fs::write(
+		dir.0.join("parent.vhdx"),
+		vhdx_image(&base, parent_guid, None),
+	)
+	.unwrap();
+	fs::write(
+		dir.0.join("child.vhdx"),
+		vhdx_image(
+			&changes,
+			"00000000-0000-0000-0000-000000000001",
+			Some(("parent.vhdx", parent_guid, &present)),
+		),
+	)
+	.unwrap();
+	check(&dir.0.join("child.vhdx"));
+	fs::write(
+		dir.0.join("stale.vhdx"),
+		vhdx_image(
+			&changes,
+			"00000000-0000-0000-0000-000000000002",
+			Some((
+				"parent.vhdx",
+				"00000000-0000-0000-0000-000000000003",
+				&present,
+			)),
+		),
+	)
+	.unwrap();
+	assert!(crate::image::open(&dir.0.join("stale.vhdx")).is_err());
+	assert!(crate::image::open(&dir.0.join("missing.vhdx")).is_err());
+}
+
+// QCOW2 映像中的一个簇
+enum QcowCluster<'a> {
+	Data(&'a [u8]),
+	Compressed(&'a [u8]),
+	Zero,
+}
+
+// 4 KiB 簇的 QCOW2 v3 映像：头和后备文件名、L1 表、L2 表，之后是数据簇和不对齐的压缩簇
+fn qcow2_image(
+	size: u64,
+	clusters: &[(usize, QcowCluster)],
+	backing: Option<&str>,
+	zstd: bool,
+) -> Vec<u8> {
+	const CLUSTER: usize = 4096;
+	let l1_size = (size as usize).div_ceil(CLUSTER * CLUSTER / 8);
+	let mut image = vec![0; CLUSTER * (2 + l1_size)];
+	image[..4].copy_from_slice(b"QFI\xfb");
+	image[4..8].copy_from_slice(&3u32.to_be_bytes());
+	if let Some(name) = backing {
+		image[8..16].copy_from_slice(&512u64.to_be_bytes());
+		image[16..20].copy_from_slice(&(name.len() as u32).to_be_bytes());
+		image[512..512 + name.len()].copy_from_slice(name.as_bytes());
+	}
+	image[20..24].copy_from_slice(&12u32.to_be_bytes());
+	image[24..32].copy_from_slice(&size.to_be_bytes());
+	image[36..40].copy_from_slice(&(l1_size as u32).to_be_bytes());
+	image[40..48].copy_from_slice(&(CLUSTER as u64).to_be_bytes());
+	image[72..80].copy_from_slice(&(if zstd { 1u64 << 3 } else { 0 }).to_be_bytes());
+	image[96..100].copy_from_slice(&4u32.to_be_bytes());
+	image[100..104].copy_from_slice(&112u32.to_be_bytes());
+	image[104] = zstd as u8;
+	for table in 0..l1_size {
+		let entry = ((2 + table) * CLUSTER) as u64 | 1 << 63;
+		image[CLUSTER + table * 8..CLUSTER + table * 8 + 8].copy_from_slice(&entry.to_be_bytes());
+	}
+	for (index, cluster) in clusters {
+		let entry = match cluster {
+			QcowCluster::Data(data) => {
+				image.resize(image.len().next_multiple_of(CLUSTER), 0);
+				let host = image.len() as u64;
+				image.extend(*data);
+				image.resize(image.len().next_multiple_of(CLUSTER), 0);
+				host | 1 << 63
+			}
+			QcowCluster::Compressed(data) => {
+				let compressed = if zstd {
+					zstd::bulk::compress(data, 3).unwrap()
+				} else {
+					let mut encoder = flate2::write::DeflateEncoder::new(
+						Vec::new(),
+						flate2::Compression::default(),
+					);
+					encoder.write_all(data).unwrap();
+					encoder.finish().unwrap()
+				};
+				let host = image.len() as u64;
+				let sectors = ((host + compressed.len() as u64 - 1) >> 9) - (host >> 9);
+				image.extend(compressed);
+				1 << 62 | host | sectors << 58
+			}
+			QcowCluster::Zero => 1,
+		};
+		let at = (2 + index / (CLUSTER / 8)) * CLUSTER + index % (CLUSTER / 8) * 8;
+		image[at..at + 8].copy_from_slice(&entry.to_be_bytes());
+	}
+	image
+}
+
+#[test]
+fn disk_images_read_qcow2_backing_chains() {
+	let dir = TempDir::new();
+	const CLUSTER: usize = 4096;
+	let size = 16 * CLUSTER;
+	// 容易压缩的簇内容
+	let pattern = |seed: u8| {
+		(0..CLUSTER)
+			.map(|i| (i / 64) as u8 ^ seed)
+			.collect::<Vec<u8>>()
+	};
+	let base = noise(6, 10 * CLUSTER);
+	let (middle_1, middle_3, middle_12) = (noise(7, CLUSTER), pattern(3), noise(8, CLUSTER));
+	let (top_2, top_4) = (pattern(9), noise(10, CLUSTER));
+	fs::write(dir.0.join("base.img"), &base).unwrap();
+	let middle = qcow2_image(
+		size as u64,
+		&[
+			(1, QcowCluster::Data(&middle_1)),
+			(3, QcowCluster::Compressed(&middle_3)),
+			(12, QcowCluster::Data(&middle_12)),
+		],
+		Some("base.img"),
+		false,
+	);
+	fs::write(dir.0.join("middle.qcow2"), middle).unwrap();
+	let top = qcow2_image(
+		size as u64,
+		&[
+			(1, QcowCluster::Zero),
+			(2, QcowCluster::Compressed(&top_2)),
+			(4, QcowCluster::Data(&top_4)),
+		],
+		Some("middle.qcow2"),
+		true,
+	);
+	fs::write(dir.0.join("top.qcow2"), top).unwrap();
+
+	// 每层只覆盖自己保存的簇，后备文件之外为 0
+	let mut expected = base.clone();
+	expected.resize(size, 0);
+	for (index, data) in [(1, &middle_1), (3, &middle_3), (12, &middle_12)] {
+		expected[index * CLUSTER..(index + 1) * CLUSTER].copy_from_slice(data);
+	}
+	let middle_expected = expected.clone();
+	expected[CLUSTER..2 * CLUSTER].fill(0);
+	for (index, data) in [(2, &top_2), (4, &top_4)] {
+		expected[index * CLUSTER..(index + 1) * CLUSTER].copy_from_slice(data);
+	}
+	for (name, expected) in [("middle.qcow2", &middle_expected), ("top.qcow2", &expected)] {
+		let device = crate::image::open(&dir.0.join(name)).unwrap();
+		assert_eq!(device.size(), size as u64);
+		let mut whole = vec![0; size];
+		device.read_at(0, &mut whole).unwrap();
+		assert!(whole == *expected, "{}", name);
+		let mut part = vec![0; 3 * CLUSTER];
+		device.read_at(2 * CLUSTER as u64 + 100, &mut part).unwrap();
+		assert!(
+			part == expected[2 * CLUSTER + 100..5 * CLUSTER + 100],
+			"{}",
+			name
+		);
+	}
+
+	// 找不到后备文件或后备文件互相引用时无法打开
+	fs::write(
+		dir.0.join("orphan.qcow2"),
+		qcow2_image(size as u64, &[], Some("missing.img"), false),
+	)
+	.unwrap();
+	assert!(crate::image::open(&dir.0.join("orphan.qcow2")).is_err());
+	fs::write(
+		dir.0.join("loop.qcow2"),
+		qcow2_image(size as u64, &[], Some("loop.qcow2"), false),
+	)
+	.unwrap();
+	assert!(crate::image::open(&dir.0.join("loop.qcow2")).is_err());
+}
+
+// 发送一个 NBD 选项，读取回复直到确认或错误；每个回复为（类型、数据）
+fn nbd_option(stream: &mut TcpStream, option: u32, data: &[u8]) -> Vec<(u32, Vec<u8>)> {
+	stream
+		.write_all(&0x4948_4156_454F_5054u64.to_be_bytes())
+		.unwrap();
+	stream.write_all(&option.to_be_bytes()).unwrap();
+	stream
+		.write_all(&(data.len() as u32).to_be_bytes())
+		.unwrap();
+	stream.write_all(data).unwrap();
+	let mut replies = Vec::new();
+	loop {
+		let mut header = [0; 20];
+		stream.read_exact(&mut header).unwrap();
+		assert_eq!(
+			u64::from_be_bytes(header[..8].try_into().unwrap()),
+			0x0003_E889_0455_65A9
+		);
+		assert_eq!(
+			u32::from_be_bytes(header[8..12].try_into().unwrap()),
+			option
+		);
+		let kind = u32::from_be_bytes(header[12..16].try_into().unwrap());
+		let mut data = vec![0; u32::from_be_bytes(header[16..20].try_into().unwrap()) as usize];
+		stream.read_exact(&mut data).unwrap();
+		replies.push((kind, data));
+		if kind == 1 || kind & 1 << 31 != 0 {
+			return replies;
+		}
+	}
+}
+
+// 发送一个 NBD 请求，返回回复中的错误码和读到的数据
+fn nbd_request(
+	stream: &mut TcpStream,
+	kind: u16,
+	offset: u64,
+	length: u32,
+	payload: &[u8],
+) -> (u32, Vec<u8>) {
+	let mut request = 0x2560_9513u32.to_be_bytes().to_vec();
+	request.extend(0u16.to_be_bytes());
+	request.extend(kind.to_be_bytes());
+	request.extend(0x1234_5678u64.to_be_bytes());
+	request.extend(offset.to_be_bytes());
+	request.extend(length.to_be_bytes());
+	request.extend(payload);
+	stream.write_all(&request).unwrap();
+	let mut reply = [0; 16];
+	stream.read_exact(&mut reply).unwrap();
+	assert_eq!(reply[..4], 0x6744_6698u32.to_be_bytes());
+	assert_eq!(reply[8..], 0x1234_5678u64.to_be_bytes());
+	let error = u32::from_be_bytes(reply[4..8].try_into().unwrap());
+	let mut data = vec![
+		0;
+		if kind == 0 && error == 0 {
+			length as usize
+		} else {
+			0
+		}
+	];
+	stream.read_exact(&mut data).unwrap();
+	(error, data)
+}
+
+#[test]
+fn nbd_exports_images_from_storage_backends() {
+	let dir = TempDir::new();
+	const CLUSTER: usize = 4096;
+	let size = 12 * CLUSTER;
+	// 共享中的 QCOW2 映像，后备文件在同一目录
+	let (base, changed) = (noise(11, 8 * CLUSTER), noise(12, CLUSTER));
+	fs::create_dir(dir.0.join("vms")).unwrap();
+	fs::write(dir.0.join("vms/base.img"), &base).unwrap();
+	fs::write(
+		dir.0.join("vms/top.qcow2"),
+		qcow2_image(
+			size as u64,
+			&[(2, QcowCluster::Data(&changed))],
+			Some("base.img"),
+			false,
+		),
+	)
+	.unwrap();
+	let mut expected = base.clone();
+	expected.resize(size, 0);
+	expected[2 * CLUSTER..3 * CLUSTER].copy_from_slice(&changed);
+
+	let backend: Arc<dyn StorageBackend> = Arc::new(LocalBackend::open(&dir.0).unwrap());
+	assert!(crate::image::open_in(backend.clone(), "vms/missing.qcow2").is_err());
+	let device = crate::image::open_in(backend, "vms/top.qcow2").unwrap();
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	thread::spawn(move || {
+		crate::nbd::serve(
+			listener,
+			Arc::new(crate::nbd::Export {
+				name: "top".to_string(),
+				device,
+			}),
+		)
+	});
+
+	// 固定新式握手，客户端不需要导出信息之后的 124 个 0
+	let mut stream = TcpStream::connect(addr).unwrap();
+	let mut hello = [0; 18];
+	stream.read_exact(&mut hello).unwrap();
+	assert_eq!(&hello[..16], b"NBDMAGICIHAVEOPT");
+	assert_eq!(hello[16..], 3u16.to_be_bytes());
+	stream.write_all(&3u32.to_be_bytes()).unwrap();
+	assert_eq!(
+		nbd_option(&mut stream, 3, &[]),
+		[(2, b"\0\0\0\x03top".to_vec()), (1, Vec::new())]
+	);
+	assert_eq!(nbd_option(&mut stream, 8, &[]), [(1 << 31 | 1, Vec::new())]);
+	let go = |name: &str| {
+		[
+			(name.len() as u32).to_be_bytes().as_slice(),
+			name.as_bytes(),
+			&1u16.to_be_bytes(),
+			&3u16.to_be_bytes(),
+		]
+		.concat()
+	};
+	assert_eq!(nbd_option(&mut stream, 7, &go("other"))[0].0, 1 << 31 | 6);
+	let replies = nbd_option(&mut stream, 7, &go("top"));
+	assert_eq!(replies.len(), 3);
+	assert_eq!(
+		replies[0],
+		(
+			3,
+			[
+				&0u16.to_be_bytes()[..],
+				&(size as u64).to_be_bytes(),
+				&0x0103u16.to_be_bytes()
+			]
+			.concat()
+		)
+	);
+	assert_eq!(replies[1].1[..2], 3u16.to_be_bytes());
+
+	assert!(nbd_request(&mut stream, 0, 0, size as u32, &[]) == (0, expected.clone()));
+	assert!(
+		nbd_request(&mut stream, 0, 2 * CLUSTER as u64 - 100, 5000, &[])
+			== (0, expected[2 * CLUSTER - 100..2 * CLUSTER + 4900].to_vec())
+	);
+	assert_eq!(nbd_request(&mut stream, 0, size as u64 - 10, 20, &[]).0, 22);
+	// 导出只读：写入被拒绝，之后的请求不受影响
+	assert_eq!(nbd_request(&mut stream, 1, 0, 3, b"abc").0, 1);
+	assert_eq!(nbd_request(&mut stream, 3, 0, 0, &[]).0, 0);
+	assert!(nbd_request(&mut stream, 0, 0, 16, &[]) == (0, expected[..16].to_vec()));
+}
+
+#[test]
+fn disk_snapshots_keep_writes_in_deltas() {
+	use crate::image::cow;
+
+	let dir = TempDir::new();
+	// 64 KiB 的块，最后一块不满
+	let size = 5 * 65536 + 1000;
+	let base = noise(13, size);
+	let path = |name: &str| dir.0.join(name);
+	fs::write(path("base.img"), &base).unwrap();
+	cow::create(&path("first.cow"), &path("base.img")).unwrap();
+	assert!(cow::create(&path("first.cow"), &path("base.img")).is_err());
+	assert!(crate::image::open_writable(&path("base.img")).is_err());
+
+	// 跨块、不满一块和最后一块的写入
+	let mut expected = base.clone();
+	let first = crate::image::open_writable(&path("first.cow")).unwrap();
+	assert!(first.writable());
+	for (offset, seed, length) in [
+		(65000, 14, 2000),
+		(3 * 65536 + 10, 15, 100),
+		(size - 500, 16, 500),
+	] {
+		let data = noise(seed, length);
+		first.write_at(offset as u64, &data).unwrap();
+		expected[offset..offset + length].copy_from_slice(&data);
+	}
+	first.write_at(65100, b"again").unwrap();
+	expected[65100..65105].copy_from_slice(b"again");
+	assert!(first.write_at(size as u64 - 1, b"xx").is_err());
+	first.flush().unwrap();
+	drop(first);
+	let read = |name: &str| {
+		let device = crate::image::open(&path(name)).unwrap();
+		assert!(!device.writable());
+		let mut data = vec![0; device.size() as usize];
+		device.read_at(0, &mut data).unwrap();
+		data
+	};
+	assert!(read("first.cow") == expected);
+	assert!(fs::read(path("base.img")).unwrap() == base);
+
+	// 第二层快照：丢弃后回到第一层的内容
+	cow::create(&path("second.cow"), &path("first.cow")).unwrap();
+	let second = crate::image::open_writable(&path("second.cow")).unwrap();
+	second.write_at(0, &[0xAA; 70000]).unwrap();
+	drop(second);
+	assert!(read("second.cow")[..70000] == [0xAA; 70000]);
+	let (layers, bottom) = cow::chain(&path("second.cow")).unwrap();
+	let changed = layers
+		.iter()
+		.map(|layer| {
+			(
+				layer.path.file_name().unwrap().to_str().unwrap(),
+				layer.changed,
+			)
+		})
+		.collect::<Vec<_>>();
+	assert_eq!(
+		changed,
+		[("second.cow", 2 * 65536), ("first.cow", 4 * 65536)]
+	);
+	assert_eq!(bottom, path("base.img"));
+	cow::discard(&path("second.cow")).unwrap();
+	assert!(!path("second.cow").exists());
+	assert!(cow::discard(&path("base.img")).is_err());
+
+	// 也可以通过 NBD 写入
+	let device = crate::image::open_writable(&path("first.cow")).unwrap();
+	let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+	let addr = listener.local_addr().unwrap();
+	thread::spawn(move || {
+		crate::nbd::serve(
+			listener,
+			Arc::new(crate::nbd::Export {
+				name: "first".to_string(),
+				device,
+			}),
+		)
+	});
+	let mut stream = TcpStream::connect(addr).unwrap();
+	stream.read_exact(&mut [0; 18]).unwrap();
+	stream.write_all(&3u32.to_be_bytes()).unwrap();
+	let replies = nbd_option(&mut stream, 7, &[0, 0, 0, 0, 0, 0]);
+	assert_eq!(replies[0].1[10..12], 0x0145u16.to_be_bytes());
+	assert_eq!(nbd_request(&mut stream, 1, 200_000, 4, b"nbd!").0, 0);
+	assert_eq!(nbd_request(&mut stream, 6, 300_000, 5000, &[]).0, 0);
+	assert_eq!(
+		nbd_request(&mut stream, 1, size as u64 - 2, 4, b"over").0,
+		28
+	);
+	assert_eq!(nbd_request(&mut stream, 3, 0, 0, &[]).0, 0);
+	drop(stream);
+	expected[200_000..200_004].copy_from_slice(b"nbd!");
+	expected[300_000..305_000].fill(0);
+
+	// 合并到原始映像后删除差异文件
+	assert_eq!(cow::merge(&path("first.cow")).unwrap(), path("base.img"));
+	assert!(!path("first.cow").exists());
+	assert!(fs::read(path("base.img")).unwrap() == expected);
+}
+
+#[test]
+fn disk_images_are_created_and_resized() {
+	use crate::image::{self, NewFormat};
+
+	const MIB: usize = 1024 * 1024;
+	let dir = TempDir::new();
+	let path = |name: &str| dir.0.join(name);
+	let read = |name: &str| {
+		let device = image::open(&path(name)).unwrap();
+		let mut data = vec![0; device.size() as usize];
+		device.read_at(0, &mut data).unwrap();
+		data
+	};
+
+	// 新建的动态 VHDX 只有头、元数据和块分配表
+	image::create(
+		&path("new.vhdx"),
+		NewFormat::Vhdx,
+		10 * MIB as u64 + 512,
+		Some(MIB as u64),
+	)
+	.unwrap();
+	assert!(read("new.vhdx") == vec![0; 10 * MIB + 512]);
+	assert_eq!(
+		fs::metadata(path("new.vhdx")).unwrap().len(),
+		4 * MIB as u64
+	);
+	assert!(image::create(&path("new.vhdx"), NewFormat::Vhdx, MIB as u64, None).is_err());
+	assert!(image::create(&path("odd.vhdx"), NewFormat::Vhdx, 1000, None).is_err());
+	assert!(image::create(
+		&path("odd.vhdx"),
+		NewFormat::Vhdx,
+		MIB as u64,
+		Some(3 * MIB as u64)
+	)
+	.is_err());
+	image::create(&path("new.img"), NewFormat::Raw, MIB as u64, None).unwrap();
+	assert_eq!(
+		image::resize(&path("new.img"), 2 * MIB as u64).unwrap(),
+		MIB as u64
+	);
+	assert!(read("new.img") == vec![0; 2 * MIB]);
+
+	// 缩小后最后一块中新末尾之后的数据清零，不再使用的块从文件末尾截掉
+	let disk = noise(18, 3 * MIB + 1536);
+	fs::write(
+		path("disk.vhdx"),
+		vhdx_image(&disk, "11111111-2222-3333-4444-555555555555", None),
+	)
+	.unwrap();
+	assert_eq!(
+		image::resize(&path("disk.vhdx"), 2 * MIB as u64 + 1024).unwrap(),
+		disk.len() as u64
+	);
+	assert!(read("disk.vhdx") == disk[..2 * MIB + 1024]);
+	assert_eq!(
+		fs::metadata(path("disk.vhdx")).unwrap().len(),
+		6 * MIB as u64
+	);
+	image::resize(&path("disk.vhdx"), 8 * MIB as u64).unwrap();
+	let mut expected = disk[..2 * MIB + 1024].to_vec();
+	expected.resize(8 * MIB, 0);
+	assert!(read("disk.vhdx") == expected);
+
+	// 块分配表放不下时移到文件末尾
+	let size = 200 << 30;
+	image::resize(&path("disk.vhdx"), size).unwrap();
+	let device = image::open(&path("disk.vhdx")).unwrap();
+	assert_eq!(device.size(), size);
+	let mut data = vec![0xFF; 2 * MIB + 2048];
+	device.read_at(0, &mut data[..]).unwrap();
+	assert!(data == expected[..2 * MIB + 2048]);
+	device.read_at(size - 2048, &mut data[..2048]).unwrap();
+	assert!(data[..2048] == [0; 2048]);
+	assert!(image::resize(&path("disk.vhdx"), 1000).is_err());
+
+	crate::image::cow::create(&path("delta.cow"), &path("new.img")).unwrap();
+	assert!(image::resize(&path("delta.cow"), 4 * MIB as u64).is_err());
+}
+
+#[test]
+fn disk_images_are_converted() {
+	use crate::image::{self, NewFormat};
+
+	const MIB: usize = 1024 * 1024;
+	let dir = TempDir::new();
+	let path = |name: &str| dir.0.join(name);
+	let read = |name: &str| {
+		let device = image::open(&path(name)).unwrap();
+		let mut data = vec![0; device.size() as usize];
+		device.read_at(0, &mut data).unwrap();
+		data
+	};
+	let mut disk = vec![0; 5 * MIB + 512];
+	disk[..100_000].copy_from_slice(&noise(19, 100_000));
+	disk[3 * MIB + 1000..3 * MIB + 5000].copy_from_slice(&noise(20, 4000));
+	let end = disk.len() - 512;
+	disk[end..].copy_from_slice(&noise(21, 512));
+	fs::write(path("disk.img"), &disk).unwrap();
+
+	// 依次转换为各种格式，每一步都与原始数据相同；全为 0 的块不分配
+	let mut reports = Vec::new();
+	image::convert(
+		&path("disk.img"),
+		&path("disk.vhd"),
+		NewFormat::Vhd,
+		Some(512 * 1024),
+		|done, total| reports.push((done, total)),
+	)
+	.unwrap();
+	assert!(read("disk.vhd") == disk);
+	assert_eq!(reports.len(), 11);
+	assert_eq!(
+		reports.last(),
+		Some(&(disk.len() as u64, disk.len() as u64))
+	);
+	assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+	// 头、块分配表、三块（各有 512 字节的位图）和页脚
+	assert_eq!(
+		fs::metadata(path("disk.vhd")).unwrap().len(),
+		1536 + 512 + 3 * (512 + 512 * 1024) + 512
+	);
+
+	image::convert(
+		&path("disk.vhd"),
+		&path("disk.qcow2"),
+		NewFormat::Qcow2,
+		None,
+		|_, _| {},
+	)
+	.unwrap();
+	assert!(read("disk.qcow2") == disk);
+	// 头、四个数据簇、一个 L2 表、L1 表、引用计数表和一个引用计数块，每簇的引用计数都是 1
+	let qcow2 = fs::read(path("disk.qcow2")).unwrap();
+	assert_eq!(qcow2.len(), 9 * 64 * 1024);
+	let table = u64::from_be_bytes(qcow2[48..56].try_into().unwrap()) as usize;
+	let block = u64::from_be_bytes(qcow2[table..table + 8].try_into().unwrap()) as usize;
+	assert!(qcow2[block..block + 18]
+		.chunks_exact(2)
+		.all(|count| count == [0, 1]));
+	assert!(qcow2[block + 18..block + 64 * 1024].iter().all(|&b| b == 0));
+
+	image::convert(
+		&path("disk.qcow2"),
+		&path("disk.vhdx"),
+		NewFormat::Vhdx,
+		Some(MIB as u64),
+		|_, _| {},
+	)
+	.unwrap();
+	assert!(read("disk.vhdx") == disk);
+	assert_eq!(
+		fs::metadata(path("disk.vhdx")).unwrap().len(),
+		(4 + 3) * MIB as u64
+	);
+	image::convert(
+		&path("disk.vhdx"),
+		&path("copy.img"),
+		NewFormat::Raw,
+		None,
+		|_, _| {},
+	)
+	.unwrap();
+	assert!(fs::read(path("copy.img")).unwrap() == disk);
+
+	// 目标已存在时不覆盖；参数不合适时不留下文件
+	assert!(image::convert(
+		&path("disk.img"),
+		&path("copy.img"),
+		NewFormat::Raw,
+		None,
+		|_, _| {}
+	)
+	.is_err());
+	assert!(fs::read(path("copy.img")).unwrap() == disk);
+	assert!(image::convert(
+		&path("disk.img"),
+		&path("bad.qcow2"),
+		NewFormat::Qcow2,
+		Some(3000),
+		|_, _| {}
+	)
+	.is_err());
+	assert!(!path("bad.qcow2").exists());
+	fs::write(path("odd.img"), [1; 1000]).unwrap();
+	assert!(image::convert(
+		&path("odd.img"),
+		&path("odd.vhd"),
+		NewFormat::Vhd,
+		None,
+		|_, _| {}
+	)
+	.is_err());
+	assert!(!path("odd.vhd").exists());
+
+	// 新建的空映像
+	image::create(&path("new.vhd"), NewFormat::Vhd, 3 * MIB as u64, None).unwrap();
+	assert!(read("new.vhd") == vec![0; 3 * MIB]);
+	image::create(
+		&path("new.qcow2"),
+		NewFormat::Qcow2,
+		3 * MIB as u64 + 512,
+		None,
+	)
+	.unwrap();
+	assert!(read("new.qcow2") == vec![0; 3 * MIB + 512]);
+	assert_eq!(
+		NewFormat::from_path(&path("x.QCOW2")),
+		Some(NewFormat::Qcow2)
+	);
+	assert_eq!(NewFormat::from_path(&path("x.iso")), None);
+}
+
+// 记录读写次数的内存磁盘
+struct CountingDisk {
+	data: Mutex<Vec<u8>>,
+	reads: AtomicUsize,
+	writes: AtomicUsize,
+}
+
+impl crate::image::BlockDevice for CountingDisk {
+	fn size(&self) -> u64 {
+		self.data.lock().unwrap().len() as u64
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> std::io::Result<()> {
+		self.reads.fetch_add(1, Ordering::SeqCst);
+		buf.copy_from_slice(
+			&self.data.lock().unwrap()[offset as usize..offset as usize + buf.len()],
+		);
+		Ok(())
+	}
+
+	fn writable(&self) -> bool {
+		true
+	}
+
+	fn write_at(&self, offset: u64, data: &[u8]) -> std::io::Result<()> {
+		self.writes.fetch_add(1, Ordering::SeqCst);
+		self.data.lock().unwrap()[offset as usize..offset as usize + data.len()]
+			.copy_from_slice(data);
+		Ok(())
+	}
+}
+
+#[test]
+fn block_cache_keeps_recent_blocks() {
+	use crate::image::cache::{self, CacheMode, CacheSettings};
+	use std::time::Duration;
+
+	// 两块的缓存，磁盘的最后一块不满
+	let size = 4 * 65536 + 1000;
+	let disk = Arc::new(CountingDisk {
+		data: Mutex::new(noise(17, size)),
+		reads: AtomicUsize::new(0),
+		writes: AtomicUsize::new(0),
+	});
+	let settings = |mode| CacheSettings {
+		size: 2 * 65536,
+		mode,
+		flush_interval: Duration::from_secs(3600),
+	};
+	let counts = || {
+		(
+			disk.reads.load(Ordering::SeqCst),
+			disk.writes.load(Ordering::SeqCst),
+		)
+	};
+	let contents = || disk.data.lock().unwrap().clone();
+	let read = |device: &dyn crate::image::BlockDevice, offset: usize, length: usize| {
+		let mut buf = vec![0; length];
+		device.read_at(offset as u64, &mut buf).unwrap();
+		buf
+	};
+
+	// 写穿：读过的块不再读取，写入立即到达磁盘并更新缓存
+	let cached = cache::wrap(disk.clone(), &settings(CacheMode::WriteThrough));
+	assert_eq!(cached.size(), size as u64);
+	assert!(read(cached.as_ref(), 65000, 1000) == contents()[65000..66000]);
+	assert_eq!(counts(), (2, 0));
+	read(cached.as_ref(), 65500, 100);
+	cached.write_at(65530, b"through").unwrap();
+	assert_eq!(counts(), (2, 1));
+	assert!(&contents()[65530..65537] == b"through");
+	assert!(&read(cached.as_ref(), 65530, 7) == b"through");
+	assert!(read(cached.as_ref(), size - 10, 10) == contents()[size - 10..]);
+	assert_eq!(counts(), (3, 1));
+	assert!(cached.read_at(size as u64 - 1, &mut [0; 2]).is_err());
+	drop(cached);
+
+	// 写回：写入留在缓存中，换出或刷新时才写到磁盘；整块覆盖不必先读
+	let mut expected = contents();
+	let cached = cache::wrap(disk.clone(), &settings(CacheMode::WriteBack));
+	let (reads, writes) = counts();
+	cached.write_at(0, &[0xBB; 65536]).unwrap();
+	cached.write_at(65536 + 100, b"back").unwrap();
+	expected[..65536].fill(0xBB);
+	expected[65636..65640].copy_from_slice(b"back");
+	assert_eq!(counts(), (reads + 1, writes));
+	assert!(contents() != expected);
+	assert!(read(cached.as_ref(), 0, 70000) == expected[..70000]);
+	// 读第三块换出最久未使用的第一块
+	read(cached.as_ref(), 2 * 65536, 10);
+	assert_eq!(counts(), (reads + 2, writes + 1));
+	assert!(contents()[..65536] == expected[..65536]);
+	cached.flush().unwrap();
+	assert!(contents() == expected);
+	assert_eq!(counts(), (reads + 2, writes + 2));
+	cached.flush().unwrap();
+	assert_eq!(counts(), (reads + 2, writes + 2));
+
+	// 释放时写出剩余的脏块
+	cached.write_at(size as u64 - 4, b"tail").unwrap();
+	drop(cached);
+	expected[size - 4..].copy_from_slice(b"tail");
+	assert!(contents() == expected);
+}
+
+#[test]
+fn handler_serves_the_platform_neutral_operations() {
+	use crate::vfs::VirtualFs;
+
+	// Dokan 和 WinFsp 给出的路径
+	assert_eq!(crate::vfs::share_path("\\"), ".");
+	assert_eq!(crate::vfs::share_path("\\docs\\a.txt"), "docs/a.txt");
+
+	let handler = crate::HttpFsHandler::new(
+		Box::new(MemoryBackend::with_capacity(Some(1 << 20))),
+		false,
+		std::time::Duration::from_secs(60),
+	);
+	assert!(handler.stat(".").unwrap().is_directory);
+	assert!(!handler.read_only());
+
+	handler.create("docs", true).unwrap();
+	handler.create("docs/a.txt", false).unwrap();
+	handler.write("docs/a.txt", 0, b"hello world").unwrap();
+	assert_eq!(handler.read("docs/a.txt", 6, 100).unwrap(), b"world");
+	let listed = handler.list("docs").unwrap();
+	assert_eq!(
+		listed
+			.iter()
+			.map(|item| item.name.as_str())
+			.collect::<Vec<_>>(),
+		["a.txt"]
+	);
+	assert_eq!(listed[0].size, 11);
+
+	// 修改使缓存的属性失效
+	assert_eq!(handler.stat("docs/a.txt").unwrap().size, 11);
+	handler.truncate("docs/a.txt", 5).unwrap();
+	assert_eq!(handler.stat("docs/a.txt").unwrap().size, 5);
+	handler
+		.set_times(
+			"docs/a.txt",
+			&TimesUpdate {
+				created: None,
+				accessed: None,
+				modified: Some(1_000_000),
+			},
+		)
+		.unwrap();
+	assert_eq!(handler.stat("docs/a.txt").unwrap().modified, 1_000_000);
+
+	handler.create("docs/b.txt", false).unwrap();
+	assert_eq!(
+		error_code(handler.rename("docs/a.txt", "docs/b.txt", false)),
+		"already_exists"
+	);
+	handler.rename("docs/a.txt", "docs/b.txt", true).unwrap();
+	assert_eq!(error_code(handler.stat("docs/a.txt")), "not_found");
+	assert_eq!(handler.read("docs/b.txt", 0, 100).unwrap(), b"hello");
+	assert_eq!(error_code(handler.remove("docs")), "directory_not_empty");
+	handler.remove("docs/b.txt").unwrap();
+	handler.remove("docs").unwrap();
+	assert!(handler.list(".").unwrap().is_empty());
+	assert_eq!(handler.space().total, 1 << 20);
+}
+
+// 名称中的各种字符：空格、URL 中有特殊含义的字符、中文、emoji 以及末尾的点和空格，包括 CON 这样的设备名
+fn exotic_name() -> impl proptest::strategy::Strategy<Value = String> {
+	use proptest::prelude::*;
+
+	prop_oneof![
+		"[a-zA-Z0-9 #%&+;=@,'!$()\\[\\]{}~^`?.\\-\\p{Han}😀🎉]{1,12}",
+		prop::sample::select(vec![
+			"CON", "aux.txt", "NUL", "name.", "name ", "$ROOT", "a%2Fb", "%25"
+		])
+		.prop_map(str::to_string),
+	]
+	.prop_filter("names of files", |name| name != "." && name != "..")
+}
+
+#[test]
+fn share_paths_and_urls_keep_exotic_names() {
+	use percent_encoding::percent_decode_str;
+	use proptest::{collection::vec, prelude::*, test_runner::TestRunner};
+
+	TestRunner::default()
+		.run(&vec(exotic_name(), 1..5), |segments| {
+			// Dokan 和 WinFsp 给出的路径
+			let path = crate::vfs::share_path(&format!("\\{}", segments.join("\\")));
+			prop_assert_eq!(&path, &segments.join("/"));
+
+			// URL 中的路径解码后得到原来的路径，名称中的字符不会成为查询或片段
+			let encoded = super::encode_api_path(&path);
+			prop_assert!(
+				encoded
+					.bytes()
+					.all(|b| b.is_ascii_alphanumeric() || b"-._~/%".contains(&b)),
+				"{}",
+				encoded
+			);
+			let url = reqwest::Url::parse(&format!("http://localhost/read/{}?offset=0", encoded))
+				.unwrap();
+			prop_assert_eq!(url.query(), Some("offset=0"));
+			prop_assert_eq!(url.fragment(), None);
+			let decoded: Vec<String> = url
+				.path_segments()
+				.unwrap()
+				.skip(1)
+				.map(|segment| {
+					percent_decode_str(segment)
+						.decode_utf8()
+						.unwrap()
+						.into_owned()
+				})
+				.collect();
+			prop_assert_eq!(decoded, segments);
+			Ok(())
+		})
+		.unwrap();
+
+	assert_eq!(crate::vfs::share_path("\\"), ".");
+	assert_eq!(super::encode_api_path("."), "$ROOT");
+	assert_eq!(
+		super::encode_api_path("报告/#1 100%.txt"),
+		"%E6%8A%A5%E5%91%8A/%231%20100%25.txt"
+	);
+}
+
+#[test]
+fn long_paths_work_through_the_handler() {
+	use winapi::shared::ntstatus::STATUS_OBJECT_NAME_INVALID;
+
+	use super::mock::{MockBackend, Operation};
+	use crate::vfs::{VirtualFs, MAX_NAME_LENGTH};
+
+	let dir = TempDir::new();
+	let mock = MockBackend::new();
+	let backends: Vec<Box<dyn StorageBackend>> = vec![
+		Box::new(LocalBackend::open(&dir.0).unwrap()),
+		Box::new(mock.clone()),
+	];
+	for backend in backends {
+		let handler = crate::HttpFsHandler::new(backend, false, std::time::Duration::from_secs(60));
+		// 四层 80 个字符的目录和 255 个字符的文件名，共 579 个字符
+		let mut deep = String::from(".");
+		for level in 0..4 {
+			deep = crate::vfs::child_path(&deep, &format!("{}{}", level, "目".repeat(79)));
+			handler.create(&deep, true).unwrap();
+		}
+		let path = crate::vfs::child_path(&deep, &"f".repeat(MAX_NAME_LENGTH));
+		assert!(path.chars().count() > 300);
+		handler.create(&path, false).unwrap();
+		handler.write(&path, 0, b"deep").unwrap();
+		assert_eq!(handler.read(&path, 0, 10).unwrap(), b"deep");
+		assert_eq!(
+			handler
+				.list(&deep)
+				.unwrap()
+				.iter()
+				.map(|info| info.name.clone())
+				.collect::<Vec<_>>(),
+			["f".repeat(MAX_NAME_LENGTH)]
+		);
+
+		let renamed = crate::vfs::child_path(&deep, &"g".repeat(MAX_NAME_LENGTH));
+		handler.rename(&path, &renamed, false).unwrap();
+		assert_eq!(handler.stat(&renamed).unwrap().size, 4);
+		handler.remove(&renamed).unwrap();
+
+		// 更长的名称在请求存储之前被拒绝，按 UTF-16 单元计算长度
+		mock.reset_calls();
+		let too_long = crate::vfs::child_path(&deep, &"h".repeat(MAX_NAME_LENGTH + 1));
+		assert_eq!(
+			handler.create(&too_long, false).unwrap_err().to_ntstatus(),
+			STATUS_OBJECT_NAME_INVALID
+		);
+		assert_eq!(
+			handler
+				.create(&crate::vfs::child_path(&deep, &"😀".repeat(128)), false)
+				.unwrap_err()
+				.to_ntstatus(),
+			STATUS_OBJECT_NAME_INVALID
+		);
+		handler
+			.create(&crate::vfs::child_path(&deep, "x"), false)
+			.unwrap();
+		assert_eq!(
+			handler
+				.rename(&crate::vfs::child_path(&deep, "x"), &too_long, false)
+				.unwrap_err()
+				.to_ntstatus(),
+			STATUS_OBJECT_NAME_INVALID
+		);
+		assert_eq!(mock.calls(Operation::Rename), 0);
+	}
+
+	// 长路径中的每一段分别编码，分隔符保持不变
+	let segment = "段 #".repeat(100);
+	let encoded = super::encode_api_path(&format!("{0}/{0}/{0}", segment));
+	assert_eq!(encoded.matches('/').count(), 2);
+	assert!(encoded.len() > 3 * 300);
+}
+
+#[test]
+fn offsets_beyond_4_gib_are_exact() {
+	use winapi::shared::ntstatus::STATUS_INVALID_PARAMETER;
+
+	use super::mock::MockBackend;
+	use crate::vfs::VirtualFs;
+
+	const GIB: u64 = 1024 * 1024 * 1024;
+	let handler = crate::HttpFsHandler::new(
+		Box::new(MockBackend::new()),
+		false,
+		std::time::Duration::from_secs(60),
+	);
+	handler.create("big.img", false).unwrap();
+	handler.write("big.img", 5 * GIB - 4, b"tail").unwrap();
+	handler.write("big.img", 4 * GIB + 1, b"mid").unwrap();
+	let info = handler.stat("big.img").unwrap();
+	assert_eq!(info.size, 5 * GIB);
+	// 大小在 JSON 中不经过浮点数或 32 位整数
+	let json = serde_json::to_string(&info).unwrap();
+	assert!(json.contains("\"size\":5368709120"), "{}", json);
+	assert_eq!(
+		serde_json::from_str::<crate::RemoteFileInfo>(&json)
+			.unwrap()
+			.size,
+		5 * GIB
+	);
+
+	assert_eq!(handler.read("big.img", 4 * GIB + 1, 3).unwrap(), b"mid");
+	assert_eq!(handler.read("big.img", 1, 3).unwrap(), [0; 3]);
+	let mut buffer = [0; 8];
+	assert_eq!(
+		handler
+			.read_into("big.img", 5 * GIB - 4, &mut buffer)
+			.unwrap(),
+		4
+	);
+	assert_eq!(&buffer[..4], b"tail");
+	handler.truncate("big.img", 4 * GIB + 3).unwrap();
+	assert_eq!(handler.stat("big.img").unwrap().size, 4 * GIB + 3);
+	assert_eq!(handler.read("big.img", 4 * GIB, 10).unwrap(), b"\0mi");
+
+	// 驱动给出的负偏移不会变成接近 u64::MAX 的位置
+	assert_eq!(crate::file_offset(5 * GIB as i64), Ok(5 * GIB));
+	assert_eq!(crate::file_offset(-1), Err(STATUS_INVALID_PARAMETER));
+	assert_eq!(crate::staged_offset(u64::MAX), usize::MAX);
+}
+
+#[test]
+fn escaped_backend_conforms() {
+	use crate::names::NameEscaping;
+
+	for escaping in [NameEscaping::Hash, NameEscaping::PrivateUse] {
+		check_conformance(&EscapedBackend::new(
+			Box::new(MemoryBackend::with_capacity(None)),
+			escaping,
+		));
+	}
+}
+
+#[test]
+fn escaped_backend_shows_names_windows_cannot_use() {
+	use super::mock::MockBackend;
+	use crate::names::NameEscaping;
+
+	let inner = MockBackend::new();
+	for name in [
+		"CON",
+		"aux.txt",
+		"a:b",
+		"notes.",
+		"what?",
+		"#0041",
+		"plain.txt",
+	] {
+		inner.commit(name, name.as_bytes()).unwrap();
+	}
+	let backend = EscapedBackend::new(Box::new(inner.clone()), NameEscaping::Hash);
+	assert_eq!(
+		names(&backend, "."),
+		[
+			"#00230041",
+			"CO#004E",
+			"a#003Ab",
+			"au#0078.txt",
+			"notes#002E",
+			"plain.txt",
+			"what#003F"
+		]
+	);
+	assert_eq!(backend.stat("a#003Ab").unwrap().name, "a#003Ab");
+	assert_eq!(backend.read("CO#004E", 0, 100).unwrap(), b"CON");
+	assert_eq!(backend.read("#00230041", 0, 100).unwrap(), b"#0041");
+	// 普通的 # 不是转义
+	backend.commit("Issue #12.txt", b"").unwrap();
+	assert!(inner.stat("Issue #12.txt").is_ok());
+
+	// 在 Windows 中以转义的名称新建和改名，远程为原来的名称
+	backend.create("dir#003F", true).unwrap();
+	backend.commit("dir#003F/x#002A", b"star").unwrap();
+	assert_eq!(inner.read("dir?/x*", 0, 100).unwrap(), b"star");
+	backend
+		.rename("notes#002E", "dir#003F/notes#002E", false)
+		.unwrap();
+	assert_eq!(inner.read("dir?/notes.", 0, 100).unwrap(), b"notes.");
+	assert_eq!(names(&backend, "dir#003F"), ["notes#002E", "x#002A"]);
+
+	let private = EscapedBackend::new(Box::new(inner), NameEscaping::PrivateUse);
+	assert_eq!(private.read("a\u{F03A}b", 0, 100).unwrap(), b"a:b");
+	assert_eq!(private.stat("CO\u{F04E}").unwrap().name, "CO\u{F04E}");
+}
+
+#[test]
+fn name_escaping_round_trips() {
+	use proptest::{prelude::*, test_runner::TestRunner};
+
+	use crate::names::NameEscaping;
+
+	// Windows 能以这个名称保存文件
+	fn storable(name: &str) -> bool {
+		let stem = name
+			.split('.')
+			.next()
+			.unwrap()
+			.trim_end_matches(' ')
+			.to_ascii_uppercase();
+		let reserved = ["CON", "PRN", "AUX", "NUL"].contains(&stem.as_str())
+			|| (stem.len() == 4
+				&& (stem.starts_with("COM") || stem.starts_with("LPT"))
+				&& matches!(stem.as_bytes()[3], b'1'..=b'9'));
+		!reserved
+			&& !name.ends_with(['.', ' '])
+			&& !name
+				.chars()
+				.any(|c| c.is_ascii_control() || "<>:\"\\|?*".contains(c))
+	}
+
+	let name = prop_oneof![
+		"[^/\u{F000}-\u{F0FF}]{1,16}",
+		"(CON|con|Aux|NUL|COM1|lpt9|#00[0-7][0-9A-F]|[.: ?#])+",
+	]
+	.prop_filter("names of files", |name| name != "." && name != "..");
+	for escaping in [NameEscaping::Hash, NameEscaping::PrivateUse] {
+		TestRunner::default()
+			.run(&name, |name| {
+				let escaped = escaping.escape(&name);
+				prop_assert!(storable(&escaped), "{:?} escaped as {:?}", name, escaped);
+				prop_assert_eq!(escaping.unescape(&escaped), name.as_str());
+				Ok(())
+			})
+			.unwrap();
+	}
+	assert_eq!(NameEscaping::None.escape("CON"), "CON");
+	assert_eq!(
+		NameEscaping::Hash.escape_path("docs/CON/a:b"),
+		"docs/CO#004E/a#003Ab"
+	);
+	assert_eq!(
+		NameEscaping::Hash.unescape_path("docs/CO#004E/a#003Ab"),
+		"docs/CON/a:b"
+	);
+	assert_eq!(NameEscaping::PrivateUse.escape("a:b"), "a\u{F03A}b");
+}
+
+#[test]
+fn mock_backend_follows_its_script() {
+	use super::mock::{Fault, MockBackend, Operation};
+
+	let backend = MockBackend::new();
+	backend.create("a.txt", false).unwrap();
+	backend.write("a.txt", 0, b"hello world").unwrap();
+
+	// 限定次数的故障用完后照常执行
+	backend.inject(Fault::fail(Operation::Stat, "connection_lost").times(2));
+	assert_eq!(error_code(backend.stat("a.txt")), "connection_lost");
+	assert_eq!(error_code(backend.stat("a.txt")), "connection_lost");
+	assert_eq!(backend.stat("a.txt").unwrap().size, 11);
+	assert_eq!(backend.calls(Operation::Stat), 3);
+
+	// 限定路径的故障不影响其他路径
+	backend.create("b.txt", false).unwrap();
+	backend.inject(Fault::fail(Operation::Write, "disk_full").on("b.txt"));
+	assert_eq!(error_code(backend.write("b.txt", 0, b"data")), "disk_full");
+	backend.write("a.txt", 0, b"HELLO").unwrap();
+	backend.clear_faults();
+	backend.write("b.txt", 0, b"data").unwrap();
+
+	backend.inject(Fault::short_read(4).times(1));
+	assert_eq!(backend.read("a.txt", 0, 100).unwrap(), b"HELL");
+	let mut buffer = [0; 100];
+	assert_eq!(backend.read_into("a.txt", 0, &mut buffer).unwrap(), 11);
+
+	backend.inject(Fault::delay(Operation::List, std::time::Duration::from_millis(50)).times(1));
+	let start = std::time::Instant::now();
+	assert_eq!(names(&backend, "."), ["a.txt", "b.txt"]);
+	assert!(start.elapsed() >= std::time::Duration::from_millis(50));
+
+	// 克隆共享脚本和调用次数
+	let clone = backend.clone();
+	clone.inject(Fault::fail(Operation::Delete, "locked"));
+	assert_eq!(error_code(backend.delete("a.txt", false)), "locked");
+	assert_eq!(clone.calls(Operation::Delete), 1);
+	backend.reset_calls();
+	assert_eq!(clone.calls(Operation::Stat), 0);
+}
+
+#[test]
+fn handler_caches_and_maps_injected_faults() {
+	use winapi::shared::ntstatus::{
+		STATUS_DISK_FULL, STATUS_OBJECT_NAME_NOT_FOUND, STATUS_UNEXPECTED_NETWORK_ERROR,
+	};
+
+	use super::mock::{Fault, MockBackend, Operation};
+	use crate::vfs::VirtualFs;
+
+	let backend = MockBackend::new();
+	let handler = crate::HttpFsHandler::new(
+		Box::new(backend.clone()),
+		false,
+		std::time::Duration::from_secs(60),
+	);
+	handler.create("a.txt", false).unwrap();
+	handler.write("a.txt", 0, b"hello world").unwrap();
+
+	// 缓存的属性不再查询后端，写入使其失效
+	backend.reset_calls();
+	assert_eq!(handler.stat("a.txt").unwrap().size, 11);
+	assert_eq!(handler.stat("a.txt").unwrap().size, 11);
+	assert_eq!(backend.calls(Operation::Stat), 1);
+	handler.write("a.txt", 11, b"!").unwrap();
+	assert_eq!(handler.stat("a.txt").unwrap().size, 12);
+	assert_eq!(backend.calls(Operation::Stat), 2);
+
+	// 失败的查询不进入缓存，恢复后再次查询后端
+	backend.inject(
+		Fault::fail(Operation::Stat, "connection_lost")
+			.on("b.txt")
+			.times(1),
+	);
+	let error = handler.stat("b.txt").unwrap_err();
+	assert_eq!(error.to_ntstatus(), STATUS_UNEXPECTED_NETWORK_ERROR);
+	assert_eq!(
+		handler.stat("b.txt").unwrap_err().to_ntstatus(),
+		STATUS_OBJECT_NAME_NOT_FOUND
+	);
+	assert_eq!(backend.calls(Operation::Stat), 4);
+
+	// 失败的写入同样使缓存失效
+	backend.inject(Fault::fail(Operation::Write, "disk_full").times(1));
+	assert_eq!(
+		handler
+			.write("a.txt", 0, b"HELLO")
+			.unwrap_err()
+			.to_ntstatus(),
+		STATUS_DISK_FULL
+	);
+	assert_eq!(handler.stat("a.txt").unwrap().size, 12);
+	assert_eq!(backend.calls(Operation::Stat), 5);
+	assert_eq!(handler.read("a.txt", 0, 100).unwrap(), b"hello world!");
+
+	// 短于请求的读取原样交给调用方，由其决定是否继续读取
+	backend.inject(Fault::short_read(5).times(1));
+	let mut buffer = [0; 12];
+	assert_eq!(handler.read_into("a.txt", 0, &mut buffer).unwrap(), 5);
+	assert_eq!(handler.read_into("a.txt", 5, &mut buffer[5..]).unwrap(), 7);
+	assert_eq!(&buffer, b"hello world!");
+}
+
+#[test]
+fn inodes_follow_renames_and_removals() {
+	use crate::vfs::{Inodes, ROOT_INODE};
+
+	let mut inodes = Inodes::new();
+	assert_eq!(inodes.path(ROOT_INODE), Some("."));
+	let dir = inodes.lookup("docs");
+	let file = inodes.lookup("docs/a.txt");
+	assert_eq!(inodes.lookup("docs"), dir);
+	assert_eq!(inodes.find("docs/a.txt"), Some(file));
+	assert_eq!(inodes.find("other"), None);
+
+	// 重命名目录时其下的条目一起移动，覆盖的条目失去路径
+	let replaced = inodes.lookup("archive");
+	inodes.rename("docs", "archive");
+	assert_eq!(inodes.path(dir), Some("archive"));
+	assert_eq!(inodes.path(file), Some("archive/a.txt"));
+	assert_eq!(inodes.path(replaced), None);
+	assert_eq!(inodes.find("docs/a.txt"), None);
+
+	inodes.remove("archive");
+	assert_eq!(inodes.path(file), None);
+	assert_eq!(inodes.find("archive"), None);
+	let recreated = inodes.lookup("archive");
+	assert_ne!(recreated, dir);
+	assert_eq!(inodes.lookup("archive"), recreated);
+
+	// 两次查找需要都被 forget 才释放；根目录不释放
+	inodes.forget(recreated, 1);
+	assert_eq!(inodes.path(recreated), Some("archive"));
+	inodes.forget(recreated, 1);
+	assert_eq!(inodes.path(recreated), None);
+	inodes.forget(ROOT_INODE, 1);
+	assert_eq!(inodes.path(ROOT_INODE), Some("."));
+}
+
+#[test]
+fn hooks_wrap_operations_in_order() {
+	use crate::hooks::{Hooks, Operation, OperationHook};
+	use winapi::shared::{ntdef::NTSTATUS, ntstatus::STATUS_ACCESS_DENIED};
+
+	// 记录调用顺序；deny 为 true 时拒绝路径以 .exe 结尾的操作
+	struct Recorder {
+		name: &'static str,
+		deny: bool,
+		calls: Arc<Mutex<Vec<String>>>,
+	}
+
+	impl OperationHook for Recorder {
+		fn before(&self, op: &Operation) -> Result<(), NTSTATUS> {
+			self.calls.lock().unwrap().push(format!(
+				"{} before {} {}",
+				self.name,
+				op.name,
+				op.path.unwrap_or("-")
+			));
+			if self.deny && op.path.is_some_and(|path| path.ends_with(".exe")) {
+				return Err(STATUS_ACCESS_DENIED);
+			}
+			Ok(())
+		}
+
+		fn after(
+			&self,
+			op: &Operation,
+			result: Result<(), NTSTATUS>,
+			_elapsed: std::time::Duration,
+		) {
+			self.calls
+				.lock()
+				.unwrap()
+				.push(format!("{} after {} {:?}", self.name, op.name, result));
+		}
+	}
+
+	let calls = Arc::new(Mutex::new(Vec::new()));
+	let mut hooks = Hooks::new();
+	assert!(hooks.is_empty());
+	for (name, deny) in [("audit", false), ("policy", true), ("scan", false)] {
+		hooks.push(Recorder {
+			name,
+			deny,
+			calls: calls.clone(),
+		});
+	}
+
+	let op = Operation {
+		name: "read_file",
+		path: Some("docs/a.txt"),
+		new_path: None,
+	};
+	let (allowed, called) = hooks.before(&op);
+	assert_eq!((allowed, called), (Ok(()), 3));
+	hooks.after(&op, called, Ok(()), std::time::Duration::ZERO);
+	assert_eq!(
+		calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+		[
+			"audit before read_file docs/a.txt",
+			"policy before read_file docs/a.txt",
+			"scan before read_file docs/a.txt",
+			"scan after read_file Ok(())",
+			"policy after read_file Ok(())",
+			"audit after read_file Ok(())",
+		]
+	);
+
+	// 被拒绝时后面的钩子不再调用，已调用的钩子看到拒绝的状态
+	let op = Operation {
+		name: "create_file",
+		path: Some("tools/setup.exe"),
+		new_path: None,
+	};
+	let (allowed, called) = hooks.before(&op);
+	assert_eq!((allowed, called), (Err(STATUS_ACCESS_DENIED), 2));
+	hooks.after(&op, called, allowed, std::time::Duration::ZERO);
+	let denied = format!("{:?}", Err::<(), NTSTATUS>(STATUS_ACCESS_DENIED));
+	assert_eq!(
+		calls.lock().unwrap().drain(..).collect::<Vec<_>>(),
+		[
+			"audit before create_file tools/setup.exe".to_string(),
+			"policy before create_file tools/setup.exe".to_string(),
+			format!("policy after create_file {}", denied),
+			format!("audit after create_file {}", denied),
+		]
+	);
+}
+
+#[test]
+fn access_rules_match_paths_processes_and_users() {
+	use crate::{
+		access::{requested_rights, AccessRules, Action, Right, Rule},
+		requester::Requester,
+	};
+	use winapi::um::winnt::{DELETE, FILE_EXECUTE, FILE_READ_DATA, GENERIC_ALL, GENERIC_READ};
+
+	let rule = |path: &str, action: Action, access: &[Right]| Rule {
+		path: path.to_string(),
+		action,
+		access: access.to_vec(),
+		process: None,
+		user: None,
+	};
+	let explorer = Requester {
+		pid: 1,
+		process_name: Some("explorer.exe".to_string()),
+		user_sid: None,
+	};
+	let denied = |pattern: &str, path: &str| {
+		AccessRules::new(vec![rule(pattern, Action::Deny, &[])])
+			.check(path, &[Right::Read], &explorer)
+			.is_err()
+	};
+
+	// 不以 / 开头的模式匹配任意目录下的条目，* 和 ? 不跨越目录
+	assert!(denied("*.exe", "setup.exe"));
+	assert!(denied("*.exe", "tools/bin/Setup.EXE"));
+	assert!(!denied("*.exe", "tools/setup.exe.txt"));
+	assert!(denied("/releases/**", "releases/v1/app.zip"));
+	// 与 gitignore 相同，dir/** 只匹配目录中的条目
+	assert!(!denied("/releases/**", "releases"));
+	assert!(!denied("/releases/**", "old/releases/app.zip"));
+	assert!(denied("/releases/*", "releases/app.zip"));
+	assert!(!denied("/releases/*", "releases/v1/app.zip"));
+	assert!(denied("/docs/?.txt", "docs/a.txt"));
+	assert!(!denied("/docs/?.txt", "docs/ab.txt"));
+	assert!(denied("/**", "."));
+
+	// 每种操作由第一条适用的规则决定
+	let mut robocopy = rule(
+		"/releases/**",
+		Action::Allow,
+		&[Right::Write, Right::Delete],
+	);
+	robocopy.process = Some("robo*.exe".to_string());
+	let rules = AccessRules::new(vec![
+		rule("*.exe", Action::Deny, &[Right::Execute]),
+		robocopy,
+		rule("/releases/**", Action::Deny, &[Right::Write, Right::Delete]),
+	]);
+	let copier = Requester {
+		pid: 2,
+		process_name: Some("Robocopy.exe".to_string()),
+		user_sid: None,
+	};
+	assert_eq!(
+		rules.check("tools/setup.exe", &[Right::Read], &explorer),
+		Ok(())
+	);
+	assert_eq!(
+		rules.check("tools/setup.exe", &[Right::Read, Right::Execute], &explorer),
+		Err(Right::Execute)
+	);
+	assert_eq!(
+		rules.check("releases/app.zip", &[Right::Read], &explorer),
+		Ok(())
+	);
+	assert_eq!(
+		rules.check("releases/app.zip", &[Right::Read, Right::Write], &explorer),
+		Err(Right::Write)
+	);
+	assert_eq!(
+		rules.check("releases/app.zip", &[Right::Write, Right::Delete], &copier),
+		Ok(())
+	);
+	assert_eq!(
+		rules.check("docs/a.txt", &[Right::Write, Right::Delete], &explorer),
+		Ok(())
+	);
+
+	// 只对指定用户生效的规则
+	let mut guest = rule("**", Action::Deny, &[Right::Write]);
+	guest.user = Some("S-1-5-21-1-2-3-501".to_string());
+	let rules = AccessRules::new(vec![guest]);
+	let user = Requester {
+		pid: 3,
+		process_name: None,
+		user_sid: Some("S-1-5-21-1-2-3-501".to_string()),
+	};
+	assert_eq!(
+		rules.check("a.txt", &[Right::Write], &user),
+		Err(Right::Write)
+	);
+	assert_eq!(rules.check("a.txt", &[Right::Write], &explorer), Ok(()));
+
+	// 打开文件时请求的操作
+	assert_eq!(requested_rights(GENERIC_READ, false, false), [Right::Read]);
+	assert_eq!(
+		requested_rights(FILE_READ_DATA | FILE_EXECUTE, false, false),
+		[Right::Read, Right::Execute]
+	);
+	assert_eq!(
+		requested_rights(GENERIC_READ, true, false),
+		[Right::Read, Right::Write]
+	);
+	assert_eq!(requested_rights(DELETE, false, false), [Right::Delete]);
+	assert_eq!(requested_rights(0, false, true), [Right::Delete]);
+	assert_eq!(
+		requested_rights(GENERIC_ALL, false, false),
+		[Right::Read, Right::Write, Right::Delete, Right::Execute]
+	);
+}
+
+#[test]
+fn renames_check_access_rules_on_both_paths() {
+	use winapi::shared::ntstatus::STATUS_ACCESS_DENIED;
+
+	use super::mock::MockBackend;
+	use crate::{
+		access::{AccessRules, Action, Right, Rule},
+		identity::UserMap,
+		requester::Requester,
+	};
+
+	let rule = |path: &str, access: &[Right]| Rule {
+		path: path.to_string(),
+		action: Action::Deny,
+		access: access.to_vec(),
+		process: None,
+		user: None,
+	};
+	let handler = crate::HttpFsHandler::new(
+		Box::new(MockBackend::new()),
+		false,
+		std::time::Duration::from_secs(60),
+	)
+	.with_access_rules(AccessRules::new(vec![
+		rule("/releases/**", &[Right::Write]),
+		rule("/locked/**", &[Right::Delete]),
+	]))
+	.with_user_map(UserMap::new(
+		[("S-1-5-21-1-2-3-1001".to_string(), "alice".to_string())],
+		None,
+	));
+	let alice = Requester {
+		pid: 1,
+		process_name: Some("explorer.exe".to_string()),
+		user_sid: Some("S-1-5-21-1-2-3-1001".to_string()),
+	};
+
+	// 源路径要求删除权限，目标路径要求写入权限
+	assert_eq!(
+		handler.authorize_move("docs/a.txt", "docs/b.txt", &alice),
+		Ok(())
+	);
+	assert_eq!(
+		handler.authorize_move("docs/a.txt", "releases/a.txt", &alice),
+		Err(STATUS_ACCESS_DENIED)
+	);
+	assert_eq!(
+		handler.authorize_move("locked/a.txt", "docs/a.txt", &alice),
+		Err(STATUS_ACCESS_DENIED)
+	);
+	assert_eq!(
+		handler.authorize_move("releases/a.txt", "docs/a.txt", &alice),
+		Ok(())
+	);
+
+	// 没有映射到服务器用户的请求者不能重命名
+	let guest = Requester {
+		user_sid: Some("S-1-5-21-1-2-3-501".to_string()),
+		..alice
+	};
+	assert_eq!(
+		handler.authorize_move("docs/a.txt", "docs/b.txt", &guest),
+		Err(STATUS_ACCESS_DENIED)
+	);
+}
+
+#[test]
+fn process_policies_pick_the_first_matching_process() {
+	use crate::{
+		policy::{CachePolicy, ProcessPolicies, ProcessPolicy},
+		requester::Requester,
+	};
+
+	let policies = ProcessPolicies::new(vec![
+		ProcessPolicy {
+			process: "sqlservr.exe".to_string(),
+			cache: CachePolicy {
+				write_back: false,
+				attr_cache: true,
+			},
+		},
+		ProcessPolicy {
+			process: "*sql*.exe".to_string(),
+			cache: CachePolicy {
+				write_back: false,
+				attr_cache: false,
+			},
+		},
+	]);
+	let process = |name: Option<&str>| Requester {
+		pid: 1,
+		process_name: name.map(str::to_string),
+		user_sid: None,
+	};
+	assert_eq!(
+		policies.cache_policy(&process(Some("SQLServr.EXE"))),
+		CachePolicy {
+			write_back: false,
+			attr_cache: true
+		}
+	);
+	assert_eq!(
+		policies.cache_policy(&process(Some("mysqld.exe"))),
+		CachePolicy {
+			write_back: false,
+			attr_cache: false
+		}
+	);
+	assert_eq!(
+		policies.cache_policy(&process(Some("explorer.exe"))),
+		CachePolicy::default()
+	);
+	// 进程已退出或无法查询名称时使用默认方式
+	assert_eq!(
+		policies.cache_policy(&process(None)),
+		CachePolicy::default()
+	);
+	assert_eq!(
+		ProcessPolicies::default().cache_policy(&process(Some("sqlservr.exe"))),
+		CachePolicy::default()
+	);
+}
+
+#[test]
+fn open_files_are_listed_until_closed() {
+	use crate::{access::Right, open_files::OpenFiles, requester::Requester};
+
+	let files = Arc::new(OpenFiles::new());
+	let excel = Requester {
+		pid: 42,
+		process_name: Some("EXCEL.EXE".to_string()),
+		user_sid: None,
+	};
+	let report = files.open(
+		"docs/report.xlsx".to_string(),
+		None,
+		excel.clone(),
+		vec![Right::Read, Right::Write],
+		Default::default(),
+	);
+	let log = files.open(
+		"logs/app.log".to_string(),
+		Some("Zone.Identifier".to_string()),
+		excel,
+		vec![Right::Read],
+		Default::default(),
+	);
+	report.add_read(100);
+	report.add_written(30);
+	report.add_written(12);
+	assert_eq!(files.len(), 2);
+
+	let listed = files.list();
+	assert_eq!(
+		listed
+			.iter()
+			.map(|file| file.path.as_str())
+			.collect::<Vec<_>>(),
+		["docs/report.xlsx", "logs/app.log:Zone.Identifier"]
+	);
+	assert_eq!(
+		(listed[0].pid, listed[0].process.as_deref()),
+		(42, Some("EXCEL.EXE"))
+	);
+	assert_eq!(listed[0].access, [Right::Read, Right::Write]);
+	assert_eq!((listed[0].bytes_read, listed[0].bytes_written), (100, 42));
+	assert_eq!((listed[1].bytes_read, listed[1].bytes_written), (0, 0));
+
+	// 关闭时（上下文被释放）从表中移除
+	drop(report);
+	assert_eq!(
+		files
+			.list()
+			.iter()
+			.map(|file| file.path.as_str())
+			.collect::<Vec<_>>(),
+		["logs/app.log:Zone.Identifier"]
+	);
+	drop(log);
+	assert!(files.is_empty());
+}
+
+#[test]
+fn draining_commits_staged_writes_of_open_files() {
+	use crate::{requester::Requester, vfs::VirtualFs, StagedContent};
+
+	let handler = crate::HttpFsHandler::new(
+		Box::new(MemoryBackend::with_capacity(None)),
+		false,
+		std::time::Duration::from_secs(60),
+	);
+	handler.create("report.docx", false).unwrap();
+	let staged = Arc::new(Mutex::new(Some(StagedContent {
+		data: b"saved".to_vec(),
+		dirty: true,
+		times: TimesUpdate::default(),
+		journal: None,
+	})));
+	let open = handler.open_files.open(
+		"report.docx".to_string(),
+		None,
+		Requester::default(),
+		Vec::new(),
+		staged.clone(),
+	);
+
+	// 暂存的内容在等待之前提交，仍打开的文件数作为结果返回
+	assert_eq!(handler.drain(std::time::Duration::ZERO), 1);
+	assert_eq!(handler.read("report.docx", 0, 100).unwrap(), b"saved");
+	assert!(!staged.lock().unwrap().as_ref().unwrap().dirty);
+
+	drop(open);
+	assert_eq!(handler.drain(std::time::Duration::from_secs(5)), 0);
+}
+
+#[test]
+fn journaled_saves_are_replayed_after_a_crash() {
+	use crate::{
+		backend::Remote,
+		journal::{conflict_copy_path, Base, Journal, Version},
+	};
+
+	let dir = TempDir::new();
+	let remote = Remote::new("mem://");
+	let backend = MemoryBackend::with_capacity(None);
+	backend.create("notes.txt", false).unwrap();
+	backend.commit("notes.txt", b"original").unwrap();
+	backend.create("report.docx", false).unwrap();
+
+	backend.commit("shared.txt", b"v1").unwrap();
+	let version = |path: &str| Version::of(backend.stat(path).ok().as_ref());
+
+	let journal = Journal::open(&dir.0, &remote).unwrap();
+	// 新建的文件从空内容开始；崩溃前没有提交
+	let mut report = journal
+		.create("report.docx", None, Base::Empty, version("report.docx"))
+		.unwrap();
+	report.write(0, b"draft").unwrap();
+	report.write(5, b" two").unwrap();
+	// 提交过一次的文件从存储中的内容开始，之后的截断也被记录
+	let mut notes = journal
+		.create("notes.txt", None, Base::Empty, Version::Unknown)
+		.unwrap();
+	notes.write(0, b"ignored").unwrap();
+	notes.reset(version("notes.txt")).unwrap();
+	notes.set_len(4).unwrap();
+	notes.write(4, b"!").unwrap();
+	// 提交后没有新写入的日志无需重放
+	let mut idle = journal
+		.create("idle.txt", None, Base::Stored, Version::Absent)
+		.unwrap();
+	idle.reset(Version::Unknown).unwrap();
+	// 关闭时删除的日志不会留下
+	journal
+		.create("closed.txt", None, Base::Empty, Version::Absent)
+		.unwrap()
+		.discard();
+	// 保存之后其他客户端修改了存储中的文件
+	let mut shared = journal
+		.create("shared.txt", None, Base::Empty, version("shared.txt"))
+		.unwrap();
+	shared.write(0, b"mine").unwrap();
+	let location = journal.dir().to_path_buf();
+	drop((report, notes, idle, shared));
+	backend.commit("shared.txt", b"theirs").unwrap();
+
+	// 崩溃时写了一半的记录被忽略
+	let last = fs::read_dir(&location)
+		.unwrap()
+		.flatten()
+		.map(|entry| entry.path())
+		.max()
+		.unwrap();
+	fs::OpenOptions::new()
+		.append(true)
+		.open(&last)
+		.unwrap()
+		.write_all(&[1, 0, 0])
+		.unwrap();
+
+	let journal = Journal::open(&dir.0, &remote).unwrap();
+	let pending = journal.pending().unwrap();
+	assert_eq!(
+		pending
+			.iter()
+			.map(|save| save.path.as_str())
+			.collect::<Vec<_>>(),
+		["report.docx", "notes.txt", "idle.txt", "shared.txt"]
+	);
+	let conflicts: Vec<String> = pending
+		.into_iter()
+		.filter_map(|save| save.replay(&backend).unwrap().conflict)
+		.collect();
+	assert_eq!(backend.read("report.docx", 0, 100).unwrap(), b"draft two");
+	assert_eq!(backend.read("notes.txt", 0, 100).unwrap(), b"orig!");
+	// 冲突时不覆盖其他客户端的修改，保存的内容写入冲突副本
+	assert_eq!(conflicts.len(), 1);
+	assert!(conflicts[0].starts_with("shared (conflict ") && conflicts[0].ends_with(").txt"));
+	assert_eq!(backend.read("shared.txt", 0, 100).unwrap(), b"theirs");
+	assert_eq!(backend.read(&conflicts[0], 0, 100).unwrap(), b"mine");
+	assert_eq!(
+		conflict_copy_path("dir/report.final.docx", 0, 1),
+		"dir/report.final (conflict 1970-01-01).docx"
+	);
+	assert_eq!(
+		conflict_copy_path(".profile", 86400 * 366, 2),
+		".profile (conflict 1971-01-02 2)"
+	);
+	assert!(journal.pending().unwrap().is_empty());
+	assert_eq!(fs::read_dir(&location).unwrap().count(), 0);
+
+	// 另一个存储的日志在不同的子目录中
+	let other = Journal::open(&dir.0, &Remote::new("mem://other")).unwrap();
+	assert_ne!(other.dir(), journal.dir());
+}
+
+#[test]
+fn delta_finds_moved_blocks() {
+	use crate::delta::{diff, weak_checksum, BlockSignature, Signature};
+
+	let signature = |data: &[u8], block_size: usize| Signature {
+		size: data.len() as u64,
+		block_size,
+		blocks: data
+			.chunks(block_size)
+			.map(|block| BlockSignature {
+				weak: weak_checksum(block),
+				strong: hex::encode(&Sha256::digest(block)[..16]),
+			})
+			.collect(),
+	};
+	let old: Vec<u8> = (0..10_000u32)
+		.map(|i| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+		.collect();
+	let old_signature = signature(&old, 1024);
+
+	// 开头插入、中间替换之后，其余的块仍从原文件复制
+	let mut new = b"abc".to_vec();
+	new.extend_from_slice(&old[..5000]);
+	new.extend_from_slice(b"zz");
+	new.extend_from_slice(&old[6000..]);
+	let delta = diff(&old_signature, &new);
+	let mut rebuilt = vec![0u8; new.len()];
+	for copy in &delta.copies {
+		let (source, offset, length) = (
+			copy.source as usize,
+			copy.offset as usize,
+			copy.length as usize,
+		);
+		rebuilt[offset..offset + length].copy_from_slice(&old[source..source + length]);
+	}
+	for &(start, end) in &delta.literals {
+		rebuilt[start as usize..end as usize].copy_from_slice(&new[start as usize..end as usize]);
+	}
+	assert_eq!(rebuilt, new);
+	assert_eq!(delta.copies[0].length, 4096);
+	assert!(delta.literal_len() < 3000);
+
+	// 不足一块的末尾总是上传；没有原内容时全部上传
+	assert_eq!(diff(&old_signature, &old).literals, [(9216, 10_000)]);
+	assert_eq!(diff(&signature(b"", 1024), b"xy").literals, [(0, 2)]);
+}
+
+#[test]
+fn upload_queue_runs_files_in_parallel_and_each_file_in_order() {
+	use std::sync::mpsc;
+
+	use crate::upload_queue::UploadQueue;
+
+	let queue = UploadQueue::new(2);
+	let order = Arc::new(Mutex::new(Vec::new()));
+	let (release, blocked) = mpsc::channel::<()>();
+	let record = |name: &'static str| {
+		let order = order.clone();
+		move || order.lock().unwrap().push(name)
+	};
+
+	// a.txt 的第一次上传阻塞时，第二次上传等待它，b.txt 由另一个线程上传
+	let first = record("a1");
+	queue.submit("a.txt", move || {
+		blocked.recv().unwrap();
+		first();
+	});
+	queue.submit("a.txt", record("a2"));
+	queue.submit("b.txt", record("b"));
+	queue.wait_for("b.txt");
+	assert_eq!(*order.lock().unwrap(), ["b"]);
+	assert_eq!(queue.len(), 2);
+
+	release.send(()).unwrap();
+	queue.wait_for("a.txt");
+	assert_eq!(*order.lock().unwrap(), ["b", "a1", "a2"]);
+	assert!(queue.is_empty());
+
+	// 释放时等待积压的上传完成
+	queue.submit("c.txt", record("c"));
+	drop(queue);
+	assert_eq!(order.lock().unwrap().last(), Some(&"c"));
+}
+
+// 记录批量提交次数的内存后端
+struct CountingBatches {
+	inner: MemoryBackend,
+	batches: Mutex<Vec<usize>>,
+}
+
+impl StorageBackend for CountingBatches {
+	fn stat(&self, path: &str) -> Result<crate::RemoteFileInfo, crate::error::RemoteError> {
+		self.inner.stat(path)
+	}
+
+	fn list_page(
+		&self,
+		path: &str,
+		cursor: Option<&str>,
+	) -> Result<crate::ListPage, crate::error::RemoteError> {
+		self.inner.list_page(path, cursor)
+	}
+
+	fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, crate::error::RemoteError> {
+		self.inner.read(path, offset, length)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), crate::error::RemoteError> {
+		self.inner.write(path, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), crate::error::RemoteError> {
+		self.inner.commit(path, data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), crate::error::RemoteError> {
+		self.inner.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), crate::error::RemoteError> {
+		self.inner.delete(path, dry_run)
+	}
+
+	fn rename(
+		&self,
+		old_path: &str,
+		new_path: &str,
+		replace: bool,
+	) -> Result<(), crate::error::RemoteError> {
+		self.inner.rename(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), crate::error::RemoteError> {
+		self.inner.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), crate::error::RemoteError> {
+		self.inner.set_times(path, times)
+	}
+
+	fn commit_batch(
+		&self,
+		files: &[super::BatchFile],
+	) -> Result<Vec<Result<(), crate::error::RemoteError>>, crate::error::RemoteError> {
+		self.batches.lock().unwrap().push(files.len());
+		self.inner.commit_batch(files)
+	}
+}
+
+#[test]
+fn packer_commits_closed_small_files_together() {
+	use crate::{attr_cache::AttrCache, metrics::Metrics, packer::Packer, StagedContent};
+
+	let backend = Arc::new(CountingBatches {
+		inner: MemoryBackend::with_capacity(None),
+		batches: Mutex::new(Vec::new()),
+	});
+	backend.create("dir", true).unwrap();
+	let packer = Packer::new(
+		backend.clone(),
+		Arc::new(AttrCache::new(std::time::Duration::from_secs(60))),
+		Arc::new(Metrics::new()),
+	);
+	let staged = |data: &[u8], modified: Option<u64>| {
+		Arc::new(Mutex::new(Some(StagedContent {
+			data: data.to_vec(),
+			dirty: true,
+			times: TimesUpdate {
+				modified,
+				..Default::default()
+			},
+			journal: None,
+		})))
+	};
+
+	let files: Vec<_> = (0..50)
+		.map(|i| {
+			(
+				format!("dir/{}.txt", i),
+				staged(format!("file {}", i).as_bytes(), Some(1_000_000 + i)),
+			)
+		})
+		.collect();
+	for (path, content) in &files {
+		packer.submit(path, content.clone(), 8);
+	}
+	// 父目录不存在的文件失败，不影响同一批中的其他文件
+	let missing = staged(b"lost", None);
+	packer.submit("missing/a.txt", missing.clone(), 4);
+	packer.wait_idle();
+
+	assert_eq!(backend.batches.lock().unwrap().iter().sum::<usize>(), 51);
+	assert!(backend.batches.lock().unwrap().len() < 5);
+	assert_eq!(backend.read("dir/7.txt", 0, 100).unwrap(), b"file 7");
+	assert_eq!(backend.stat("dir/7.txt").unwrap().modified, 1_000_007);
+	assert!(files
+		.iter()
+		.all(|(_, content)| !content.lock().unwrap().as_ref().unwrap().dirty));
+	assert!(missing.lock().unwrap().as_ref().unwrap().dirty);
+
+	// 同一文件的下一次提交等待上一次完成；释放时提交排队的文件
+	packer.submit("dir/7.txt", staged(b"again", None), 5);
+	packer.wait_for("dir/7.txt");
+	assert_eq!(backend.read("dir/7.txt", 0, 100).unwrap(), b"again");
+	packer.submit("dir/last.txt", staged(b"last", None), 4);
+	drop(packer);
+	assert_eq!(backend.read("dir/last.txt", 0, 100).unwrap(), b"last");
+}
+
+#[test]
+fn attr_cache_evicts_by_policy_and_keeps_pinned_entries() {
+	use crate::attr_cache::{AttrCache, CacheLimits, Eviction};
+
+	let info = |name: &str| super::file_info(name, 1, 0);
+	let cache = |eviction| {
+		let cache = AttrCache::new(std::time::Duration::from_secs(60));
+		cache.set_limits(CacheLimits {
+			max_entries: Some(10),
+			eviction,
+			pinned: vec!["/keep/**".to_string()],
+			..Default::default()
+		});
+		cache
+	};
+
+	// LRU 换出最久未使用的条目，一次换出到上限的九成；固定的条目不会被换出
+	let lru = cache(Eviction::Lru);
+	lru.insert("keep/a".to_string(), info("a"));
+	for i in 0..9 {
+		lru.insert(format!("f{}", i), info("f"));
+	}
+	assert!(lru.get("f0").is_some());
+	lru.insert("new".to_string(), info("new"));
+	let stats = lru.stats();
+	assert_eq!((stats.entries, stats.pinned, stats.evictions), (9, 1, 2));
+	assert!(lru.get("keep/a").is_some() && lru.get("f0").is_some() && lru.get("new").is_some());
+	assert!(lru.get("f1").is_none() && lru.get("f2").is_none());
+
+	// LFU 换出命中次数最少的条目
+	let lfu = cache(Eviction::Lfu);
+	for i in 0..10 {
+		lfu.insert(format!("f{}", i), info("f"));
+		for _ in 0..i {
+			lfu.get(&format!("f{}", i));
+		}
+	}
+	lfu.get("f0");
+	lfu.get("f0");
+	lfu.insert("new".to_string(), info("new"));
+	assert!(lfu.get("f0").is_some() && lfu.get("f1").is_none() && lfu.get("f2").is_none());
+
+	// 只按过期清理时，满了就不再缓存新条目
+	let ttl_only = cache(Eviction::TtlOnly);
+	for i in 0..10 {
+		ttl_only.insert(format!("f{}", i), info("f"));
+	}
+	ttl_only.insert("new".to_string(), info("new"));
+	assert!(ttl_only.get("new").is_none());
+	assert_eq!(ttl_only.stats().entries, 10);
+
+	// 内存上限按估计的占用计算
+	let small = AttrCache::new(std::time::Duration::from_secs(60));
+	small.set_limits(CacheLimits {
+		max_bytes: Some(4096),
+		..Default::default()
+	});
+	for i in 0..1000 {
+		small.insert(format!("dir/file-{}.txt", i), info("file.txt"));
+	}
+	let stats = small.stats();
+	assert!(stats.bytes <= 4096 && stats.entries > 0);
+	assert!(small.get("dir/file-999.txt").is_some());
+}
+
+// 置位后像服务器不可达一样失败
+struct Unreachable {
+	inner: MemoryBackend,
+	down: std::sync::atomic::AtomicBool,
+}
+
+impl Unreachable {
+	fn check(&self) -> Result<(), crate::error::RemoteError> {
+		if self.down.load(Ordering::SeqCst) {
+			return Err(crate::error::RemoteError::backend(
+				"connection_lost",
+				"server unreachable",
+			));
+		}
+		Ok(())
+	}
+}
+
+impl StorageBackend for Unreachable {
+	fn stat(&self, path: &str) -> Result<crate::RemoteFileInfo, crate::error::RemoteError> {
+		self.check()?;
+		self.inner.stat(path)
+	}
+
+	fn list_page(
+		&self,
+		path: &str,
+		cursor: Option<&str>,
+	) -> Result<crate::ListPage, crate::error::RemoteError> {
+		self.check()?;
+		self.inner.list_page(path, cursor)
+	}
+
+	fn read(
+		&self,
+		path: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, crate::error::RemoteError> {
+		self.check()?;
+		self.inner.read(path, offset, length)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.write(path, offset, data)
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.commit(path, data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.create(path, is_directory)
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.delete(path, dry_run)
+	}
+
+	fn rename(
+		&self,
+		old_path: &str,
+		new_path: &str,
+		replace: bool,
+	) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.rename(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.truncate(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), crate::error::RemoteError> {
+		self.check()?;
+		self.inner.set_times(path, times)
+	}
+}
+
+#[test]
+fn offline_cache_serves_pinned_paths_while_unreachable() {
+	use crate::offline::{OfflineBackend, OfflineCache};
+
+	let dir = TempDir::new();
+	let remote = Arc::new(Unreachable {
+		inner: MemoryBackend::with_capacity(None),
+		down: Default::default(),
+	});
+	for path in ["docs", "docs/reports", "other"] {
+		remote.create(path, true).unwrap();
+	}
+	remote
+		.commit("docs/reports/q1.txt", b"first quarter")
+		.unwrap();
+	remote
+		.commit("docs/reports/q2.txt", b"second quarter")
+		.unwrap();
+	remote.commit("docs/readme.txt", b"not pinned").unwrap();
+	remote.commit("other/a.txt", b"not pinned").unwrap();
+
+	let cache = Arc::new(
+		OfflineCache::open(
+			&dir.0,
+			&super::Remote::new("memory:"),
+			&["/docs/reports".to_string()],
+		)
+		.unwrap(),
+	);
+	assert!(cache.is_pinned("docs/reports/q1.txt") && !cache.is_pinned("docs/readme.txt"));
+	let report = cache.sync_path(remote.as_ref(), "docs/reports").unwrap();
+	assert_eq!((report.downloaded, report.bytes), (2, 27));
+	// 没有变化的文件不再下载，删除的文件的副本随之删除
+	remote.delete("docs/reports/q2.txt", false).unwrap();
+	let report = cache.sync_path(remote.as_ref(), "docs/reports").unwrap();
+	assert_eq!((report.downloaded, report.removed), (0, 1));
+
+	let backend = OfflineBackend::new(remote.clone(), cache.clone());
+	remote.down.store(true, Ordering::SeqCst);
+	// 固定的条目和通往它们的目录可以列出和读取，其他路径仍然失败
+	assert_eq!(names(&backend, "."), ["docs"]);
+	assert_eq!(names(&backend, "docs"), ["reports"]);
+	assert_eq!(names(&backend, "docs/reports"), ["q1.txt"]);
+	assert_eq!(backend.stat("docs/reports/q1.txt").unwrap().size, 13);
+	assert_eq!(
+		backend.read("docs/reports/q1.txt", 6, 100).unwrap(),
+		b"quarter"
+	);
+	assert_eq!(
+		error_code(backend.read("docs/readme.txt", 0, 100)),
+		"connection_lost"
+	);
+	assert_eq!(error_code(backend.stat("other")), "connection_lost");
+	assert_eq!(
+		error_code(backend.commit("docs/reports/q1.txt", b"changed")),
+		"connection_lost"
+	);
+
+	// 服务器恢复后使用存储的内容
+	remote.down.store(false, Ordering::SeqCst);
+	remote.commit("docs/reports/q1.txt", b"revised").unwrap();
+	assert_eq!(
+		backend.read("docs/reports/q1.txt", 0, 100).unwrap(),
+		b"revised"
+	);
+	cache
+		.sync_path(remote.as_ref(), "docs/reports/q1.txt")
+		.unwrap();
+	remote.down.store(true, Ordering::SeqCst);
+	assert_eq!(
+		backend.read("docs/reports/q1.txt", 0, 100).unwrap(),
+		b"revised"
+	);
+}
+
+#[test]
+fn offline_cache_hydrates_read_files_and_dehydrates_them() {
+	use crate::offline::{Dehydrated, OfflineBackend, OfflineCache};
+
+	let dir = TempDir::new();
+	let remote = Arc::new(Unreachable {
+		inner: MemoryBackend::with_capacity(None),
+		down: Default::default(),
+	});
+	remote.create("dir", true).unwrap();
+	remote.commit("dir/a.txt", b"placeholder").unwrap();
+	remote.commit("pinned.txt", b"always here").unwrap();
+	let cache = Arc::new(
+		OfflineCache::open(
+			&dir.0,
+			&super::Remote::new("memory:"),
+			&["pinned.txt".to_string()],
+		)
+		.unwrap(),
+	);
+	cache.sync_path(remote.as_ref(), "pinned.txt").unwrap();
+	let backend = OfflineBackend::new(remote.clone(), cache.clone());
+
+	// 占位符第一次读取时从存储读取，之后在后台下载
+	assert!(!cache.is_hydrated("dir/a.txt"));
+	assert_eq!(backend.read("dir/a.txt", 0, 100).unwrap(), b"placeholder");
+	assert_eq!(
+		cache
+			.sync_path(remote.as_ref(), "dir/a.txt")
+			.unwrap()
+			.downloaded,
+		1
+	);
+	assert!(cache.is_hydrated("dir/a.txt"));
+	assert_eq!(
+		cache.usage(),
+		Dehydrated {
+			files: 2,
+			bytes: 22
+		}
+	);
+	remote.down.store(true, Ordering::SeqCst);
+	assert_eq!(backend.read("dir/a.txt", 0, 5).unwrap(), b"place");
+	assert_eq!(names(&backend, "dir"), ["a.txt"]);
+
+	// 删除副本后重新成为占位符，固定的文件保留
+	assert_eq!(
+		cache.dehydrate("/").unwrap(),
+		Dehydrated {
+			files: 1,
+			bytes: 11
+		}
+	);
+	assert!(!cache.is_hydrated("dir/a.txt") && cache.is_hydrated("pinned.txt"));
+	assert_eq!(
+		error_code(backend.read("dir/a.txt", 0, 5)),
+		"connection_lost"
+	);
+	assert_eq!(backend.read("pinned.txt", 0, 6).unwrap(), b"always");
+}
+
+#[test]
+fn atomic_save_turns_office_save_into_one_replace() {
+	use crate::atomic_save::AtomicSaveBackend;
+
+	let inner = Arc::new(MemoryBackend::with_capacity(None));
+	inner.commit("doc.docx", b"old").unwrap();
+	let backend = AtomicSaveBackend::new(inner.clone(), vec!["*.tmp".to_string()]);
+
+	// Word 的保存：写入临时文件，原文件改名为临时名称，临时文件改名为原文件，删除改名后的原文件
+	backend.create("~WRL0001.tmp", false).unwrap();
+	backend.write("~WRL0001.tmp", 0, b"new content").unwrap();
+	assert_eq!(names(inner.as_ref(), "."), ["doc.docx"]);
+	backend.rename("doc.docx", "~WRL0002.tmp", false).unwrap();
+	assert_eq!(names(&backend, "."), ["~WRL0001.tmp", "~WRL0002.tmp"]);
+	assert_eq!(error_code(backend.stat("doc.docx")), "not_found");
+	assert_eq!(backend.read("~WRL0002.tmp", 0, 100).unwrap(), b"old");
+	backend.rename("~WRL0001.tmp", "doc.docx", false).unwrap();
+	backend.delete("~WRL0002.tmp", false).unwrap();
+
+	// 存储中只有原文件被替换为新内容
+	assert_eq!(names(&backend, "."), ["doc.docx"]);
+	assert_eq!(names(inner.as_ref(), "."), ["doc.docx"]);
+	assert_eq!(inner.read("doc.docx", 0, 100).unwrap(), b"new content");
+
+	// 没有完成保存的临时文件在释放时提交
+	backend.create("other.tmp", false).unwrap();
+	backend.commit("other.tmp", b"kept").unwrap();
+	drop(backend);
+	assert_eq!(inner.read("other.tmp", 0, 100).unwrap(), b"kept");
+}
+
+#[test]
+fn user_map_resolves_sids_and_scopes_identity() {
+	use crate::identity::{self, UserMap};
+
+	let dir = TempDir::new();
+	let path = dir.0.join("users.toml");
+	fs::write(
+		&path,
+		"default = \"guest\"\n\n[users]\n\"S-1-5-21-1-2-3-1001\" = \"alice\"\n",
+	)
+	.unwrap();
+	let users = UserMap::load(&path).unwrap();
+	// SID 不区分大小写，没有列出或查不到的用户使用 default
+	assert_eq!(users.user_for(Some("s-1-5-21-1-2-3-1001")), Some("alice"));
+	assert_eq!(users.user_for(Some("S-1-5-21-1-2-3-1002")), Some("guest"));
+	assert_eq!(users.user_for(None), Some("guest"));
+	let strict = UserMap::new(
+		[("S-1-5-21-1-2-3-1001".to_string(), "alice".to_string())],
+		None,
+	);
+	assert_eq!(strict.user_for(Some("S-1-5-21-1-2-3-1002")), None);
+
+	// 用户名作为请求头发送
+	fs::write(&path, "[users]\n\"S-1-5-21-1-2-3-1001\" = \"ali\\nce\"\n").unwrap();
+	assert!(UserMap::load(&path).is_err());
+	fs::write(
+		&path,
+		"[users]\n\"S-1-5-21-1-2-3-1001\" = \"alice\"\nunknown = 1\n",
+	)
+	.unwrap();
+	assert!(UserMap::load(&path).is_err());
+
+	// 身份只在操作期间有效，嵌套时恢复外层的身份
+	assert_eq!(identity::current_user(), None);
+	identity::with_user(Some("alice"), || {
+		assert_eq!(identity::current_user().as_deref(), Some("alice"));
+		identity::with_user(None, || assert_eq!(identity::current_user(), None));
+		assert_eq!(identity::current_user().as_deref(), Some("alice"));
+		// 其他线程不继承当前线程的身份
+		thread::spawn(|| assert_eq!(identity::current_user(), None))
+			.join()
+			.unwrap();
+	});
+	assert_eq!(identity::current_user(), None);
+}
+
+#[test]
+fn token_that_cannot_be_sent_is_a_configuration_error() {
+	assert!(crate::check_token("secret").is_ok());
+	let error = crate::check_token("secret\r\nX-Injected: 1").unwrap_err();
+	assert_eq!(error.code(), Some("invalid_input"));
+	assert!(crate::auth_headers(Some("résumé")).is_err());
+	assert!(crate::auth_headers(None).unwrap().is_empty());
+}
@@ -0,0 +1,504 @@
+use std::{error::Error, time::Duration};
+
+use percent_encoding::percent_decode_str;
+use reqwest::{
+	blocking::{Client, RequestBuilder, Response},
+	header::{CONTENT_TYPE, RANGE},
+	Method, StatusCode, Url,
+};
+
+use super::{base_name, child_text, parse_timestamp, parse_xml, uri_encode, StorageBackend};
+use crate::{
+	auth_headers,
+	backend::Remote,
+	error::{ApiError, RemoteError, SendRetrying},
+	ListPage, RemoteFileInfo, SpaceResponse, TimesUpdate,
+};
+
+// 查询条目属性时请求的属性
+const PROPFIND_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:resourcetype/><D:getcontentlength/><D:getlastmodified/><D:creationdate/></D:prop></D:propfind>"#;
+
+// RFC 4331 的配额属性，只在查询容量时请求，部分服务器计算它们的开销较大
+const QUOTA_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:propfind xmlns:D="DAV:"><D:prop><D:quota-available-bytes/><D:quota-used-bytes/></D:prop></D:propfind>"#;
+
+const LOCK_BODY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<D:lockinfo xmlns:D="DAV:"><D:lockscope><D:exclusive/></D:lockscope><D:locktype><D:write/></D:locktype><D:owner>httpfs</D:owner></D:lockinfo>"#;
+
+// 修改文件期间持有的写锁的有效期，客户端异常退出时锁在这之后由服务器释放
+const LOCK_TIMEOUT: &str = "Second-60";
+
+fn method(name: &'static str) -> Method {
+	Method::from_bytes(name.as_bytes()).unwrap()
+}
+
+// 把 WebDAV 的状态码换成与 httpfs 服务器相同的错误类别，409 表示父集合不存在
+fn check(response: Response) -> Result<Response, RemoteError> {
+	let status = response.status();
+	if status.is_success() {
+		return Ok(response);
+	}
+	let code = match status {
+		StatusCode::NOT_FOUND | StatusCode::GONE => "not_found",
+		StatusCode::CONFLICT => "parent_not_found",
+		StatusCode::PRECONDITION_FAILED => "already_exists",
+		StatusCode::METHOD_NOT_ALLOWED => "method_not_allowed",
+		StatusCode::LOCKED => "locked",
+		StatusCode::INSUFFICIENT_STORAGE => "disk_full",
+		StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+		StatusCode::RANGE_NOT_SATISFIABLE => "invalid_range",
+		StatusCode::TOO_MANY_REQUESTS | StatusCode::SERVICE_UNAVAILABLE => "rate_limited",
+		_ => "http_error",
+	};
+	let request_id = response
+		.headers()
+		.get("x-request-id")
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string);
+	// Nextcloud 等 SabreDAV 服务器在 <d:error> 中给出 <s:message>
+	let body = response.text().unwrap_or_default();
+	let message = roxmltree::Document::parse(&body)
+		.ok()
+		.and_then(|doc| child_text(doc.root_element(), "message").map(str::to_string))
+		.filter(|message| !message.is_empty())
+		.unwrap_or_else(|| status.canonical_reason().unwrap_or("error").to_string());
+	Err(RemoteError::Api {
+		status,
+		error: ApiError {
+			code: code.to_string(),
+			message,
+			os_error: None,
+		},
+		request_id,
+	})
+}
+
+// href 可以是绝对 URL 或绝对路径，统一为解码后不带末尾 / 的路径
+fn href_path(href: &str) -> String {
+	let path = match href.split_once("://") {
+		Some((_, rest)) => rest.find('/').map_or("", |start| &rest[start..]),
+		None => href,
+	};
+	percent_decode_str(path)
+		.decode_utf8_lossy()
+		.trim_end_matches('/')
+		.to_string()
+}
+
+// PROPFIND 多状态响应中的一个条目
+struct DavEntry {
+	path: String,
+	info: RemoteFileInfo,
+}
+
+fn parse_multistatus(body: &str) -> Result<Vec<DavEntry>, RemoteError> {
+	let doc = parse_xml(body)?;
+	let mut entries = Vec::new();
+	for response in doc
+		.root_element()
+		.children()
+		.filter(|node| node.has_tag_name("response"))
+	{
+		let Some(href) = child_text(response, "href") else {
+			continue;
+		};
+		// 只使用状态为 200 的 propstat，服务器不支持的属性在另一个 propstat 中返回 404
+		let Some(prop) = response
+			.children()
+			.filter(|node| node.has_tag_name("propstat"))
+			.find(|propstat| {
+				child_text(*propstat, "status").is_some_and(|status| status.contains(" 200 "))
+			})
+			.and_then(|propstat| propstat.children().find(|node| node.has_tag_name("prop")))
+		else {
+			continue;
+		};
+		let is_directory = prop
+			.children()
+			.find(|node| node.has_tag_name("resourcetype"))
+			.is_some_and(|resource_type| {
+				resource_type
+					.children()
+					.any(|node| node.has_tag_name("collection"))
+			});
+		let modified = child_text(prop, "getlastmodified")
+			.and_then(|value| httpdate::parse_http_date(value).ok())
+			.and_then(|time| time.duration_since(std::time::UNIX_EPOCH).ok())
+			.map_or(0, |since| since.as_secs());
+		let path = href_path(href);
+		entries.push(DavEntry {
+			info: RemoteFileInfo {
+				name: percent_decode_str(base_name(href.trim_end_matches('/')))
+					.decode_utf8_lossy()
+					.into_owned(),
+				is_directory,
+				size: if is_directory {
+					0
+				} else {
+					child_text(prop, "getcontentlength")
+						.and_then(|size| size.parse().ok())
+						.unwrap_or(0)
+				},
+				created: child_text(prop, "creationdate")
+					.and_then(parse_timestamp)
+					.unwrap_or(modified),
+				modified,
+				accessed: modified,
+				stored_size: None,
+				allocated_size: None,
+			},
+			path,
+		});
+	}
+	Ok(entries)
+}
+
+// WebDAV 服务器（如 Nextcloud、SharePoint、Apache mod_dav）上的一个目录。
+// 用户名和密码写在 URL 中时以 Basic 认证发送，--token 以 Bearer 认证发送。
+// 文件不能原地修改，write 和 truncate 在排他写锁（服务器支持 LOCK 时）下读出整个文件修改后重新上传；
+// 修改时间由服务器在写入时设置
+pub struct WebDavBackend {
+	client: Client,
+	// 共享根目录的 URL，不含用户信息和末尾的 /
+	base_url: String,
+	// base_url 解码后的路径部分，用于在 PROPFIND 结果中识别条目
+	base_path: String,
+	credentials: Option<(String, String)>,
+}
+
+impl WebDavBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() {
+			return Err(
+				"--share does not apply to dav:// URLs; put the directory in the URL instead"
+					.into(),
+			);
+		}
+		// dav:// 使用 HTTP，davs:// 使用 HTTPS
+		let url = match remote.server_url.split_once("://") {
+			Some((scheme, rest)) if scheme.eq_ignore_ascii_case("davs") => {
+				format!("https://{}", rest)
+			}
+			Some((_, rest)) => format!("http://{}", rest),
+			None => remote.server_url.clone(),
+		};
+		let mut url = Url::parse(&url)
+			.map_err(|e| format!("invalid WebDAV URL {}: {}", remote.server_url, e))?;
+		let credentials = if url.username().is_empty() {
+			None
+		} else {
+			let decode = |text: &str| percent_decode_str(text).decode_utf8_lossy().into_owned();
+			Some((
+				decode(url.username()),
+				decode(url.password().unwrap_or_default()),
+			))
+		};
+		let _ = url.set_username("");
+		let _ = url.set_password(None);
+		let base_url = url.as_str().trim_end_matches('/').to_string();
+
+		Ok(Self {
+			client: Client::builder()
+				.timeout(Duration::from_secs(30))
+				.default_headers(auth_headers(remote.token.as_deref())?)
+				.build()?,
+			base_path: href_path(url.path()),
+			base_url,
+			credentials,
+		})
+	}
+
+	// 集合的 URL 以 / 结尾，否则部分服务器会先重定向
+	fn url(&self, path: &str, is_directory: bool) -> String {
+		match (path, is_directory) {
+			(".", _) => format!("{}/", self.base_url),
+			(_, true) => format!("{}/{}/", self.base_url, uri_encode(path, true)),
+			(_, false) => format!("{}/{}", self.base_url, uri_encode(path, true)),
+		}
+	}
+
+	// 与 href_path 的结果比较的条目路径
+	fn entry_path(&self, path: &str) -> String {
+		if path == "." {
+			self.base_path.clone()
+		} else {
+			format!("{}/{}", self.base_path, path)
+		}
+	}
+
+	fn request(&self, method: Method, url: &str) -> RequestBuilder {
+		let request = self.client.request(method, url);
+		match &self.credentials {
+			Some((user, password)) => request.basic_auth(user, Some(password)),
+			None => request,
+		}
+	}
+
+	// depth 为 0 时只返回条目本身，为 1 时同时返回集合的直接成员；返回条目本身和成员
+	fn propfind(
+		&self,
+		path: &str,
+		depth: &str,
+	) -> Result<(RemoteFileInfo, Vec<RemoteFileInfo>), RemoteError> {
+		let response = self
+			.request(method("PROPFIND"), &self.url(path, false))
+			.header("Depth", depth)
+			.header(CONTENT_TYPE, "application/xml; charset=utf-8")
+			.body(PROPFIND_BODY)
+			.send_retrying()?;
+		let body = check(response)?.text()?;
+		let own_path = self.entry_path(path);
+		let mut own = None;
+		let mut members = Vec::new();
+		for entry in parse_multistatus(&body)? {
+			if entry.path == own_path {
+				own = Some(entry.info);
+			} else {
+				members.push(entry.info);
+			}
+		}
+		let mut own = own.ok_or_else(|| {
+			RemoteError::backend(
+				"invalid_response",
+				format!("the server did not describe {}", path),
+			)
+		})?;
+		if path != "." {
+			own.name = base_name(path).to_string();
+		}
+		Ok((own, members))
+	}
+
+	// 获取条目的排他写锁，返回锁令牌；服务器不支持锁时返回 None
+	fn lock(&self, path: &str) -> Result<Option<String>, RemoteError> {
+		let response = self
+			.request(method("LOCK"), &self.url(path, false))
+			.header("Depth", "0")
+			.header("Timeout", LOCK_TIMEOUT)
+			.header(CONTENT_TYPE, "application/xml; charset=utf-8")
+			.body(LOCK_BODY)
+			.send_retrying()?;
+		if matches!(
+			response.status(),
+			StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+		) {
+			return Ok(None);
+		}
+		let response = check(response)?;
+		Ok(response
+			.headers()
+			.get("lock-token")
+			.and_then(|value| value.to_str().ok())
+			.map(|token| token.trim().to_string()))
+	}
+
+	fn unlock(&self, path: &str, token: &str) {
+		let _ = self
+			.request(method("UNLOCK"), &self.url(path, false))
+			.header("Lock-Token", token)
+			.send();
+	}
+
+	fn get(&self, path: &str) -> Result<Vec<u8>, RemoteError> {
+		let response = self
+			.request(Method::GET, &self.url(path, false))
+			.send_retrying()?;
+		Ok(check(response)?.bytes()?.to_vec())
+	}
+
+	// lock_token 为持有的写锁，服务器要求在 If 头中给出
+	fn put(&self, path: &str, data: &[u8], lock_token: Option<&str>) -> Result<(), RemoteError> {
+		let mut request = self
+			.request(Method::PUT, &self.url(path, false))
+			.body(data.to_vec());
+		if let Some(token) = lock_token {
+			request = request.header("If", format!("({})", token));
+		}
+		check(request.send_retrying()?)?;
+		Ok(())
+	}
+
+	// 在写锁下读出整个文件，修改后重新上传
+	fn rewrite(&self, path: &str, edit: impl FnOnce(&mut Vec<u8>)) -> Result<(), RemoteError> {
+		let token = self.lock(path)?;
+		let result = self.get(path).and_then(|mut data| {
+			edit(&mut data);
+			self.put(path, &data, token.as_deref())
+		});
+		if let Some(token) = &token {
+			self.unlock(path, token);
+		}
+		result
+	}
+}
+
+impl StorageBackend for WebDavBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		Ok(self.propfind(path, "0")?.0)
+	}
+
+	// WebDAV 没有分页，一次返回整个目录
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let (_, items) = self.propfind(path, "1")?;
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		if length == 0 {
+			return Ok(Vec::new());
+		}
+		let range = format!("bytes={}-{}", offset, offset + length as u64 - 1);
+		let response = self
+			.request(Method::GET, &self.url(path, false))
+			.header(RANGE, range)
+			.send_retrying()?;
+		match check(response) {
+			// 不支持范围请求的服务器返回整个文件
+			Ok(response) if response.status() == StatusCode::OK => {
+				let data = response.bytes()?;
+				let start = (offset as usize).min(data.len());
+				let end = (start + length).min(data.len());
+				Ok(data[start..end].to_vec())
+			}
+			Ok(response) => Ok(response.bytes()?.to_vec()),
+			// 从文件末尾之后开始读取
+			Err(e) if e.code() == Some("invalid_range") => Ok(Vec::new()),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.rewrite(path, |content| {
+			let start = offset as usize;
+			let end = start + data.len();
+			if content.len() < end {
+				content.resize(end, 0);
+			}
+			content[start..end].copy_from_slice(data);
+		})
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.put(path, data, None)
+	}
+
+	// 文件以 If-None-Match: * 创建，不覆盖同时被其他客户端创建的文件；已存在的集合 MKCOL 返回 405
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		let result = if is_directory {
+			self.request(method("MKCOL"), &self.url(path, true))
+				.send_retrying()
+				.and_then(check)
+		} else {
+			self.request(Method::PUT, &self.url(path, false))
+				.header("If-None-Match", "*")
+				.send_retrying()
+				.and_then(check)
+		};
+		match result {
+			Ok(_) => Ok(()),
+			Err(e) if e.code() == Some("method_not_allowed") => Err(RemoteError::backend(
+				"already_exists",
+				format!("{} already exists", path),
+			)),
+			Err(e) => Err(e),
+		}
+	}
+
+	// WebDAV 的 DELETE 会递归删除集合，先确认目录为空
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let (info, members) = self.propfind(path, "1")?;
+		if !members.is_empty() {
+			return Err(RemoteError::backend(
+				"directory_not_empty",
+				format!("{} is not empty", path),
+			));
+		}
+		if dry_run {
+			return Ok(());
+		}
+		check(
+			self.request(Method::DELETE, &self.url(path, info.is_directory))
+				.send_retrying()?,
+		)?;
+		Ok(())
+	}
+
+	// Overwrite: T 会递归删除已存在的目标，先检查目标的类型和是否为空
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let source = self.stat(old_path)?;
+		match self.propfind(new_path, "1") {
+			Ok(_) if !replace => {
+				return Err(RemoteError::backend(
+					"already_exists",
+					format!("{} already exists", new_path),
+				))
+			}
+			Ok((target, _)) if target.is_directory != source.is_directory => {
+				return Err(RemoteError::backend(
+					"type_mismatch",
+					format!("{} is of a different type", new_path),
+				));
+			}
+			Ok((_, members)) if !members.is_empty() => {
+				return Err(RemoteError::backend(
+					"directory_not_empty",
+					format!("{} is not empty", new_path),
+				));
+			}
+			Ok(_) => {}
+			Err(e) if e.code() == Some("not_found") => {}
+			Err(e) => return Err(e),
+		}
+		if source.is_directory && new_path.starts_with(&format!("{}/", old_path)) {
+			return Err(RemoteError::backend(
+				"move_into_self",
+				format!("cannot move {} into itself", old_path),
+			));
+		}
+		let response = self
+			.request(method("MOVE"), &self.url(old_path, source.is_directory))
+			.header("Destination", self.url(new_path, source.is_directory))
+			.header("Overwrite", if replace { "T" } else { "F" })
+			.send_retrying()?;
+		check(response)?;
+		Ok(())
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.rewrite(path, |content| content.resize(size as usize, 0))
+	}
+
+	// getlastmodified 是服务器维护的属性，不能通过 PROPPATCH 修改
+	fn set_times(&self, _path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Ok(())
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		let response = self
+			.request(method("PROPFIND"), &self.url(".", true))
+			.header("Depth", "0")
+			.header(CONTENT_TYPE, "application/xml; charset=utf-8")
+			.body(QUOTA_BODY)
+			.send_retrying()?;
+		let body = check(response)?.text()?;
+		let doc = parse_xml(&body)?;
+		let quota = |name| {
+			doc.descendants()
+				.find(|node| node.has_tag_name(name))
+				.and_then(|node| node.text())
+				.and_then(|value| value.trim().parse::<i64>().ok())
+		};
+		// 配额未知时服务器不返回这些属性或返回负数
+		match (quota("quota-available-bytes"), quota("quota-used-bytes")) {
+			(Some(available), Some(used)) if available >= 0 && used >= 0 => Ok(SpaceResponse {
+				total: (available + used) as u64,
+				available: available as u64,
+			}),
+			_ => Err(RemoteError::unsupported("space")),
+		}
+	}
+}
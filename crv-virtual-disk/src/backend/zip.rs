@@ -0,0 +1,509 @@
+use std::{
+	collections::{HashMap, VecDeque},
+	error::Error,
+	fs::File,
+	io::{self, BufReader, Read, Seek, SeekFrom},
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::UNIX_EPOCH,
+};
+
+use flate2::read::DeflateDecoder;
+
+use super::{
+	base_name, days_from_civil, directory_info, file_info, invalid, read_at, u16_at, u32_at,
+	u64_at, url_path, write_protected, StorageBackend,
+};
+use crate::{backend::Remote, error::RemoteError, ListPage, RemoteFileInfo, TimesUpdate};
+
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+const ZIP64_END_LOCATOR: u32 = 0x0706_4b50;
+const ZIP64_END_OF_CENTRAL_DIRECTORY: u32 = 0x0606_4b50;
+const CENTRAL_FILE_HEADER: u32 = 0x0201_4b50;
+const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+// 中央目录结束记录之后最多 65535 字节的注释
+const MAX_END_SEARCH: u64 = 22 + 65535;
+
+const EXTRA_ZIP64: u16 = 0x0001;
+const EXTRA_TIMESTAMP: u16 = 0x5455;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+const METHOD_ZSTD: u16 = 93;
+
+const FLAG_ENCRYPTED: u16 = 0x0001;
+const FLAG_UTF8: u16 = 0x0800;
+
+// 压缩的成员按块解压并缓存，同一成员的顺序读取继续使用上次的解压流
+const CHUNK_SIZE: usize = 1024 * 1024;
+const CACHE_SIZE: usize = 64 * 1024 * 1024;
+const MAX_STREAMS: usize = 16;
+
+// 未设置 UTF-8 标志的文件名使用 IBM 437 编码
+const CP437_HIGH: &str = "ÇüéâäàåçêëèïîìÄÅÉæÆôöòûùÿÖÜ¢£¥₧ƒáíóúñÑªº¿⌐¬½¼¡«»░▒▓│┤╡╢╖╕╣║╗╝╜╛┐└┴┬├─┼╞╟╚╔╩╦╠═╬╧╨╤╥╙╘╒╓╫╪┘┌█▄▌▐▀αßΓπΣσµτΦΘΩδ∞φε∩≡±≥≤⌠⌡÷≈°∙·√ⁿ²■\u{a0}";
+
+fn decode_name(raw: &[u8], flags: u16) -> String {
+	if flags & FLAG_UTF8 != 0 {
+		return String::from_utf8_lossy(raw).into_owned();
+	}
+	raw.iter()
+		.map(|&byte| match byte {
+			0..=127 => byte as char,
+			_ => CP437_HIGH.chars().nth(byte as usize - 128).unwrap(),
+		})
+		.collect()
+}
+
+// MS-DOS 格式的修改时间没有时区，按 UTC 处理
+fn dos_time(date: u16, time: u16) -> u64 {
+	let days = days_from_civil(
+		1980 + (date >> 9) as i64,
+		((date >> 5) & 0xf) as i64,
+		(date & 0x1f) as i64,
+	);
+	let secs = days * 86400
+		+ (time >> 11) as i64 * 3600
+		+ ((time >> 5) & 0x3f) as i64 * 60
+		+ (time & 0x1f) as i64 * 2;
+	u64::try_from(secs).unwrap_or(0)
+}
+
+struct Entry {
+	is_directory: bool,
+	size: u64,
+	compressed_size: u64,
+	method: u16,
+	flags: u16,
+	header_offset: u64,
+	modified: u64,
+}
+
+// 中央目录中的一条记录，消耗的字节数用于定位下一条
+fn parse_central_entry(data: &[u8]) -> io::Result<(String, Entry, usize)> {
+	if data.len() < 46 || u32_at(data, 0) != CENTRAL_FILE_HEADER {
+		return Err(invalid("corrupt central directory"));
+	}
+	let flags = u16_at(data, 8);
+	let name_len = u16_at(data, 28) as usize;
+	let extra_len = u16_at(data, 30) as usize;
+	let comment_len = u16_at(data, 32) as usize;
+	let total = 46 + name_len + extra_len + comment_len;
+	if data.len() < total {
+		return Err(invalid("corrupt central directory"));
+	}
+	let name = decode_name(&data[46..46 + name_len], flags);
+	let mut entry = Entry {
+		is_directory: name.ends_with('/'),
+		size: u32_at(data, 24) as u64,
+		compressed_size: u32_at(data, 20) as u64,
+		method: u16_at(data, 10),
+		flags,
+		header_offset: u32_at(data, 42) as u64,
+		modified: dos_time(u16_at(data, 14), u16_at(data, 12)),
+	};
+
+	// ZIP64 扩展字段只包含值为 0xFFFFFFFF 的字段，顺序固定
+	let mut extra = &data[46 + name_len..46 + name_len + extra_len];
+	while extra.len() >= 4 {
+		let (id, len) = (u16_at(extra, 0), u16_at(extra, 2) as usize);
+		let field = &extra[4..(4 + len).min(extra.len())];
+		match id {
+			EXTRA_ZIP64 => {
+				let mut values = field
+					.chunks_exact(8)
+					.map(|value| u64::from_le_bytes(value.try_into().unwrap()));
+				for target in [
+					&mut entry.size,
+					&mut entry.compressed_size,
+					&mut entry.header_offset,
+				] {
+					if *target == u32::MAX as u64 {
+						*target = values
+							.next()
+							.ok_or_else(|| invalid("truncated ZIP64 field"))?;
+					}
+				}
+			}
+			EXTRA_TIMESTAMP if field.len() >= 5 && field[0] & 1 != 0 => {
+				entry.modified = u32_at(field, 1) as u64
+			}
+			_ => {}
+		}
+		extra = &extra[(4 + len).min(extra.len())..];
+	}
+	Ok((name, entry, total))
+}
+
+// 读取中央目录：先从文件末尾找到结束记录，条目数或偏移溢出时使用 ZIP64 结束记录
+fn read_central_directory(file: &mut File) -> io::Result<Vec<(String, Entry)>> {
+	let file_len = file.seek(SeekFrom::End(0))?;
+	let tail_start = file_len.saturating_sub(MAX_END_SEARCH);
+	let tail = read_at(file, tail_start, (file_len - tail_start) as usize)?;
+	let end = (0..tail.len().saturating_sub(21))
+		.rev()
+		.find(|&i| u32_at(&tail, i) == END_OF_CENTRAL_DIRECTORY)
+		.ok_or_else(|| invalid("not a ZIP archive"))?;
+	let mut count = u16_at(&tail, end + 10) as u64;
+	let mut size = u32_at(&tail, end + 12) as u64;
+	let mut offset = u32_at(&tail, end + 16) as u64;
+	if end >= 20 && u32_at(&tail, end - 20) == ZIP64_END_LOCATOR {
+		let record = read_at(file, u64_at(&tail, end - 12), 56)?;
+		if u32_at(&record, 0) != ZIP64_END_OF_CENTRAL_DIRECTORY {
+			return Err(invalid("corrupt ZIP64 end of central directory"));
+		}
+		count = u64_at(&record, 32);
+		size = u64_at(&record, 40);
+		offset = u64_at(&record, 48);
+	}
+	if offset.checked_add(size).is_none_or(|end| end > file_len) {
+		return Err(invalid("central directory lies outside the file"));
+	}
+
+	let directory = read_at(file, offset, size as usize)?;
+	let mut entries = Vec::new();
+	let mut position = 0;
+	for _ in 0..count {
+		let (name, entry, consumed) = parse_central_entry(&directory[position..])?;
+		position += consumed;
+		entries.push((name, entry));
+	}
+	Ok(entries)
+}
+
+// 一个成员的解压流，next 为下一个要产生的块
+struct Stream {
+	next: u64,
+	reader: Box<dyn Read + Send>,
+}
+
+#[derive(Default)]
+struct ChunkCache {
+	chunks: HashMap<(String, u64), Arc<Vec<u8>>>,
+	// 最近使用的块在末尾
+	order: VecDeque<(String, u64)>,
+	bytes: usize,
+	streams: HashMap<String, Stream>,
+}
+
+impl ChunkCache {
+	fn get(&mut self, key: &(String, u64)) -> Option<Arc<Vec<u8>>> {
+		let chunk = self.chunks.get(key)?.clone();
+		if let Some(position) = self.order.iter().position(|k| k == key) {
+			let key = self.order.remove(position).unwrap();
+			self.order.push_back(key);
+		}
+		Some(chunk)
+	}
+
+	fn insert(&mut self, key: (String, u64), chunk: Arc<Vec<u8>>) {
+		self.bytes += chunk.len();
+		if let Some(old) = self.chunks.insert(key.clone(), chunk) {
+			self.bytes -= old.len();
+		} else {
+			self.order.push_back(key);
+		}
+		while self.bytes > CACHE_SIZE {
+			let Some(oldest) = self.order.pop_front() else {
+				break;
+			};
+			if let Some(chunk) = self.chunks.remove(&oldest) {
+				self.bytes -= chunk.len();
+			}
+		}
+	}
+}
+
+// 以只读卷挂载的 ZIP 文件：打开时解析中央目录建立目录树，读取时才解压成员。
+// 支持 ZIP64、存储、Deflate 和 Zstandard 压缩的成员，不支持加密的成员；
+// 中央目录没有列出的上级目录自动补齐
+pub struct ZipBackend {
+	archive: PathBuf,
+	file: Mutex<File>,
+	entries: HashMap<String, Entry>,
+	// 目录路径到直接成员名称的映射，根目录为 "."
+	children: HashMap<String, Vec<String>>,
+	// 根目录和补齐的目录使用 ZIP 文件本身的修改时间
+	modified: u64,
+	cache: Mutex<ChunkCache>,
+}
+
+impl ZipBackend {
+	pub fn new(remote: &Remote) -> Result<Self, Box<dyn Error>> {
+		if remote.share.is_some() || remote.token.is_some() {
+			return Err("--share and --token do not apply to zip:// URLs".into());
+		}
+		let archive = url_path(&remote.server_url);
+		Ok(
+			Self::open(&archive)
+				.map_err(|e| format!("cannot open {}: {}", archive.display(), e))?,
+		)
+	}
+
+	pub fn open(archive: &Path) -> io::Result<Self> {
+		let mut file = File::open(archive)?;
+		let modified = file
+			.metadata()?
+			.modified()?
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |d| d.as_secs());
+		let mut entries = HashMap::new();
+		let mut children: HashMap<String, Vec<String>> =
+			HashMap::from([(".".to_string(), Vec::new())]);
+		for (name, entry) in read_central_directory(&mut file)? {
+			// 忽略绝对路径、.. 和空的路径分量
+			let name = name.replace('\\', "/");
+			let parts: Vec<&str> = name
+				.split('/')
+				.filter(|part| !part.is_empty() && *part != ".")
+				.collect();
+			if parts.is_empty() || parts.contains(&"..") {
+				continue;
+			}
+			let path = parts.join("/");
+			let mut parent = ".".to_string();
+			for (depth, part) in parts.iter().enumerate() {
+				let current = parts[..=depth].join("/");
+				let is_leaf = depth == parts.len() - 1;
+				if !entries.contains_key(&current) {
+					children
+						.entry(parent.clone())
+						.or_default()
+						.push(part.to_string());
+				}
+				if !is_leaf && !entries.contains_key(&current) {
+					entries.insert(
+						current.clone(),
+						Entry {
+							is_directory: true,
+							size: 0,
+							compressed_size: 0,
+							method: METHOD_STORED,
+							flags: 0,
+							header_offset: 0,
+							modified,
+						},
+					);
+				}
+				if is_leaf && entry.is_directory {
+					children.entry(current.clone()).or_default();
+				}
+				parent = current;
+			}
+			if entry.is_directory {
+				children.entry(path.clone()).or_default();
+			}
+			entries.insert(path, entry);
+		}
+		for names in children.values_mut() {
+			names.sort();
+			names.dedup();
+		}
+		Ok(Self {
+			archive: archive.to_path_buf(),
+			file: Mutex::new(file),
+			entries,
+			children,
+			modified,
+			cache: Mutex::new(ChunkCache::default()),
+		})
+	}
+
+	fn entry(&self, path: &str) -> Result<&Entry, RemoteError> {
+		self.entries.get(path).ok_or_else(|| {
+			RemoteError::backend("not_found", format!("{} is not in the archive", path))
+		})
+	}
+
+	fn info(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		if path == "." {
+			return Ok(directory_info(".", self.modified));
+		}
+		let entry = self.entry(path)?;
+		let name = base_name(path);
+		Ok(if entry.is_directory {
+			directory_info(name, entry.modified)
+		} else {
+			file_info(name, entry.size, entry.modified)
+		})
+	}
+
+	// 成员数据在本地文件头之后，本地文件头的扩展字段长度可能与中央目录中的不同
+	fn data_offset(&self, path: &str, entry: &Entry) -> Result<u64, RemoteError> {
+		let mut file = self.file.lock().unwrap();
+		let header =
+			read_at(&mut file, entry.header_offset, 30).map_err(|e| archive_error(e, path))?;
+		if u32_at(&header, 0) != LOCAL_FILE_HEADER {
+			return Err(archive_error(invalid("corrupt local file header"), path));
+		}
+		Ok(entry.header_offset + 30 + u16_at(&header, 26) as u64 + u16_at(&header, 28) as u64)
+	}
+
+	fn open_stream(&self, path: &str, entry: &Entry) -> Result<Box<dyn Read + Send>, RemoteError> {
+		let mut file = File::open(&self.archive).map_err(|e| archive_error(e, path))?;
+		file.seek(SeekFrom::Start(self.data_offset(path, entry)?))
+			.map_err(|e| archive_error(e, path))?;
+		let compressed = BufReader::new(file.take(entry.compressed_size));
+		Ok(match entry.method {
+			METHOD_DEFLATED => Box::new(DeflateDecoder::new(compressed)),
+			METHOD_ZSTD => Box::new(
+				zstd::stream::read::Decoder::with_buffer(compressed)
+					.map_err(|e| archive_error(e, path))?,
+			),
+			method => {
+				return Err(RemoteError::backend(
+					"not_supported",
+					format!("{} uses unsupported compression method {}", path, method),
+				))
+			}
+		})
+	}
+
+	// 解压成员的第 index 块：从缓存中取，否则推进已有的解压流（或从头开始新的解压流），沿途的块都放入缓存
+	fn chunk(&self, path: &str, entry: &Entry, index: u64) -> Result<Arc<Vec<u8>>, RemoteError> {
+		let mut cache = self.cache.lock().unwrap();
+		let key = (path.to_string(), index);
+		if let Some(chunk) = cache.get(&key) {
+			return Ok(chunk);
+		}
+		let mut stream = match cache.streams.remove(path) {
+			Some(stream) if stream.next <= index => stream,
+			_ => Stream {
+				next: 0,
+				reader: self.open_stream(path, entry)?,
+			},
+		};
+		loop {
+			let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+			(&mut stream.reader)
+				.take(CHUNK_SIZE as u64)
+				.read_to_end(&mut chunk)
+				.map_err(|e| archive_error(e, path))?;
+			let chunk = Arc::new(chunk);
+			let current = stream.next;
+			stream.next += 1;
+			cache.insert((path.to_string(), current), chunk.clone());
+			if current == index {
+				if chunk.len() == CHUNK_SIZE {
+					if cache.streams.len() >= MAX_STREAMS {
+						cache.streams.clear();
+					}
+					cache.streams.insert(path.to_string(), stream);
+				}
+				return Ok(chunk);
+			}
+			if chunk.len() < CHUNK_SIZE {
+				return Ok(Arc::new(Vec::new()));
+			}
+		}
+	}
+}
+
+fn archive_error(e: io::Error, path: &str) -> RemoteError {
+	RemoteError::backend("archive_error", format!("{}: {}", path, e))
+}
+
+impl StorageBackend for ZipBackend {
+	fn read_only(&self) -> bool {
+		true
+	}
+
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		self.info(path)
+	}
+
+	fn list_page(&self, path: &str, _cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		let names = self
+			.children
+			.get(path)
+			.ok_or_else(|| match self.entries.get(path) {
+				Some(_) => {
+					RemoteError::backend("not_a_directory", format!("{} is not a directory", path))
+				}
+				None => {
+					RemoteError::backend("not_found", format!("{} is not in the archive", path))
+				}
+			})?;
+		let items = names
+			.iter()
+			.map(|name| {
+				self.info(&if path == "." {
+					name.clone()
+				} else {
+					format!("{}/{}", path, name)
+				})
+			})
+			.collect::<Result<_, _>>()?;
+		Ok(ListPage {
+			items,
+			next_cursor: None,
+		})
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let entry = self.entry(path)?;
+		if entry.is_directory {
+			return Err(RemoteError::backend(
+				"is_a_directory",
+				format!("{} is a directory", path),
+			));
+		}
+		if entry.flags & FLAG_ENCRYPTED != 0 {
+			return Err(RemoteError::backend(
+				"not_supported",
+				format!("{} is encrypted", path),
+			));
+		}
+		let end = entry.size.min(offset.saturating_add(length as u64));
+		if offset >= end {
+			return Ok(Vec::new());
+		}
+		if entry.method == METHOD_STORED {
+			let start = self.data_offset(path, entry)? + offset;
+			let mut file = self.file.lock().unwrap();
+			return read_at(&mut file, start, (end - offset) as usize)
+				.map_err(|e| archive_error(e, path));
+		}
+		let mut data = Vec::with_capacity((end - offset) as usize);
+		let mut position = offset;
+		while position < end {
+			let index = position / CHUNK_SIZE as u64;
+			let chunk = self.chunk(path, entry, index)?;
+			let start = (position - index * CHUNK_SIZE as u64) as usize;
+			let stop = chunk.len().min(start + (end - position) as usize);
+			if start >= stop {
+				break;
+			}
+			data.extend_from_slice(&chunk[start..stop]);
+			position += (stop - start) as u64;
+		}
+		Ok(data)
+	}
+
+	fn write(&self, path: &str, _offset: u64, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn commit(&self, path: &str, _data: &[u8]) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn create(&self, path: &str, _is_directory: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn delete(&self, path: &str, _dry_run: bool) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn rename(&self, old_path: &str, _new_path: &str, _replace: bool) -> Result<(), RemoteError> {
+		Err(write_protected(old_path))
+	}
+
+	fn truncate(&self, path: &str, _size: u64) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+
+	fn set_times(&self, path: &str, _times: &TimesUpdate) -> Result<(), RemoteError> {
+		Err(write_protected(path))
+	}
+}
@@ -0,0 +1,92 @@
+use std::{
+	io, mem,
+	ops::{Deref, DerefMut},
+	sync::Mutex,
+};
+
+use bytes::Bytes;
+
+// 池中最多保留的缓冲区数
+const MAX_POOLED: usize = 16;
+// 容量超过这个值的缓冲区用完后释放，不放回池中
+const MAX_POOLED_CAPACITY: usize = 4 * 1024 * 1024;
+
+static POOL: Mutex<Vec<Vec<u8>>> = Mutex::new(Vec::new());
+
+/// 写入路径上复用的缓冲区：从池中取出，释放时清空后放回，大量写入时不必为每个请求体重新分配内存。
+/// 作为请求体时以 [`PooledBuffer::into_bytes`] 交给 reqwest，请求结束后放回池中。
+pub struct PooledBuffer {
+	data: Vec<u8>,
+}
+
+impl PooledBuffer {
+	/// 取出容量至少为 `capacity` 的空缓冲区，池中没有足够大的时扩展其中一个。
+	pub fn with_capacity(capacity: usize) -> Self {
+		let mut data = {
+			let mut pool = POOL.lock().unwrap();
+			match pool.iter().position(|data| data.capacity() >= capacity) {
+				Some(index) => pool.swap_remove(index),
+				None => pool.pop().unwrap_or_default(),
+			}
+		};
+		data.reserve(capacity);
+		Self { data }
+	}
+
+	/// 复制 `data` 到池中的缓冲区。
+	pub fn copy_from(data: &[u8]) -> Self {
+		let mut buffer = Self::with_capacity(data.len());
+		buffer.extend_from_slice(data);
+		buffer
+	}
+
+	/// 不再复制地转换为请求体，最后一个引用释放时缓冲区放回池中。
+	pub fn into_bytes(self) -> Bytes {
+		Bytes::from_owner(self)
+	}
+}
+
+impl Deref for PooledBuffer {
+	type Target = Vec<u8>;
+
+	fn deref(&self) -> &Vec<u8> {
+		&self.data
+	}
+}
+
+impl DerefMut for PooledBuffer {
+	fn deref_mut(&mut self) -> &mut Vec<u8> {
+		&mut self.data
+	}
+}
+
+impl AsRef<[u8]> for PooledBuffer {
+	fn as_ref(&self) -> &[u8] {
+		&self.data
+	}
+}
+
+// 压缩器直接写进池中的缓冲区
+impl io::Write for PooledBuffer {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.data.write(buf)
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Drop for PooledBuffer {
+	fn drop(&mut self) {
+		let mut data = mem::take(&mut self.data);
+		if data.capacity() == 0 || data.capacity() > MAX_POOLED_CAPACITY {
+			return;
+		}
+		data.clear();
+		let mut pool = POOL.lock().unwrap();
+		if pool.len() < MAX_POOLED {
+			pool.push(data);
+		}
+	}
+}
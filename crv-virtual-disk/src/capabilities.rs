@@ -0,0 +1,52 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+
+/// 客户端使用的协议版本，与服务器的 `min_protocol_version` 比较。
+pub const PROTOCOL_VERSION: u32 = 1;
+
+// 没有 /capabilities 的旧版服务器：只假定基本的文件操作、按区间读取和分页列目录
+const LEGACY_FEATURES: [&str; 2] = ["ranges", "paging"];
+
+/// httpfs 服务器的 `GET /capabilities`：协议版本和共享支持的功能（`batch`、`xattrs`、`events`、`locks` 等）。
+/// 挂载时查询，服务器不支持的功能改用较慢的通用做法或不再使用，而不是在文件操作中途失败。
+#[derive(Debug, Clone, Deserialize)]
+pub struct Capabilities {
+	pub protocol_version: u32,
+	// 服务器仍然支持的最旧的客户端协议版本
+	pub min_protocol_version: u32,
+	pub features: HashSet<String>,
+	// 单个 /write 请求体的上限，更长的写入分成多个请求
+	#[serde(default)]
+	pub max_write_size: Option<usize>,
+}
+
+impl Capabilities {
+	/// 没有 `/capabilities` 的旧版服务器，协议版本为 0。
+	pub fn legacy() -> Self {
+		Self {
+			protocol_version: 0,
+			min_protocol_version: 0,
+			features: LEGACY_FEATURES
+				.iter()
+				.map(|feature| feature.to_string())
+				.collect(),
+			max_write_size: None,
+		}
+	}
+
+	pub fn supports(&self, feature: &str) -> bool {
+		self.features.contains(feature)
+	}
+
+	/// 服务器要求更新的客户端时返回错误，挂载随之失败。
+	pub fn check(&self) -> Result<(), String> {
+		if self.min_protocol_version > PROTOCOL_VERSION {
+			return Err(format!(
+				"the server requires protocol version {} or newer, this client speaks version {}; upgrade the client",
+				self.min_protocol_version, PROTOCOL_VERSION
+			));
+		}
+		Ok(())
+	}
+}
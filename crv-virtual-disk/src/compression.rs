@@ -0,0 +1,96 @@
+use std::{fmt, io::Write, str::FromStr};
+
+use reqwest::{blocking::RequestBuilder, header::CONTENT_ENCODING};
+
+use crate::buffers::PooledBuffer;
+
+// 小于该大小的写入不压缩
+const MIN_COMPRESS_SIZE: usize = 64 * 1024;
+
+// 本身已经压缩过的文件格式，上传时不再压缩
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+	"7z", "avi", "br", "bz2", "cab", "docx", "flac", "gif", "gz", "heic", "jar", "jpeg", "jpg",
+	"m4a", "mkv", "mov", "mp3", "mp4", "ogg", "png", "pptx", "rar", "tgz", "webm", "webp", "xlsx",
+	"xz", "zip", "zst",
+];
+
+// 传输压缩方式：决定上传时使用的编码，以及是否接受服务器压缩的响应
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+	None,
+	Gzip,
+	Zstd,
+}
+
+impl FromStr for Compression {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"none" | "off" => Ok(Compression::None),
+			"gzip" => Ok(Compression::Gzip),
+			"zstd" => Ok(Compression::Zstd),
+			_ => Err(format!(
+				"unknown compression '{}', expected none, gzip or zstd",
+				s
+			)),
+		}
+	}
+}
+
+impl fmt::Display for Compression {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Compression::None => "none",
+			Compression::Gzip => "gzip",
+			Compression::Zstd => "zstd",
+		})
+	}
+}
+
+fn is_precompressed(path: &str) -> bool {
+	path.rsplit_once('.').is_some_and(|(_, ext)| {
+		PRECOMPRESSED_EXTENSIONS
+			.iter()
+			.any(|known| known.eq_ignore_ascii_case(ext))
+	})
+}
+
+impl Compression {
+	// 把写入的数据作为请求体，足够大且不是已压缩格式时压缩后附带 Content-Encoding；
+	// 请求体放在池中的缓冲区里，请求结束后放回
+	pub fn body(self, request: RequestBuilder, path: &str, data: &[u8]) -> RequestBuilder {
+		if self == Compression::None || data.len() < MIN_COMPRESS_SIZE || is_precompressed(path) {
+			return request.body(PooledBuffer::copy_from(data).into_bytes());
+		}
+		let encoded = match self {
+			Compression::Gzip => {
+				let mut encoder = flate2::write::GzEncoder::new(
+					PooledBuffer::with_capacity(data.len()),
+					flate2::Compression::fast(),
+				);
+				encoder.write_all(data).and_then(|_| encoder.finish())
+			}
+			Compression::Zstd => {
+				let mut encoded = PooledBuffer::with_capacity(data.len());
+				zstd::stream::copy_encode(data, &mut encoded, 0).map(|_| encoded)
+			}
+			Compression::None => unreachable!(),
+		};
+		match encoded {
+			// 压缩后没有变小就直接发送原始数据
+			Ok(encoded) if encoded.len() < data.len() => request
+				.header(CONTENT_ENCODING, self.encoding())
+				.body(encoded.into_bytes()),
+			_ => request.body(PooledBuffer::copy_from(data).into_bytes()),
+		}
+	}
+
+	fn encoding(self) -> &'static str {
+		match self {
+			Compression::None => "identity",
+			Compression::Gzip => "gzip",
+			Compression::Zstd => "zstd",
+		}
+	}
+}
@@ -0,0 +1,559 @@
+use std::{
+	error::Error,
+	fs::{self, File, FileTimes, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	iter,
+	os::windows::{
+		ffi::OsStrExt,
+		fs::{FileTimesExt, OpenOptionsExt},
+	},
+	path::{Path, PathBuf},
+	sync::{Mutex, MutexGuard},
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use winapi::{
+	shared::winerror::{
+		ERROR_ALREADY_EXISTS, ERROR_DIR_NOT_EMPTY, ERROR_FILE_EXISTS, ERROR_FILE_NOT_FOUND,
+		ERROR_PATH_NOT_FOUND, ERROR_SHARING_VIOLATION,
+	},
+	um::{
+		fileapi::GetLogicalDrives,
+		winbase::{MoveFileExW, FILE_FLAG_DELETE_ON_CLOSE},
+		winnt::{
+			DELETE, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, GENERIC_READ,
+			GENERIC_WRITE,
+		},
+	},
+};
+
+use crate::{backend::Remote, Mount, MountHandle};
+
+// 等待挂载完成的上限
+const MOUNT_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 同一进程中同时只有一个临时挂载：dokan::init 和 dokan::shutdown 成对调用
+static MOUNT_LOCK: Mutex<()> = Mutex::new(());
+
+/// 一项检查，在自己的空目录中经 Win32 文件 API 执行，失败时返回原因。
+pub struct Case {
+	pub name: &'static str,
+	check: fn(&Path) -> Result<(), String>,
+}
+
+/// 所有检查：创建方式、读写、目录、重命名、关闭时删除、共享方式、时间戳和超过 MAX_PATH 的路径。
+pub const CASES: [Case; 8] = [
+	Case {
+		name: "create_dispositions",
+		check: create_dispositions,
+	},
+	Case {
+		name: "read_write",
+		check: read_write,
+	},
+	Case {
+		name: "directories",
+		check: directories,
+	},
+	Case {
+		name: "rename",
+		check: rename,
+	},
+	Case {
+		name: "delete_on_close",
+		check: delete_on_close,
+	},
+	Case {
+		name: "share_modes",
+		check: share_modes,
+	},
+	Case {
+		name: "timestamps",
+		check: timestamps,
+	},
+	Case {
+		name: "long_paths",
+		check: long_paths,
+	},
+];
+
+/// 在 `root`（挂载点或其中的目录）下逐项检查，返回失败的检查和原因，全部通过时为空。
+/// 与 winfstest 一样只使用应用程序能用的文件 API，任何文件系统（Dokan 或 WinFsp 挂载的处理器，也包括作为参照的 NTFS 目录）都可以检查。
+pub fn run(root: &Path) -> Vec<(&'static str, String)> {
+	CASES
+		.iter()
+		.filter_map(|case| {
+			let dir = root.join(format!("conformance-{}", case.name));
+			let result = fs::create_dir(&dir)
+				.map_err(|e| format!("creating {} failed: {}", dir.display(), e))
+				.and_then(|()| (case.check)(&dir));
+			let _ = fs::remove_dir_all(&dir);
+			result.err().map(|reason| (case.name, reason))
+		})
+		.collect()
+}
+
+/// 以 Dokan 把 `remote` 挂载到空闲的盘符，供 [`run`] 检查，释放时卸载。需要安装 Dokan 驱动。
+/// 属性缓存和变更通知关闭，检查看到的总是存储中的状态。
+pub struct TemporaryMount {
+	handle: MountHandle,
+	root: PathBuf,
+	_lock: MutexGuard<'static, ()>,
+}
+
+impl TemporaryMount {
+	pub fn new(remote: Remote) -> Result<Self, Box<dyn Error>> {
+		let lock = MOUNT_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+		let drives = unsafe { GetLogicalDrives() };
+		let letter = (b'M'..=b'Z')
+			.rev()
+			.find(|letter| drives & (1 << (letter - b'A')) == 0)
+			.ok_or("no free drive letter")?;
+		let root = PathBuf::from(format!("{}:\\", letter as char));
+		let mut mount = Mount::new(remote, root.to_string_lossy());
+		mount.attr_cache_ttl = 0;
+		mount.events = false;
+
+		dokan::init();
+		let handle = MountHandle::spawn(mount, |_| {});
+		let started = Instant::now();
+		while !handle.is_mounted() {
+			if handle.is_finished() || started.elapsed() > MOUNT_TIMEOUT {
+				let error = match handle.wait() {
+					Err(e) => e.to_string(),
+					Ok(()) => "the mount did not start".to_string(),
+				};
+				dokan::shutdown();
+				return Err(error.into());
+			}
+			thread::sleep(Duration::from_millis(50));
+		}
+		Ok(Self {
+			handle,
+			root,
+			_lock: lock,
+		})
+	}
+
+	pub fn root(&self) -> &Path {
+		&self.root
+	}
+}
+
+impl Drop for TemporaryMount {
+	fn drop(&mut self) {
+		let _ = self.handle.unmount_and_wait();
+		dokan::shutdown();
+	}
+}
+
+// 为 I/O 错误加上正在进行的操作
+trait Step<T> {
+	fn step(self, what: &str) -> Result<T, String>;
+}
+
+impl<T> Step<T> for io::Result<T> {
+	fn step(self, what: &str) -> Result<T, String> {
+		self.map_err(|e| format!("{} failed: {}", what, e))
+	}
+}
+
+fn ensure(condition: bool, message: impl FnOnce() -> String) -> Result<(), String> {
+	if condition {
+		Ok(())
+	} else {
+		Err(message())
+	}
+}
+
+// 操作应以 code 失败
+fn expect_error<T>(result: io::Result<T>, code: u32, what: &str) -> Result<(), String> {
+	match result {
+		Ok(_) => Err(format!("{} succeeded, expected Win32 error {}", what, code)),
+		Err(e) if e.raw_os_error() == Some(code as i32) => Ok(()),
+		Err(e) => Err(format!(
+			"{} failed with {}, expected Win32 error {}",
+			what, e, code
+		)),
+	}
+}
+
+fn read_all(path: &Path) -> Result<Vec<u8>, String> {
+	fs::read(path).step(&format!("reading {}", path.display()))
+}
+
+// CREATE_NEW、OPEN_EXISTING、OPEN_ALWAYS、CREATE_ALWAYS 和 TRUNCATE_EXISTING
+fn create_dispositions(dir: &Path) -> Result<(), String> {
+	let path = dir.join("file.txt");
+	expect_error(
+		File::open(&path),
+		ERROR_FILE_NOT_FOUND,
+		"OPEN_EXISTING of a missing file",
+	)?;
+	expect_error(
+		OpenOptions::new().write(true).truncate(true).open(&path),
+		ERROR_FILE_NOT_FOUND,
+		"TRUNCATE_EXISTING of a missing file",
+	)?;
+	expect_error(
+		File::create(dir.join("missing").join("file.txt")),
+		ERROR_PATH_NOT_FOUND,
+		"creating a file in a missing directory",
+	)?;
+
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.open(&path)
+		.step("CREATE_NEW")?;
+	file.write_all(b"hello").step("writing")?;
+	drop(file);
+	expect_error(
+		OpenOptions::new().write(true).create_new(true).open(&path),
+		ERROR_FILE_EXISTS,
+		"CREATE_NEW of an existing file",
+	)?;
+
+	drop(
+		OpenOptions::new()
+			.write(true)
+			.create(true)
+			.open(&path)
+			.step("OPEN_ALWAYS")?,
+	);
+	ensure(read_all(&path)? == b"hello", || {
+		"OPEN_ALWAYS changed the content".to_string()
+	})?;
+	drop(File::create(&path).step("CREATE_ALWAYS")?);
+	ensure(read_all(&path)?.is_empty(), || {
+		"CREATE_ALWAYS did not truncate the file".to_string()
+	})?;
+	fs::write(&path, b"again").step("writing")?;
+	drop(
+		OpenOptions::new()
+			.write(true)
+			.truncate(true)
+			.open(&path)
+			.step("TRUNCATE_EXISTING")?,
+	);
+	ensure(fs::metadata(&path).step("querying")?.len() == 0, || {
+		"TRUNCATE_EXISTING did not truncate the file".to_string()
+	})
+}
+
+// 在任意位置读写、越过末尾写入和调整大小
+fn read_write(dir: &Path) -> Result<(), String> {
+	let path = dir.join("data.bin");
+	let mut file = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create_new(true)
+		.open(&path)
+		.step("creating")?;
+	file.write_all(b"hello world").step("writing")?;
+	file.seek(SeekFrom::Start(6)).step("seeking")?;
+	file.write_all(b"there").step("overwriting")?;
+	file.seek(SeekFrom::Start(13))
+		.step("seeking past the end")?;
+	file.write_all(b"!").step("writing past the end")?;
+	let mut data = Vec::new();
+	file.seek(SeekFrom::Start(0)).step("seeking")?;
+	file.read_to_end(&mut data).step("reading back")?;
+	ensure(data == b"hello there\0\0!", || {
+		format!(
+			"read back {:?} through the same handle",
+			String::from_utf8_lossy(&data)
+		)
+	})?;
+	file.set_len(5).step("truncating")?;
+	file.set_len(8).step("extending")?;
+	drop(file);
+	let data = read_all(&path)?;
+	ensure(data == b"hello\0\0\0", || {
+		format!(
+			"read back {:?} after reopening",
+			String::from_utf8_lossy(&data)
+		)
+	})?;
+
+	let mut file = OpenOptions::new()
+		.append(true)
+		.open(&path)
+		.step("opening for appending")?;
+	file.write_all(b"+").step("appending")?;
+	drop(file);
+	let data = read_all(&path)?;
+	ensure(data == b"hello\0\0\0+", || {
+		format!(
+			"read back {:?} after appending",
+			String::from_utf8_lossy(&data)
+		)
+	})
+}
+
+fn names(dir: &Path) -> Result<Vec<String>, String> {
+	let mut names = fs::read_dir(dir)
+		.step(&format!("listing {}", dir.display()))?
+		.map(|entry| entry.map(|entry| entry.file_name().to_string_lossy().into_owned()))
+		.collect::<io::Result<Vec<_>>>()
+		.step("listing")?;
+	names.sort();
+	Ok(names)
+}
+
+// 创建、列出和删除目录，非空目录不能删除
+fn directories(dir: &Path) -> Result<(), String> {
+	let sub = dir.join("sub");
+	fs::create_dir(&sub).step("creating a directory")?;
+	expect_error(
+		fs::create_dir(&sub),
+		ERROR_ALREADY_EXISTS,
+		"creating an existing directory",
+	)?;
+	ensure(fs::metadata(&sub).step("querying")?.is_dir(), || {
+		"the directory is not reported as a directory".to_string()
+	})?;
+	for name in ["b.txt", "a.txt", "c"] {
+		if name == "c" {
+			fs::create_dir(sub.join(name)).step("creating a subdirectory")?;
+		} else {
+			fs::write(sub.join(name), name).step("creating a file")?;
+		}
+	}
+	let listed = names(&sub)?;
+	ensure(listed == ["a.txt", "b.txt", "c"], || {
+		format!("listed {:?}", listed)
+	})?;
+	expect_error(
+		fs::remove_dir(&sub),
+		ERROR_DIR_NOT_EMPTY,
+		"removing a non-empty directory",
+	)?;
+	fs::remove_file(sub.join("a.txt")).step("deleting a file")?;
+	expect_error(
+		File::open(sub.join("a.txt")),
+		ERROR_FILE_NOT_FOUND,
+		"opening a deleted file",
+	)?;
+	fs::remove_file(sub.join("b.txt")).step("deleting a file")?;
+	fs::remove_dir(sub.join("c")).step("removing an empty directory")?;
+	fs::remove_dir(&sub).step("removing the emptied directory")?;
+	ensure(names(dir)?.is_empty(), || {
+		"removed entries are still listed".to_string()
+	})
+}
+
+fn wide(path: &Path) -> Vec<u16> {
+	path.as_os_str()
+		.encode_wide()
+		.chain(iter::once(0))
+		.collect()
+}
+
+// MoveFileExW 不带 MOVEFILE_REPLACE_EXISTING，目标已存在时失败
+fn move_without_replacing(from: &Path, to: &Path) -> io::Result<()> {
+	if unsafe { MoveFileExW(wide(from).as_ptr(), wide(to).as_ptr(), 0) } == 0 {
+		return Err(io::Error::last_os_error());
+	}
+	Ok(())
+}
+
+// 改名、替换已有的文件、只改变大小写和移动含有条目的目录
+fn rename(dir: &Path) -> Result<(), String> {
+	let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+	fs::write(&a, b"first").step("creating")?;
+	fs::write(&b, b"second").step("creating")?;
+	expect_error(
+		move_without_replacing(&a, &b),
+		ERROR_ALREADY_EXISTS,
+		"renaming onto an existing file without replacing",
+	)?;
+	fs::rename(&a, &b).step("renaming onto an existing file")?;
+	ensure(read_all(&b)? == b"first", || {
+		"the replaced file kept its old content".to_string()
+	})?;
+	expect_error(
+		File::open(&a),
+		ERROR_FILE_NOT_FOUND,
+		"opening the renamed file by its old name",
+	)?;
+
+	fs::rename(&b, dir.join("B.TXT")).step("changing only the case")?;
+	let listed = names(dir)?;
+	ensure(listed == ["B.TXT"], || {
+		format!("listed {:?} after changing the case", listed)
+	})?;
+
+	let (from, to) = (dir.join("from"), dir.join("to"));
+	fs::create_dir(&from).step("creating a directory")?;
+	fs::write(from.join("inner.txt"), b"inner").step("creating")?;
+	fs::rename(&from, &to).step("renaming a directory")?;
+	ensure(read_all(&to.join("inner.txt"))? == b"inner", || {
+		"the moved directory lost its content".to_string()
+	})?;
+	expect_error(
+		fs::metadata(&from),
+		ERROR_FILE_NOT_FOUND,
+		"querying the old directory name",
+	)
+}
+
+// FILE_FLAG_DELETE_ON_CLOSE 和在其他句柄仍打开时删除（共享删除）
+fn delete_on_close(dir: &Path) -> Result<(), String> {
+	let path = dir.join("temporary.txt");
+	let mut file = OpenOptions::new()
+		.write(true)
+		.create_new(true)
+		.access_mode(GENERIC_READ | GENERIC_WRITE | DELETE)
+		.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+		.custom_flags(FILE_FLAG_DELETE_ON_CLOSE)
+		.open(&path)
+		.step("creating with FILE_FLAG_DELETE_ON_CLOSE")?;
+	file.write_all(b"scratch").step("writing")?;
+	ensure(read_all(&path)? == b"scratch", || {
+		"the file is not readable before it is closed".to_string()
+	})?;
+	drop(file);
+	expect_error(
+		fs::metadata(&path),
+		ERROR_FILE_NOT_FOUND,
+		"querying a file deleted on close",
+	)?;
+
+	let path = dir.join("open.txt");
+	fs::write(&path, b"open").step("creating")?;
+	let file = OpenOptions::new()
+		.read(true)
+		.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE)
+		.open(&path)
+		.step("opening")?;
+	fs::remove_file(&path).step("deleting an open file")?;
+	drop(file);
+	expect_error(
+		fs::metadata(&path),
+		ERROR_FILE_NOT_FOUND,
+		"querying a file deleted while open",
+	)
+}
+
+// 已打开的句柄不允许的访问以共享冲突失败
+fn share_modes(dir: &Path) -> Result<(), String> {
+	let path = dir.join("shared.txt");
+	fs::write(&path, b"shared").step("creating")?;
+	let reader = OpenOptions::new()
+		.read(true)
+		.share_mode(FILE_SHARE_READ)
+		.open(&path)
+		.step("opening with FILE_SHARE_READ")?;
+	drop(
+		OpenOptions::new()
+			.read(true)
+			.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+			.open(&path)
+			.step("opening a second reader")?,
+	);
+	expect_error(
+		OpenOptions::new()
+			.write(true)
+			.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+			.open(&path),
+		ERROR_SHARING_VIOLATION,
+		"writing a file shared for reading only",
+	)?;
+	expect_error(
+		fs::remove_file(&path),
+		ERROR_SHARING_VIOLATION,
+		"deleting a file shared without FILE_SHARE_DELETE",
+	)?;
+	drop(reader);
+
+	let exclusive = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.share_mode(0)
+		.open(&path)
+		.step("opening exclusively")?;
+	expect_error(
+		OpenOptions::new()
+			.read(true)
+			.share_mode(FILE_SHARE_READ | FILE_SHARE_WRITE)
+			.open(&path),
+		ERROR_SHARING_VIOLATION,
+		"reading an exclusively opened file",
+	)?;
+	drop(exclusive);
+	drop(
+		OpenOptions::new()
+			.write(true)
+			.open(&path)
+			.step("opening after the exclusive handle closed")?,
+	);
+	Ok(())
+}
+
+// 设置的时间戳在重新打开后保持（精确到秒），写入更新修改时间
+fn timestamps(dir: &Path) -> Result<(), String> {
+	let path = dir.join("times.txt");
+	fs::write(&path, b"times").step("creating")?;
+	let created = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+	let modified = UNIX_EPOCH + Duration::from_secs(1_600_000_000);
+	let file = OpenOptions::new().write(true).open(&path).step("opening")?;
+	file.set_times(FileTimes::new().set_modified(modified).set_created(created))
+		.step("setting the timestamps")?;
+	drop(file);
+
+	let seconds = |time: io::Result<SystemTime>| -> Result<u64, String> {
+		Ok(time
+			.step("querying a timestamp")?
+			.duration_since(UNIX_EPOCH)
+			.unwrap_or_default()
+			.as_secs())
+	};
+	let metadata = fs::metadata(&path).step("querying")?;
+	ensure(seconds(metadata.modified())? == 1_600_000_000, || {
+		format!("the modification time is {:?}", metadata.modified().ok())
+	})?;
+	ensure(seconds(metadata.created())? == 1_500_000_000, || {
+		format!("the creation time is {:?}", metadata.created().ok())
+	})?;
+
+	fs::write(&path, b"changed").step("rewriting")?;
+	let metadata = fs::metadata(&path).step("querying")?;
+	ensure(seconds(metadata.modified())? > 1_600_000_000, || {
+		"writing did not update the modification time".to_string()
+	})
+}
+
+// 总长超过 300 个字符的路径（标准库为长路径加上 \\?\ 前缀）上的创建、读写、列出、改名和删除；
+// 单个名称最长 255 个字符，更长时创建失败
+fn long_paths(dir: &Path) -> Result<(), String> {
+	let deep = (0..4).fold(dir.to_path_buf(), |path, level| {
+		path.join(format!("{}{}", level, "d".repeat(79)))
+	});
+	fs::create_dir_all(&deep).step("creating nested directories")?;
+	let name = "f".repeat(255);
+	let path = deep.join(&name);
+	ensure(path.as_os_str().len() > 300, || {
+		format!("{} is not a long path", path.display())
+	})?;
+	fs::write(&path, b"deep").step("creating a file at a long path")?;
+	ensure(read_all(&path)? == b"deep", || {
+		"the file at a long path has the wrong content".to_string()
+	})?;
+	let listed = names(&deep)?;
+	ensure(listed == [name.as_str()], || {
+		format!("listed {:?} in a deep directory", listed)
+	})?;
+
+	let renamed = deep.join("g".repeat(255));
+	fs::rename(&path, &renamed).step("renaming at a long path")?;
+	ensure(read_all(&renamed)? == b"deep", || {
+		"the renamed file lost its content".to_string()
+	})?;
+	ensure(File::create(deep.join("h".repeat(256))).is_err(), || {
+		"created a file with a 256-character name".to_string()
+	})?;
+	fs::remove_file(&renamed).step("deleting at a long path")?;
+	fs::remove_dir_all(dir.join(format!("0{}", "d".repeat(79)))).step("removing nested directories")
+}
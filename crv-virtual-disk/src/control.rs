@@ -0,0 +1,361 @@
+use std::{
+	fs::{self, OpenOptions},
+	io::{self, BufRead, BufReader, Read, Write},
+	ptr,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	thread,
+	time::{Duration, Instant},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use widestring::U16CString;
+use winapi::{
+	shared::{
+		minwindef::DWORD,
+		winerror::{ERROR_BROKEN_PIPE, ERROR_PIPE_CONNECTED},
+	},
+	um::{
+		fileapi::{FlushFileBuffers, ReadFile, WriteFile},
+		handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+		namedpipeapi::{ConnectNamedPipe, CreateNamedPipeW, DisconnectNamedPipe},
+		winbase::{
+			FILE_FLAG_FIRST_PIPE_INSTANCE, PIPE_ACCESS_DUPLEX, PIPE_READMODE_BYTE,
+			PIPE_REJECT_REMOTE_CLIENTS, PIPE_TYPE_BYTE, PIPE_UNLIMITED_INSTANCES, PIPE_WAIT,
+		},
+		winnt::HANDLE,
+	},
+};
+
+use crate::{
+	attr_cache::{AttrCache, CacheStats},
+	journal::{Conflict, Conflicts},
+	metrics::{Metrics, MetricsSnapshot},
+	mount::{request_unmount, Driver, ForceUnmount},
+	offline::{Dehydrated, OfflineCache},
+	open_files::{OpenFile, OpenFiles},
+};
+
+// 每个挂载的控制管道为 \\.\pipe\httpfs-<挂载点>，只接受本机连接
+const PIPE_DIR: &str = r"\\.\pipe\";
+const PIPE_PREFIX: &str = "httpfs-";
+
+// 单个请求行的长度上限
+const MAX_REQUEST_SIZE: u64 = 64 * 1024;
+
+// 客户端通过控制管道发送的命令，每个连接一行 JSON 请求、一行 JSON 响应
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Request {
+	Status,
+	Unmount,
+	// 拒绝新的打开，最多等待 timeout_secs 让打开的文件关闭后强制卸载
+	ForceUnmount { timeout_secs: u64 },
+	CachePurge,
+	CacheStats,
+	OpenFiles,
+	// 删除共享内 path 下未固定的文件的本地副本
+	Dehydrate { path: String },
+	Conflicts,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+	Status(Status),
+	Unmounting,
+	Purged { entries: usize },
+	Cache(CacheStats),
+	OpenFiles(Vec<OpenFile>),
+	Dehydrated(Dehydrated),
+	Conflicts(Vec<Conflict>),
+	Error { message: String },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Status {
+	pub mount_point: String,
+	pub server: String,
+	pub share: Option<String>,
+	pub uptime_secs: u64,
+	pub events: bool,
+	pub cache: CacheStats,
+	pub metrics: MetricsSnapshot,
+	// 当前打开的文件数，旧版本的挂载没有这一项
+	#[serde(default)]
+	pub open_files: usize,
+	// 重放日志时发现的冲突数
+	#[serde(default)]
+	pub conflicts: usize,
+}
+
+// 把挂载点转换为管道名中的标识：盘符挂载为大写字母（M:\ -> M），目录挂载的其他字符替换为 _
+pub fn mount_id(mount_point: &str) -> String {
+	mount_point
+		.trim_end_matches(['\\', '/', ':'])
+		.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() {
+				c.to_ascii_uppercase()
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+fn pipe_path(id: &str) -> String {
+	format!("{}{}{}", PIPE_DIR, PIPE_PREFIX, id)
+}
+
+// 本机上所有运行中挂载的标识
+pub fn running_mounts() -> io::Result<Vec<String>> {
+	let mut ids: Vec<String> = fs::read_dir(PIPE_DIR)?
+		.flatten()
+		.filter_map(|entry| {
+			entry
+				.file_name()
+				.to_str()?
+				.strip_prefix(PIPE_PREFIX)
+				.map(str::to_string)
+		})
+		.collect();
+	ids.sort();
+	Ok(ids)
+}
+
+// 向标识为 id 的挂载发送命令；该挂载不存在时返回 NotFound
+pub fn send(id: &str, request: &Request) -> io::Result<Response> {
+	let mut pipe = OpenOptions::new()
+		.read(true)
+		.write(true)
+		.open(pipe_path(id))?;
+	let mut line = serde_json::to_string(request)?;
+	line.push('\n');
+	pipe.write_all(line.as_bytes())?;
+	let mut response = String::new();
+	BufReader::new(pipe).read_line(&mut response)?;
+	Ok(serde_json::from_str(&response)?)
+}
+
+// 运行中的挂载响应控制命令所需的状态
+pub struct Controller {
+	pub mount_point: String,
+	pub server: String,
+	pub share: Option<String>,
+	pub events: bool,
+	pub attrs: Arc<AttrCache>,
+	pub metrics: Arc<Metrics>,
+	pub open_files: Arc<OpenFiles>,
+	// 没有启用本地副本时为 None
+	pub offline: Option<Arc<OfflineCache>>,
+	pub conflicts: Arc<Conflicts>,
+	// 卸载前先停止发出变更通知
+	pub stop_events: Arc<AtomicBool>,
+	pub force: Arc<ForceUnmount>,
+	pub driver: Driver,
+	pub started: Instant,
+}
+
+impl Controller {
+	fn handle(&self, request: Request) -> Response {
+		match request {
+			Request::Status => Response::Status(Status {
+				mount_point: self.mount_point.clone(),
+				server: self.server.clone(),
+				share: self.share.clone(),
+				uptime_secs: self.started.elapsed().as_secs(),
+				events: self.events,
+				cache: self.attrs.stats(),
+				metrics: self.metrics.snapshot(),
+				open_files: self.open_files.len(),
+				conflicts: self.conflicts.len(),
+			}),
+			Request::ForceUnmount { timeout_secs } if self.driver == Driver::Dokan => {
+				self.stop_events.store(true, Ordering::Relaxed);
+				self.force.request(Duration::from_secs(timeout_secs));
+				Response::Unmounting
+			}
+			// WinFsp 和 ProjFS 挂载没有等待打开的文件的卸载
+			Request::Unmount | Request::ForceUnmount { .. } => {
+				match U16CString::from_str(&self.mount_point) {
+					Ok(mount_point)
+						if request_unmount(&mount_point, self.driver, &self.stop_events) =>
+					{
+						Response::Unmounting
+					}
+					_ => Response::Error {
+						message: "failed to unmount file system".to_string(),
+					},
+				}
+			}
+			Request::CachePurge => Response::Purged {
+				entries: self.attrs.clear(),
+			},
+			Request::CacheStats => Response::Cache(self.attrs.stats()),
+			Request::OpenFiles => Response::OpenFiles(self.open_files.list()),
+			Request::Dehydrate { path } => match &self.offline {
+				Some(offline) => match offline.dehydrate(&path) {
+					Ok(dehydrated) => Response::Dehydrated(dehydrated),
+					Err(e) => Response::Error {
+						message: format!("dehydrate failed: {}", e),
+					},
+				},
+				None => Response::Error {
+					message: "the mount has no offline cache".to_string(),
+				},
+			},
+			Request::Conflicts => Response::Conflicts(self.conflicts.list()),
+		}
+	}
+
+	fn serve_connection(&self, pipe: Pipe) -> io::Result<()> {
+		let mut line = String::new();
+		BufReader::new((&pipe).take(MAX_REQUEST_SIZE)).read_line(&mut line)?;
+		let response = match serde_json::from_str::<Request>(&line) {
+			Ok(request) => self.handle(request),
+			Err(e) => Response::Error {
+				message: format!("invalid request: {}", e),
+			},
+		};
+		let mut line = serde_json::to_string(&response)?;
+		line.push('\n');
+		(&pipe).write_all(line.as_bytes())
+	}
+}
+
+// 在后台线程中逐个处理控制管道上的连接，直到进程退出
+pub fn serve(controller: Controller) {
+	let id = mount_id(&controller.mount_point);
+	let Ok(name) = U16CString::from_str(pipe_path(&id)) else {
+		return;
+	};
+	thread::spawn(move || {
+		let mut first = true;
+		loop {
+			let pipe = match Pipe::create(&name, first) {
+				Ok(pipe) => pipe,
+				Err(e) => {
+					error!(mount_point = %controller.mount_point, error = %e, "control: cannot create pipe");
+					return;
+				}
+			};
+			first = false;
+			if let Err(e) = pipe.connect() {
+				error!(error = %e, "control: connection failed");
+				continue;
+			}
+			if let Err(e) = controller.serve_connection(pipe) {
+				error!(error = %e, "control: request failed");
+			}
+		}
+	});
+}
+
+// 命名管道服务端的一个实例，关闭时断开客户端
+struct Pipe(HANDLE);
+
+impl Pipe {
+	// 第一个实例要求管道名未被占用，避免两个进程为同一挂载点响应命令
+	fn create(name: &U16CString, first: bool) -> io::Result<Self> {
+		let mut open_mode = PIPE_ACCESS_DUPLEX;
+		if first {
+			open_mode |= FILE_FLAG_FIRST_PIPE_INSTANCE;
+		}
+		let handle = unsafe {
+			CreateNamedPipeW(
+				name.as_ptr(),
+				open_mode,
+				PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT | PIPE_REJECT_REMOTE_CLIENTS,
+				PIPE_UNLIMITED_INSTANCES,
+				4096,
+				4096,
+				0,
+				ptr::null_mut(),
+			)
+		};
+		if handle == INVALID_HANDLE_VALUE {
+			Err(io::Error::last_os_error())
+		} else {
+			Ok(Self(handle))
+		}
+	}
+
+	// 等待客户端连接；客户端在创建和等待之间已经连接时同样视为成功
+	fn connect(&self) -> io::Result<()> {
+		if unsafe { ConnectNamedPipe(self.0, ptr::null_mut()) } != 0 {
+			return Ok(());
+		}
+		let error = io::Error::last_os_error();
+		if error.raw_os_error() == Some(ERROR_PIPE_CONNECTED as i32) {
+			Ok(())
+		} else {
+			Err(error)
+		}
+	}
+}
+
+impl Read for &Pipe {
+	fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+		let mut read: DWORD = 0;
+		let len = buf.len().min(DWORD::MAX as usize) as DWORD;
+		if unsafe {
+			ReadFile(
+				self.0,
+				buf.as_mut_ptr().cast(),
+				len,
+				&mut read,
+				ptr::null_mut(),
+			)
+		} != 0
+		{
+			return Ok(read as usize);
+		}
+		let error = io::Error::last_os_error();
+		// 客户端关闭了它的一端
+		if error.raw_os_error() == Some(ERROR_BROKEN_PIPE as i32) {
+			Ok(0)
+		} else {
+			Err(error)
+		}
+	}
+}
+
+impl Write for &Pipe {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		let mut written: DWORD = 0;
+		let len = buf.len().min(DWORD::MAX as usize) as DWORD;
+		if unsafe {
+			WriteFile(
+				self.0,
+				buf.as_ptr().cast(),
+				len,
+				&mut written,
+				ptr::null_mut(),
+			)
+		} != 0
+		{
+			Ok(written as usize)
+		} else {
+			Err(io::Error::last_os_error())
+		}
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Drop for Pipe {
+	fn drop(&mut self) {
+		// 等客户端读完响应后再断开
+		unsafe {
+			FlushFileBuffers(self.0);
+			DisconnectNamedPipe(self.0);
+			CloseHandle(self.0);
+		}
+	}
+}
@@ -0,0 +1,225 @@
+use std::io;
+#[cfg(unix)]
+use std::{
+	io::Write,
+	process::{Command, Stdio},
+};
+#[cfg(windows)]
+use std::{mem, ptr, slice};
+
+use serde::{Deserialize, Serialize};
+#[cfg(windows)]
+use widestring::{U16CStr, U16CString};
+#[cfg(windows)]
+use winapi::{
+	shared::winerror::ERROR_NOT_FOUND,
+	um::wincred::{
+		CredDeleteW, CredEnumerateW, CredFree, CredReadW, CredWriteW, CREDENTIALW,
+		CRED_MAX_CREDENTIAL_BLOB_SIZE, CRED_PERSIST_LOCAL_MACHINE, CRED_TYPE_GENERIC, PCREDENTIALW,
+	},
+};
+
+// 保存的访问设置在密钥环中的目标名称前缀
+const CREDENTIAL_PREFIX: &str = "httpfs:credential:";
+
+/// 以名称保存在系统密钥环（Windows 凭据管理器，其他平台上为 Secret Service）中的访问设置，
+/// 挂载时用 `--credential <名称>` 取用，服务器地址、令牌和加密口令不必出现在命令行或配置文件中。
+/// 只有保存它的用户能读取。
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Credentials {
+	/// 服务器地址，可以带有 WebDAV 的用户名和密码等
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub url: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub token: Option<String>,
+	/// 客户端加密的口令，没有设置 `HTTPFS_PASSPHRASE` 时使用
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub passphrase: Option<String>,
+}
+
+impl Credentials {
+	/// 读取名为 `name` 的设置，没有保存时返回 None。
+	pub fn load(name: &str) -> io::Result<Option<Self>> {
+		match read_secret(&target(name)) {
+			Some(text) => serde_json::from_str(&text).map(Some).map_err(|e| {
+				io::Error::new(
+					io::ErrorKind::InvalidData,
+					format!("the saved credential '{}' is damaged: {}", name, e),
+				)
+			}),
+			None => Ok(None),
+		}
+	}
+
+	/// 以名称 `name` 保存，替换原有的设置。
+	pub fn save(&self, name: &str) -> io::Result<()> {
+		write_secret(&target(name), &serde_json::to_string(self)?)
+	}
+
+	/// 删除名为 `name` 的设置，没有保存时什么也不做。
+	pub fn remove(name: &str) -> io::Result<()> {
+		delete_secret(&target(name))
+	}
+
+	/// 当前用户保存的所有设置的名称，按名称排序。
+	pub fn names() -> io::Result<Vec<String>> {
+		let mut names: Vec<String> = list_secrets(CREDENTIAL_PREFIX)?
+			.into_iter()
+			.map(|target| target[CREDENTIAL_PREFIX.len()..].to_string())
+			.collect();
+		names.sort();
+		Ok(names)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.url.is_none() && self.token.is_none() && self.passphrase.is_none()
+	}
+}
+
+fn target(name: &str) -> String {
+	format!("{}{}", CREDENTIAL_PREFIX, name)
+}
+
+// 凭据管理器中的通用凭据，内容为 UTF-8 文本
+#[cfg(windows)]
+pub(crate) fn read_secret(target: &str) -> Option<String> {
+	let target = U16CString::from_str(target).ok()?;
+	unsafe {
+		let mut credential: PCREDENTIALW = ptr::null_mut();
+		if CredReadW(target.as_ptr(), CRED_TYPE_GENERIC, 0, &mut credential) == 0 {
+			return None;
+		}
+		let blob = slice::from_raw_parts(
+			(*credential).CredentialBlob,
+			(*credential).CredentialBlobSize as usize,
+		);
+		let secret = String::from_utf8(blob.to_vec()).ok();
+		CredFree(credential.cast());
+		secret
+	}
+}
+
+#[cfg(windows)]
+pub(crate) fn write_secret(target: &str, secret: &str) -> io::Result<()> {
+	if secret.len() > CRED_MAX_CREDENTIAL_BLOB_SIZE as usize {
+		return Err(io::Error::other(format!(
+			"{} is longer than the {} bytes the Credential Manager can keep",
+			target, CRED_MAX_CREDENTIAL_BLOB_SIZE
+		)));
+	}
+	let target = U16CString::from_str(target).map_err(io::Error::other)?;
+	let mut user = U16CString::from_str("httpfs").unwrap();
+	unsafe {
+		let mut credential: CREDENTIALW = mem::zeroed();
+		credential.Type = CRED_TYPE_GENERIC;
+		credential.TargetName = target.as_ptr().cast_mut();
+		credential.UserName = user.as_mut_ptr();
+		credential.CredentialBlobSize = secret.len() as u32;
+		credential.CredentialBlob = secret.as_ptr().cast_mut();
+		credential.Persist = CRED_PERSIST_LOCAL_MACHINE;
+		if CredWriteW(&mut credential, 0) == 0 {
+			return Err(io::Error::last_os_error());
+		}
+	}
+	Ok(())
+}
+
+#[cfg(windows)]
+pub(crate) fn delete_secret(target: &str) -> io::Result<()> {
+	let target = U16CString::from_str(target).map_err(io::Error::other)?;
+	if unsafe { CredDeleteW(target.as_ptr(), CRED_TYPE_GENERIC, 0) } == 0 {
+		let error = io::Error::last_os_error();
+		if error.raw_os_error() != Some(ERROR_NOT_FOUND as i32) {
+			return Err(error);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(windows)]
+fn list_secrets(prefix: &str) -> io::Result<Vec<String>> {
+	let filter = U16CString::from_str(format!("{}*", prefix)).map_err(io::Error::other)?;
+	let mut targets = Vec::new();
+	unsafe {
+		let mut count = 0;
+		let mut credentials: *mut PCREDENTIALW = ptr::null_mut();
+		if CredEnumerateW(filter.as_ptr(), 0, &mut count, &mut credentials) == 0 {
+			let error = io::Error::last_os_error();
+			return if error.raw_os_error() == Some(ERROR_NOT_FOUND as i32) {
+				Ok(targets)
+			} else {
+				Err(error)
+			};
+		}
+		for &credential in slice::from_raw_parts(credentials, count as usize) {
+			if (*credential).Type == CRED_TYPE_GENERIC {
+				targets.push(U16CStr::from_ptr_str((*credential).TargetName).to_string_lossy());
+			}
+		}
+		CredFree(credentials.cast());
+	}
+	Ok(targets)
+}
+
+// 其他平台通过 libsecret 的 secret-tool 访问 Secret Service，条目带有 service=httpfs 和 target 两个属性
+#[cfg(unix)]
+fn secret_tool(args: &[&str]) -> Command {
+	let mut command = Command::new("secret-tool");
+	command
+		.args(args)
+		.stdin(Stdio::null())
+		.stderr(Stdio::null());
+	command
+}
+
+#[cfg(unix)]
+pub(crate) fn read_secret(target: &str) -> Option<String> {
+	let output = secret_tool(&["lookup", "service", "httpfs", "target", target])
+		.output()
+		.ok()?;
+	if !output.status.success() {
+		return None;
+	}
+	String::from_utf8(output.stdout).ok()
+}
+
+#[cfg(unix)]
+pub(crate) fn write_secret(target: &str, secret: &str) -> io::Result<()> {
+	let label = format!("--label={}", target);
+	let mut child = secret_tool(&["store", &label, "service", "httpfs", "target", target])
+		.stdin(Stdio::piped())
+		.spawn()?;
+	child.stdin.take().unwrap().write_all(secret.as_bytes())?;
+	match child.wait()? {
+		status if status.success() => Ok(()),
+		status => Err(io::Error::other(format!(
+			"secret-tool could not save {} ({})",
+			target, status
+		))),
+	}
+}
+
+#[cfg(unix)]
+pub(crate) fn delete_secret(target: &str) -> io::Result<()> {
+	// 没有匹配的条目时 secret-tool 同样成功退出
+	match secret_tool(&["clear", "service", "httpfs", "target", target]).status()? {
+		status if status.success() => Ok(()),
+		status => Err(io::Error::other(format!(
+			"secret-tool could not delete {} ({})",
+			target, status
+		))),
+	}
+}
+
+#[cfg(unix)]
+fn list_secrets(prefix: &str) -> io::Result<Vec<String>> {
+	let output = secret_tool(&["search", "--all", "service", "httpfs"]).output()?;
+	// 每个条目输出 "attribute.target = <名称>" 等行；没有条目时以非零状态退出
+	Ok(String::from_utf8_lossy(&output.stdout)
+		.lines()
+		.filter_map(|line| line.strip_prefix("attribute.target = "))
+		.filter(|target| target.starts_with(prefix))
+		.map(str::to_string)
+		.collect())
+}
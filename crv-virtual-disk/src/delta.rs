@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+// 块的强校验和取 SHA-256 的前 16 字节，与服务器一致
+const STRONG_LEN: usize = 16;
+
+/// 服务器上文件的块签名（`GET /signature/:path`），最后一块可能不足 `block_size`。
+#[derive(Debug, Clone, Deserialize)]
+pub struct Signature {
+	pub size: u64,
+	pub block_size: usize,
+	pub blocks: Vec<BlockSignature>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct BlockSignature {
+	pub weak: u32,
+	pub strong: String,
+}
+
+/// 新内容中与服务器上的文件相同的一段：从原文件的 `source` 处复制 `length` 字节到新内容的 `offset` 处。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CopyRange {
+	pub source: u64,
+	pub offset: u64,
+	pub length: u64,
+}
+
+/// 新内容相对服务器上文件的差异：可以在服务器上复制的区间，其余（`literals`，`[start, end)`）需要上传。
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Delta {
+	pub copies: Vec<CopyRange>,
+	pub literals: Vec<(u64, u64)>,
+}
+
+impl Delta {
+	/// 需要上传的字节数。
+	pub fn literal_len(&self) -> u64 {
+		self.literals.iter().map(|(start, end)| end - start).sum()
+	}
+}
+
+/// rsync 的弱校验和：a 为字节之和，b 为按位置加权的和，各取低 16 位。
+pub fn weak_checksum(block: &[u8]) -> u32 {
+	Rolling::new(block).value()
+}
+
+fn strong_checksum(block: &[u8]) -> String {
+	hex::encode(&Sha256::digest(block)[..STRONG_LEN])
+}
+
+// 窗口的弱校验和，窗口后移一个字节时只需常数时间更新
+struct Rolling {
+	a: u32,
+	b: u32,
+	len: u32,
+}
+
+impl Rolling {
+	fn new(window: &[u8]) -> Self {
+		let len = window.len() as u32;
+		let mut rolling = Self { a: 0, b: 0, len };
+		for (i, &byte) in window.iter().enumerate() {
+			rolling.a = rolling.a.wrapping_add(byte as u32);
+			rolling.b = rolling
+				.b
+				.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+		}
+		rolling
+	}
+
+	// 移出窗口开头的 out，移入 into
+	fn roll(&mut self, out: u8, into: u8) {
+		self.a = self.a.wrapping_sub(out as u32).wrapping_add(into as u32);
+		self.b = self
+			.b
+			.wrapping_sub(self.len.wrapping_mul(out as u32))
+			.wrapping_add(self.a);
+	}
+
+	fn value(&self) -> u32 {
+		(self.a & 0xffff) | ((self.b & 0xffff) << 16)
+	}
+}
+
+/// 在 `data` 中逐字节滑动窗口查找与签名中整块相同的部分。块可以移动位置（插入或删除内容之后），
+/// 相邻的复制合并为一个区间。
+pub fn diff(signature: &Signature, data: &[u8]) -> Delta {
+	let block_size = signature.block_size;
+	let mut delta = Delta::default();
+	let full_blocks = signature.size.checked_div(block_size as u64).unwrap_or(0) as usize;
+	let mut by_weak: HashMap<u32, Vec<usize>> = HashMap::new();
+	for (index, block) in signature.blocks.iter().enumerate().take(full_blocks) {
+		by_weak.entry(block.weak).or_default().push(index);
+	}
+	if block_size == 0 || by_weak.is_empty() || data.len() < block_size {
+		if !data.is_empty() {
+			delta.literals.push((0, data.len() as u64));
+		}
+		return delta;
+	}
+
+	let mut literal_start = 0;
+	let mut position = 0;
+	let mut rolling = Rolling::new(&data[..block_size]);
+	loop {
+		let window = &data[position..position + block_size];
+		let matched = by_weak.get(&rolling.value()).and_then(|candidates| {
+			let strong = strong_checksum(window);
+			candidates
+				.iter()
+				.copied()
+				.find(|&index| signature.blocks[index].strong == strong)
+		});
+		if let Some(index) = matched {
+			if literal_start < position {
+				delta.literals.push((literal_start as u64, position as u64));
+			}
+			let source = (index * block_size) as u64;
+			match delta.copies.last_mut() {
+				Some(last)
+					if last.source + last.length == source
+						&& last.offset + last.length == position as u64 =>
+				{
+					last.length += block_size as u64
+				}
+				_ => delta.copies.push(CopyRange {
+					source,
+					offset: position as u64,
+					length: block_size as u64,
+				}),
+			}
+			position += block_size;
+			literal_start = position;
+			if position + block_size > data.len() {
+				break;
+			}
+			rolling = Rolling::new(&data[position..position + block_size]);
+		} else {
+			if position + block_size >= data.len() {
+				break;
+			}
+			rolling.roll(data[position], data[position + block_size]);
+			position += 1;
+		}
+	}
+	if literal_start < data.len() {
+		delta
+			.literals
+			.push((literal_start as u64, data.len() as u64));
+	}
+	delta
+}
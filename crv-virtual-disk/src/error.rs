@@ -0,0 +1,256 @@
+use std::{fmt, thread, time::Duration};
+
+use reqwest::{
+	blocking::{RequestBuilder, Response},
+	header::RETRY_AFTER,
+	StatusCode,
+};
+use serde::Deserialize;
+use winapi::shared::{ntdef::NTSTATUS, ntstatus::*};
+
+// 服务器失败响应的 JSON 体
+#[derive(Debug, Deserialize)]
+pub struct ApiError {
+	pub code: String,
+	pub message: String,
+	pub os_error: Option<i32>,
+}
+
+#[derive(Debug)]
+pub enum RemoteError {
+	// 请求没有得到响应（连接失败、超时）或响应体无法解析
+	Transport(reqwest::Error),
+	// 服务器返回的错误状态码及错误体，request_id 用于在服务器访问日志中查找该请求
+	Api {
+		status: StatusCode,
+		error: ApiError,
+		request_id: Option<String>,
+	},
+	// 不经过 httpfs 服务器的后端给出的错误，code 使用与服务器相同的错误类别
+	Backend {
+		code: &'static str,
+		message: String,
+	},
+}
+
+impl RemoteError {
+	pub fn backend(code: &'static str, message: impl Into<String>) -> Self {
+		RemoteError::Backend {
+			code,
+			message: message.into(),
+		}
+	}
+
+	// 后端没有实现的可选操作
+	pub fn unsupported(operation: &str) -> Self {
+		Self::backend(
+			"not_supported",
+			format!("{} is not supported by this storage backend", operation),
+		)
+	}
+
+	pub fn code(&self) -> Option<&str> {
+		match self {
+			RemoteError::Transport(_) => None,
+			RemoteError::Api { error, .. } => Some(&error.code),
+			RemoteError::Backend { code, .. } => Some(code),
+		}
+	}
+
+	// 按服务器给出的错误类别选择最接近的 NTSTATUS，未知类别按访问被拒绝处理
+	pub fn to_ntstatus(&self) -> NTSTATUS {
+		let code = match self {
+			RemoteError::Transport(e) if e.is_timeout() => return STATUS_IO_TIMEOUT,
+			RemoteError::Transport(_) => return STATUS_UNEXPECTED_NETWORK_ERROR,
+			RemoteError::Api { error, .. } => error.code.as_str(),
+			RemoteError::Backend { code, .. } => code,
+		};
+		match code {
+			"not_found" | "xattr_not_found" => STATUS_OBJECT_NAME_NOT_FOUND,
+			"parent_not_found" => STATUS_OBJECT_PATH_NOT_FOUND,
+			"already_exists" | "merge_conflict" => STATUS_OBJECT_NAME_COLLISION,
+			"directory_not_empty" => STATUS_DIRECTORY_NOT_EMPTY,
+			"not_a_directory" => STATUS_NOT_A_DIRECTORY,
+			"is_a_directory" => STATUS_FILE_IS_A_DIRECTORY,
+			// 超过服务器单次写入上限的内容无法保存，按磁盘已满处理
+			"disk_full" | "write_too_large" => STATUS_DISK_FULL,
+			"file_too_large" | "payload_too_large" => STATUS_FILE_TOO_LARGE,
+			"invalid_name" => STATUS_OBJECT_NAME_INVALID,
+			"bad_request" | "invalid_input" | "move_into_self" => STATUS_INVALID_PARAMETER,
+			"checksum_mismatch" | "decryption_failed" | "corrupt_data" => STATUS_DATA_ERROR,
+			"share_not_found" => STATUS_BAD_NETWORK_NAME,
+			// 服务器的内容扫描器暂时不可用，稍后重试即可
+			"rate_limited" | "scan_failed" => STATUS_DEVICE_BUSY,
+			"locked" => STATUS_SHARING_VIOLATION,
+			"connection_lost" => STATUS_UNEXPECTED_NETWORK_ERROR,
+			"read_only" => STATUS_MEDIA_WRITE_PROTECTED,
+			"not_supported" => STATUS_NOT_SUPPORTED,
+			"virus_infected" => STATUS_VIRUS_INFECTED,
+			// WORM 共享中保留期限内的文件，错误消息给出期限
+			"retained" => STATUS_ACCESS_DENIED,
+			_ => STATUS_ACCESS_DENIED,
+		}
+	}
+
+	// FUSE 挂载中与 to_ntstatus 对应的 errno
+	#[cfg(all(unix, feature = "fuse"))]
+	pub fn to_errno(&self) -> i32 {
+		let code = match self {
+			RemoteError::Transport(e) if e.is_timeout() => return libc::ETIMEDOUT,
+			RemoteError::Transport(_) => return libc::EIO,
+			RemoteError::Api { error, .. } => error.code.as_str(),
+			RemoteError::Backend { code, .. } => code,
+		};
+		match code {
+			"not_found" | "parent_not_found" => libc::ENOENT,
+			"xattr_not_found" => libc::ENODATA,
+			"already_exists" | "merge_conflict" => libc::EEXIST,
+			"directory_not_empty" => libc::ENOTEMPTY,
+			"not_a_directory" => libc::ENOTDIR,
+			"is_a_directory" => libc::EISDIR,
+			"disk_full" | "write_too_large" => libc::ENOSPC,
+			"file_too_large" | "payload_too_large" => libc::EFBIG,
+			"invalid_name" | "bad_request" | "invalid_input" | "move_into_self" => libc::EINVAL,
+			"checksum_mismatch" | "decryption_failed" | "corrupt_data" | "connection_lost"
+			| "share_not_found" => libc::EIO,
+			"rate_limited" | "scan_failed" | "locked" => libc::EBUSY,
+			"read_only" => libc::EROFS,
+			"not_supported" => libc::EOPNOTSUPP,
+			_ => libc::EACCES,
+		}
+	}
+}
+
+// 失败状态的简短说明，供 status 和托盘列出最近的错误
+pub fn describe_status(status: u32) -> &'static str {
+	match status as NTSTATUS {
+		STATUS_UNEXPECTED_NETWORK_ERROR => "server unreachable",
+		STATUS_IO_TIMEOUT => "server timed out",
+		STATUS_ACCESS_DENIED => "access denied",
+		STATUS_DISK_FULL => "share is full",
+		STATUS_FILE_TOO_LARGE => "file too large",
+		STATUS_DATA_ERROR => "checksum mismatch or corrupt data",
+		STATUS_BAD_NETWORK_NAME => "share not found",
+		STATUS_DEVICE_BUSY => "server busy",
+		STATUS_OBJECT_NAME_INVALID => "invalid name",
+		STATUS_INVALID_PARAMETER => "invalid request",
+		STATUS_NOT_SUPPORTED => "not supported by the backend",
+		STATUS_SHARING_VIOLATION => "locked by another client",
+		STATUS_MEDIA_WRITE_PROTECTED => "read-only volume",
+		STATUS_VIRUS_INFECTED => "blocked by the server's virus scanner",
+		_ => "failed",
+	}
+}
+
+impl fmt::Display for RemoteError {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			RemoteError::Transport(e) => write!(f, "request failed: {}", e),
+			RemoteError::Api {
+				status,
+				error,
+				request_id,
+			} => {
+				write!(
+					f,
+					"server returned {} {}: {}",
+					status.as_u16(),
+					error.code,
+					error.message
+				)?;
+				if let Some(os_error) = error.os_error {
+					write!(f, " (os error {})", os_error)?;
+				}
+				if let Some(request_id) = request_id {
+					write!(f, " [request {}]", request_id)?;
+				}
+				Ok(())
+			}
+			RemoteError::Backend { code, message } => write!(f, "{}: {}", code, message),
+		}
+	}
+}
+
+impl std::error::Error for RemoteError {}
+
+impl From<reqwest::Error> for RemoteError {
+	fn from(e: reqwest::Error) -> Self {
+		RemoteError::Transport(e)
+	}
+}
+
+// 代替 error_for_status：失败时解析服务器的错误体，旧版服务器的空错误体按状态码生成类别
+pub trait CheckStatus {
+	fn check_status(self) -> Result<Response, RemoteError>;
+}
+
+impl CheckStatus for Response {
+	fn check_status(self) -> Result<Response, RemoteError> {
+		let status = self.status();
+		if !(status.is_client_error() || status.is_server_error()) {
+			return Ok(self);
+		}
+		let request_id = self
+			.headers()
+			.get("x-request-id")
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string);
+		let body = self.text().unwrap_or_default();
+		let error = serde_json::from_str::<ApiError>(&body).unwrap_or_else(|_| ApiError {
+			code: match status {
+				StatusCode::NOT_FOUND => "not_found",
+				StatusCode::CONFLICT => "already_exists",
+				StatusCode::INSUFFICIENT_STORAGE => "disk_full",
+				StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+				_ => "http_error",
+			}
+			.to_string(),
+			message: if body.is_empty() {
+				status.canonical_reason().unwrap_or("error").to_string()
+			} else {
+				body
+			},
+			os_error: None,
+		});
+		Err(RemoteError::Api {
+			status,
+			error,
+			request_id,
+		})
+	}
+}
+
+// 服务器限流时的重试次数和单次等待的上限，避免 Dokan 操作长时间阻塞
+const RATE_LIMIT_RETRIES: usize = 5;
+const MAX_RATE_LIMIT_WAIT: Duration = Duration::from_secs(5);
+
+// 代替 send：服务器返回 429 时按 Retry-After 等待后重发，请求体无法复制（流式上传）时不重试
+pub trait SendRetrying {
+	fn send_retrying(self) -> Result<Response, RemoteError>;
+}
+
+impl SendRetrying for RequestBuilder {
+	fn send_retrying(self) -> Result<Response, RemoteError> {
+		let mut request = self;
+		let mut attempt = 0;
+		loop {
+			let retry = request.try_clone();
+			let response = request.send()?;
+			let Some(next) = retry.filter(|_| {
+				response.status() == StatusCode::TOO_MANY_REQUESTS && attempt < RATE_LIMIT_RETRIES
+			}) else {
+				return Ok(response);
+			};
+			let wait = response
+				.headers()
+				.get(RETRY_AFTER)
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.parse::<u64>().ok())
+				.map_or(Duration::from_secs(1), Duration::from_secs)
+				.min(MAX_RATE_LIMIT_WAIT);
+			thread::sleep(wait);
+			request = next;
+			attempt += 1;
+		}
+	}
+}
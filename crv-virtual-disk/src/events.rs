@@ -0,0 +1,268 @@
+use std::{
+	io::{BufRead, BufReader},
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc,
+	},
+	thread,
+	time::Duration,
+};
+
+use clap::ValueEnum;
+use dokan::{
+	notify_create, notify_delete, notify_rename, notify_update, unmount, FileSystemHandle,
+};
+use reqwest::{
+	blocking::Client,
+	header::{HeaderMap, ACCEPT},
+};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+use widestring::U16CString;
+
+use crate::{
+	attr_cache::AttrCache,
+	auth::OAuth,
+	error::{CheckStatus, RemoteError},
+	offline::OfflineCache,
+	open_files::OpenFiles,
+	oplock::OplockBreaker,
+};
+
+// 连接断开后重新订阅前的等待时间
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+// 服务器预告关闭时，在计划时间之前这么久卸载，留出写回打开文件的时间
+const UNMOUNT_MARGIN: Duration = Duration::from_secs(30);
+
+// 服务器预告关闭时的处理：两种方式都会清空属性缓存
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ShutdownPolicy {
+	// 在计划关闭前卸载，卸载时打开的文件写回服务器
+	#[default]
+	Unmount,
+	// 保持挂载，服务器停机期间的操作失败，恢复后自动继续
+	Keep,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShutdownNotice {
+	remaining_secs: u64,
+	message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ChangeEvent {
+	path: String,
+	new_path: Option<String>,
+	is_directory: bool,
+}
+
+// 订阅服务器的 /events，把其他客户端或服务器本地造成的变化转换为 Dokan 变更通知，
+// 让资源管理器等程序刷新缓存的目录内容，同时使属性缓存中的对应条目失效、重新同步固定在本地的副本，
+// 并打破本机程序在被修改的文件上持有的机会锁；服务器预告关闭时按 on_shutdown 处理。stop 置位后不再发出通知
+#[allow(clippy::too_many_arguments)]
+pub fn spawn(
+	base_url: String,
+	headers: HeaderMap,
+	oauth: Option<Arc<OAuth>>,
+	mount_point: String,
+	instance: FileSystemHandle,
+	attrs: Arc<AttrCache>,
+	offline: Option<Arc<OfflineCache>>,
+	open_files: Arc<OpenFiles>,
+	stop: Arc<AtomicBool>,
+	on_shutdown: ShutdownPolicy,
+) {
+	thread::spawn(move || {
+		// 事件流是长连接，不设置总超时
+		let client = match Client::builder()
+			.default_headers(headers)
+			.timeout(None)
+			.build()
+		{
+			Ok(client) => client,
+			Err(e) => {
+				error!(error = %e, "events: failed to build client");
+				return;
+			}
+		};
+		let notifier = Notifier {
+			mount_point,
+			instance,
+			attrs,
+			offline,
+			open_files,
+			oplocks: OplockBreaker::spawn(),
+			stop: stop.clone(),
+			on_shutdown,
+			unmount_scheduled: AtomicBool::new(false),
+		};
+		while !stop.load(Ordering::Relaxed) {
+			let mut request = client
+				.get(format!("{}/events", base_url))
+				.header(ACCEPT, "text/event-stream");
+			// 每次重新订阅时取当前的访问令牌，已建立的事件流不受令牌到期影响
+			if let Some(oauth) = &oauth {
+				request = oauth.authorize(request);
+			}
+			let response = request
+				.send()
+				.map_err(RemoteError::from)
+				.and_then(CheckStatus::check_status);
+			match response {
+				Ok(response) => {
+					let mut event = String::new();
+					for line in BufReader::new(response).lines() {
+						let Ok(line) = line else {
+							break;
+						};
+						if stop.load(Ordering::Relaxed) {
+							return;
+						}
+						if let Some(name) = line.strip_prefix("event:") {
+							event = name.trim().to_string();
+						} else if let Some(data) = line.strip_prefix("data:") {
+							notifier.dispatch(&event, data.trim());
+						} else if line.is_empty() {
+							event.clear();
+						}
+					}
+					warn!("events: stream closed, reconnecting");
+				}
+				// 旧版服务器没有 /events
+				Err(e) if e.code() == Some("not_found") => {
+					warn!("events: server does not support change notifications");
+					return;
+				}
+				Err(e) => error!(error = %e, "events: subscribe failed"),
+			}
+			thread::sleep(RECONNECT_DELAY);
+		}
+	});
+}
+
+struct Notifier {
+	mount_point: String,
+	instance: FileSystemHandle,
+	attrs: Arc<AttrCache>,
+	offline: Option<Arc<OfflineCache>>,
+	open_files: Arc<OpenFiles>,
+	oplocks: OplockBreaker,
+	stop: Arc<AtomicBool>,
+	on_shutdown: ShutdownPolicy,
+	// 重新订阅时会再次收到同一预告，只安排一次卸载
+	unmount_scheduled: AtomicBool,
+}
+
+impl Notifier {
+	// 共享内的路径转换为挂载点下的完整路径
+	fn full_path(&self, path: &str) -> Option<U16CString> {
+		let full = format!(
+			"{}\\{}",
+			self.mount_point.trim_end_matches('\\'),
+			path.replace('/', "\\")
+		);
+		U16CString::from_str(full).ok()
+	}
+
+	fn shutdown_notice(&self, data: &str) {
+		let Ok(notice) = serde_json::from_str::<ShutdownNotice>(data) else {
+			return;
+		};
+		warn!(
+			mount_point = %self.mount_point,
+			remaining_secs = notice.remaining_secs,
+			message = notice.message.as_deref().unwrap_or_default(),
+			"events: server announced a shutdown"
+		);
+		// 服务器停机期间共享中的文件可能被修改
+		self.attrs.clear();
+		if self.on_shutdown != ShutdownPolicy::Unmount
+			|| self.unmount_scheduled.swap(true, Ordering::Relaxed)
+		{
+			return;
+		}
+		let delay = Duration::from_secs(notice.remaining_secs).saturating_sub(UNMOUNT_MARGIN);
+		let (mount_point, stop) = (self.mount_point.clone(), self.stop.clone());
+		thread::spawn(move || {
+			thread::sleep(delay);
+			// 已经在卸载
+			if stop.swap(true, Ordering::Relaxed) {
+				return;
+			}
+			info!(mount_point = %mount_point, "events: unmounting before the server shuts down");
+			if !U16CString::from_str(&mount_point).is_ok_and(|mount_point| unmount(&mount_point)) {
+				error!(mount_point = %mount_point, "events: failed to unmount file system");
+			}
+		});
+	}
+
+	fn dispatch(&self, event: &str, data: &str) {
+		if event == "shutdown_notice" {
+			self.shutdown_notice(data);
+			return;
+		}
+		// 丢失了部分事件，通知根目录整体刷新
+		if event == "resync" {
+			self.attrs.clear();
+			if let Some(offline) = &self.offline {
+				offline.resync();
+			}
+			if let Ok(root) = U16CString::from_str(&self.mount_point) {
+				let _ = notify_update(self.instance, &root);
+			}
+			return;
+		}
+		let Ok(change) = serde_json::from_str::<ChangeEvent>(data) else {
+			return;
+		};
+		self.attrs.invalidate(&change.path);
+		if let Some(new_path) = &change.new_path {
+			self.attrs.invalidate(new_path);
+		}
+		if let Some(offline) = &self.offline {
+			offline.changed(&change.path);
+			if let Some(new_path) = &change.new_path {
+				offline.changed(new_path);
+			}
+		}
+		let Some(path) = self.full_path(&change.path) else {
+			return;
+		};
+		// 其他程序打开着被修改的文件时，让它们丢弃缓存的内容
+		if event == "modify"
+			&& !change.is_directory
+			&& self.open_files.is_open_elsewhere(&change.path)
+		{
+			self.oplocks.request(path.clone());
+		}
+		// 通知失败（例如对应的目录从未被打开过）不影响后续事件
+		let _ = match event {
+			"create" => notify_create(self.instance, &path, change.is_directory),
+			"delete" => notify_delete(self.instance, &path, change.is_directory),
+			"modify" => notify_update(self.instance, &path),
+			"rename" => {
+				let Some(new_path) = change.new_path.as_deref().and_then(|p| self.full_path(p))
+				else {
+					return;
+				};
+				let parent = |p: &str| {
+					p.rsplit_once('/')
+						.map_or("", |(parent, _)| parent)
+						.to_string()
+				};
+				let same_dir = change.new_path.as_deref().map(parent) == Some(parent(&change.path));
+				notify_rename(
+					self.instance,
+					&path,
+					&new_path,
+					change.is_directory,
+					same_dir,
+				)
+			}
+			_ => return,
+		};
+	}
+}
@@ -0,0 +1,405 @@
+use std::{
+	ffi::OsStr,
+	io,
+	path::Path,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use fuser::{
+	FileAttr, FileType, Filesystem, MountOption, ReplyAttr, ReplyCreate, ReplyData, ReplyDirectory,
+	ReplyEmpty, ReplyEntry, ReplyStatfs, ReplyWrite, Request, TimeOrNow,
+};
+use tracing::error;
+
+use crate::{
+	error::RemoteError,
+	vfs::{child_path, Inodes, VirtualFs, MAX_NAME_LENGTH},
+	RemoteFileInfo, TimesUpdate,
+};
+
+// 内核缓存属性和查找结果的时间，其他客户端的修改最多延迟这么久才能看到
+const TTL: Duration = Duration::from_secs(1);
+const BLOCK_SIZE: u32 = 4096;
+// readdir 中还没有分配 inode 号的条目；内核只在 use_ino 挂载选项下使用 readdir 给出的 inode 号
+const UNKNOWN_INODE: u64 = u64::MAX;
+// renameat2 的标志
+const RENAME_NOREPLACE: u32 = 1;
+
+// 把内核的 FUSE 请求映射到 VirtualFs 的操作。写入直接发送到存储，不像 Dokan 挂载那样暂存整文件后原子提交；
+// 不提供 .snapshots 伪目录和扩展属性
+struct FuseAdapter<F> {
+	fs: F,
+	inodes: Inodes,
+	uid: u32,
+	gid: u32,
+}
+
+// 记录错误并换算为 errno；条目不存在是查找的正常结果，不记录
+fn errno(path: &str, e: &RemoteError) -> i32 {
+	if e.code() != Some("not_found") {
+		error!(path = %path, error = %e, "fuse operation failed");
+	}
+	e.to_errno()
+}
+
+// 内核给出的文件偏移是 i64，负值无效
+fn file_offset(offset: i64) -> Result<u64, i32> {
+	u64::try_from(offset).map_err(|_| libc::EINVAL)
+}
+
+fn seconds(time: TimeOrNow) -> u64 {
+	let time = match time {
+		TimeOrNow::SpecificTime(time) => time,
+		TimeOrNow::Now => SystemTime::now(),
+	};
+	time.duration_since(UNIX_EPOCH)
+		.map_or(0, |elapsed| elapsed.as_secs())
+}
+
+impl<F: VirtualFs> FuseAdapter<F> {
+	// 只读的存储去掉写权限，权限由内核按 default_permissions 检查
+	fn attr(&self, inode: u64, info: &RemoteFileInfo) -> FileAttr {
+		let time = |secs| UNIX_EPOCH + Duration::from_secs(secs);
+		let (kind, perm, nlink) = match info.is_directory {
+			true => (FileType::Directory, 0o755, 2),
+			false => (FileType::RegularFile, 0o644, 1),
+		};
+		FileAttr {
+			ino: inode,
+			size: info.size,
+			blocks: info.allocated_size.unwrap_or(info.size).div_ceil(512),
+			atime: time(info.accessed),
+			mtime: time(info.modified),
+			ctime: time(info.modified),
+			crtime: time(info.created),
+			kind,
+			perm: if self.fs.read_only() {
+				perm & 0o555
+			} else {
+				perm
+			},
+			nlink,
+			uid: self.uid,
+			gid: self.gid,
+			rdev: 0,
+			blksize: BLOCK_SIZE,
+			flags: 0,
+		}
+	}
+
+	fn path(&self, inode: u64) -> Result<String, i32> {
+		self.inodes
+			.path(inode)
+			.map(str::to_string)
+			.ok_or(libc::ENOENT)
+	}
+
+	fn child(&self, parent: u64, name: &OsStr) -> Result<String, i32> {
+		let name = name.to_str().ok_or(libc::EINVAL)?;
+		Ok(child_path(&self.path(parent)?, name))
+	}
+
+	// 查找到或新建的条目：计入查找并返回属性
+	fn entry(&mut self, path: &str) -> Result<FileAttr, i32> {
+		let info = self.fs.stat(path).map_err(|e| errno(path, &e))?;
+		let inode = self.inodes.lookup(path);
+		Ok(self.attr(inode, &info))
+	}
+
+	fn stat(&self, inode: u64) -> Result<FileAttr, i32> {
+		let path = self.path(inode)?;
+		let info = self.fs.stat(&path).map_err(|e| errno(&path, &e))?;
+		Ok(self.attr(inode, &info))
+	}
+
+	fn set_attributes(
+		&self,
+		inode: u64,
+		size: Option<u64>,
+		accessed: Option<TimeOrNow>,
+		modified: Option<TimeOrNow>,
+	) -> Result<FileAttr, i32> {
+		let path = self.path(inode)?;
+		if let Some(size) = size {
+			self.fs
+				.truncate(&path, size)
+				.map_err(|e| errno(&path, &e))?;
+		}
+		let times = TimesUpdate {
+			created: None,
+			accessed: accessed.map(seconds),
+			modified: modified.map(seconds),
+		};
+		if !times.is_empty() {
+			self.fs
+				.set_times(&path, &times)
+				.map_err(|e| errno(&path, &e))?;
+		}
+		self.stat(inode)
+	}
+
+	fn create_entry(
+		&mut self,
+		parent: u64,
+		name: &OsStr,
+		is_directory: bool,
+	) -> Result<FileAttr, i32> {
+		let path = self.child(parent, name)?;
+		self.fs
+			.create(&path, is_directory)
+			.map_err(|e| errno(&path, &e))?;
+		self.entry(&path)
+	}
+
+	fn remove(&mut self, parent: u64, name: &OsStr) -> Result<(), i32> {
+		let path = self.child(parent, name)?;
+		self.fs.remove(&path).map_err(|e| errno(&path, &e))?;
+		self.inodes.remove(&path);
+		Ok(())
+	}
+}
+
+impl<F: VirtualFs> Filesystem for FuseAdapter<F> {
+	fn lookup(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEntry) {
+		match self.child(parent, name).and_then(|path| self.entry(&path)) {
+			Ok(attr) => reply.entry(&TTL, &attr, 0),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn forget(&mut self, _req: &Request<'_>, ino: u64, nlookup: u64) {
+		self.inodes.forget(ino, nlookup);
+	}
+
+	fn getattr(&mut self, _req: &Request<'_>, ino: u64, _fh: Option<u64>, reply: ReplyAttr) {
+		match self.stat(ino) {
+			Ok(attr) => reply.attr(&TTL, &attr),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	// 只支持改变大小和时间；所有者和权限由挂载决定，修改它们的请求被忽略
+	fn setattr(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_mode: Option<u32>,
+		_uid: Option<u32>,
+		_gid: Option<u32>,
+		size: Option<u64>,
+		atime: Option<TimeOrNow>,
+		mtime: Option<TimeOrNow>,
+		_ctime: Option<SystemTime>,
+		_fh: Option<u64>,
+		_crtime: Option<SystemTime>,
+		_chgtime: Option<SystemTime>,
+		_bkuptime: Option<SystemTime>,
+		_flags: Option<u32>,
+		reply: ReplyAttr,
+	) {
+		match self.set_attributes(ino, size, atime, mtime) {
+			Ok(attr) => reply.attr(&TTL, &attr),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn mkdir(
+		&mut self,
+		_req: &Request<'_>,
+		parent: u64,
+		name: &OsStr,
+		_mode: u32,
+		_umask: u32,
+		reply: ReplyEntry,
+	) {
+		match self.create_entry(parent, name, true) {
+			Ok(attr) => reply.entry(&TTL, &attr, 0),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn unlink(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+		match self.remove(parent, name) {
+			Ok(()) => reply.ok(),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn rmdir(&mut self, _req: &Request<'_>, parent: u64, name: &OsStr, reply: ReplyEmpty) {
+		match self.remove(parent, name) {
+			Ok(()) => reply.ok(),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	// RENAME_EXCHANGE 不支持
+	fn rename(
+		&mut self,
+		_req: &Request<'_>,
+		parent: u64,
+		name: &OsStr,
+		newparent: u64,
+		newname: &OsStr,
+		flags: u32,
+		reply: ReplyEmpty,
+	) {
+		if flags & !RENAME_NOREPLACE != 0 {
+			return reply.error(libc::EINVAL);
+		}
+		let result = self.child(parent, name).and_then(|old_path| {
+			let new_path = self.child(newparent, newname)?;
+			self.fs
+				.rename(&old_path, &new_path, flags & RENAME_NOREPLACE == 0)
+				.map_err(|e| errno(&old_path, &e))?;
+			self.inodes.rename(&old_path, &new_path);
+			Ok(())
+		});
+		match result {
+			Ok(()) => reply.ok(),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn read(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		offset: i64,
+		size: u32,
+		_flags: i32,
+		_lock_owner: Option<u64>,
+		reply: ReplyData,
+	) {
+		let result = self.path(ino).and_then(|path| {
+			self.fs
+				.read(&path, file_offset(offset)?, size as usize)
+				.map_err(|e| errno(&path, &e))
+		});
+		match result {
+			Ok(data) => reply.data(&data),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn write(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		offset: i64,
+		data: &[u8],
+		_write_flags: u32,
+		_flags: i32,
+		_lock_owner: Option<u64>,
+		reply: ReplyWrite,
+	) {
+		let result = self.path(ino).and_then(|path| {
+			self.fs
+				.write(&path, file_offset(offset)?, data)
+				.map_err(|e| errno(&path, &e))
+		});
+		match result {
+			Ok(()) => reply.written(data.len() as u32),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	// 每次从 offset 处继续，目录在两次调用之间的变化可能使条目重复或遗漏
+	fn readdir(
+		&mut self,
+		_req: &Request<'_>,
+		ino: u64,
+		_fh: u64,
+		offset: i64,
+		mut reply: ReplyDirectory,
+	) {
+		let listing = self.path(ino).and_then(|path| match self.fs.list(&path) {
+			Ok(items) => Ok((path, items)),
+			Err(e) => Err(errno(&path, &e)),
+		});
+		let (path, items) = match listing {
+			Ok(listing) => listing,
+			Err(errno) => return reply.error(errno),
+		};
+		let dots = [
+			(ino, FileType::Directory, ".".to_string()),
+			(ino, FileType::Directory, "..".to_string()),
+		];
+		let entries = dots.into_iter().chain(items.into_iter().map(|item| {
+			let inode = self
+				.inodes
+				.find(&child_path(&path, &item.name))
+				.unwrap_or(UNKNOWN_INODE);
+			let kind = if item.is_directory {
+				FileType::Directory
+			} else {
+				FileType::RegularFile
+			};
+			(inode, kind, item.name)
+		}));
+		for (index, (inode, kind, name)) in entries.enumerate().skip(offset as usize) {
+			if reply.add(inode, index as i64 + 1, kind, name) {
+				break;
+			}
+		}
+		reply.ok();
+	}
+
+	fn create(
+		&mut self,
+		_req: &Request<'_>,
+		parent: u64,
+		name: &OsStr,
+		_mode: u32,
+		_umask: u32,
+		_flags: i32,
+		reply: ReplyCreate,
+	) {
+		match self.create_entry(parent, name, false) {
+			Ok(attr) => reply.created(&TTL, &attr, 0, 0, 0),
+			Err(errno) => reply.error(errno),
+		}
+	}
+
+	fn statfs(&mut self, _req: &Request<'_>, _ino: u64, reply: ReplyStatfs) {
+		let space = self.fs.space();
+		let (blocks, available) = (
+			space.total / BLOCK_SIZE as u64,
+			space.available / BLOCK_SIZE as u64,
+		);
+		reply.statfs(
+			blocks,
+			available,
+			available,
+			0,
+			0,
+			BLOCK_SIZE,
+			MAX_NAME_LENGTH as u32,
+			BLOCK_SIZE,
+		);
+	}
+}
+
+// 在 mount_point（已存在的空目录）挂载，直到用 fusermount -u 或 umount 卸载后返回。
+// 只读的存储以只读方式挂载，文件属于挂载它的用户
+pub fn mount(fs: impl VirtualFs + 'static, mount_point: &Path) -> io::Result<()> {
+	let mut options = vec![
+		MountOption::FSName("httpfs".to_string()),
+		MountOption::DefaultPermissions,
+	];
+	if fs.read_only() {
+		options.push(MountOption::RO);
+	}
+	let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+	fuser::mount2(
+		FuseAdapter {
+			fs,
+			inodes: Inodes::new(),
+			uid,
+			gid,
+		},
+		mount_point,
+		&options,
+	)
+}
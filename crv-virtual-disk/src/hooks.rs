@@ -0,0 +1,75 @@
+use std::{fmt, sync::Arc, time::Duration};
+
+use winapi::shared::ntdef::NTSTATUS;
+
+/// 一次 Dokan 回调：回调名称（与指标中的名称相同，如 `create_file`、`write_file`）和涉及的共享内路径。
+#[derive(Debug, Clone, Copy)]
+pub struct Operation<'a> {
+	pub name: &'static str,
+	// 以 / 分隔的共享内路径，根目录为 "."；get_disk_free_space 等不针对条目的回调没有路径
+	pub path: Option<&'a str>,
+	// move_file 的目标路径
+	pub new_path: Option<&'a str>,
+}
+
+/// 包在每个 Dokan 回调外的钩子，用于审计、内容扫描、访问策略或自定义指标，而无需修改 [`HttpFsHandler`](crate::HttpFsHandler)。
+///
+/// 钩子按注册顺序调用 `before`，按相反顺序调用 `after`；一个钩子的 `before` 返回错误时回调不执行，
+/// 之后的钩子也不再调用，已调用过 `before` 的钩子仍会在 `after` 中看到这个错误。
+/// 钩子在 Dokan 的工作线程中同步调用，耗时会计入回调的耗时。
+pub trait OperationHook: Send + Sync {
+	/// 回调执行之前调用；返回错误状态时拒绝该操作，调用者收到这个状态。
+	fn before(&self, _op: &Operation) -> Result<(), NTSTATUS> {
+		Ok(())
+	}
+
+	/// 回调执行之后（或被拒绝之后）调用，带有结果状态和耗时。
+	fn after(&self, _op: &Operation, _result: Result<(), NTSTATUS>, _elapsed: Duration) {}
+}
+
+/// 按顺序调用的一组钩子，可以随 [`Mount`](crate::Mount) 一起复制。
+#[derive(Clone, Default)]
+pub struct Hooks(Vec<Arc<dyn OperationHook>>);
+
+impl Hooks {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn push(&mut self, hook: impl OperationHook + 'static) {
+		self.0.push(Arc::new(hook));
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.0.is_empty()
+	}
+
+	// 依次调用 before，返回第一个拒绝的状态和已调用过的钩子数
+	pub(crate) fn before(&self, op: &Operation) -> (Result<(), NTSTATUS>, usize) {
+		for (index, hook) in self.0.iter().enumerate() {
+			if let Err(status) = hook.before(op) {
+				return (Err(status), index + 1);
+			}
+		}
+		(Ok(()), self.0.len())
+	}
+
+	// 对调用过 before 的前 called 个钩子按相反顺序调用 after
+	pub(crate) fn after(
+		&self,
+		op: &Operation,
+		called: usize,
+		result: Result<(), NTSTATUS>,
+		elapsed: Duration,
+	) {
+		for hook in self.0[..called].iter().rev() {
+			hook.after(op, result, elapsed);
+		}
+	}
+}
+
+impl fmt::Debug for Hooks {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		write!(f, "Hooks({})", self.0.len())
+	}
+}
@@ -0,0 +1,69 @@
+use std::{fmt, str::FromStr, time::Duration};
+
+use reqwest::blocking::ClientBuilder;
+
+// 连接上的 TCP 保活：防火墙和负载均衡会悄悄断开长时间没有数据的连接，
+// HTTP/2 的所有请求共用一个连接，断开的代价很大，按间隔探测保持连接并尽早发现断开
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(30);
+// 每个服务器保留的空闲连接数。HTTP/2 的请求在同一个连接上并发，只需要一个；
+// HTTP/1.1 的请求各占一个连接，保留够 Dokan 各线程同时使用的连接
+const HTTP1_IDLE_CONNECTIONS: usize = 32;
+
+/// 与 httpfs 服务器之间使用的 HTTP 版本。HTTP/2 在一个连接上并发多个请求，列目录时大量的小请求
+/// 不必各占一个连接，也不会因为连接池用尽而排队。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Http2 {
+	/// HTTPS 时通过 ALPN 与服务器协商，服务器支持时使用 HTTP/2，否则使用 HTTP/1.1；HTTP 时使用 HTTP/1.1
+	#[default]
+	Auto,
+	/// 不经协商直接以 HTTP/2 连接（prior knowledge），明文 HTTP 上也使用 HTTP/2（h2c），服务器必须支持
+	PriorKnowledge,
+	/// 只使用 HTTP/1.1
+	Off,
+}
+
+impl FromStr for Http2 {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s.to_ascii_lowercase().as_str() {
+			"auto" => Ok(Http2::Auto),
+			"prior-knowledge" | "always" => Ok(Http2::PriorKnowledge),
+			"off" | "none" => Ok(Http2::Off),
+			_ => Err(format!(
+				"unknown HTTP/2 mode '{}', expected auto, prior-knowledge or off",
+				s
+			)),
+		}
+	}
+}
+
+impl fmt::Display for Http2 {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Http2::Auto => "auto",
+			Http2::PriorKnowledge => "prior-knowledge",
+			Http2::Off => "off",
+		})
+	}
+}
+
+impl Http2 {
+	/// 按模式设置客户端使用的 HTTP 版本和连接池。
+	pub fn configure(self, builder: ClientBuilder) -> ClientBuilder {
+		let builder = match self {
+			Http2::Off => {
+				return builder
+					.http1_only()
+					.pool_max_idle_per_host(HTTP1_IDLE_CONNECTIONS)
+			}
+			Http2::PriorKnowledge => builder.http2_prior_knowledge(),
+			Http2::Auto => builder.pool_max_idle_per_host(HTTP1_IDLE_CONNECTIONS),
+		};
+		// 按实际的往返时间和带宽调整流量控制窗口，大文件的读写不受默认 64 KiB 窗口的限制，
+		// 同一连接上并发的小请求也不会被大传输阻塞
+		builder
+			.http2_adaptive_window(true)
+			.tcp_keepalive(KEEP_ALIVE_INTERVAL)
+	}
+}
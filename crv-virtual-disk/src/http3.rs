@@ -0,0 +1,105 @@
+use std::{
+	sync::Mutex,
+	time::{Duration, Instant},
+};
+
+use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use tracing::{info, warn};
+
+use crate::error::{RemoteError, SendRetrying};
+
+// QUIC 请求失败后改用 TCP 的时间，之后再尝试 HTTP/3
+const FALLBACK_PERIOD: Duration = Duration::from_secs(5 * 60);
+
+/// 实验性的 HTTP/3 传输：请求经 QUIC 发往服务器的 UDP 端口，一个请求丢包不会阻塞同一连接上的其他请求，
+/// 在丢包较多的无线网络和广域网上吞吐更稳定。QUIC 请求没有得到响应时（UDP 被防火墙阻断、服务器未启用 HTTP/3）
+/// 改用原来的 TCP 连接（HTTP/1.1，或 `--http2` 选择的版本），一段时间后再尝试。
+///
+/// 需要以 `http3` 特性构建，reqwest 的 HTTP/3 支持还要求 `RUSTFLAGS="--cfg reqwest_unstable"`。
+pub struct Http3 {
+	client: Client,
+	// 在这个时间之前不使用 QUIC
+	fallback_until: Mutex<Option<Instant>>,
+}
+
+impl Http3 {
+	/// 以 `builder` 的设置（请求头、超时、压缩、重定向）另外建立 QUIC 的客户端，只用于 https:// 服务器。
+	pub fn new(server_url: &str, builder: ClientBuilder) -> Result<Self, String> {
+		if !server_url.to_ascii_lowercase().starts_with("https://") {
+			return Err(format!(
+				"HTTP/3 needs an https:// server, not {}",
+				server_url
+			));
+		}
+		#[cfg(feature = "http3")]
+		{
+			let client = builder
+				.use_rustls_tls()
+				.http3_prior_knowledge()
+				.build()
+				.map_err(|e| e.to_string())?;
+			Ok(Self {
+				client,
+				fallback_until: Mutex::new(None),
+			})
+		}
+		#[cfg(not(feature = "http3"))]
+		{
+			let _ = builder;
+			Err("this build does not support HTTP/3 (build with the http3 feature)".to_string())
+		}
+	}
+
+	// 回退期间不使用 QUIC，期满后清除
+	fn usable(&self) -> bool {
+		let mut fallback_until = self.fallback_until.lock().unwrap();
+		match *fallback_until {
+			Some(until) if Instant::now() < until => false,
+			Some(_) => {
+				info!("retrying HTTP/3");
+				*fallback_until = None;
+				true
+			}
+			None => true,
+		}
+	}
+
+	/// 经 QUIC 发送请求。没有得到响应时在一段时间内改用 TCP，幂等的请求立即经 TCP 重发；
+	/// 其他请求可能已经被服务器处理，返回原来的错误。流式的请求体无法重发，直接经 TCP 发送。
+	pub fn send(&self, request: RequestBuilder) -> Result<Response, RemoteError> {
+		if !self.usable() {
+			return request.send_retrying();
+		}
+		let (client, request) = request.build_split();
+		let request = request?;
+		let Some(mut quic) = request.try_clone() else {
+			return RequestBuilder::from_parts(client, request).send_retrying();
+		};
+		*quic.version_mut() = reqwest::Version::HTTP_3;
+		match RequestBuilder::from_parts(self.client.clone(), quic).send_retrying() {
+			Err(RemoteError::Transport(e)) => {
+				warn!(error = %e, fallback_secs = FALLBACK_PERIOD.as_secs(), "HTTP/3 request failed, falling back to TCP");
+				*self.fallback_until.lock().unwrap() = Some(Instant::now() + FALLBACK_PERIOD);
+				if !request.method().is_idempotent() {
+					return Err(RemoteError::Transport(e));
+				}
+				RequestBuilder::from_parts(client, request).send_retrying()
+			}
+			result => result,
+		}
+	}
+}
+
+/// 代替 [`SendRetrying::send_retrying`]：启用了 HTTP/3 时经 [`Http3::send`] 发送。
+pub trait SendVia {
+	fn send_via(self, http3: &Option<Http3>) -> Result<Response, RemoteError>;
+}
+
+impl SendVia for RequestBuilder {
+	fn send_via(self, http3: &Option<Http3>) -> Result<Response, RemoteError> {
+		match http3 {
+			Some(http3) => http3.send(self),
+			None => self.send_retrying(),
+		}
+	}
+}
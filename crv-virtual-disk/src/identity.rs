@@ -0,0 +1,93 @@
+use std::{cell::RefCell, collections::HashMap, fs, io, path::Path};
+
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+
+/// 携带请求代表的用户的请求头，服务器按共享中为该用户配置的权限处理请求。
+pub const USER_HEADER: &str = "X-Httpfs-User";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct UserMapFile {
+	default: Option<String>,
+	#[serde(default)]
+	users: HashMap<String, String>,
+}
+
+/// 把打开文件的 Windows 用户（SID）映射为服务器上的用户名，多用户的终端服务器上同一个挂载按各自的权限访问共享。
+/// 没有列出的 SID 使用 `default`；没有 `default` 时这些用户的打开被拒绝。
+///
+/// ```toml
+/// default = "guest"
+///
+/// [users]
+/// "S-1-5-21-1004336348-1177238915-682003330-1001" = "alice"
+/// "S-1-5-21-1004336348-1177238915-682003330-1002" = "bob"
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct UserMap {
+	default: Option<String>,
+	// SID 统一为大写
+	users: HashMap<String, String>,
+}
+
+impl UserMap {
+	pub fn new(users: impl IntoIterator<Item = (String, String)>, default: Option<String>) -> Self {
+		Self {
+			default,
+			users: users
+				.into_iter()
+				.map(|(sid, user)| (sid.to_ascii_uppercase(), user))
+				.collect(),
+		}
+	}
+
+	/// 读取 TOML 格式的映射文件。用户名要作为请求头发送，只能包含可见的 ASCII 字符。
+	pub fn load(path: &Path) -> io::Result<Self> {
+		let text = fs::read_to_string(path)?;
+		let invalid = |message: String| {
+			io::Error::new(
+				io::ErrorKind::InvalidData,
+				format!("invalid user map {}: {}", path.display(), message),
+			)
+		};
+		let file: UserMapFile = toml::from_str(&text).map_err(|e| invalid(e.to_string()))?;
+		for user in file.users.values().chain(&file.default) {
+			if user.is_empty() || HeaderValue::from_str(user).is_err() {
+				return Err(invalid(format!("'{}' is not a valid user name", user)));
+			}
+		}
+		Ok(Self::new(file.users, file.default))
+	}
+
+	/// 用户 `sid` 对应的服务器用户名；查不到请求者的用户时只能使用 `default`。
+	pub fn user_for(&self, sid: Option<&str>) -> Option<&str> {
+		sid.and_then(|sid| self.users.get(&sid.to_ascii_uppercase()))
+			.or(self.default.as_ref())
+			.map(String::as_str)
+	}
+}
+
+thread_local! {
+	// 当前线程上的存储请求代表的用户
+	static CURRENT_USER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// 以 `user` 的身份执行 `operation`：期间当前线程发出的 httpfs 请求带有 [`USER_HEADER`]，结束后恢复之前的身份。
+pub fn with_user<T>(user: Option<&str>, operation: impl FnOnce() -> T) -> T {
+	let previous = CURRENT_USER.with(|current| current.replace(user.map(str::to_string)));
+	// 操作 panic 时也要恢复，Dokan 的线程会继续处理其他请求
+	struct Restore(Option<String>);
+	impl Drop for Restore {
+		fn drop(&mut self) {
+			CURRENT_USER.with(|current| *current.borrow_mut() = self.0.take());
+		}
+	}
+	let _restore = Restore(previous);
+	operation()
+}
+
+/// 当前线程代表的用户，交给后台线程的工作用它在提交时恢复身份。
+pub fn current_user() -> Option<String> {
+	CURRENT_USER.with(|current| current.borrow().clone())
+}
@@ -0,0 +1,619 @@
+pub mod cache;
+mod convert;
+pub mod cow;
+mod exfat;
+mod fat;
+mod ntfs;
+mod partition;
+mod qcow2;
+mod vhd;
+mod vhdx;
+
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::Path,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use clap::ValueEnum;
+
+use crate::{
+	backend::{invalid, u16_at, u32_at, StorageBackend},
+	error::RemoteError,
+};
+
+pub use convert::convert;
+pub use partition::{partitions, Partition};
+
+// 差异磁盘的父磁盘、QCOW2 后备文件和快照下层的层数上限，防止互相引用的映像造成死循环
+const MAX_PARENTS: usize = 16;
+
+// 虚拟磁盘：各种映像格式都以按字节偏移读取的块设备提供，分区和文件系统在此之上解析
+pub trait BlockDevice: Send + Sync {
+	// 磁盘的大小（字节）
+	fn size(&self) -> u64;
+
+	// 逻辑扇区的大小，分区表中的地址以此为单位
+	fn sector_size(&self) -> u32 {
+		512
+	}
+
+	// 读满 buf；映像中未分配的区域读出为 0，超出磁盘末尾时返回错误
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+	// 只有以可写方式打开的快照差异文件可以写入，其他映像只读
+	fn writable(&self) -> bool {
+		false
+	}
+
+	fn write_at(&self, _offset: u64, _data: &[u8]) -> io::Result<()> {
+		Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			"the disk is read-only",
+		))
+	}
+
+	// 把已写入的数据保存到磁盘上
+	fn flush(&self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+// GUID 按 Windows 的方式存储：前三段小端
+const fn guid(a: u32, b: u16, c: u16, d: [u8; 8]) -> [u8; 16] {
+	let (a, b, c) = (a.to_le_bytes(), b.to_le_bytes(), c.to_le_bytes());
+	[
+		a[0], a[1], a[2], a[3], b[0], b[1], c[0], c[1], d[0], d[1], d[2], d[3], d[4], d[5], d[6],
+		d[7],
+	]
+}
+
+fn guid_string(guid: &[u8]) -> String {
+	let hex = |bytes: &[u8]| {
+		bytes
+			.iter()
+			.map(|b| format!("{:02X}", b))
+			.collect::<String>()
+	};
+	format!(
+		"{{{:08X}-{:04X}-{:04X}-{}-{}}}",
+		u32_at(guid, 0),
+		u16_at(guid, 4),
+		u16_at(guid, 6),
+		hex(&guid[8..10]),
+		hex(&guid[10..16])
+	)
+}
+
+// 随机生成的 GUID（版本 4）
+fn random_guid() -> [u8; 16] {
+	let mut guid = [0; 16];
+	OsRng.fill_bytes(&mut guid);
+	guid[7] = guid[7] & 0x0F | 0x40;
+	guid[8] = guid[8] & 0x3F | 0x80;
+	guid
+}
+
+fn utf16(data: &[u8]) -> String {
+	String::from_utf16_lossy(
+		&data
+			.chunks_exact(2)
+			.map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+			.collect::<Vec<_>>(),
+	)
+}
+
+// 反射的 CRC-32：GPT 使用 0xEDB88320，VHDX 使用 CRC-32C 的 0x82F63B78
+fn crc32(polynomial: u32, data: &[u8]) -> u32 {
+	let mut crc = !0u32;
+	for &byte in data {
+		crc ^= byte as u32;
+		for _ in 0..8 {
+			crc = if crc & 1 != 0 {
+				(crc >> 1) ^ polynomial
+			} else {
+				crc >> 1
+			};
+		}
+	}
+	!crc
+}
+
+fn check_range(offset: u64, length: usize, size: u64) -> io::Result<()> {
+	match offset.checked_add(length as u64) {
+		Some(end) if end <= size => Ok(()),
+		_ => Err(io::Error::new(
+			io::ErrorKind::UnexpectedEof,
+			format!(
+				"reading {} bytes at {} goes past the end of the disk ({} bytes)",
+				length, offset, size
+			),
+		)),
+	}
+}
+
+// 把 [offset, offset + buf.len()) 按 block_size 对齐分段，依次以段在磁盘中的位置调用 read
+fn for_each_block(
+	offset: u64,
+	buf: &mut [u8],
+	block_size: u64,
+	mut read: impl FnMut(u64, &mut [u8]) -> io::Result<()>,
+) -> io::Result<()> {
+	let mut done = 0;
+	while done < buf.len() {
+		let position = offset + done as u64;
+		let length = (block_size - position % block_size).min((buf.len() - done) as u64) as usize;
+		read(position, &mut buf[done..done + length])?;
+		done += length;
+	}
+	Ok(())
+}
+
+// 按扇区位图分段读取：present 给出扇区是否在本映像中，相邻且状态相同的扇区合并为一次 read
+fn for_each_run(
+	offset: u64,
+	buf: &mut [u8],
+	sector_size: u64,
+	present: impl Fn(u64) -> bool,
+	mut read: impl FnMut(bool, u64, &mut [u8]) -> io::Result<()>,
+) -> io::Result<()> {
+	let end = offset + buf.len() as u64;
+	let mut position = offset;
+	while position < end {
+		let state = present(position / sector_size);
+		let mut stop = ((position / sector_size + 1) * sector_size).min(end);
+		while stop < end && present(stop / sector_size) == state {
+			stop = (stop + sector_size).min(end);
+		}
+		read(
+			state,
+			position,
+			&mut buf[(position - offset) as usize..(stop - offset) as usize],
+		)?;
+		position = stop;
+	}
+	Ok(())
+}
+
+// 映像文件的来源：本地文件，或存储后端（httpfs 服务器、S3、去重存储等）中的文件
+enum Source {
+	Local(Mutex<File>),
+	Backend(Arc<dyn StorageBackend>, String),
+}
+
+// 映像文件本身；没有可识别的格式时按原始映像作为块设备使用。
+// 写入只用于快照的差异文件和合并快照，原始映像作为块设备时总是只读
+pub struct ImageFile {
+	source: Source,
+	// 写入可以使文件变长
+	len: AtomicU64,
+	writable: bool,
+}
+
+fn remote_error(error: RemoteError) -> io::Error {
+	let kind = match error.code() {
+		Some("not_found") => io::ErrorKind::NotFound,
+		_ => io::ErrorKind::Other,
+	};
+	io::Error::new(kind, error.to_string())
+}
+
+impl ImageFile {
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let file = File::open(path)?;
+		let len = file.metadata()?.len();
+		Ok(Self {
+			source: Source::Local(Mutex::new(file)),
+			len: AtomicU64::new(len),
+			writable: false,
+		})
+	}
+
+	fn open_writable(path: &Path) -> io::Result<Self> {
+		let file = OpenOptions::new().read(true).write(true).open(path)?;
+		let len = file.metadata()?.len();
+		Ok(Self {
+			source: Source::Local(Mutex::new(file)),
+			len: AtomicU64::new(len),
+			writable: true,
+		})
+	}
+
+	// path 为共享中以 / 分隔的路径
+	fn open_in(backend: Arc<dyn StorageBackend>, path: &str) -> io::Result<Self> {
+		let info = backend.stat(path).map_err(remote_error)?;
+		if info.is_directory {
+			return Err(io::Error::new(
+				io::ErrorKind::IsADirectory,
+				format!("{} is a directory", path),
+			));
+		}
+		Ok(Self {
+			source: Source::Backend(backend, path.to_string()),
+			len: AtomicU64::new(info.size),
+			writable: false,
+		})
+	}
+
+	// 与本文件位于同一存储中的另一个文件，如差异磁盘的父磁盘
+	fn open_sibling(&self, path: &Path) -> io::Result<Self> {
+		match &self.source {
+			Source::Local(_) => Self::open(path),
+			Source::Backend(backend, _) => {
+				Self::open_in(backend.clone(), &path.to_string_lossy().replace('\\', "/"))
+			}
+		}
+	}
+
+	fn read(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+		let mut data = vec![0; length];
+		self.read_at(offset, &mut data)?;
+		Ok(data)
+	}
+
+	// 写入可以超出文件末尾
+	fn write(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+		match &self.source {
+			Source::Local(file) if self.writable => {
+				let mut file = file.lock().unwrap();
+				file.seek(SeekFrom::Start(offset))?;
+				file.write_all(data)?;
+				self.len
+					.fetch_max(offset + data.len() as u64, Ordering::Relaxed);
+				Ok(())
+			}
+			_ => Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"the image is opened read-only",
+			)),
+		}
+	}
+
+	fn set_len(&self, len: u64) -> io::Result<()> {
+		match &self.source {
+			Source::Local(file) if self.writable => {
+				file.lock().unwrap().set_len(len)?;
+				self.len.store(len, Ordering::Relaxed);
+				Ok(())
+			}
+			_ => Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"the image is opened read-only",
+			)),
+		}
+	}
+
+	fn sync(&self) -> io::Result<()> {
+		match &self.source {
+			Source::Local(file) => file.lock().unwrap().sync_data(),
+			Source::Backend(..) => Ok(()),
+		}
+	}
+}
+
+impl BlockDevice for ImageFile {
+	fn size(&self) -> u64 {
+		self.len.load(Ordering::Relaxed)
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		check_range(offset, buf.len(), self.size())?;
+		match &self.source {
+			Source::Local(file) => {
+				let mut file = file.lock().unwrap();
+				file.seek(SeekFrom::Start(offset))?;
+				file.read_exact(buf)
+			}
+			Source::Backend(backend, path) => {
+				let data = backend
+					.read(path, offset, buf.len())
+					.map_err(remote_error)?;
+				if data.len() != buf.len() {
+					return Err(io::Error::new(
+						io::ErrorKind::UnexpectedEof,
+						format!("{} became shorter while it was being read", path),
+					));
+				}
+				buf.copy_from_slice(&data);
+				Ok(())
+			}
+		}
+	}
+}
+
+// 映像格式，按内容识别：快照差异文件和 VHDX 以各自的标识开头，QCOW2 以 QFI\xfb 开头，
+// VHD 以 conectix 页脚结尾，其他文件按原始映像处理
+enum Format {
+	Delta,
+	Vhdx,
+	Qcow2,
+	Vhd(Vec<u8>),
+	Raw,
+}
+
+fn detect(file: &ImageFile) -> io::Result<Format> {
+	if cow::is_delta(file)? {
+		return Ok(Format::Delta);
+	}
+	if vhdx::is_vhdx(file)? {
+		return Ok(Format::Vhdx);
+	}
+	if file.size() >= 4 && file.read(0, 4)? == qcow2::MAGIC {
+		return Ok(Format::Qcow2);
+	}
+	Ok(match vhd::footer(file)? {
+		Some(footer) => Format::Vhd(footer),
+		None => Format::Raw,
+	})
+}
+
+// 可以新建的映像格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum NewFormat {
+	// 动态 VHDX，块在写入时才分配
+	#[default]
+	Vhdx,
+	// 动态 VHD
+	Vhd,
+	// QCOW2 版本 2，簇在写入时才分配
+	Qcow2,
+	// 原始映像，以稀疏文件保存
+	Raw,
+}
+
+impl NewFormat {
+	// 按文件扩展名判断格式：.vhdx、.vhd、.qcow2，以及原始映像常用的 .img、.raw 和 .bin
+	pub fn from_path(path: &Path) -> Option<Self> {
+		match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+			"vhdx" => Some(Self::Vhdx),
+			"vhd" => Some(Self::Vhd),
+			"qcow2" => Some(Self::Qcow2),
+			"img" | "raw" | "bin" => Some(Self::Raw),
+			_ => None,
+		}
+	}
+}
+
+// 新建大小为 size 的空映像；block_size 为 VHDX 和 VHD 的块大小或 QCOW2 的簇大小，原始映像不使用
+pub fn create(
+	path: &Path,
+	format: NewFormat,
+	size: u64,
+	block_size: Option<u64>,
+) -> io::Result<()> {
+	convert::writer(path, format, size, block_size)?.finish()
+}
+
+// 改变动态 VHDX 或原始映像的大小，返回原来的大小。缩小时新末尾之后的数据丢失，
+// 应先在虚拟机中缩小其中的分区和文件系统；其他格式和快照差异文件不能改变大小
+pub fn resize(path: &Path, size: u64) -> io::Result<u64> {
+	match detect(&ImageFile::open(path)?)? {
+		Format::Vhdx => vhdx::resize(path, size),
+		Format::Raw => {
+			let file = OpenOptions::new().write(true).open(path)?;
+			let old_size = file.metadata()?.len();
+			file.set_len(size)?;
+			file.sync_all()?;
+			Ok(old_size)
+		}
+		_ => Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			format!(
+				"{} is not a VHDX or raw image and cannot be resized",
+				path.display()
+			),
+		)),
+	}
+}
+
+pub fn open(path: &Path) -> io::Result<Arc<dyn BlockDevice>> {
+	open_layer(ImageFile::open(path)?, path, 0)
+}
+
+// 打开快照差异文件并允许写入，写入的数据只进入这个文件；其下各层仍然只读
+pub fn open_writable(path: &Path) -> io::Result<Arc<dyn BlockDevice>> {
+	if !cow::is_delta(&ImageFile::open(path)?)? {
+		return Err(io::Error::new(
+			io::ErrorKind::PermissionDenied,
+			format!("{} is not a snapshot delta; only deltas created with `httpfs disk-snapshot create` can be written", path.display()),
+		));
+	}
+	open_layer(ImageFile::open_writable(path)?, path, 0)
+}
+
+// 打开存储后端中的映像，差异磁盘的父磁盘和后备文件也在同一存储中查找
+pub fn open_in(backend: Arc<dyn StorageBackend>, path: &str) -> io::Result<Arc<dyn BlockDevice>> {
+	open_layer(ImageFile::open_in(backend, path)?, Path::new(path), 0)
+}
+
+// depth 为映像在差异磁盘、后备文件或快照链中的层数
+fn open_layer(file: ImageFile, path: &Path, depth: usize) -> io::Result<Arc<dyn BlockDevice>> {
+	if depth > MAX_PARENTS {
+		return Err(invalid(format!(
+			"{} is more than {} parents deep",
+			path.display(),
+			MAX_PARENTS
+		)));
+	}
+	Ok(match detect(&file)? {
+		Format::Delta => Arc::new(cow::Delta::open(file, path, depth)?),
+		Format::Vhdx => Arc::new(vhdx::Vhdx::open(file, path, depth)?),
+		Format::Qcow2 => Arc::new(qcow2::Qcow2::open(file, path, depth)?),
+		Format::Vhd(footer) => Arc::new(vhd::Vhd::open(file, footer, path, depth)?),
+		Format::Raw => Arc::new(file),
+	})
+}
+
+// 磁盘中的一段，如一个分区
+pub struct Slice {
+	device: Arc<dyn BlockDevice>,
+	offset: u64,
+	size: u64,
+}
+
+impl Slice {
+	pub fn new(device: Arc<dyn BlockDevice>, offset: u64, size: u64) -> Self {
+		Self {
+			device,
+			offset,
+			size,
+		}
+	}
+}
+
+impl BlockDevice for Slice {
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn sector_size(&self) -> u32 {
+		self.device.sector_size()
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		check_range(offset, buf.len(), self.size)?;
+		self.device.read_at(self.offset + offset, buf)
+	}
+
+	fn writable(&self) -> bool {
+		self.device.writable()
+	}
+
+	fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+		check_range(offset, data.len(), self.size)?;
+		self.device.write_at(self.offset + offset, data)
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		self.device.flush()
+	}
+}
+
+// 文件系统中的一个条目；location 由各文件系统解释，如 FAT 的起始簇号
+#[derive(Clone, Debug)]
+pub struct Entry {
+	pub name: String,
+	pub is_directory: bool,
+	pub size: u64,
+	// 写入过的长度，之后到 size 的部分读出为 0（exFAT 的有效数据长度、NTFS 的初始化长度）
+	pub valid_size: u64,
+	pub created: u64,
+	pub modified: u64,
+	pub accessed: u64,
+	pub location: u64,
+}
+
+// 只读的文件系统，目录在列出时才读取
+pub trait FileSystem: Send + Sync {
+	fn root(&self) -> Entry;
+
+	fn list(&self, directory: &Entry) -> io::Result<Vec<Entry>>;
+
+	// 读取 [offset, offset + length) 中文件范围内的部分
+	fn read(&self, file: &Entry, offset: u64, length: usize) -> io::Result<Vec<u8>>;
+}
+
+// 按引导扇区识别文件系统
+fn boot_sector_file_system(sector: &[u8]) -> Option<&'static str> {
+	match &sector[3..11] {
+		b"NTFS    " => Some("NTFS"),
+		b"EXFAT   " => Some("exFAT"),
+		_ if fat::is_boot_sector(sector) => Some("FAT"),
+		_ => None,
+	}
+}
+
+// 卷中的文件系统，不能识别时为 None
+pub fn file_system(volume: &dyn BlockDevice) -> io::Result<Option<&'static str>> {
+	if volume.size() < 512 {
+		return Ok(None);
+	}
+	let mut sector = [0; 512];
+	volume.read_at(0, &mut sector)?;
+	Ok(boot_sector_file_system(&sector))
+}
+
+// 挂载卷中的文件系统
+pub fn mount(volume: Arc<dyn BlockDevice>) -> io::Result<Box<dyn FileSystem>> {
+	match file_system(volume.as_ref())? {
+		Some("FAT") => Ok(Box::new(fat::Fat::open(volume)?)),
+		Some("exFAT") => Ok(Box::new(exfat::Exfat::open(volume)?)),
+		Some("NTFS") => Ok(Box::new(ntfs::Ntfs::open(volume)?)),
+		Some(name) => Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			format!("{} volumes are not supported", name),
+		)),
+		None => Err(invalid("no supported file system found")),
+	}
+}
+
+// 分区所在的一段磁盘
+fn slice(device: &Arc<dyn BlockDevice>, partition: &Partition) -> io::Result<Arc<dyn BlockDevice>> {
+	if partition
+		.offset
+		.checked_add(partition.size)
+		.is_none_or(|end| end > device.size())
+	{
+		return Err(invalid(format!(
+			"partition {} lies outside the disk",
+			partition.number
+		)));
+	}
+	Ok(Arc::new(Slice::new(
+		device.clone(),
+		partition.offset,
+		partition.size,
+	)))
+}
+
+// 序号为 number 的分区
+pub fn open_partition(
+	device: Arc<dyn BlockDevice>,
+	number: usize,
+) -> io::Result<Arc<dyn BlockDevice>> {
+	let partitions = partitions(device.as_ref())?;
+	let partition = partitions
+		.iter()
+		.find(|partition| partition.number == number)
+		.ok_or_else(|| invalid(format!("partition {} does not exist", number)))?;
+	slice(&device, partition)
+}
+
+// 打开映像中的卷：number 为分区序号，未给出时使用第一个能识别文件系统的分区；
+// 没有分区表的磁盘整个作为一个卷
+pub fn open_volume(
+	device: Arc<dyn BlockDevice>,
+	number: Option<usize>,
+) -> io::Result<Box<dyn FileSystem>> {
+	let partitions = partitions(device.as_ref())?;
+	if partitions.is_empty() {
+		return match number {
+			None | Some(1) => mount(device),
+			Some(number) => Err(invalid(format!(
+				"partition {} does not exist: the disk has no partition table",
+				number
+			))),
+		};
+	}
+	if let Some(number) = number {
+		return mount(open_partition(device, number)?);
+	}
+	let mut first_error = None;
+	for partition in &partitions {
+		match slice(&device, partition).and_then(mount) {
+			Ok(fs) => return Ok(fs),
+			Err(e) => {
+				first_error.get_or_insert(io::Error::new(
+					e.kind(),
+					format!("partition {}: {}", partition.number, e),
+				));
+			}
+		}
+	}
+	Err(first_error.unwrap())
+}
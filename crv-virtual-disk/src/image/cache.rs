@@ -0,0 +1,245 @@
+use std::{
+	collections::{BTreeMap, HashMap},
+	io,
+	sync::{Arc, Mutex},
+	thread,
+	time::Duration,
+};
+
+use clap::ValueEnum;
+use serde::Deserialize;
+use tracing::warn;
+
+use super::{check_range, for_each_block, BlockDevice};
+
+// 缓存按 64 KiB 的块读写映像
+const CACHE_BLOCK: u64 = 64 * 1024;
+
+// 写入的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CacheMode {
+	// 写入立即写到映像，缓存中已有的块同时更新
+	#[default]
+	WriteThrough,
+	// 写入只修改缓存中的块并标记为脏，刷新、定期刷新或块被换出时才写到映像
+	WriteBack,
+}
+
+#[derive(Debug, Clone)]
+pub struct CacheSettings {
+	// 缓存的大小（字节），至少缓存一块
+	pub size: u64,
+	pub mode: CacheMode,
+	// 写回模式下定期写出脏块的间隔
+	pub flush_interval: Duration,
+}
+
+struct Block {
+	data: Vec<u8>,
+	dirty: bool,
+	used: u64,
+}
+
+struct Blocks {
+	blocks: HashMap<u64, Block>,
+	// 缓存中的块序号，键为最近一次使用的序号
+	recent: BTreeMap<u64, u64>,
+	clock: u64,
+}
+
+impl Blocks {
+	fn touch(&mut self, index: u64) -> Option<&mut Block> {
+		self.clock += 1;
+		let block = self.blocks.get_mut(&index)?;
+		self.recent.remove(&block.used);
+		block.used = self.clock;
+		self.recent.insert(self.clock, index);
+		Some(block)
+	}
+}
+
+// 映像之上的块缓存：最近使用的块保存在内存中，缓存满时换出最久未使用的块。
+// 所有读写在一个锁内进行，缓存不会与映像不一致
+pub struct BlockCache {
+	inner: Arc<dyn BlockDevice>,
+	mode: CacheMode,
+	capacity: usize,
+	blocks: Mutex<Blocks>,
+}
+
+// 在映像之上加块缓存；写回模式下后台线程定期写出脏块，缓存释放后线程随之结束
+pub fn wrap(inner: Arc<dyn BlockDevice>, settings: &CacheSettings) -> Arc<dyn BlockDevice> {
+	let cache = Arc::new(BlockCache {
+		inner,
+		mode: settings.mode,
+		capacity: (settings.size / CACHE_BLOCK).max(1) as usize,
+		blocks: Mutex::new(Blocks {
+			blocks: HashMap::new(),
+			recent: BTreeMap::new(),
+			clock: 0,
+		}),
+	});
+	if settings.mode == CacheMode::WriteBack && cache.inner.writable() {
+		let cache = Arc::downgrade(&cache);
+		let interval = settings.flush_interval.max(Duration::from_secs(1));
+		thread::spawn(move || loop {
+			thread::sleep(interval);
+			let Some(cache) = cache.upgrade() else {
+				return;
+			};
+			if let Err(e) = cache.flush() {
+				warn!(error = %e, "block cache: periodic flush failed");
+			}
+		});
+	}
+	cache
+}
+
+impl BlockCache {
+	// 最后一块可能不足 CACHE_BLOCK
+	fn block_length(&self, index: u64) -> usize {
+		CACHE_BLOCK.min(self.inner.size() - index * CACHE_BLOCK) as usize
+	}
+
+	// 缓存中的块，没有时从映像读取
+	fn load<'a>(&self, blocks: &'a mut Blocks, index: u64) -> io::Result<&'a mut Block> {
+		if !blocks.blocks.contains_key(&index) {
+			let mut data = vec![0; self.block_length(index)];
+			self.inner.read_at(index * CACHE_BLOCK, &mut data)?;
+			self.insert(blocks, index, data, false)?;
+		}
+		Ok(blocks.touch(index).unwrap())
+	}
+
+	fn insert(
+		&self,
+		blocks: &mut Blocks,
+		index: u64,
+		data: Vec<u8>,
+		dirty: bool,
+	) -> io::Result<()> {
+		while blocks.blocks.len() >= self.capacity {
+			self.evict(blocks)?;
+		}
+		blocks.clock += 1;
+		blocks.recent.insert(blocks.clock, index);
+		blocks.blocks.insert(
+			index,
+			Block {
+				data,
+				dirty,
+				used: blocks.clock,
+			},
+		);
+		Ok(())
+	}
+
+	// 换出最久未使用的块，脏块先写到映像；写入失败时块留在缓存中
+	fn evict(&self, blocks: &mut Blocks) -> io::Result<()> {
+		let (&used, &index) = blocks.recent.first_key_value().unwrap();
+		let block = &blocks.blocks[&index];
+		if block.dirty {
+			self.inner.write_at(index * CACHE_BLOCK, &block.data)?;
+		}
+		blocks.recent.remove(&used);
+		blocks.blocks.remove(&index);
+		Ok(())
+	}
+
+	// 按在映像中的顺序写出所有脏块
+	fn write_dirty(&self, blocks: &mut Blocks) -> io::Result<()> {
+		let mut dirty: Vec<u64> = blocks
+			.blocks
+			.iter()
+			.filter(|(_, block)| block.dirty)
+			.map(|(&index, _)| index)
+			.collect();
+		dirty.sort_unstable();
+		for index in dirty {
+			let block = blocks.blocks.get_mut(&index).unwrap();
+			self.inner.write_at(index * CACHE_BLOCK, &block.data)?;
+			block.dirty = false;
+		}
+		Ok(())
+	}
+}
+
+impl BlockDevice for BlockCache {
+	fn size(&self) -> u64 {
+		self.inner.size()
+	}
+
+	fn sector_size(&self) -> u32 {
+		self.inner.sector_size()
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		check_range(offset, buf.len(), self.size())?;
+		let mut blocks = self.blocks.lock().unwrap();
+		for_each_block(offset, buf, CACHE_BLOCK, |position, part| {
+			let within = (position % CACHE_BLOCK) as usize;
+			let block = self.load(&mut blocks, position / CACHE_BLOCK)?;
+			part.copy_from_slice(&block.data[within..within + part.len()]);
+			Ok(())
+		})
+	}
+
+	fn writable(&self) -> bool {
+		self.inner.writable()
+	}
+
+	// 写回模式下整块覆盖的块不必先从映像读出
+	fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+		if !self.inner.writable() {
+			return self.inner.write_at(offset, data);
+		}
+		check_range(offset, data.len(), self.size())?;
+		let mut blocks = self.blocks.lock().unwrap();
+		if self.mode == CacheMode::WriteThrough {
+			self.inner.write_at(offset, data)?;
+		}
+		let mut done = 0;
+		while done < data.len() {
+			let position = offset + done as u64;
+			let (index, within) = (position / CACHE_BLOCK, (position % CACHE_BLOCK) as usize);
+			let length = (CACHE_BLOCK as usize - within).min(data.len() - done);
+			let part = &data[done..done + length];
+			match self.mode {
+				CacheMode::WriteThrough => {
+					if let Some(block) = blocks.touch(index) {
+						block.data[within..within + length].copy_from_slice(part);
+					}
+				}
+				CacheMode::WriteBack
+					if within == 0
+						&& length == self.block_length(index)
+						&& !blocks.blocks.contains_key(&index) =>
+				{
+					self.insert(&mut blocks, index, part.to_vec(), true)?;
+				}
+				CacheMode::WriteBack => {
+					let block = self.load(&mut blocks, index)?;
+					block.data[within..within + length].copy_from_slice(part);
+					block.dirty = true;
+				}
+			}
+			done += length;
+		}
+		Ok(())
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		self.write_dirty(&mut self.blocks.lock().unwrap())?;
+		self.inner.flush()
+	}
+}
+
+// 释放时写出剩余的脏块
+impl Drop for BlockCache {
+	fn drop(&mut self) {
+		if let Err(e) = self.flush() {
+			warn!(error = %e, "block cache: writing dirty blocks failed");
+		}
+	}
+}
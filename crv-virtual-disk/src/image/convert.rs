@@ -0,0 +1,120 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Seek, SeekFrom, Write},
+	path::Path,
+};
+
+use super::{qcow2, vhd, vhdx, BlockDevice, NewFormat};
+
+// 原始映像按 1 MiB 写入
+const RAW_CHUNK: u64 = 1024 * 1024;
+
+// 按顺序写入的新映像：依次给出含有非零数据的块，全为 0 的块不给出，在新映像中保持未分配
+pub(super) trait ImageWriter {
+	// 分配单位：VHDX 和 VHD 的块、QCOW2 的簇
+	fn chunk_size(&self) -> u64;
+
+	// offset 按 chunk_size 对齐且递增；磁盘末尾的一块可能不足 chunk_size
+	fn write_chunk(&mut self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+	// 写出分配表等元数据并保存到磁盘上
+	fn finish(self: Box<Self>) -> io::Result<()>;
+}
+
+// 新建映像文件；文件已存在时失败
+pub(super) fn writer(
+	path: &Path,
+	format: NewFormat,
+	size: u64,
+	block_size: Option<u64>,
+) -> io::Result<Box<dyn ImageWriter>> {
+	Ok(match format {
+		NewFormat::Vhdx => Box::new(vhdx::Writer::create(
+			path,
+			size,
+			block_size.unwrap_or(vhdx::DEFAULT_BLOCK_SIZE),
+		)?),
+		NewFormat::Vhd => Box::new(vhd::Writer::create(
+			path,
+			size,
+			block_size.unwrap_or(vhd::DEFAULT_BLOCK_SIZE),
+		)?),
+		NewFormat::Qcow2 => Box::new(qcow2::Writer::create(
+			path,
+			size,
+			block_size.unwrap_or(qcow2::DEFAULT_CLUSTER_SIZE),
+		)?),
+		NewFormat::Raw => Box::new(RawWriter::create(path, size)?),
+	})
+}
+
+// 原始映像先设为完整大小，没有写入的部分是稀疏文件中的空洞
+struct RawWriter {
+	file: File,
+}
+
+impl RawWriter {
+	fn create(path: &Path, size: u64) -> io::Result<Self> {
+		let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+		file.set_len(size)?;
+		Ok(Self { file })
+	}
+}
+
+impl ImageWriter for RawWriter {
+	fn chunk_size(&self) -> u64 {
+		RAW_CHUNK
+	}
+
+	fn write_chunk(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+		self.file.seek(SeekFrom::Start(offset))?;
+		self.file.write_all(data)
+	}
+
+	fn finish(self: Box<Self>) -> io::Result<()> {
+		self.file.sync_all()
+	}
+}
+
+// 把 source 转换为 format 格式的新映像 target。源映像可以是任何能打开的格式，差异磁盘、后备文件和快照差异文件
+// 与其下各层合并为一个映像。按新映像的分配单位顺序读写，内存中只有一块数据和新映像的分配表；全为 0 的块不写入，
+// 源映像中未分配的部分读出为 0 时不读取文件。每处理一块以已处理和总共的字节数调用 progress。
+// 转换失败时删除不完整的新映像
+pub fn convert(
+	source: &Path,
+	target: &Path,
+	format: NewFormat,
+	block_size: Option<u64>,
+	mut progress: impl FnMut(u64, u64),
+) -> io::Result<()> {
+	let device = super::open(source)?;
+	let mut writer = writer(target, format, device.size(), block_size)?;
+	let result = match copy(device.as_ref(), writer.as_mut(), &mut progress) {
+		Ok(()) => writer.finish(),
+		Err(e) => Err(e),
+	};
+	if result.is_err() {
+		let _ = fs::remove_file(target);
+	}
+	result
+}
+
+fn copy(
+	device: &dyn BlockDevice,
+	writer: &mut dyn ImageWriter,
+	progress: &mut impl FnMut(u64, u64),
+) -> io::Result<()> {
+	let (size, chunk) = (device.size(), writer.chunk_size());
+	let mut buffer = vec![0; chunk as usize];
+	let mut offset = 0;
+	while offset < size {
+		let data = &mut buffer[..chunk.min(size - offset) as usize];
+		device.read_at(offset, data)?;
+		if data.iter().any(|&byte| byte != 0) {
+			writer.write_chunk(offset, data)?;
+		}
+		offset += data.len() as u64;
+		progress(offset, size);
+	}
+	Ok(())
+}
@@ -0,0 +1,338 @@
+use std::{
+	fs::{self, OpenOptions},
+	io::{self, Write},
+	path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+	sync::{Arc, Mutex},
+};
+
+use super::{check_range, detect, for_each_block, BlockDevice, Format, ImageFile};
+use crate::backend::{invalid, u32_at, u64_at};
+
+// 快照差异文件：4 KiB 的头（标识、版本、块大小、磁盘大小、块数、下层映像的路径），
+// 其后是每块一项的块表（块在文件中的位置，0 为没有写入过），再后是按块对齐的数据。字段都是小端
+pub const MAGIC: &[u8] = b"HFSDELTA";
+const VERSION: u32 = 1;
+const HEADER_SIZE: u64 = 4096;
+const BLOCK_BITS: u32 = 16;
+const MAX_LOWER_NAME: usize = 1024;
+
+struct Header {
+	block_size: u64,
+	size: u64,
+	entries: u64,
+	lower: PathBuf,
+}
+
+impl Header {
+	// 数据区的开头：块表之后按块对齐
+	fn data_start(&self) -> u64 {
+		(HEADER_SIZE + self.entries * 8).next_multiple_of(self.block_size)
+	}
+}
+
+pub fn is_delta(file: &ImageFile) -> io::Result<bool> {
+	Ok(file.size() >= HEADER_SIZE && file.read(0, MAGIC.len())? == MAGIC)
+}
+
+// 下层映像的路径相对于差异文件所在的目录
+fn read_header(file: &ImageFile, path: &Path) -> io::Result<Header> {
+	let header = file.read(0, HEADER_SIZE as usize)?;
+	if &header[..8] != MAGIC || u32_at(&header, 8) != VERSION {
+		return Err(invalid(format!(
+			"{} is not a snapshot delta of a supported version",
+			path.display()
+		)));
+	}
+	let (block_bits, size, entries, name_length) = (
+		u32_at(&header, 12),
+		u64_at(&header, 16),
+		u64_at(&header, 24),
+		u32_at(&header, 32) as usize,
+	);
+	if !(12..=24).contains(&block_bits)
+		|| entries != size.div_ceil(1 << block_bits)
+		|| name_length == 0
+		|| name_length > MAX_LOWER_NAME
+	{
+		return Err(invalid(format!(
+			"the header of {} is damaged",
+			path.display()
+		)));
+	}
+	let name = String::from_utf8_lossy(&header[64..64 + name_length])
+		.replace(['/', '\\'], MAIN_SEPARATOR_STR);
+	let header = Header {
+		block_size: 1 << block_bits,
+		size,
+		entries,
+		lower: path.parent().unwrap_or(Path::new("")).join(name),
+	};
+	if file.size() < header.data_start() {
+		return Err(invalid(format!(
+			"the block table of {} is truncated",
+			path.display()
+		)));
+	}
+	Ok(header)
+}
+
+fn read_table(file: &ImageFile, header: &Header) -> io::Result<Vec<u64>> {
+	Ok(file
+		.read(HEADER_SIZE, (header.entries * 8) as usize)?
+		.chunks_exact(8)
+		.map(|entry| u64_at(entry, 0))
+		.collect())
+}
+
+// 在 lower 之上创建空的差异文件 path，lower 从此不应再被修改；
+// lower 与 path 在同一目录时记录文件名，否则记录绝对路径
+pub fn create(path: &Path, lower: &Path) -> io::Result<()> {
+	let size = super::open(lower)?.size();
+	let name = match lower.parent() == path.parent() {
+		true => PathBuf::from(
+			lower
+				.file_name()
+				.ok_or_else(|| invalid(format!("{} is not a file", lower.display())))?,
+		),
+		false => fs::canonicalize(lower)?,
+	};
+	let name = name
+		.to_str()
+		.ok_or_else(|| invalid(format!("{} is not valid Unicode", name.display())))?;
+	if name.len() > MAX_LOWER_NAME {
+		return Err(invalid(format!(
+			"the path of {} is too long",
+			lower.display()
+		)));
+	}
+	let header = Header {
+		block_size: 1 << BLOCK_BITS,
+		size,
+		entries: size.div_ceil(1 << BLOCK_BITS),
+		lower: PathBuf::from(name),
+	};
+	let mut data = vec![0; HEADER_SIZE as usize];
+	data[..8].copy_from_slice(MAGIC);
+	data[8..12].copy_from_slice(&VERSION.to_le_bytes());
+	data[12..16].copy_from_slice(&BLOCK_BITS.to_le_bytes());
+	data[16..24].copy_from_slice(&size.to_le_bytes());
+	data[24..32].copy_from_slice(&header.entries.to_le_bytes());
+	data[32..36].copy_from_slice(&(name.len() as u32).to_le_bytes());
+	data[64..64 + name.len()].copy_from_slice(name.as_bytes());
+	let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+	file.write_all(&data)?;
+	// 块表全为 0，由文件系统以稀疏方式保存
+	file.set_len(header.data_start())?;
+	file.sync_all()
+}
+
+// 快照链中的一个差异文件，changed 为其中保存的数据量
+pub struct Layer {
+	pub path: PathBuf,
+	pub changed: u64,
+}
+
+// 从 path 向下列出差异文件，直到第一个不是差异文件的映像；返回差异文件和最下层的映像
+pub fn chain(path: &Path) -> io::Result<(Vec<Layer>, PathBuf)> {
+	let mut layers = Vec::new();
+	let mut path = path.to_path_buf();
+	loop {
+		let file = ImageFile::open(&path)?;
+		if !is_delta(&file)? {
+			return Ok((layers, path));
+		}
+		if layers.len() > super::MAX_PARENTS {
+			return Err(invalid(format!(
+				"{} is more than {} snapshots deep",
+				path.display(),
+				super::MAX_PARENTS
+			)));
+		}
+		let header = read_header(&file, &path)?;
+		let written = read_table(&file, &header)?
+			.iter()
+			.filter(|&&host| host != 0)
+			.count() as u64;
+		layers.push(Layer {
+			path,
+			changed: written * header.block_size,
+		});
+		path = header.lower;
+	}
+}
+
+// 把差异文件中写入过的块写回它的下层（另一个差异文件或原始映像），然后删除差异文件；返回下层的路径。
+// 其他以该差异文件为下层的快照随之失效
+pub fn merge(path: &Path) -> io::Result<PathBuf> {
+	let file = ImageFile::open(path)?;
+	if !is_delta(&file)? {
+		return Err(invalid(format!(
+			"{} is not a snapshot delta",
+			path.display()
+		)));
+	}
+	let header = read_header(&file, path)?;
+	let table = read_table(&file, &header)?;
+	let copy = |write: &dyn Fn(u64, &[u8]) -> io::Result<()>| -> io::Result<()> {
+		for (index, &host) in table.iter().enumerate().filter(|(_, &host)| host != 0) {
+			let offset = index as u64 * header.block_size;
+			write(
+				offset,
+				&file.read(host, header.block_size.min(header.size - offset) as usize)?,
+			)?;
+		}
+		Ok(())
+	};
+	let lower = ImageFile::open_writable(&header.lower)?;
+	match detect(&lower)? {
+		Format::Delta => {
+			let lower = Delta::open(lower, &header.lower, 0)?;
+			copy(&|offset, data| lower.write_at(offset, data))?;
+			lower.flush()?;
+		}
+		Format::Raw if lower.size() == header.size => {
+			copy(&|offset, data| lower.write(offset, data))?;
+			lower.sync()?;
+		}
+		Format::Raw => {
+			return Err(invalid(format!(
+				"{} has changed size since the snapshot was created",
+				header.lower.display()
+			)))
+		}
+		_ => {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				format!(
+					"{} is not a raw image or a snapshot delta and cannot be written",
+					header.lower.display()
+				),
+			))
+		}
+	}
+	drop(file);
+	fs::remove_file(path)?;
+	Ok(header.lower)
+}
+
+// 丢弃差异文件中的修改，回到它的下层
+pub fn discard(path: &Path) -> io::Result<()> {
+	if !is_delta(&ImageFile::open(path)?)? {
+		return Err(invalid(format!(
+			"{} is not a snapshot delta",
+			path.display()
+		)));
+	}
+	fs::remove_file(path)
+}
+
+// 块表和文件当前的末尾，新写入的块追加到末尾
+struct Table {
+	blocks: Vec<u64>,
+	end: u64,
+}
+
+// 下层映像之上的写时复制层：写入的块保存在差异文件中，其他块从下层读取。
+// 块第一次写入时先从下层复制整块，数据写入后才更新块表，中断的写入不会使块表指向未写完的数据
+pub struct Delta {
+	file: ImageFile,
+	size: u64,
+	block_size: u64,
+	lower: Arc<dyn BlockDevice>,
+	table: Mutex<Table>,
+}
+
+impl Delta {
+	pub fn open(file: ImageFile, path: &Path, depth: usize) -> io::Result<Self> {
+		let header = read_header(&file, path)?;
+		let lower = file
+			.open_sibling(&header.lower)
+			.and_then(|lower| super::open_layer(lower, &header.lower, depth + 1))
+			.map_err(|e| {
+				io::Error::new(
+					e.kind(),
+					format!("the image below {}: {}", path.display(), e),
+				)
+			})?;
+		if lower.size() != header.size {
+			return Err(invalid(format!(
+				"{} has changed size since the snapshot was created",
+				header.lower.display()
+			)));
+		}
+		let blocks = read_table(&file, &header)?;
+		let end = file
+			.size()
+			.max(header.data_start())
+			.next_multiple_of(header.block_size);
+		Ok(Self {
+			file,
+			size: header.size,
+			block_size: header.block_size,
+			lower,
+			table: Mutex::new(Table { blocks, end }),
+		})
+	}
+}
+
+impl BlockDevice for Delta {
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn sector_size(&self) -> u32 {
+		self.lower.sector_size()
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		check_range(offset, buf.len(), self.size)?;
+		for_each_block(offset, buf, self.block_size, |position, part| {
+			match self.table.lock().unwrap().blocks[(position / self.block_size) as usize] {
+				0 => self.lower.read_at(position, part),
+				host => self.file.read_at(host + position % self.block_size, part),
+			}
+		})
+	}
+
+	fn writable(&self) -> bool {
+		self.file.writable
+	}
+
+	fn write_at(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+		check_range(offset, data.len(), self.size)?;
+		let mut table = self.table.lock().unwrap();
+		let mut done = 0;
+		while done < data.len() {
+			let position = offset + done as u64;
+			let (block, within) = (
+				position / self.block_size,
+				(position % self.block_size) as usize,
+			);
+			let length = (self.block_size as usize - within).min(data.len() - done);
+			let part = &data[done..done + length];
+			match table.blocks[block as usize] {
+				0 => {
+					let start = block * self.block_size;
+					let mut buffer = vec![0; self.block_size.min(self.size - start) as usize];
+					if length < buffer.len() {
+						self.lower.read_at(start, &mut buffer)?;
+					}
+					buffer[within..within + length].copy_from_slice(part);
+					let host = table.end;
+					self.file.write(host, &buffer)?;
+					self.file
+						.write(HEADER_SIZE + block * 8, &host.to_le_bytes())?;
+					table.blocks[block as usize] = host;
+					table.end += self.block_size;
+				}
+				host => self.file.write(host + within as u64, part)?,
+			}
+			done += length;
+		}
+		Ok(())
+	}
+
+	fn flush(&self) -> io::Result<()> {
+		self.file.sync()
+	}
+}
@@ -0,0 +1,262 @@
+use std::{io, sync::Arc};
+
+use super::{fat, BlockDevice, Entry, FileSystem};
+use crate::backend::{invalid, u16_at, u32_at, u64_at};
+
+const DIRECTORY_ENTRY: usize = 32;
+// 目录项的类型；最高位为 0 的项已删除
+const ENTRY_FILE: u8 = 0x85;
+const ENTRY_STREAM: u8 = 0xC0;
+const ENTRY_NAME: u8 = 0xC1;
+const ATTR_DIRECTORY: u16 = 0x10;
+// 流扩展项的标志：数据占用连续的簇，不经过 FAT
+const NO_FAT_CHAIN: u8 = 0x02;
+// Entry::location 中表示连续簇的位
+const CONTIGUOUS: u64 = 1 << 32;
+// 文件名项中的 15 个 UTF-16 字符
+const NAME_CHARS: usize = 15;
+
+// 目录项集合的校验和，跳过文件项中存放校验和的两个字节
+fn set_checksum(set: &[u8]) -> u16 {
+	set.iter()
+		.enumerate()
+		.filter(|&(index, _)| index != 2 && index != 3)
+		.fold(0u16, |sum, (_, &b)| {
+			sum.rotate_right(1).wrapping_add(b as u16)
+		})
+}
+
+// 时间戳的高 16 位是日期、低 16 位是时间，与 FAT 相同；另有以 10 毫秒为单位的部分，
+// 以及以 15 分钟为单位的 7 位有符号 UTC 偏移（最高位表示有效），没有偏移时按 UTC 处理
+fn timestamp(value: u32, increment: u8, offset: u8) -> u64 {
+	let local = fat::timestamp((value >> 16) as u16, value as u16);
+	if local == 0 {
+		return 0;
+	}
+	let local = local + increment as u64 / 100;
+	match offset & 0x80 {
+		0 => local,
+		_ => local.saturating_add_signed(-(((offset << 1) as i8 >> 1) as i64 * 15 * 60)),
+	}
+}
+
+// exFAT 卷：整个 FAT 在打开时读入内存，目录在列出时读取。
+// Entry::location 的低 32 位为起始簇号，第 32 位表示数据占用连续的簇；目录的 Entry::valid_size 为目录数据的长度
+pub struct Exfat {
+	volume: Arc<dyn BlockDevice>,
+	cluster_size: u64,
+	// 第 2 簇在卷中的位置
+	data_start: u64,
+	clusters: u32,
+	table: Vec<u32>,
+	root_cluster: u32,
+}
+
+impl Exfat {
+	pub fn open(volume: Arc<dyn BlockDevice>) -> io::Result<Self> {
+		let mut boot = [0; 512];
+		volume.read_at(0, &mut boot)?;
+		let (sector_shift, cluster_shift, fats) =
+			(boot[108] as u32, boot[109] as u32, boot[110] as u64);
+		if &boot[3..11] != b"EXFAT   "
+			|| !(9..=12).contains(&sector_shift)
+			|| sector_shift + cluster_shift > 25
+			|| !matches!(fats, 1 | 2)
+			|| boot[510..512] != [0x55, 0xAA]
+		{
+			return Err(invalid("the exFAT boot sector is damaged"));
+		}
+		if boot[105] != 1 {
+			return Err(invalid(format!(
+				"unsupported exFAT revision {}.{}",
+				boot[105], boot[104]
+			)));
+		}
+		let sector_size = 1u64 << sector_shift;
+		let (fat_offset, fat_length) = (u32_at(&boot, 80) as u64, u32_at(&boot, 84) as u64);
+		let (heap_offset, clusters) = (u32_at(&boot, 88) as u64, u32_at(&boot, 92));
+		let entries = clusters as u64 + 2;
+		if clusters > 0xFFFF_FFF5 || entries * 4 > fat_length * sector_size {
+			return Err(invalid(
+				"the exFAT allocation table is smaller than the volume",
+			));
+		}
+		// 有两份 FAT 时卷标志的第 0 位选择使用中的一份
+		let active = if fats == 2 {
+			(u16_at(&boot, 106) & 1) as u64
+		} else {
+			0
+		};
+		let mut raw = vec![0; (entries * 4) as usize];
+		volume.read_at((fat_offset + active * fat_length) * sector_size, &mut raw)?;
+		Ok(Self {
+			volume,
+			cluster_size: sector_size << cluster_shift,
+			data_start: heap_offset * sector_size,
+			clusters,
+			table: raw.chunks_exact(4).map(|entry| u32_at(entry, 0)).collect(),
+			root_cluster: u32_at(&boot, 96),
+		})
+	}
+
+	// 从 location 开始的最多 limit 个簇：连续的簇直接计算，否则沿 FAT 中的簇链，链在遇到结束标记、空闲或坏簇时终止
+	fn chain(&self, location: u64, limit: u64) -> io::Result<Vec<u32>> {
+		let start = location as u32;
+		let valid = |cluster: u32| (2..self.clusters + 2).contains(&cluster);
+		if location & CONTIGUOUS != 0 {
+			let count = limit.min(self.clusters as u64) as u32;
+			if count > 0 && !(valid(start) && valid(start.saturating_add(count - 1))) {
+				return Err(invalid(format!(
+					"the clusters starting at {} lie outside the volume",
+					start
+				)));
+			}
+			return Ok((start..start + count).collect());
+		}
+		let mut chain = Vec::new();
+		let mut cluster = start;
+		while valid(cluster) && (chain.len() as u64) < limit {
+			if chain.len() as u64 > self.clusters as u64 {
+				return Err(invalid(format!(
+					"the cluster chain starting at {} loops",
+					start
+				)));
+			}
+			chain.push(cluster);
+			cluster = self.table[cluster as usize];
+		}
+		Ok(chain)
+	}
+
+	// 存放 length 字节数据的簇
+	fn data_chain(&self, entry: &Entry, length: u64) -> io::Result<Vec<u32>> {
+		let chain = self.chain(entry.location, length.div_ceil(self.cluster_size))?;
+		if (chain.len() as u64) < length.div_ceil(self.cluster_size) {
+			return Err(invalid(format!(
+				"the cluster chain of {} is shorter than its data",
+				entry.name
+			)));
+		}
+		Ok(chain)
+	}
+
+	// 读取簇链中的 [offset, offset + length)，相邻的簇合并为一次读取
+	fn read_clusters(&self, chain: &[u32], offset: u64, length: u64) -> io::Result<Vec<u8>> {
+		let mut data = vec![0; length as usize];
+		let mut done = 0;
+		while done < length {
+			let position = offset + done;
+			let (index, within) = (
+				(position / self.cluster_size) as usize,
+				position % self.cluster_size,
+			);
+			let mut run = 1;
+			while run as u64 * self.cluster_size - within < length - done
+				&& index + run < chain.len()
+				&& chain[index + run] == chain[index] + run as u32
+			{
+				run += 1;
+			}
+			let count = (run as u64 * self.cluster_size - within).min(length - done);
+			let start = self.data_start + (chain[index] - 2) as u64 * self.cluster_size + within;
+			self.volume
+				.read_at(start, &mut data[done as usize..(done + count) as usize])?;
+			done += count;
+		}
+		Ok(data)
+	}
+
+	// 每个文件或目录是一组目录项：文件项、流扩展项和若干文件名项；校验和不符的项集合被跳过
+	fn parse_directory(&self, data: &[u8]) -> Vec<Entry> {
+		let mut entries = Vec::new();
+		let mut index = 0;
+		while let Some(raw) = data.get(index * DIRECTORY_ENTRY..(index + 1) * DIRECTORY_ENTRY) {
+			if raw[0] == 0 {
+				break;
+			}
+			let count = raw[1] as usize;
+			let set = data.get(index * DIRECTORY_ENTRY..(index + 1 + count) * DIRECTORY_ENTRY);
+			index += 1;
+			let Some(set) = set.filter(|set| {
+				raw[0] == ENTRY_FILE && count >= 2 && set[DIRECTORY_ENTRY] == ENTRY_STREAM
+			}) else {
+				continue;
+			};
+			if set_checksum(set) != u16_at(raw, 2) {
+				continue;
+			}
+			index += count;
+			let stream = &set[DIRECTORY_ENTRY..2 * DIRECTORY_ENTRY];
+			let name: Vec<u16> = set[2 * DIRECTORY_ENTRY..]
+				.chunks_exact(DIRECTORY_ENTRY)
+				.take_while(|entry| entry[0] == ENTRY_NAME)
+				.flat_map(|entry| (0..NAME_CHARS).map(|char| u16_at(entry, 2 + char * 2)))
+				.take(stream[3] as usize)
+				.collect();
+			let is_directory = u16_at(raw, 4) & ATTR_DIRECTORY != 0;
+			let (valid_size, size) = (u64_at(stream, 8), u64_at(stream, 24));
+			entries.push(Entry {
+				name: String::from_utf16_lossy(&name),
+				is_directory,
+				size: if is_directory { 0 } else { size },
+				valid_size: if is_directory {
+					size
+				} else {
+					valid_size.min(size)
+				},
+				created: timestamp(u32_at(raw, 8), raw[20], raw[22]),
+				modified: timestamp(u32_at(raw, 12), raw[21], raw[23]),
+				accessed: timestamp(u32_at(raw, 16), 0, raw[24]),
+				location: if stream[1] & NO_FAT_CHAIN != 0 {
+					CONTIGUOUS
+				} else {
+					0
+				} | u32_at(stream, 20) as u64,
+			});
+		}
+		entries
+	}
+}
+
+impl FileSystem for Exfat {
+	fn root(&self) -> Entry {
+		Entry {
+			name: String::new(),
+			is_directory: true,
+			size: 0,
+			valid_size: 0,
+			created: 0,
+			modified: 0,
+			accessed: 0,
+			location: self.root_cluster as u64,
+		}
+	}
+
+	fn list(&self, directory: &Entry) -> io::Result<Vec<Entry>> {
+		// 根目录没有记录长度，读到簇链结束
+		let chain = match directory.location == self.root_cluster as u64 {
+			true => self.chain(directory.location, u64::MAX)?,
+			false => self.data_chain(directory, directory.valid_size)?,
+		};
+		Ok(self.parse_directory(&self.read_clusters(
+			&chain,
+			0,
+			chain.len() as u64 * self.cluster_size,
+		)?))
+	}
+
+	// 有效数据长度之后的部分读出为 0
+	fn read(&self, file: &Entry, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+		let end = file.size.min(offset.saturating_add(length as u64));
+		if offset >= end {
+			return Ok(Vec::new());
+		}
+		let stored = end.min(file.valid_size);
+		let mut data = match offset < stored {
+			true => self.read_clusters(&self.data_chain(file, stored)?, offset, stored - offset)?,
+			false => Vec::new(),
+		};
+		data.resize((end - offset) as usize, 0);
+		Ok(data)
+	}
+}
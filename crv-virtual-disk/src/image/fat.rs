@@ -0,0 +1,364 @@
+use std::{io, sync::Arc};
+
+use super::{BlockDevice, Entry, FileSystem};
+use crate::backend::{days_from_civil, invalid, u16_at, u32_at};
+
+const DIRECTORY_ENTRY: usize = 32;
+const ATTR_VOLUME_LABEL: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0F;
+// 长文件名项中 13 个 UTF-16 字符的位置
+const LONG_NAME_CHARS: [usize; 13] = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30];
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+	Fat12,
+	Fat16,
+	Fat32,
+}
+
+// FAT 的引导扇区：跳转指令、合理的扇区和簇大小、至少一个保留扇区和一份 FAT
+pub fn is_boot_sector(sector: &[u8]) -> bool {
+	matches!(sector[0], 0xEB | 0xE9)
+		&& matches!(u16_at(sector, 11), 512 | 1024 | 2048 | 4096)
+		&& sector[13].is_power_of_two()
+		&& u16_at(sector, 14) > 0
+		&& sector[16] > 0
+		&& sector[510..512] == [0x55, 0xAA]
+}
+
+// 目录项中的日期和时间：1980 年起的年份、月、日，时、分和以 2 秒为单位的秒；没有时区，按 UTC 处理
+pub fn timestamp(date: u16, time: u16) -> u64 {
+	if date == 0 {
+		return 0;
+	}
+	let days = days_from_civil(
+		1980 + (date >> 9) as i64,
+		((date >> 5) & 0x0F) as i64,
+		(date & 0x1F) as i64,
+	);
+	let secs = days * 86400
+		+ (time >> 11) as i64 * 3600
+		+ ((time >> 5) & 0x3F) as i64 * 60
+		+ (time & 0x1F) as i64 * 2;
+	u64::try_from(secs).unwrap_or(0)
+}
+
+// 8.3 短文件名；NT 用保留字节中的两位记录全小写的主名和扩展名。
+// 非 ASCII 字符按 Latin-1 解码，这样的名称通常同时带有长文件名
+fn short_name(raw: &[u8]) -> String {
+	let part = |bytes: &[u8], lowercase: bool| {
+		let text: String = bytes.iter().map(|&b| b as char).collect();
+		let text = text.trim_end_matches(' ');
+		if lowercase {
+			text.to_lowercase()
+		} else {
+			text.to_string()
+		}
+	};
+	let mut base = raw[..8].to_vec();
+	// 以 0xE5 开头的名称存为 0x05，因为 0xE5 表示已删除
+	if base[0] == 0x05 {
+		base[0] = 0xE5;
+	}
+	let (base, extension) = (
+		part(&base, raw[12] & 0x08 != 0),
+		part(&raw[8..11], raw[12] & 0x10 != 0),
+	);
+	if extension.is_empty() {
+		base
+	} else {
+		format!("{}.{}", base, extension)
+	}
+}
+
+// 长文件名项中记录的短文件名校验和
+fn short_name_checksum(raw: &[u8]) -> u8 {
+	raw[..11]
+		.iter()
+		.fold(0u8, |sum, &b| sum.rotate_right(1).wrapping_add(b))
+}
+
+// 正在拼接的长文件名：各项从最后一段开始倒序存放，next 为期望的下一个序号
+struct LongName {
+	chars: Vec<u16>,
+	checksum: u8,
+	next: u8,
+}
+
+// FAT12/16/32 卷：整个 FAT 在打开时读入内存，目录在列出时读取。
+// Entry::location 为起始簇号，FAT12/16 的根目录位于 FAT 之后的固定区域，以 0 表示
+pub struct Fat {
+	volume: Arc<dyn BlockDevice>,
+	kind: Kind,
+	cluster_size: u64,
+	// 第 2 簇在卷中的位置
+	data_start: u64,
+	clusters: u32,
+	table: Vec<u32>,
+	root_cluster: u32,
+	// FAT12/16 根目录区域的位置和大小
+	root_region: (u64, u64),
+}
+
+impl Fat {
+	pub fn open(volume: Arc<dyn BlockDevice>) -> io::Result<Self> {
+		let mut boot = [0; 512];
+		volume.read_at(0, &mut boot)?;
+		let bytes_per_sector = u16_at(&boot, 11) as u64;
+		let sectors_per_cluster = boot[13] as u64;
+		let (reserved, fats, root_entries) = (
+			u16_at(&boot, 14) as u64,
+			boot[16] as u64,
+			u16_at(&boot, 17) as u64,
+		);
+		let total_sectors = match u16_at(&boot, 19) {
+			0 => u32_at(&boot, 32) as u64,
+			count => count as u64,
+		};
+		let fat_sectors = match u16_at(&boot, 22) {
+			0 => u32_at(&boot, 36) as u64,
+			count => count as u64,
+		};
+		let root_sectors = (root_entries * DIRECTORY_ENTRY as u64).div_ceil(bytes_per_sector);
+		let data_sector = reserved + fats * fat_sectors + root_sectors;
+		if fat_sectors == 0 || total_sectors <= data_sector {
+			return Err(invalid("the FAT boot sector is damaged"));
+		}
+		// FAT 的类型只由簇的数量决定
+		let clusters = (total_sectors - data_sector) / sectors_per_cluster;
+		let kind = match clusters {
+			0..4085 => Kind::Fat12,
+			4085..65525 => Kind::Fat16,
+			_ => Kind::Fat32,
+		};
+		let clusters = u32::try_from(clusters)
+			.ok()
+			.filter(|&clusters| clusters < 0x0FFF_FFF5)
+			.ok_or_else(|| invalid("the FAT volume has too many clusters"))?;
+
+		// FAT32 可以只使用其中一份 FAT（扩展标志的第 7 位），其余时候读第一份
+		let active = match kind {
+			Kind::Fat32 if u16_at(&boot, 40) & 0x80 != 0 => (u16_at(&boot, 40) & 0x0F) as u64,
+			_ => 0,
+		};
+		let entries = clusters as u64 + 2;
+		let table_size = match kind {
+			Kind::Fat12 => (entries * 3).div_ceil(2),
+			Kind::Fat16 => entries * 2,
+			Kind::Fat32 => entries * 4,
+		};
+		if table_size > fat_sectors * bytes_per_sector || active >= fats {
+			return Err(invalid("the FAT is smaller than the volume"));
+		}
+		let mut raw = vec![0; table_size as usize];
+		volume.read_at(
+			(reserved + active * fat_sectors) * bytes_per_sector,
+			&mut raw,
+		)?;
+		let table = (0..entries as usize)
+			.map(|n| match kind {
+				Kind::Fat12 => {
+					let pair = u16_at(&raw, n * 3 / 2);
+					(if n % 2 == 0 { pair & 0x0FFF } else { pair >> 4 }) as u32
+				}
+				Kind::Fat16 => u16_at(&raw, n * 2) as u32,
+				Kind::Fat32 => u32_at(&raw, n * 4) & 0x0FFF_FFFF,
+			})
+			.collect();
+
+		Ok(Self {
+			volume,
+			kind,
+			cluster_size: bytes_per_sector * sectors_per_cluster,
+			data_start: data_sector * bytes_per_sector,
+			clusters,
+			table,
+			root_cluster: if kind == Kind::Fat32 {
+				u32_at(&boot, 44)
+			} else {
+				0
+			},
+			root_region: (
+				(reserved + fats * fat_sectors) * bytes_per_sector,
+				root_sectors * bytes_per_sector,
+			),
+		})
+	}
+
+	// 从 start 开始的簇链，最多 limit 个簇；链在遇到结束标记、空闲或坏簇时终止
+	fn chain(&self, start: u32, limit: u64) -> io::Result<Vec<u32>> {
+		let mut chain = Vec::new();
+		let mut cluster = start;
+		while (2..self.clusters + 2).contains(&cluster) && (chain.len() as u64) < limit {
+			if chain.len() as u64 > self.clusters as u64 {
+				return Err(invalid(format!(
+					"the cluster chain starting at {} loops",
+					start
+				)));
+			}
+			chain.push(cluster);
+			cluster = self.table[cluster as usize];
+		}
+		Ok(chain)
+	}
+
+	// 读取簇链中的 [offset, offset + length)，相邻的簇合并为一次读取
+	fn read_clusters(&self, chain: &[u32], offset: u64, length: u64) -> io::Result<Vec<u8>> {
+		let mut data = vec![0; length as usize];
+		let mut done = 0;
+		while done < length {
+			let position = offset + done;
+			let (index, within) = (
+				(position / self.cluster_size) as usize,
+				position % self.cluster_size,
+			);
+			let mut run = 1;
+			while run as u64 * self.cluster_size - within < length - done
+				&& index + run < chain.len()
+				&& chain[index + run] == chain[index] + run as u32
+			{
+				run += 1;
+			}
+			let count = (run as u64 * self.cluster_size - within).min(length - done);
+			let start = self.data_start + (chain[index] - 2) as u64 * self.cluster_size + within;
+			self.volume
+				.read_at(start, &mut data[done as usize..(done + count) as usize])?;
+			done += count;
+		}
+		Ok(data)
+	}
+
+	fn parse_directory(&self, data: &[u8]) -> Vec<Entry> {
+		let mut entries = Vec::new();
+		let mut long_name: Option<LongName> = None;
+		for raw in data.chunks_exact(DIRECTORY_ENTRY) {
+			match raw[0] {
+				0 => break,
+				0xE5 => {
+					long_name = None;
+					continue;
+				}
+				_ => {}
+			}
+			let attributes = raw[11];
+			if attributes & 0x3F == ATTR_LONG_NAME {
+				let sequence = raw[0] & 0x1F;
+				// 第一个物理项带有 0x40 标志，序号就是总段数
+				if raw[0] & 0x40 != 0 && sequence > 0 {
+					long_name = Some(LongName {
+						chars: vec![0xFFFF; sequence as usize * 13],
+						checksum: raw[13],
+						next: sequence,
+					});
+				}
+				match &mut long_name {
+					Some(name)
+						if sequence > 0 && name.next == sequence && name.checksum == raw[13] =>
+					{
+						let at = (sequence as usize - 1) * 13;
+						for (index, &offset) in LONG_NAME_CHARS.iter().enumerate() {
+							name.chars[at + index] = u16_at(raw, offset);
+						}
+						name.next -= 1;
+					}
+					_ => long_name = None,
+				}
+				continue;
+			}
+			let long_name = long_name
+				.take()
+				.filter(|name| name.next == 0 && name.checksum == short_name_checksum(raw));
+			// 跳过卷标和 . 与 ..
+			if attributes & ATTR_VOLUME_LABEL != 0 || raw[0] == b'.' {
+				continue;
+			}
+			let name = match long_name {
+				Some(name) => {
+					let end = name
+						.chars
+						.iter()
+						.position(|&c| c == 0 || c == 0xFFFF)
+						.unwrap_or(name.chars.len());
+					String::from_utf16_lossy(&name.chars[..end])
+				}
+				None => short_name(raw),
+			};
+			// FAT12/16 中簇号的高 16 位另有用途
+			let high = if self.kind == Kind::Fat32 {
+				u16_at(raw, 20) as u64
+			} else {
+				0
+			};
+			let is_directory = attributes & ATTR_DIRECTORY != 0;
+			entries.push(Entry {
+				name,
+				is_directory,
+				size: if is_directory {
+					0
+				} else {
+					u32_at(raw, 28) as u64
+				},
+				valid_size: if is_directory {
+					0
+				} else {
+					u32_at(raw, 28) as u64
+				},
+				// 创建时间另有以 10 毫秒为单位的部分
+				created: timestamp(u16_at(raw, 16), u16_at(raw, 14)) + raw[13] as u64 / 100,
+				modified: timestamp(u16_at(raw, 24), u16_at(raw, 22)),
+				accessed: timestamp(u16_at(raw, 18), 0),
+				location: high << 16 | u16_at(raw, 26) as u64,
+			});
+		}
+		entries
+	}
+}
+
+impl FileSystem for Fat {
+	fn root(&self) -> Entry {
+		Entry {
+			name: String::new(),
+			is_directory: true,
+			size: 0,
+			valid_size: 0,
+			created: 0,
+			modified: 0,
+			accessed: 0,
+			location: self.root_cluster as u64,
+		}
+	}
+
+	fn list(&self, directory: &Entry) -> io::Result<Vec<Entry>> {
+		let data = match directory.location {
+			0 if self.kind != Kind::Fat32 => {
+				let (offset, size) = self.root_region;
+				let mut data = vec![0; size as usize];
+				self.volume.read_at(offset, &mut data)?;
+				data
+			}
+			// 指向第 0 簇的子目录是损坏的，按空目录处理
+			0 => Vec::new(),
+			location => {
+				let chain = self.chain(location as u32, u64::MAX)?;
+				self.read_clusters(&chain, 0, chain.len() as u64 * self.cluster_size)?
+			}
+		};
+		Ok(self.parse_directory(&data))
+	}
+
+	fn read(&self, file: &Entry, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+		let end = file.size.min(offset.saturating_add(length as u64));
+		if offset >= end {
+			return Ok(Vec::new());
+		}
+		let chain = self.chain(file.location as u32, end.div_ceil(self.cluster_size))?;
+		if (chain.len() as u64) < end.div_ceil(self.cluster_size) {
+			return Err(invalid(format!(
+				"the cluster chain of {} is shorter than the file",
+				file.name
+			)));
+		}
+		self.read_clusters(&chain, offset, end - offset)
+	}
+}
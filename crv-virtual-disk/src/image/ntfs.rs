@@ -0,0 +1,593 @@
+use std::{io, sync::Arc};
+
+use super::{BlockDevice, Entry, FileSystem};
+use crate::backend::{invalid, u16_at, u32_at, u64_at};
+
+// 属性类型
+const STANDARD_INFORMATION: u32 = 0x10;
+const ATTRIBUTE_LIST: u32 = 0x20;
+const DATA: u32 = 0x80;
+const INDEX_ROOT: u32 = 0x90;
+const INDEX_ALLOCATION: u32 = 0xA0;
+const BITMAP: u32 = 0xB0;
+const END: u32 = 0xFFFF_FFFF;
+// 属性标志
+const ATTR_COMPRESSED: u16 = 0x0001;
+const ATTR_ENCRYPTED: u16 = 0x4000;
+// 文件记录标志
+const RECORD_IN_USE: u16 = 0x01;
+const RECORD_DIRECTORY: u16 = 0x02;
+// 索引项标志
+const ENTRY_LAST: u16 = 0x02;
+// 只有 DOS 8.3 名称的文件名，同一文件另有长名称
+const NAMESPACE_DOS: u8 = 2;
+const MFT_RECORD: u64 = 0;
+const ROOT_RECORD: u64 = 5;
+// 0 到 23 号记录是 $MFT、$Bitmap 等元文件和保留的记录，不显示
+const FIRST_USER_RECORD: u64 = 24;
+// 目录索引的名称
+const I30: &str = "$I30";
+// 更新序列按 512 字节一段保护记录
+const FIXUP_STRIDE: usize = 512;
+// 整个读入内存的属性（属性列表、索引）的大小上限
+const MAX_VALUE: u64 = 256 * 1024 * 1024;
+// 1601 年到 1970 年的秒数
+const FILETIME_EPOCH: u64 = 11_644_473_600;
+
+// NTFS 时间：1601 年起以 100 纳秒为单位
+fn timestamp(filetime: u64) -> u64 {
+	(filetime / 10_000_000).saturating_sub(FILETIME_EPOCH)
+}
+
+// 文件记录和索引记录的每 512 字节的最后两字节被替换为更新序列号，原来的值保存在更新序列数组中
+fn fixup(record: &mut [u8]) -> io::Result<()> {
+	let (offset, count) = (u16_at(record, 4) as usize, u16_at(record, 6) as usize);
+	if count == 0 || offset + count * 2 > record.len() || (count - 1) * FIXUP_STRIDE > record.len()
+	{
+		return Err(invalid("an NTFS record has an invalid update sequence"));
+	}
+	let sequence = u16_at(record, offset);
+	for index in 1..count {
+		let at = index * FIXUP_STRIDE - 2;
+		if u16_at(record, at) != sequence {
+			return Err(invalid("an NTFS record was torn while being written"));
+		}
+		record.copy_within(offset + index * 2..offset + index * 2 + 2, at);
+	}
+	Ok(())
+}
+
+// 数据段：从 vcn 开始的 length 个簇位于卷中的 lcn，稀疏的段没有 lcn
+#[derive(Clone)]
+struct Run {
+	vcn: u64,
+	length: u64,
+	lcn: Option<u64>,
+}
+
+// 数据段列表：每段以一个字节开头，低 4 位和高 4 位分别是长度和偏移的字节数，偏移是相对上一段的有符号数
+fn decode_runs(data: &[u8], mut vcn: u64) -> io::Result<Vec<Run>> {
+	let damaged = || invalid("an NTFS data run list is damaged");
+	let mut runs = Vec::new();
+	let (mut position, mut lcn) = (0, 0i64);
+	while let Some(&header) = data.get(position).filter(|&&header| header != 0) {
+		let (length_size, offset_size) = ((header & 0x0F) as usize, (header >> 4) as usize);
+		let fields = data
+			.get(position + 1..position + 1 + length_size + offset_size)
+			.filter(|_| (1..=8).contains(&length_size) && offset_size <= 8)
+			.ok_or_else(damaged)?;
+		let length = fields[..length_size]
+			.iter()
+			.rev()
+			.fold(0u64, |value, &b| value << 8 | b as u64);
+		let run_lcn = match offset_size {
+			0 => None,
+			_ => {
+				let bytes = &fields[length_size..];
+				// 最高字节按有符号数扩展
+				let delta = bytes.iter().rev().fold(
+					if bytes[offset_size - 1] & 0x80 != 0 {
+						-1i64
+					} else {
+						0
+					},
+					|value, &b| value << 8 | b as i64,
+				);
+				lcn = lcn
+					.checked_add(delta)
+					.filter(|&lcn| lcn >= 0)
+					.ok_or_else(damaged)?;
+				Some(lcn as u64)
+			}
+		};
+		runs.push(Run {
+			vcn,
+			length,
+			lcn: run_lcn,
+		});
+		vcn = vcn.checked_add(length).ok_or_else(damaged)?;
+		position += 1 + length_size + offset_size;
+	}
+	Ok(runs)
+}
+
+enum Value {
+	Resident(Vec<u8>),
+	NonResident {
+		runs: Vec<Run>,
+		size: u64,
+		// 初始化过的长度，之后的部分读出为 0
+		initialized: u64,
+	},
+}
+
+struct Attribute {
+	kind: u32,
+	name: String,
+	flags: u16,
+	// 非常驻属性可以分成多段记录在不同的文件记录中，start 为这一段的起始簇
+	start: u64,
+	value: Value,
+}
+
+impl Attribute {
+	fn size(&self) -> u64 {
+		match &self.value {
+			Value::Resident(data) => data.len() as u64,
+			Value::NonResident { size, .. } => *size,
+		}
+	}
+}
+
+// 文件记录中的属性
+fn parse_attributes(record: &[u8]) -> io::Result<Vec<Attribute>> {
+	let damaged = || invalid("an NTFS file record is damaged");
+	let mut attributes = Vec::new();
+	let mut position = u16_at(record, 20) as usize;
+	while position + 16 <= record.len() && u32_at(record, position) != END {
+		let length = u32_at(record, position + 4) as usize;
+		let header = record
+			.get(position..position + length)
+			.filter(|_| length >= 16)
+			.ok_or_else(damaged)?;
+		let (name_length, name_offset) = (header[9] as usize, u16_at(header, 10) as usize);
+		let name = header
+			.get(name_offset..name_offset + name_length * 2)
+			.ok_or_else(damaged)?;
+		let value = match header[8] {
+			0 => {
+				let (value_length, value_offset) =
+					(u32_at(header, 16) as usize, u16_at(header, 20) as usize);
+				Value::Resident(
+					header
+						.get(value_offset..value_offset + value_length)
+						.ok_or_else(damaged)?
+						.to_vec(),
+				)
+			}
+			_ if length >= 64 => Value::NonResident {
+				runs: decode_runs(
+					header
+						.get(u16_at(header, 32) as usize..)
+						.ok_or_else(damaged)?,
+					u64_at(header, 16),
+				)?,
+				size: u64_at(header, 48),
+				initialized: u64_at(header, 56),
+			},
+			_ => return Err(damaged()),
+		};
+		attributes.push(Attribute {
+			kind: u32_at(header, 0),
+			name: super::utf16(name),
+			flags: u16_at(header, 12),
+			start: if header[8] == 0 {
+				0
+			} else {
+				u64_at(header, 16)
+			},
+			value,
+		});
+		position += length;
+	}
+	Ok(attributes)
+}
+
+// 分成多段的非常驻属性按起始簇合并为一个，大小等取自第一段
+fn merge_extents(attributes: Vec<Attribute>) -> Vec<Attribute> {
+	let mut merged: Vec<Attribute> = Vec::new();
+	let mut rest = Vec::new();
+	for attribute in attributes {
+		match attribute.start {
+			0 => merged.push(attribute),
+			_ => rest.push(attribute),
+		}
+	}
+	rest.sort_by_key(|attribute| attribute.start);
+	for extent in rest {
+		let first = merged
+			.iter_mut()
+			.find(|first| first.kind == extent.kind && first.name == extent.name);
+		if let (
+			Some(Attribute {
+				value: Value::NonResident { runs, .. },
+				..
+			}),
+			Value::NonResident { runs: more, .. },
+		) = (first, extent.value)
+		{
+			runs.extend(more);
+		}
+	}
+	merged
+}
+
+// 目录索引中的一项：文件名属性和它指向的文件记录
+struct IndexEntry {
+	record: u64,
+	sequence: u16,
+	name: String,
+	namespace: u8,
+}
+
+// 一个索引节点中的项，到带有最后标志的项为止
+fn parse_index_entries(node: &[u8]) -> io::Result<Vec<IndexEntry>> {
+	let damaged = || invalid("an NTFS directory index is damaged");
+	let mut entries = Vec::new();
+	let mut position = u32_at(node, 0) as usize;
+	let end = (u32_at(node, 4) as usize).min(node.len());
+	while position + 16 <= end {
+		let (length, key_length, flags) = (
+			u16_at(node, position + 8) as usize,
+			u16_at(node, position + 10) as usize,
+			u16_at(node, position + 12),
+		);
+		if flags & ENTRY_LAST != 0 {
+			break;
+		}
+		let key = node
+			.get(position + 16..position + 16 + key_length)
+			.filter(|_| length >= 16 && key_length >= 66)
+			.ok_or_else(damaged)?;
+		let name = key.get(66..66 + key[64] as usize * 2).ok_or_else(damaged)?;
+		let reference = u64_at(node, position);
+		entries.push(IndexEntry {
+			record: reference & 0xFFFF_FFFF_FFFF,
+			sequence: (reference >> 48) as u16,
+			name: super::utf16(name),
+			namespace: key[65],
+		});
+		position += length;
+	}
+	Ok(entries)
+}
+
+// NTFS 卷：文件记录按需从 $MFT 读取，目录列出时读取索引根和全部在用的索引记录，并读取每个子项的文件记录以取得准确的大小和时间。
+// Entry::location 为文件记录号。压缩和加密的文件不能读取
+pub struct Ntfs {
+	volume: Arc<dyn BlockDevice>,
+	cluster_size: u64,
+	record_size: u64,
+	// $MFT 自身的数据段
+	mft: Vec<Run>,
+}
+
+impl Ntfs {
+	pub fn open(volume: Arc<dyn BlockDevice>) -> io::Result<Self> {
+		let mut boot = [0; 512];
+		volume.read_at(0, &mut boot)?;
+		let sector_size = u16_at(&boot, 11) as u64;
+		// 每簇扇区数大于 0x80 时表示 2 的 (256 - 值) 次方
+		let sectors_per_cluster = match boot[13] {
+			count @ 0..=0x80 => count as u64,
+			exponent => 1u64 << (256 - exponent as u32).min(31),
+		};
+		let cluster_size = sector_size * sectors_per_cluster;
+		if &boot[3..11] != b"NTFS    "
+			|| !matches!(sector_size, 512 | 1024 | 2048 | 4096)
+			|| sectors_per_cluster == 0
+			|| cluster_size > 2 * 1024 * 1024
+		{
+			return Err(invalid("the NTFS boot sector is damaged"));
+		}
+		// 记录大小为负数时表示 2 的 (-值) 次方字节，否则是簇数
+		let record_size = match boot[64] as i8 {
+			size @ 1.. => size as u64 * cluster_size,
+			exponent => 1u64 << (-(exponent as i32)).clamp(0, 20),
+		};
+		if !(1024..=64 * 1024).contains(&record_size) {
+			return Err(invalid(
+				"the NTFS boot sector has an invalid file record size",
+			));
+		}
+		let mft_cluster = u64_at(&boot, 48);
+		// 先按 0 号记录自己的数据段读取 $MFT，有属性列表时再合并其他记录中的数据段
+		let mut ntfs = Self {
+			volume,
+			cluster_size,
+			record_size,
+			mft: vec![Run {
+				vcn: 0,
+				length: u64::MAX / cluster_size,
+				lcn: Some(mft_cluster),
+			}],
+		};
+		let record = ntfs.record(MFT_RECORD)?;
+		ntfs.mft = match parse_attributes(&record)?
+			.into_iter()
+			.find(|attribute| attribute.kind == DATA && attribute.name.is_empty())
+		{
+			Some(Attribute {
+				value: Value::NonResident { runs, .. },
+				..
+			}) => runs,
+			_ => return Err(invalid("the NTFS $MFT has no data")),
+		};
+		if let Some(Attribute {
+			value: Value::NonResident { runs, .. },
+			..
+		}) = ntfs
+			.attributes(MFT_RECORD)?
+			.into_iter()
+			.find(|attribute| attribute.kind == DATA && attribute.name.is_empty())
+		{
+			ntfs.mft = runs;
+		}
+		Ok(ntfs)
+	}
+
+	// 读取非常驻数据中的 [offset, offset + length)；稀疏的段读出为 0
+	fn read_runs(&self, runs: &[Run], offset: u64, length: u64) -> io::Result<Vec<u8>> {
+		let mut data = vec![0; length as usize];
+		let end = offset + length;
+		for run in runs {
+			let (start, stop) = (
+				run.vcn.saturating_mul(self.cluster_size),
+				(run.vcn.saturating_add(run.length)).saturating_mul(self.cluster_size),
+			);
+			let (from, to) = (start.max(offset), stop.min(end));
+			if from >= to {
+				continue;
+			}
+			if let Some(lcn) = run.lcn {
+				let position = lcn
+					.checked_mul(self.cluster_size)
+					.and_then(|base| base.checked_add(from - start))
+					.ok_or_else(|| invalid("an NTFS data run lies outside the volume"))?;
+				self.volume.read_at(
+					position,
+					&mut data[(from - offset) as usize..(to - offset) as usize],
+				)?;
+			}
+		}
+		let covered: u64 = runs
+			.iter()
+			.map(|run| run.length)
+			.sum::<u64>()
+			.saturating_mul(self.cluster_size);
+		if covered < end {
+			return Err(invalid("an NTFS data run list is shorter than its data"));
+		}
+		Ok(data)
+	}
+
+	// 文件记录，已应用更新序列
+	fn record(&self, number: u64) -> io::Result<Vec<u8>> {
+		let offset = number
+			.checked_mul(self.record_size)
+			.ok_or_else(|| invalid(format!("NTFS file record {} does not exist", number)))?;
+		let mut record = self.read_runs(&self.mft, offset, self.record_size)?;
+		if &record[..4] != b"FILE" {
+			return Err(invalid(format!("NTFS file record {} is damaged", number)));
+		}
+		fixup(&mut record)?;
+		Ok(record)
+	}
+
+	fn attributes(&self, number: u64) -> io::Result<Vec<Attribute>> {
+		self.record_attributes(number, &self.record(number)?)
+	}
+
+	// 文件的全部属性；有属性列表时属性可以分布在多个文件记录中
+	fn record_attributes(&self, number: u64, record: &[u8]) -> io::Result<Vec<Attribute>> {
+		let mut attributes = parse_attributes(record)?;
+		let Some(list) = attributes
+			.iter()
+			.position(|attribute| attribute.kind == ATTRIBUTE_LIST)
+		else {
+			return Ok(attributes);
+		};
+		let list = attributes.remove(list);
+		let list = self.value(&list)?;
+		let mut others: Vec<u64> = Vec::new();
+		let mut position = 0;
+		while position + 26 <= list.len() {
+			let length = u16_at(&list, position + 4) as usize;
+			if length < 26 {
+				return Err(invalid("an NTFS attribute list is damaged"));
+			}
+			let other = u64_at(&list, position + 16) & 0xFFFF_FFFF_FFFF;
+			if other != number && !others.contains(&other) {
+				others.push(other);
+			}
+			position += length;
+		}
+		for other in others {
+			let record = self.record(other)?;
+			// 扩展记录指向它的基本记录
+			if u64_at(&record, 32) & 0xFFFF_FFFF_FFFF != number {
+				return Err(invalid(format!(
+					"NTFS file record {} does not belong to record {}",
+					other, number
+				)));
+			}
+			attributes.extend(parse_attributes(&record)?);
+		}
+		Ok(merge_extents(attributes))
+	}
+
+	// 属性的全部内容
+	fn value(&self, attribute: &Attribute) -> io::Result<Vec<u8>> {
+		match &attribute.value {
+			Value::Resident(data) => Ok(data.clone()),
+			Value::NonResident { size, .. } if *size > MAX_VALUE => {
+				Err(invalid("an NTFS index or attribute list is too large"))
+			}
+			Value::NonResident {
+				runs,
+				size,
+				initialized,
+			} => {
+				let mut data = self.read_runs(runs, 0, *initialized.min(size))?;
+				data.resize(*size as usize, 0);
+				Ok(data)
+			}
+		}
+	}
+
+	// 文件记录对应的条目：大小取自未命名的数据属性，时间取自标准信息属性
+	fn entry(&self, name: String, number: u64, sequence: u16) -> io::Result<Option<Entry>> {
+		let record = self.record(number)?;
+		let flags = u16_at(&record, 22);
+		// 序号不符说明记录已被其他文件重用
+		if flags & RECORD_IN_USE == 0 || (sequence != 0 && u16_at(&record, 16) != sequence) {
+			return Ok(None);
+		}
+		let attributes = self.record_attributes(number, &record)?;
+		let is_directory = flags & RECORD_DIRECTORY != 0;
+		let data = attributes
+			.iter()
+			.find(|attribute| attribute.kind == DATA && attribute.name.is_empty());
+		let (size, valid_size) = match data.map(|attribute| &attribute.value) {
+			Some(Value::NonResident {
+				size, initialized, ..
+			}) => (*size, *initialized.min(size)),
+			Some(Value::Resident(data)) => (data.len() as u64, data.len() as u64),
+			None => (0, 0),
+		};
+		let times = match attributes
+			.iter()
+			.find(|attribute| attribute.kind == STANDARD_INFORMATION)
+			.map(|attribute| &attribute.value)
+		{
+			Some(Value::Resident(data)) if data.len() >= 32 => {
+				[u64_at(data, 0), u64_at(data, 8), u64_at(data, 24)].map(timestamp)
+			}
+			_ => [0; 3],
+		};
+		Ok(Some(Entry {
+			name,
+			is_directory,
+			size: if is_directory { 0 } else { size },
+			valid_size: if is_directory { 0 } else { valid_size },
+			created: times[0],
+			modified: times[1],
+			accessed: times[2],
+			location: number,
+		}))
+	}
+}
+
+impl FileSystem for Ntfs {
+	fn root(&self) -> Entry {
+		Entry {
+			name: String::new(),
+			is_directory: true,
+			size: 0,
+			valid_size: 0,
+			created: 0,
+			modified: 0,
+			accessed: 0,
+			location: ROOT_RECORD,
+		}
+	}
+
+	// 索引是 B 树，但列出时不需要顺序：读取索引根中的项和位图中标记为在用的每个索引记录中的项
+	fn list(&self, directory: &Entry) -> io::Result<Vec<Entry>> {
+		let attributes = self.attributes(directory.location)?;
+		let find = |kind: u32| {
+			attributes
+				.iter()
+				.find(|attribute| attribute.kind == kind && attribute.name == I30)
+		};
+		let root = match find(INDEX_ROOT).map(|attribute| &attribute.value) {
+			Some(Value::Resident(data)) if data.len() >= 32 => data,
+			_ => {
+				return Err(invalid(format!(
+					"NTFS directory record {} has no index",
+					directory.location
+				)))
+			}
+		};
+		let mut index = parse_index_entries(&root[16..])?;
+		if let Some(allocation) = find(INDEX_ALLOCATION) {
+			let record_size = u32_at(root, 8) as usize;
+			if !(512..=64 * 1024).contains(&record_size) {
+				return Err(invalid(
+					"an NTFS directory index has an invalid record size",
+				));
+			}
+			let bitmap = find(BITMAP)
+				.map(|bitmap| self.value(bitmap))
+				.transpose()?
+				.unwrap_or_default();
+			let data = self.value(allocation)?;
+			for (number, record) in data.chunks_exact(record_size).enumerate() {
+				if bitmap
+					.get(number / 8)
+					.is_none_or(|&bits| bits & (1 << (number % 8)) == 0)
+					|| &record[..4] != b"INDX"
+				{
+					continue;
+				}
+				let mut record = record.to_vec();
+				fixup(&mut record)?;
+				index.extend(parse_index_entries(&record[24..])?);
+			}
+		}
+		let mut entries = Vec::new();
+		for item in index {
+			if item.namespace == NAMESPACE_DOS || item.record < FIRST_USER_RECORD {
+				continue;
+			}
+			if let Some(entry) = self.entry(item.name, item.record, item.sequence)? {
+				entries.push(entry);
+			}
+		}
+		Ok(entries)
+	}
+
+	// 初始化长度之后的部分读出为 0
+	fn read(&self, file: &Entry, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+		let end = file.size.min(offset.saturating_add(length as u64));
+		if offset >= end {
+			return Ok(Vec::new());
+		}
+		let attributes = self.attributes(file.location)?;
+		let data = attributes
+			.iter()
+			.find(|attribute| attribute.kind == DATA && attribute.name.is_empty())
+			.ok_or_else(|| invalid(format!("{} has no data", file.name)))?;
+		if data.flags & (ATTR_COMPRESSED | ATTR_ENCRYPTED) != 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				format!("{} is compressed or encrypted by NTFS", file.name),
+			));
+		}
+		let end = end.min(data.size());
+		let mut content = match &data.value {
+			Value::Resident(value) => value[offset.min(end) as usize..end as usize].to_vec(),
+			Value::NonResident {
+				runs, initialized, ..
+			} => {
+				let stored = end.min(*initialized);
+				match offset < stored {
+					true => self.read_runs(runs, offset, stored - offset)?,
+					false => Vec::new(),
+				}
+			}
+		};
+		content.resize(end.saturating_sub(offset) as usize, 0);
+		Ok(content)
+	}
+}
@@ -0,0 +1,256 @@
+use std::{fmt, io};
+
+use super::{boot_sector_file_system, crc32, guid, guid_string, utf16, BlockDevice};
+use crate::backend::{invalid, u32_at, u64_at};
+
+// MBR 分区项的类型：扩展分区，以及表示磁盘使用 GPT 的保护分区
+const EXTENDED: [u8; 3] = [0x05, 0x0F, 0x85];
+const PROTECTIVE: u8 = 0xEE;
+// 逻辑分区数和 GPT 分区项数的上限
+const MAX_LOGICAL: usize = 128;
+const MAX_GPT_ENTRIES: u64 = 1024;
+const GPT_CRC32: u32 = 0xEDB8_8320;
+
+const EFI_SYSTEM: [u8; 16] = guid(
+	0xC12A7328,
+	0xF81F,
+	0x11D2,
+	[0xBA, 0x4B, 0x00, 0xA0, 0xC9, 0x3E, 0xC9, 0x3B],
+);
+const MICROSOFT_RESERVED: [u8; 16] = guid(
+	0xE3C9E316,
+	0x0B5C,
+	0x4DB8,
+	[0x81, 0x7D, 0xF9, 0x2D, 0xF0, 0x02, 0x15, 0xAE],
+);
+const BASIC_DATA: [u8; 16] = guid(
+	0xEBD0A0A2,
+	0xB9E5,
+	0x4433,
+	[0x87, 0xC0, 0x68, 0xB6, 0xB7, 0x26, 0x99, 0xC7],
+);
+const WINDOWS_RECOVERY: [u8; 16] = guid(
+	0xDE94BBA4,
+	0x06D1,
+	0x4D40,
+	[0xA1, 0x6A, 0xBF, 0xD5, 0x01, 0x79, 0xD6, 0xAC],
+);
+const LINUX_FILESYSTEM: [u8; 16] = guid(
+	0x0FC63DAF,
+	0x8483,
+	0x4772,
+	[0x8E, 0x79, 0x3D, 0x69, 0xD8, 0x47, 0x7D, 0xE4],
+);
+const LINUX_SWAP: [u8; 16] = guid(
+	0x0657FD6D,
+	0xA4AB,
+	0x43C4,
+	[0x84, 0xE5, 0x09, 0x33, 0xC8, 0x4B, 0x4F, 0x4F],
+);
+
+// 分区类型：MBR 的类型字节或 GPT 的类型 GUID
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartitionType {
+	Mbr(u8),
+	Gpt([u8; 16]),
+}
+
+// 常见类型显示名称，其他类型显示类型字节或 GUID
+impl fmt::Display for PartitionType {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let name = match self {
+			Self::Mbr(0x01) => "FAT12",
+			Self::Mbr(0x04 | 0x06 | 0x0E) => "FAT16",
+			Self::Mbr(0x07) => "NTFS/exFAT",
+			Self::Mbr(0x0B | 0x0C) => "FAT32",
+			Self::Mbr(0x27) => "Windows recovery",
+			Self::Mbr(0x82) => "Linux swap",
+			Self::Mbr(0x83) => "Linux",
+			Self::Mbr(0xEF) | Self::Gpt(EFI_SYSTEM) => "EFI system",
+			Self::Gpt(MICROSOFT_RESERVED) => "Microsoft reserved",
+			Self::Gpt(BASIC_DATA) => "Basic data",
+			Self::Gpt(WINDOWS_RECOVERY) => "Windows recovery",
+			Self::Gpt(LINUX_FILESYSTEM) => "Linux",
+			Self::Gpt(LINUX_SWAP) => "Linux swap",
+			Self::Mbr(kind) => return write!(f, "0x{:02X}", kind),
+			Self::Gpt(kind) => return f.write_str(&guid_string(kind)),
+		};
+		f.write_str(name)
+	}
+}
+
+#[derive(Clone, Debug)]
+pub struct Partition {
+	// 分区表中的序号，从 1 开始；MBR 的逻辑分区从 5 开始
+	pub number: usize,
+	pub offset: u64,
+	pub size: u64,
+	pub kind: PartitionType,
+	// GPT 分区的名称，MBR 分区没有名称
+	pub name: String,
+}
+
+fn read_sector(device: &dyn BlockDevice, lba: u64) -> io::Result<Vec<u8>> {
+	let sector_size = device.sector_size() as u64;
+	let mut sector = vec![0; sector_size as usize];
+	device.read_at(lba * sector_size, &mut sector)?;
+	Ok(sector)
+}
+
+// MBR 和扩展引导记录中的第 index 项：类型、起始扇区和扇区数
+fn mbr_entry(sector: &[u8], index: usize) -> (u8, u64, u64) {
+	let entry = &sector[446 + index * 16..462 + index * 16];
+	(entry[4], u32_at(entry, 8) as u64, u32_at(entry, 12) as u64)
+}
+
+// 磁盘上的分区：GPT 的分区项，或 MBR 的主分区和扩展分区中的逻辑分区；
+// 第一个扇区不是分区表（如整个磁盘就是一个卷）时返回空列表
+pub fn partitions(device: &dyn BlockDevice) -> io::Result<Vec<Partition>> {
+	let sector_size = device.sector_size() as u64;
+	if device.size() < sector_size * 2 {
+		return Ok(Vec::new());
+	}
+	let mbr = read_sector(device, 0)?;
+	if mbr[510..512] != [0x55, 0xAA] || boot_sector_file_system(&mbr).is_some() {
+		return Ok(Vec::new());
+	}
+	if (0..4).any(|index| mbr_entry(&mbr, index).0 == PROTECTIVE)
+		|| &read_sector(device, 1)?[..8] == b"EFI PART"
+	{
+		return gpt_partitions(device);
+	}
+	mbr_partitions(device, &mbr)
+}
+
+fn mbr_partitions(device: &dyn BlockDevice, mbr: &[u8]) -> io::Result<Vec<Partition>> {
+	let sector_size = device.sector_size() as u64;
+	let partition = |number, kind, start: u64, count: u64| Partition {
+		number,
+		offset: start * sector_size,
+		size: count * sector_size,
+		kind: PartitionType::Mbr(kind),
+		name: String::new(),
+	};
+	let mut partitions = Vec::new();
+	let mut extended = None;
+	for index in 0..4 {
+		let (kind, start, count) = mbr_entry(mbr, index);
+		if kind == 0 || start == 0 || count == 0 {
+			continue;
+		}
+		if EXTENDED.contains(&kind) {
+			extended.get_or_insert(start);
+			continue;
+		}
+		partitions.push(partition(index + 1, kind, start, count));
+	}
+
+	// 扩展分区是扩展引导记录的链：每个记录的第一项是逻辑分区（相对于记录本身），
+	// 第二项指向下一个记录（相对于扩展分区的开头）
+	let Some(base) = extended else {
+		return Ok(partitions);
+	};
+	let (mut record, mut number) = (base, 5);
+	for _ in 0..MAX_LOGICAL {
+		let ebr = read_sector(device, record)?;
+		if ebr[510..512] != [0x55, 0xAA] {
+			return Err(invalid(format!(
+				"the extended boot record at sector {} is damaged",
+				record
+			)));
+		}
+		let (kind, start, count) = mbr_entry(&ebr, 0);
+		if kind != 0 && count != 0 {
+			partitions.push(partition(number, kind, record + start, count));
+			number += 1;
+		}
+		match mbr_entry(&ebr, 1) {
+			(kind, next, _) if EXTENDED.contains(&kind) && next != 0 => record = base + next,
+			_ => return Ok(partitions),
+		}
+	}
+	Err(invalid(format!(
+		"the extended partition has more than {} logical partitions",
+		MAX_LOGICAL
+	)))
+}
+
+// 校验通过的 GPT 头：头本身的 CRC-32 计算时校验和字段按 0 处理，头中记录的位置必须就是读取的位置
+fn gpt_header(device: &dyn BlockDevice, lba: u64) -> io::Result<Option<Vec<u8>>> {
+	let mut header = read_sector(device, lba)?;
+	let size = u32_at(&header, 12) as usize;
+	if &header[..8] != b"EFI PART"
+		|| !(92..=header.len()).contains(&size)
+		|| u64_at(&header, 24) != lba
+	{
+		return Ok(None);
+	}
+	let checksum = u32_at(&header, 16);
+	header[16..20].fill(0);
+	if crc32(GPT_CRC32, &header[..size]) != checksum {
+		return Ok(None);
+	}
+	header[16..20].copy_from_slice(&checksum.to_le_bytes());
+	Ok(Some(header))
+}
+
+// 头所指向的分区项数组，CRC-32 与头中记录的不一致时为 None
+fn gpt_entries(device: &dyn BlockDevice, header: &[u8]) -> io::Result<Option<Vec<u8>>> {
+	let sector_size = device.sector_size() as u64;
+	let (table, count, entry_size) = (
+		u64_at(header, 72),
+		u32_at(header, 80) as u64,
+		u32_at(header, 84) as u64,
+	);
+	if !(128..=4096).contains(&entry_size)
+		|| !entry_size.is_power_of_two()
+		|| count > MAX_GPT_ENTRIES
+	{
+		return Ok(None);
+	}
+	if table
+		.checked_mul(sector_size)
+		.and_then(|offset| offset.checked_add(count * entry_size))
+		.is_none_or(|end| end > device.size())
+	{
+		return Ok(None);
+	}
+	let mut entries = vec![0; (count * entry_size) as usize];
+	device.read_at(table * sector_size, &mut entries)?;
+	Ok((crc32(GPT_CRC32, &entries) == u32_at(header, 88)).then_some(entries))
+}
+
+// GPT 的主头位于第 1 个扇区，备份头位于最后一个扇区；主头或其分区项损坏时使用备份
+fn gpt_partitions(device: &dyn BlockDevice) -> io::Result<Vec<Partition>> {
+	let sector_size = device.sector_size() as u64;
+	let mut found = None;
+	for lba in [1, device.size() / sector_size - 1] {
+		if let Some(header) = gpt_header(device, lba)? {
+			if let Some(entries) = gpt_entries(device, &header)? {
+				found = Some((header, entries));
+				break;
+			}
+		}
+	}
+	let (header, entries) = found.ok_or_else(|| invalid("the GPT and its backup are damaged"))?;
+
+	let mut partitions = Vec::new();
+	for (index, entry) in entries
+		.chunks_exact(u32_at(&header, 84) as usize)
+		.enumerate()
+	{
+		let (first, last) = (u64_at(entry, 32), u64_at(entry, 40));
+		// 分区类型为全 0 的是空项
+		if entry[..16].iter().all(|&b| b == 0) || last < first {
+			continue;
+		}
+		partitions.push(Partition {
+			number: index + 1,
+			offset: first * sector_size,
+			size: (last - first + 1) * sector_size,
+			kind: PartitionType::Gpt(entry[..16].try_into().unwrap()),
+			name: utf16(&entry[56..128]).trim_end_matches('\0').to_string(),
+		});
+	}
+	Ok(partitions)
+}
@@ -0,0 +1,374 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fs::{File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::{Path, MAIN_SEPARATOR_STR},
+	sync::{Arc, Mutex},
+};
+
+use flate2::read::DeflateDecoder;
+
+use super::{convert::ImageWriter, for_each_block, BlockDevice, ImageFile};
+use crate::backend::invalid;
+
+pub const MAGIC: &[u8] = b"QFI\xfb";
+const V2_HEADER_SIZE: usize = 72;
+// 表项中的主机偏移，以及标准 L2 项中表示整簇为 0 的位
+const OFFSET_MASK: u64 = 0x00FF_FFFF_FFFF_FE00;
+const COMPRESSED: u64 = 1 << 62;
+const ZERO: u64 = 1;
+// L1 和 L2 项中表示簇只被引用一次、可以直接改写的位
+const COPIED: u64 = 1 << 63;
+// 不兼容特性位：外部数据文件和扩展 L2 项不支持，其余（脏、损坏、压缩方式）不影响只读
+const INCOMPATIBLE_EXTERNAL_DATA: u64 = 1 << 2;
+const INCOMPATIBLE_COMPRESSION_TYPE: u64 = 1 << 3;
+const INCOMPATIBLE_EXTENDED_L2: u64 = 1 << 4;
+const KNOWN_INCOMPATIBLE: u64 = 0x1F;
+// 缓存的 L2 表数，以及 L1 表的大小上限
+const CACHED_TABLES: usize = 16;
+const MAX_L1_ENTRIES: u64 = 32 * 1024 * 1024;
+pub const DEFAULT_CLUSTER_SIZE: u64 = 64 * 1024;
+
+fn be32(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+	u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// QCOW2 映像：L1 表指向 L2 表，L2 表的每一项给出一个簇在文件中的位置；簇可以整簇为 0、
+// 以 deflate 或 zstd 压缩，或者没有分配而从后备文件读取（没有后备文件时为 0）。
+// 只读取当前状态，内部快照被忽略
+pub struct Qcow2 {
+	file: ImageFile,
+	size: u64,
+	cluster_bits: u32,
+	l1: Vec<u64>,
+	zstd: bool,
+	backing: Option<Arc<dyn BlockDevice>>,
+	// 最近使用的 L2 表和最近解压的簇
+	tables: Mutex<VecDeque<(u64, Arc<Vec<u64>>)>>,
+	decompressed: Mutex<Option<(u64, Arc<Vec<u8>>)>>,
+}
+
+impl Qcow2 {
+	pub fn open(file: ImageFile, path: &Path, depth: usize) -> io::Result<Self> {
+		let header = file.read(0, V2_HEADER_SIZE.min(file.size() as usize))?;
+		if header.len() < V2_HEADER_SIZE || &header[..4] != MAGIC {
+			return Err(invalid("the QCOW2 header is damaged"));
+		}
+		let version = be32(&header, 4);
+		let (cluster_bits, size) = (be32(&header, 20), be64(&header, 24));
+		let (l1_size, l1_offset) = (be32(&header, 36) as u64, be64(&header, 40));
+		if !matches!(version, 2 | 3) {
+			return Err(invalid(format!("unsupported QCOW2 version {}", version)));
+		}
+		if !(9..=21).contains(&cluster_bits) {
+			return Err(invalid(format!(
+				"invalid QCOW2 cluster size 2^{}",
+				cluster_bits
+			)));
+		}
+		if be32(&header, 32) != 0 {
+			return Err(io::Error::new(
+				io::ErrorKind::Unsupported,
+				"encrypted QCOW2 images are not supported",
+			));
+		}
+
+		let mut zstd = false;
+		if version == 3 {
+			let extended = file.read(V2_HEADER_SIZE as u64, 32)?;
+			let incompatible = be64(&extended, 0);
+			if incompatible & (INCOMPATIBLE_EXTERNAL_DATA | INCOMPATIBLE_EXTENDED_L2) != 0
+				|| incompatible & !KNOWN_INCOMPATIBLE != 0
+			{
+				return Err(io::Error::new(
+					io::ErrorKind::Unsupported,
+					format!("unsupported QCOW2 features {:#x}", incompatible),
+				));
+			}
+			// 头长度超过 104 字节时第 104 字节是压缩方式：0 为 deflate，1 为 zstd
+			if incompatible & INCOMPATIBLE_COMPRESSION_TYPE != 0 && be32(&extended, 28) > 104 {
+				zstd = file.read(104, 1)?[0] == 1;
+			}
+		}
+
+		// 每个 L2 表占一簇，覆盖 2^(cluster_bits - 3) 个簇
+		let covered = 1u64 << (cluster_bits * 2 - 3);
+		if l1_size > MAX_L1_ENTRIES || l1_size.saturating_mul(covered) < size {
+			return Err(invalid("the QCOW2 L1 table does not cover the disk"));
+		}
+		let l1 = file
+			.read(l1_offset, (l1_size * 8) as usize)?
+			.chunks_exact(8)
+			.map(|entry| be64(entry, 0) & OFFSET_MASK)
+			.collect();
+
+		let (backing_offset, backing_size) = (be64(&header, 8), be32(&header, 16) as usize);
+		let backing = match backing_offset {
+			0 => None,
+			_ if backing_size == 0 || backing_size > 1023 => {
+				return Err(invalid("the QCOW2 backing file name is damaged"))
+			}
+			_ => {
+				// 相对路径相对于映像所在的目录
+				let name = String::from_utf8_lossy(&file.read(backing_offset, backing_size)?)
+					.replace('\\', MAIN_SEPARATOR_STR);
+				let backing_path = path.parent().unwrap_or(Path::new("")).join(name);
+				Some(
+					file.open_sibling(&backing_path)
+						.and_then(|backing| super::open_layer(backing, &backing_path, depth + 1))
+						.map_err(|e| {
+							io::Error::new(
+								e.kind(),
+								format!("backing file {}: {}", backing_path.display(), e),
+							)
+						})?,
+				)
+			}
+		};
+		Ok(Self {
+			file,
+			size,
+			cluster_bits,
+			l1,
+			zstd,
+			backing,
+			tables: Mutex::new(VecDeque::new()),
+			decompressed: Mutex::new(None),
+		})
+	}
+
+	fn cluster_size(&self) -> u64 {
+		1 << self.cluster_bits
+	}
+
+	fn table(&self, offset: u64) -> io::Result<Arc<Vec<u64>>> {
+		if let Some((_, table)) = self
+			.tables
+			.lock()
+			.unwrap()
+			.iter()
+			.find(|(cached, _)| *cached == offset)
+		{
+			return Ok(table.clone());
+		}
+		let table = Arc::new(
+			self.file
+				.read(offset, self.cluster_size() as usize)?
+				.chunks_exact(8)
+				.map(|entry| be64(entry, 0))
+				.collect::<Vec<_>>(),
+		);
+		let mut tables = self.tables.lock().unwrap();
+		if tables.len() >= CACHED_TABLES {
+			tables.pop_front();
+		}
+		tables.push_back((offset, table.clone()));
+		Ok(table)
+	}
+
+	// 簇在 L2 表中的项；L1 或 L2 项为 0 表示没有分配
+	fn l2_entry(&self, position: u64) -> io::Result<u64> {
+		let l2_bits = self.cluster_bits - 3;
+		let cluster = position >> self.cluster_bits;
+		let table_offset = match self.l1.get((cluster >> l2_bits) as usize) {
+			Some(&offset) if offset != 0 => offset,
+			_ => return Ok(0),
+		};
+		Ok(self.table(table_offset)?[(cluster & ((1 << l2_bits) - 1)) as usize])
+	}
+
+	// 压缩簇的项：低位是数据在文件中的位置，其上是数据跨越的 512 字节扇区数减一
+	fn decompress(&self, entry: u64) -> io::Result<Arc<Vec<u8>>> {
+		if let Some((cached, data)) = &*self.decompressed.lock().unwrap() {
+			if *cached == entry {
+				return Ok(data.clone());
+			}
+		}
+		let offset_bits = 62 - (self.cluster_bits - 8);
+		let offset = entry & ((1 << offset_bits) - 1);
+		let sectors = ((entry & !COMPRESSED) >> offset_bits) + 1;
+		let length = (sectors * 512 - (offset & 511)).min(self.file.size().saturating_sub(offset));
+		let compressed = self.file.read(offset, length as usize)?;
+		let mut data = vec![0; self.cluster_size() as usize];
+		let result = if self.zstd {
+			zstd::stream::read::Decoder::with_buffer(&compressed[..])
+				.and_then(|decoder| decoder.single_frame().read_exact(&mut data))
+		} else {
+			DeflateDecoder::new(&compressed[..]).read_exact(&mut data)
+		};
+		result.map_err(|e| invalid(format!("a compressed QCOW2 cluster is damaged: {}", e)))?;
+		let data = Arc::new(data);
+		*self.decompressed.lock().unwrap() = Some((entry, data.clone()));
+		Ok(data)
+	}
+
+	// 没有分配的簇从后备文件读取，后备文件比本映像小时超出的部分为 0
+	fn read_backing(&self, position: u64, buf: &mut [u8]) -> io::Result<()> {
+		let available = match &self.backing {
+			Some(backing) if position < backing.size() => {
+				let available = (backing.size() - position).min(buf.len() as u64) as usize;
+				backing.read_at(position, &mut buf[..available])?;
+				available
+			}
+			_ => 0,
+		};
+		buf[available..].fill(0);
+		Ok(())
+	}
+}
+
+impl BlockDevice for Qcow2 {
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		super::check_range(offset, buf.len(), self.size)?;
+		for_each_block(offset, buf, self.cluster_size(), |position, part| {
+			let entry = self.l2_entry(position)?;
+			let within = (position % self.cluster_size()) as usize;
+			if entry & COMPRESSED != 0 {
+				let data = self.decompress(entry)?;
+				part.copy_from_slice(&data[within..within + part.len()]);
+				return Ok(());
+			}
+			match entry & OFFSET_MASK {
+				_ if entry & ZERO != 0 => {
+					part.fill(0);
+					Ok(())
+				}
+				0 => self.read_backing(position, part),
+				host => self.file.read_at(host + within as u64, part),
+			}
+		})
+	}
+}
+
+// 转换时按顺序写入新的 QCOW2（版本 2）：第 0 簇是头，数据簇依次追加在其后；L2 表在内存中，
+// 最后依次写出 L2 表、L1 表、引用计数表和引用计数块，再写入头。文件中的每一簇都只被引用一次
+pub struct Writer {
+	file: File,
+	size: u64,
+	cluster_bits: u32,
+	// 文件中已使用的簇数
+	clusters: u64,
+	// 有数据的 L2 表，键为在 L1 表中的序号
+	tables: BTreeMap<u64, Vec<u64>>,
+}
+
+impl Writer {
+	pub fn create(path: &Path, size: u64, cluster_size: u64) -> io::Result<Self> {
+		if !cluster_size.is_power_of_two() || !(512..=2 * 1024 * 1024).contains(&cluster_size) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"the QCOW2 cluster size must be a power of two between 512 bytes and 2 MiB",
+			));
+		}
+		let cluster_bits = cluster_size.trailing_zeros();
+		if size == 0 || size.div_ceil(1 << (cluster_bits * 2 - 3)) > MAX_L1_ENTRIES {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"the disk is empty or too large for the QCOW2 cluster size",
+			));
+		}
+		let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+		file.set_len(cluster_size)?;
+		Ok(Self {
+			file,
+			size,
+			cluster_bits,
+			clusters: 1,
+			tables: BTreeMap::new(),
+		})
+	}
+
+	fn cluster_size(&self) -> u64 {
+		1 << self.cluster_bits
+	}
+
+	// 在文件末尾写入 data，返回起始位置
+	fn append(&mut self, data: &[u8]) -> io::Result<u64> {
+		let offset = self.clusters << self.cluster_bits;
+		self.file.seek(SeekFrom::Start(offset))?;
+		self.file.write_all(data)?;
+		self.clusters += (data.len() as u64).div_ceil(self.cluster_size());
+		Ok(offset)
+	}
+}
+
+fn be_bytes(entries: &[u64]) -> Vec<u8> {
+	entries
+		.iter()
+		.flat_map(|entry| entry.to_be_bytes())
+		.collect()
+}
+
+impl ImageWriter for Writer {
+	fn chunk_size(&self) -> u64 {
+		self.cluster_size()
+	}
+
+	fn write_chunk(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+		let host = self.append(data)?;
+		let (cluster, l2_bits) = (offset >> self.cluster_bits, self.cluster_bits - 3);
+		let entries = 1 << l2_bits;
+		let table = self
+			.tables
+			.entry(cluster >> l2_bits)
+			.or_insert_with(|| vec![0; entries]);
+		table[(cluster & (entries as u64 - 1)) as usize] = host | COPIED;
+		Ok(())
+	}
+
+	fn finish(mut self: Box<Self>) -> io::Result<()> {
+		let cluster_size = self.cluster_size();
+		let mut l1 = vec![0; self.size.div_ceil(1 << (self.cluster_bits * 2 - 3)) as usize];
+		for (index, table) in std::mem::take(&mut self.tables) {
+			l1[index as usize] = self.append(&be_bytes(&table))? | COPIED;
+		}
+		let l1_offset = self.append(&be_bytes(&l1))?;
+		// 引用计数为 16 位，引用计数表和引用计数块本身也要计数，反复计算直到它们的簇数不再变化
+		let per_block = cluster_size / 2;
+		let (mut blocks, mut table_clusters) = (0, 0);
+		loop {
+			let total = self.clusters + table_clusters + blocks;
+			let needed = total.div_ceil(per_block);
+			let needed_table = (needed * 8).div_ceil(cluster_size);
+			if (needed, needed_table) == (blocks, table_clusters) {
+				break;
+			}
+			(blocks, table_clusters) = (needed, needed_table);
+		}
+		let total = self.clusters + table_clusters + blocks;
+		let table_offset = self.clusters << self.cluster_bits;
+		let first_block = self.clusters + table_clusters;
+		let mut table = vec![0; (table_clusters * cluster_size / 8) as usize];
+		for (index, entry) in table.iter_mut().take(blocks as usize).enumerate() {
+			*entry = (first_block + index as u64) << self.cluster_bits;
+		}
+		self.append(&be_bytes(&table))?;
+		let mut refcounts = vec![0u8; (blocks * cluster_size) as usize];
+		for count in refcounts.chunks_exact_mut(2).take(total as usize) {
+			count.copy_from_slice(&1u16.to_be_bytes());
+		}
+		self.append(&refcounts)?;
+		self.file.set_len(total << self.cluster_bits)?;
+
+		let mut header = vec![0; V2_HEADER_SIZE];
+		header[..4].copy_from_slice(MAGIC);
+		header[4..8].copy_from_slice(&2u32.to_be_bytes());
+		header[20..24].copy_from_slice(&self.cluster_bits.to_be_bytes());
+		header[24..32].copy_from_slice(&self.size.to_be_bytes());
+		header[36..40].copy_from_slice(&(l1.len() as u32).to_be_bytes());
+		header[40..48].copy_from_slice(&l1_offset.to_be_bytes());
+		header[48..56].copy_from_slice(&table_offset.to_be_bytes());
+		header[56..60].copy_from_slice(&(table_clusters as u32).to_be_bytes());
+		self.file.seek(SeekFrom::Start(0))?;
+		self.file.write_all(&header)?;
+		self.file.sync_all()
+	}
+}
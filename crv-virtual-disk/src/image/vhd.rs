@@ -0,0 +1,406 @@
+use std::{
+	fs::{File, OpenOptions},
+	io::{self, Seek, SeekFrom, Write},
+	path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use super::{
+	convert::ImageWriter, for_each_block, for_each_run, random_guid, BlockDevice, ImageFile,
+	MAX_PARENTS,
+};
+use crate::backend::invalid;
+
+const SECTOR: u64 = 512;
+const FOOTER_SIZE: usize = 512;
+const DYNAMIC_HEADER_SIZE: usize = 1024;
+// 块分配表中未分配的块
+const UNALLOCATED: u32 = u32::MAX;
+// 块的大小上限，规范默认 2 MiB
+const MAX_BLOCK_SIZE: u64 = 256 * 1024 * 1024;
+pub const DEFAULT_BLOCK_SIZE: u64 = 2 * 1024 * 1024;
+// 页脚中的 CHS 几何能表示的最大扇区数，约 127 GiB 以上的磁盘几何只是近似值，大小以页脚中的字段为准
+const MAX_GEOMETRY_SECTORS: u64 = 65535 * 16 * 255;
+// VHD 时间戳的起点 2000-01-01 UTC
+const VHD_EPOCH: Duration = Duration::from_secs(946_684_800);
+
+const FIXED: u32 = 2;
+const DYNAMIC: u32 = 3;
+const DIFFERENCING: u32 = 4;
+
+// VHD 的字段都是大端
+fn be32(data: &[u8], offset: usize) -> u32 {
+	u32::from_be_bytes(data[offset..offset + 4].try_into().unwrap())
+}
+
+fn be64(data: &[u8], offset: usize) -> u64 {
+	u64::from_be_bytes(data[offset..offset + 8].try_into().unwrap())
+}
+
+// 页脚和动态磁盘头的校验和：除校验和字段外所有字节之和取反
+fn checksum(data: &[u8], field: usize) -> u32 {
+	let sum = data
+		.iter()
+		.enumerate()
+		.filter(|(index, _)| !(field..field + 4).contains(index))
+		.fold(0u32, |sum, (_, &byte)| sum.wrapping_add(byte as u32));
+	!sum
+}
+
+fn valid_footer(data: &[u8]) -> bool {
+	&data[..8] == b"conectix" && be32(data, 64) == checksum(data, 64)
+}
+
+// 映像的页脚：位于文件末尾，动态磁盘在开头另有一份副本，末尾的损坏时使用开头的
+pub fn footer(file: &ImageFile) -> io::Result<Option<Vec<u8>>> {
+	if file.size() < FOOTER_SIZE as u64 {
+		return Ok(None);
+	}
+	let last = file.read(file.size() - FOOTER_SIZE as u64, FOOTER_SIZE)?;
+	if valid_footer(&last) {
+		return Ok(Some(last));
+	}
+	let first = file.read(0, FOOTER_SIZE)?;
+	Ok((valid_footer(&first) && be32(&first, 60) != FIXED).then_some(first))
+}
+
+// 动态和差异磁盘的块：块分配表给出块在文件中的扇区，块以扇区位图开头，其后是数据
+struct Blocks {
+	block_size: u64,
+	bitmap_size: u64,
+	table: Vec<u32>,
+	// 差异磁盘中位图为 0 的扇区从父磁盘读取
+	parent: Option<Arc<dyn BlockDevice>>,
+}
+
+// VHD 映像：固定磁盘的数据就是文件开头的部分，动态磁盘和差异磁盘按块分配
+pub struct Vhd {
+	file: ImageFile,
+	size: u64,
+	unique_id: [u8; 16],
+	blocks: Option<Blocks>,
+}
+
+impl Vhd {
+	pub fn open(file: ImageFile, footer: Vec<u8>, path: &Path, depth: usize) -> io::Result<Self> {
+		let size = be64(&footer, 48);
+		let unique_id = footer[68..84].try_into().unwrap();
+		let blocks = match be32(&footer, 60) {
+			FIXED if size > file.size() - FOOTER_SIZE as u64 => {
+				return Err(invalid("the fixed VHD is shorter than its disk size"))
+			}
+			FIXED => None,
+			kind @ (DYNAMIC | DIFFERENCING) => {
+				Some(Self::read_blocks(&file, &footer, size, kind, path, depth)?)
+			}
+			kind => return Err(invalid(format!("unsupported VHD disk type {}", kind))),
+		};
+		Ok(Self {
+			file,
+			size,
+			unique_id,
+			blocks,
+		})
+	}
+
+	fn read_blocks(
+		file: &ImageFile,
+		footer: &[u8],
+		size: u64,
+		kind: u32,
+		path: &Path,
+		depth: usize,
+	) -> io::Result<Blocks> {
+		let header = file.read(be64(footer, 16), DYNAMIC_HEADER_SIZE)?;
+		if &header[..8] != b"cxsparse" || be32(&header, 36) != checksum(&header, 36) {
+			return Err(invalid("the VHD dynamic disk header is damaged"));
+		}
+		let (table_offset, entries, block_size) = (
+			be64(&header, 16),
+			be32(&header, 28) as u64,
+			be32(&header, 32) as u64,
+		);
+		if block_size == 0
+			|| block_size % SECTOR != 0
+			|| block_size > MAX_BLOCK_SIZE
+			|| entries * block_size < size
+		{
+			return Err(invalid(
+				"the VHD block allocation table does not cover the disk",
+			));
+		}
+		if table_offset
+			.checked_add(entries * 4)
+			.is_none_or(|end| end > file.size())
+		{
+			return Err(invalid(
+				"the VHD block allocation table lies outside the file",
+			));
+		}
+		let table = file
+			.read(table_offset, (entries * 4) as usize)?
+			.chunks_exact(4)
+			.map(|entry| be32(entry, 0))
+			.collect();
+		let parent = match kind {
+			DIFFERENCING => Some(Self::open_parent(&header, file, path, depth)?),
+			_ => None,
+		};
+		Ok(Blocks {
+			block_size,
+			// 每个扇区一位，按扇区对齐
+			bitmap_size: (block_size / SECTOR).div_ceil(8).next_multiple_of(SECTOR),
+			table,
+			parent,
+		})
+	}
+
+	// 差异磁盘的父磁盘位置记录在动态磁盘头的定位项中：W2ru 为相对路径，W2ku 为绝对路径，都是 UTF-16LE。
+	// 父磁盘的唯一 ID 必须与创建差异磁盘时记录的一致
+	fn open_parent(
+		header: &[u8],
+		file: &ImageFile,
+		path: &Path,
+		depth: usize,
+	) -> io::Result<Arc<dyn BlockDevice>> {
+		if depth >= MAX_PARENTS {
+			return Err(invalid(format!(
+				"{} is more than {} parents deep",
+				path.display(),
+				MAX_PARENTS
+			)));
+		}
+		let mut candidates = Vec::new();
+		for entry in header[576..576 + 8 * 24].chunks_exact(24) {
+			let (code, length, offset) = (&entry[..4], be32(entry, 8) as usize, be64(entry, 16));
+			if !matches!(code, b"W2ru" | b"W2ku") || length == 0 || length > 64 * 1024 {
+				continue;
+			}
+			let data = file.read(offset, length)?;
+			let name = String::from_utf16_lossy(
+				&data
+					.chunks_exact(2)
+					.map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+					.collect::<Vec<_>>(),
+			);
+			let name = name
+				.trim_end_matches('\0')
+				.replace('\\', MAIN_SEPARATOR_STR);
+			candidates.push(match code {
+				b"W2ru" => path.parent().unwrap_or(Path::new("")).join(name),
+				_ => PathBuf::from(name),
+			});
+		}
+		let (parent_path, parent_file) = candidates
+			.into_iter()
+			.find_map(|candidate| {
+				file.open_sibling(&candidate)
+					.ok()
+					.map(|parent| (candidate, parent))
+			})
+			.ok_or_else(|| invalid(format!("cannot find the parent disk of {}", path.display())))?;
+		let parent_footer = footer(&parent_file)?.ok_or_else(|| {
+			invalid(format!(
+				"the parent disk {} is not a VHD",
+				parent_path.display()
+			))
+		})?;
+		let parent = Self::open(parent_file, parent_footer, &parent_path, depth + 1)?;
+		if parent.unique_id != header[40..56] {
+			return Err(invalid(format!(
+				"the parent disk {} has changed since {} was created",
+				parent_path.display(),
+				path.display()
+			)));
+		}
+		Ok(Arc::new(parent))
+	}
+
+	fn read_missing(blocks: &Blocks, position: u64, buf: &mut [u8]) -> io::Result<()> {
+		match &blocks.parent {
+			Some(parent) => parent.read_at(position, buf),
+			None => {
+				buf.fill(0);
+				Ok(())
+			}
+		}
+	}
+}
+
+impl BlockDevice for Vhd {
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		super::check_range(offset, buf.len(), self.size)?;
+		let Some(blocks) = &self.blocks else {
+			return self.file.read_at(offset, buf);
+		};
+		for_each_block(offset, buf, blocks.block_size, |position, part| {
+			let block = position / blocks.block_size;
+			let start = match blocks.table[block as usize] {
+				UNALLOCATED => return Self::read_missing(blocks, position, part),
+				sector => sector as u64 * SECTOR,
+			};
+			// 只读取覆盖这一段的位图字节，最高位对应第一个扇区
+			let first_sector = block * blocks.block_size / SECTOR;
+			let (first, last) = (
+				position / SECTOR - first_sector,
+				(position + part.len() as u64 - 1) / SECTOR - first_sector,
+			);
+			let bitmap = self
+				.file
+				.read(start + first / 8, (last / 8 - first / 8 + 1) as usize)?;
+			let present = |sector: u64| {
+				let index = sector - first_sector;
+				bitmap[(index / 8 - first / 8) as usize] & (0x80 >> (index % 8)) != 0
+			};
+			let (data, block_start) = (start + blocks.bitmap_size, block * blocks.block_size);
+			for_each_run(
+				position,
+				part,
+				SECTOR,
+				present,
+				|present, position, run| match present {
+					true => self.file.read_at(data + position - block_start, run),
+					false => Self::read_missing(blocks, position, run),
+				},
+			)
+		})
+	}
+}
+
+// 页脚中的 CHS 几何，按 VHD 规范附录中的算法由扇区数计算
+fn geometry(size: u64) -> (u16, u8, u8) {
+	let total = (size / SECTOR).min(MAX_GEOMETRY_SECTORS);
+	let (sectors, heads) = match total {
+		_ if total >= 65535 * 16 * 63 => (255, 16),
+		_ => {
+			let heads = (total / 17).div_ceil(1024).max(4);
+			match () {
+				_ if total / 17 < heads * 1024 && heads <= 16 => (17, heads),
+				_ if total / 31 < 16 * 1024 => (31, 16),
+				_ => (63, 16),
+			}
+		}
+	};
+	((total / sectors / heads) as u16, heads as u8, sectors as u8)
+}
+
+// 转换时按顺序写入新的动态 VHD：文件开头是页脚的副本、动态磁盘头和块分配表，块依次追加在其后，
+// 每块以全为 1 的扇区位图开头；块分配表在内存中，最后与文件末尾的页脚一起写出
+pub struct Writer {
+	file: File,
+	block_size: u64,
+	table: Vec<u32>,
+	footer: Vec<u8>,
+	// 下一块在文件中的位置
+	next: u64,
+}
+
+impl Writer {
+	pub fn create(path: &Path, size: u64, block_size: u64) -> io::Result<Self> {
+		if size == 0 || !size.is_multiple_of(SECTOR) || size / SECTOR > MAX_GEOMETRY_SECTORS {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"the size of a VHD must be a multiple of 512 bytes and at most 2040 GiB",
+			));
+		}
+		if !block_size.is_power_of_two() || !(512 * 1024..=MAX_BLOCK_SIZE).contains(&block_size) {
+			return Err(io::Error::new(
+				io::ErrorKind::InvalidInput,
+				"the VHD block size must be a power of two between 512 KiB and 256 MiB",
+			));
+		}
+		let entries = size.div_ceil(block_size);
+		let table_offset = (FOOTER_SIZE + DYNAMIC_HEADER_SIZE) as u64;
+		let mut footer = vec![0; FOOTER_SIZE];
+		footer[..8].copy_from_slice(b"conectix");
+		footer[8..12].copy_from_slice(&2u32.to_be_bytes());
+		footer[12..16].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+		footer[16..24].copy_from_slice(&(FOOTER_SIZE as u64).to_be_bytes());
+		let created = SystemTime::now()
+			.duration_since(UNIX_EPOCH + VHD_EPOCH)
+			.unwrap_or_default()
+			.as_secs() as u32;
+		footer[24..28].copy_from_slice(&created.to_be_bytes());
+		footer[28..32].copy_from_slice(b"hfs ");
+		footer[32..36].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+		footer[36..40].copy_from_slice(b"Wi2k");
+		footer[40..48].copy_from_slice(&size.to_be_bytes());
+		footer[48..56].copy_from_slice(&size.to_be_bytes());
+		let (cylinders, heads, sectors) = geometry(size);
+		footer[56..58].copy_from_slice(&cylinders.to_be_bytes());
+		footer[58] = heads;
+		footer[59] = sectors;
+		footer[60..64].copy_from_slice(&DYNAMIC.to_be_bytes());
+		footer[68..84].copy_from_slice(&random_guid());
+		let sum = checksum(&footer, 64);
+		footer[64..68].copy_from_slice(&sum.to_be_bytes());
+		let mut header = vec![0; DYNAMIC_HEADER_SIZE];
+		header[..8].copy_from_slice(b"cxsparse");
+		header[8..16].copy_from_slice(&u64::MAX.to_be_bytes());
+		header[16..24].copy_from_slice(&table_offset.to_be_bytes());
+		header[24..28].copy_from_slice(&0x0001_0000u32.to_be_bytes());
+		header[28..32].copy_from_slice(&(entries as u32).to_be_bytes());
+		header[32..36].copy_from_slice(&(block_size as u32).to_be_bytes());
+		let sum = checksum(&header, 36);
+		header[36..40].copy_from_slice(&sum.to_be_bytes());
+		let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+		file.write_all(&footer)?;
+		file.write_all(&header)?;
+		Ok(Self {
+			file,
+			block_size,
+			table: vec![UNALLOCATED; entries as usize],
+			footer,
+			next: table_offset + (entries * 4).next_multiple_of(SECTOR),
+		})
+	}
+
+	fn bitmap_size(&self) -> u64 {
+		(self.block_size / SECTOR)
+			.div_ceil(8)
+			.next_multiple_of(SECTOR)
+	}
+}
+
+impl ImageWriter for Writer {
+	fn chunk_size(&self) -> u64 {
+		self.block_size
+	}
+
+	// 磁盘末尾不足一块时补足为整块
+	fn write_chunk(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+		self.file.seek(SeekFrom::Start(self.next))?;
+		self.file
+			.write_all(&vec![0xFF; self.bitmap_size() as usize])?;
+		self.file.write_all(data)?;
+		self.file
+			.write_all(&vec![0; (self.block_size - data.len() as u64) as usize])?;
+		self.table[(offset / self.block_size) as usize] = (self.next / SECTOR) as u32;
+		self.next += self.bitmap_size() + self.block_size;
+		Ok(())
+	}
+
+	fn finish(mut self: Box<Self>) -> io::Result<()> {
+		let table: Vec<u8> = self
+			.table
+			.iter()
+			.flat_map(|entry| entry.to_be_bytes())
+			.collect();
+		self.file
+			.seek(SeekFrom::Start((FOOTER_SIZE + DYNAMIC_HEADER_SIZE) as u64))?;
+		self.file.write_all(&table)?;
+		self.file.write_all(&vec![
+			0xFF;
+			(table.len() as u64).next_multiple_of(SECTOR) as usize
+				- table.len()
+		])?;
+		self.file.seek(SeekFrom::Start(self.next))?;
+		self.file.write_all(&self.footer)?;
+		self.file.sync_all()
+	}
+}
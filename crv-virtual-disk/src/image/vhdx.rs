@@ -0,0 +1,774 @@
+use std::{
+	fs::OpenOptions,
+	io::{self, Write},
+	path::{Path, PathBuf, MAIN_SEPARATOR_STR},
+	sync::Arc,
+};
+
+use super::{
+	convert::ImageWriter, crc32, for_each_block, for_each_run, guid, guid_string, random_guid,
+	utf16, BlockDevice, ImageFile, MAX_PARENTS,
+};
+use crate::backend::{invalid, u16_at, u32_at, u64_at};
+
+const KIB: u64 = 1024;
+const MIB: u64 = 1024 * KIB;
+// 文件开头依次是文件类型标识、两份头、两份区域表
+const HEADERS: [u64; 2] = [64 * KIB, 128 * KIB];
+const HEADER_SIZE: usize = 4 * KIB as usize;
+const REGION_TABLES: [u64; 2] = [192 * KIB, 256 * KIB];
+const REGION_TABLE_SIZE: usize = 64 * KIB as usize;
+// 元数据表和块分配表的大小上限
+const MAX_REGION_SIZE: u64 = 256 * MIB;
+// 一个扇区位图块覆盖的扇区数
+const SECTORS_PER_BITMAP: u64 = 1 << 23;
+// 虚拟磁盘大小的上限 64 TiB
+const MAX_SIZE: u64 = 64 * MIB * MIB;
+// 新建的映像中日志、元数据表和块分配表的位置；元数据项从元数据表的 64 KiB 处开始
+const LOG_OFFSET: u64 = MIB;
+const METADATA_OFFSET: u64 = 2 * MIB;
+const METADATA_ITEMS: usize = 64 * KIB as usize;
+const BAT_OFFSET: u64 = 3 * MIB;
+// 新建映像默认的块大小，与 Hyper-V 相同
+pub const DEFAULT_BLOCK_SIZE: u64 = 32 * MIB;
+
+// 块分配表项的状态，低 3 位
+const PAYLOAD_NOT_PRESENT: u64 = 0;
+const PAYLOAD_UNDEFINED: u64 = 1;
+const PAYLOAD_ZERO: u64 = 2;
+const PAYLOAD_UNMAPPED: u64 = 3;
+const PAYLOAD_FULLY_PRESENT: u64 = 6;
+const PAYLOAD_PARTIALLY_PRESENT: u64 = 7;
+const SECTOR_BITMAP_PRESENT: u64 = 6;
+
+// VHDX 的头、区域表和元数据都以 CRC-32C 校验
+const CRC32C: u32 = 0x82F6_3B78;
+
+const BAT_REGION: [u8; 16] = guid(
+	0x2DC27766,
+	0xF623,
+	0x4200,
+	[0x9D, 0x64, 0x11, 0x5E, 0x9B, 0xFD, 0x4A, 0x08],
+);
+const METADATA_REGION: [u8; 16] = guid(
+	0x8B7CA206,
+	0x4790,
+	0x4B9A,
+	[0xB8, 0xFE, 0x57, 0x5F, 0x05, 0x0F, 0x88, 0x6E],
+);
+const FILE_PARAMETERS: [u8; 16] = guid(
+	0xCAA16737,
+	0xFA36,
+	0x4D43,
+	[0xB3, 0xB6, 0x33, 0xF0, 0xAA, 0x44, 0xE7, 0x6B],
+);
+const VIRTUAL_DISK_SIZE: [u8; 16] = guid(
+	0x2FA54224,
+	0xCD1B,
+	0x4876,
+	[0xB2, 0x11, 0x5D, 0xBE, 0xD8, 0x3B, 0xF4, 0xB8],
+);
+const VIRTUAL_DISK_ID: [u8; 16] = guid(
+	0xBECA12AB,
+	0xB2E6,
+	0x4523,
+	[0x93, 0xEF, 0xC3, 0x09, 0xE0, 0x00, 0xC7, 0x46],
+);
+const LOGICAL_SECTOR_SIZE: [u8; 16] = guid(
+	0x8141BF1D,
+	0xA96F,
+	0x4709,
+	[0xBA, 0x47, 0xF2, 0x33, 0xA8, 0xFA, 0xAB, 0x5F],
+);
+const PHYSICAL_SECTOR_SIZE: [u8; 16] = guid(
+	0xCDA348C7,
+	0x445D,
+	0x4471,
+	[0x9C, 0xC9, 0xE9, 0x88, 0x52, 0x51, 0xC5, 0x56],
+);
+const PARENT_LOCATOR: [u8; 16] = guid(
+	0xA8D35F2D,
+	0xB30B,
+	0x454D,
+	[0xAB, 0xF7, 0xD3, 0xD8, 0x48, 0x34, 0xAB, 0x0C],
+);
+const VHDX_PARENT_LOCATOR: [u8; 16] = guid(
+	0xB04AEFB7,
+	0xD19E,
+	0x4A81,
+	[0xB7, 0x89, 0x25, 0xB8, 0xE9, 0x44, 0x59, 0x13],
+);
+
+// 校验和位于第 4 字节，计算时按 0 处理
+fn checksum_matches(data: &[u8]) -> bool {
+	let mut copy = data.to_vec();
+	copy[4..8].fill(0);
+	crc32(CRC32C, &copy) == u32_at(data, 4)
+}
+
+fn set_checksum(data: &mut [u8]) {
+	data[4..8].fill(0);
+	let checksum = crc32(CRC32C, data);
+	data[4..8].copy_from_slice(&checksum.to_le_bytes());
+}
+
+pub fn is_vhdx(file: &ImageFile) -> io::Result<bool> {
+	if file.size() < (REGION_TABLES[1] + REGION_TABLE_SIZE as u64) {
+		return Ok(false);
+	}
+	Ok(file.read(0, 8)? == b"vhdxfile")
+}
+
+// 打开和修改映像需要的头、区域表和元数据
+struct Layout {
+	// 当前的头（序号较大的一份）和它所在的位置
+	header: Vec<u8>,
+	header_offset: u64,
+	regions: Vec<u8>,
+	// 块分配表的位置和长度
+	bat: (u64, u64),
+	// 虚拟磁盘大小在文件中的位置
+	size_offset: u64,
+	size: u64,
+	sector_size: u32,
+	block_size: u64,
+	// 固定大小的映像所有块都已分配
+	leave_allocated: bool,
+	has_parent: bool,
+	locator: Option<Vec<u8>>,
+}
+
+impl Layout {
+	// 每个扇区位图块覆盖的数据块数
+	fn chunk_ratio(&self) -> u64 {
+		SECTORS_PER_BITMAP * self.sector_size as u64 / self.block_size
+	}
+
+	fn entries(&self, size: u64) -> u64 {
+		entries(size, self.block_size, self.chunk_ratio(), self.has_parent)
+	}
+
+	// 块 block 在块分配表中的项
+	fn entry(&self, block: u64) -> u64 {
+		block + block / self.chunk_ratio()
+	}
+}
+
+// 大小为 size 的磁盘的块分配表项数；没有父磁盘时最后一组数据块之后可以没有位图块的项
+fn entries(size: u64, block_size: u64, chunk_ratio: u64, has_parent: bool) -> u64 {
+	let blocks = size.div_ceil(block_size);
+	match has_parent {
+		true => blocks.div_ceil(chunk_ratio) * (chunk_ratio + 1),
+		false => blocks + blocks.saturating_sub(1) / chunk_ratio,
+	}
+}
+
+// 不回放日志：日志中有未写入的更新时拒绝打开
+fn read_layout(file: &ImageFile) -> io::Result<Layout> {
+	// 两份头中取校验正确且序号较大的一份
+	let mut header: Option<(u64, Vec<u8>)> = None;
+	for offset in HEADERS {
+		let data = file.read(offset, HEADER_SIZE)?;
+		if &data[..4] == b"head"
+			&& checksum_matches(&data)
+			&& header
+				.as_ref()
+				.is_none_or(|(_, current)| u64_at(&data, 8) > u64_at(current, 8))
+		{
+			header = Some((offset, data));
+		}
+	}
+	let (header_offset, header) = header.ok_or_else(|| invalid("both VHDX headers are damaged"))?;
+	if u16_at(&header, 66) != 1 {
+		return Err(invalid(format!(
+			"unsupported VHDX version {}",
+			u16_at(&header, 66)
+		)));
+	}
+	if header[48..64].iter().any(|&b| b != 0) {
+		return Err(invalid(
+			"the VHDX log has not been replayed; attach the disk in Windows once to repair it",
+		));
+	}
+
+	let regions = REGION_TABLES
+		.into_iter()
+		.map(|offset| file.read(offset, REGION_TABLE_SIZE))
+		.find(|table| {
+			table.as_ref().map_or(true, |table| {
+				&table[..4] == b"regi" && checksum_matches(table)
+			})
+		})
+		.ok_or_else(|| invalid("both VHDX region tables are damaged"))??;
+	let (mut bat, mut metadata) = (None, None);
+	for entry in regions[16..]
+		.chunks_exact(32)
+		.take((u32_at(&regions, 8) as usize).min(2047))
+	{
+		let (offset, length) = (u64_at(entry, 16), u32_at(entry, 24) as u64);
+		if length > MAX_REGION_SIZE
+			|| offset
+				.checked_add(length)
+				.is_none_or(|end| end > file.size())
+		{
+			return Err(invalid("a VHDX region lies outside the file"));
+		}
+		match entry[..16].try_into().unwrap() {
+			BAT_REGION => bat = Some((offset, length)),
+			METADATA_REGION => metadata = Some((offset, length)),
+			_ if u32_at(entry, 28) & 1 != 0 => {
+				return Err(invalid(format!(
+					"unsupported required VHDX region {}",
+					guid_string(&entry[..16])
+				)))
+			}
+			_ => {}
+		}
+	}
+	let (Some(bat), Some(metadata_region)) = (bat, metadata) else {
+		return Err(invalid(
+			"the VHDX region table lacks the block allocation table or metadata",
+		));
+	};
+
+	let metadata = file.read(metadata_region.0, metadata_region.1 as usize)?;
+	if metadata.len() < 32 || &metadata[..8] != b"metadata" {
+		return Err(invalid("the VHDX metadata table is damaged"));
+	}
+	let item = |data: &[u8], entry: &[u8]| -> io::Result<Vec<u8>> {
+		let (offset, length) = (u32_at(entry, 16) as usize, u32_at(entry, 20) as usize);
+		data.get(offset..offset + length)
+			.map(<[u8]>::to_vec)
+			.ok_or_else(|| invalid("a VHDX metadata item lies outside the metadata region"))
+	};
+	let (mut parameters, mut size, mut sector_size, mut locator) = (None, None, None, None);
+	for entry in metadata[32..]
+		.chunks_exact(32)
+		.take(u16_at(&metadata, 10) as usize)
+	{
+		let value = item(&metadata, entry)?;
+		match entry[..16].try_into().unwrap() {
+			FILE_PARAMETERS if value.len() >= 8 => {
+				parameters = Some((u32_at(&value, 0) as u64, u32_at(&value, 4)))
+			}
+			VIRTUAL_DISK_SIZE if value.len() >= 8 => {
+				size = Some((
+					metadata_region.0 + u32_at(entry, 16) as u64,
+					u64_at(&value, 0),
+				))
+			}
+			LOGICAL_SECTOR_SIZE if value.len() >= 4 => sector_size = Some(u32_at(&value, 0)),
+			PARENT_LOCATOR => locator = Some(value),
+			VIRTUAL_DISK_ID | PHYSICAL_SECTOR_SIZE => {}
+			_ if u32_at(entry, 24) & 4 != 0 => {
+				return Err(invalid(format!(
+					"unsupported required VHDX metadata item {}",
+					guid_string(&entry[..16])
+				)))
+			}
+			_ => {}
+		}
+	}
+	let (Some((block_size, flags)), Some((size_offset, size)), Some(sector_size)) =
+		(parameters, size, sector_size)
+	else {
+		return Err(invalid(
+			"the VHDX metadata lacks the block size, disk size or sector size",
+		));
+	};
+	if !block_size.is_power_of_two()
+		|| !(MIB..=256 * MIB).contains(&block_size)
+		|| !matches!(sector_size, 512 | 4096)
+	{
+		return Err(invalid(
+			"the VHDX metadata has an invalid block or sector size",
+		));
+	}
+	let layout = Layout {
+		header,
+		header_offset,
+		regions,
+		bat,
+		size_offset,
+		size,
+		sector_size,
+		block_size,
+		leave_allocated: flags & 1 != 0,
+		has_parent: flags & 2 != 0,
+		locator,
+	};
+	if bat.1 < layout.entries(size) * 8 {
+		return Err(invalid(
+			"the VHDX block allocation table does not cover the disk",
+		));
+	}
+	Ok(layout)
+}
+
+// VHDX 映像：数据按块分配，块分配表中每个数据块的项之间穿插着扇区位图块的项。只读打开
+pub struct Vhdx {
+	file: ImageFile,
+	size: u64,
+	sector_size: u32,
+	block_size: u64,
+	// 每个扇区位图块覆盖的数据块数
+	chunk_ratio: u64,
+	table: Vec<u64>,
+	// 差异磁盘中未分配的块和位图为 0 的扇区从父磁盘读取
+	parent: Option<Arc<dyn BlockDevice>>,
+	data_write_guid: [u8; 16],
+}
+
+impl Vhdx {
+	pub fn open(file: ImageFile, path: &Path, depth: usize) -> io::Result<Self> {
+		let layout = read_layout(&file)?;
+		let chunk_ratio = layout.chunk_ratio();
+		let entries = layout.entries(layout.size);
+		let table = file
+			.read(layout.bat.0, (entries * 8) as usize)?
+			.chunks_exact(8)
+			.map(|entry| u64_at(entry, 0))
+			.collect();
+		let parent = match (layout.has_parent, layout.locator) {
+			(false, _) => None,
+			(true, Some(locator)) => Some(Self::open_parent(&locator, &file, path, depth)?),
+			(true, None) => return Err(invalid("the differencing VHDX has no parent locator")),
+		};
+		Ok(Self {
+			file,
+			size: layout.size,
+			sector_size: layout.sector_size,
+			block_size: layout.block_size,
+			chunk_ratio,
+			table,
+			parent,
+			data_write_guid: layout.header[32..48].try_into().unwrap(),
+		})
+	}
+
+	// 父磁盘定位项是一组 UTF-16 键值：依次尝试 relative_path（相对于差异磁盘所在目录）、
+	// volume_path 和 absolute_win32_path。parent_linkage 是父磁盘的数据写入 GUID，父磁盘被修改过时不再匹配
+	fn open_parent(
+		locator: &[u8],
+		file: &ImageFile,
+		path: &Path,
+		depth: usize,
+	) -> io::Result<Arc<dyn BlockDevice>> {
+		if depth >= MAX_PARENTS {
+			return Err(invalid(format!(
+				"{} is more than {} parents deep",
+				path.display(),
+				MAX_PARENTS
+			)));
+		}
+		if locator.len() < 20 || locator[..16] != VHDX_PARENT_LOCATOR {
+			return Err(invalid("unsupported VHDX parent locator"));
+		}
+		let mut values = Vec::new();
+		for entry in locator[20..]
+			.chunks_exact(12)
+			.take(u16_at(locator, 18) as usize)
+		{
+			let text =
+				|offset: usize, length: usize| locator.get(offset..offset + length).map(utf16);
+			let key = text(u32_at(entry, 0) as usize, u16_at(entry, 8) as usize);
+			let value = text(u32_at(entry, 4) as usize, u16_at(entry, 10) as usize);
+			if let (Some(key), Some(value)) = (key, value) {
+				values.push((key, value));
+			}
+		}
+		let value = |key: &str| {
+			values
+				.iter()
+				.find(|(name, _)| name == key)
+				.map(|(_, value)| value.as_str())
+		};
+		let mut candidates = ["relative_path", "volume_path", "absolute_win32_path"]
+			.into_iter()
+			.filter_map(|key| {
+				let location = value(key)?.replace('\\', MAIN_SEPARATOR_STR);
+				Some(match key {
+					"relative_path" => path.parent().unwrap_or(Path::new("")).join(location),
+					_ => PathBuf::from(location),
+				})
+			});
+		let (parent_path, parent_file) = candidates
+			.find_map(|candidate| {
+				file.open_sibling(&candidate)
+					.ok()
+					.map(|parent| (candidate, parent))
+			})
+			.ok_or_else(|| invalid(format!("cannot find the parent disk of {}", path.display())))?;
+		if !is_vhdx(&parent_file)? {
+			return Err(invalid(format!(
+				"the parent disk {} is not a VHDX",
+				parent_path.display()
+			)));
+		}
+		let parent = Self::open(parent_file, &parent_path, depth + 1)?;
+		let linkage = guid_string(&parent.data_write_guid);
+		let linked = [value("parent_linkage"), value("parent_linkage2")]
+			.into_iter()
+			.flatten()
+			.any(|expected| expected.eq_ignore_ascii_case(&linkage));
+		if !linked {
+			return Err(invalid(format!(
+				"the parent disk {} has changed since {} was created",
+				parent_path.display(),
+				path.display()
+			)));
+		}
+		Ok(Arc::new(parent))
+	}
+
+	fn read_missing(&self, position: u64, buf: &mut [u8]) -> io::Result<()> {
+		match &self.parent {
+			Some(parent) => parent.read_at(position, buf),
+			None => {
+				buf.fill(0);
+				Ok(())
+			}
+		}
+	}
+
+	// 部分存在的块：按所在的扇区位图块逐扇区决定从本映像还是父磁盘读取，最低位对应第一个扇区
+	fn read_partial(&self, block: u64, data: u64, position: u64, buf: &mut [u8]) -> io::Result<()> {
+		let chunk = block / self.chunk_ratio;
+		let entry = self.table[(chunk * (self.chunk_ratio + 1) + self.chunk_ratio) as usize];
+		if entry & 7 != SECTOR_BITMAP_PRESENT {
+			return Err(invalid(
+				"a partially present VHDX block has no sector bitmap",
+			));
+		}
+		let sector_size = self.sector_size as u64;
+		let chunk_start = chunk * SECTORS_PER_BITMAP;
+		let (first, last) = (
+			position / sector_size - chunk_start,
+			(position + buf.len() as u64 - 1) / sector_size - chunk_start,
+		);
+		let bitmap = self.file.read(
+			(entry & !(MIB - 1)) + first / 8,
+			(last / 8 - first / 8 + 1) as usize,
+		)?;
+		let present = |sector: u64| {
+			let index = sector - chunk_start;
+			bitmap[(index / 8 - first / 8) as usize] & (1 << (index % 8)) != 0
+		};
+		let block_start = block * self.block_size;
+		for_each_run(
+			position,
+			buf,
+			sector_size,
+			present,
+			|present, position, run| match present {
+				true => self.file.read_at(data + position - block_start, run),
+				false => self.read_missing(position, run),
+			},
+		)
+	}
+}
+
+impl BlockDevice for Vhdx {
+	fn size(&self) -> u64 {
+		self.size
+	}
+
+	fn sector_size(&self) -> u32 {
+		self.sector_size
+	}
+
+	fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+		super::check_range(offset, buf.len(), self.size)?;
+		for_each_block(offset, buf, self.block_size, |position, part| {
+			let block = position / self.block_size;
+			let entry = self.table[(block + block / self.chunk_ratio) as usize];
+			// 高 44 位是块在文件中以 MiB 为单位的位置
+			let data = entry & !(MIB - 1);
+			match entry & 7 {
+				PAYLOAD_FULLY_PRESENT => self.file.read_at(data + position % self.block_size, part),
+				PAYLOAD_PARTIALLY_PRESENT if self.parent.is_some() => {
+					self.read_partial(block, data, position, part)
+				}
+				PAYLOAD_NOT_PRESENT => self.read_missing(position, part),
+				PAYLOAD_UNDEFINED | PAYLOAD_ZERO | PAYLOAD_UNMAPPED => {
+					part.fill(0);
+					Ok(())
+				}
+				state => Err(invalid(format!(
+					"block {} has an invalid VHDX state {}",
+					block, state
+				))),
+			}
+		})
+	}
+}
+
+fn check_size(size: u64, sector_size: u32) -> io::Result<()> {
+	if size == 0 || size > MAX_SIZE || !size.is_multiple_of(sector_size as u64) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			format!(
+				"the size of the VHDX must be a multiple of {} bytes and at most 64 TiB",
+				sector_size
+			),
+		));
+	}
+	Ok(())
+}
+
+// 块分配表区按 MiB 对齐
+fn bat_length(entries: u64) -> io::Result<u64> {
+	let length = (entries * 8).next_multiple_of(MIB);
+	if length > MAX_REGION_SIZE {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"the disk has too many blocks; use a larger block size",
+		));
+	}
+	Ok(length)
+}
+
+// 新建空的动态 VHDX：512 字节的逻辑扇区、4 KiB 的物理扇区，块都未分配，文件只包含头、区域表、空的日志、元数据和块分配表
+pub fn create(path: &Path, size: u64, block_size: u64) -> io::Result<()> {
+	check_size(size, 512)?;
+	if !block_size.is_power_of_two() || !(MIB..=256 * MIB).contains(&block_size) {
+		return Err(io::Error::new(
+			io::ErrorKind::InvalidInput,
+			"the VHDX block size must be a power of two between 1 MiB and 256 MiB",
+		));
+	}
+	let bat_length = bat_length(entries(
+		size,
+		block_size,
+		SECTORS_PER_BITMAP * 512 / block_size,
+		false,
+	))?;
+	let mut data = vec![0; BAT_OFFSET as usize];
+	data[..8].copy_from_slice(b"vhdxfile");
+	for (index, unit) in "httpfs".encode_utf16().enumerate() {
+		data[8 + index * 2..10 + index * 2].copy_from_slice(&unit.to_le_bytes());
+	}
+	let (file_write_guid, data_write_guid) = (random_guid(), random_guid());
+	for (sequence, offset) in HEADERS.into_iter().enumerate() {
+		let header = &mut data[offset as usize..offset as usize + HEADER_SIZE];
+		header[..4].copy_from_slice(b"head");
+		header[8..16].copy_from_slice(&(sequence as u64).to_le_bytes());
+		header[16..32].copy_from_slice(&file_write_guid);
+		header[32..48].copy_from_slice(&data_write_guid);
+		header[66..68].copy_from_slice(&1u16.to_le_bytes());
+		header[68..72].copy_from_slice(&(MIB as u32).to_le_bytes());
+		header[72..80].copy_from_slice(&LOG_OFFSET.to_le_bytes());
+		set_checksum(header);
+	}
+	for offset in REGION_TABLES {
+		let table = &mut data[offset as usize..offset as usize + REGION_TABLE_SIZE];
+		table[..4].copy_from_slice(b"regi");
+		table[8..12].copy_from_slice(&2u32.to_le_bytes());
+		for (entry, (id, offset, length)) in table[16..].chunks_exact_mut(32).zip([
+			(BAT_REGION, BAT_OFFSET, bat_length),
+			(METADATA_REGION, METADATA_OFFSET, MIB),
+		]) {
+			entry[..16].copy_from_slice(&id);
+			entry[16..24].copy_from_slice(&offset.to_le_bytes());
+			entry[24..28].copy_from_slice(&(length as u32).to_le_bytes());
+			// 必需的区域
+			entry[28..32].copy_from_slice(&1u32.to_le_bytes());
+		}
+		set_checksum(table);
+	}
+	// 元数据项的标志：4 为必需，2 为虚拟磁盘的属性
+	let items = [
+		(
+			FILE_PARAMETERS,
+			[(block_size as u32).to_le_bytes(), [0; 4]].concat(),
+			4u32,
+		),
+		(VIRTUAL_DISK_SIZE, size.to_le_bytes().to_vec(), 6),
+		(VIRTUAL_DISK_ID, random_guid().to_vec(), 6),
+		(LOGICAL_SECTOR_SIZE, 512u32.to_le_bytes().to_vec(), 6),
+		(PHYSICAL_SECTOR_SIZE, 4096u32.to_le_bytes().to_vec(), 6),
+	];
+	let metadata = &mut data[METADATA_OFFSET as usize..];
+	metadata[..8].copy_from_slice(b"metadata");
+	metadata[10..12].copy_from_slice(&(items.len() as u16).to_le_bytes());
+	let mut position = METADATA_ITEMS;
+	for (index, (id, value, flags)) in items.into_iter().enumerate() {
+		let entry = &mut metadata[32 + index * 32..64 + index * 32];
+		entry[..16].copy_from_slice(&id);
+		entry[16..20].copy_from_slice(&(position as u32).to_le_bytes());
+		entry[20..24].copy_from_slice(&(value.len() as u32).to_le_bytes());
+		entry[24..28].copy_from_slice(&flags.to_le_bytes());
+		metadata[position..position + value.len()].copy_from_slice(&value);
+		position += value.len();
+	}
+	let mut file = OpenOptions::new().write(true).create_new(true).open(path)?;
+	file.write_all(&data)?;
+	// 块分配表全为 0，由文件系统以稀疏方式保存
+	file.set_len(BAT_OFFSET + bat_length)?;
+	file.sync_all()
+}
+
+// 转换时按顺序写入新的动态 VHDX：块依次追加到文件末尾，块分配表在内存中，最后一次写出
+pub struct Writer {
+	file: ImageFile,
+	layout: Layout,
+	table: Vec<u64>,
+}
+
+impl Writer {
+	pub fn create(path: &Path, size: u64, block_size: u64) -> io::Result<Self> {
+		create(path, size, block_size)?;
+		let file = ImageFile::open_writable(path)?;
+		let layout = read_layout(&file)?;
+		let table = vec![0; layout.entries(size) as usize];
+		Ok(Self {
+			file,
+			layout,
+			table,
+		})
+	}
+}
+
+impl ImageWriter for Writer {
+	fn chunk_size(&self) -> u64 {
+		self.layout.block_size
+	}
+
+	// 块按 MiB 对齐；磁盘末尾不足一块时补足为整块
+	fn write_chunk(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+		let position = self.file.size().next_multiple_of(MIB);
+		self.file.write(position, data)?;
+		if (data.len() as u64) < self.layout.block_size {
+			self.file.set_len(position + self.layout.block_size)?;
+		}
+		self.table[self.layout.entry(offset / self.layout.block_size) as usize] =
+			position | PAYLOAD_FULLY_PRESENT;
+		Ok(())
+	}
+
+	fn finish(self: Box<Self>) -> io::Result<()> {
+		if self.table.iter().any(|&entry| entry != 0) {
+			let table: Vec<u8> = self
+				.table
+				.iter()
+				.flat_map(|entry| entry.to_le_bytes())
+				.collect();
+			self.file.write(self.layout.bat.0, &table)?;
+		}
+		self.file.sync()
+	}
+}
+
+// 修改映像前换上新的文件写入 GUID 和数据写入 GUID，以它为父磁盘的差异磁盘随之不再匹配。
+// 先写较旧的一份头再写另一份，任何时候至少有一份头完整
+fn update_headers(file: &ImageFile, layout: &Layout) -> io::Result<()> {
+	let mut header = layout.header.clone();
+	header[16..32].copy_from_slice(&random_guid());
+	header[32..48].copy_from_slice(&random_guid());
+	let sequence = u64_at(&header, 8);
+	let older = if layout.header_offset == HEADERS[0] {
+		HEADERS[1]
+	} else {
+		HEADERS[0]
+	};
+	for (offset, sequence) in [(older, sequence + 1), (layout.header_offset, sequence + 2)] {
+		header[8..16].copy_from_slice(&sequence.to_le_bytes());
+		set_checksum(&mut header);
+		file.write(offset, &header)?;
+		file.sync()?;
+	}
+	Ok(())
+}
+
+// 改变动态 VHDX 的大小，返回原来的大小。不经过日志，而是按中断时映像仍然完整的顺序修改：
+// 扩大时块分配表放不下则先复制到文件末尾，再依次改写两份区域表，最后写入新的大小；
+// 缩小时先清零最后一块中新末尾之后的数据、清除之后各块的块分配表项，再写入新的大小，最后截掉文件末尾不再使用的部分
+pub fn resize(path: &Path, size: u64) -> io::Result<u64> {
+	let file = ImageFile::open_writable(path)?;
+	let layout = read_layout(&file)?;
+	if layout.has_parent || layout.leave_allocated {
+		return Err(io::Error::new(
+			io::ErrorKind::Unsupported,
+			"only dynamic VHDX images without a parent can be resized",
+		));
+	}
+	check_size(size, layout.sector_size)?;
+	let (old_size, block_size) = (layout.size, layout.block_size);
+	if size == old_size {
+		return Ok(old_size);
+	}
+	let (old_entries, new_entries) = (layout.entries(old_size), layout.entries(size));
+	let table: Vec<u64> = file
+		.read(layout.bat.0, (old_entries * 8) as usize)?
+		.chunks_exact(8)
+		.map(|entry| u64_at(entry, 0))
+		.collect();
+	update_headers(&file, &layout)?;
+	if size > old_size && new_entries * 8 > layout.bat.1 {
+		let length = bat_length(new_entries)?;
+		let offset = file.size().next_multiple_of(MIB);
+		let mut copy = file.read(layout.bat.0, (old_entries * 8) as usize)?;
+		copy.resize(length as usize, 0);
+		file.write(offset, &copy)?;
+		file.sync()?;
+		let mut regions = layout.regions.clone();
+		let count = (u32_at(&regions, 8) as usize).min(2047);
+		for entry in regions[16..]
+			.chunks_exact_mut(32)
+			.take(count)
+			.filter(|entry| entry[..16] == BAT_REGION)
+		{
+			entry[16..24].copy_from_slice(&offset.to_le_bytes());
+			entry[24..28].copy_from_slice(&(length as u32).to_le_bytes());
+		}
+		set_checksum(&mut regions);
+		for table in REGION_TABLES {
+			file.write(table, &regions)?;
+			file.sync()?;
+		}
+	}
+	if size < old_size {
+		// 最后一块中新末尾之后的部分以后扩大时应读出为 0
+		let (block, tail) = (size / block_size, size % block_size);
+		let entry = table[layout.entry(block) as usize];
+		if tail != 0 && entry & 7 == PAYLOAD_FULLY_PRESENT {
+			let zeros = vec![0; MIB as usize];
+			let mut position = tail;
+			while position < block_size {
+				let length = (block_size - position).min(MIB);
+				file.write((entry & !(MIB - 1)) + position, &zeros[..length as usize])?;
+				position += length;
+			}
+		}
+		file.write(
+			layout.bat.0 + new_entries * 8,
+			&vec![0; ((old_entries - new_entries) * 8) as usize],
+		)?;
+		file.sync()?;
+	}
+	file.write(layout.size_offset, &size.to_le_bytes())?;
+	file.sync()?;
+	if size < old_size {
+		// 文件末尾之前还在使用的部分：各区域、日志和仍被引用的块
+		let count = (u32_at(&layout.regions, 8) as usize).min(2047);
+		let regions = layout.regions[16..]
+			.chunks_exact(32)
+			.take(count)
+			.map(|entry| u64_at(entry, 16) + u32_at(entry, 24) as u64);
+		let log = u64_at(&layout.header, 72) + u32_at(&layout.header, 68) as u64;
+		let blocks = table[..new_entries as usize]
+			.iter()
+			.filter(|&&entry| {
+				matches!(entry & 7, PAYLOAD_FULLY_PRESENT | PAYLOAD_PARTIALLY_PRESENT)
+			})
+			.map(|&entry| (entry & !(MIB - 1)) + block_size);
+		let end = regions.chain(blocks).fold(
+			log.max(REGION_TABLES[1] + REGION_TABLE_SIZE as u64),
+			u64::max,
+		);
+		if end < file.size() {
+			file.set_len(end)?;
+		}
+	}
+	Ok(old_size)
+}
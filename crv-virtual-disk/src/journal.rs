@@ -0,0 +1,505 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Mutex,
+	},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+	backend::{civil_from_days, Remote},
+	error::RemoteError,
+	vfs, RemoteFileInfo, StorageBackend,
+};
+
+// 日志文件的开头，格式变化时修改；仍然读取上一版格式（没有版本）的日志
+const MAGIC: &[u8; 8] = b"HTTPFSJ2";
+const MAGIC_V1: &[u8; 8] = b"HTTPFSJ1";
+const EXTENSION: &str = "journal";
+
+const RECORD_WRITE: u8 = 1;
+const RECORD_SET_LEN: u8 = 2;
+
+// 重放时内容的起点：新建或覆盖打开的文件从空内容开始，提交过一次之后从存储中已提交的内容开始
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Base {
+	Empty,
+	Stored,
+}
+
+// 保存所基于的存储中的文件版本，重放前与存储中的当前版本比较，发现其他客户端在这期间做的修改
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+	// 没有记录（备用数据流、旧格式的日志），不检查冲突
+	Unknown,
+	Absent,
+	Stored { size: u64, modified: u64 },
+}
+
+impl Version {
+	pub fn of(info: Option<&RemoteFileInfo>) -> Self {
+		match info {
+			Some(info) => Version::Stored {
+				size: info.size,
+				modified: info.modified,
+			},
+			None => Version::Absent,
+		}
+	}
+
+	fn matches(self, current: Option<&RemoteFileInfo>) -> bool {
+		self == Version::Unknown || self == Version::of(current)
+	}
+}
+
+// 重放时发现的冲突：存储中的文件在保存之后被修改过，保存的内容写入了冲突副本 copy，原文件保持不变
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Conflict {
+	pub path: String,
+	pub copy: String,
+	// 发现的时间（Unix 秒）
+	pub detected: u64,
+}
+
+// 挂载以来发现的冲突，通过控制管道列出
+#[derive(Debug, Default)]
+pub struct Conflicts(Mutex<Vec<Conflict>>);
+
+impl Conflicts {
+	pub fn record(&self, conflict: Conflict) {
+		self.0.lock().unwrap().push(conflict);
+	}
+
+	pub fn list(&self) -> Vec<Conflict> {
+		self.0.lock().unwrap().clone()
+	}
+
+	pub fn len(&self) -> usize {
+		self.0.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+// 重放一次保存的结果
+#[derive(Debug)]
+pub struct Replayed {
+	pub bytes: usize,
+	// 发生冲突时保存的内容所在的冲突副本
+	pub conflict: Option<String>,
+}
+
+// 冲突副本的名称：同一目录下的 name (conflict YYYY-MM-DD).ext，同一天的第 n 个副本为 name (conflict YYYY-MM-DD n).ext
+pub fn conflict_copy_path(path: &str, secs: u64, n: usize) -> String {
+	let (parent, name) = match path.rsplit_once('/') {
+		Some((parent, name)) => (parent, name),
+		None => (".", path),
+	};
+	let (stem, extension) = match name.rfind('.') {
+		Some(dot) if dot > 0 => name.split_at(dot),
+		_ => (name, ""),
+	};
+	let (year, month, day) = civil_from_days((secs / 86400) as i64);
+	let suffix = if n > 1 {
+		format!(" {}", n)
+	} else {
+		String::new()
+	};
+	vfs::child_path(
+		parent,
+		&format!(
+			"{} (conflict {:04}-{:02}-{:02}{}){}",
+			stem, year, month, day, suffix, extension
+		),
+	)
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |since| since.as_secs())
+}
+
+// 整文件保存的预写日志。暂存的每次写入先追加到日志文件并落盘，再向应用程序确认；
+// 提交到存储后清空日志。客户端崩溃或提交失败时日志留在磁盘上，下次挂载时重放到存储
+pub struct Journal {
+	dir: PathBuf,
+	next_id: AtomicU64,
+}
+
+// 一个打开的文件的日志
+pub struct JournalFile {
+	file: File,
+	location: PathBuf,
+	path: String,
+	stream: Option<String>,
+}
+
+// 上次挂载留下、尚未提交的一次保存
+#[derive(Debug)]
+pub struct PendingSave {
+	pub path: String,
+	pub stream: Option<String>,
+	pub base: Base,
+	pub version: Version,
+	records: Vec<Record>,
+	location: PathBuf,
+}
+
+#[derive(Debug)]
+enum Record {
+	Write { offset: u64, data: Vec<u8> },
+	SetLen(u64),
+}
+
+impl Journal {
+	// 日志保存在 dir 下按存储区分的子目录中，同一存储挂载到不同位置时也能找到上次的日志
+	pub fn open(dir: &Path, remote: &Remote) -> io::Result<Self> {
+		let identity = format!(
+			"{}\n{}",
+			remote.server_url,
+			remote.share.as_deref().unwrap_or("")
+		);
+		let dir = dir.join(&hex::encode(Sha256::digest(identity.as_bytes()))[..16]);
+		fs::create_dir_all(&dir)?;
+		// 文件名以启动时间开头，不会与上次留下的日志重名
+		let started = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |since| since.as_micros() as u64);
+		Ok(Self {
+			dir,
+			next_id: AtomicU64::new(started),
+		})
+	}
+
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	// version 为打开时存储中的文件版本
+	pub fn create(
+		&self,
+		path: &str,
+		stream: Option<&str>,
+		base: Base,
+		version: Version,
+	) -> io::Result<JournalFile> {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let location = self.dir.join(format!("{:016x}.{}", id, EXTENSION));
+		let file = OpenOptions::new()
+			.read(true)
+			.write(true)
+			.create_new(true)
+			.open(&location)?;
+		let mut journal = JournalFile {
+			file,
+			location,
+			path: path.to_string(),
+			stream: stream.map(str::to_string),
+		};
+		journal.write_header(base, version)?;
+		Ok(journal)
+	}
+
+	// 上次挂载留下的日志，按创建顺序；头部不完整的日志没有确认过任何写入，直接删除
+	pub fn pending(&self) -> io::Result<Vec<PendingSave>> {
+		let mut locations: Vec<PathBuf> = fs::read_dir(&self.dir)?
+			.flatten()
+			.map(|entry| entry.path())
+			.filter(|path| {
+				path.extension()
+					.is_some_and(|extension| extension == EXTENSION)
+			})
+			.collect();
+		locations.sort();
+		let mut pending = Vec::new();
+		for location in locations {
+			match PendingSave::read(&location)? {
+				Some(save) => pending.push(save),
+				None => fs::remove_file(&location)?,
+			}
+		}
+		Ok(pending)
+	}
+}
+
+impl JournalFile {
+	pub fn write(&mut self, offset: u64, data: &[u8]) -> io::Result<()> {
+		self.append(RECORD_WRITE, offset, data)
+	}
+
+	pub fn set_len(&mut self, len: u64) -> io::Result<()> {
+		self.append(RECORD_SET_LEN, len, &[])
+	}
+
+	// 暂存内容已提交：之后的重放从存储中的内容（提交后的版本为 version）开始
+	pub fn reset(&mut self, version: Version) -> io::Result<()> {
+		self.write_header(Base::Stored, version)
+	}
+
+	// 内容已经直接写入存储或文件已被删除，不再需要日志
+	pub fn discard(self) {
+		drop(self.file);
+		let _ = fs::remove_file(&self.location);
+	}
+
+	fn write_header(&mut self, base: Base, version: Version) -> io::Result<()> {
+		let mut header = Vec::from(&MAGIC[..]);
+		header.push(base as u8);
+		put_string(&mut header, &self.path);
+		match &self.stream {
+			Some(stream) => {
+				header.push(1);
+				put_string(&mut header, stream);
+			}
+			None => header.push(0),
+		}
+		match version {
+			Version::Unknown => header.push(0),
+			Version::Absent => header.push(1),
+			Version::Stored { size, modified } => {
+				header.push(2);
+				header.extend_from_slice(&size.to_le_bytes());
+				header.extend_from_slice(&modified.to_le_bytes());
+			}
+		}
+		append_checksum(&mut header);
+		self.file.set_len(0)?;
+		self.file.seek(SeekFrom::Start(0))?;
+		self.file.write_all(&header)?;
+		self.file.sync_data()
+	}
+
+	// 每条记录带有校验和，崩溃时写了一半的最后一条记录在重放时被忽略；它还没有被确认
+	fn append(&mut self, kind: u8, value: u64, data: &[u8]) -> io::Result<()> {
+		let mut record = Vec::with_capacity(data.len() + 17);
+		record.push(kind);
+		record.extend_from_slice(&value.to_le_bytes());
+		record.extend_from_slice(&(data.len() as u32).to_le_bytes());
+		record.extend_from_slice(data);
+		append_checksum(&mut record);
+		self.file.seek(SeekFrom::End(0))?;
+		self.file.write_all(&record)?;
+		self.file.sync_data()
+	}
+}
+
+impl PendingSave {
+	fn read(location: &Path) -> io::Result<Option<Self>> {
+		let mut bytes = Vec::new();
+		File::open(location)?.read_to_end(&mut bytes)?;
+		let mut reader = Reader {
+			bytes: &bytes,
+			position: 0,
+		};
+		let Some((base, path, stream, version)) = reader.header() else {
+			return Ok(None);
+		};
+		let mut records = Vec::new();
+		while let Some(record) = reader.record() {
+			records.push(record);
+		}
+		Ok(Some(Self {
+			path,
+			stream,
+			base,
+			version,
+			records,
+			location: location.to_path_buf(),
+		}))
+	}
+
+	// 在起点内容上依次应用日志中的写入
+	pub fn content(&self, backend: &dyn StorageBackend) -> Result<Vec<u8>, RemoteError> {
+		let mut data = match (self.base, &self.stream) {
+			(Base::Empty, _) => Vec::new(),
+			(Base::Stored, Some(stream)) => {
+				backend.get_xattr(&self.path, stream)?.unwrap_or_default()
+			}
+			(Base::Stored, None) => {
+				let size = backend.stat(&self.path)?.size;
+				backend.read(&self.path, 0, size as usize)?
+			}
+		};
+		for record in &self.records {
+			match record {
+				Record::Write {
+					offset,
+					data: written,
+				} => {
+					let end = *offset as usize + written.len();
+					if data.len() < end {
+						data.resize(end, 0);
+					}
+					data[*offset as usize..end].copy_from_slice(written);
+				}
+				Record::SetLen(len) => data.resize(*len as usize, 0),
+			}
+		}
+		Ok(data)
+	}
+
+	// 把重建的内容原子提交到存储，成功后删除日志；提交之后没有新写入的日志无需重放。
+	// 存储中的文件在保存之后被其他客户端修改过时不覆盖它，内容写入同一目录下的冲突副本
+	pub fn replay(self, backend: &dyn StorageBackend) -> Result<Replayed, RemoteError> {
+		if self.base == Base::Stored && self.records.is_empty() {
+			let _ = fs::remove_file(&self.location);
+			return Ok(Replayed {
+				bytes: 0,
+				conflict: None,
+			});
+		}
+		let conflicted = match (&self.stream, self.version) {
+			(Some(_), _) | (None, Version::Unknown) => false,
+			(None, version) => match backend.stat(&self.path) {
+				Ok(current) => !version.matches(Some(&current)),
+				// 文件在这期间被删除时按原路径重新创建
+				Err(e) if e.code() == Some("not_found") => false,
+				Err(e) => return Err(e),
+			},
+		};
+		let data = self.content(backend)?;
+		let conflict = match &self.stream {
+			Some(stream) => {
+				backend.put_xattr(&self.path, stream, &data)?;
+				None
+			}
+			None if conflicted => Some(self.commit_copy(backend, &data)?),
+			None => {
+				backend.commit(&self.path, &data)?;
+				None
+			}
+		};
+		let _ = fs::remove_file(&self.location);
+		Ok(Replayed {
+			bytes: data.len(),
+			conflict,
+		})
+	}
+
+	// 以新建的方式占用一个还不存在的冲突副本名称，再写入内容
+	fn commit_copy(
+		&self,
+		backend: &dyn StorageBackend,
+		data: &[u8],
+	) -> Result<String, RemoteError> {
+		let now = now_secs();
+		let mut n = 1;
+		loop {
+			let copy = conflict_copy_path(&self.path, now, n);
+			match backend.create(&copy, false) {
+				Ok(()) => {
+					backend.commit(&copy, data)?;
+					return Ok(copy);
+				}
+				Err(e) if e.code() == Some("already_exists") && n < 100 => n += 1,
+				Err(e) => return Err(e),
+			}
+		}
+	}
+}
+
+struct Reader<'a> {
+	bytes: &'a [u8],
+	position: usize,
+}
+
+impl<'a> Reader<'a> {
+	fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+		let bytes = self
+			.bytes
+			.get(self.position..self.position.checked_add(len)?)?;
+		self.position += len;
+		Some(bytes)
+	}
+
+	fn u32(&mut self) -> Option<u32> {
+		Some(u32::from_le_bytes(self.take(4)?.try_into().ok()?))
+	}
+
+	fn u64(&mut self) -> Option<u64> {
+		Some(u64::from_le_bytes(self.take(8)?.try_into().ok()?))
+	}
+
+	fn string(&mut self) -> Option<String> {
+		let len = self.u32()? as usize;
+		String::from_utf8(self.take(len)?.to_vec()).ok()
+	}
+
+	// 校验从 start 到当前位置的内容
+	fn checked(&mut self, start: usize) -> Option<()> {
+		let expected = checksum(&self.bytes[start..self.position]);
+		(self.take(4)? == expected).then_some(())
+	}
+
+	fn header(&mut self) -> Option<(Base, String, Option<String>, Version)> {
+		let magic = self.take(MAGIC.len())?;
+		let current = magic == MAGIC;
+		(current || magic == MAGIC_V1).then_some(())?;
+		let base = match self.take(1)?[0] {
+			0 => Base::Empty,
+			1 => Base::Stored,
+			_ => return None,
+		};
+		let path = self.string()?;
+		let stream = match self.take(1)?[0] {
+			0 => None,
+			_ => Some(self.string()?),
+		};
+		let version = if current {
+			match self.take(1)?[0] {
+				0 => Version::Unknown,
+				1 => Version::Absent,
+				2 => Version::Stored {
+					size: self.u64()?,
+					modified: self.u64()?,
+				},
+				_ => return None,
+			}
+		} else {
+			Version::Unknown
+		};
+		self.checked(0)?;
+		Some((base, path, stream, version))
+	}
+
+	fn record(&mut self) -> Option<Record> {
+		let start = self.position;
+		let kind = self.take(1)?[0];
+		let value = self.u64()?;
+		let len = self.u32()? as usize;
+		let data = self.take(len)?.to_vec();
+		self.checked(start)?;
+		match kind {
+			RECORD_WRITE => Some(Record::Write {
+				offset: value,
+				data,
+			}),
+			RECORD_SET_LEN => Some(Record::SetLen(value)),
+			_ => None,
+		}
+	}
+}
+
+fn put_string(buffer: &mut Vec<u8>, text: &str) {
+	buffer.extend_from_slice(&(text.len() as u32).to_le_bytes());
+	buffer.extend_from_slice(text.as_bytes());
+}
+
+fn checksum(bytes: &[u8]) -> [u8; 4] {
+	let digest = Sha256::digest(bytes);
+	[digest[0], digest[1], digest[2], digest[3]]
+}
+
+fn append_checksum(buffer: &mut Vec<u8>) {
+	let sum = checksum(buffer);
+	buffer.extend_from_slice(&sum);
+}
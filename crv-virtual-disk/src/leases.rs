@@ -0,0 +1,214 @@
+use std::{
+	collections::HashMap,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::Duration,
+};
+
+use reqwest::{
+	blocking::{Client, RequestBuilder},
+	Method,
+};
+use serde::Deserialize;
+use tracing::{debug, info, warn};
+
+use crate::{
+	attr_cache::AttrCache,
+	auth::OAuth,
+	auth_headers,
+	backend::{encode_api_path, Remote},
+	error::{CheckStatus, RemoteError},
+};
+
+// 每个心跳请求在服务器上最多等待召回的时间，需要短于服务器的会话期限
+const HEARTBEAT_WAIT: Duration = Duration::from_secs(15);
+// 心跳失败后重试前的等待时间
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Deserialize)]
+struct SessionResponse {
+	session: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HeartbeatResponse {
+	recalled: Vec<String>,
+}
+
+/// 与 httpfs 服务器之间的客户端会话和租约。打开文件或目录时取得它的租约（以写方式打开时为写租约），
+/// 持有租约期间属性缓存中的条目不过期；另一个客户端以写方式打开同一路径时服务器召回租约，
+/// 这里使对应的条目失效后归还。心跳请求在服务器上等待召回，召回几乎立即送达。
+/// 心跳中断后不知道租约是否仍然有效，放弃所有租约，条目恢复按期限过期。
+pub struct Leases {
+	base_url: String,
+	client: Client,
+	oauth: Option<Arc<OAuth>>,
+	attrs: Arc<AttrCache>,
+	// 当前的会话，会话过期后重新建立
+	session: Mutex<Option<String>>,
+	// 持有的租约，值为是否为写租约
+	held: Mutex<HashMap<String, bool>>,
+}
+
+impl Leases {
+	/// 建立会话并在后台发送心跳，`stop` 置位后结束会话。
+	pub fn start(
+		remote: &Remote,
+		attrs: Arc<AttrCache>,
+		stop: Arc<AtomicBool>,
+	) -> Result<Arc<Self>, RemoteError> {
+		// 心跳请求在服务器上等待召回，超时需要长于等待时间
+		let client = Client::builder()
+			.default_headers(auth_headers(remote.token.as_deref())?)
+			.timeout(HEARTBEAT_WAIT * 2)
+			.build()?;
+		let leases = Arc::new(Self {
+			base_url: remote.base_url(),
+			client,
+			oauth: remote.oauth.as_ref().map(OAuth::shared),
+			attrs,
+			session: Mutex::new(None),
+			held: Mutex::new(HashMap::new()),
+		});
+		leases.open_session()?;
+		thread::spawn({
+			let leases = leases.clone();
+			move || leases.heartbeats(&stop)
+		});
+		Ok(leases)
+	}
+
+	fn request(&self, method: Method, url: String) -> RequestBuilder {
+		let request = self.client.request(method, url);
+		match &self.oauth {
+			Some(oauth) => oauth.authorize(request),
+			None => request,
+		}
+	}
+
+	fn open_session(&self) -> Result<String, RemoteError> {
+		let session = self
+			.request(Method::POST, format!("{}/session", self.base_url))
+			.send()?
+			.check_status()?
+			.json::<SessionResponse>()?
+			.session;
+		debug!(session = %session, "leases: session opened");
+		*self.session.lock().unwrap() = Some(session.clone());
+		Ok(session)
+	}
+
+	fn heartbeats(&self, stop: &AtomicBool) {
+		while !stop.load(Ordering::Relaxed) {
+			let session = match self.session.lock().unwrap().clone() {
+				Some(session) => Ok(session),
+				None => self.open_session(),
+			};
+			let result = session.and_then(|session| {
+				self.request(
+					Method::POST,
+					format!("{}/session/{}/heartbeat", self.base_url, session),
+				)
+				.query(&[("wait", HEARTBEAT_WAIT.as_secs())])
+				.send()?
+				.check_status()?
+				.json::<HeartbeatResponse>()
+				.map_err(RemoteError::from)
+			});
+			match result {
+				Ok(response) => {
+					for path in response.recalled {
+						self.recalled(if path == "$ROOT" { "." } else { &path });
+					}
+				}
+				Err(e) => {
+					// 会话可能已经过期，其间的召回没有收到
+					if e.code() == Some("session_not_found") {
+						info!("leases: the session expired, opening a new one");
+					} else {
+						warn!(error = %e, "leases: heartbeat failed");
+					}
+					*self.session.lock().unwrap() = None;
+					self.drop_all();
+					thread::sleep(RETRY_DELAY);
+				}
+			}
+		}
+		if let Some(session) = self.session.lock().unwrap().take() {
+			let _ = self
+				.request(
+					Method::DELETE,
+					format!("{}/session/{}", self.base_url, session),
+				)
+				.send();
+		}
+		self.drop_all();
+	}
+
+	// 服务器召回的租约：条目失效后归还
+	fn recalled(&self, path: &str) {
+		debug!(path = %path, "leases: recalled");
+		self.held.lock().unwrap().remove(path);
+		self.attrs.unlease(path);
+		self.attrs.invalidate(path);
+		let Some(session) = self.session.lock().unwrap().clone() else {
+			return;
+		};
+		let result = self
+			.request(
+				Method::DELETE,
+				format!("{}/lease/{}", self.base_url, encode_api_path(path)),
+			)
+			.query(&[("session", session)])
+			.send()
+			.map_err(RemoteError::from)
+			.and_then(CheckStatus::check_status);
+		if let Err(e) = result {
+			warn!(path = %path, error = %e, "leases: returning a recalled lease failed");
+		}
+	}
+
+	// 不再认为持有任何租约
+	fn drop_all(&self) {
+		for (path, _) in self.held.lock().unwrap().drain() {
+			self.attrs.unlease(&path);
+			self.attrs.invalidate(&path);
+		}
+	}
+
+	/// 取得 `path` 的租约，已经持有足够的租约时不发送请求。其他客户端持有冲突的租约时服务器先召回，
+	/// 这里最多等待到它们归还。失败时不持有租约，条目照常按期限过期。
+	pub fn acquire(&self, path: &str, write: bool) {
+		if self
+			.held
+			.lock()
+			.unwrap()
+			.get(path)
+			.is_some_and(|&held| held || !write)
+		{
+			return;
+		}
+		let Some(session) = self.session.lock().unwrap().clone() else {
+			return;
+		};
+		let result = self
+			.request(
+				Method::POST,
+				format!("{}/lease/{}", self.base_url, encode_api_path(path)),
+			)
+			.json(&serde_json::json!({ "session": session, "write": write }))
+			.send()
+			.map_err(RemoteError::from)
+			.and_then(CheckStatus::check_status);
+		match result {
+			Ok(_) => {
+				self.held.lock().unwrap().insert(path.to_string(), write);
+				self.attrs.lease(path);
+			}
+			Err(e) => debug!(path = %path, error = %e, "leases: acquiring a lease failed"),
+		}
+	}
+}
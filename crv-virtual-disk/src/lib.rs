@@ -0,0 +1,2396 @@
+//! 把存储挂载为 Windows 文件系统：httpfs 示例的挂载逻辑都在这个库中，示例只提供命令行、Windows 服务和托盘图标。
+//!
+//! - [`StorageBackend`] 是存储的操作，[`backend::open`] 按 URL 的协议打开 httpfs 服务器、S3、WebDAV、SFTP、本地目录、内存盘、压缩包和磁盘映像
+//! - [`HttpFsHandler`] 在存储后端之上实现 Dokan 的回调，以及 FUSE、WinFsp 和 ProjFS 共用的 [`vfs::VirtualFs`]
+//! - [`OperationHook`] 包在每个 Dokan 回调外，可以实现审计、内容扫描、访问策略或指标
+//! - [`access::AccessRules`] 在打开文件时按路径通配符、进程名和用户 SID 允许或拒绝读、写、删除和执行
+//! - [`identity::UserMap`] 把打开文件的 Windows 用户映射为服务器上的用户，请求按该用户的权限处理
+//! - [`policy::ProcessPolicies`] 按打开文件的进程选择缓存方式，打开的文件记录请求者（[`FileContext::requester`]）
+//! - [`MountConfig`] 是挂载时传给 Dokan 的卷选项，由 [`MountConfig::builder`] 构造
+//! - [`Mount`] 是一个挂载的全部设置，[`MountHandle`] 在后台线程中运行挂载，可以查询状态、请求卸载和等待卸载完成，
+//!   挂载和卸载时以 [`MountEvent`] 通知调用者
+//!
+//! ```no_run
+//! use crv_virtual_disk::{backend::Remote, Mount, MountEvent, MountHandle};
+//!
+//! dokan::init();
+//! let handle = MountHandle::spawn(Mount::new(Remote::new("http://localhost:8080"), r"M:\"), |event| match event {
+//! 	MountEvent::Mounted => println!("mounted"),
+//! 	MountEvent::Unmounted => println!("unmounted"),
+//! });
+//! // ...
+//! if handle.is_mounted() {
+//! 	handle.unmount();
+//! }
+//! handle.wait().unwrap();
+//! dokan::shutdown();
+//! ```
+
+pub mod access;
+pub mod atomic_save;
+pub mod attr_cache;
+pub mod auth;
+pub mod backend;
+pub mod buffers;
+pub mod capabilities;
+pub mod compression;
+pub mod conformance;
+pub mod control;
+pub mod credentials;
+pub mod delta;
+pub mod error;
+pub mod events;
+#[cfg(all(unix, feature = "fuse"))]
+pub mod fuse;
+pub mod hooks;
+pub mod http2;
+pub mod http3;
+pub mod identity;
+pub mod image;
+pub mod journal;
+pub mod leases;
+pub mod metrics;
+pub mod mount;
+pub mod mount_config;
+pub mod mount_point;
+pub mod names;
+pub mod nbd;
+pub mod offline;
+pub mod open_files;
+pub mod oplock;
+pub mod packer;
+pub mod policy;
+#[cfg(all(windows, feature = "projfs"))]
+pub mod projfs;
+pub mod requester;
+pub mod snapshots;
+pub mod upload_queue;
+pub mod verify;
+pub mod vfs;
+#[cfg(all(windows, feature = "winfsp"))]
+pub mod winfsp;
+
+use std::{
+	io,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use dokan::{
+	CreateFileInfo, DiskSpaceInfo, FileInfo, FileSystemHandler, FileTimeOperation, FillDataError,
+	FillDataResult, FindData, FindStreamData, OperationInfo, OperationResult, VolumeInfo,
+	IO_SECURITY_CONTEXT,
+};
+use dokan_sys::win32::{
+	FILE_CREATE, FILE_DELETE_ON_CLOSE, FILE_DIRECTORY_FILE, FILE_MAXIMUM_DISPOSITION, FILE_OPEN,
+	FILE_OPEN_IF, FILE_OVERWRITE, FILE_OVERWRITE_IF, FILE_SUPERSEDE,
+};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use serde::{Deserialize, Serialize};
+use tracing::{debug_span, error, field::Empty, warn, Span};
+use widestring::{U16CStr, U16CString};
+use winapi::{
+	shared::{ntdef::NTSTATUS, ntstatus::*},
+	um::winnt,
+};
+
+use crate::{
+	access::{AccessRules, Right},
+	atomic_save::AtomicSaveBackend,
+	attr_cache::{AttrCache, CacheLimits},
+	error::RemoteError,
+	hooks::{Hooks, Operation},
+	identity::{self, UserMap},
+	journal::{Conflict, Conflicts, Journal, JournalFile},
+	leases::Leases,
+	metrics::Metrics,
+	offline::{OfflineBackend, OfflineCache},
+	open_files::{OpenFiles, OpenHandle},
+	packer::{Packer, MAX_PACKED_FILE_SIZE},
+	policy::{CachePolicy, ProcessPolicies},
+	requester::Requester,
+	snapshots::Node as SnapshotNode,
+	upload_queue::UploadQueue,
+	vfs::VirtualFs,
+};
+
+pub use backend::StorageBackend;
+pub use hooks::{Hooks, OperationHook};
+pub use mount::{Driver, Mount, MountEvent, MountHandle};
+pub use mount_config::{MountConfig, MountConfigBuilder};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RemoteFileInfo {
+	pub name: String,
+	pub is_directory: bool,
+	pub size: u64,
+	pub created: u64,
+	pub modified: u64,
+	pub accessed: u64,
+	// 存储中实际占用的长度，只在与 size 不同时（如压缩存储的文件）给出
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub stored_size: Option<u64>,
+	// 稀疏文件实际占用的存储，只在小于 size 时给出
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub allocated_size: Option<u64>,
+}
+
+// 整文件保存时暂存在本地的文件内容，flush 或 cleanup 时以原子写入提交到服务器
+struct StagedContent {
+	data: Vec<u8>,
+	dirty: bool,
+	// 提交前设置的时间戳，提交后再发送，避免被写入更新的修改时间覆盖
+	times: TimesUpdate,
+	// 启用预写日志时每次修改先写入这里，提交后清空
+	journal: Option<JournalFile>,
+}
+
+impl StagedContent {
+	// 修改暂存内容；有日志时先写入日志并落盘，日志写入失败时内容不变
+	fn write(&mut self, offset: usize, data: &[u8]) -> io::Result<()> {
+		if let Some(journal) = &mut self.journal {
+			journal.write(offset as u64, data)?;
+		}
+		let end = offset + data.len();
+		if self.data.len() < end {
+			self.data.resize(end, 0);
+		}
+		self.data[offset..end].copy_from_slice(data);
+		self.dirty = true;
+		Ok(())
+	}
+
+	fn set_len(&mut self, len: usize) -> io::Result<()> {
+		if let Some(journal) = &mut self.journal {
+			journal.set_len(len as u64)?;
+		}
+		self.data.resize(len, 0);
+		self.dirty = true;
+		Ok(())
+	}
+
+	// 内容已直接写入存储或文件已删除
+	fn discard_journal(&mut self) {
+		if let Some(journal) = self.journal.take() {
+			journal.discard();
+		}
+	}
+}
+
+// 本地的日志无法写入时拒绝这次修改，不确认没有落盘的写入
+fn journal_error(path: &str, e: io::Error) -> NTSTATUS {
+	error!(path = %path, error = %e, "journal write failed");
+	STATUS_IO_DEVICE_ERROR
+}
+
+// 提交暂存内容，也在后台上传的线程中调用，所以只使用可以共享的状态
+fn commit_content(
+	backend: &dyn StorageBackend,
+	attrs: &AttrCache,
+	metrics: &Metrics,
+	path: &str,
+	stream: Option<&str>,
+	staged: &Mutex<Option<StagedContent>>,
+) -> OperationResult<()> {
+	let mut staged = staged.lock().unwrap();
+	if let Some(content) = staged.as_mut() {
+		if content.dirty {
+			let result = if let Some(stream) = stream {
+				backend.put_xattr(path, stream, &content.data)
+			} else {
+				let result = backend.commit(path, &content.data);
+				attrs.invalidate(path);
+				result.inspect(|_| metrics.add_written(content.data.len()))
+			};
+			result.map_err(|e| {
+				error!(path = %path, error = %e, "commit_file_data failed");
+				e.to_ntstatus()
+			})?;
+			content.dirty = false;
+			if let Some(journal) = &mut content.journal {
+				let version = match stream {
+					Some(_) => journal::Version::Unknown,
+					None => backend
+						.stat(path)
+						.map_or(journal::Version::Unknown, |info| {
+							journal::Version::of(Some(&info))
+						}),
+				};
+				if let Err(e) = journal.reset(version) {
+					error!(path = %path, error = %e, "journal reset failed");
+				}
+			}
+		}
+		let times = std::mem::take(&mut content.times);
+		if !times.is_empty() {
+			let result = backend.set_times(path, &times);
+			attrs.invalidate(path);
+			if let Err(e) = result {
+				error!(path = %path, error = %e, "set_times_remote failed");
+			}
+		}
+	}
+	Ok(())
+}
+
+fn discard_journal(staged: &Mutex<Option<StagedContent>>) {
+	if let Some(content) = staged.lock().unwrap().as_mut() {
+		content.discard_journal();
+	}
+}
+
+// 打开的文件的暂存内容，没有暂存时为 None
+type Staged = Arc<Mutex<Option<StagedContent>>>;
+
+// 要设置的时间戳（秒），None 表示保持不变
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct TimesUpdate {
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub created: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub accessed: Option<u64>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub modified: Option<u64>,
+}
+
+impl TimesUpdate {
+	pub fn is_empty(&self) -> bool {
+		self.created.is_none() && self.accessed.is_none() && self.modified.is_none()
+	}
+
+	fn merge(&mut self, newer: TimesUpdate) {
+		self.created = newer.created.or(self.created);
+		self.accessed = newer.accessed.or(self.accessed);
+		self.modified = newer.modified.or(self.modified);
+	}
+}
+
+// 超过该大小的暂存内容不再保留在内存中，改为直接写入服务器
+const MAX_STAGED_SIZE: usize = 64 * 1024 * 1024;
+
+// 强制卸载时检查打开的文件是否都已关闭的间隔
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// 备用数据流映射为服务器上的扩展属性，大小受服务器限制
+const MAX_STREAM_SIZE: usize = 64 * 1024;
+
+// 至少这么长的全 0 写入改为请求存储把这段置 0，支持稀疏文件的存储不为它分配空间
+const MIN_ZERO_RANGE: usize = 64 * 1024;
+
+// 固定在本地的文件的属性，winapi 没有定义
+const FILE_ATTRIBUTE_PINNED: u32 = 0x0008_0000;
+
+#[derive(Debug, Deserialize)]
+pub struct XattrEntry {
+	pub name: String,
+	pub size: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListPage {
+	pub items: Vec<RemoteFileInfo>,
+	pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchHit {
+	pub path: String,
+	#[serde(flatten)]
+	pub info: RemoteFileInfo,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchResponse {
+	pub hits: Vec<SearchHit>,
+	pub truncated: bool,
+}
+
+// 共享的容量信息；服务器设置了配额时 total 为配额，available 为剩余配额
+#[derive(Debug, Deserialize)]
+pub struct SpaceResponse {
+	pub total: u64,
+	pub available: u64,
+}
+
+// 服务器回收站中的一个条目，path 为删除前的路径
+#[derive(Debug, Deserialize)]
+pub struct TrashEntry {
+	pub id: String,
+	pub path: String,
+	pub is_directory: bool,
+	pub size: u64,
+	pub deleted: u64,
+}
+
+// 服务器为文件保存的一个历史版本
+#[derive(Debug, Deserialize)]
+pub struct VersionInfo {
+	pub id: String,
+	pub size: u64,
+	pub modified: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChecksumResponse {
+	pub digest: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreResponse {
+	pub path: String,
+}
+
+// 审计日志的查询条件，未给出的条件不限制
+#[derive(Debug, Default, Serialize)]
+pub struct AuditFilter {
+	// 只查询这个文件或目录之下的修改（包括重命名的目标）
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub path: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub user: Option<String>,
+	// 只查询这个时间（Unix 秒）及之后的修改
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub since: Option<u64>,
+	// 最多返回最近的多少条
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub limit: Option<usize>,
+}
+
+// 服务器审计日志中的一次修改
+#[derive(Debug, Deserialize)]
+pub struct AuditEntry {
+	pub seq: u64,
+	pub time: u64,
+	pub user: Option<String>,
+	pub client: Option<String>,
+	pub action: String,
+	pub path: String,
+	pub new_path: Option<String>,
+	pub hash: String,
+}
+
+// 审计日志的查询结果；verified 为 false 时日志从第 broken_at 行起被改动过，head 为最后一条的哈希
+#[derive(Debug, Deserialize)]
+pub struct AuditResponse {
+	pub entries: Vec<AuditEntry>,
+	pub verified: bool,
+	pub broken_at: Option<u64>,
+	pub head: String,
+}
+
+/// Dokan 为每个打开的文件或目录保存的状态。
+pub struct FileContext {
+	path: String,
+	// 打开的备用数据流（`file.txt:name`），内容整体暂存在 staged 中
+	stream: Option<String>,
+	delete_on_close: bool,
+	// 与打开文件表共享，强制卸载前可以提交
+	staged: Staged,
+	// 打开的是 .snapshots 下的只读条目
+	snapshot: Option<SnapshotNode>,
+	// 打开文件的进程，由 create_file 设置
+	requester: Requester,
+	// 请求者映射到的服务器用户，之后对这个文件的请求都代表该用户
+	user: Option<String>,
+	// 按打开文件的进程选择的缓存方式
+	policy: CachePolicy,
+	// 在打开文件表中的一项，上下文释放时移除
+	open: Option<OpenHandle>,
+}
+
+impl FileContext {
+	/// 打开文件的进程。
+	pub fn requester(&self) -> &Requester {
+		&self.requester
+	}
+
+	/// 请求者映射到的服务器用户，见 [`HttpFsHandler::with_user_map`]。
+	pub fn user(&self) -> Option<&str> {
+		self.user.as_deref()
+	}
+
+	/// 打开的文件使用的缓存方式。
+	pub fn cache_policy(&self) -> CachePolicy {
+		self.policy
+	}
+
+	fn new(path: String, delete_on_close: bool) -> Self {
+		Self {
+			path,
+			stream: None,
+			delete_on_close,
+			staged: Arc::new(Mutex::new(None)),
+			snapshot: None,
+			requester: Requester::default(),
+			user: None,
+			policy: CachePolicy::default(),
+			open: None,
+		}
+	}
+
+	fn new_snapshot(path: String, node: SnapshotNode) -> Self {
+		Self {
+			path,
+			stream: None,
+			delete_on_close: false,
+			staged: Arc::new(Mutex::new(None)),
+			snapshot: Some(node),
+			requester: Requester::default(),
+			user: None,
+			policy: CachePolicy::default(),
+			open: None,
+		}
+	}
+
+	// 新建或覆盖打开的文件：从空内容开始暂存，原文件在提交前保持不变
+	fn new_staged(path: String, delete_on_close: bool) -> Self {
+		Self {
+			path,
+			stream: None,
+			delete_on_close,
+			staged: Arc::new(Mutex::new(Some(StagedContent {
+				data: Vec::new(),
+				dirty: true,
+				times: TimesUpdate::default(),
+				journal: None,
+			}))),
+			snapshot: None,
+			requester: Requester::default(),
+			user: None,
+			policy: CachePolicy::default(),
+			open: None,
+		}
+	}
+
+	fn new_stream(
+		path: String,
+		stream: String,
+		data: Vec<u8>,
+		dirty: bool,
+		delete_on_close: bool,
+	) -> Self {
+		Self {
+			path,
+			stream: Some(stream),
+			delete_on_close,
+			staged: Arc::new(Mutex::new(Some(StagedContent {
+				data,
+				dirty,
+				times: TimesUpdate::default(),
+				journal: None,
+			}))),
+			snapshot: None,
+			requester: Requester::default(),
+			user: None,
+			policy: CachePolicy::default(),
+			open: None,
+		}
+	}
+
+	fn add_read(&self, bytes: usize) {
+		if let Some(open) = &self.open {
+			open.add_read(bytes);
+		}
+	}
+
+	fn add_written(&self, bytes: usize) {
+		if let Some(open) = &self.open {
+			open.add_written(bytes);
+		}
+	}
+
+	// 暂存内容允许的最大大小，备用数据流不能退回到直接写入
+	fn staged_limit(&self) -> usize {
+		if self.stream.is_some() {
+			MAX_STREAM_SIZE
+		} else {
+			MAX_STAGED_SIZE
+		}
+	}
+}
+
+// 驱动给出的偏移和大小是 i64，负值不是文件中的位置
+fn file_offset(offset: i64) -> OperationResult<u64> {
+	u64::try_from(offset).map_err(|_| STATUS_INVALID_PARAMETER)
+}
+
+// 暂存内容中的位置；超出 usize 的偏移在 32 位系统上不能截断，必然超过暂存上限
+fn staged_offset(offset: u64) -> usize {
+	usize::try_from(offset).unwrap_or(usize::MAX)
+}
+
+// 把 `dir/file.txt:name:$DATA` 拆分为文件路径和流名称，主数据流返回 None
+fn split_stream(path: String) -> OperationResult<(String, Option<String>)> {
+	let Some((base, stream)) = path.split_once(':') else {
+		return Ok((path, None));
+	};
+	let (name, stream_type) = stream.split_once(':').unwrap_or((stream, ""));
+	if !stream_type.is_empty() && !stream_type.eq_ignore_ascii_case("$DATA") {
+		return Err(STATUS_OBJECT_NAME_INVALID);
+	}
+	if name.is_empty() {
+		Ok((base.to_string(), None))
+	} else {
+		Ok((base.to_string(), Some(name.to_string())))
+	}
+}
+
+/// 检查访问令牌能否放进 `Authorization` 请求头：读取设置时调用，令牌含有换行等字符时直接报告设置错误，
+/// 不必等到第一次请求。
+pub fn check_token(token: &str) -> Result<(), RemoteError> {
+	auth_headers(Some(token)).map(|_| ())
+}
+
+// 携带访问令牌的默认请求头
+fn auth_headers(token: Option<&str>) -> Result<HeaderMap, RemoteError> {
+	let mut headers = HeaderMap::new();
+	if let Some(token) = token {
+		let mut value = HeaderValue::from_str(&format!("Bearer {}", token)).map_err(|_| {
+			RemoteError::backend(
+				"invalid_input",
+				"the access token contains characters that are not allowed in an HTTP header",
+			)
+		})?;
+		value.set_sensitive(true);
+		headers.insert(AUTHORIZATION, value);
+	}
+	Ok(headers)
+}
+
+/// 在存储后端之上实现的文件系统：Dokan 的回调经过属性缓存访问存储，整文件保存时暂存内容并以原子写入提交，
+/// 备用数据流映射为扩展属性，按设置在根目录下提供只读的 `.snapshots` 伪目录。
+///
+/// 同一个处理器也实现了 [`VirtualFs`]，可以交给 FUSE、WinFsp 和 ProjFS 的适配器。
+pub struct HttpFsHandler {
+	// 与后台上传的线程共享
+	backend: Arc<dyn StorageBackend>,
+	// 在根目录下显示只读的 .snapshots 伪目录
+	snapshots: bool,
+	// 与事件订阅线程共享，收到变化时使对应条目失效
+	attrs: Arc<AttrCache>,
+	// 与控制管道和指标导出线程共享
+	metrics: Arc<Metrics>,
+	// 包在每个 Dokan 回调外的钩子
+	hooks: Hooks,
+	// 打开文件时按路径、进程和用户检查的访问规则
+	access: AccessRules,
+	// 请求者的 SID 到服务器用户的映射，未设置时所有请求按访问令牌本身的权限处理
+	users: Option<UserMap>,
+	// 按打开文件的进程选择缓存方式
+	policies: ProcessPolicies,
+	// 与控制管道共享，列出当前打开的文件
+	open_files: Arc<OpenFiles>,
+	// 强制卸载开始后置位，之后的打开返回 STATUS_DEVICE_NOT_CONNECTED
+	draining: AtomicBool,
+	// 整文件保存的预写日志，未启用时暂存内容只在内存中
+	journal: Option<Journal>,
+	// 关闭的文件在后台并行提交，未启用时在关闭时同步提交
+	uploads: Option<UploadQueue>,
+	// 关闭的小文件合并为一个请求提交，未启用时每个文件单独提交
+	packer: Option<Packer>,
+	// 固定和下载过的文件的本地副本，未启用时不显示占位符属性
+	offline: Option<Arc<OfflineCache>>,
+	// 与控制管道共享，列出重放日志时发现的冲突
+	conflicts: Arc<Conflicts>,
+	// 打开的路径取得服务器的租约，持有期间属性缓存不过期，未启用时只按期限缓存
+	leases: Option<Arc<Leases>>,
+}
+
+impl HttpFsHandler {
+	/// 以 `backend` 为存储构造处理器，条目的属性缓存 `attr_ttl`；`snapshots` 为 true 时提供 `.snapshots` 伪目录。
+	pub fn new(backend: Box<dyn StorageBackend>, snapshots: bool, attr_ttl: Duration) -> Self {
+		Self {
+			backend: Arc::from(backend),
+			snapshots,
+			attrs: Arc::new(AttrCache::new(attr_ttl)),
+			metrics: Arc::new(Metrics::new()),
+			hooks: Hooks::new(),
+			access: AccessRules::default(),
+			users: None,
+			policies: ProcessPolicies::default(),
+			open_files: Arc::new(OpenFiles::new()),
+			draining: AtomicBool::new(false),
+			journal: None,
+			uploads: None,
+			packer: None,
+			offline: None,
+			conflicts: Arc::default(),
+			leases: None,
+		}
+	}
+
+	/// 在每个 Dokan 回调前后调用 `hooks` 中的钩子，见 [`OperationHook`]。
+	pub fn with_hooks(mut self, hooks: Hooks) -> Self {
+		self.hooks = hooks;
+		self
+	}
+
+	/// 打开文件时按 `rules` 检查请求者能否进行所请求的操作，被拒绝的打开返回 `STATUS_ACCESS_DENIED`。
+	pub fn with_access_rules(mut self, rules: AccessRules) -> Self {
+		self.access = rules;
+		self
+	}
+
+	/// 按 `users` 把打开文件的 Windows 用户映射为服务器上的用户，对打开的文件的请求（包括关闭后在后台的提交）都代表该用户，
+	/// 服务器按共享中为该用户配置的权限处理；映射不到用户的打开返回 `STATUS_ACCESS_DENIED`。只有 httpfs 服务器支持。
+	/// 属性缓存在用户之间共享，设置映射时应关闭属性缓存（`attr_ttl` 为 0）。
+	pub fn with_user_map(mut self, users: UserMap) -> Self {
+		self.users = Some(users);
+		self
+	}
+
+	/// 整文件保存暂存的每次修改先写入 `journal` 并落盘再确认，客户端崩溃或提交失败时由下次挂载重放，见 [`HttpFsHandler::replay_journal`]。
+	pub fn with_journal(mut self, journal: Journal) -> Self {
+		self.journal = Some(journal);
+		self
+	}
+
+	/// 关闭的文件的暂存内容由 `workers` 个线程在后台并行提交，关闭不再等待上传；同一文件的提交按顺序进行，
+	/// 再次打开或 flush 该文件前等待它之前的提交完成。见 [`UploadQueue`]。
+	pub fn with_upload_workers(mut self, workers: usize) -> Self {
+		self.uploads = Some(UploadQueue::new(workers));
+		self
+	}
+
+	/// 按 `limits` 限制属性缓存的条目数和占用的内存，选择换出方式并固定匹配的路径，见 [`CacheLimits`]。
+	pub fn with_cache_limits(self, limits: CacheLimits) -> Self {
+		self.attrs.set_limits(limits);
+		self
+	}
+
+	/// 固定在 `cache` 中的文件在后台下载到本地并保持最新，服务器不可达时从本地副本列出和读取；其他文件显示为占位符，
+	/// 第一次读取时下载，见 [`OfflineCache`]。
+	/// 需在 [`HttpFsHandler::with_upload_workers`] 和 [`HttpFsHandler::with_small_file_packing`] 之前调用，后台提交的修改才会同步到副本。
+	pub fn with_offline_cache(mut self, cache: Arc<OfflineCache>) -> Self {
+		cache.spawn_sync(self.backend.clone());
+		self.backend = Arc::new(OfflineBackend::new(self.backend, cache.clone()));
+		self.offline = Some(cache);
+		self
+	}
+
+	/// 识别 Office 等程序“写临时文件 + 改名 + 删除”的保存：名称匹配 `patterns`（如 [`atomic_save::DEFAULT_PATTERNS`]）的新文件先只在本地，
+	/// 改名为原文件时以一次原子替换提交，存储中只多一个历史版本，见 [`AtomicSaveBackend`]。
+	/// 需在 [`HttpFsHandler::with_upload_workers`] 和 [`HttpFsHandler::with_small_file_packing`] 之前调用。
+	pub fn with_atomic_saves(mut self, patterns: Vec<String>) -> Self {
+		self.backend = Arc::new(AtomicSaveBackend::new(self.backend, patterns));
+		self
+	}
+
+	/// 关闭的小文件（不超过 [`MAX_PACKED_FILE_SIZE`]）的内容和时间戳由 [`Packer`] 合并为一个请求提交，
+	/// 复制大量小文件时省去每个文件一次的往返；文件仍在打开时创建。存储不支持批量提交时逐个提交。
+	pub fn with_small_file_packing(mut self) -> Self {
+		self.packer = Some(Packer::new(
+			self.backend.clone(),
+			self.attrs.clone(),
+			self.metrics.clone(),
+		));
+		self
+	}
+
+	/// 把上次挂载留在 `journal` 中、没有提交的保存提交到存储，返回提交的文件数；提交失败的日志保留到下一次。
+	/// 存储中的文件在保存之后被修改过时，保存的内容写入冲突副本，通过控制管道列出。
+	pub fn replay_journal(&self, journal: &Journal) -> io::Result<usize> {
+		let mut replayed = 0;
+		for save in journal.pending()? {
+			let path = save.path.clone();
+			match save.replay(self.backend.as_ref()) {
+				Ok(result) => {
+					self.attrs.invalidate(&path);
+					self.metrics.add_written(result.bytes);
+					if let Some(copy) = result.conflict {
+						warn!(
+							path = %path,
+							copy = %copy,
+							"the file changed in storage since it was saved, kept the save as a conflict copy"
+						);
+						self.attrs.invalidate(&copy);
+						let detected = SystemTime::now()
+							.duration_since(UNIX_EPOCH)
+							.map_or(0, |since| since.as_secs());
+						self.conflicts.record(Conflict {
+							path,
+							copy,
+							detected,
+						});
+					}
+					replayed += 1;
+				}
+				Err(e) => error!(path = %path, error = %e, "journal replay failed"),
+			}
+		}
+		Ok(replayed)
+	}
+
+	/// 打开文件或目录时通过 `leases` 取得服务器的租约，以写方式打开时为写租约；持有租约的路径的属性一直缓存，
+	/// 其他客户端以写方式打开时服务器召回租约，缓存随之失效。只有 httpfs 服务器支持，见 [`Leases`]。
+	pub fn with_leases(mut self, leases: Arc<Leases>) -> Self {
+		self.leases = Some(leases);
+		self
+	}
+
+	/// 按 `policies` 为各进程打开的文件选择缓存方式，例如不暂存数据库引擎的写入。
+	pub fn with_process_policies(mut self, policies: ProcessPolicies) -> Self {
+		self.policies = policies;
+		self
+	}
+
+	fn normalize_path(&self, file_name: &U16CStr) -> String {
+		vfs::share_path(&file_name.to_string_lossy())
+	}
+
+	// 优先使用属性缓存中的信息
+	fn get_remote_file_info(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		if let Some(info) = self.attrs.get(path) {
+			return Ok(info);
+		}
+		let info = self.fetch_remote_file_info(path)?;
+		self.attrs.insert(path.to_string(), info.clone());
+		Ok(info)
+	}
+
+	// 不经过缓存，总是向服务器查询
+	fn fetch_remote_file_info(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		self.backend.stat(path)
+	}
+
+	// 按打开文件的进程的缓存方式查询属性
+	fn file_info_for(
+		&self,
+		path: &str,
+		policy: CachePolicy,
+	) -> Result<RemoteFileInfo, RemoteError> {
+		if policy.attr_cache {
+			self.get_remote_file_info(path)
+		} else {
+			self.fetch_remote_file_info(path)
+		}
+	}
+
+	fn read_version_data(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let data = self.backend.read_version(path, id, offset, length)?;
+		self.metrics.add_read(data.len());
+		Ok(data)
+	}
+
+	fn read_file_data(
+		&self,
+		path: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		let data = self.backend.read(path, offset, length)?;
+		self.metrics.add_read(data.len());
+		Ok(data)
+	}
+
+	// 直接读进驱动给出的缓冲区，不经过中间的 Vec
+	fn read_file_into(
+		&self,
+		path: &str,
+		offset: u64,
+		buffer: &mut [u8],
+	) -> Result<usize, RemoteError> {
+		let len = self.backend.read_into(path, offset, buffer)?;
+		self.metrics.add_read(len);
+		Ok(len)
+	}
+
+	fn write_file_data(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		let result = if data.len() >= MIN_ZERO_RANGE && data.iter().all(|&b| b == 0) {
+			self.backend.zero_range(path, offset, data.len() as u64)
+		} else {
+			self.backend.write(path, offset, data)
+		};
+		self.attrs.invalidate(path);
+		result?;
+		self.metrics.add_written(data.len());
+		Ok(())
+	}
+
+	// 将暂存内容原子提交到服务器；先等待同一文件已关闭的句柄在后台的提交，保持提交顺序
+	fn commit_staged(&self, context: &FileContext) -> OperationResult<()> {
+		self.wait_for_commits(&context.path);
+		self.commit_content(&context.path, context.stream.as_deref(), &context.staged)
+	}
+
+	fn commit_content(
+		&self,
+		path: &str,
+		stream: Option<&str>,
+		staged: &Mutex<Option<StagedContent>>,
+	) -> OperationResult<()> {
+		commit_content(
+			self.backend.as_ref(),
+			&self.attrs,
+			&self.metrics,
+			path,
+			stream,
+			staged,
+		)
+	}
+
+	// 等待同一文件已关闭的句柄在后台或批量提交中的提交完成
+	fn wait_for_commits(&self, path: &str) {
+		if let Some(uploads) = &self.uploads {
+			uploads.wait_for(path);
+		}
+		if let Some(packer) = &self.packer {
+			packer.wait_for(path);
+		}
+	}
+
+	fn wait_for_all_commits(&self) {
+		if let Some(uploads) = &self.uploads {
+			uploads.wait_idle();
+		}
+		if let Some(packer) = &self.packer {
+			packer.wait_idle();
+		}
+	}
+
+	// 关闭时提交暂存内容：修改过的小文件交给批量提交，启用后台上传时交给上传线程，成功提交后删除日志；
+	// 提交失败时日志留在磁盘上，下次挂载时重放
+	fn commit_closed(&self, context: &FileContext) {
+		if let Some(packer) = &self.packer {
+			let size = match context.staged.lock().unwrap().as_ref() {
+				Some(content)
+					if content.dirty
+						&& context.stream.is_none()
+						&& content.data.len() <= MAX_PACKED_FILE_SIZE =>
+				{
+					Some(content.data.len())
+				}
+				_ => None,
+			};
+			if let Some(size) = size {
+				self.wait_for_commits(&context.path);
+				packer.submit(&context.path, context.staged.clone(), size);
+				return;
+			}
+		}
+		let Some(uploads) = &self.uploads else {
+			if self.commit_staged(context).is_ok() {
+				discard_journal(&context.staged);
+			}
+			return;
+		};
+		if context.staged.lock().unwrap().is_none() {
+			return;
+		}
+		let (backend, attrs, metrics) = (
+			self.backend.clone(),
+			self.attrs.clone(),
+			self.metrics.clone(),
+		);
+		let (path, stream, staged) = (
+			context.path.clone(),
+			context.stream.clone(),
+			context.staged.clone(),
+		);
+		// 后台线程以关闭者的身份提交
+		let user = identity::current_user();
+		uploads.submit(&context.path, move || {
+			identity::with_user(user.as_deref(), || {
+				if commit_content(
+					backend.as_ref(),
+					&attrs,
+					&metrics,
+					&path,
+					stream.as_deref(),
+					&staged,
+				)
+				.is_ok()
+				{
+					discard_journal(&staged);
+				}
+			})
+		});
+	}
+
+	// 强制卸载前调用：拒绝之后的打开，提交打开的文件中暂存的内容，最多等待 timeout 让打开的文件关闭，返回仍打开的文件数
+	pub(crate) fn drain(&self, timeout: Duration) -> usize {
+		self.draining.store(true, Ordering::SeqCst);
+		for (path, stream, staged) in self.open_files.staged() {
+			let _ = self.commit_content(&path, stream.as_deref(), &staged);
+		}
+		self.wait_for_all_commits();
+		let deadline = Instant::now() + timeout;
+		while !self.open_files.is_empty() && Instant::now() < deadline {
+			thread::sleep(DRAIN_POLL_INTERVAL);
+		}
+		self.open_files.len()
+	}
+
+	// 暂存内容过大时放弃原子提交：先截断远程文件，再直接写入已暂存的数据
+	fn spill_staged(
+		&self,
+		context: &FileContext,
+		mut content: StagedContent,
+	) -> OperationResult<()> {
+		self.truncate_file(&context.path, 0)
+			.and_then(|_| self.write_file_data(&context.path, 0, &content.data))
+			.and_then(|_| {
+				if content.times.is_empty() {
+					Ok(())
+				} else {
+					self.set_times_remote(&context.path, &content.times)
+				}
+			})
+			.map_err(|e| {
+				error!(path = %context.path, error = %e, "spill_staged failed");
+				e.to_ntstatus()
+			})?;
+		// 之后的写入直接发送到存储；失败时日志留到下次挂载重放
+		content.discard_journal();
+		Ok(())
+	}
+
+	fn create_remote(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		vfs::check_name_length(path)?;
+		let result = self.backend.create(path, is_directory);
+		self.attrs.invalidate(path);
+		result
+	}
+
+	fn delete_remote(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let result = self.backend.delete(path, dry_run);
+		if !dry_run {
+			self.attrs.invalidate(path);
+		}
+		result
+	}
+
+	fn move_remote(
+		&self,
+		old_path: &str,
+		new_path: &str,
+		replace: bool,
+	) -> Result<(), RemoteError> {
+		vfs::check_name_length(new_path)?;
+		let result = self.backend.rename(old_path, new_path, replace);
+		self.attrs.invalidate(old_path);
+		self.attrs.invalidate(new_path);
+		result
+	}
+
+	fn truncate_file(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		let result = self.backend.truncate(path, size);
+		self.attrs.invalidate(path);
+		result
+	}
+
+	fn set_times_remote(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		let result = self.backend.set_times(path, times);
+		self.attrs.invalidate(path);
+		result
+	}
+
+	// 按访问规则检查请求者对 path 的操作，并取得它映射到的服务器用户；打开文件和重命名在访问存储之前调用
+	fn authorize(
+		&self,
+		path: &str,
+		rights: &[Right],
+		requester: &Requester,
+	) -> OperationResult<Option<String>> {
+		if !self.access.is_empty() {
+			if let Err(right) = self.access.check(path, rights, requester) {
+				warn!(
+					path = %path,
+					pid = requester.pid,
+					process = ?requester.process_name,
+					user = ?requester.user_sid,
+					?right,
+					"access denied by rule"
+				);
+				return Err(STATUS_ACCESS_DENIED);
+			}
+		}
+		match &self.users {
+			Some(users) => match users.user_for(requester.user_sid.as_deref()) {
+				Some(user) => Ok(Some(user.to_string())),
+				None => {
+					warn!(
+						path = %path,
+						pid = requester.pid,
+						user = ?requester.user_sid,
+						"access denied, the requester is not mapped to a server user"
+					);
+					Err(STATUS_ACCESS_DENIED)
+				}
+			},
+			None => Ok(None),
+		}
+	}
+
+	// 重命名相当于删除源路径并写入目标路径，两者都要经过打开文件的进程的访问规则和用户映射
+	fn authorize_move(
+		&self,
+		path: &str,
+		new_path: &str,
+		requester: &Requester,
+	) -> OperationResult<()> {
+		self.authorize(path, &[Right::Delete], requester)?;
+		self.authorize(new_path, &[Right::Write], requester)?;
+		Ok(())
+	}
+
+	// 打开或创建条目，create_file 在检查访问规则之后调用
+	fn open_entry(
+		&self,
+		path: String,
+		stream: Option<String>,
+		create_disposition: u32,
+		create_options: u32,
+		delete_on_close: bool,
+		policy: CachePolicy,
+	) -> OperationResult<CreateFileInfo<FileContext>> {
+		if self.snapshots {
+			if let Some(rest) = snapshots::split(&path) {
+				if stream.is_some() {
+					return Err(STATUS_OBJECT_NAME_NOT_FOUND);
+				}
+				let rest = rest.to_string();
+				return self.open_snapshot(path, &rest, create_disposition, delete_on_close);
+			}
+		}
+
+		if let Some(stream) = stream {
+			return self.open_stream(path, stream, create_disposition, delete_on_close);
+		}
+
+		// 根目录特殊处理：总是存在，总是目录
+		if path == "." {
+			return Ok(CreateFileInfo {
+				context: FileContext::new(path, false),
+				is_dir: true,
+				new_file_created: false,
+			});
+		}
+
+		// 检查远程是否存在，其他错误直接返回
+		let remote_info = match self.file_info_for(&path, policy) {
+			Ok(info) => Some(info),
+			Err(e) if e.code() == Some("not_found") => None,
+			Err(e) => {
+				error!(path = %path, error = %e, "get_remote_file_info (create_file) failed");
+				return Err(e.to_ntstatus());
+			}
+		};
+		let exists = remote_info.is_some();
+
+		// 确定是否是目录
+		let is_directory = if let Some(ref info) = remote_info {
+			info.is_directory
+		} else {
+			create_options & FILE_DIRECTORY_FILE != 0
+		};
+
+		let mut new_file_created = false;
+		// 新建或覆盖的文件视为整文件保存，内容先在本地暂存
+		let mut whole_file_save = false;
+
+		// 根据 create_disposition 处理
+		match create_disposition {
+			FILE_CREATE => {
+				if exists {
+					return Err(STATUS_OBJECT_NAME_COLLISION);
+				}
+				self.create_remote(&path, is_directory).map_err(|e| {
+					error!(path = %path, error = %e, "create_remote failed");
+					e.to_ntstatus()
+				})?;
+				new_file_created = true;
+				whole_file_save = !is_directory;
+			}
+			FILE_OPEN => {
+				if !exists {
+					return Err(STATUS_OBJECT_NAME_NOT_FOUND);
+				}
+			}
+			FILE_OPEN_IF => {
+				if !exists {
+					self.create_remote(&path, is_directory).map_err(|e| {
+						error!(path = %path, error = %e, "create_remote (FILE_OPEN_IF) failed");
+						e.to_ntstatus()
+					})?;
+					new_file_created = true;
+					whole_file_save = !is_directory;
+				}
+			}
+			FILE_OVERWRITE => {
+				if !exists {
+					return Err(STATUS_OBJECT_NAME_NOT_FOUND);
+				}
+				// 不立即截断远程文件，提交时整体替换
+				whole_file_save = !is_directory;
+			}
+			FILE_OVERWRITE_IF | FILE_SUPERSEDE => {
+				if !exists {
+					self.create_remote(&path, is_directory).map_err(|e| {
+						error!(path = %path, error = %e, "create_remote (FILE_OVERWRITE_IF) failed");
+						e.to_ntstatus()
+					})?;
+					new_file_created = true;
+				}
+				whole_file_save = !is_directory;
+			}
+			_ => return Err(STATUS_INVALID_PARAMETER),
+		}
+
+		// 不暂存时覆盖打开的文件需要立即截断，之后的写入直接发送到存储
+		if whole_file_save && !policy.write_back {
+			if exists {
+				self.truncate_file(&path, 0).map_err(|e| {
+					error!(path = %path, error = %e, "truncate_file (create_file) failed");
+					e.to_ntstatus()
+				})?;
+			}
+			whole_file_save = false;
+		}
+
+		let context = if whole_file_save {
+			FileContext::new_staged(path, delete_on_close)
+		} else {
+			FileContext::new(path, delete_on_close)
+		};
+
+		Ok(CreateFileInfo {
+			context,
+			is_dir: is_directory,
+			new_file_created,
+		})
+	}
+
+	// 打开备用数据流：属性值整体读入暂存区，关闭时写回
+	fn open_stream(
+		&self,
+		path: String,
+		stream: String,
+		create_disposition: u32,
+		delete_on_close: bool,
+	) -> OperationResult<CreateFileInfo<FileContext>> {
+		if let Err(e) = self.get_remote_file_info(&path) {
+			return Err(e.to_ntstatus());
+		}
+		let value = self.backend.get_xattr(&path, &stream).map_err(|e| {
+			error!(path = %path, stream = %stream, error = %e, "get_xattr failed");
+			e.to_ntstatus()
+		})?;
+
+		let (data, dirty, new_file_created) = match (create_disposition, value) {
+			(FILE_CREATE, Some(_)) => return Err(STATUS_OBJECT_NAME_COLLISION),
+			(FILE_OPEN | FILE_OVERWRITE, None) => return Err(STATUS_OBJECT_NAME_NOT_FOUND),
+			(FILE_OPEN | FILE_OPEN_IF, Some(value)) => (value, false, false),
+			(FILE_OVERWRITE | FILE_OVERWRITE_IF | FILE_SUPERSEDE, Some(_)) => {
+				(Vec::new(), true, false)
+			}
+			(FILE_CREATE | FILE_OPEN_IF | FILE_OVERWRITE_IF | FILE_SUPERSEDE, None) => {
+				(Vec::new(), true, true)
+			}
+			_ => return Err(STATUS_INVALID_PARAMETER),
+		};
+
+		Ok(CreateFileInfo {
+			context: FileContext::new_stream(path, stream, data, dirty, delete_on_close),
+			is_dir: false,
+			new_file_created,
+		})
+	}
+
+	fn timestamp_to_systime(ts: u64) -> SystemTime {
+		UNIX_EPOCH + Duration::from_secs(ts)
+	}
+
+	// 压缩存储的文件标记为压缩，占用的存储小于长度的文件标记为稀疏
+	fn file_attributes(item: &RemoteFileInfo) -> u32 {
+		if item.is_directory {
+			return winnt::FILE_ATTRIBUTE_DIRECTORY;
+		}
+		let mut attributes = 0;
+		if item.stored_size.is_some() {
+			attributes |= winnt::FILE_ATTRIBUTE_COMPRESSED;
+		}
+		if item
+			.allocated_size
+			.is_some_and(|allocated| allocated < item.size)
+		{
+			attributes |= winnt::FILE_ATTRIBUTE_SPARSE_FILE;
+		}
+		match attributes {
+			0 => winnt::FILE_ATTRIBUTE_NORMAL,
+			_ => attributes,
+		}
+	}
+
+	// 启用本地副本时，内容还没有下载的文件显示为脱机的占位符，资源管理器不会为生成缩略图等读取它们；固定的文件标记为固定
+	fn presented_attributes(&self, path: &str, item: &RemoteFileInfo) -> u32 {
+		let attributes = Self::file_attributes(item);
+		let Some(offline) = self.offline.as_ref().filter(|_| !item.is_directory) else {
+			return attributes;
+		};
+		let placeholder = if offline.is_pinned(path) {
+			FILE_ATTRIBUTE_PINNED
+		} else if offline.is_hydrated(path) {
+			return attributes;
+		} else {
+			winnt::FILE_ATTRIBUTE_OFFLINE | winnt::FILE_ATTRIBUTE_RECALL_ON_DATA_ACCESS
+		};
+		match attributes {
+			winnt::FILE_ATTRIBUTE_NORMAL => placeholder,
+			_ => attributes | placeholder,
+		}
+	}
+
+	fn to_find_data(item: &RemoteFileInfo, attributes: u32) -> FindData {
+		let file_name =
+			U16CString::from_str(&item.name).unwrap_or_else(|_| U16CString::from_str("?").unwrap());
+
+		FindData {
+			attributes,
+			creation_time: Self::timestamp_to_systime(item.created),
+			last_access_time: Self::timestamp_to_systime(item.accessed),
+			last_write_time: Self::timestamp_to_systime(item.modified),
+			file_size: item.size,
+			file_name,
+		}
+	}
+
+	fn find_version(&self, path: &str, id: &str) -> OperationResult<VersionInfo> {
+		let versions = self.backend.list_versions(path).map_err(|e| {
+			error!(path = %path, error = %e, "list_versions_remote failed");
+			e.to_ntstatus()
+		})?;
+		versions
+			.into_iter()
+			.find(|version| version.id == id)
+			.ok_or(STATUS_OBJECT_NAME_NOT_FOUND)
+	}
+
+	// 把 .snapshots 下的路径解析为镜像目录、文件的版本列表或某个版本
+	fn resolve_snapshot(&self, rest: &str) -> OperationResult<SnapshotNode> {
+		if rest.is_empty() {
+			return Ok(SnapshotNode::Directory(".".to_string()));
+		}
+		match self.get_remote_file_info(rest) {
+			Ok(info) if info.is_directory => return Ok(SnapshotNode::Directory(rest.to_string())),
+			Ok(_) => return Ok(SnapshotNode::Versions(rest.to_string())),
+			Err(e) if e.code() == Some("not_found") => {}
+			Err(e) => {
+				error!(path = %rest, error = %e, "get_remote_file_info (snapshot) failed");
+				return Err(e.to_ntstatus());
+			}
+		}
+		let (path, name) = rest.rsplit_once('/').ok_or(STATUS_OBJECT_NAME_NOT_FOUND)?;
+		let id = snapshots::version_id(name, path).ok_or(STATUS_OBJECT_NAME_NOT_FOUND)?;
+		let version = self.find_version(path, id)?;
+		Ok(SnapshotNode::Version {
+			path: path.to_string(),
+			id: version.id,
+		})
+	}
+
+	// .snapshots 下的条目只能打开，不能创建、覆盖或删除
+	fn open_snapshot(
+		&self,
+		path: String,
+		rest: &str,
+		create_disposition: u32,
+		delete_on_close: bool,
+	) -> OperationResult<CreateFileInfo<FileContext>> {
+		let node = match self.resolve_snapshot(rest) {
+			Ok(node) => node,
+			Err(STATUS_OBJECT_NAME_NOT_FOUND)
+				if create_disposition != FILE_OPEN && create_disposition != FILE_OVERWRITE =>
+			{
+				return Err(STATUS_MEDIA_WRITE_PROTECTED);
+			}
+			Err(status) => return Err(status),
+		};
+		match create_disposition {
+			FILE_CREATE => return Err(STATUS_OBJECT_NAME_COLLISION),
+			FILE_OPEN | FILE_OPEN_IF if !delete_on_close => {}
+			_ => return Err(STATUS_MEDIA_WRITE_PROTECTED),
+		}
+		Ok(CreateFileInfo {
+			is_dir: !matches!(node, SnapshotNode::Version { .. }),
+			context: FileContext::new_snapshot(path, node),
+			new_file_created: false,
+		})
+	}
+
+	fn snapshot_information(&self, node: &SnapshotNode) -> OperationResult<FileInfo> {
+		let (attributes, info) = match node {
+			SnapshotNode::Directory(path) | SnapshotNode::Versions(path) => {
+				let info = if path == "." {
+					None
+				} else {
+					Some(
+						self.get_remote_file_info(path)
+							.map_err(|e| e.to_ntstatus())?,
+					)
+				};
+				(
+					winnt::FILE_ATTRIBUTE_DIRECTORY | winnt::FILE_ATTRIBUTE_READONLY,
+					info.map(|info| (info.created, info.accessed, info.modified, 0)),
+				)
+			}
+			SnapshotNode::Version { path, id } => {
+				let version = self.find_version(path, id)?;
+				(
+					winnt::FILE_ATTRIBUTE_READONLY,
+					Some((
+						version.modified,
+						version.modified,
+						version.modified,
+						version.size,
+					)),
+				)
+			}
+		};
+		let (created, accessed, modified, file_size) = match info {
+			Some((created, accessed, modified, size)) => (
+				Self::timestamp_to_systime(created),
+				Self::timestamp_to_systime(accessed),
+				Self::timestamp_to_systime(modified),
+				size,
+			),
+			None => (SystemTime::now(), SystemTime::now(), SystemTime::now(), 0),
+		};
+		Ok(FileInfo {
+			attributes,
+			creation_time: created,
+			last_access_time: accessed,
+			last_write_time: modified,
+			file_size,
+			number_of_links: 1,
+			file_index: 0,
+		})
+	}
+
+	// 镜像目录中的文件显示为目录，版本列表中的每个版本显示为只读文件
+	fn find_snapshot_files(
+		&self,
+		node: &SnapshotNode,
+		mut fill: impl FnMut(&FindData) -> OperationResult<()>,
+	) -> OperationResult<()> {
+		match node {
+			SnapshotNode::Directory(path) => {
+				let mut cursor = None;
+				loop {
+					let page = self
+						.backend
+						.list_page(path, cursor.as_deref())
+						.map_err(|e| {
+							error!(path = %path, error = %e, "list_remote_page (snapshot) failed");
+							e.to_ntstatus()
+						})?;
+					for item in &page.items {
+						let mut data = Self::to_find_data(item, Self::file_attributes(item));
+						data.attributes =
+							winnt::FILE_ATTRIBUTE_DIRECTORY | winnt::FILE_ATTRIBUTE_READONLY;
+						data.file_size = 0;
+						fill(&data)?;
+					}
+					cursor = match page.next_cursor {
+						Some(next) => Some(next),
+						None => break,
+					};
+				}
+			}
+			SnapshotNode::Versions(path) => {
+				let versions = self.backend.list_versions(path).map_err(|e| {
+					error!(path = %path, error = %e, "list_versions_remote failed");
+					e.to_ntstatus()
+				})?;
+				for version in versions {
+					let Ok(file_name) =
+						U16CString::from_str(snapshots::version_name(&version.id, path))
+					else {
+						continue;
+					};
+					let modified = Self::timestamp_to_systime(version.modified);
+					fill(&FindData {
+						attributes: winnt::FILE_ATTRIBUTE_READONLY,
+						creation_time: modified,
+						last_access_time: modified,
+						last_write_time: modified,
+						file_size: version.size,
+						file_name,
+					})?;
+				}
+			}
+			SnapshotNode::Version { .. } => return Err(STATUS_NOT_A_DIRECTORY),
+		}
+		Ok(())
+	}
+}
+
+impl HttpFsHandler {
+	// 在 Dokan 回调的 span 中执行操作，前后调用钩子，记录结果状态并计入统计；span 关闭时按日志设置输出耗时。
+	// file_names 为回调涉及的路径（move_file 为源和目标），只在有钩子时转换为共享内路径；操作以打开文件的 user 的身份执行
+	fn traced<T>(
+		&self,
+		op: &'static str,
+		file_names: &[&U16CStr],
+		user: Option<&str>,
+		span: Span,
+		operation: impl FnOnce() -> OperationResult<T>,
+	) -> OperationResult<T> {
+		let _entered = span.enter();
+		let started = Instant::now();
+		let paths: Vec<String> = if self.hooks.is_empty() {
+			Vec::new()
+		} else {
+			file_names
+				.iter()
+				.map(|name| self.normalize_path(name))
+				.collect()
+		};
+		let hooked = Operation {
+			name: op,
+			path: paths.first().map(String::as_str),
+			new_path: paths.get(1).map(String::as_str),
+		};
+		let (allowed, called) = self.hooks.before(&hooked);
+		let result = allowed.and_then(|()| identity::with_user(user, operation));
+		let elapsed = started.elapsed();
+		let status = result.as_ref().map(|_| ()).map_err(|status| *status);
+		self.hooks.after(&hooked, called, status, elapsed);
+		self.metrics.record(op, elapsed, status);
+		match &result {
+			Ok(_) => span.record("status", "success"),
+			Err(status) => span.record("status", format_args!("{:#010x}", *status as u32)),
+		};
+		result
+	}
+}
+
+// 与驱动无关的操作，经过属性缓存并计入统计；FUSE 挂载直接使用，Dokan 的回调在其上处理 Windows 的语义
+impl VirtualFs for HttpFsHandler {
+	// 根目录总是存在
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		if path == "." {
+			let now = SystemTime::now()
+				.duration_since(UNIX_EPOCH)
+				.map_or(0, |elapsed| elapsed.as_secs());
+			return Ok(RemoteFileInfo {
+				name: String::new(),
+				is_directory: true,
+				size: 0,
+				created: now,
+				modified: now,
+				accessed: now,
+				stored_size: None,
+				allocated_size: None,
+			});
+		}
+		self.get_remote_file_info(path)
+	}
+
+	// 列出的条目填入属性缓存
+	fn list(&self, path: &str) -> Result<Vec<RemoteFileInfo>, RemoteError> {
+		let mut items = Vec::new();
+		let mut cursor = None;
+		loop {
+			let page = self.backend.list_page(path, cursor.as_deref())?;
+			for item in &page.items {
+				self.attrs
+					.insert(vfs::child_path(path, &item.name), item.clone());
+			}
+			items.extend(page.items);
+			cursor = match page.next_cursor {
+				Some(next) => Some(next),
+				None => return Ok(items),
+			};
+		}
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		self.read_file_data(path, offset, length)
+	}
+
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		self.read_file_into(path, offset, buffer)
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.write_file_data(path, offset, data)
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.create_remote(path, is_directory)
+	}
+
+	fn remove(&self, path: &str) -> Result<(), RemoteError> {
+		self.delete_remote(path, false)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		self.move_remote(old_path, new_path, replace)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.truncate_file(path, size)
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.set_times_remote(path, times)
+	}
+
+	// 旧版服务器没有 /space，没有容量概念的后端（如 S3）也沿用固定的容量
+	fn space(&self) -> SpaceResponse {
+		self.backend.space().unwrap_or_else(|e| {
+			if e.code() != Some("not_supported") {
+				warn!(error = %e, "space query failed, reporting a fixed capacity");
+			}
+			SpaceResponse {
+				total: 10 * 1024 * 1024 * 1024,
+				available: 5 * 1024 * 1024 * 1024,
+			}
+		})
+	}
+
+	fn read_only(&self) -> bool {
+		self.backend.read_only()
+	}
+}
+
+impl<'c, 'h: 'c> FileSystemHandler<'c, 'h> for HttpFsHandler {
+	type Context = FileContext;
+
+	fn create_file(
+		&'h self,
+		file_name: &U16CStr,
+		_security_context: &IO_SECURITY_CONTEXT,
+		desired_access: winnt::ACCESS_MASK,
+		_file_attributes: u32,
+		_share_access: u32,
+		create_disposition: u32,
+		create_options: u32,
+		info: &mut OperationInfo<'c, 'h, Self>,
+	) -> OperationResult<CreateFileInfo<Self::Context>> {
+		let span = debug_span!(
+			"create_file",
+			path = %file_name.display(),
+			disposition = create_disposition,
+			options = create_options,
+			pid = info.pid(),
+			process = Empty,
+			status = Empty
+		);
+		self.traced("create_file", &[file_name], None, span, || {
+			if self.draining.load(Ordering::SeqCst) {
+				return Err(STATUS_DEVICE_NOT_CONNECTED);
+			}
+			if create_disposition > FILE_MAXIMUM_DISPOSITION {
+				return Err(STATUS_INVALID_PARAMETER);
+			}
+
+			let (path, stream) = split_stream(self.normalize_path(file_name))?;
+			let delete_on_close = create_options & FILE_DELETE_ON_CLOSE != 0;
+
+			// 只有访问规则和用户映射需要用户，查询令牌的开销较大
+			let needs_user = !self.access.is_empty() || self.users.is_some();
+			let requester = Requester::new(
+				info.pid(),
+				if needs_user {
+					info.requester_token()
+				} else {
+					None
+				},
+			);
+			if let Some(name) = &requester.process_name {
+				Span::current().record("process", name.as_str());
+			}
+			let creates = matches!(
+				create_disposition,
+				FILE_CREATE | FILE_OPEN_IF | FILE_OVERWRITE | FILE_OVERWRITE_IF | FILE_SUPERSEDE
+			);
+			let rights = access::requested_rights(desired_access, creates, delete_on_close);
+			let user = self.authorize(&path, &rights, &requester)?;
+
+			// 同一文件之前关闭的句柄还在后台提交时，等它完成后再打开
+			self.wait_for_commits(&path);
+			let policy = self.policies.cache_policy(&requester);
+			let mut created = identity::with_user(user.as_deref(), || {
+				self.open_entry(
+					path,
+					stream,
+					create_disposition,
+					create_options,
+					delete_on_close,
+					policy,
+				)
+			})?;
+			// 备用数据流和 .snapshots 中的条目没有自己的租约
+			if let Some(leases) = &self.leases {
+				if created.context.stream.is_none() && created.context.snapshot.is_none() {
+					leases.acquire(&created.context.path, rights.contains(&Right::Write));
+				}
+			}
+			if let Some(journal) = &self.journal {
+				if let Some(content) = created.context.staged.lock().unwrap().as_mut() {
+					// 新建或覆盖的内容从空开始，打开已有的备用数据流时从存储中的值开始
+					let base = if content.dirty && content.data.is_empty() {
+						journal::Base::Empty
+					} else {
+						journal::Base::Stored
+					};
+					// 记录保存所基于的版本，重放时发现其他客户端在这期间的修改；备用数据流不检查
+					let version = match &created.context.stream {
+						Some(_) => journal::Version::Unknown,
+						None => journal::Version::of(
+							identity::with_user(user.as_deref(), || {
+								self.get_remote_file_info(&created.context.path)
+							})
+							.ok()
+							.as_ref(),
+						),
+					};
+					let file = journal
+						.create(
+							&created.context.path,
+							created.context.stream.as_deref(),
+							base,
+							version,
+						)
+						.map_err(|e| journal_error(&created.context.path, e))?;
+					content.journal = Some(file);
+				}
+			}
+			let context = &created.context;
+			let open = self.open_files.open(
+				context.path.clone(),
+				context.stream.clone(),
+				requester.clone(),
+				rights,
+				context.staged.clone(),
+			);
+			created.context.open = Some(open);
+			created.context.requester = requester;
+			created.context.user = user;
+			created.context.policy = policy;
+			Ok(created)
+		})
+	}
+
+	fn cleanup(
+		&'h self,
+		file_name: &U16CStr,
+		info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) {
+		let _span = debug_span!("cleanup", path = %file_name.display()).entered();
+		if context.snapshot.is_some() {
+			return;
+		}
+		// 即将删除的文件无需提交暂存内容，直接删除远程文件；
+		// 目录删除是非递归的，期间目录被写入新内容时服务器会拒绝删除
+		identity::with_user(context.user.as_deref(), || {
+			if context.delete_on_close || info.delete_pending() {
+				discard_journal(&context.staged);
+				if let Some(stream) = &context.stream {
+					let _ = self.backend.delete_xattr(&context.path, stream);
+				} else if let Err(e) = self.delete_remote(&context.path, false) {
+					error!(path = %context.path, error = %e, "delete_remote failed");
+				}
+			} else {
+				self.commit_closed(context);
+			}
+		});
+	}
+
+	fn read_file(
+		&'h self,
+		file_name: &U16CStr,
+		offset: i64,
+		buffer: &mut [u8],
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<u32> {
+		let span = debug_span!("read_file", path = %file_name.display(), offset, length = buffer.len(), status = Empty);
+		self.traced(
+			"read_file",
+			&[file_name],
+			context.user.as_deref(),
+			span,
+			|| {
+				let offset = file_offset(offset)?;
+				if let Some(content) = context.staged.lock().unwrap().as_ref() {
+					let start = staged_offset(offset).min(content.data.len());
+					let len = (content.data.len() - start).min(buffer.len());
+					buffer[..len].copy_from_slice(&content.data[start..start + len]);
+					context.add_read(len);
+					return Ok(len as u32);
+				}
+
+				let len = match &context.snapshot {
+					Some(SnapshotNode::Version { path, id }) => self
+						.read_version_data(path, id, offset, buffer.len())
+						.map(|data| {
+							let len = data.len().min(buffer.len());
+							buffer[..len].copy_from_slice(&data[..len]);
+							len
+						}),
+					Some(_) => return Err(STATUS_INVALID_DEVICE_REQUEST),
+					None => self.read_file_into(&context.path, offset, buffer),
+				};
+				let len = len.map_err(|e| {
+					error!(path = %context.path, error = %e, "read_file_data failed");
+					e.to_ntstatus()
+				})?;
+
+				context.add_read(len);
+				Ok(len as u32)
+			},
+		)
+	}
+
+	fn write_file(
+		&'h self,
+		file_name: &U16CStr,
+		offset: i64,
+		buffer: &[u8],
+		info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<u32> {
+		let span = debug_span!("write_file", path = %file_name.display(), offset, length = buffer.len(), status = Empty);
+		self.traced(
+			"write_file",
+			&[file_name],
+			context.user.as_deref(),
+			span,
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				{
+					let mut staged = context.staged.lock().unwrap();
+					if let Some(content) = staged.as_mut() {
+						let start = if info.write_to_eof() {
+							content.data.len()
+						} else {
+							staged_offset(file_offset(offset)?)
+						};
+						if start
+							.checked_add(buffer.len())
+							.is_some_and(|end| end <= context.staged_limit())
+						{
+							content
+								.write(start, buffer)
+								.map_err(|e| journal_error(&context.path, e))?;
+							context.add_written(buffer.len());
+							return Ok(buffer.len() as u32);
+						}
+						if context.stream.is_some() {
+							return Err(STATUS_DISK_FULL);
+						}
+						let content = staged.take().unwrap();
+						self.spill_staged(context, content)?;
+					}
+				}
+
+				let offset = if info.write_to_eof() {
+					// 获取当前文件大小，追加的位置不能使用缓存中可能过时的大小
+					let file_info = self.fetch_remote_file_info(&context.path).map_err(|e| {
+						error!(path = %context.path, error = %e, "get_remote_file_info (write_to_eof) failed");
+						e.to_ntstatus()
+					})?;
+					file_info.size
+				} else {
+					file_offset(offset)?
+				};
+
+				self.write_file_data(&context.path, offset, buffer)
+					.map_err(|e| {
+						error!(path = %context.path, error = %e, "write_file_data failed");
+						e.to_ntstatus()
+					})?;
+				context.add_written(buffer.len());
+
+				Ok(buffer.len() as u32)
+			},
+		)
+	}
+
+	fn flush_file_buffers(
+		&'h self,
+		file_name: &U16CStr,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"flush_file_buffers",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("flush_file_buffers", path = %file_name.display(), status = Empty),
+			|| self.commit_staged(context),
+		)
+	}
+
+	fn get_file_information(
+		&'h self,
+		file_name: &U16CStr,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<FileInfo> {
+		self.traced(
+			"get_file_information",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("get_file_information", path = %file_name.display(), status = Empty),
+			|| {
+				if let Some(node) = &context.snapshot {
+					return self.snapshot_information(node);
+				}
+
+				// 根目录特殊处理
+				if context.path == "." {
+					return Ok(FileInfo {
+						attributes: winnt::FILE_ATTRIBUTE_DIRECTORY,
+						creation_time: SystemTime::now(),
+						last_access_time: SystemTime::now(),
+						last_write_time: SystemTime::now(),
+						file_size: 0,
+						number_of_links: 1,
+						file_index: 0,
+					});
+				}
+
+				let remote_info = self
+				.file_info_for(&context.path, context.policy)
+				.map_err(|e| {
+					error!(path = %context.path, error = %e, "get_remote_file_info (get_file_information) failed");
+					e.to_ntstatus()
+				})?;
+
+				// 备用数据流是普通的数据，没有目录、压缩和稀疏属性
+				let attributes = match context.stream {
+					None => self.presented_attributes(&context.path, &remote_info),
+					Some(_) => winnt::FILE_ATTRIBUTE_NORMAL,
+				};
+
+				let file_size = match context.staged.lock().unwrap().as_ref() {
+					Some(content) => content.data.len() as u64,
+					None => remote_info.size,
+				};
+
+				Ok(FileInfo {
+					attributes,
+					creation_time: Self::timestamp_to_systime(remote_info.created),
+					last_access_time: Self::timestamp_to_systime(remote_info.accessed),
+					last_write_time: Self::timestamp_to_systime(remote_info.modified),
+					file_size,
+					number_of_links: 1,
+					file_index: 0,
+				})
+			},
+		)
+	}
+
+	fn find_files(
+		&'h self,
+		file_name: &U16CStr,
+		mut fill_find_data: impl FnMut(&FindData) -> FillDataResult,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"find_files",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("find_files", path = %file_name.display(), status = Empty),
+			|| {
+				let mut fill = |data: &FindData| {
+					fill_find_data(data).map_err(|e| match e {
+						FillDataError::BufferFull => STATUS_BUFFER_OVERFLOW,
+						FillDataError::NameTooLong => STATUS_SUCCESS,
+					})
+				};
+
+				if let Some(node) = &context.snapshot {
+					return self.find_snapshot_files(node, fill);
+				}
+				if self.snapshots && context.path == "." {
+					let now = SystemTime::now();
+					fill(&FindData {
+						attributes: winnt::FILE_ATTRIBUTE_DIRECTORY
+							| winnt::FILE_ATTRIBUTE_READONLY,
+						creation_time: now,
+						last_access_time: now,
+						last_write_time: now,
+						file_size: 0,
+						file_name: U16CString::from_str(snapshots::SNAPSHOTS_DIR).unwrap(),
+					})?;
+				}
+
+				// 逐页获取并填充，不在内存中保留整个目录
+				let mut cursor = None;
+				loop {
+					let page = self
+						.backend
+						.list_page(&context.path, cursor.as_deref())
+						.map_err(|e| {
+							error!(path = %context.path, error = %e, "list_remote_page (find_files) failed");
+							e.to_ntstatus()
+						})?;
+
+					for item in &page.items {
+						let path = vfs::child_path(&context.path, &item.name);
+						self.attrs.insert(path.clone(), item.clone());
+						fill(&Self::to_find_data(
+							item,
+							self.presented_attributes(&path, item),
+						))?;
+					}
+
+					cursor = match page.next_cursor {
+						Some(next) => Some(next),
+						None => break,
+					};
+				}
+
+				Ok(())
+			},
+		)
+	}
+
+	fn find_files_with_pattern(
+		&'h self,
+		file_name: &U16CStr,
+		pattern: &U16CStr,
+		mut fill_find_data: impl FnMut(&FindData) -> FillDataResult,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		let span = debug_span!(
+			"find_files_with_pattern",
+			path = %file_name.display(),
+			pattern = %pattern.display(),
+			status = Empty
+		);
+		self.traced(
+			"find_files_with_pattern",
+			&[file_name],
+			context.user.as_deref(),
+			span,
+			|| {
+				// .snapshots 及根目录（需要列出 .snapshots）交给 find_files
+				if context.snapshot.is_some() || (self.snapshots && context.path == ".") {
+					return Err(STATUS_NOT_IMPLEMENTED);
+				}
+				let pattern = pattern.to_string_lossy();
+				// "*" 走分页列目录；DOS 通配符（< > "）服务器不支持，交给 Dokan 在 find_files 的结果上匹配
+				if pattern == "*" || pattern.contains(['<', '>', '"']) {
+					return Err(STATUS_NOT_IMPLEMENTED);
+				}
+
+				// 不支持搜索的后端同样交给 find_files
+				let response = self
+					.backend
+					.search(&context.path, &pattern, false)
+					.map_err(|e| match e.code() {
+						Some("not_supported") => STATUS_NOT_IMPLEMENTED,
+						_ => {
+							error!(path = %context.path, error = %e, "search_remote (find_files_with_pattern) failed");
+							e.to_ntstatus()
+						}
+					})?;
+				// 结果被截断时退回到完整列目录
+				if response.truncated {
+					return Err(STATUS_NOT_IMPLEMENTED);
+				}
+
+				for hit in &response.hits {
+					self.attrs.insert(hit.path.clone(), hit.info.clone());
+					fill_find_data(&Self::to_find_data(
+						&hit.info,
+						self.presented_attributes(&hit.path, &hit.info),
+					))
+					.map_err(|e| match e {
+						FillDataError::BufferFull => STATUS_BUFFER_OVERFLOW,
+						FillDataError::NameTooLong => STATUS_SUCCESS,
+					})?;
+				}
+
+				Ok(())
+			},
+		)
+	}
+
+	fn set_file_attributes(
+		&'h self,
+		file_name: &U16CStr,
+		_file_attributes: u32,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"set_file_attributes",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("set_file_attributes", path = %file_name.display(), status = Empty),
+			|| Ok(()),
+		)
+	}
+
+	fn set_file_time(
+		&'h self,
+		file_name: &U16CStr,
+		creation_time: FileTimeOperation,
+		last_access_time: FileTimeOperation,
+		last_write_time: FileTimeOperation,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"set_file_time",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("set_file_time", path = %file_name.display(), status = Empty),
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				// 服务器以秒为单位保存时间戳，1970 年之前的时间忽略
+				let secs = |operation| match operation {
+					FileTimeOperation::SetTime(time) => {
+						time.duration_since(UNIX_EPOCH).ok().map(|d| d.as_secs())
+					}
+					_ => None,
+				};
+				let times = TimesUpdate {
+					created: secs(creation_time),
+					accessed: secs(last_access_time),
+					modified: secs(last_write_time),
+				};
+				if times.is_empty() {
+					return Ok(());
+				}
+				// 复制文件时先写入内容再设置时间戳，未提交的内容等提交后再设置
+				if context.stream.is_none() {
+					if let Some(content) = context.staged.lock().unwrap().as_mut() {
+						if content.dirty {
+							content.times.merge(times);
+							return Ok(());
+						}
+					}
+				}
+				self.set_times_remote(&context.path, &times).map_err(|e| {
+					error!(path = %context.path, error = %e, "set_times_remote failed");
+					e.to_ntstatus()
+				})
+			},
+		)
+	}
+
+	fn delete_file(
+		&'h self,
+		file_name: &U16CStr,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"delete_file",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("delete_file", path = %file_name.display(), status = Empty),
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				Ok(())
+			},
+		)
+	}
+
+	fn delete_directory(
+		&'h self,
+		file_name: &U16CStr,
+		info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"delete_directory",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("delete_directory", path = %file_name.display(), status = Empty),
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				if info.delete_pending() {
+					// 由服务器检查目录是否为空，实际删除在 cleanup 中进行
+					self.delete_remote(&context.path, true)
+						.map_err(|e| match e.code() {
+							Some("directory_not_empty" | "not_found") => e.to_ntstatus(),
+							_ => {
+								error!(path = %context.path, error = %e, "delete_remote (delete_directory) failed");
+								e.to_ntstatus()
+							}
+						})?;
+				}
+
+				Ok(())
+			},
+		)
+	}
+
+	fn move_file(
+		&'h self,
+		file_name: &U16CStr,
+		new_file_name: &U16CStr,
+		replace_if_existing: bool,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		let span = debug_span!(
+			"move_file",
+			path = %file_name.display(),
+			new_path = %new_file_name.display(),
+			replace = replace_if_existing,
+			status = Empty
+		);
+		self.traced(
+			"move_file",
+			&[file_name, new_file_name],
+			context.user.as_deref(),
+			span,
+			|| {
+				// 不支持重命名备用数据流
+				if context.stream.is_some() {
+					return Err(STATUS_NOT_SUPPORTED);
+				}
+				let new_path = self.normalize_path(new_file_name);
+				if context.snapshot.is_some()
+					|| (self.snapshots && snapshots::split(&new_path).is_some())
+				{
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				self.authorize_move(&context.path, &new_path, &context.requester)?;
+				// 目录中或目标位置的文件还在后台提交时，它们会提交到重命名前的路径
+				self.wait_for_all_commits();
+
+				self.move_remote(&context.path, &new_path, replace_if_existing)
+					.map_err(|e| match e.code() {
+						// 允许覆盖时目标仍然冲突，说明目标是非空目录或类型不同
+						Some("already_exists" | "directory_not_empty" | "type_mismatch")
+							if replace_if_existing =>
+						{
+							STATUS_ACCESS_DENIED
+						}
+						Some("already_exists" | "type_mismatch") => STATUS_OBJECT_NAME_COLLISION,
+						Some("not_found" | "parent_not_found") => e.to_ntstatus(),
+						_ => {
+							error!(path = %context.path, new_path = %new_path, error = %e, "move_remote failed");
+							e.to_ntstatus()
+						}
+					})?;
+
+				Ok(())
+			},
+		)
+	}
+
+	fn set_end_of_file(
+		&'h self,
+		file_name: &U16CStr,
+		offset: i64,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"set_end_of_file",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("set_end_of_file", path = %file_name.display(), offset, status = Empty),
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				let size = file_offset(offset)?;
+				{
+					let mut staged = context.staged.lock().unwrap();
+					if let Some(content) = staged.as_mut() {
+						if staged_offset(size) <= context.staged_limit() {
+							content
+								.set_len(staged_offset(size))
+								.map_err(|e| journal_error(&context.path, e))?;
+							return Ok(());
+						}
+						if context.stream.is_some() {
+							return Err(STATUS_DISK_FULL);
+						}
+						let content = staged.take().unwrap();
+						self.spill_staged(context, content)?;
+					}
+				}
+
+				self.truncate_file(&context.path, size).map_err(|e| {
+					error!(path = %context.path, error = %e, "truncate_file (set_end_of_file) failed");
+					e.to_ntstatus()
+				})?;
+
+				Ok(())
+			},
+		)
+	}
+
+	fn set_allocation_size(
+		&'h self,
+		file_name: &U16CStr,
+		alloc_size: i64,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		let span = debug_span!("set_allocation_size", path = %file_name.display(), length = alloc_size, status = Empty);
+		self.traced(
+			"set_allocation_size",
+			&[file_name],
+			context.user.as_deref(),
+			span,
+			|| {
+				if context.snapshot.is_some() {
+					return Err(STATUS_MEDIA_WRITE_PROTECTED);
+				}
+				let size = file_offset(alloc_size)?;
+				// 暂存内容只在分配大小小于文件大小时截断
+				if let Some(content) = context.staged.lock().unwrap().as_mut() {
+					if staged_offset(size) < content.data.len() {
+						content
+							.set_len(staged_offset(size))
+							.map_err(|e| journal_error(&context.path, e))?;
+					}
+					return Ok(());
+				}
+
+				self.truncate_file(&context.path, size).map_err(|e| {
+					error!(path = %context.path, error = %e, "truncate_file (set_allocation_size) failed");
+					e.to_ntstatus()
+				})?;
+
+				Ok(())
+			},
+		)
+	}
+
+	fn get_disk_free_space(
+		&'h self,
+		_info: &OperationInfo<'c, 'h, Self>,
+	) -> OperationResult<DiskSpaceInfo> {
+		self.traced(
+			"get_disk_free_space",
+			&[],
+			None,
+			debug_span!("get_disk_free_space", status = Empty),
+			|| {
+				let space = VirtualFs::space(self);
+				Ok(DiskSpaceInfo {
+					byte_count: space.total,
+					free_byte_count: space.available,
+					available_byte_count: space.available,
+				})
+			},
+		)
+	}
+
+	fn get_volume_information(
+		&'h self,
+		_info: &OperationInfo<'c, 'h, Self>,
+	) -> OperationResult<VolumeInfo> {
+		Ok(VolumeInfo {
+			name: U16CString::from_str("HTTP FS").unwrap(),
+			serial_number: 0x19831116,
+			max_component_length: vfs::MAX_NAME_LENGTH as u32,
+			fs_flags: winnt::FILE_CASE_PRESERVED_NAMES
+				| winnt::FILE_UNICODE_ON_DISK
+				| winnt::FILE_NAMED_STREAMS,
+			fs_name: U16CString::from_str("HTTPFS").unwrap(),
+		})
+	}
+
+	fn find_streams(
+		&'h self,
+		file_name: &U16CStr,
+		mut fill_find_stream_data: impl FnMut(&FindStreamData) -> FillDataResult,
+		_info: &OperationInfo<'c, 'h, Self>,
+		context: &'c Self::Context,
+	) -> OperationResult<()> {
+		self.traced(
+			"find_streams",
+			&[file_name],
+			context.user.as_deref(),
+			debug_span!("find_streams", path = %file_name.display(), status = Empty),
+			|| {
+				let mut fill = |data: &FindStreamData| {
+					fill_find_stream_data(data).or_else(|e| match e {
+						FillDataError::BufferFull => Err(STATUS_BUFFER_OVERFLOW),
+						FillDataError::NameTooLong => Ok(()),
+					})
+				};
+
+				if let Some(node) = &context.snapshot {
+					if let SnapshotNode::Version { path, id } = node {
+						let version = self.find_version(path, id)?;
+						fill(&FindStreamData {
+							size: version.size as i64,
+							name: U16CString::from_str("::$DATA").unwrap(),
+						})?;
+					}
+					return Ok(());
+				}
+
+				if context.path != "." {
+					let remote_info = self.get_remote_file_info(&context.path).map_err(|e| {
+						error!(path = %context.path, error = %e, "get_remote_file_info (find_streams) failed");
+						e.to_ntstatus()
+					})?;
+					if !remote_info.is_directory {
+						fill(&FindStreamData {
+							size: remote_info.size as i64,
+							name: U16CString::from_str("::$DATA").unwrap(),
+						})?;
+					}
+
+					let attrs = self.backend.list_xattrs(&context.path).map_err(|e| {
+						error!(path = %context.path, error = %e, "list_xattrs failed");
+						e.to_ntstatus()
+					})?;
+					for attr in attrs {
+						let Ok(name) = U16CString::from_str(format!(":{}:$DATA", attr.name)) else {
+							continue;
+						};
+						fill(&FindStreamData {
+							size: attr.size as i64,
+							name,
+						})?;
+					}
+				}
+
+				Ok(())
+			},
+		)
+	}
+
+	fn mounted(
+		&'h self,
+		_mount_point: &U16CStr,
+		_info: &OperationInfo<'c, 'h, Self>,
+	) -> OperationResult<()> {
+		Ok(())
+	}
+
+	fn unmounted(&'h self, _info: &OperationInfo<'c, 'h, Self>) -> OperationResult<()> {
+		Ok(())
+	}
+}
@@ -0,0 +1,337 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	fmt::Write as _,
+	io::{self, BufRead, BufReader, Write},
+	net::{SocketAddr, TcpListener, TcpStream},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	thread,
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use winapi::shared::{ntdef::NTSTATUS, ntstatus::*};
+
+use crate::attr_cache::AttrCache;
+
+// 回调耗时直方图的桶上限（秒），与服务器的请求耗时一致
+const LATENCY_BUCKETS: [f64; 11] = [
+	0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+// 保留的最近错误条数
+const RECENT_ERRORS: usize = 10;
+
+// 读取导出端请求头的超时，避免不发送请求的连接阻塞后续抓取
+const SCRAPE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// 一种 Dokan 回调的统计；errors 为返回错误状态（包括文件不存在等正常情况）的次数
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OpStats {
+	pub count: u64,
+	pub errors: u64,
+	pub latency_sum: f64,
+	pub latency_buckets: [u64; LATENCY_BUCKETS.len()],
+}
+
+impl OpStats {
+	pub fn mean_latency(&self) -> Duration {
+		if self.count == 0 {
+			Duration::ZERO
+		} else {
+			Duration::from_secs_f64(self.latency_sum / self.count as f64)
+		}
+	}
+}
+
+// 一次非预期的失败，不包括文件不存在、已到目录末尾等正常情况
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecentError {
+	pub unix_secs: u64,
+	pub op: String,
+	pub status: u32,
+}
+
+// 通过控制管道查询的统计快照
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricsSnapshot {
+	pub uptime_secs: f64,
+	pub ops: BTreeMap<String, OpStats>,
+	pub bytes_read: u64,
+	pub bytes_written: u64,
+	// 最新的在前；旧版本的挂载没有这一项
+	#[serde(default)]
+	pub recent_errors: Vec<RecentError>,
+}
+
+// 应用程序正常探测时就会出现的状态，不计入最近错误
+fn is_expected(status: NTSTATUS) -> bool {
+	matches!(
+		status,
+		STATUS_OBJECT_NAME_NOT_FOUND
+			| STATUS_OBJECT_PATH_NOT_FOUND
+			| STATUS_NO_SUCH_FILE
+			| STATUS_OBJECT_NAME_COLLISION
+			| STATUS_NO_MORE_FILES
+			| STATUS_END_OF_FILE
+			| STATUS_BUFFER_OVERFLOW
+			| STATUS_NOT_IMPLEMENTED
+			| STATUS_DIRECTORY_NOT_EMPTY
+			| STATUS_NOT_A_DIRECTORY
+			| STATUS_FILE_IS_A_DIRECTORY
+	)
+}
+
+// 挂载以来的统计，由 HttpFsHandler 记录，供 status 和本地 Prometheus 导出端读取
+pub struct Metrics {
+	started: Instant,
+	ops: Mutex<BTreeMap<&'static str, OpStats>>,
+	bytes_read: AtomicU64,
+	bytes_written: AtomicU64,
+	recent_errors: Mutex<VecDeque<RecentError>>,
+}
+
+impl Metrics {
+	pub fn new() -> Self {
+		Self {
+			started: Instant::now(),
+			ops: Mutex::new(BTreeMap::new()),
+			bytes_read: AtomicU64::new(0),
+			bytes_written: AtomicU64::new(0),
+			recent_errors: Mutex::new(VecDeque::with_capacity(RECENT_ERRORS)),
+		}
+	}
+
+	pub fn record(&self, op: &'static str, elapsed: Duration, result: Result<(), NTSTATUS>) {
+		if let Err(status) = result {
+			if !is_expected(status) {
+				self.push_error(op, status);
+			}
+		}
+		let seconds = elapsed.as_secs_f64();
+		let mut ops = self.ops.lock().unwrap();
+		let stats = ops.entry(op).or_default();
+		stats.count += 1;
+		if result.is_err() {
+			stats.errors += 1;
+		}
+		stats.latency_sum += seconds;
+		for (bucket, bound) in stats.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+			if seconds <= bound {
+				*bucket += 1;
+			}
+		}
+	}
+
+	fn push_error(&self, op: &'static str, status: NTSTATUS) {
+		let unix_secs = SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map_or(0, |since| since.as_secs());
+		let mut recent = self.recent_errors.lock().unwrap();
+		if recent.len() == RECENT_ERRORS {
+			recent.pop_back();
+		}
+		recent.push_front(RecentError {
+			unix_secs,
+			op: op.to_string(),
+			status: status as u32,
+		});
+	}
+
+	// 从服务器读取的文件内容字节数
+	pub fn add_read(&self, bytes: usize) {
+		self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	// 写入服务器的文件内容字节数（压缩前）
+	pub fn add_written(&self, bytes: usize) {
+		self.bytes_written
+			.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	pub fn snapshot(&self) -> MetricsSnapshot {
+		MetricsSnapshot {
+			uptime_secs: self.started.elapsed().as_secs_f64(),
+			ops: self
+				.ops
+				.lock()
+				.unwrap()
+				.iter()
+				.map(|(op, stats)| (op.to_string(), stats.clone()))
+				.collect(),
+			bytes_read: self.bytes_read.load(Ordering::Relaxed),
+			bytes_written: self.bytes_written.load(Ordering::Relaxed),
+			recent_errors: self.recent_errors.lock().unwrap().iter().cloned().collect(),
+		}
+	}
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(out, "# HELP {} {}", name, help);
+	let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+// 以 Prometheus 文本格式输出，每个指标带 mount 标签
+fn render(mount_point: &str, metrics: &Metrics, attrs: &AttrCache) -> String {
+	let snapshot = metrics.snapshot();
+	let cache = attrs.stats();
+	let mount = mount_point.replace('\\', "\\\\").replace('"', "\\\"");
+	let mut out = String::new();
+
+	header(
+		&mut out,
+		"httpfs_client_operations_total",
+		"counter",
+		"Dokan callbacks handled, by operation.",
+	);
+	for (op, stats) in &snapshot.ops {
+		let _ = writeln!(
+			out,
+			"httpfs_client_operations_total{{mount=\"{}\",op=\"{}\"}} {}",
+			mount, op, stats.count
+		);
+	}
+	header(
+		&mut out,
+		"httpfs_client_operation_errors_total",
+		"counter",
+		"Dokan callbacks that returned an error status, by operation.",
+	);
+	for (op, stats) in &snapshot.ops {
+		let _ = writeln!(
+			out,
+			"httpfs_client_operation_errors_total{{mount=\"{}\",op=\"{}\"}} {}",
+			mount, op, stats.errors
+		);
+	}
+	header(
+		&mut out,
+		"httpfs_client_operation_duration_seconds",
+		"histogram",
+		"Dokan callback latency, by operation.",
+	);
+	for (op, stats) in &snapshot.ops {
+		for (count, bound) in stats.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+			let _ = writeln!(
+				out,
+				"httpfs_client_operation_duration_seconds_bucket{{mount=\"{}\",op=\"{}\",le=\"{}\"}} {}",
+				mount,
+				op,
+				bound,
+				count
+			);
+		}
+		let _ = writeln!(
+			out,
+			"httpfs_client_operation_duration_seconds_bucket{{mount=\"{}\",op=\"{}\",le=\"+Inf\"}} {}",
+			mount,
+			op,
+			stats.count
+		);
+		let _ = writeln!(
+			out,
+			"httpfs_client_operation_duration_seconds_sum{{mount=\"{}\",op=\"{}\"}} {}",
+			mount, op, stats.latency_sum
+		);
+		let _ = writeln!(
+			out,
+			"httpfs_client_operation_duration_seconds_count{{mount=\"{}\",op=\"{}\"}} {}",
+			mount, op, stats.count
+		);
+	}
+
+	let counters = [
+		(
+			"httpfs_client_read_bytes_total",
+			"Bytes of file content read from the server.",
+			snapshot.bytes_read,
+		),
+		(
+			"httpfs_client_written_bytes_total",
+			"Bytes of file content written to the server.",
+			snapshot.bytes_written,
+		),
+		(
+			"httpfs_client_attr_cache_hits_total",
+			"Attribute lookups answered from the cache.",
+			cache.hits,
+		),
+		(
+			"httpfs_client_attr_cache_misses_total",
+			"Attribute lookups that had to ask the server.",
+			cache.misses,
+		),
+	];
+	for (name, help, value) in counters {
+		header(&mut out, name, "counter", help);
+		let _ = writeln!(out, "{}{{mount=\"{}\"}} {}", name, mount, value);
+	}
+	header(
+		&mut out,
+		"httpfs_client_attr_cache_entries",
+		"gauge",
+		"Entries in the attribute cache.",
+	);
+	let _ = writeln!(
+		out,
+		"httpfs_client_attr_cache_entries{{mount=\"{}\"}} {}",
+		mount, cache.entries
+	);
+	out
+}
+
+// 在 addr 上提供 GET /metrics，只应监听本机地址；每个连接处理一个请求
+pub fn serve(
+	addr: SocketAddr,
+	mount_point: String,
+	metrics: Arc<Metrics>,
+	attrs: Arc<AttrCache>,
+) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			let result = stream.and_then(|stream| respond(stream, &mount_point, &metrics, &attrs));
+			if let Err(e) = result {
+				error!(error = %e, "metrics: request failed");
+			}
+		}
+	});
+	Ok(())
+}
+
+fn respond(
+	mut stream: TcpStream,
+	mount_point: &str,
+	metrics: &Metrics,
+	attrs: &AttrCache,
+) -> io::Result<()> {
+	stream.set_read_timeout(Some(SCRAPE_TIMEOUT))?;
+	let mut reader = BufReader::new(&stream);
+	let mut request_line = String::new();
+	reader.read_line(&mut request_line)?;
+	// 读完请求头，忽略其内容
+	let mut line = String::new();
+	while reader.read_line(&mut line)? > 2 {
+		line.clear();
+	}
+	let (status, content_type, body) =
+		match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+			["GET", "/metrics"] => (
+				"200 OK",
+				"text/plain; version=0.0.4",
+				render(mount_point, metrics, attrs),
+			),
+			_ => ("404 Not Found", "text/plain", "not found\n".to_string()),
+		};
+	write!(
+		stream,
+		"HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+		status,
+		content_type,
+		body.len(),
+		body
+	)
+}
@@ -0,0 +1,736 @@
+use std::{
+	error::Error,
+	fmt,
+	net::SocketAddr,
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicBool, Ordering},
+		Arc, Mutex,
+	},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use dokan::{unmount, FileSystemMounter, MountFlags};
+use serde::Deserialize;
+use tracing::{error, warn};
+use widestring::{U16CStr, U16CString};
+
+#[cfg(all(windows, feature = "projfs"))]
+use crate::projfs;
+#[cfg(all(windows, feature = "winfsp"))]
+use crate::winfsp;
+use crate::{
+	access::AccessRules,
+	attr_cache::{CacheLimits, Eviction},
+	auth::OAuth,
+	auth_headers,
+	backend::{self, Remote},
+	control,
+	credentials::Credentials,
+	events::{self, ShutdownPolicy},
+	hooks::Hooks,
+	http2::Http2,
+	identity::UserMap,
+	image::cache::CacheMode,
+	journal::Journal,
+	leases::Leases,
+	metrics, mount_point,
+	names::NameEscaping,
+	offline::OfflineCache,
+	policy::ProcessPolicies,
+	HttpFsHandler, MountConfig,
+};
+
+/// 未设置时条目属性的缓存时间（秒）。
+pub const DEFAULT_ATTR_CACHE_TTL: u64 = 2;
+
+// 等待 Dokan 挂载卸载时检查强制卸载请求的间隔
+const FORCE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
+/// 提供挂载的驱动。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Driver {
+	#[default]
+	Dokan,
+	// 需要以 winfsp 功能编译；只使用 VirtualFs 的操作，没有 .snapshots、备用数据流和变更通知
+	Winfsp,
+	// 需要以 projfs 功能编译；挂载点为虚拟化根目录，条目在第一次访问时从存储取得，本地的修改不写回
+	Projfs,
+}
+
+impl fmt::Display for Driver {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.write_str(match self {
+			Driver::Dokan => "dokan",
+			Driver::Winfsp => "winfsp",
+			Driver::Projfs => "projfs",
+		})
+	}
+}
+
+/// 一个挂载的全部设置。
+#[derive(Debug, Clone)]
+pub struct Mount {
+	pub remote: Remote,
+	pub mount_point: String,
+	pub snapshots: bool,
+	pub attr_cache_ttl: u64,
+	// 属性缓存的容量、换出方式和固定的路径
+	pub attr_cache_limits: CacheLimits,
+	pub events: bool,
+	pub on_shutdown_notice: ShutdownPolicy,
+	pub dokan: MountConfig,
+	pub metrics_addr: Option<SocketAddr>,
+	pub driver: Driver,
+	pub service: bool,
+	// 包在每个 Dokan 回调外的钩子，只用于 Dokan 挂载
+	pub hooks: Hooks,
+	// 打开文件时检查的访问规则文件，只用于 Dokan 挂载
+	pub access_rules: Option<PathBuf>,
+	// 打开文件的 Windows 用户到服务器用户的映射文件，设置时不使用属性缓存，只用于 Dokan 挂载
+	pub user_map: Option<PathBuf>,
+	// 按进程选择的缓存方式，只用于 Dokan 挂载
+	pub process_policies: ProcessPolicies,
+	// 整文件保存的预写日志目录，挂载前重放上次没有提交的保存
+	pub journal: Option<PathBuf>,
+	// 在后台并行提交关闭的文件的线程数，0 表示在关闭时同步提交，只用于 Dokan 挂载
+	pub upload_workers: usize,
+	// 关闭的小文件合并为一个请求提交，只用于 Dokan 挂载
+	pub pack_small_files: bool,
+	// 打开的路径取得 httpfs 服务器的租约，持有期间属性缓存不过期
+	pub leases: bool,
+	// 固定在本地的副本所在的目录，服务器不可达时仍可读取固定的路径
+	pub offline_cache: Option<PathBuf>,
+	// 固定在本地的路径（共享内的文件或目录），需要设置 offline_cache
+	pub pin: Vec<String>,
+	// 视为保存过程中临时文件的名称模式，非空时把 Office 式的保存合并为一次原子替换
+	pub atomic_save_patterns: Vec<String>,
+}
+
+impl Mount {
+	/// 以默认设置把 `remote` 挂载到 `mount_point`（盘符或 NTFS 卷上的空目录）：Dokan 驱动，订阅变更通知，没有 `.snapshots` 和指标。
+	pub fn new(remote: Remote, mount_point: impl Into<String>) -> Self {
+		Self {
+			remote,
+			mount_point: mount_point.into(),
+			snapshots: false,
+			attr_cache_ttl: DEFAULT_ATTR_CACHE_TTL,
+			attr_cache_limits: CacheLimits::default(),
+			events: true,
+			on_shutdown_notice: ShutdownPolicy::default(),
+			dokan: MountConfig::default(),
+			metrics_addr: None,
+			driver: Driver::default(),
+			service: false,
+			hooks: Hooks::new(),
+			access_rules: None,
+			user_map: None,
+			process_policies: ProcessPolicies::default(),
+			journal: None,
+			upload_workers: 0,
+			pack_small_files: false,
+			leases: false,
+			offline_cache: None,
+			pin: Vec::new(),
+			atomic_save_patterns: Vec::new(),
+		}
+	}
+
+	/// 服务命令行中 mount 子命令的参数，不依赖安装时用户的 mounts.toml。
+	pub fn to_args(&self) -> Vec<String> {
+		let mut args = vec!["mount".to_string(), "--service".to_string()];
+		// 取自密钥环的地址和令牌不写入服务的命令行，服务启动时重新读取
+		let stored = self
+			.remote
+			.credential
+			.as_deref()
+			.and_then(|name| Credentials::load(name).ok().flatten())
+			.unwrap_or_default();
+		if let Some(name) = &self.remote.credential {
+			args.extend(["--credential".to_string(), name.clone()]);
+		}
+		if stored.url.as_ref() != Some(&self.remote.server_url) {
+			args.extend(["--url".to_string(), self.remote.server_url.clone()]);
+		}
+		if let Some(share) = &self.remote.share {
+			args.extend(["--share".to_string(), share.clone()]);
+		}
+		if let Some(token) = self
+			.remote
+			.token
+			.as_ref()
+			.filter(|token| stored.token.as_ref() != Some(token))
+		{
+			args.extend(["--token".to_string(), token.clone()]);
+		}
+		if let Some(oauth) = &self.remote.oauth {
+			args.extend([
+				"--oauth-issuer".to_string(),
+				oauth.issuer.clone(),
+				"--oauth-client-id".to_string(),
+				oauth.client_id.clone(),
+			]);
+			if let Some(scope) = &oauth.scope {
+				args.extend(["--oauth-scope".to_string(), scope.clone()]);
+			}
+		}
+		args.extend([
+			"--compression".to_string(),
+			self.remote.compression.to_string(),
+		]);
+		if self.remote.http2 != Http2::Auto {
+			args.extend(["--http2".to_string(), self.remote.http2.to_string()]);
+		}
+		for (flag, value) in [
+			("--s3-endpoint", &self.remote.s3_endpoint),
+			("--s3-region", &self.remote.s3_region),
+			("--aws-profile", &self.remote.aws_profile),
+			("--ssh-key", &self.remote.ssh_key),
+			("--ssh-host-key", &self.remote.ssh_host_key),
+			("--key-file", &self.remote.key_file),
+			("--git-ref", &self.remote.git_ref),
+		] {
+			if let Some(value) = value {
+				args.extend([flag.to_string(), value.clone()]);
+			}
+		}
+		if let Some(capacity) = self.remote.mem_capacity {
+			args.extend(["--mem-capacity".to_string(), capacity.to_string()]);
+		}
+		if let Some(limit) = self.remote.mem_limit {
+			args.extend(["--mem-limit".to_string(), limit.to_string()]);
+		}
+		if let Some(partition) = self.remote.partition {
+			args.extend(["--partition".to_string(), partition.to_string()]);
+		}
+		if let Some(cache) = &self.remote.block_cache {
+			args.extend(["--block-cache".to_string(), cache.size.to_string()]);
+			if cache.mode == CacheMode::WriteBack {
+				args.extend(["--block-cache-mode", "write-back"].map(String::from));
+			}
+			args.extend([
+				"--flush-interval".to_string(),
+				cache.flush_interval.as_secs().to_string(),
+			]);
+		}
+		if let Some(upper) = &self.remote.upper {
+			args.extend(["--upper".to_string(), upper.clone()]);
+		}
+		for lower in &self.remote.lower {
+			args.extend(["--lower".to_string(), lower.clone()]);
+		}
+		for extension in &self.remote.compress_skip {
+			args.extend(["--compress-skip".to_string(), extension.clone()]);
+		}
+		if self.remote.escape_names != NameEscaping::None {
+			args.extend([
+				"--escape-names".to_string(),
+				self.remote
+					.escape_names
+					.to_possible_value()
+					.unwrap()
+					.get_name()
+					.to_string(),
+			]);
+		}
+		args.extend(["--mount-point".to_string(), self.mount_point.clone()]);
+		args.extend([
+			"--attr-cache-ttl".to_string(),
+			self.attr_cache_ttl.to_string(),
+		]);
+		let limits = &self.attr_cache_limits;
+		if let Some(max) = limits.max_entries {
+			args.extend(["--attr-cache-max-entries".to_string(), max.to_string()]);
+		}
+		if let Some(max) = limits.max_bytes {
+			args.extend(["--attr-cache-max-bytes".to_string(), max.to_string()]);
+		}
+		if limits.eviction != Eviction::default() {
+			args.extend([
+				"--attr-cache-eviction".to_string(),
+				limits
+					.eviction
+					.to_possible_value()
+					.unwrap()
+					.get_name()
+					.to_string(),
+			]);
+		}
+		for pattern in &limits.pinned {
+			args.extend(["--attr-cache-pin".to_string(), pattern.clone()]);
+		}
+		if let Some(addr) = self.metrics_addr {
+			args.extend(["--metrics-addr".to_string(), addr.to_string()]);
+		}
+		if let Some(rules) = &self.access_rules {
+			args.extend(["--access-rules".to_string(), rules.display().to_string()]);
+		}
+		if let Some(map) = &self.user_map {
+			args.extend(["--user-map".to_string(), map.display().to_string()]);
+		}
+		if let Some(journal) = &self.journal {
+			args.extend(["--journal".to_string(), journal.display().to_string()]);
+		}
+		if self.upload_workers > 0 {
+			args.extend([
+				"--upload-workers".to_string(),
+				self.upload_workers.to_string(),
+			]);
+		}
+		if self.pack_small_files {
+			args.push("--pack-small-files".to_string());
+		}
+		if self.leases {
+			args.push("--leases".to_string());
+		}
+		if let Some(dir) = &self.offline_cache {
+			args.extend(["--offline-cache".to_string(), dir.display().to_string()]);
+		}
+		for path in &self.pin {
+			args.extend(["--pin".to_string(), path.clone()]);
+		}
+		for pattern in &self.atomic_save_patterns {
+			args.extend(["--atomic-save-pattern".to_string(), pattern.clone()]);
+		}
+		for policy in self.process_policies.iter() {
+			if !policy.cache.write_back {
+				args.extend(["--write-through".to_string(), policy.process.clone()]);
+			}
+			if !policy.cache.attr_cache {
+				args.extend(["--no-attr-cache-for".to_string(), policy.process.clone()]);
+			}
+		}
+		if self.driver != Driver::Dokan {
+			args.extend(["--driver".to_string(), self.driver.to_string()]);
+		}
+		if self.on_shutdown_notice == ShutdownPolicy::Keep {
+			args.extend(["--on-shutdown-notice".to_string(), "keep".to_string()]);
+		}
+		for (set, flag) in [
+			(self.snapshots, "--snapshots"),
+			(!self.events, "--no-events"),
+			(self.remote.s3_path_style, "--s3-path-style"),
+			(self.remote.encrypt, "--encrypt"),
+			(self.remote.encrypt_names, "--encrypt-names"),
+			(self.remote.compress_files, "--compress-files"),
+			(self.remote.dedup, "--dedup"),
+			(self.remote.delta_sync, "--delta-sync"),
+			(
+				self.remote.access_based_enumeration,
+				"--access-based-enumeration",
+			),
+			(self.remote.direct_reads, "--direct-reads"),
+			(self.remote.http3, "--http3"),
+		] {
+			if set {
+				args.push(flag.to_string());
+			}
+		}
+		args.extend(self.dokan.to_args());
+		args
+	}
+}
+
+/// 打开挂载的存储并构造处理器。
+pub fn connect(
+	remote: &Remote,
+	snapshots: bool,
+	attr_ttl: Duration,
+) -> Result<HttpFsHandler, Box<dyn Error>> {
+	Ok(HttpFsHandler::new(
+		backend::open(remote)?,
+		snapshots,
+		attr_ttl,
+	))
+}
+
+/// 强制卸载的请求，由 [`MountHandle::force_unmount`] 或控制管道设置，Dokan 挂载的 [`mount`] 在等待卸载时检查：
+/// 之后的打开返回 `STATUS_DEVICE_NOT_CONNECTED`，暂存的写入立即提交，最多等待请求给出的时间让打开的文件关闭，然后移除挂载点。
+#[derive(Debug, Default)]
+pub struct ForceUnmount(Mutex<Option<Duration>>);
+
+impl ForceUnmount {
+	/// 请求强制卸载，打开的文件最多等待 `timeout` 关闭。
+	pub fn request(&self, timeout: Duration) {
+		*self.0.lock().unwrap() = Some(timeout);
+	}
+
+	fn take(&self) -> Option<Duration> {
+		self.0.lock().unwrap().take()
+	}
+}
+
+/// 挂载并阻塞到文件系统被卸载，调用前后需要分别调用 [`dokan::init`] 和 [`dokan::shutdown`]。
+/// `mounted` 在挂载成功后调用；`stop_events` 置位后不再发出变更通知，WinFsp 和 ProjFS 挂载随之卸载；
+/// `force` 收到请求时强制卸载 Dokan 挂载。
+pub fn mount(
+	args: &Mount,
+	stop_events: Arc<AtomicBool>,
+	force: Arc<ForceUnmount>,
+	mounted: impl FnOnce(),
+) -> Result<(), Box<dyn Error>> {
+	let server_url = args.remote.server_url.clone();
+	let base_url = args.remote.base_url();
+	// 缓存的属性来自某个用户的请求，不能给其他用户看到
+	let attr_ttl = if args.user_map.is_some() {
+		0
+	} else {
+		args.attr_cache_ttl
+	};
+	let mut handler = connect(&args.remote, args.snapshots, Duration::from_secs(attr_ttl))?
+		.with_hooks(args.hooks.clone())
+		.with_cache_limits(args.attr_cache_limits.clone());
+	if let Some(path) = &args.access_rules {
+		let rules = AccessRules::load(path)
+			.map_err(|e| format!("cannot read access rules {}: {}", path.display(), e))?;
+		handler = handler.with_access_rules(rules);
+	}
+	if let Some(path) = &args.user_map {
+		let users = UserMap::load(path)
+			.map_err(|e| format!("cannot read user map {}: {}", path.display(), e))?;
+		handler = handler.with_user_map(users);
+	}
+	if !args.process_policies.is_empty() {
+		handler = handler.with_process_policies(args.process_policies.clone());
+	}
+	if let Some(dir) = &args.journal {
+		let journal = Journal::open(dir, &args.remote)
+			.map_err(|e| format!("cannot open journal {}: {}", dir.display(), e))?;
+		let replayed = handler
+			.replay_journal(&journal)
+			.map_err(|e| format!("cannot read journal {}: {}", journal.dir().display(), e))?;
+		if replayed > 0 {
+			println!("Replayed {} unsaved file(s) from the journal.", replayed);
+		}
+		for conflict in handler.conflicts.list() {
+			println!(
+				"  {} changed in storage meanwhile, the save was kept as {}",
+				conflict.path, conflict.copy
+			);
+		}
+		handler = handler.with_journal(journal);
+	}
+	if let Some(dir) = &args.offline_cache {
+		let cache = OfflineCache::open(dir, &args.remote, &args.pin)
+			.map_err(|e| format!("cannot open offline cache {}: {}", dir.display(), e))?;
+		handler = handler.with_offline_cache(Arc::new(cache));
+	} else if !args.pin.is_empty() {
+		return Err("--pin requires --offline-cache".into());
+	}
+	if !args.atomic_save_patterns.is_empty() {
+		handler = handler.with_atomic_saves(args.atomic_save_patterns.clone());
+	}
+	if args.upload_workers > 0 {
+		handler = handler.with_upload_workers(args.upload_workers);
+	}
+	if args.pack_small_files {
+		handler = handler.with_small_file_packing();
+	}
+	// 服务器的共享支持的功能，其他后端和无法访问的服务器为 None
+	let capabilities = backend::capabilities(&args.remote);
+	if args.leases {
+		if !capabilities
+			.as_ref()
+			.is_some_and(|capabilities| capabilities.supports("leases"))
+		{
+			return Err("--leases requires an httpfs server that supports leases".into());
+		}
+		let leases = Leases::start(&args.remote, handler.attrs.clone(), stop_events.clone())
+			.map_err(|e| format!("cannot open a lease session: {}", e))?;
+		handler = handler.with_leases(leases);
+	}
+	#[cfg(all(windows, any(feature = "winfsp", feature = "projfs")))]
+	if args.driver != Driver::Dokan {
+		return mount_without_dokan(args, handler, stop_events, mounted);
+	}
+	mount_point::check(&args.mount_point)?;
+	let mount_point = U16CString::from_str(&args.mount_point)?;
+
+	// 服务运行在会话 0 中，挂载需要对所有会话可见；只读的后端总是以写保护方式挂载
+	let mut options = args.dokan.options(args.service);
+	if handler.backend.read_only() {
+		options.flags |= MountFlags::WRITE_PROTECT;
+	}
+
+	if let Some(addr) = args.metrics_addr {
+		metrics::serve(
+			addr,
+			args.mount_point.clone(),
+			handler.metrics.clone(),
+			handler.attrs.clone(),
+		)
+		.map_err(|e| format!("cannot serve metrics on {}: {}", addr, e))?;
+	}
+
+	let mut mounter = FileSystemMounter::new(&handler, &mount_point, &options);
+
+	println!("HTTP File System");
+	println!("  Server: {}", server_url);
+	if let Some(share) = &args.remote.share {
+		println!("  Share:  {}", share);
+	}
+	println!("  Mount:  {}", mount_point.to_string_lossy());
+
+	let file_system = mounter.mount()?;
+
+	// 变更通知来自 httpfs 服务器的事件流，其他后端和没有事件流的旧版服务器没有；加密文件名时事件中是加密后的路径，不使用
+	let events = args.events
+		&& args.remote.is_httpfs()
+		&& !args.remote.encrypt_names
+		&& capabilities
+			.as_ref()
+			.map_or(true, |capabilities| capabilities.supports("events"));
+	if events {
+		events::spawn(
+			base_url,
+			auth_headers(args.remote.token.as_deref())?,
+			args.remote.oauth.as_ref().map(OAuth::shared),
+			mount_point.to_string_lossy(),
+			file_system.instance(),
+			handler.attrs.clone(),
+			handler.offline.clone(),
+			handler.open_files.clone(),
+			stop_events.clone(),
+			args.on_shutdown_notice,
+		);
+	}
+	control::serve(control::Controller {
+		mount_point: mount_point.to_string_lossy(),
+		server: server_url,
+		share: args.remote.share.clone(),
+		events,
+		attrs: handler.attrs.clone(),
+		metrics: handler.metrics.clone(),
+		open_files: handler.open_files.clone(),
+		offline: handler.offline.clone(),
+		conflicts: handler.conflicts.clone(),
+		stop_events: stop_events.clone(),
+		force: force.clone(),
+		driver: Driver::Dokan,
+		started: Instant::now(),
+	});
+
+	mounted();
+
+	// 普通的卸载在仍有文件打开或回调阻塞时可能一直等待；强制卸载先排空打开的文件，再移除挂载点。
+	// 释放 file_system 时阻塞到挂载被卸载，强制卸载的请求由另一个线程检查
+	let closed = AtomicBool::new(false);
+	thread::scope(|scope| {
+		scope.spawn(|| {
+			while !closed.load(Ordering::Relaxed) {
+				if let Some(timeout) = force.take() {
+					stop_events.store(true, Ordering::Relaxed);
+					let open = handler.drain(timeout);
+					if open > 0 {
+						warn!(mount_point = %args.mount_point, open, "forcing unmount with files still open");
+					}
+					if !unmount(&mount_point) {
+						error!(mount_point = %args.mount_point, "forced unmount failed");
+					}
+				}
+				thread::sleep(FORCE_POLL_INTERVAL);
+			}
+		});
+		drop(file_system);
+		closed.store(true, Ordering::Relaxed);
+	});
+	stop_events.store(true, Ordering::Relaxed);
+
+	println!("File system on {} is unmounted.", args.mount_point);
+
+	Ok(())
+}
+
+// 以 WinFsp 挂载或以 ProjFS 提供虚拟化根目录，与 mount 一样阻塞到被卸载。两者都由自己的线程处理请求，这里等待 stop_events 被置位；
+// 没有变更通知，控制管道和指标照常提供
+#[cfg(all(windows, any(feature = "winfsp", feature = "projfs")))]
+fn mount_without_dokan(
+	args: &Mount,
+	handler: HttpFsHandler,
+	stop_events: Arc<AtomicBool>,
+	mounted: impl FnOnce(),
+) -> Result<(), Box<dyn Error>> {
+	if let Some(addr) = args.metrics_addr {
+		metrics::serve(
+			addr,
+			args.mount_point.clone(),
+			handler.metrics.clone(),
+			handler.attrs.clone(),
+		)
+		.map_err(|e| format!("cannot serve metrics on {}: {}", addr, e))?;
+	}
+	let controller = control::Controller {
+		mount_point: args.mount_point.clone(),
+		server: args.remote.server_url.clone(),
+		share: args.remote.share.clone(),
+		events: false,
+		attrs: handler.attrs.clone(),
+		metrics: handler.metrics.clone(),
+		open_files: handler.open_files.clone(),
+		offline: handler.offline.clone(),
+		conflicts: handler.conflicts.clone(),
+		stop_events: stop_events.clone(),
+		force: Arc::default(),
+		driver: args.driver,
+		started: Instant::now(),
+	};
+
+	println!("HTTP File System ({})", args.driver);
+	println!("  Server: {}", args.remote.server_url);
+	if let Some(share) = &args.remote.share {
+		println!("  Share:  {}", share);
+	}
+	println!("  Mount:  {}", args.mount_point);
+
+	// drop 时卸载或停止提供
+	let running: Box<dyn std::any::Any> = match args.driver {
+		#[cfg(feature = "winfsp")]
+		Driver::Winfsp => Box::new(winfsp::mount(
+			handler,
+			&mount_point::check_winfsp(&args.mount_point)?,
+		)?),
+		#[cfg(feature = "projfs")]
+		Driver::Projfs => Box::new(projfs::start(
+			handler,
+			&mount_point::check_projfs(&args.mount_point)?,
+		)?),
+		driver => return Err(format!("this httpfs was built without {} support", driver).into()),
+	};
+	control::serve(controller);
+
+	mounted();
+
+	while !stop_events.load(Ordering::Relaxed) {
+		thread::sleep(Duration::from_millis(200));
+	}
+	drop(running);
+
+	println!("File system on {} is unmounted.", args.mount_point);
+
+	Ok(())
+}
+
+/// 请求卸载运行中的挂载并停止发出变更通知：Dokan 挂载由驱动卸载，WinFsp 和 ProjFS 挂载在 [`mount`] 看到 `stop_events` 后停止。
+pub fn request_unmount(mount_point: &U16CStr, driver: Driver, stop_events: &AtomicBool) -> bool {
+	stop_events.store(true, Ordering::Relaxed);
+	driver != Driver::Dokan || unmount(mount_point)
+}
+
+/// 挂载生命周期中的事件，由 [`MountHandle::spawn`] 的回调在挂载线程中接收。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MountEvent {
+	/// 文件系统已挂载，可以访问。
+	Mounted,
+	/// 文件系统已卸载；挂载失败时没有这个事件，原因由 [`MountHandle::wait`] 返回。
+	Unmounted,
+}
+
+/// 在后台线程中运行的挂载，由 [`MountHandle::spawn`] 启动。
+///
+/// 句柄可以在线程间共享：一个线程在 [`wait`](Self::wait) 中等待卸载时，另一个线程（如 Ctrl-C 处理函数）可以调用 [`unmount`](Self::unmount)。
+/// 与 [`mount`] 一样，第一个挂载之前需要调用 [`dokan::init`]，全部卸载之后调用 [`dokan::shutdown`]。
+pub struct MountHandle {
+	mount_point: String,
+	driver: Driver,
+	stop_events: Arc<AtomicBool>,
+	force: Arc<ForceUnmount>,
+	// 挂载成功后置位，mount 返回时清除
+	mounted: Arc<AtomicBool>,
+	thread: Mutex<Option<JoinHandle<Result<(), String>>>>,
+}
+
+impl MountHandle {
+	/// 在新线程中挂载 `mount`，挂载和卸载时在该线程中调用 `on_event`；挂载失败的错误由 [`wait`](Self::wait) 返回。
+	pub fn spawn(mount: Mount, mut on_event: impl FnMut(MountEvent) + Send + 'static) -> Self {
+		let stop_events = Arc::new(AtomicBool::new(false));
+		let force = Arc::new(ForceUnmount::default());
+		let mounted = Arc::new(AtomicBool::new(false));
+		let mount_point = mount.mount_point.clone();
+		let driver = mount.driver;
+		let thread = {
+			let stop_events = stop_events.clone();
+			let force = force.clone();
+			let mounted = mounted.clone();
+			thread::spawn(move || {
+				let result = self::mount(&mount, stop_events, force, || {
+					mounted.store(true, Ordering::SeqCst);
+					on_event(MountEvent::Mounted);
+				});
+				if mounted.swap(false, Ordering::SeqCst) {
+					on_event(MountEvent::Unmounted);
+				}
+				result.map_err(|e| e.to_string())
+			})
+		};
+		Self {
+			mount_point,
+			driver,
+			stop_events,
+			force,
+			mounted,
+			thread: Mutex::new(Some(thread)),
+		}
+	}
+
+	pub fn mount_point(&self) -> &str {
+		&self.mount_point
+	}
+
+	/// 文件系统是否已挂载：挂载完成之前、挂载失败和卸载之后为 false。
+	pub fn is_mounted(&self) -> bool {
+		self.mounted.load(Ordering::SeqCst)
+	}
+
+	/// 挂载线程是否已经结束（卸载完成或挂载失败），此后 [`wait`](Self::wait) 不再阻塞。
+	pub fn is_finished(&self) -> bool {
+		self.thread
+			.lock()
+			.unwrap()
+			.as_ref()
+			.map_or(true, JoinHandle::is_finished)
+	}
+
+	/// 请求卸载，不等待卸载完成；没有挂载或请求未被驱动接受时返回 false。
+	pub fn unmount(&self) -> bool {
+		if !self.is_mounted() {
+			return false;
+		}
+		match U16CString::from_str(&self.mount_point) {
+			Ok(mount_point) => request_unmount(&mount_point, self.driver, &self.stop_events),
+			Err(_) => false,
+		}
+	}
+
+	/// 请求强制卸载，不等待卸载完成：拒绝新的打开，提交暂存的写入，最多等待 `timeout` 让打开的文件关闭后移除挂载点，
+	/// 见 [`ForceUnmount`]。WinFsp 和 ProjFS 挂载与 [`unmount`](Self::unmount) 相同。没有挂载时返回 false。
+	pub fn force_unmount(&self, timeout: Duration) -> bool {
+		if self.driver != Driver::Dokan {
+			return self.unmount();
+		}
+		if !self.is_mounted() {
+			return false;
+		}
+		self.force.request(timeout);
+		true
+	}
+
+	/// 阻塞到文件系统被卸载，返回挂载失败的原因；已经等待过的句柄立即返回 `Ok`。
+	pub fn wait(&self) -> Result<(), Box<dyn Error>> {
+		let Some(thread) = self.thread.lock().unwrap().take() else {
+			return Ok(());
+		};
+		match thread.join() {
+			Ok(result) => Ok(result?),
+			Err(_) => Err("the mount thread panicked".into()),
+		}
+	}
+
+	/// 请求卸载并等待卸载完成。
+	pub fn unmount_and_wait(&self) -> Result<(), Box<dyn Error>> {
+		self.unmount();
+		self.wait()
+	}
+}
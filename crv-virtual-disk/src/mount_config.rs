@@ -0,0 +1,223 @@
+use std::time::Duration;
+
+use dokan::{MountFlags, MountOptions};
+use widestring::U16CString;
+
+// 未指定扇区大小时 Dokan 使用 512 字节，分配单元大小必须是扇区大小的整数倍
+const DEFAULT_SECTOR_SIZE: u32 = 512;
+const MAX_SECTOR_SIZE: u32 = 4096;
+
+// 挂载时传给 Dokan 的卷选项，由 MountConfig::builder() 构造，build 时检查取值；
+// 未设置的超时、分配单元大小和扇区大小使用 Dokan 的默认值
+#[derive(Debug, Clone, Default)]
+pub struct MountConfig {
+	single_thread: bool,
+	debug: bool,
+	write_protect: bool,
+	mount_manager: bool,
+	removable: bool,
+	network: bool,
+	timeout: Option<Duration>,
+	allocation_unit_size: Option<u32>,
+	sector_size: Option<u32>,
+	unc_name: Option<U16CString>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MountConfigBuilder {
+	single_thread: bool,
+	debug: bool,
+	write_protect: bool,
+	mount_manager: bool,
+	removable: bool,
+	network: bool,
+	timeout: Option<Duration>,
+	allocation_unit_size: Option<u32>,
+	sector_size: Option<u32>,
+	unc_name: Option<String>,
+}
+
+impl MountConfigBuilder {
+	pub fn single_thread(mut self, enabled: bool) -> Self {
+		self.single_thread = enabled;
+		self
+	}
+
+	// Dokan 的调试输出写到标准错误
+	pub fn debug(mut self, enabled: bool) -> Self {
+		self.debug = enabled;
+		self
+	}
+
+	// 卷以只读方式挂载，写入在到达 HttpFsHandler 之前被驱动拒绝
+	pub fn write_protect(mut self, enabled: bool) -> Self {
+		self.write_protect = enabled;
+		self
+	}
+
+	// 通过挂载管理器挂载，卷对所有会话可见，资源管理器可以像本地磁盘一样管理它
+	pub fn mount_manager(mut self, enabled: bool) -> Self {
+		self.mount_manager = enabled;
+		self
+	}
+
+	// 显示为可移动磁盘
+	pub fn removable(mut self, enabled: bool) -> Self {
+		self.removable = enabled;
+		self
+	}
+
+	// 通过 Dokan 网络提供程序显示为网络驱动器（需要 UNC 名称），资源管理器中可以断开连接
+	pub fn network(mut self, enabled: bool) -> Self {
+		self.network = enabled;
+		self
+	}
+
+	// 单个请求的最长处理时间，超过后驱动卸载文件系统
+	pub fn timeout(mut self, timeout: Duration) -> Self {
+		self.timeout = Some(timeout);
+		self
+	}
+
+	pub fn allocation_unit_size(mut self, bytes: u32) -> Self {
+		self.allocation_unit_size = Some(bytes);
+		self
+	}
+
+	pub fn sector_size(mut self, bytes: u32) -> Self {
+		self.sector_size = Some(bytes);
+		self
+	}
+
+	// 网络重定向器使用的 UNC 名称，如 \\server\share
+	pub fn unc_name(mut self, name: impl Into<String>) -> Self {
+		self.unc_name = Some(name.into());
+		self
+	}
+
+	pub fn build(self) -> Result<MountConfig, String> {
+		if let Some(size) = self.sector_size {
+			if !size.is_power_of_two() || !(DEFAULT_SECTOR_SIZE..=MAX_SECTOR_SIZE).contains(&size) {
+				return Err(format!(
+					"sector size {} must be a power of two between {} and {}",
+					size, DEFAULT_SECTOR_SIZE, MAX_SECTOR_SIZE
+				));
+			}
+		}
+		let sector_size = self.sector_size.unwrap_or(DEFAULT_SECTOR_SIZE);
+		if let Some(size) = self.allocation_unit_size {
+			if size == 0 || size % sector_size != 0 {
+				return Err(format!(
+					"allocation unit size {} must be a multiple of the sector size ({})",
+					size, sector_size
+				));
+			}
+		}
+		if let Some(timeout) = self.timeout {
+			if timeout.is_zero() || timeout.as_millis() > u32::MAX as u128 {
+				return Err(format!(
+					"timeout of {} seconds is out of range",
+					timeout.as_secs()
+				));
+			}
+		}
+		if self.network && self.mount_manager {
+			return Err("the mount manager cannot be used with a network drive".to_string());
+		}
+		if self.network && self.unc_name.is_none() {
+			return Err("a network drive needs a UNC name".to_string());
+		}
+		let unc_name = match self.unc_name {
+			Some(name) if !name.starts_with(r"\\") || name.len() <= 2 => {
+				return Err(format!(
+					r"UNC name '{}' must have the form \\server\share",
+					name
+				));
+			}
+			Some(name) => {
+				Some(U16CString::from_str(&name).map_err(|e| format!("invalid UNC name: {}", e))?)
+			}
+			None => None,
+		};
+		Ok(MountConfig {
+			single_thread: self.single_thread,
+			debug: self.debug,
+			write_protect: self.write_protect,
+			mount_manager: self.mount_manager,
+			removable: self.removable,
+			network: self.network,
+			timeout: self.timeout,
+			allocation_unit_size: self.allocation_unit_size,
+			sector_size: self.sector_size,
+			unc_name,
+		})
+	}
+}
+
+impl MountConfig {
+	pub fn builder() -> MountConfigBuilder {
+		MountConfigBuilder::default()
+	}
+
+	// all_sessions 为 false 时卷只对当前会话可见；挂载管理器挂载的卷总是对所有会话可见，不能设置 CURRENT_SESSION
+	pub fn options(&self, all_sessions: bool) -> MountOptions<'_> {
+		let mut flags = MountFlags::ALT_STREAM;
+		for (set, flag) in [
+			(
+				!all_sessions && !self.mount_manager,
+				MountFlags::CURRENT_SESSION,
+			),
+			(self.debug, MountFlags::DEBUG | MountFlags::STDERR),
+			(self.write_protect, MountFlags::WRITE_PROTECT),
+			(self.mount_manager, MountFlags::MOUNT_MANAGER),
+			(self.removable, MountFlags::REMOVABLE),
+			(
+				self.network,
+				MountFlags::NETWORK | MountFlags::ENABLE_UNMOUNT_NETWORK_DRIVE,
+			),
+		] {
+			if set {
+				flags |= flag;
+			}
+		}
+		MountOptions {
+			single_thread: self.single_thread,
+			flags,
+			unc_name: self.unc_name.as_deref(),
+			timeout: self.timeout.unwrap_or_default(),
+			allocation_unit_size: self.allocation_unit_size.unwrap_or_default(),
+			sector_size: self.sector_size.unwrap_or_default(),
+			..Default::default()
+		}
+	}
+
+	// 服务命令行中对应的 mount 参数
+	pub fn to_args(&self) -> Vec<String> {
+		let mut args = Vec::new();
+		if let Some(timeout) = self.timeout {
+			args.extend(["--dokan-timeout".to_string(), timeout.as_secs().to_string()]);
+		}
+		if let Some(size) = self.allocation_unit_size {
+			args.extend(["--allocation-unit-size".to_string(), size.to_string()]);
+		}
+		if let Some(size) = self.sector_size {
+			args.extend(["--sector-size".to_string(), size.to_string()]);
+		}
+		if let Some(name) = &self.unc_name {
+			args.extend(["--unc-name".to_string(), name.to_string_lossy()]);
+		}
+		for (set, flag) in [
+			(self.single_thread, "--single-thread"),
+			(self.debug, "--dokan-debug"),
+			(self.write_protect, "--write-protect"),
+			(self.mount_manager, "--mount-manager"),
+			(self.removable, "--removable"),
+			(self.network, "--network"),
+		] {
+			if set {
+				args.push(flag.to_string());
+			}
+		}
+		args
+	}
+}
@@ -0,0 +1,143 @@
+use std::{error::Error, fs, io, path::Path, ptr};
+
+use widestring::{U16CStr, U16CString};
+use winapi::{
+	shared::minwindef::{DWORD, MAX_PATH},
+	um::fileapi::{GetLogicalDrives, GetVolumeInformationW, GetVolumePathNameW},
+};
+
+// --mount-point auto 挂载到第一个空闲的盘符
+pub const AUTO: &str = "auto";
+
+// 盘符挂载点（M、M: 或 M:\）的盘符，目录挂载点返回 None
+pub fn drive_letter(mount_point: &str) -> Option<char> {
+	let mut chars = mount_point
+		.trim_end_matches('\\')
+		.trim_end_matches(':')
+		.chars();
+	match (chars.next(), chars.next()) {
+		(Some(letter), None) if letter.is_ascii_alphabetic() => Some(letter.to_ascii_uppercase()),
+		_ => None,
+	}
+}
+
+fn drive_in_use(letter: char) -> bool {
+	unsafe { GetLogicalDrives() & (1 << (letter as u32 - 'A' as u32)) != 0 }
+}
+
+// 当前未被占用的盘符，跳过软驱使用的 A 和 B
+pub fn free_drive_letters() -> Vec<char> {
+	('C'..='Z')
+		.filter(|letter| !drive_in_use(*letter))
+		.collect()
+}
+
+// 包含 path 的卷上的文件系统名称，如 NTFS
+fn file_system_name(path: &Path) -> io::Result<String> {
+	let path = U16CString::from_os_str(path)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+	// 卷的挂载路径不长于 path 本身，path 可以超过 MAX_PATH
+	let mut volume = vec![0u16; path.len() + 2];
+	let mut name = [0u16; MAX_PATH + 1];
+	unsafe {
+		if GetVolumePathNameW(path.as_ptr(), volume.as_mut_ptr(), volume.len() as DWORD) == 0 {
+			return Err(io::Error::last_os_error());
+		}
+		if GetVolumeInformationW(
+			volume.as_ptr(),
+			ptr::null_mut(),
+			0,
+			ptr::null_mut(),
+			ptr::null_mut(),
+			ptr::null_mut(),
+			name.as_mut_ptr(),
+			name.len() as DWORD,
+		) == 0
+		{
+			return Err(io::Error::last_os_error());
+		}
+	}
+	Ok(U16CStr::from_slice_truncate(&name)
+		.map(U16CStr::to_string_lossy)
+		.unwrap_or_default())
+}
+
+// 挂载前检查挂载点：盘符不能已被占用，目录必须是 NTFS 卷上已存在的空目录。
+// Dokan 本身对这些情况只返回笼统的挂载点错误
+pub fn check(mount_point: &str) -> Result<(), Box<dyn Error>> {
+	if let Some(letter) = drive_letter(mount_point) {
+		if drive_in_use(letter) {
+			return Err(format!("drive {}: is already in use", letter).into());
+		}
+		return Ok(());
+	}
+	let path = Path::new(mount_point);
+	if !path.is_absolute() {
+		return Err(format!(
+			"mount point {} must be a drive letter or an absolute directory path",
+			mount_point
+		)
+		.into());
+	}
+	let metadata = fs::metadata(path).map_err(|e| format!("mount point {}: {}", mount_point, e))?;
+	if !metadata.is_dir() {
+		return Err(format!("mount point {} is not a directory", mount_point).into());
+	}
+	if fs::read_dir(path)?.next().is_some() {
+		return Err(format!("mount point directory {} is not empty", mount_point).into());
+	}
+	let file_system =
+		file_system_name(path).map_err(|e| format!("mount point {}: {}", mount_point, e))?;
+	if file_system != "NTFS" {
+		return Err(format!(
+			"mount point {} is on a {} volume; directory mount points must be on NTFS",
+			mount_point, file_system
+		)
+		.into());
+	}
+	Ok(())
+}
+
+// WinFsp 挂载前检查挂载点并换算为 WinFsp 的形式：盘符为 M:，目录由 WinFsp 自己创建，必须尚不存在
+#[cfg(feature = "winfsp")]
+pub fn check_winfsp(mount_point: &str) -> Result<String, Box<dyn Error>> {
+	if let Some(letter) = drive_letter(mount_point) {
+		if drive_in_use(letter) {
+			return Err(format!("drive {}: is already in use", letter).into());
+		}
+		return Ok(format!("{}:", letter));
+	}
+	let path = Path::new(mount_point);
+	if !path.is_absolute() {
+		return Err(format!(
+			"mount point {} must be a drive letter or an absolute directory path",
+			mount_point
+		)
+		.into());
+	}
+	if path.exists() {
+		return Err(format!(
+			"mount point {} already exists; WinFsp creates the mount point directory itself",
+			mount_point
+		)
+		.into());
+	}
+	Ok(mount_point.trim_end_matches('\\').to_string())
+}
+
+// ProjFS 的虚拟化根目录：绝对路径的目录，不存在时由 projfs::start 创建
+#[cfg(feature = "projfs")]
+pub fn check_projfs(mount_point: &str) -> Result<std::path::PathBuf, Box<dyn Error>> {
+	let path = Path::new(mount_point);
+	if drive_letter(mount_point).is_some() || !path.is_absolute() {
+		return Err(format!(
+			"mount point {} must be an absolute directory path; ProjFS cannot project a drive letter",
+			mount_point
+		)
+		.into());
+	}
+	if path.exists() && !path.is_dir() {
+		return Err(format!("mount point {} is not a directory", mount_point).into());
+	}
+	Ok(path.to_path_buf())
+}
@@ -0,0 +1,156 @@
+use std::borrow::Cow;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+// 设备名（带扩展名也一样）在 Windows 中打开的是设备而不是文件
+const RESERVED_NAMES: [&str; 22] = [
+	"CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+	"COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// 私用区中映射的起点，与 WSL 和 Cygwin 相同
+const PRIVATE_USE_BASE: u32 = 0xF000;
+
+/// Windows 不能使用的远程名称在挂载中的表示方式：含有 `<>:"\|?*` 或控制字符、以点或空格结尾的名称，
+/// 以及 `CON`、`AUX`、`COM1` 这样的设备名。不转义时这些文件在目录中无法打开，甚至不显示。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameEscaping {
+	/// 名称原样显示
+	#[default]
+	None,
+	/// 不能使用的字符写成 `#` 加 4 位十六进制的字符编码，如 `a:b` 显示为 `a#003Ab`、`CON` 显示为 `CO#004E`；
+	/// 本身形如转义的 `#` 也被转义，任何名称都能精确还原
+	Hash,
+	/// 不能使用的字符映射到私用区的 U+F000 加字符编码，与 WSL 和 Cygwin 显示同一目录时的名称相同；
+	/// 远程名称中原有的这些私用区字符会被当作转义还原
+	PrivateUse,
+}
+
+impl NameEscaping {
+	/// 远程名称在挂载中显示的名称。
+	pub fn escape(self, name: &str) -> Cow<'_, str> {
+		if self == NameEscaping::None || name == "." || name == ".." {
+			return Cow::Borrowed(name);
+		}
+		let chars: Vec<char> = name.chars().collect();
+		let reserved = reserved_stem_end(name);
+		let escaped: Vec<bool> = (0..chars.len())
+			.map(|index| match chars[index] {
+				'#' if self == NameEscaping::Hash => hash_escape_at(&chars[index + 1..]).is_some(),
+				c => {
+					is_forbidden(c)
+						|| (index + 1 == chars.len() && matches!(c, '.' | ' '))
+						|| Some(index) == reserved
+				}
+			})
+			.collect();
+		if !escaped.contains(&true) {
+			return Cow::Borrowed(name);
+		}
+		let mut result = String::with_capacity(name.len() + 8);
+		for (&c, escaped) in chars.iter().zip(escaped) {
+			match (escaped, self) {
+				(false, _) => result.push(c),
+				(true, NameEscaping::PrivateUse) => {
+					result.push(char::from_u32(PRIVATE_USE_BASE + c as u32).unwrap())
+				}
+				(true, _) => result.push_str(&format!("#{:04X}", c as u32)),
+			}
+		}
+		Cow::Owned(result)
+	}
+
+	/// 挂载中的名称对应的远程名称，是 [`escape`](Self::escape) 的逆运算；没有转义的名称原样返回。
+	pub fn unescape(self, name: &str) -> Cow<'_, str> {
+		match self {
+			NameEscaping::None => Cow::Borrowed(name),
+			NameEscaping::Hash if name.contains('#') => {
+				let chars: Vec<char> = name.chars().collect();
+				let mut result = String::with_capacity(name.len());
+				let mut index = 0;
+				while index < chars.len() {
+					match (chars[index], hash_escape_at(&chars[index + 1..])) {
+						('#', Some(c)) => {
+							result.push(c);
+							index += 5;
+						}
+						(c, _) => {
+							result.push(c);
+							index += 1;
+						}
+					}
+				}
+				Cow::Owned(result)
+			}
+			NameEscaping::PrivateUse if name.chars().any(|c| private_use_escape(c).is_some()) => {
+				Cow::Owned(
+					name.chars()
+						.map(|c| private_use_escape(c).unwrap_or(c))
+						.collect(),
+				)
+			}
+			_ => Cow::Borrowed(name),
+		}
+	}
+
+	/// 逐段转义远程路径，根目录 `.` 不变。
+	pub fn escape_path(self, path: &str) -> String {
+		self.map_path(path, Self::escape)
+	}
+
+	/// 逐段还原挂载中的路径。
+	pub fn unescape_path(self, path: &str) -> String {
+		self.map_path(path, Self::unescape)
+	}
+
+	fn map_path<'a>(self, path: &'a str, map: fn(Self, &'a str) -> Cow<'a, str>) -> String {
+		if self == NameEscaping::None || path == "." {
+			return path.to_string();
+		}
+		path.split('/')
+			.map(|name| map(self, name))
+			.collect::<Vec<_>>()
+			.join("/")
+	}
+}
+
+// Windows 在名称的任何位置都不接受的字符
+fn is_forbidden(c: char) -> bool {
+	c.is_ascii_control() || "<>:\"\\|?*".contains(c)
+}
+
+// 转义可能产生的字符：不能使用的字符、结尾的点和空格、设备名中的字母和数字，以及 # 本身
+fn is_escapable(c: char) -> bool {
+	is_forbidden(c) || c.is_ascii_alphanumeric() || matches!(c, '.' | ' ' | '#')
+}
+
+// 设备名是去掉扩展名和其后空格的部分，转义其最后一个字符
+fn reserved_stem_end(name: &str) -> Option<usize> {
+	let stem = name
+		.split('.')
+		.next()
+		.unwrap_or_default()
+		.trim_end_matches(' ');
+	RESERVED_NAMES
+		.iter()
+		.any(|reserved| stem.eq_ignore_ascii_case(reserved))
+		.then(|| stem.len() - 1)
+}
+
+// # 之后的 4 位十六进制数是转义时表示的字符；其他 # 按原样显示
+fn hash_escape_at(rest: &[char]) -> Option<char> {
+	let digits: String = rest.iter().take(4).collect();
+	if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+		return None;
+	}
+	char::from_u32(u32::from_str_radix(&digits, 16).ok()?).filter(|&c| is_escapable(c))
+}
+
+fn private_use_escape(c: char) -> Option<char> {
+	(c as u32)
+		.checked_sub(PRIVATE_USE_BASE)
+		.and_then(char::from_u32)
+		.filter(|&c| c.is_ascii() && is_escapable(c) && c != '#')
+}
@@ -0,0 +1,337 @@
+use std::{
+	io::{self, BufReader, BufWriter, Read, Write},
+	net::{TcpListener, TcpStream},
+	sync::Arc,
+	thread,
+};
+
+use tracing::{info, warn};
+
+use crate::image::BlockDevice;
+
+// 固定新式握手：服务器先发送 NBDMAGIC、IHAVEOPT 和握手标志
+const NBDMAGIC: u64 = 0x4E42_444D_4147_4943;
+const IHAVEOPT: u64 = 0x4948_4156_454F_5054;
+const OPTION_REPLY_MAGIC: u64 = 0x0003_E889_0455_65A9;
+const FLAG_FIXED_NEWSTYLE: u16 = 1;
+const FLAG_NO_ZEROES: u16 = 2;
+
+const OPT_EXPORT_NAME: u32 = 1;
+const OPT_ABORT: u32 = 2;
+const OPT_LIST: u32 = 3;
+const OPT_INFO: u32 = 6;
+const OPT_GO: u32 = 7;
+const REP_ACK: u32 = 1;
+const REP_SERVER: u32 = 2;
+const REP_INFO: u32 = 3;
+const REP_ERR_UNSUP: u32 = 1 << 31 | 1;
+const REP_ERR_INVALID: u32 = 1 << 31 | 3;
+const REP_ERR_UNKNOWN: u32 = 1 << 31 | 6;
+const INFO_EXPORT: u16 = 0;
+const INFO_BLOCK_SIZE: u16 = 3;
+
+// 导出的标志：多个连接看到的内容一致，任一连接的 FLUSH 保存所有连接写入的数据
+const FLAG_HAS_FLAGS: u16 = 1;
+const FLAG_READ_ONLY: u16 = 1 << 1;
+const FLAG_SEND_FLUSH: u16 = 1 << 2;
+const FLAG_SEND_WRITE_ZEROES: u16 = 1 << 6;
+const FLAG_CAN_MULTI_CONN: u16 = 1 << 8;
+
+const REQUEST_MAGIC: u32 = 0x2560_9513;
+const SIMPLE_REPLY_MAGIC: u32 = 0x6744_6698;
+const CMD_READ: u16 = 0;
+const CMD_WRITE: u16 = 1;
+const CMD_DISC: u16 = 2;
+const CMD_FLUSH: u16 = 3;
+const CMD_TRIM: u16 = 4;
+const CMD_WRITE_ZEROES: u16 = 6;
+
+// 回复中的错误码，取值与 Linux 的 errno 相同
+const EPERM: u32 = 1;
+const EIO: u32 = 5;
+const EINVAL: u32 = 22;
+const ENOSPC: u32 = 28;
+
+// 选项数据和单个读写请求的长度上限，以及写入 0 时每次写入的长度
+const MAX_OPTION: u32 = 64 * 1024;
+const MAX_REQUEST: u32 = 32 * 1024 * 1024;
+const PREFERRED_BLOCK: u32 = 4096;
+const ZERO_CHUNK: u64 = 1024 * 1024;
+
+// 以 NBD 协议导出的块设备，客户端按名称选择；名称为空的请求得到默认导出，也就是这一个
+pub struct Export {
+	pub name: String,
+	pub device: Arc<dyn BlockDevice>,
+}
+
+impl Export {
+	fn matches(&self, name: &[u8]) -> bool {
+		name.is_empty() || name == self.name.as_bytes()
+	}
+
+	// 块设备可以写入（快照差异文件）时接受写入、写入 0 和 FLUSH，否则只读
+	fn flags(&self) -> u16 {
+		match self.device.writable() {
+			true => FLAG_HAS_FLAGS | FLAG_SEND_FLUSH | FLAG_SEND_WRITE_ZEROES | FLAG_CAN_MULTI_CONN,
+			false => FLAG_HAS_FLAGS | FLAG_READ_ONLY | FLAG_CAN_MULTI_CONN,
+		}
+	}
+}
+
+fn invalid_data(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+	let mut bytes = [0; 4];
+	reader.read_exact(&mut bytes)?;
+	Ok(u32::from_be_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+	let mut bytes = [0; 8];
+	reader.read_exact(&mut bytes)?;
+	Ok(u64::from_be_bytes(bytes))
+}
+
+// 接受连接直到监听失败，每个连接一个线程；Linux 上可以用 nbd-client 或 qemu 附加
+pub fn serve(listener: TcpListener, export: Arc<Export>) -> io::Result<()> {
+	for stream in listener.incoming() {
+		let stream = stream?;
+		let export = export.clone();
+		thread::spawn(move || {
+			let peer = stream
+				.peer_addr()
+				.map(|addr| addr.to_string())
+				.unwrap_or_default();
+			info!(peer = %peer, "nbd: client connected");
+			match session(stream, &export) {
+				Ok(()) => info!(peer = %peer, "nbd: client disconnected"),
+				Err(e) => warn!(peer = %peer, error = %e, "nbd: connection closed"),
+			}
+		});
+	}
+	Ok(())
+}
+
+fn session(stream: TcpStream, export: &Export) -> io::Result<()> {
+	stream.set_nodelay(true)?;
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut writer = BufWriter::new(stream);
+	writer.write_all(&NBDMAGIC.to_be_bytes())?;
+	writer.write_all(&IHAVEOPT.to_be_bytes())?;
+	writer.write_all(&(FLAG_FIXED_NEWSTYLE | FLAG_NO_ZEROES).to_be_bytes())?;
+	writer.flush()?;
+	let client_flags = read_u32(&mut reader)?;
+	if client_flags & FLAG_FIXED_NEWSTYLE as u32 == 0 {
+		return Err(invalid_data(
+			"the client does not support the fixed newstyle handshake",
+		));
+	}
+	if negotiate(
+		&mut reader,
+		&mut writer,
+		export,
+		client_flags & FLAG_NO_ZEROES as u32 != 0,
+	)? {
+		transmit(&mut reader, &mut writer, export)?;
+	}
+	Ok(())
+}
+
+fn option_reply(writer: &mut impl Write, option: u32, kind: u32, data: &[u8]) -> io::Result<()> {
+	writer.write_all(&OPTION_REPLY_MAGIC.to_be_bytes())?;
+	writer.write_all(&option.to_be_bytes())?;
+	writer.write_all(&kind.to_be_bytes())?;
+	writer.write_all(&(data.len() as u32).to_be_bytes())?;
+	writer.write_all(data)?;
+	writer.flush()
+}
+
+// 处理握手阶段的选项，返回是否进入传输阶段
+fn negotiate(
+	reader: &mut impl Read,
+	writer: &mut impl Write,
+	export: &Export,
+	no_zeroes: bool,
+) -> io::Result<bool> {
+	let size = export.device.size();
+	loop {
+		if read_u64(reader)? != IHAVEOPT {
+			return Err(invalid_data("the client sent a damaged option"));
+		}
+		let (option, length) = (read_u32(reader)?, read_u32(reader)?);
+		if length > MAX_OPTION {
+			return Err(invalid_data("the client sent an option that is too long"));
+		}
+		let mut data = vec![0; length as usize];
+		reader.read_exact(&mut data)?;
+		match option {
+			// 旧的选项没有错误回复，导出不存在时只能断开
+			OPT_EXPORT_NAME => {
+				if !export.matches(&data) {
+					return Ok(false);
+				}
+				writer.write_all(&size.to_be_bytes())?;
+				writer.write_all(&export.flags().to_be_bytes())?;
+				if !no_zeroes {
+					writer.write_all(&[0; 124])?;
+				}
+				writer.flush()?;
+				return Ok(true);
+			}
+			OPT_ABORT => {
+				option_reply(writer, option, REP_ACK, &[])?;
+				return Ok(false);
+			}
+			OPT_LIST => {
+				let mut server = (export.name.len() as u32).to_be_bytes().to_vec();
+				server.extend(export.name.as_bytes());
+				option_reply(writer, option, REP_SERVER, &server)?;
+				option_reply(writer, option, REP_ACK, &[])?;
+			}
+			// 数据为带长度的导出名和请求的信息类型；导出信息总是发送，块大小只在请求时发送
+			OPT_INFO | OPT_GO => {
+				let name_length = data.get(..4).map_or(usize::MAX, |bytes| {
+					u32::from_be_bytes(bytes.try_into().unwrap()) as usize
+				});
+				let Some(requests) = data
+					.get(4..)
+					.and_then(|rest| rest.get(name_length..))
+					.filter(|requests| requests.len() >= 2)
+				else {
+					option_reply(
+						writer,
+						option,
+						REP_ERR_INVALID,
+						b"the option data is damaged",
+					)?;
+					continue;
+				};
+				if !export.matches(&data[4..4 + name_length]) {
+					option_reply(
+						writer,
+						option,
+						REP_ERR_UNKNOWN,
+						format!("the only export is '{}'", export.name).as_bytes(),
+					)?;
+					continue;
+				}
+				let mut info = INFO_EXPORT.to_be_bytes().to_vec();
+				info.extend(size.to_be_bytes());
+				info.extend(export.flags().to_be_bytes());
+				option_reply(writer, option, REP_INFO, &info)?;
+				if requests[2..]
+					.chunks_exact(2)
+					.any(|kind| kind == INFO_BLOCK_SIZE.to_be_bytes())
+				{
+					let mut info = INFO_BLOCK_SIZE.to_be_bytes().to_vec();
+					for value in [1, PREFERRED_BLOCK, MAX_REQUEST] {
+						info.extend(value.to_be_bytes());
+					}
+					option_reply(writer, option, REP_INFO, &info)?;
+				}
+				option_reply(writer, option, REP_ACK, &[])?;
+				if option == OPT_GO {
+					return Ok(true);
+				}
+			}
+			_ => option_reply(writer, option, REP_ERR_UNSUP, &[])?,
+		}
+	}
+}
+
+// 读写失败时记录日志并回复 EIO
+fn reply_code(result: io::Result<()>, operation: &str, offset: u64, length: u32) -> u32 {
+	match result {
+		Ok(()) => 0,
+		Err(e) => {
+			warn!(offset, length, error = %e, "nbd: {} failed", operation);
+			EIO
+		}
+	}
+}
+
+// 传输阶段：按顺序处理请求，每个请求以简单回复应答
+fn transmit(reader: &mut impl Read, writer: &mut impl Write, export: &Export) -> io::Result<()> {
+	let (size, writable) = (export.device.size(), export.device.writable());
+	loop {
+		let mut request = [0; 28];
+		reader.read_exact(&mut request)?;
+		if request[..4] != REQUEST_MAGIC.to_be_bytes() {
+			return Err(invalid_data("the client sent a damaged request"));
+		}
+		let kind = u16::from_be_bytes([request[6], request[7]]);
+		let handle = &request[8..16];
+		let offset = u64::from_be_bytes(request[16..24].try_into().unwrap());
+		let length = u32::from_be_bytes(request[24..28].try_into().unwrap());
+		let in_range = offset
+			.checked_add(length as u64)
+			.is_some_and(|end| end <= size);
+		let mut data = Vec::new();
+		let error = match kind {
+			CMD_READ if length > MAX_REQUEST || !in_range => EINVAL,
+			CMD_READ => {
+				data = vec![0; length as usize];
+				let error = reply_code(
+					export.device.read_at(offset, &mut data),
+					"read",
+					offset,
+					length,
+				);
+				if error != 0 {
+					data.clear();
+				}
+				error
+			}
+			// 写入的数据跟在请求之后，拒绝时也要读完
+			CMD_WRITE if !writable || length > MAX_REQUEST => {
+				io::copy(&mut reader.by_ref().take(length as u64), &mut io::sink())?;
+				if writable {
+					EINVAL
+				} else {
+					EPERM
+				}
+			}
+			CMD_WRITE => {
+				let mut payload = vec![0; length as usize];
+				reader.read_exact(&mut payload)?;
+				match in_range {
+					true => reply_code(
+						export.device.write_at(offset, &payload),
+						"write",
+						offset,
+						length,
+					),
+					false => ENOSPC,
+				}
+			}
+			CMD_WRITE_ZEROES | CMD_TRIM if !writable => EPERM,
+			CMD_WRITE_ZEROES if !in_range => ENOSPC,
+			CMD_WRITE_ZEROES => {
+				let zeros = vec![0; ZERO_CHUNK.min(length as u64) as usize];
+				let mut result = Ok(());
+				for start in (offset..offset + length as u64).step_by(ZERO_CHUNK as usize) {
+					result = export.device.write_at(
+						start,
+						&zeros[..(offset + length as u64 - start).min(ZERO_CHUNK) as usize],
+					);
+					if result.is_err() {
+						break;
+					}
+				}
+				reply_code(result, "write zeroes", offset, length)
+			}
+			// 丢弃只是提示，不回收空间
+			CMD_TRIM => 0,
+			CMD_FLUSH => reply_code(export.device.flush(), "flush", offset, length),
+			CMD_DISC => return Ok(()),
+			_ => EINVAL,
+		};
+		writer.write_all(&SIMPLE_REPLY_MAGIC.to_be_bytes())?;
+		writer.write_all(&error.to_be_bytes())?;
+		writer.write_all(handle)?;
+		writer.write_all(&data)?;
+		writer.flush()?;
+	}
+}
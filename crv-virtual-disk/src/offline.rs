@@ -0,0 +1,717 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	fs::{self, File},
+	io::{self, Seek, SeekFrom, Write},
+	mem,
+	path::{Path, PathBuf},
+	sync::{Arc, Condvar, Mutex},
+	thread,
+	time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{debug, info, warn};
+
+use crate::{
+	backend::{read_full, BatchFile, Remote},
+	error::RemoteError,
+	vfs, ChecksumResponse, ListPage, RemoteFileInfo, RestoreResponse, SearchResponse,
+	SpaceResponse, StorageBackend, TimesUpdate, TrashEntry, VersionInfo, XattrEntry,
+};
+
+const INDEX_FILE: &str = "index.json";
+const FILES_DIR: &str = "files";
+
+// 下载固定的文件时每次读取的长度
+const DOWNLOAD_CHUNK: usize = 4 * 1024 * 1024;
+// 没有变化通知时也定期完整同步一次，覆盖未订阅事件或丢失事件的情况
+const RESYNC_INTERVAL: Duration = Duration::from_secs(15 * 60);
+// 同步失败（通常是服务器不可达）后重试前的等待
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+// 无法连接服务器的错误，这时改用本地的副本
+fn is_unreachable(e: &RemoteError) -> bool {
+	matches!(e, RemoteError::Transport(_)) || e.code() == Some("connection_lost")
+}
+
+fn is_missing(e: &RemoteError) -> bool {
+	matches!(
+		e.code(),
+		Some("not_found" | "parent_not_found" | "not_a_directory")
+	)
+}
+
+fn parent(path: &str) -> &str {
+	path.rsplit_once('/').map_or(".", |(parent, _)| parent)
+}
+
+// path 是否为 dir 或在 dir 之下
+fn is_within(path: &str, dir: &str) -> bool {
+	dir == "."
+		|| path == dir
+		|| path
+			.strip_prefix(dir)
+			.is_some_and(|rest| rest.starts_with('/'))
+}
+
+// 固定的路径可以写成 docs/reports、/docs/reports 或 \docs\reports
+fn normalize_pin(pin: &str) -> String {
+	vfs::share_path(
+		pin.trim_end_matches(['/', '\\'])
+			.replace('/', "\\")
+			.as_str(),
+	)
+}
+
+/// 固定在本地的文件（“始终保留在此设备上”）：固定的文件和目录下的所有文件预先下载到本地目录，
+/// 之后按服务器的变化通知、本客户端的修改和定期的完整同步保持最新。服务器不可达时，
+/// 这些文件及其所在的目录仍然可以列出和读取（内容为最后一次同步时的版本），修改仍然失败。
+///
+/// 其他文件是占位符：只有属性，内容在第一次读取时才下载到本地（“水合”），之后与固定的文件一样保持最新，
+/// 直到用 [`OfflineCache::dehydrate`] 删除本地副本、重新成为占位符。
+///
+/// 副本保存在 `dir` 下按存储区分的子目录中：`index.json` 记录每个条目的属性，`files` 下按路径的哈希保存文件内容。
+pub struct OfflineCache {
+	dir: PathBuf,
+	pins: Vec<String>,
+	index: Mutex<HashMap<String, RemoteFileInfo>>,
+	sync: Mutex<SyncState>,
+	changed: Condvar,
+}
+
+#[derive(Default)]
+struct SyncState {
+	// 需要重新同步的路径
+	stale: BTreeSet<String>,
+	closed: bool,
+}
+
+/// 删除或保留的本地副本的文件数和总大小。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Dehydrated {
+	pub files: usize,
+	pub bytes: u64,
+}
+
+/// 一次同步的结果。
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct SyncReport {
+	pub downloaded: usize,
+	pub bytes: u64,
+	pub removed: usize,
+}
+
+impl OfflineCache {
+	/// 打开 `dir` 中 `remote` 的本地副本，固定 `pins` 中的路径（共享内的路径，目录包括其下的所有条目）。
+	pub fn open(dir: &Path, remote: &Remote, pins: &[String]) -> io::Result<Self> {
+		let identity = format!(
+			"{}\n{}",
+			remote.server_url,
+			remote.share.as_deref().unwrap_or("")
+		);
+		let dir = dir.join(&hex::encode(Sha256::digest(identity.as_bytes()))[..16]);
+		fs::create_dir_all(dir.join(FILES_DIR))?;
+		let index = match fs::read(dir.join(INDEX_FILE)) {
+			Ok(bytes) => serde_json::from_slice(&bytes)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+			Err(e) => return Err(e),
+		};
+		// 不再固定的条目的副本保留为下载过的文件
+		let cache = Self {
+			dir,
+			pins: pins.iter().map(|pin| normalize_pin(pin)).collect(),
+			index: Mutex::new(index),
+			sync: Mutex::new(SyncState::default()),
+			changed: Condvar::new(),
+		};
+		cache.resync();
+		Ok(cache)
+	}
+
+	pub fn dir(&self) -> &Path {
+		&self.dir
+	}
+
+	/// `path` 是否为固定的路径或在固定的目录之下。
+	pub fn is_pinned(&self, path: &str) -> bool {
+		self.pins.iter().any(|pin| is_within(path, pin))
+	}
+
+	/// `path` 的内容是否已经下载到本地；否则为占位符，第一次读取时下载。
+	pub fn is_hydrated(&self, path: &str) -> bool {
+		self.index
+			.lock()
+			.unwrap()
+			.get(path)
+			.is_some_and(|info| !info.is_directory)
+	}
+
+	// 需要保持最新的路径：固定的路径和下载过的未固定的文件
+	fn tracked(&self) -> Vec<String> {
+		let index = self.index.lock().unwrap();
+		let hydrated = index
+			.iter()
+			.filter(|(path, info)| !info.is_directory && !self.is_pinned(path))
+			.map(|(path, _)| path.clone());
+		self.pins.iter().cloned().chain(hydrated).collect()
+	}
+
+	fn content_path(&self, path: &str) -> PathBuf {
+		self.dir
+			.join(FILES_DIR)
+			.join(hex::encode(&Sha256::digest(path.as_bytes())[..16]))
+	}
+
+	/// 本地副本中条目的属性。
+	pub fn stat(&self, path: &str) -> Option<RemoteFileInfo> {
+		self.index.lock().unwrap().get(path).cloned()
+	}
+
+	/// 本地副本中目录的内容，只包括固定的条目和通往它们的目录。
+	pub fn list(&self, path: &str) -> Option<Vec<RemoteFileInfo>> {
+		let index = self.index.lock().unwrap();
+		if !index.get(path)?.is_directory {
+			return None;
+		}
+		let mut items: Vec<_> = index
+			.iter()
+			.filter(|(child, _)| *child != path && parent(child) == path)
+			.map(|(_, info)| info.clone())
+			.collect();
+		items.sort_by(|a, b| a.name.cmp(&b.name));
+		Some(items)
+	}
+
+	/// 从本地副本读到 `buffer`，返回读到的字节数；没有副本时返回 None。
+	pub fn read_into(
+		&self,
+		path: &str,
+		offset: u64,
+		buffer: &mut [u8],
+	) -> Option<io::Result<usize>> {
+		if self.stat(path).is_none_or(|info| info.is_directory) {
+			return None;
+		}
+		let read = |buffer: &mut [u8]| {
+			let mut file = File::open(self.content_path(path))?;
+			file.seek(SeekFrom::Start(offset))?;
+			read_full(&mut file, buffer)
+		};
+		Some(read(buffer))
+	}
+
+	/// `path` 在存储中发生了变化（服务器的变化通知或本客户端的修改），在后台重新同步其中固定或下载过的部分。
+	pub fn changed(&self, path: &str) {
+		let affected: Vec<String> = if self.is_pinned(path) {
+			vec![path.to_string()]
+		} else {
+			// 所在的目录被移动或删除的固定的条目，以及 path 下下载过的文件
+			let index = self.index.lock().unwrap();
+			let hydrated = index
+				.iter()
+				.filter(|(key, info)| !info.is_directory && is_within(key, path))
+				.map(|(key, _)| key.clone());
+			self.pins
+				.iter()
+				.filter(|pin| is_within(pin, path))
+				.cloned()
+				.chain(hydrated)
+				.collect()
+		};
+		if affected.is_empty() {
+			return;
+		}
+		self.sync.lock().unwrap().stale.extend(affected);
+		self.changed.notify_all();
+	}
+
+	/// 重新同步所有固定和下载过的路径，例如丢失了部分变化通知之后。
+	pub fn resync(&self) {
+		let tracked = self.tracked();
+		self.sync.lock().unwrap().stale.extend(tracked);
+		self.changed.notify_all();
+	}
+
+	/// 在后台下载占位符 `path` 的内容。
+	pub fn hydrate(&self, path: &str) {
+		if !self.is_hydrated(path) {
+			self.sync.lock().unwrap().stale.insert(path.to_string());
+			self.changed.notify_all();
+		}
+	}
+
+	/// 删除 `path`（文件或目录）下未固定的文件的本地副本，使它们重新成为占位符，返回释放的文件数和字节数；
+	/// 固定的文件保留，需要先取消固定。
+	pub fn dehydrate(&self, path: &str) -> io::Result<Dehydrated> {
+		let path = normalize_pin(path);
+		let mut dehydrated = Dehydrated::default();
+		let mut index = self.index.lock().unwrap();
+		let files: Vec<String> = index
+			.iter()
+			.filter(|(key, info)| {
+				!info.is_directory && is_within(key, &path) && !self.is_pinned(key)
+			})
+			.map(|(key, _)| key.clone())
+			.collect();
+		for file in files {
+			let info = index.remove(&file).expect("listed from the index");
+			match fs::remove_file(self.content_path(&file)) {
+				Ok(()) => {}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => {
+					index.insert(file, info);
+					return Err(e);
+				}
+			}
+			dehydrated.files += 1;
+			dehydrated.bytes += info.size;
+		}
+		drop(index);
+		self.save_index()?;
+		Ok(dehydrated)
+	}
+
+	/// 本地副本的文件数和总大小。
+	pub fn usage(&self) -> Dehydrated {
+		let index = self.index.lock().unwrap();
+		let files = index.values().filter(|info| !info.is_directory);
+		Dehydrated {
+			files: files.clone().count(),
+			bytes: files.map(|info| info.size).sum(),
+		}
+	}
+
+	/// 同步 `path`（固定的路径或其下的条目）：下载新增和变化的文件，删除存储中已不存在的条目的副本。
+	pub fn sync_path(
+		&self,
+		backend: &dyn StorageBackend,
+		path: &str,
+	) -> Result<SyncReport, RemoteError> {
+		let mut report = SyncReport::default();
+		// 上级目录只记录属性
+		let mut ancestor = path;
+		while ancestor != "." {
+			ancestor = parent(ancestor);
+			if self.stat(ancestor).is_none() {
+				let info = backend.stat(ancestor)?;
+				self.index
+					.lock()
+					.unwrap()
+					.insert(ancestor.to_string(), info);
+			}
+		}
+		match backend.stat(path) {
+			// 下载过的文件变成了目录，不下载整个目录
+			Ok(info) if info.is_directory && !self.is_pinned(path) => {
+				report.removed += self.remove_tree(path)
+			}
+			Ok(info) => self.sync_entry(backend, path, info, &mut report)?,
+			Err(e) if is_missing(&e) => report.removed += self.remove_tree(path),
+			Err(e) => return Err(e),
+		}
+		Ok(report)
+	}
+
+	fn sync_entry(
+		&self,
+		backend: &dyn StorageBackend,
+		path: &str,
+		info: RemoteFileInfo,
+		report: &mut SyncReport,
+	) -> Result<(), RemoteError> {
+		let previous = self.stat(path);
+		if info.is_directory {
+			if previous
+				.as_ref()
+				.is_some_and(|previous| !previous.is_directory)
+			{
+				report.removed += self.remove_tree(path);
+			}
+			self.index.lock().unwrap().insert(path.to_string(), info);
+			let mut items = Vec::new();
+			let mut cursor = None;
+			loop {
+				let page = backend.list_page(path, cursor.as_deref())?;
+				items.extend(page.items);
+				match page.next_cursor {
+					Some(next) => cursor = Some(next),
+					None => break,
+				}
+			}
+			// 存储中已经不存在的子条目
+			let children: Vec<String> = items
+				.iter()
+				.map(|item| vfs::child_path(path, &item.name))
+				.collect();
+			let gone: Vec<String> = self
+				.index
+				.lock()
+				.unwrap()
+				.keys()
+				.filter(|child| {
+					*child != path && parent(child) == path && !children.contains(child)
+				})
+				.cloned()
+				.collect();
+			for child in gone {
+				report.removed += self.remove_tree(&child);
+			}
+			for (child, item) in children.iter().zip(items) {
+				self.sync_entry(backend, child, item, report)?;
+			}
+			return Ok(());
+		}
+		let unchanged = previous.as_ref().is_some_and(|previous| {
+			!previous.is_directory
+				&& previous.size == info.size
+				&& previous.modified == info.modified
+		});
+		if unchanged && self.content_path(path).exists() {
+			return Ok(());
+		}
+		if previous
+			.as_ref()
+			.is_some_and(|previous| previous.is_directory)
+		{
+			report.removed += self.remove_tree(path);
+		}
+		self.download(backend, path, info.size)?;
+		report.downloaded += 1;
+		report.bytes += info.size;
+		self.index.lock().unwrap().insert(path.to_string(), info);
+		Ok(())
+	}
+
+	// 先下载到临时文件再替换，下载中途失败时保留原来的副本
+	fn download(
+		&self,
+		backend: &dyn StorageBackend,
+		path: &str,
+		size: u64,
+	) -> Result<(), RemoteError> {
+		let target = self.content_path(path);
+		let temp = target.with_extension("part");
+		let local_error = |e: io::Error| {
+			RemoteError::backend("io_error", format!("offline copy of {}: {}", path, e))
+		};
+		let mut file = File::create(&temp).map_err(local_error)?;
+		let mut offset = 0;
+		while offset < size {
+			let data = backend.read(path, offset, DOWNLOAD_CHUNK)?;
+			if data.is_empty() {
+				break;
+			}
+			file.write_all(&data).map_err(local_error)?;
+			offset += data.len() as u64;
+		}
+		file.sync_all().map_err(local_error)?;
+		drop(file);
+		fs::rename(&temp, &target).map_err(local_error)?;
+		debug!(path = %path, size = offset, "offline copy downloaded");
+		Ok(())
+	}
+
+	// 删除 path 及其下所有条目的副本，返回删除的条目数
+	fn remove_tree(&self, path: &str) -> usize {
+		let mut index = self.index.lock().unwrap();
+		let removed: Vec<String> = index
+			.keys()
+			.filter(|key| is_within(key, path))
+			.cloned()
+			.collect();
+		for key in &removed {
+			if index.remove(key).is_some_and(|info| !info.is_directory) {
+				let _ = fs::remove_file(self.content_path(key));
+			}
+		}
+		removed.len()
+	}
+
+	fn save_index(&self) -> io::Result<()> {
+		let bytes = serde_json::to_vec(&*self.index.lock().unwrap()).map_err(io::Error::other)?;
+		let temp = self.dir.join(format!("{}.tmp", INDEX_FILE));
+		fs::write(&temp, bytes)?;
+		fs::rename(temp, self.dir.join(INDEX_FILE))
+	}
+
+	// 后台同步：先同步所有固定的路径，之后同步发生变化的路径，并定期完整同步
+	pub(crate) fn spawn_sync(self: &Arc<Self>, backend: Arc<dyn StorageBackend>) {
+		let cache = self.clone();
+		thread::Builder::new()
+			.name("offline-sync".to_string())
+			.spawn(move || cache.run(backend.as_ref()))
+			.expect("cannot spawn offline sync");
+	}
+
+	fn run(&self, backend: &dyn StorageBackend) {
+		let mut sync = self.sync.lock().unwrap();
+		loop {
+			if sync.closed {
+				return;
+			}
+			if sync.stale.is_empty() {
+				let (next, timeout) = self.changed.wait_timeout(sync, RESYNC_INTERVAL).unwrap();
+				sync = next;
+				if timeout.timed_out() {
+					drop(sync);
+					self.resync();
+					sync = self.sync.lock().unwrap();
+				}
+				continue;
+			}
+			let stale = mem::take(&mut sync.stale);
+			drop(sync);
+
+			let mut total = SyncReport::default();
+			let mut failed = Vec::new();
+			for path in stale {
+				match self.sync_path(backend, &path) {
+					Ok(report) => {
+						total.downloaded += report.downloaded;
+						total.bytes += report.bytes;
+						total.removed += report.removed;
+					}
+					Err(e) => {
+						warn!(path = %path, error = %e, "offline sync failed");
+						failed.push(path);
+					}
+				}
+			}
+			if let Err(e) = self.save_index() {
+				warn!(dir = %self.dir.display(), error = %e, "saving the offline index failed");
+			}
+			if total != SyncReport::default() {
+				info!(
+					downloaded = total.downloaded,
+					bytes = total.bytes,
+					removed = total.removed,
+					"offline copies updated"
+				);
+			}
+
+			sync = self.sync.lock().unwrap();
+			if !failed.is_empty() {
+				sync.stale.extend(failed);
+				sync = self.changed.wait_timeout(sync, RETRY_DELAY).unwrap().0;
+			}
+		}
+	}
+
+	fn close(&self) {
+		self.sync.lock().unwrap().closed = true;
+		self.changed.notify_all();
+	}
+}
+
+/// 在存储之上使用 [`OfflineCache`]：读取过的文件在本地保留副本，服务器不可达时固定和下载过的条目从本地副本列出和读取，
+/// 修改这些条目成功后重新同步它们的副本。释放时停止后台同步。
+pub(crate) struct OfflineBackend {
+	inner: Arc<dyn StorageBackend>,
+	cache: Arc<OfflineCache>,
+}
+
+impl OfflineBackend {
+	pub(crate) fn new(inner: Arc<dyn StorageBackend>, cache: Arc<OfflineCache>) -> Self {
+		Self { inner, cache }
+	}
+
+	fn read_offline(
+		&self,
+		path: &str,
+		offset: u64,
+		buffer: &mut [u8],
+		e: RemoteError,
+	) -> Result<usize, RemoteError> {
+		match self.cache.read_into(path, offset, buffer) {
+			Some(Ok(len)) => Ok(len),
+			Some(Err(local)) => {
+				warn!(path = %path, error = %local, "reading the offline copy failed");
+				Err(e)
+			}
+			None => Err(e),
+		}
+	}
+
+	// 修改成功后使副本重新同步
+	fn changed<T>(&self, path: &str, result: Result<T, RemoteError>) -> Result<T, RemoteError> {
+		if result.is_ok() {
+			self.cache.changed(path);
+		}
+		result
+	}
+}
+
+impl Drop for OfflineBackend {
+	fn drop(&mut self) {
+		self.cache.close();
+	}
+}
+
+impl StorageBackend for OfflineBackend {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError> {
+		match self.inner.stat(path) {
+			Err(e) if is_unreachable(&e) => self.cache.stat(path).ok_or(e),
+			result => result,
+		}
+	}
+
+	// 离线时整个目录在一页中返回
+	fn list_page(&self, path: &str, cursor: Option<&str>) -> Result<ListPage, RemoteError> {
+		match self.inner.list_page(path, cursor) {
+			Err(e) if is_unreachable(&e) => match self.cache.list(path) {
+				Some(items) => Ok(ListPage {
+					items,
+					next_cursor: None,
+				}),
+				None => Err(e),
+			},
+			result => result,
+		}
+	}
+
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError> {
+		let mut data = vec![0; length];
+		let len = self.read_into(path, offset, &mut data)?;
+		data.truncate(len);
+		Ok(data)
+	}
+
+	// 下载过的文件与存储中的属性相同时从本地副本读取；占位符第一次读取后在后台下载
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError> {
+		if let Some(local) = self.cache.stat(path).filter(|info| !info.is_directory) {
+			match self.inner.stat(path) {
+				Ok(current) if current.size == local.size && current.modified == local.modified => {
+					if let Some(Ok(len)) = self.cache.read_into(path, offset, buffer) {
+						return Ok(len);
+					}
+				}
+				Ok(_) => self.cache.changed(path),
+				Err(e) if is_unreachable(&e) => return self.read_offline(path, offset, buffer, e),
+				Err(_) => {}
+			}
+		}
+		match self.inner.read_into(path, offset, buffer) {
+			Ok(len) => {
+				self.cache.hydrate(path);
+				Ok(len)
+			}
+			Err(e) if is_unreachable(&e) => self.read_offline(path, offset, buffer, e),
+			Err(e) => Err(e),
+		}
+	}
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.write(path, offset, data))
+	}
+
+	fn commit(&self, path: &str, data: &[u8]) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.commit(path, data))
+	}
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.create(path, is_directory))
+	}
+
+	fn delete(&self, path: &str, dry_run: bool) -> Result<(), RemoteError> {
+		let result = self.inner.delete(path, dry_run);
+		if dry_run {
+			return result;
+		}
+		self.changed(path, result)
+	}
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError> {
+		let result = self.changed(old_path, self.inner.rename(old_path, new_path, replace));
+		self.changed(new_path, result)
+	}
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.truncate(path, size))
+	}
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.set_times(path, times))
+	}
+
+	fn commit_batch(
+		&self,
+		files: &[BatchFile],
+	) -> Result<Vec<Result<(), RemoteError>>, RemoteError> {
+		let results = self.inner.commit_batch(files)?;
+		for (file, result) in files.iter().zip(&results) {
+			if result.is_ok() {
+				self.cache.changed(file.path);
+			}
+		}
+		Ok(results)
+	}
+
+	fn zero_range(&self, path: &str, offset: u64, length: u64) -> Result<(), RemoteError> {
+		self.changed(path, self.inner.zero_range(path, offset, length))
+	}
+
+	fn read_only(&self) -> bool {
+		self.inner.read_only()
+	}
+
+	fn space(&self) -> Result<SpaceResponse, RemoteError> {
+		self.inner.space()
+	}
+
+	fn search(
+		&self,
+		path: &str,
+		pattern: &str,
+		recursive: bool,
+	) -> Result<SearchResponse, RemoteError> {
+		self.inner.search(path, pattern, recursive)
+	}
+
+	fn list_xattrs(&self, path: &str) -> Result<Vec<XattrEntry>, RemoteError> {
+		self.inner.list_xattrs(path)
+	}
+
+	fn get_xattr(&self, path: &str, name: &str) -> Result<Option<Vec<u8>>, RemoteError> {
+		self.inner.get_xattr(path, name)
+	}
+
+	fn put_xattr(&self, path: &str, name: &str, value: &[u8]) -> Result<(), RemoteError> {
+		self.inner.put_xattr(path, name, value)
+	}
+
+	fn delete_xattr(&self, path: &str, name: &str) -> Result<(), RemoteError> {
+		self.inner.delete_xattr(path, name)
+	}
+
+	fn list_versions(&self, path: &str) -> Result<Vec<VersionInfo>, RemoteError> {
+		self.inner.list_versions(path)
+	}
+
+	fn read_version(
+		&self,
+		path: &str,
+		id: &str,
+		offset: u64,
+		length: usize,
+	) -> Result<Vec<u8>, RemoteError> {
+		self.inner.read_version(path, id, offset, length)
+	}
+
+	fn list_trash(&self) -> Result<Vec<TrashEntry>, RemoteError> {
+		self.inner.list_trash()
+	}
+
+	fn restore_trash(&self, id: &str, path: Option<&str>) -> Result<RestoreResponse, RemoteError> {
+		let result = self.inner.restore_trash(id, path);
+		if let Ok(restored) = &result {
+			self.cache.changed(&restored.path);
+		}
+		result
+	}
+
+	fn checksum(&self, path: &str) -> Result<ChecksumResponse, RemoteError> {
+		self.inner.checksum(path)
+	}
+}
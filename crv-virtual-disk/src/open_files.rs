@@ -0,0 +1,161 @@
+use std::{
+	collections::BTreeMap,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{access::Right, requester::Requester, Staged};
+
+// 挂载中当前打开的文件。卸载前可以通过控制管道查看哪个程序还打开着卷上的文件
+#[derive(Default)]
+pub struct OpenFiles {
+	next_id: AtomicU64,
+	entries: Mutex<BTreeMap<u64, Arc<OpenEntry>>>,
+}
+
+struct OpenEntry {
+	path: String,
+	stream: Option<String>,
+	requester: Requester,
+	access: Vec<Right>,
+	opened: SystemTime,
+	read: AtomicU64,
+	written: AtomicU64,
+	// 整文件保存的暂存内容，强制卸载前提交
+	staged: Staged,
+}
+
+// 通过控制管道返回的一个打开的文件
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpenFile {
+	// 打开的是备用数据流时为 `file.txt:name`
+	pub path: String,
+	pub pid: u32,
+	pub process: Option<String>,
+	// 打开时请求的操作
+	pub access: Vec<Right>,
+	pub opened_unix_secs: u64,
+	pub bytes_read: u64,
+	pub bytes_written: u64,
+}
+
+// 表中的一项，由打开的文件的上下文持有，关闭（上下文被释放）时移除
+pub struct OpenHandle {
+	files: Arc<OpenFiles>,
+	id: u64,
+	entry: Arc<OpenEntry>,
+}
+
+impl OpenFiles {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub(crate) fn open(
+		self: &Arc<Self>,
+		path: String,
+		stream: Option<String>,
+		requester: Requester,
+		access: Vec<Right>,
+		staged: Staged,
+	) -> OpenHandle {
+		let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+		let entry = Arc::new(OpenEntry {
+			path,
+			stream,
+			requester,
+			access,
+			opened: SystemTime::now(),
+			read: AtomicU64::new(0),
+			written: AtomicU64::new(0),
+			staged,
+		});
+		self.entries.lock().unwrap().insert(id, entry.clone());
+		OpenHandle {
+			files: self.clone(),
+			id,
+			entry,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.entries.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
+	// 按打开的先后顺序
+	pub fn list(&self) -> Vec<OpenFile> {
+		self.entries
+			.lock()
+			.unwrap()
+			.values()
+			.map(|entry| OpenFile {
+				path: match &entry.stream {
+					Some(stream) => format!("{}:{}", entry.path, stream),
+					None => entry.path.clone(),
+				},
+				pid: entry.requester.pid,
+				process: entry.requester.process_name.clone(),
+				access: entry.access.clone(),
+				opened_unix_secs: entry
+					.opened
+					.duration_since(UNIX_EPOCH)
+					.map_or(0, |since| since.as_secs()),
+				bytes_read: entry.read.load(Ordering::Relaxed),
+				bytes_written: entry.written.load(Ordering::Relaxed),
+			})
+			.collect()
+	}
+
+	// 其他进程是否打开着 path（任一数据流），不计本进程为打破机会锁等自己打开的句柄
+	pub(crate) fn is_open_elsewhere(&self, path: &str) -> bool {
+		let pid = std::process::id();
+		self.entries
+			.lock()
+			.unwrap()
+			.values()
+			.any(|entry| entry.path == path && entry.requester.pid != pid)
+	}
+
+	// 打开的文件的路径、数据流和暂存内容
+	pub(crate) fn staged(&self) -> Vec<(String, Option<String>, Staged)> {
+		self.entries
+			.lock()
+			.unwrap()
+			.values()
+			.map(|entry| {
+				(
+					entry.path.clone(),
+					entry.stream.clone(),
+					entry.staged.clone(),
+				)
+			})
+			.collect()
+	}
+}
+
+impl OpenHandle {
+	pub fn add_read(&self, bytes: usize) {
+		self.entry.read.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	pub fn add_written(&self, bytes: usize) {
+		self.entry
+			.written
+			.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+}
+
+impl Drop for OpenHandle {
+	fn drop(&mut self) {
+		self.files.entries.lock().unwrap().remove(&self.id);
+	}
+}
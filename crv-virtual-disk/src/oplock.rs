@@ -0,0 +1,114 @@
+use std::{
+	collections::BTreeSet,
+	mem, ptr,
+	sync::{Arc, Condvar, Mutex},
+	thread,
+};
+
+use tracing::debug;
+use widestring::U16CString;
+use winapi::um::{
+	fileapi::{CreateFileW, LockFileEx, UnlockFileEx, OPEN_EXISTING},
+	handleapi::{CloseHandle, INVALID_HANDLE_VALUE},
+	minwinbase::{LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED},
+	winnt::{FILE_READ_DATA, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE},
+};
+
+/// 其他客户端修改文件后，打破本机程序在这个文件上持有的机会锁（oplock），让它们丢弃缓存的内容重新读取。
+///
+/// Dokan 驱动自己授予和打破机会锁，没有交给用户态的回调；驱动只在本卷上出现冲突的操作时打破它们，
+/// 看不到存储中的修改。这里在卷上打开文件并对第一个字节加共享的字节范围锁：加锁前驱动打破文件上的所有机会锁，
+/// 等持有者写回并确认后才返回，锁随即释放。打开可能等待持有者确认，所以在单独的线程中进行。
+pub(crate) struct OplockBreaker {
+	shared: Arc<Shared>,
+}
+
+#[derive(Default)]
+struct Queue {
+	// 挂载点下等待打破机会锁的完整路径
+	paths: BTreeSet<U16CString>,
+	closed: bool,
+}
+
+#[derive(Default)]
+struct Shared {
+	queue: Mutex<Queue>,
+	changed: Condvar,
+}
+
+impl OplockBreaker {
+	pub(crate) fn spawn() -> Self {
+		let shared = Arc::new(Shared::default());
+		{
+			let shared = shared.clone();
+			thread::Builder::new()
+				.name("oplock-break".to_string())
+				.spawn(move || shared.run())
+				.expect("cannot spawn oplock breaker");
+		}
+		Self { shared }
+	}
+
+	// 排队打破 path 上的机会锁，同一路径排队多次只处理一次
+	pub(crate) fn request(&self, path: U16CString) {
+		self.shared.queue.lock().unwrap().paths.insert(path);
+		self.shared.changed.notify_one();
+	}
+}
+
+impl Drop for OplockBreaker {
+	fn drop(&mut self) {
+		self.shared.queue.lock().unwrap().closed = true;
+		self.shared.changed.notify_one();
+	}
+}
+
+impl Shared {
+	fn run(&self) {
+		loop {
+			let path = {
+				let mut queue = self.queue.lock().unwrap();
+				loop {
+					if queue.closed {
+						return;
+					}
+					if let Some(path) = queue.paths.pop_first() {
+						break path;
+					}
+					queue = self.changed.wait(queue).unwrap();
+				}
+			};
+			if !break_oplocks(&path) {
+				debug!(
+					path = %path.display(),
+					error = %std::io::Error::last_os_error(),
+					"oplock: cannot open file to break its oplocks"
+				);
+			}
+		}
+	}
+}
+
+// 打开失败（文件已被删除等）时返回 false；加锁失败（其他程序持有冲突的锁）时机会锁已经被打破
+fn break_oplocks(path: &U16CString) -> bool {
+	unsafe {
+		let handle = CreateFileW(
+			path.as_ptr(),
+			FILE_READ_DATA,
+			FILE_SHARE_READ | FILE_SHARE_WRITE | FILE_SHARE_DELETE,
+			ptr::null_mut(),
+			OPEN_EXISTING,
+			0,
+			ptr::null_mut(),
+		);
+		if handle == INVALID_HANDLE_VALUE {
+			return false;
+		}
+		let mut overlapped: OVERLAPPED = mem::zeroed();
+		if LockFileEx(handle, LOCKFILE_FAIL_IMMEDIATELY, 0, 1, 0, &mut overlapped) != 0 {
+			UnlockFileEx(handle, 0, 1, 0, &mut overlapped);
+		}
+		CloseHandle(handle);
+		true
+	}
+}
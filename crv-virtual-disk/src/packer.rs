@@ -0,0 +1,240 @@
+use std::{
+	collections::HashSet,
+	sync::{Arc, Condvar, Mutex},
+	thread::{self, JoinHandle},
+	time::{Duration, Instant},
+};
+
+use tracing::{debug, error, warn};
+
+use crate::{
+	attr_cache::AttrCache, backend::BatchFile, commit_content, discard_journal, identity,
+	metrics::Metrics, Staged, StorageBackend,
+};
+
+/// 不超过这个大小的关闭的文件交给 [`Packer`] 批量提交。
+pub const MAX_PACKED_FILE_SIZE: usize = 256 * 1024;
+
+// 一批最多的文件数和内容总量，达到任一上限时立即发送
+const MAX_BATCH_FILES: usize = 256;
+const MAX_BATCH_BYTES: usize = 8 * 1024 * 1024;
+// 第一个文件加入后最多等待这么久，让之后关闭的文件加入同一批
+const LINGER: Duration = Duration::from_millis(20);
+
+/// 小文件的批量提交：关闭的小文件先排队，短暂等待后与之后关闭的文件一起在一个请求中提交（httpfs 服务器的 `POST /batch`），
+/// 复制大量小文件时省去每个文件一次的往返。排队的文件达到上限时关闭者等待。释放时提交所有排队的文件。
+/// 不同用户（见 [`identity::with_user`]）关闭的文件分开提交，各自代表关闭者。
+pub struct Packer {
+	shared: Arc<Shared>,
+	sender: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+	state: Mutex<State>,
+	// 有文件加入或队列关闭时通知发送线程，一批发送完成时通知等待的关闭者和 wait_for、wait_idle
+	changed: Condvar,
+	backend: Arc<dyn StorageBackend>,
+	attrs: Arc<AttrCache>,
+	metrics: Arc<Metrics>,
+}
+
+// 排队的文件：路径、暂存内容和关闭者代表的用户
+type Pending = (String, Staged, Option<String>);
+
+#[derive(Default)]
+struct State {
+	pending: Vec<Pending>,
+	pending_bytes: usize,
+	// 第一个排队的文件加入的时间
+	first_queued: Option<Instant>,
+	// 正在发送的一批中的路径
+	sending: HashSet<String>,
+	closed: bool,
+}
+
+impl State {
+	fn is_full(&self) -> bool {
+		self.pending.len() >= MAX_BATCH_FILES || self.pending_bytes >= MAX_BATCH_BYTES
+	}
+
+	fn is_busy(&self, path: &str) -> bool {
+		self.sending.contains(path) || self.pending.iter().any(|(pending, _, _)| pending == path)
+	}
+}
+
+impl Packer {
+	pub(crate) fn new(
+		backend: Arc<dyn StorageBackend>,
+		attrs: Arc<AttrCache>,
+		metrics: Arc<Metrics>,
+	) -> Self {
+		let shared = Arc::new(Shared {
+			state: Mutex::new(State::default()),
+			changed: Condvar::new(),
+			backend,
+			attrs,
+			metrics,
+		});
+		let sender = {
+			let shared = shared.clone();
+			thread::Builder::new()
+				.name("packer".to_string())
+				.spawn(move || shared.run())
+				.expect("cannot spawn packer")
+		};
+		Self {
+			shared,
+			sender: Some(sender),
+		}
+	}
+
+	// 把关闭的文件的暂存内容加入下一批；排队已满时等待上一批发送
+	pub(crate) fn submit(&self, path: &str, staged: Staged, size: usize) {
+		let mut state = self.shared.state.lock().unwrap();
+		while state.is_full() {
+			state = self.shared.changed.wait(state).unwrap();
+		}
+		state
+			.pending
+			.push((path.to_string(), staged, identity::current_user()));
+		state.pending_bytes += size;
+		state.first_queued.get_or_insert_with(Instant::now);
+		self.shared.changed.notify_all();
+	}
+
+	/// 等待 `path` 排队或正在发送的提交完成。
+	pub fn wait_for(&self, path: &str) {
+		let mut state = self.shared.state.lock().unwrap();
+		while state.is_busy(path) {
+			state = self.shared.changed.wait(state).unwrap();
+		}
+	}
+
+	/// 等待所有排队的文件提交完成。
+	pub fn wait_idle(&self) {
+		let mut state = self.shared.state.lock().unwrap();
+		while !state.pending.is_empty() || !state.sending.is_empty() {
+			state = self.shared.changed.wait(state).unwrap();
+		}
+	}
+}
+
+impl Shared {
+	fn run(&self) {
+		let mut state = self.state.lock().unwrap();
+		loop {
+			let Some(first_queued) = state.first_queued else {
+				if state.closed {
+					return;
+				}
+				state = self.changed.wait(state).unwrap();
+				continue;
+			};
+			let waited = first_queued.elapsed();
+			if !state.closed && !state.is_full() && waited < LINGER {
+				state = self.changed.wait_timeout(state, LINGER - waited).unwrap().0;
+				continue;
+			}
+			let batch = std::mem::take(&mut state.pending);
+			state.pending_bytes = 0;
+			state.first_queued = None;
+			state.sending = batch.iter().map(|(path, _, _)| path.clone()).collect();
+			// 排队的文件已取走，等待的关闭者可以继续
+			self.changed.notify_all();
+			drop(state);
+
+			for (user, files) in by_user(batch) {
+				identity::with_user(user.as_deref(), || self.send(&files));
+			}
+
+			state = self.state.lock().unwrap();
+			state.sending.clear();
+			self.changed.notify_all();
+		}
+	}
+
+	// 在一个请求中提交一批文件；整批失败时（例如服务器不支持 /batch）逐个提交
+	fn send(&self, batch: &[(String, Staged)]) {
+		let mut contents: Vec<_> = batch
+			.iter()
+			.map(|(_, staged)| staged.lock().unwrap())
+			.collect();
+		let files: Vec<BatchFile> = batch
+			.iter()
+			.zip(&contents)
+			.filter_map(|((path, _), content)| {
+				let content = content.as_ref()?;
+				Some(BatchFile {
+					path,
+					data: &content.data,
+					times: content.times,
+				})
+			})
+			.collect();
+		let results = match self.backend.commit_batch(&files) {
+			Ok(results) => results,
+			Err(e) => {
+				warn!(files = files.len(), error = %e, "commit_batch failed, committing files one by one");
+				drop(files);
+				drop(contents);
+				for (path, staged) in batch {
+					if commit_content(
+						self.backend.as_ref(),
+						&self.attrs,
+						&self.metrics,
+						path,
+						None,
+						staged,
+					)
+					.is_ok()
+					{
+						discard_journal(staged);
+					}
+				}
+				return;
+			}
+		};
+		debug!(files = files.len(), "packed commit");
+		drop(files);
+		let mut results = results.into_iter();
+		for ((path, _), content) in batch.iter().zip(&mut contents) {
+			let Some(content) = content.as_mut() else {
+				continue;
+			};
+			self.attrs.invalidate(path);
+			match results.next() {
+				Some(Ok(())) => {
+					self.metrics.add_written(content.data.len());
+					content.dirty = false;
+					content.times = Default::default();
+					content.discard_journal();
+				}
+				// 日志留在磁盘上，下次挂载时重放
+				Some(Err(e)) => error!(path = %path, error = %e, "packed commit failed"),
+				None => {}
+			}
+		}
+	}
+}
+
+// 按关闭者代表的用户分组，保持各组内的顺序
+fn by_user(batch: Vec<Pending>) -> Vec<(Option<String>, Vec<(String, Staged)>)> {
+	let mut groups: Vec<(Option<String>, Vec<(String, Staged)>)> = Vec::new();
+	for (path, staged, user) in batch {
+		match groups.iter_mut().find(|(group, _)| *group == user) {
+			Some((_, files)) => files.push((path, staged)),
+			None => groups.push((user, vec![(path, staged)])),
+		}
+	}
+	groups
+}
+
+impl Drop for Packer {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().closed = true;
+		self.shared.changed.notify_all();
+		if let Some(sender) = self.sender.take() {
+			let _ = sender.join();
+		}
+	}
+}
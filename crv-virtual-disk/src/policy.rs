@@ -0,0 +1,58 @@
+use crate::{access, requester::Requester};
+
+/// 对一个进程打开的文件使用的缓存方式。
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CachePolicy {
+	// 新建或覆盖的文件先在本地暂存，关闭或 flush 时整体提交；为 false 时每次写入直接发送到存储
+	pub write_back: bool,
+	// 查询打开的文件的属性时使用属性缓存；为 false 时总是向存储查询
+	pub attr_cache: bool,
+}
+
+impl Default for CachePolicy {
+	fn default() -> Self {
+		Self {
+			write_back: true,
+			attr_cache: true,
+		}
+	}
+}
+
+/// 一条按进程的策略：`process` 为可执行文件名（如 `sqlservr.exe`），可以带通配符，大小写不敏感。
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProcessPolicy {
+	pub process: String,
+	pub cache: CachePolicy,
+}
+
+/// 按进程选择缓存方式，例如数据库引擎需要自己控制写入顺序，不能让整文件暂存推迟写入。
+/// 第一条匹配请求者进程名的策略生效，没有匹配时使用默认的缓存方式。
+#[derive(Debug, Clone, Default)]
+pub struct ProcessPolicies {
+	policies: Vec<ProcessPolicy>,
+}
+
+impl ProcessPolicies {
+	pub fn new(policies: Vec<ProcessPolicy>) -> Self {
+		Self { policies }
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.policies.is_empty()
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = &ProcessPolicy> {
+		self.policies.iter()
+	}
+
+	/// 请求者打开的文件使用的缓存方式；进程名未知时使用默认方式。
+	pub fn cache_policy(&self, requester: &Requester) -> CachePolicy {
+		let Some(name) = requester.process_name.as_deref() else {
+			return CachePolicy::default();
+		};
+		self.policies
+			.iter()
+			.find(|policy| access::name_matches(&policy.process, name))
+			.map_or_else(CachePolicy::default, |policy| policy.cache)
+	}
+}
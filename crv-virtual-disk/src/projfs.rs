@@ -0,0 +1,316 @@
+use std::{collections::HashMap, fs, io, mem, path::Path, ptr, sync::Mutex};
+
+use aes_gcm::aead::{rand_core::RngCore, OsRng};
+use tracing::error;
+use widestring::{U16CStr, U16CString};
+use windows_sys::{
+	core::{GUID, HRESULT},
+	Win32::{
+		Foundation::{
+			ERROR_FILE_NOT_FOUND, ERROR_INSUFFICIENT_BUFFER, ERROR_REPARSE_POINT_ENCOUNTERED,
+			E_INVALIDARG, E_OUTOFMEMORY, S_OK,
+		},
+		Storage::ProjectedFileSystem::{
+			PrjAllocateAlignedBuffer, PrjFileNameCompare, PrjFileNameMatch, PrjFillDirEntryBuffer,
+			PrjFreeAlignedBuffer, PrjMarkDirectoryAsPlaceholder, PrjStartVirtualizing,
+			PrjStopVirtualizing, PrjWriteFileData, PrjWritePlaceholderInfo, PRJ_CALLBACKS,
+			PRJ_CALLBACK_DATA, PRJ_CB_DATA_FLAG_ENUM_RESTART_SCAN, PRJ_DIR_ENTRY_BUFFER_HANDLE,
+			PRJ_FILE_BASIC_INFO, PRJ_NAMESPACE_VIRTUALIZATION_CONTEXT, PRJ_PLACEHOLDER_INFO,
+		},
+	},
+};
+
+use crate::{
+	error::RemoteError,
+	vfs::{share_path, VirtualFs},
+	HttpFsHandler, RemoteFileInfo,
+};
+
+// 文件内容按 1 MiB 从存储读取并写入本地
+const HYDRATE_CHUNK: u64 = 1024 * 1024;
+// FILETIME 的起点 1601-01-01 到 UNIX 纪元的秒数
+const FILETIME_EPOCH_OFFSET: i64 = 11_644_473_600;
+const FILETIME_TICKS: i64 = 10_000_000;
+
+// 一次目录枚举：开始时取得整个目录并按 ProjFS 的名称顺序排序，之后按缓冲区的容量分批填充
+struct Enumeration {
+	entries: Vec<(U16CString, RemoteFileInfo)>,
+	next: usize,
+	// 第一次填充和重新开始时给出的通配符
+	pattern: Option<U16CString>,
+}
+
+// 回调通过实例上下文取得的状态
+struct Provider {
+	fs: Box<dyn VirtualFs>,
+	enumerations: Mutex<HashMap<u128, Enumeration>>,
+}
+
+// 正在提供内容的虚拟化根目录，drop 时停止；已经放到本地的占位符和文件保留在目录中
+pub struct Virtualization {
+	context: PRJ_NAMESPACE_VIRTUALIZATION_CONTEXT,
+	_provider: Box<Provider>,
+}
+
+impl Drop for Virtualization {
+	fn drop(&mut self) {
+		unsafe { PrjStopVirtualizing(self.context) };
+	}
+}
+
+fn hresult_from_win32(code: u32) -> HRESULT {
+	(code & 0xFFFF | 0x8007_0000) as HRESULT
+}
+
+// 记录错误并换算为 HRESULT（HRESULT_FROM_NT）；条目不存在是查找的正常结果，不记录，ProjFS 要求以 ERROR_FILE_NOT_FOUND 报告
+fn hresult(path: &str, e: &RemoteError) -> HRESULT {
+	if e.code() == Some("not_found") {
+		return hresult_from_win32(ERROR_FILE_NOT_FOUND);
+	}
+	error!(path = %path, error = %e, "projfs operation failed");
+	e.to_ntstatus() | 0x1000_0000
+}
+
+fn check(result: HRESULT, what: &str) -> io::Result<()> {
+	if result < 0 {
+		return Err(io::Error::other(format!(
+			"{} failed with {:#010x}",
+			what, result
+		)));
+	}
+	Ok(())
+}
+
+fn guid_key(guid: &GUID) -> u128 {
+	let mut key = [0; 16];
+	key[..4].copy_from_slice(&guid.data1.to_le_bytes());
+	key[4..6].copy_from_slice(&guid.data2.to_le_bytes());
+	key[6..8].copy_from_slice(&guid.data3.to_le_bytes());
+	key[8..].copy_from_slice(&guid.data4);
+	u128::from_le_bytes(key)
+}
+
+fn filetime(secs: u64) -> i64 {
+	(secs as i64 + FILETIME_EPOCH_OFFSET) * FILETIME_TICKS
+}
+
+fn basic_info(info: &RemoteFileInfo) -> PRJ_FILE_BASIC_INFO {
+	PRJ_FILE_BASIC_INFO {
+		IsDirectory: info.is_directory as u8,
+		FileSize: if info.is_directory {
+			0
+		} else {
+			info.size as i64
+		},
+		CreationTime: filetime(info.created),
+		LastAccessTime: filetime(info.accessed),
+		LastWriteTime: filetime(info.modified),
+		ChangeTime: filetime(info.modified),
+		FileAttributes: HttpFsHandler::file_attributes(info),
+	}
+}
+
+// 回调数据中的提供程序和请求的路径（相对于虚拟化根目录，以 \ 分隔）
+unsafe fn request<'a>(data: *const PRJ_CALLBACK_DATA) -> (&'a Provider, String) {
+	let data = &*data;
+	let provider = &*(data.InstanceContext as *const Provider);
+	let path = if data.FilePathName.is_null() {
+		String::new()
+	} else {
+		U16CStr::from_ptr_str(data.FilePathName).to_string_lossy()
+	};
+	(provider, path)
+}
+
+unsafe extern "system" fn start_enumeration(
+	data: *const PRJ_CALLBACK_DATA,
+	id: *const GUID,
+) -> HRESULT {
+	let (provider, request_path) = request(data);
+	let path = share_path(&request_path);
+	let items = match provider.fs.list(&path) {
+		Ok(items) => items,
+		Err(e) => return hresult(&path, &e),
+	};
+	let mut entries: Vec<_> = items
+		.into_iter()
+		.filter_map(|item| Some((U16CString::from_str(&item.name).ok()?, item)))
+		.collect();
+	entries.sort_by(|(a, _), (b, _)| PrjFileNameCompare(a.as_ptr(), b.as_ptr()).cmp(&0));
+	provider.enumerations.lock().unwrap().insert(
+		guid_key(&*id),
+		Enumeration {
+			entries,
+			next: 0,
+			pattern: None,
+		},
+	);
+	S_OK
+}
+
+unsafe extern "system" fn end_enumeration(
+	data: *const PRJ_CALLBACK_DATA,
+	id: *const GUID,
+) -> HRESULT {
+	let (provider, _) = request(data);
+	provider
+		.enumerations
+		.lock()
+		.unwrap()
+		.remove(&guid_key(&*id));
+	S_OK
+}
+
+// 填充到缓冲区满为止，下一次从没有放下的条目继续；第一个条目就放不下时返回缓冲区不足
+unsafe extern "system" fn get_enumeration(
+	data: *const PRJ_CALLBACK_DATA,
+	id: *const GUID,
+	search_expression: *const u16,
+	buffer: PRJ_DIR_ENTRY_BUFFER_HANDLE,
+) -> HRESULT {
+	let (provider, _) = request(data);
+	let mut enumerations = provider.enumerations.lock().unwrap();
+	let Some(enumeration) = enumerations.get_mut(&guid_key(&*id)) else {
+		return E_INVALIDARG;
+	};
+	if (*data).Flags & PRJ_CB_DATA_FLAG_ENUM_RESTART_SCAN != 0 || enumeration.pattern.is_none() {
+		enumeration.next = 0;
+		enumeration.pattern = Some(match search_expression.is_null() {
+			true => U16CString::from_str("*").unwrap(),
+			false => U16CStr::from_ptr_str(search_expression).to_ucstring(),
+		});
+	}
+	let pattern = enumeration.pattern.as_ref().unwrap();
+	let mut filled = 0;
+	while let Some((name, info)) = enumeration.entries.get(enumeration.next) {
+		if PrjFileNameMatch(name.as_ptr(), pattern.as_ptr()) != 0 {
+			let result = PrjFillDirEntryBuffer(name.as_ptr(), &basic_info(info), buffer);
+			if result == hresult_from_win32(ERROR_INSUFFICIENT_BUFFER) && filled > 0 {
+				break;
+			}
+			if result < 0 {
+				return result;
+			}
+			filled += 1;
+		}
+		enumeration.next += 1;
+	}
+	S_OK
+}
+
+// 第一次访问条目时在本地创建占位符，名称按存储中的大小写
+unsafe extern "system" fn get_placeholder_info(data: *const PRJ_CALLBACK_DATA) -> HRESULT {
+	let (provider, request_path) = request(data);
+	let path = share_path(&request_path);
+	let info = match provider.fs.stat(&path) {
+		Ok(info) => info,
+		Err(e) => return hresult(&path, &e),
+	};
+	let destination = match (request_path.rfind('\\'), info.name.is_empty()) {
+		(_, true) => request_path.clone(),
+		(Some(index), false) => format!("{}\\{}", &request_path[..index], info.name),
+		(None, false) => info.name.clone(),
+	};
+	let Ok(destination) = U16CString::from_str(&destination) else {
+		return E_INVALIDARG;
+	};
+	let mut placeholder: PRJ_PLACEHOLDER_INFO = mem::zeroed();
+	placeholder.FileBasicInfo = basic_info(&info);
+	PrjWritePlaceholderInfo(
+		(*data).NamespaceVirtualizationContext,
+		destination.as_ptr(),
+		&placeholder,
+		mem::size_of::<PRJ_PLACEHOLDER_INFO>() as u32,
+	)
+}
+
+// 第一次读取文件时把请求的范围从存储写入本地，之后文件的读取不再经过提供程序
+unsafe extern "system" fn get_file_data(
+	data: *const PRJ_CALLBACK_DATA,
+	offset: u64,
+	length: u32,
+) -> HRESULT {
+	let (provider, request_path) = request(data);
+	let path = share_path(&request_path);
+	let context = (*data).NamespaceVirtualizationContext;
+	let end = offset + length as u64;
+	let mut position = offset;
+	while position < end {
+		let chunk = match provider.fs.read(
+			&path,
+			position,
+			(end - position).min(HYDRATE_CHUNK) as usize,
+		) {
+			Ok(chunk) => chunk,
+			Err(e) => return hresult(&path, &e),
+		};
+		// 文件在存储中变短
+		if chunk.is_empty() {
+			break;
+		}
+		let buffer = PrjAllocateAlignedBuffer(context, chunk.len());
+		if buffer.is_null() {
+			return E_OUTOFMEMORY;
+		}
+		ptr::copy_nonoverlapping(chunk.as_ptr(), buffer.cast(), chunk.len());
+		let result = PrjWriteFileData(
+			context,
+			&(*data).DataStreamId,
+			buffer,
+			position,
+			chunk.len() as u32,
+		);
+		PrjFreeAlignedBuffer(buffer);
+		if result < 0 {
+			return result;
+		}
+		position += chunk.len() as u64;
+	}
+	S_OK
+}
+
+// 以 root 为虚拟化根目录提供存储中的内容；目录不存在时创建，第一次使用时标记为虚拟化根目录。
+// 目录和文件在第一次访问时从存储取得，之后由本地文件系统直接提供
+pub fn start(fs: impl VirtualFs + 'static, root: &Path) -> io::Result<Virtualization> {
+	fs::create_dir_all(root)?;
+	let root = U16CString::from_os_str(root)
+		.map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+	let instance = GUID::from_u128((OsRng.next_u64() as u128) << 64 | OsRng.next_u64() as u128);
+	// 已经是虚拟化根目录时沿用其中记录的实例
+	let result = unsafe {
+		PrjMarkDirectoryAsPlaceholder(root.as_ptr(), ptr::null(), ptr::null(), &instance)
+	};
+	if result != hresult_from_win32(ERROR_REPARSE_POINT_ENCOUNTERED) {
+		check(result, "PrjMarkDirectoryAsPlaceholder")?;
+	}
+
+	let provider = Box::new(Provider {
+		fs: Box::new(fs),
+		enumerations: Mutex::new(HashMap::new()),
+	});
+	let callbacks = PRJ_CALLBACKS {
+		StartDirectoryEnumerationCallback: Some(start_enumeration),
+		EndDirectoryEnumerationCallback: Some(end_enumeration),
+		GetDirectoryEnumerationCallback: Some(get_enumeration),
+		GetPlaceholderInfoCallback: Some(get_placeholder_info),
+		GetFileDataCallback: Some(get_file_data),
+		QueryFileNameCallback: None,
+		NotificationCallback: None,
+		CancelCommandCallback: None,
+	};
+	let mut context = ptr::null_mut();
+	let result = unsafe {
+		PrjStartVirtualizing(
+			root.as_ptr(),
+			&callbacks,
+			&*provider as *const Provider as *const _,
+			ptr::null(),
+			&mut context,
+		)
+	};
+	check(result, "PrjStartVirtualizing")?;
+	Ok(Virtualization {
+		context,
+		_provider: provider,
+	})
+}
@@ -0,0 +1,101 @@
+use std::{
+	os::windows::io::{AsRawHandle, OwnedHandle},
+	ptr,
+};
+
+use widestring::U16CStr;
+use winapi::{
+	shared::{
+		minwindef::{DWORD, FALSE, MAX_PATH},
+		winerror::ERROR_INSUFFICIENT_BUFFER,
+	},
+	um::{
+		errhandlingapi::GetLastError,
+		handleapi::CloseHandle,
+		processthreadsapi::OpenProcess,
+		sddl::ConvertSidToStringSidW,
+		securitybaseapi::GetTokenInformation,
+		winbase::{LocalFree, QueryFullProcessImageNameW},
+		winnt::{TokenUser, PROCESS_QUERY_LIMITED_INFORMATION, TOKEN_USER},
+	},
+};
+
+// 带 \\?\ 前缀的路径的长度上限（UTF-16 单元）
+const MAX_LONG_PATH: usize = 32768;
+
+/// 发起请求的进程：进程号、可执行文件名（如 `explorer.exe`）和运行它的用户的 SID（如 `S-1-5-21-...`）。
+/// 进程已退出或没有权限查询时名称或 SID 为 None。
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Requester {
+	pub pid: u32,
+	pub process_name: Option<String>,
+	pub user_sid: Option<String>,
+}
+
+impl Requester {
+	/// 由 Dokan 回调给出的进程号和请求者令牌查询进程名称和用户。
+	pub fn new(pid: u32, token: Option<OwnedHandle>) -> Self {
+		Self {
+			pid,
+			process_name: process_name(pid),
+			user_sid: token.as_ref().and_then(user_sid),
+		}
+	}
+}
+
+// 进程可执行文件的文件名
+fn process_name(pid: u32) -> Option<String> {
+	unsafe {
+		let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, pid);
+		if process.is_null() {
+			return None;
+		}
+		// 可执行文件的路径可以超过 MAX_PATH：缓冲区不够时加倍重试，直到路径的长度上限
+		let mut buffer = vec![0u16; MAX_PATH];
+		let path = loop {
+			let mut length = buffer.len() as DWORD;
+			if QueryFullProcessImageNameW(process, 0, buffer.as_mut_ptr(), &mut length) != 0 {
+				break Some(String::from_utf16_lossy(&buffer[..length as usize]));
+			}
+			if GetLastError() != ERROR_INSUFFICIENT_BUFFER || buffer.len() >= MAX_LONG_PATH {
+				break None;
+			}
+			buffer.resize(buffer.len() * 2, 0);
+		};
+		CloseHandle(process);
+		let path = path?;
+		Some(path.rsplit('\\').next().unwrap_or_default().to_string())
+	}
+}
+
+// 令牌所属用户的 SID 字符串
+fn user_sid(token: &OwnedHandle) -> Option<String> {
+	unsafe {
+		let handle = token.as_raw_handle().cast();
+		let mut size: DWORD = 0;
+		GetTokenInformation(handle, TokenUser, ptr::null_mut(), 0, &mut size);
+		if size == 0 {
+			return None;
+		}
+		// TOKEN_USER 需要按指针对齐
+		let mut buffer = vec![0u64; (size as usize).div_ceil(8)];
+		if GetTokenInformation(
+			handle,
+			TokenUser,
+			buffer.as_mut_ptr().cast(),
+			size,
+			&mut size,
+		) == 0
+		{
+			return None;
+		}
+		let user = &*(buffer.as_ptr() as *const TOKEN_USER);
+		let mut string = ptr::null_mut();
+		if ConvertSidToStringSidW(user.User.Sid, &mut string) == 0 {
+			return None;
+		}
+		let sid = U16CStr::from_ptr_str(string).to_string_lossy();
+		LocalFree(string.cast());
+		Some(sid)
+	}
+}
@@ -0,0 +1,34 @@
+// 只读的 .snapshots 伪目录：其中镜像共享的目录结构，每个文件显示为一个目录，
+// 目录中列出该文件在服务器上保存的历史版本，版本文件名为 "<版本 ID>_<文件名>"
+pub const SNAPSHOTS_DIR: &str = ".snapshots";
+
+#[derive(Debug, Clone)]
+pub enum Node {
+	// 镜像的共享目录，"." 为根目录
+	Directory(String),
+	// 某个文件的版本列表
+	Versions(String),
+	// 某个文件的一个历史版本
+	Version { path: String, id: String },
+}
+
+// 位于 .snapshots 下的路径返回它在共享中对应的部分，.snapshots 本身返回空字符串
+pub fn split(path: &str) -> Option<&str> {
+	if path == SNAPSHOTS_DIR {
+		return Some("");
+	}
+	path.strip_prefix(SNAPSHOTS_DIR)?.strip_prefix('/')
+}
+
+pub fn version_name(id: &str, path: &str) -> String {
+	let file_name = path.rsplit('/').next().unwrap_or(path);
+	format!("{}_{}", id, file_name)
+}
+
+// 从版本文件名中取出版本 ID，文件名部分必须与所属文件一致
+pub fn version_id<'a>(name: &'a str, path: &str) -> Option<&'a str> {
+	let (id, file_name) = name.split_once('_')?;
+	file_name
+		.eq_ignore_ascii_case(path.rsplit('/').next().unwrap_or(path))
+		.then_some(id)
+}
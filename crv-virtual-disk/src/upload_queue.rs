@@ -0,0 +1,163 @@
+use std::{
+	collections::{HashMap, HashSet, VecDeque},
+	sync::{Arc, Condvar, Mutex},
+	thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+// 每个工作线程最多积压的任务数，超过后提交者等待
+const QUEUED_PER_WORKER: usize = 4;
+
+/// 关闭的文件的后台上传：固定数量的工作线程并行上传不同文件的暂存内容，同一文件的上传按提交顺序依次执行。
+/// 积压的上传达到上限时提交者等待，复制大量小文件时不会无限占用内存。释放时等待所有上传完成。
+pub struct UploadQueue {
+	shared: Arc<Shared>,
+	workers: Vec<JoinHandle<()>>,
+}
+
+struct Shared {
+	state: Mutex<State>,
+	// 有新任务或队列关闭时通知工作线程
+	work: Condvar,
+	// 有任务完成时通知等待的提交者和 wait_for、wait_idle
+	done: Condvar,
+	capacity: usize,
+}
+
+#[derive(Default)]
+struct State {
+	// 每个路径尚未开始的任务
+	pending: HashMap<String, VecDeque<Job>>,
+	// 有待执行任务且没有正在执行的任务的路径，按就绪顺序
+	ready: VecDeque<String>,
+	// 正在执行任务的路径
+	running: HashSet<String>,
+	queued: usize,
+	closed: bool,
+}
+
+impl State {
+	fn is_busy(&self, path: &str) -> bool {
+		self.pending.contains_key(path) || self.running.contains(path)
+	}
+
+	fn is_idle(&self) -> bool {
+		self.pending.is_empty() && self.running.is_empty()
+	}
+}
+
+impl UploadQueue {
+	/// 启动 `workers` 个上传线程（至少一个）。
+	pub fn new(workers: usize) -> Self {
+		let workers = workers.max(1);
+		let shared = Arc::new(Shared {
+			state: Mutex::new(State::default()),
+			work: Condvar::new(),
+			done: Condvar::new(),
+			capacity: workers * QUEUED_PER_WORKER,
+		});
+		let workers = (0..workers)
+			.map(|index| {
+				let shared = shared.clone();
+				thread::Builder::new()
+					.name(format!("upload-{}", index))
+					.spawn(move || shared.run())
+					.expect("cannot spawn upload worker")
+			})
+			.collect();
+		Self { shared, workers }
+	}
+
+	/// 在 `path` 之前提交的上传完成后执行 `job`；积压已满时等待。
+	pub fn submit(&self, path: &str, job: impl FnOnce() + Send + 'static) {
+		let mut state = self.shared.state.lock().unwrap();
+		while state.queued >= self.shared.capacity {
+			state = self.shared.done.wait(state).unwrap();
+		}
+		let first = !state.is_busy(path);
+		state
+			.pending
+			.entry(path.to_string())
+			.or_default()
+			.push_back(Box::new(job));
+		state.queued += 1;
+		if first {
+			state.ready.push_back(path.to_string());
+			self.shared.work.notify_one();
+		}
+	}
+
+	/// 等待 `path` 已提交的上传全部完成。
+	pub fn wait_for(&self, path: &str) {
+		let mut state = self.shared.state.lock().unwrap();
+		while state.is_busy(path) {
+			state = self.shared.done.wait(state).unwrap();
+		}
+	}
+
+	/// 等待所有已提交的上传完成。
+	pub fn wait_idle(&self) {
+		let mut state = self.shared.state.lock().unwrap();
+		while !state.is_idle() {
+			state = self.shared.done.wait(state).unwrap();
+		}
+	}
+
+	/// 积压和正在执行的上传数。
+	pub fn len(&self) -> usize {
+		let state = self.shared.state.lock().unwrap();
+		state.queued + state.running.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+impl Shared {
+	fn run(&self) {
+		let mut state = self.state.lock().unwrap();
+		loop {
+			let Some(path) = state.ready.pop_front() else {
+				if state.closed {
+					return;
+				}
+				state = self.work.wait(state).unwrap();
+				continue;
+			};
+			let jobs = state
+				.pending
+				.get_mut(&path)
+				.expect("ready path has pending uploads");
+			let job = jobs.pop_front().expect("ready path has pending uploads");
+			if jobs.is_empty() {
+				state.pending.remove(&path);
+			}
+			state.queued -= 1;
+			state.running.insert(path.clone());
+			drop(state);
+
+			job();
+
+			state = self.state.lock().unwrap();
+			state.running.remove(&path);
+			// 同一路径的下一个上传在这个完成之后才就绪
+			if state.pending.contains_key(&path) {
+				state.ready.push_back(path);
+				self.work.notify_one();
+			}
+			self.done.notify_all();
+		}
+	}
+}
+
+impl Drop for UploadQueue {
+	fn drop(&mut self) {
+		self.shared.state.lock().unwrap().closed = true;
+		self.shared.work.notify_all();
+		for worker in self.workers.drain(..) {
+			let _ = worker.join();
+		}
+	}
+}
@@ -0,0 +1,75 @@
+use std::{
+	fs::{self, File},
+	io::{self, Read},
+	path::Path,
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::backend::StorageBackend;
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+	let mut file = File::open(path)?;
+	let mut hasher = Sha256::new();
+	let mut buffer = vec![0u8; 1024 * 1024];
+	loop {
+		let n = file.read(&mut buffer)?;
+		if n == 0 {
+			break;
+		}
+		hasher.update(&buffer[..n]);
+	}
+	Ok(hex::encode(hasher.finalize()))
+}
+
+// 比较本地目录中的每个文件与服务器上 remote_dir 下同名文件的 sha256，
+// 打印不一致或服务器上缺失的文件，返回这类文件的数量
+pub fn verify(
+	backend: &dyn StorageBackend,
+	local_dir: &Path,
+	remote_dir: &str,
+) -> io::Result<usize> {
+	let mut divergent = 0;
+	let mut pending = vec![(
+		local_dir.to_path_buf(),
+		remote_dir.trim_matches('/').to_string(),
+	)];
+	while let Some((dir, remote)) = pending.pop() {
+		let mut entries: Vec<_> = fs::read_dir(&dir)?.flatten().collect();
+		entries.sort_by_key(|entry| entry.file_name());
+		for entry in entries {
+			let name = entry.file_name().to_string_lossy().into_owned();
+			let remote_path = if remote.is_empty() {
+				name
+			} else {
+				format!("{}/{}", remote, name)
+			};
+			let file_type = entry.file_type()?;
+			if file_type.is_dir() {
+				pending.push((entry.path(), remote_path));
+				continue;
+			}
+			if !file_type.is_file() {
+				continue;
+			}
+
+			let local = sha256_file(&entry.path())?;
+			match backend.checksum(&remote_path) {
+				Ok(remote) if remote.digest.eq_ignore_ascii_case(&local) => {}
+				Ok(_) => {
+					println!("MISMATCH  {}", remote_path);
+					divergent += 1;
+				}
+				Err(e) if e.code() == Some("not_found") => {
+					println!("MISSING   {}", remote_path);
+					divergent += 1;
+				}
+				Err(e) => {
+					println!("ERROR     {}: {}", remote_path, e);
+					divergent += 1;
+				}
+			}
+		}
+	}
+	Ok(divergent)
+}
@@ -0,0 +1,181 @@
+use std::collections::HashMap;
+
+use crate::{error::RemoteError, RemoteFileInfo, SpaceResponse, TimesUpdate};
+
+// 根目录的 inode 号，由 FUSE 规定
+pub const ROOT_INODE: u64 = 1;
+
+// 挂载中单个名称的最大长度（UTF-16 单元），与 NTFS 相同；路径的总长度不受限制
+pub const MAX_NAME_LENGTH: usize = 255;
+
+// 与挂载驱动无关的文件系统操作。路径为共享内以 / 分隔的路径，根目录为 "."；修改操作使属性缓存中的相关条目失效。
+// Dokan 的处理程序在这些操作之上实现 Windows 的语义（整文件暂存后原子提交、备用数据流、.snapshots 伪目录），
+// FUSE 适配器把内核的请求直接映射到这些操作
+pub trait VirtualFs: Send + Sync {
+	fn stat(&self, path: &str) -> Result<RemoteFileInfo, RemoteError>;
+
+	// 目录中的所有条目
+	fn list(&self, path: &str) -> Result<Vec<RemoteFileInfo>, RemoteError>;
+
+	// 读到文件末尾时返回的数据可以短于 length
+	fn read(&self, path: &str, offset: u64, length: usize) -> Result<Vec<u8>, RemoteError>;
+
+	// 读到调用者的缓冲区，返回读到的字节数
+	fn read_into(&self, path: &str, offset: u64, buffer: &mut [u8]) -> Result<usize, RemoteError>;
+
+	fn write(&self, path: &str, offset: u64, data: &[u8]) -> Result<(), RemoteError>;
+
+	fn create(&self, path: &str, is_directory: bool) -> Result<(), RemoteError>;
+
+	// 非递归删除
+	fn remove(&self, path: &str) -> Result<(), RemoteError>;
+
+	fn rename(&self, old_path: &str, new_path: &str, replace: bool) -> Result<(), RemoteError>;
+
+	fn truncate(&self, path: &str, size: u64) -> Result<(), RemoteError>;
+
+	fn set_times(&self, path: &str, times: &TimesUpdate) -> Result<(), RemoteError>;
+
+	// 总容量和可用空间；存储不提供时为固定的值
+	fn space(&self) -> SpaceResponse;
+
+	fn read_only(&self) -> bool;
+}
+
+// Windows 驱动给出的以 \ 开头的路径转换为共享内的路径
+pub fn share_path(file_name: &str) -> String {
+	let trimmed = file_name.trim_start_matches('\\').replace('\\', "/");
+	if trimmed.is_empty() {
+		".".to_string()
+	} else {
+		trimmed
+	}
+}
+
+// 新建或改名得到的名称超过 MAX_NAME_LENGTH 时返回 invalid_name，与 NTFS 一样在请求存储之前拒绝
+pub fn check_name_length(path: &str) -> Result<(), RemoteError> {
+	let name = path.rsplit('/').next().unwrap_or_default();
+	if name.encode_utf16().count() > MAX_NAME_LENGTH {
+		return Err(RemoteError::backend(
+			"invalid_name",
+			format!("{} is longer than {} characters", name, MAX_NAME_LENGTH),
+		));
+	}
+	Ok(())
+}
+
+// 目录路径与条目名称拼接为条目的路径
+pub fn child_path(dir: &str, name: &str) -> String {
+	if dir == "." {
+		name.to_string()
+	} else {
+		format!("{}/{}", dir, name)
+	}
+}
+
+struct Inode {
+	// 被删除或被重命名覆盖的条目没有路径，内核 forget 之前对它的请求返回不存在
+	path: Option<String>,
+	lookups: u64,
+}
+
+// FUSE 以 inode 号引用条目：第一次查找到路径时分配 inode 号，内核 forget 所有查找后释放。
+// 重命名时条目及其下各条目的路径一起更新，已分配的 inode 号不变
+pub struct Inodes {
+	inodes: HashMap<u64, Inode>,
+	by_path: HashMap<String, u64>,
+	next: u64,
+}
+
+impl Inodes {
+	pub fn new() -> Self {
+		Self {
+			inodes: HashMap::from([(
+				ROOT_INODE,
+				Inode {
+					path: Some(".".to_string()),
+					lookups: 1,
+				},
+			)]),
+			by_path: HashMap::from([(".".to_string(), ROOT_INODE)]),
+			next: ROOT_INODE + 1,
+		}
+	}
+
+	pub fn path(&self, inode: u64) -> Option<&str> {
+		self.inodes.get(&inode)?.path.as_deref()
+	}
+
+	// 已分配的 inode 号，不计入查找
+	pub fn find(&self, path: &str) -> Option<u64> {
+		self.by_path.get(path).copied()
+	}
+
+	// 查找到 path：返回它的 inode 号，没有时分配一个，并增加查找计数
+	pub fn lookup(&mut self, path: &str) -> u64 {
+		let inode = match self.by_path.get(path) {
+			Some(&inode) => inode,
+			None => {
+				let inode = self.next;
+				self.next += 1;
+				self.inodes.insert(
+					inode,
+					Inode {
+						path: Some(path.to_string()),
+						lookups: 0,
+					},
+				);
+				self.by_path.insert(path.to_string(), inode);
+				inode
+			}
+		};
+		self.inodes.get_mut(&inode).unwrap().lookups += 1;
+		inode
+	}
+
+	// 内核不再引用 count 次查找得到的 inode 号；根目录始终保留
+	pub fn forget(&mut self, inode: u64, count: u64) {
+		if inode == ROOT_INODE {
+			return;
+		}
+		let Some(entry) = self.inodes.get_mut(&inode) else {
+			return;
+		};
+		entry.lookups = entry.lookups.saturating_sub(count);
+		if entry.lookups == 0 {
+			if let Some(path) = self.inodes.remove(&inode).and_then(|entry| entry.path) {
+				self.by_path.remove(&path);
+			}
+		}
+	}
+
+	// 条目及其下的各条目被删除
+	pub fn remove(&mut self, path: &str) {
+		for inode in self.descendants(path) {
+			let path = self.inodes.get_mut(&inode).unwrap().path.take().unwrap();
+			self.by_path.remove(&path);
+		}
+	}
+
+	// 条目及其下的各条目移到新路径，新路径上原有的条目被覆盖
+	pub fn rename(&mut self, old_path: &str, new_path: &str) {
+		self.remove(new_path);
+		for inode in self.descendants(old_path) {
+			let entry = self.inodes.get_mut(&inode).unwrap();
+			let path = entry.path.take().unwrap();
+			self.by_path.remove(&path);
+			let path = format!("{}{}", new_path, &path[old_path.len()..]);
+			self.by_path.insert(path.clone(), inode);
+			entry.path = Some(path);
+		}
+	}
+
+	fn descendants(&self, path: &str) -> Vec<u64> {
+		let prefix = format!("{}/", path);
+		self.by_path
+			.iter()
+			.filter(|(key, _)| *key == path || key.starts_with(&prefix))
+			.map(|(_, &inode)| inode)
+			.collect()
+	}
+}
@@ -0,0 +1,404 @@
+use std::{
+	ffi::c_void,
+	io,
+	sync::atomic::{AtomicBool, Ordering},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use tracing::error;
+use windows::Win32::Storage::FileSystem::{FILE_ACCESS_RIGHTS, FILE_FLAGS_AND_ATTRIBUTES};
+use winfsp::{
+	filesystem::{
+		DirBuffer, DirBufferLock, DirInfo, DirMarker, FileInfo, FileSecurity, FileSystemContext,
+		OpenFileInfo, VolumeInfo, WideNameInfo as _,
+	},
+	host::{FileSystemHost, VolumeParams},
+	FspError, U16CStr,
+};
+
+use crate::{
+	error::RemoteError,
+	vfs::{share_path, VirtualFs, MAX_NAME_LENGTH},
+	HttpFsHandler, RemoteFileInfo, TimesUpdate,
+};
+
+// CreateOptions 中的 FILE_DIRECTORY_FILE
+const FILE_DIRECTORY_FILE: u32 = 0x0000_0001;
+// Cleanup 的标志：关闭时删除，以及需要更新的时间
+const CLEANUP_DELETE: u32 = 0x01;
+const CLEANUP_SET_LAST_ACCESS_TIME: u32 = 0x20;
+const CLEANUP_SET_LAST_WRITE_TIME: u32 = 0x40;
+// FILETIME 的起点 1601-01-01 到 UNIX 纪元的秒数
+const FILETIME_EPOCH_OFFSET: u64 = 11_644_473_600;
+const FILETIME_TICKS: u64 = 10_000_000;
+// 内核缓存文件信息的毫秒数
+const FILE_INFO_TIMEOUT: u32 = 1000;
+
+// 打开的文件或目录
+pub struct Handle {
+	path: String,
+	is_directory: bool,
+	// 目录的列表在第一次读取时取得，之后按 WinFsp 给出的标记继续
+	entries: DirBuffer,
+	// 关闭时已修改的文件更新修改时间
+	modified: AtomicBool,
+}
+
+// 把 WinFsp 的回调映射到 VirtualFs 的操作，与 FUSE 适配器一样写入直接发送到存储，不暂存整个文件；
+// 不提供 .snapshots 伪目录、备用数据流和变更通知
+struct WinFspAdapter<F> {
+	fs: F,
+}
+
+// 记录错误并换算为 NTSTATUS；条目不存在是查找的正常结果，不记录
+fn status(path: &str, e: &RemoteError) -> FspError {
+	if e.code() != Some("not_found") {
+		error!(path = %path, error = %e, "winfsp operation failed");
+	}
+	FspError::NTSTATUS(e.to_ntstatus())
+}
+
+fn filetime(secs: u64) -> u64 {
+	(secs + FILETIME_EPOCH_OFFSET) * FILETIME_TICKS
+}
+
+// 0 表示不修改
+fn seconds(filetime: u64) -> Option<u64> {
+	(filetime != 0).then(|| (filetime / FILETIME_TICKS).saturating_sub(FILETIME_EPOCH_OFFSET))
+}
+
+fn now() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0, |elapsed| elapsed.as_secs())
+}
+
+fn fill(file_info: &mut FileInfo, info: &RemoteFileInfo) {
+	file_info.file_attributes = HttpFsHandler::file_attributes(info);
+	file_info.file_size = info.size;
+	file_info.allocation_size = info.allocated_size.unwrap_or(info.size);
+	file_info.creation_time = filetime(info.created);
+	file_info.last_access_time = filetime(info.accessed);
+	file_info.last_write_time = filetime(info.modified);
+	file_info.change_time = filetime(info.modified);
+	file_info.index_number = 0;
+	file_info.hard_links = 0;
+}
+
+impl<F: VirtualFs> WinFspAdapter<F> {
+	fn stat(&self, path: &str) -> winfsp::Result<RemoteFileInfo> {
+		self.fs.stat(path).map_err(|e| status(path, &e))
+	}
+
+	fn open_handle(&self, path: String, file_info: &mut OpenFileInfo) -> winfsp::Result<Handle> {
+		let info = self.stat(&path)?;
+		fill(file_info.as_mut(), &info);
+		Ok(Handle {
+			path,
+			is_directory: info.is_directory,
+			entries: DirBuffer::new(),
+			modified: AtomicBool::new(false),
+		})
+	}
+
+	fn refresh(&self, context: &Handle, file_info: &mut FileInfo) -> winfsp::Result<()> {
+		fill(file_info, &self.stat(&context.path)?);
+		Ok(())
+	}
+
+	fn write_entry(
+		entries: &DirBufferLock<'_>,
+		name: &str,
+		info: &RemoteFileInfo,
+	) -> winfsp::Result<()> {
+		let mut entry: DirInfo<MAX_NAME_LENGTH> = DirInfo::new();
+		fill(entry.file_info_mut(), info);
+		entry.set_name(name)?;
+		entries.write(&mut entry)
+	}
+}
+
+impl<F: VirtualFs> FileSystemContext for WinFspAdapter<F> {
+	type FileContext = Handle;
+
+	// 不保存安全描述符，访问由卷的只读属性控制
+	fn get_security_by_name(
+		&self,
+		file_name: &U16CStr,
+		_security_descriptor: Option<&mut [c_void]>,
+		_reparse_point_resolver: impl FnOnce(&U16CStr) -> Option<FileSecurity>,
+	) -> winfsp::Result<FileSecurity> {
+		let path = share_path(&file_name.to_string_lossy());
+		let info = self.stat(&path)?;
+		Ok(FileSecurity {
+			reparse: false,
+			sz_security_descriptor: 0,
+			attributes: HttpFsHandler::file_attributes(&info),
+		})
+	}
+
+	fn open(
+		&self,
+		file_name: &U16CStr,
+		_create_options: u32,
+		_granted_access: FILE_ACCESS_RIGHTS,
+		file_info: &mut OpenFileInfo,
+	) -> winfsp::Result<Handle> {
+		self.open_handle(share_path(&file_name.to_string_lossy()), file_info)
+	}
+
+	fn close(&self, _context: Handle) {}
+
+	fn create(
+		&self,
+		file_name: &U16CStr,
+		create_options: u32,
+		_granted_access: FILE_ACCESS_RIGHTS,
+		_file_attributes: FILE_FLAGS_AND_ATTRIBUTES,
+		_security_descriptor: Option<&[c_void]>,
+		_allocation_size: u64,
+		_extra_buffer: Option<&[u8]>,
+		_extra_buffer_is_reparse_point: bool,
+		file_info: &mut OpenFileInfo,
+	) -> winfsp::Result<Handle> {
+		let path = share_path(&file_name.to_string_lossy());
+		self.fs
+			.create(&path, create_options & FILE_DIRECTORY_FILE != 0)
+			.map_err(|e| status(&path, &e))?;
+		self.open_handle(path, file_info)
+	}
+
+	// 删除在最后一个句柄关闭时进行；被修改的文件按需要更新时间
+	fn cleanup(&self, context: &Handle, _file_name: Option<&U16CStr>, flags: u32) {
+		if flags & CLEANUP_DELETE != 0 {
+			if let Err(e) = self.fs.remove(&context.path) {
+				error!(path = %context.path, error = %e, "winfsp delete on close failed");
+			}
+			return;
+		}
+		if context.modified.load(Ordering::Relaxed)
+			&& flags & (CLEANUP_SET_LAST_ACCESS_TIME | CLEANUP_SET_LAST_WRITE_TIME) != 0
+		{
+			let now = now();
+			let times = TimesUpdate {
+				created: None,
+				accessed: (flags & CLEANUP_SET_LAST_ACCESS_TIME != 0).then_some(now),
+				modified: (flags & CLEANUP_SET_LAST_WRITE_TIME != 0).then_some(now),
+			};
+			if let Err(e) = self.fs.set_times(&context.path, &times) {
+				error!(path = %context.path, error = %e, "winfsp set times on close failed");
+			}
+		}
+	}
+
+	// 非空目录不能删除
+	fn set_delete(
+		&self,
+		context: &Handle,
+		_file_name: &U16CStr,
+		delete_file: bool,
+	) -> winfsp::Result<()> {
+		if delete_file
+			&& context.is_directory
+			&& !self
+				.fs
+				.list(&context.path)
+				.map_err(|e| status(&context.path, &e))?
+				.is_empty()
+		{
+			return Err(FspError::NTSTATUS(
+				winapi::shared::ntstatus::STATUS_DIRECTORY_NOT_EMPTY,
+			));
+		}
+		Ok(())
+	}
+
+	fn get_file_info(&self, context: &Handle, file_info: &mut FileInfo) -> winfsp::Result<()> {
+		self.refresh(context, file_info)
+	}
+
+	fn get_volume_info(&self, out_volume_info: &mut VolumeInfo) -> winfsp::Result<()> {
+		let space = self.fs.space();
+		out_volume_info.total_size = space.total;
+		out_volume_info.free_size = space.available;
+		out_volume_info.set_volume_label("HttpFS");
+		Ok(())
+	}
+
+	fn read(&self, context: &Handle, buffer: &mut [u8], offset: u64) -> winfsp::Result<u32> {
+		let len = self
+			.fs
+			.read_into(&context.path, offset, buffer)
+			.map_err(|e| status(&context.path, &e))?;
+		if len == 0 && !buffer.is_empty() {
+			return Err(FspError::NTSTATUS(
+				winapi::shared::ntstatus::STATUS_END_OF_FILE,
+			));
+		}
+		Ok(len as u32)
+	}
+
+	// constrained_io（分页写入）不扩展文件
+	fn write(
+		&self,
+		context: &Handle,
+		buffer: &[u8],
+		offset: u64,
+		write_to_eof: bool,
+		constrained_io: bool,
+		file_info: &mut FileInfo,
+	) -> winfsp::Result<u32> {
+		let size = self.stat(&context.path)?.size;
+		let offset = if write_to_eof { size } else { offset };
+		let length = match constrained_io {
+			true if offset >= size => 0,
+			true => buffer.len().min((size - offset) as usize),
+			false => buffer.len(),
+		};
+		if length > 0 {
+			self.fs
+				.write(&context.path, offset, &buffer[..length])
+				.map_err(|e| status(&context.path, &e))?;
+			context.modified.store(true, Ordering::Relaxed);
+		}
+		self.refresh(context, file_info)?;
+		Ok(length as u32)
+	}
+
+	fn overwrite(
+		&self,
+		context: &Handle,
+		_file_attributes: FILE_FLAGS_AND_ATTRIBUTES,
+		_replace_file_attributes: bool,
+		_allocation_size: u64,
+		_extra_buffer: Option<&[u8]>,
+		file_info: &mut FileInfo,
+	) -> winfsp::Result<()> {
+		self.fs
+			.truncate(&context.path, 0)
+			.map_err(|e| status(&context.path, &e))?;
+		context.modified.store(true, Ordering::Relaxed);
+		self.refresh(context, file_info)
+	}
+
+	// 只改变分配大小时不修改文件
+	fn set_file_size(
+		&self,
+		context: &Handle,
+		new_size: u64,
+		set_allocation_size: bool,
+		file_info: &mut FileInfo,
+	) -> winfsp::Result<()> {
+		if !set_allocation_size {
+			self.fs
+				.truncate(&context.path, new_size)
+				.map_err(|e| status(&context.path, &e))?;
+			context.modified.store(true, Ordering::Relaxed);
+		}
+		self.refresh(context, file_info)
+	}
+
+	// 文件属性不保存，只设置时间
+	fn set_basic_info(
+		&self,
+		context: &Handle,
+		_file_attributes: u32,
+		creation_time: u64,
+		last_access_time: u64,
+		last_write_time: u64,
+		_last_change_time: u64,
+		file_info: &mut FileInfo,
+	) -> winfsp::Result<()> {
+		let times = TimesUpdate {
+			created: seconds(creation_time),
+			accessed: seconds(last_access_time),
+			modified: seconds(last_write_time),
+		};
+		if !times.is_empty() {
+			self.fs
+				.set_times(&context.path, &times)
+				.map_err(|e| status(&context.path, &e))?;
+		}
+		self.refresh(context, file_info)
+	}
+
+	fn rename(
+		&self,
+		context: &Handle,
+		_file_name: &U16CStr,
+		new_file_name: &U16CStr,
+		replace_if_exists: bool,
+	) -> winfsp::Result<()> {
+		let new_path = share_path(&new_file_name.to_string_lossy());
+		self.fs
+			.rename(&context.path, &new_path, replace_if_exists)
+			.map_err(|e| status(&context.path, &e))
+	}
+
+	// 第一次读取（没有标记）时取得整个目录，非根目录加上 . 和 ..
+	fn read_directory(
+		&self,
+		context: &Handle,
+		_pattern: Option<&U16CStr>,
+		marker: DirMarker,
+		buffer: &mut [u8],
+	) -> winfsp::Result<u32> {
+		if let Ok(entries) = context.entries.acquire(marker.is_none(), None) {
+			let items = self
+				.fs
+				.list(&context.path)
+				.map_err(|e| status(&context.path, &e))?;
+			if context.path != "." {
+				let info = self.stat(&context.path)?;
+				Self::write_entry(&entries, ".", &info)?;
+				Self::write_entry(&entries, "..", &info)?;
+			}
+			for item in &items {
+				Self::write_entry(&entries, &item.name, item)?;
+			}
+		}
+		Ok(context.entries.read(marker, buffer))
+	}
+
+	fn flush(&self, context: Option<&Handle>, file_info: &mut FileInfo) -> winfsp::Result<()> {
+		match context {
+			Some(context) => self.refresh(context, file_info),
+			None => Ok(()),
+		}
+	}
+}
+
+// 挂载中的卷，drop 时卸载
+pub struct Mounted {
+	host: FileSystemHost<'static>,
+}
+
+impl Drop for Mounted {
+	fn drop(&mut self) {
+		self.host.stop();
+		self.host.unmount();
+	}
+}
+
+// 在 mount_point（盘符或不存在的目录）挂载，文件系统由 WinFsp 的线程服务，直到返回值被 drop。
+// 只读的存储以只读卷挂载
+pub fn mount(fs: impl VirtualFs + 'static, mount_point: &str) -> io::Result<Mounted> {
+	winfsp::winfsp_init()
+		.map_err(|e| io::Error::other(format!("WinFsp is not installed: {:?}", e)))?;
+	let mut params = VolumeParams::new();
+	params
+		.filesystem_name("httpfs")
+		.sector_size(512)
+		.sectors_per_allocation_unit(8)
+		.volume_creation_time(filetime(now()))
+		.file_info_timeout(FILE_INFO_TIMEOUT)
+		.case_sensitive_search(false)
+		.case_preserved_names(true)
+		.unicode_on_disk(true)
+		.read_only_volume(fs.read_only());
+	let host_error = |e: FspError| io::Error::other(format!("{:?}", e));
+	let mut host = FileSystemHost::new(params, WinFspAdapter { fs }).map_err(host_error)?;
+	host.mount(mount_point).map_err(host_error)?;
+	host.start().map_err(host_error)?;
+	Ok(Mounted { host })
+}
@@ -0,0 +1,10 @@
+fn main() {
+	// gRPC 接口由 proto/httpfs.proto 生成，客户端（crv-virtual-disk 的 gRPC 后端）使用同一份定义；
+	// 使用自带的 protoc，不需要另外安装
+	println!("cargo:rerun-if-changed=proto/httpfs.proto");
+	std::env::set_var("PROTOC", protoc_bin_vendored::protoc_bin_path().unwrap());
+	tonic_build::configure()
+		.build_client(false)
+		.compile_protos(&["proto/httpfs.proto"], &["proto"])
+		.unwrap();
+}
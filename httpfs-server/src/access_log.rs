@@ -0,0 +1,106 @@
+use std::{fs::OpenOptions, io, path::Path, sync::Mutex, time::Duration};
+
+use axum::{
+	body::{Body, HttpBody},
+	http::{header, HeaderMap, Request, Response},
+	Router,
+};
+use tower_http::{
+	request_id::{MakeRequestUuid, PropagateRequestIdLayer, SetRequestIdLayer},
+	trace::TraceLayer,
+};
+use tracing::{info_span, level_filters::LevelFilter, Span};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, Registry};
+
+// 用于在重新加载配置时调整日志级别
+pub type LogHandle = reload::Handle<LevelFilter, Registry>;
+
+// 初始化日志输出：指定文件时以 JSON 行追加写入，否则以文本格式输出到标准错误
+pub fn init(log_file: Option<&Path>, level: LevelFilter) -> io::Result<LogHandle> {
+	let (filter, handle) = reload::Layer::new(level);
+	let registry = tracing_subscriber::registry().with(filter);
+	match log_file {
+		Some(path) => {
+			let file = OpenOptions::new().create(true).append(true).open(path)?;
+			registry
+				.with(
+					fmt::layer()
+						.with_target(false)
+						.json()
+						.with_current_span(true)
+						.with_span_list(false)
+						.with_writer(Mutex::new(file)),
+				)
+				.init();
+		}
+		None => registry
+			.with(fmt::layer().with_target(false).with_writer(io::stderr))
+			.init(),
+	}
+	Ok(handle)
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+	headers
+		.get(header::CONTENT_LENGTH)?
+		.to_str()
+		.ok()?
+		.parse()
+		.ok()
+}
+
+// 请求 ID 在最外层生成（客户端已带 X-Request-Id 时沿用），并回写到响应头中
+pub fn request_ids<S>(router: Router<S>) -> Router<S>
+where
+	S: Clone + Send + Sync + 'static,
+{
+	router
+		.layer(PropagateRequestIdLayer::x_request_id())
+		.layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+// 每个请求记录一条访问日志：请求 ID、方法、路径、状态码、耗时以及请求和响应的字节数。
+// 放在压缩层之内，记录的是未压缩的响应大小
+pub fn trace<S>(router: Router<S>) -> Router<S>
+where
+	S: Clone + Send + Sync + 'static,
+{
+	router.layer(
+		TraceLayer::new_for_http()
+			.make_span_with(|request: &Request<Body>| {
+				let request_id = request
+					.headers()
+					.get("x-request-id")
+					.and_then(|value| value.to_str().ok())
+					.unwrap_or_default();
+				info_span!(
+					"request",
+					request_id,
+					method = %request.method(),
+					uri = %request.uri(),
+					request_bytes = content_length(request.headers()),
+				)
+			})
+			.on_request(())
+			.on_response(|response: &Response<Body>, latency: Duration, _: &Span| {
+				let status = response.status();
+				let outcome = if status.is_server_error() {
+					"server_error"
+				} else if status.is_client_error() {
+					"client_error"
+				} else {
+					"ok"
+				};
+				let response_bytes = content_length(response.headers())
+					.or_else(|| response.body().size_hint().exact());
+				tracing::info!(
+					status = status.as_u16(),
+					latency_ms = latency.as_secs_f64() * 1000.0,
+					response_bytes,
+					outcome,
+					"request finished"
+				);
+			})
+			.on_failure(()),
+	)
+}
@@ -0,0 +1,52 @@
+use std::{
+	fs::{self, File},
+	io::{self, Write},
+	path::{Path, PathBuf},
+	sync::atomic::{AtomicU64, Ordering},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+// 原子写入使用的临时文件后缀，列目录时会隐藏这些文件
+const TEMP_SUFFIX: &str = ".httpfs-tmp";
+
+pub fn is_temp_name(name: &str) -> bool {
+	name.starts_with('.') && name.ends_with(TEMP_SUFFIX)
+}
+
+// 在目标文件所在目录生成一个唯一的临时文件名，保证 rename 不跨文件系统
+pub fn temp_path_for(path: &Path) -> PathBuf {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_nanos())
+		.unwrap_or(0);
+	let file_name = path
+		.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	path.with_file_name(format!(
+		".{}.{}-{}-{}{}",
+		file_name,
+		std::process::id(),
+		nanos,
+		COUNTER.fetch_add(1, Ordering::Relaxed),
+		TEMP_SUFFIX
+	))
+}
+
+// 先把完整内容写入同目录下的临时文件并落盘，再 rename 覆盖目标文件；
+// 任意一步失败都不会破坏原文件
+pub fn write_atomic(path: &Path, data: &[u8]) -> io::Result<()> {
+	let temp_path = temp_path_for(path);
+	let result = (|| {
+		let mut file = File::create(&temp_path)?;
+		file.write_all(data)?;
+		file.sync_all()?;
+		drop(file);
+		fs::rename(&temp_path, path)
+	})();
+	if result.is_err() {
+		let _ = fs::remove_file(&temp_path);
+	}
+	result
+}
@@ -0,0 +1,306 @@
+use std::{
+	fs::{self, File, OpenOptions},
+	io::{self, Write},
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	async_trait,
+	extract::{ConnectInfo, FromRequestParts, Query, State},
+	http::{request::Parts, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+	error::ApiError,
+	users::{User, USER_HEADER},
+	ServerState, ShareAccess,
+};
+
+// 第一条记录之前的哈希
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+// 一次查询最多返回的记录数
+const MAX_QUERY_LIMIT: usize = 10_000;
+const DEFAULT_QUERY_LIMIT: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+	Create,
+	Mkdir,
+	Write,
+	Truncate,
+	Delete,
+	Rename,
+	Restore,
+	SetTimes,
+	SetXattr,
+	RemoveXattr,
+}
+
+// 审计日志中的一条记录（一行 JSON）。hash 是把 hash 置为空字符串后整条记录的 JSON 的 SHA-256，
+// 记录中包含上一条的 hash，修改或删除中间的任何一条都会使之后的校验失败
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AuditEntry {
+	seq: u64,
+	time: u64,
+	share: String,
+	// X-Httpfs-User 给出的用户，没有时为持有令牌的客户端本身
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	user: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	client: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	request_id: Option<String>,
+	action: Action,
+	path: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	new_path: Option<String>,
+	prev: String,
+	hash: String,
+}
+
+impl AuditEntry {
+	fn digest(&self) -> String {
+		let unsigned = AuditEntry {
+			hash: String::new(),
+			..self.clone()
+		};
+		hex::encode(Sha256::digest(serde_json::to_vec(&unsigned).unwrap()))
+	}
+
+	// 路径或重命名的目标在 dir 之下
+	fn touches(&self, dir: &str) -> bool {
+		let dir = dir.trim_matches('/');
+		let within = |path: &str| {
+			dir.is_empty()
+				|| path == dir
+				|| path
+					.strip_prefix(dir)
+					.is_some_and(|rest| rest.starts_with('/'))
+		};
+		within(&self.path) || self.new_path.as_deref().is_some_and(within)
+	}
+}
+
+// 修改共享的请求来自谁：共享、代表的用户、客户端地址和请求 ID
+pub struct Actor {
+	share: String,
+	user: Option<String>,
+	client: Option<String>,
+	request_id: Option<String>,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for Actor {
+	type Rejection = Response;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &Arc<ServerState>,
+	) -> Result<Self, Self::Rejection> {
+		let ShareAccess(share) = ShareAccess::from_request_parts(parts, state).await?;
+		let header = |name: &str| {
+			parts
+				.headers
+				.get(name)
+				.and_then(|value| value.to_str().ok())
+				.map(str::to_string)
+		};
+		Ok(Actor {
+			share: share.name.clone(),
+			user: header(USER_HEADER),
+			// 测试中直接调用路由时没有连接信息
+			client: parts
+				.extensions
+				.get::<ConnectInfo<SocketAddr>>()
+				.map(|info| info.0.ip().to_string()),
+			request_id: header("x-request-id"),
+		})
+	}
+}
+
+struct Chain {
+	path: PathBuf,
+	file: File,
+	seq: u64,
+	last: String,
+}
+
+// 只追加的审计日志，未配置 log.audit_log 时不记录
+#[derive(Default)]
+pub struct AuditLog {
+	chain: Option<Mutex<Chain>>,
+}
+
+// 校验整个文件：返回所有记录、第一条校验失败的行号（从 1 开始）以及最后一条的序号和哈希
+fn verify(text: &str) -> (Vec<AuditEntry>, Option<u64>, u64, String) {
+	let mut entries = Vec::new();
+	let mut broken = None;
+	let (mut seq, mut last) = (0, GENESIS.to_string());
+	for (index, line) in text.lines().enumerate() {
+		let line_number = index as u64 + 1;
+		let Ok(entry) = serde_json::from_str::<AuditEntry>(line) else {
+			broken.get_or_insert(line_number);
+			continue;
+		};
+		if entry.seq != seq + 1 || entry.prev != last || entry.digest() != entry.hash {
+			broken.get_or_insert(line_number);
+		}
+		// 之后的记录接在这一条后面，断裂只报告一次
+		seq = entry.seq;
+		last = entry.hash.clone();
+		entries.push(entry);
+	}
+	(entries, broken, seq, last)
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+impl AuditLog {
+	// 打开（或创建）日志文件，接着已有的最后一条记录写入；已有的记录校验失败时只报告，仍然启动
+	pub fn open(path: &Path) -> io::Result<Self> {
+		let text = match fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(e) => return Err(e),
+		};
+		let (_, broken, seq, last) = verify(&text);
+		if let Some(line) = broken {
+			eprintln!(
+				"[SERVER] audit log {} fails verification at line {}",
+				path.display(),
+				line
+			);
+		}
+		let file = OpenOptions::new().create(true).append(true).open(path)?;
+		Ok(Self {
+			chain: Some(Mutex::new(Chain {
+				path: path.to_path_buf(),
+				file,
+				seq,
+				last,
+			})),
+		})
+	}
+
+	// 修改成功后记录；写入失败不影响已经完成的修改
+	pub fn record(&self, actor: &Actor, action: Action, path: &str, new_path: Option<&str>) {
+		let Some(chain) = &self.chain else {
+			return;
+		};
+		let mut chain = chain.lock().unwrap();
+		let mut entry = AuditEntry {
+			seq: chain.seq + 1,
+			time: now_secs(),
+			share: actor.share.clone(),
+			user: actor.user.clone(),
+			client: actor.client.clone(),
+			request_id: actor.request_id.clone(),
+			action,
+			path: path.trim_matches('/').to_string(),
+			new_path: new_path.map(|path| path.trim_matches('/').to_string()),
+			prev: chain.last.clone(),
+			hash: String::new(),
+		};
+		entry.hash = entry.digest();
+		let mut line = serde_json::to_vec(&entry).unwrap();
+		line.push(b'\n');
+		// 整行一次写入并落盘，崩溃时最多留下不完整的最后一行
+		match chain
+			.file
+			.write_all(&line)
+			.and_then(|_| chain.file.sync_data())
+		{
+			Ok(()) => {
+				chain.seq = entry.seq;
+				chain.last = entry.hash;
+			}
+			Err(e) => eprintln!("[SERVER] writing the audit log failed: {:?}", e),
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+	// 只返回路径（或重命名的目标）在这个目录或文件之下的记录
+	path: Option<String>,
+	user: Option<String>,
+	// 只返回这个时间（Unix 秒）及之后的记录
+	since: Option<u64>,
+	// 最多返回最近的多少条，默认 1000
+	limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AuditResponse {
+	entries: Vec<AuditEntry>,
+	// 整个日志（包括其他共享的记录）的哈希链是否完整
+	verified: bool,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	broken_at: Option<u64>,
+	// 最后一条记录的哈希，保存下来可以在以后发现末尾的记录被删除
+	head: String,
+}
+
+// GET /audit - 按共享查询审计日志，同时校验整个哈希链。只有令牌本身可以查询，代表用户的请求被拒绝
+pub async fn query_audit(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	Query(query): Query<AuditQuery>,
+) -> Response {
+	if let Err(error) = user.check_unrestricted() {
+		return error.into_response();
+	}
+	let Some(chain) = &state.audit.chain else {
+		return ApiError::new(
+			StatusCode::NOT_FOUND,
+			"audit_disabled",
+			"set log.audit_log in the server config to record an audit log",
+		)
+		.into_response();
+	};
+	// 持有锁读取，不会读到写了一半的记录
+	let text = {
+		let chain = chain.lock().unwrap();
+		fs::read_to_string(&chain.path)
+	};
+	let text = match text {
+		Ok(text) => text,
+		Err(e) => return ApiError::io("reading the audit log failed", &e).into_response(),
+	};
+	let (entries, broken_at, _, head) = verify(&text);
+	let mut entries: Vec<AuditEntry> = entries
+		.into_iter()
+		.filter(|entry| entry.share == share.name)
+		.filter(|entry| query.path.as_deref().is_none_or(|path| entry.touches(path)))
+		.filter(|entry| query.user.is_none() || entry.user == query.user)
+		.filter(|entry| query.since.is_none_or(|since| entry.time >= since))
+		.collect();
+	let limit = query
+		.limit
+		.unwrap_or(DEFAULT_QUERY_LIMIT)
+		.min(MAX_QUERY_LIMIT);
+	let skip = entries.len().saturating_sub(limit);
+	entries.drain(..skip);
+	Json(AuditResponse {
+		entries,
+		verified: broken_at.is_none(),
+		broken_at,
+		head,
+	})
+	.into_response()
+}
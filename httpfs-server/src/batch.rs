@@ -0,0 +1,139 @@
+use std::{fs, sync::Arc};
+
+use axum::{
+	body::Bytes,
+	extract::{Multipart, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	atomic::write_atomic,
+	audit::{Action, Actor},
+	error::ApiError,
+	invalid_path, keep_created, quota, retention, save_version, scan,
+	share::Share,
+	times,
+	users::User,
+	ServerState, ShareAccess,
+};
+
+// 单个 /batch 请求最多包含的文件数
+const MAX_BATCH_FILES: usize = 1000;
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+	files: Vec<BatchEntry>,
+}
+
+// 清单中的一个文件：提交后设置给出的时间戳
+#[derive(Debug, Deserialize)]
+struct BatchEntry {
+	path: String,
+	#[serde(flatten)]
+	times: times::TimesRequest,
+}
+
+// 批量提交中一个文件的结果，失败时带有单独请求 /write 时的状态码和相同的错误体
+#[derive(Debug, Serialize)]
+pub struct BatchResult {
+	path: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	status: Option<u16>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<ApiError>,
+}
+
+fn invalid_batch(message: impl Into<String>) -> Response {
+	ApiError::new(StatusCode::BAD_REQUEST, "invalid_batch", message).into_response()
+}
+
+// 与 /write?atomic=true 相同：替换前扫描内容、保存历史版本并保留创建时间，不存在的文件被创建
+async fn commit_entry(
+	state: &ServerState,
+	share: &Share,
+	user: &User,
+	actor: &Actor,
+	entry: &BatchEntry,
+	data: &Bytes,
+) -> Result<(), ApiError> {
+	user.check_write(&entry.path)?;
+	let real_path = share
+		.get_real_path(&entry.path)
+		.map_err(|status| invalid_path(status, &entry.path))?;
+	let _guard = state.locks.lock(&real_path).await;
+	if real_path.is_dir() {
+		return Err(ApiError::new(
+			StatusCode::CONFLICT,
+			"is_a_directory",
+			format!("'{}' is a directory", entry.path),
+		));
+	}
+	retention::check(share, &entry.path, &real_path)?;
+	let old_size = fs::metadata(&real_path).map_or(0, |m| m.len());
+	quota::check(share, old_size, data.len() as u64)?;
+	scan::check_content(state, share, &entry.path, data)?;
+	save_version(state, &real_path, &share.root_path);
+	let created = times::created_before_replace(&real_path, &share.root_path);
+	write_atomic(&real_path, data).map_err(|e| {
+		eprintln!("[SERVER] batch: atomic write failed: {:?}", e);
+		ApiError::io("atomic write failed", &e)
+	})?;
+	keep_created(&real_path, &share.root_path, created);
+	state.metrics.add_written(data.len());
+	state.audit.record(actor, Action::Write, &entry.path, None);
+	if !entry.times.is_empty() {
+		entry.times.apply(&real_path, &share.root_path)?;
+	}
+	retention::commit(share, &real_path);
+	Ok(())
+}
+
+// POST /batch - 在一个 multipart 请求中提交多个小文件：第一部分 manifest 为 JSON 清单
+// {"files": [{path, created, accessed, modified}]}，之后按清单顺序每个文件一个 file 部分，内容为完整的文件数据。
+// 每个文件各自原子替换，结果按清单顺序返回，一个文件失败不影响其他文件
+pub async fn commit_batch(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	actor: Actor,
+	mut multipart: Multipart,
+) -> Response {
+	let manifest = match multipart.next_field().await {
+		Ok(Some(field)) if field.name() == Some("manifest") => match field.bytes().await {
+			Ok(bytes) => match serde_json::from_slice::<Manifest>(&bytes) {
+				Ok(manifest) => manifest,
+				Err(e) => return invalid_batch(format!("invalid manifest: {}", e)),
+			},
+			Err(e) => return invalid_batch(format!("reading manifest failed: {}", e)),
+		},
+		Ok(_) => return invalid_batch("the first part must be the manifest"),
+		Err(e) => return invalid_batch(format!("invalid multipart body: {}", e)),
+	};
+	if manifest.files.len() > MAX_BATCH_FILES {
+		return invalid_batch(format!("at most {} files per batch", MAX_BATCH_FILES));
+	}
+
+	let mut results = Vec::with_capacity(manifest.files.len());
+	for entry in &manifest.files {
+		let data = match multipart.next_field().await {
+			Ok(Some(field)) if field.name() == Some("file") => match field.bytes().await {
+				Ok(data) => data,
+				Err(e) => return invalid_batch(format!("reading '{}' failed: {}", entry.path, e)),
+			},
+			Ok(_) => {
+				return invalid_batch(format!("missing file part for '{}'", entry.path))
+			}
+			Err(e) => return invalid_batch(format!("invalid multipart body: {}", e)),
+		};
+		let error = commit_entry(&state, &share, &user, &actor, entry, &data).await.err();
+		results.push(BatchResult {
+			path: entry.path.clone(),
+			status: error.as_ref().map(|e| e.status().as_u16()),
+			error,
+		});
+	}
+	Json(results).into_response()
+}
@@ -0,0 +1,79 @@
+use std::sync::Arc;
+
+use axum::{extract::State, Json};
+use serde::Serialize;
+
+use crate::{ServerState, ShareAccess, MAX_BODY_SIZE};
+
+// 协议版本：删除路由或改变已有字段含义的不兼容修改时增加，新增的路由和字段只在 features 中声明
+pub const PROTOCOL_VERSION: u32 = 1;
+// 仍然支持的最旧的客户端协议版本
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+// 每个共享都提供的功能
+const FEATURES: [&str; 17] = [
+	// /read 的 offset 和 length
+	"ranges",
+	// /list 的 limit 和 cursor
+	"paging",
+	"stat_batch",
+	"batch",
+	"xattrs",
+	"events",
+	"search",
+	"checksum",
+	// /signature 和 /upload/:session/copy
+	"delta",
+	"chunked_upload",
+	"chunks",
+	// /zero 打洞
+	"sparse",
+	"times",
+	"merge",
+	"audit",
+	"grpc",
+	// /session 和 /lease
+	"leases",
+];
+
+#[derive(Debug, Serialize)]
+pub struct Capabilities {
+	protocol_version: u32,
+	min_protocol_version: u32,
+	features: Vec<&'static str>,
+	// 单个 /write 请求体的上限（字节）
+	max_write_size: usize,
+}
+
+// GET /capabilities - 协议版本和共享支持的功能。客户端挂载时查询，不支持的功能不再使用，
+// 而不是在文件操作中途失败；没有该路由的旧版服务器只提供最基本的操作
+pub async fn get_capabilities(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+) -> Json<Capabilities> {
+	let settings = state.settings();
+	let mut features = FEATURES.to_vec();
+	for (enabled, feature) in [
+		(settings.max_versions > 0, "versions"),
+		(settings.trash_retention.is_some(), "trash"),
+		(settings.advisory_locks, "locks"),
+		(settings.scan.is_some(), "scan"),
+		(share.quota.is_some(), "quota"),
+		(share.retention.is_some(), "worm"),
+		(share.direct_reads.is_some(), "direct_reads"),
+		(!share.users.is_empty(), "users"),
+	] {
+		if enabled {
+			features.push(feature);
+		}
+	}
+	Json(Capabilities {
+		protocol_version: PROTOCOL_VERSION,
+		min_protocol_version: MIN_PROTOCOL_VERSION,
+		features,
+		max_write_size: settings
+			.max_write_size
+			.unwrap_or(MAX_BODY_SIZE)
+			.min(MAX_BODY_SIZE),
+	})
+}
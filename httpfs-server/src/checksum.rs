@@ -0,0 +1,150 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File},
+	io::{self, Read},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::SystemTime,
+};
+
+use axum::{
+	extract::{Query, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256, Sha512};
+
+use crate::{error::ApiError, is_a_directory, ServerState, Target};
+
+// 缓存的条目数超过该值时整体清空
+const MAX_CACHED_DIGESTS: usize = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Algorithm {
+	Sha256,
+	Sha512,
+}
+
+fn digest_file<D: Digest>(path: &Path) -> io::Result<String> {
+	let mut file = File::open(path)?;
+	let mut hasher = D::new();
+	let mut buffer = vec![0u8; 1024 * 1024];
+	loop {
+		let n = file.read(&mut buffer)?;
+		if n == 0 {
+			break;
+		}
+		hasher.update(&buffer[..n]);
+	}
+	Ok(hex::encode(hasher.finalize()))
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+	digest_file::<Sha256>(path)
+}
+
+struct CachedDigest {
+	size: u64,
+	modified: SystemTime,
+	digest: String,
+}
+
+// 按路径和算法缓存文件摘要，文件大小或修改时间变化后缓存失效
+#[derive(Default)]
+pub struct ChecksumCache {
+	digests: Mutex<HashMap<(PathBuf, Algorithm), CachedDigest>>,
+	hits: AtomicU64,
+	misses: AtomicU64,
+}
+
+impl ChecksumCache {
+	// 命中缓存和需要重新计算的次数，用于 /metrics
+	pub fn stats(&self) -> (u64, u64) {
+		(
+			self.hits.load(Ordering::Relaxed),
+			self.misses.load(Ordering::Relaxed),
+		)
+	}
+
+	// 返回摘要以及计算时的文件大小
+	fn digest(&self, path: &Path, algo: Algorithm) -> io::Result<(String, u64)> {
+		let metadata = fs::metadata(path)?;
+		let modified = metadata.modified()?;
+		let key = (path.to_path_buf(), algo);
+		if let Some(cached) = self.digests.lock().unwrap().get(&key) {
+			if cached.size == metadata.len() && cached.modified == modified {
+				self.hits.fetch_add(1, Ordering::Relaxed);
+				return Ok((cached.digest.clone(), cached.size));
+			}
+		}
+		self.misses.fetch_add(1, Ordering::Relaxed);
+
+		let digest = match algo {
+			Algorithm::Sha256 => digest_file::<Sha256>(path)?,
+			Algorithm::Sha512 => digest_file::<Sha512>(path)?,
+		};
+		// 计算期间文件被修改时不缓存
+		let unchanged = fs::metadata(path)
+			.is_ok_and(|m| m.len() == metadata.len() && m.modified().ok() == Some(modified));
+		if unchanged {
+			let mut digests = self.digests.lock().unwrap();
+			if digests.len() >= MAX_CACHED_DIGESTS {
+				digests.clear();
+			}
+			digests.insert(
+				key,
+				CachedDigest {
+					size: metadata.len(),
+					modified,
+					digest: digest.clone(),
+				},
+			);
+		}
+		Ok((digest, metadata.len()))
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChecksumQuery {
+	algo: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChecksumResponse {
+	algo: Algorithm,
+	digest: String,
+	size: u64,
+}
+
+// GET /checksum/:path?algo=sha256 - 计算文件摘要（默认 sha256），结果按文件的大小和修改时间缓存
+pub async fn get_checksum(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	Query(query): Query<ChecksumQuery>,
+) -> Response {
+	let algo = match query.algo.as_deref().unwrap_or("sha256") {
+		"sha256" => Algorithm::Sha256,
+		"sha512" => Algorithm::Sha512,
+		other => {
+			return ApiError::new(
+				StatusCode::BAD_REQUEST,
+				"unsupported_algorithm",
+				format!("unsupported checksum algorithm '{}'", other),
+			)
+			.into_response()
+		}
+	};
+	if target.real_path.is_dir() {
+		return is_a_directory(&target.path);
+	}
+	match state.checksums.digest(&target.real_path, algo) {
+		Ok((digest, size)) => Json(ChecksumResponse { algo, digest, size }).into_response(),
+		Err(e) => ApiError::io("hashing failed", &e).into_response(),
+	}
+}
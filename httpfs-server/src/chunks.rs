@@ -0,0 +1,304 @@
+use std::{
+	collections::{HashMap, HashSet},
+	fs::{self, File},
+	io::{self, Read},
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
+
+use axum::{
+	body::Bytes,
+	extract::{Path as AxumPath, Query, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+	atomic::write_atomic, error::ApiError, quota, users::User, ServerState, ShareAccess,
+};
+
+// 去重存储的块按内容的 sha256 保存在共享根目录下：块 ab12… 位于 .httpfs-chunks/ab/ab12…。
+// 客户端的文件内容是引用这些块的清单，块只由 /chunks/gc 在没有任何清单引用时删除
+pub const CHUNKS_DIR: &str = ".httpfs-chunks";
+
+pub fn is_chunks_name(name: &str) -> bool {
+	name == CHUNKS_DIR
+}
+
+// 清单文件的开头，与客户端写入的格式一致
+const MANIFEST_PREFIX: &[u8] = b"{\"httpfs_manifest\":1,";
+
+// 单块的上限，客户端的分块不超过 256 KiB
+const MAX_CHUNK_SIZE: usize = 4 * 1024 * 1024;
+
+// 单次 /chunks/exists 查询最多包含的哈希数
+const MAX_EXISTS_BATCH: usize = 1000;
+
+// 未被引用的块至少保留这么久，避免删除客户端刚上传、清单尚未写入的块
+const DEFAULT_GC_GRACE: Duration = Duration::from_secs(60 * 60);
+
+fn is_chunk_hash(hash: &str) -> bool {
+	hash.len() == 64 && hash.bytes().all(|b| matches!(b, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+fn chunk_path(root: &Path, hash: &str) -> PathBuf {
+	root.join(CHUNKS_DIR).join(&hash[..2]).join(hash)
+}
+
+fn invalid_hash(hash: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::BAD_REQUEST,
+		"invalid_input",
+		format!("'{}' is not a lowercase hex sha256", hash),
+	)
+}
+
+// 已有的块被再次上传或查询时刷新修改时间，使其不会在宽限期内被回收
+fn touch(path: &Path) -> io::Result<()> {
+	File::options()
+		.write(true)
+		.open(path)?
+		.set_modified(SystemTime::now())
+}
+
+// PUT /chunks/:hash - 保存一个块，内容必须与哈希一致；块已存在时不重复写入
+pub async fn put_chunk(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	body: Bytes,
+) -> Response {
+	if let Err(error) = user.check_writer() {
+		return error.into_response();
+	}
+	let hash = &params["hash"];
+	if !is_chunk_hash(hash) {
+		return invalid_hash(hash).into_response();
+	}
+	if body.len() > MAX_CHUNK_SIZE {
+		return ApiError::new(
+			StatusCode::PAYLOAD_TOO_LARGE,
+			"file_too_large",
+			format!("chunks are limited to {} bytes", MAX_CHUNK_SIZE),
+		)
+		.into_response();
+	}
+	if hex::encode(Sha256::digest(&body)) != *hash {
+		return ApiError::new(
+			StatusCode::UNPROCESSABLE_ENTITY,
+			"checksum_mismatch",
+			"chunk sha256 does not match",
+		)
+		.into_response();
+	}
+	let path = chunk_path(&share.root_path, hash);
+	if touch(&path).is_ok() {
+		return StatusCode::OK.into_response();
+	}
+	if let Err(error) = quota::check(&share, 0, body.len() as u64) {
+		return error.into_response();
+	}
+	let result =
+		fs::create_dir_all(path.parent().unwrap()).and_then(|_| write_atomic(&path, &body));
+	match result {
+		Ok(_) => {
+			state.metrics.add_written(body.len());
+			StatusCode::CREATED.into_response()
+		}
+		Err(e) => {
+			eprintln!("[SERVER] put_chunk: write failed: {:?}", e);
+			ApiError::io("write failed", &e).into_response()
+		}
+	}
+}
+
+// GET /chunks/:hash - 读取一个块
+pub async fn get_chunk(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+) -> Response {
+	let hash = &params["hash"];
+	if !is_chunk_hash(hash) {
+		return invalid_hash(hash).into_response();
+	}
+	match fs::read(chunk_path(&share.root_path, hash)) {
+		Ok(data) => {
+			state.metrics.add_read(data.len());
+			data.into_response()
+		}
+		Err(e) if e.kind() == io::ErrorKind::NotFound => ApiError::new(
+			StatusCode::NOT_FOUND,
+			"chunk_not_found",
+			format!("chunk {} does not exist", hash),
+		)
+		.into_response(),
+		Err(e) => ApiError::io("read failed", &e).into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExistsRequest {
+	hashes: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExistsResponse {
+	missing: Vec<String>,
+}
+
+// POST /chunks/exists - 返回给出的哈希中服务器还没有的块，客户端只上传这些块
+pub async fn chunks_exist(
+	ShareAccess(share): ShareAccess,
+	Json(request): Json<ExistsRequest>,
+) -> Response {
+	if request.hashes.len() > MAX_EXISTS_BATCH {
+		return ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"invalid_input",
+			format!("at most {} hashes per request", MAX_EXISTS_BATCH),
+		)
+		.into_response();
+	}
+	if let Some(hash) = request.hashes.iter().find(|hash| !is_chunk_hash(hash)) {
+		return invalid_hash(hash).into_response();
+	}
+	let missing = request
+		.hashes
+		.into_iter()
+		.filter(|hash| touch(&chunk_path(&share.root_path, hash)).is_err())
+		.collect();
+	Json(ExistsResponse { missing }).into_response()
+}
+
+#[derive(Debug, Deserialize)]
+struct ManifestChunk {
+	hash: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Manifest {
+	chunks: Vec<ManifestChunk>,
+}
+
+// 收集目录下所有清单引用的块，包括回收站和历史版本中的清单；不跟随符号链接，跳过根目录下的块目录本身
+fn collect_references(
+	dir: &Path,
+	at_root: bool,
+	references: &mut HashSet<String>,
+) -> io::Result<()> {
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		if at_root && is_chunks_name(&entry.file_name().to_string_lossy()) {
+			continue;
+		}
+		let file_type = entry.file_type()?;
+		let path = entry.path();
+		if file_type.is_dir() {
+			collect_references(&path, false, references)?;
+			continue;
+		}
+		if !file_type.is_file() {
+			continue;
+		}
+		let mut head = [0u8; MANIFEST_PREFIX.len()];
+		let is_manifest = File::open(&path)
+			.and_then(|mut file| file.read_exact(&mut head))
+			.is_ok_and(|_| head == MANIFEST_PREFIX);
+		if !is_manifest {
+			continue;
+		}
+		// 无法解析的文件只是恰好以相同内容开头
+		match fs::read(&path).map(|data| serde_json::from_slice::<Manifest>(&data)) {
+			Ok(Ok(manifest)) => {
+				references.extend(manifest.chunks.into_iter().filter_map(|chunk| chunk.hash))
+			}
+			Ok(Err(e)) => eprintln!("[SERVER] chunk gc: ignoring {:?}: {}", path, e),
+			Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+			Err(e) => return Err(e),
+		}
+	}
+	Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct GcQuery {
+	// 未被引用的块的最短保留时间（秒）
+	grace: Option<u64>,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct GcResponse {
+	removed: u64,
+	freed: u64,
+	kept: u64,
+}
+
+// 删除没有被任何清单引用、且超过宽限期的块
+fn collect_garbage(root: &Path, grace: Duration) -> io::Result<GcResponse> {
+	let mut references = HashSet::new();
+	collect_references(root, true, &mut references)?;
+
+	let mut response = GcResponse::default();
+	let now = SystemTime::now();
+	let prefixes = match fs::read_dir(root.join(CHUNKS_DIR)) {
+		Ok(prefixes) => prefixes,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(response),
+		Err(e) => return Err(e),
+	};
+	for prefix in prefixes {
+		let prefix = prefix?;
+		if !prefix.file_type()?.is_dir() {
+			continue;
+		}
+		for chunk in fs::read_dir(prefix.path())? {
+			let chunk = chunk?;
+			let name = chunk.file_name().to_string_lossy().into_owned();
+			let metadata = chunk.metadata()?;
+			let expired = metadata
+				.modified()
+				.ok()
+				.and_then(|modified| now.duration_since(modified).ok())
+				.is_some_and(|age| age >= grace);
+			if !is_chunk_hash(&name) || references.contains(&name) || !expired {
+				response.kept += 1;
+				continue;
+			}
+			match fs::remove_file(chunk.path()) {
+				Ok(_) => {
+					response.removed += 1;
+					response.freed += metadata.len();
+				}
+				Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+				Err(e) => return Err(e),
+			}
+		}
+		// 清空的前缀目录一并删除，非空时失败无妨
+		let _ = fs::remove_dir(prefix.path());
+	}
+	Ok(response)
+}
+
+// POST /chunks/gc?grace=3600 - 回收未被引用的块，grace 为最短保留时间（秒），默认一小时
+pub async fn gc_chunks(
+	ShareAccess(share): ShareAccess,
+	user: User,
+	Query(query): Query<GcQuery>,
+) -> Response {
+	if let Err(error) = user.check_unrestricted() {
+		return error.into_response();
+	}
+	let grace = query.grace.map_or(DEFAULT_GC_GRACE, Duration::from_secs);
+	match collect_garbage(&share.root_path, grace) {
+		Ok(response) => Json(response).into_response(),
+		Err(e) => {
+			eprintln!("[SERVER] gc_chunks: failed: {:?}", e);
+			ApiError::io("collecting chunks failed", &e).into_response()
+		}
+	}
+}
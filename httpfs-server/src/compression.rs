@@ -0,0 +1,61 @@
+use std::{path::Path, sync::Arc};
+
+use axum::{body::HttpBody, extract::State, http::Response};
+use tower_http::compression::{
+	predicate::{And, Predicate, SizeAbove},
+	CompressionLayer,
+};
+
+use crate::ServerState;
+
+// 小于该大小的响应压缩收益太小
+const MIN_COMPRESS_SIZE: u16 = 1024;
+
+// 本身已经压缩过的文件格式，再压缩只会浪费 CPU
+const PRECOMPRESSED_EXTENSIONS: &[&str] = &[
+	"7z", "avi", "br", "bz2", "cab", "docx", "flac", "gif", "gz", "heic", "jar", "jpeg", "jpg",
+	"m4a", "mkv", "mov", "mp3", "mp4", "ogg", "png", "pptx", "rar", "tgz", "webm", "webp", "xlsx",
+	"xz", "zip", "zst",
+];
+
+// 处理函数在允许压缩的响应上插入此标记，只有 /read 和 /list 的响应会被压缩
+#[derive(Debug, Clone, Copy)]
+pub struct Compressible;
+
+pub fn is_precompressed(path: &Path) -> bool {
+	path.extension()
+		.and_then(|ext| ext.to_str())
+		.is_some_and(|ext| {
+			PRECOMPRESSED_EXTENSIONS
+				.iter()
+				.any(|known| known.eq_ignore_ascii_case(ext))
+		})
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct MarkedCompressible;
+
+impl Predicate for MarkedCompressible {
+	fn should_compress<B>(&self, response: &Response<B>) -> bool
+	where
+		B: HttpBody,
+	{
+		response.extensions().get::<Compressible>().is_some()
+	}
+}
+
+// 根据客户端的 Accept-Encoding 选择 zstd 或 gzip
+pub fn layer() -> CompressionLayer<And<SizeAbove, MarkedCompressible>> {
+	CompressionLayer::new().compress_when(SizeAbove::new(MIN_COMPRESS_SIZE).and(MarkedCompressible))
+}
+
+// 配置关闭压缩时去掉响应上的标记
+pub async fn apply_setting<B>(
+	State(state): State<Arc<ServerState>>,
+	mut response: Response<B>,
+) -> Response<B> {
+	if !state.settings().compress_responses {
+		response.extensions_mut().remove::<Compressible>();
+	}
+	response
+}
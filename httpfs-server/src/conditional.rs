@@ -0,0 +1,96 @@
+use std::{
+	fs::Metadata,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	http::{header, HeaderMap, HeaderValue, StatusCode},
+	response::{IntoResponse, Response},
+};
+
+use crate::error::ApiError;
+
+// ETag 由文件大小和修改时间（纳秒）组成，内容变化时两者之一必然变化
+pub fn etag(metadata: &Metadata) -> String {
+	let modified = metadata
+		.modified()
+		.ok()
+		.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+		.map(|d| d.as_nanos())
+		.unwrap_or(0);
+	format!("\"{:x}-{:x}\"", metadata.len(), modified)
+}
+
+// 给响应加上 ETag 和 Last-Modified 头
+pub fn with_validators(metadata: &Metadata, response: impl IntoResponse) -> Response {
+	let mut response = response.into_response();
+	let headers = response.headers_mut();
+	if let Ok(value) = HeaderValue::from_str(&etag(metadata)) {
+		headers.insert(header::ETAG, value);
+	}
+	if let Ok(modified) = metadata.modified() {
+		if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(modified)) {
+			headers.insert(header::LAST_MODIFIED, value);
+		}
+	}
+	response
+}
+
+// If-None-Match / If-Match 中的任一标签与当前 ETag 相同（忽略弱标签前缀）即视为匹配
+fn matches_any(header_value: &str, current: &str) -> bool {
+	header_value
+		.split(',')
+		.map(str::trim)
+		.any(|tag| tag == "*" || tag.trim_start_matches("W/") == current.trim_start_matches("W/"))
+}
+
+// If-None-Match 优先；没有时比较 If-Modified-Since（HTTP 日期精度为秒）
+pub fn is_not_modified(headers: &HeaderMap, metadata: &Metadata) -> bool {
+	if let Some(value) = headers.get(header::IF_NONE_MATCH) {
+		return value
+			.to_str()
+			.is_ok_and(|value| matches_any(value, &etag(metadata)));
+	}
+
+	let since = headers
+		.get(header::IF_MODIFIED_SINCE)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| httpdate::parse_http_date(value).ok());
+	match (since, metadata.modified()) {
+		(Some(since), Ok(modified)) => truncate_to_secs(modified) <= since,
+		_ => false,
+	}
+}
+
+fn truncate_to_secs(time: SystemTime) -> SystemTime {
+	let secs = time
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0);
+	UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+// 304 响应同样带上验证器，便于客户端更新缓存
+pub fn not_modified(metadata: &Metadata) -> Response {
+	with_validators(metadata, StatusCode::NOT_MODIFIED)
+}
+
+// 检查 If-Match 前置条件：目标不存在或 ETag 不匹配时返回 412
+pub fn check_if_match(headers: &HeaderMap, metadata: Option<&Metadata>) -> Result<(), ApiError> {
+	let Some(value) = headers.get(header::IF_MATCH) else {
+		return Ok(());
+	};
+	let matched = match (value.to_str(), metadata) {
+		(Ok(value), Some(metadata)) => matches_any(value, &etag(metadata)),
+		_ => false,
+	};
+	if matched {
+		Ok(())
+	} else {
+		Err(ApiError::new(
+			StatusCode::PRECONDITION_FAILED,
+			"precondition_failed",
+			"If-Match does not match the current ETag",
+		))
+	}
+}
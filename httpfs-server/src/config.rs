@@ -0,0 +1,462 @@
+use std::{
+	collections::BTreeMap,
+	fs,
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use axum::{
+	extract::State,
+	http::{HeaderMap, StatusCode},
+	response::{IntoResponse, Response},
+};
+use axum_server::tls_rustls::RustlsConfig;
+use serde::Deserialize;
+use tracing::level_filters::LevelFilter;
+
+use crate::{
+	access_log::LogHandle,
+	bearer_token,
+	error::ApiError,
+	events,
+	limits::RateLimit,
+	presign::{DirectReads, MAX_EXPIRES},
+	quic,
+	scan::{IcapScanner, ScanAction, ScanSettings},
+	share::{Share, SymlinkPolicy},
+	users::UserAccess,
+	ServerState, Settings,
+};
+
+// 服务器配置文件（TOML）。除监听地址、是否启用 TLS、访问日志和审计日志文件外，其余设置都可以在运行中重新加载
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+	#[serde(default = "default_bind")]
+	pub bind: SocketAddr,
+	pub tls: Option<TlsConfig>,
+	// 无前缀的请求访问的共享，默认为 "default"
+	default_share: Option<String>,
+	shares: BTreeMap<String, ShareConfig>,
+	#[serde(default)]
+	auth: AuthConfig,
+	#[serde(default = "default_compression")]
+	compression: bool,
+	// 回收站保留天数，未设置时直接删除
+	trash_days: Option<u64>,
+	// 每个文件保留的历史版本数
+	#[serde(default)]
+	versions: usize,
+	// 写入和截断期间对文件加操作系统的排他锁
+	#[serde(default)]
+	advisory_locks: bool,
+	#[serde(default)]
+	limits: LimitsConfig,
+	// 读写内容前交给防病毒扫描器检查，未设置时不扫描
+	scan: Option<ScanConfig>,
+	#[serde(default)]
+	pub log: LogConfig,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TlsConfig {
+	pub cert: PathBuf,
+	pub key: PathBuf,
+	// 同时在同一端口上以 UDP 提供实验性的 HTTP/3
+	#[serde(default)]
+	pub http3: bool,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ShareConfig {
+	path: PathBuf,
+	token: Option<String>,
+	// 共享内所有文件的总大小上限（字节）
+	quota: Option<u64>,
+	#[serde(default)]
+	symlinks: SymlinkPolicy,
+	// 用户名到权限，客户端通过 X-Httpfs-User 指明代表的用户
+	#[serde(default)]
+	users: BTreeMap<String, UserAccess>,
+	// 设置后共享为一次写入多次读取（WORM）：写入的文件在这么多天内不能修改或删除
+	retention_days: Option<u64>,
+	// 共享的内容同时保存在对象存储中时，允许客户端通过签名 URL 直接读取
+	direct_reads: Option<DirectReadsConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectReadsConfig {
+	// 对象存储的地址，如 https://s3.eu-west-1.amazonaws.com
+	endpoint: String,
+	bucket: String,
+	// 共享根目录在桶中对应的键前缀，如 "team/"
+	#[serde(default)]
+	prefix: String,
+	region: Option<String>,
+	access_key: String,
+	secret_key: String,
+	// 签名 URL 的有效期（秒），默认 300
+	expires_secs: Option<u64>,
+	// 只重定向至少这么大的文件（字节），默认 1 MiB
+	min_size: Option<u64>,
+}
+
+impl DirectReadsConfig {
+	fn settings(&self) -> Result<DirectReads, String> {
+		if !self.endpoint.starts_with("http://") && !self.endpoint.starts_with("https://") {
+			return Err(format!(
+				"direct_reads.endpoint '{}' must be an http(s) URL",
+				self.endpoint
+			));
+		}
+		let mut direct = DirectReads::new(
+			&self.endpoint,
+			self.bucket.clone(),
+			self.access_key.clone(),
+			self.secret_key.clone(),
+		);
+		direct.prefix = self.prefix.clone();
+		if let Some(region) = &self.region {
+			direct.region = region.clone();
+		}
+		if let Some(secs) = self.expires_secs {
+			direct.expires = Duration::from_secs(secs);
+			if secs == 0 || direct.expires > MAX_EXPIRES {
+				return Err(format!(
+					"direct_reads.expires_secs must be between 1 and {}",
+					MAX_EXPIRES.as_secs()
+				));
+			}
+		}
+		if let Some(min_size) = self.min_size {
+			direct.min_size = min_size;
+		}
+		Ok(direct)
+	}
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AuthConfig {
+	admin_token: Option<String>,
+	metrics_token: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LimitsConfig {
+	// 每个客户端地址每秒的平均请求数，未设置时不限制
+	requests_per_second: Option<f64>,
+	// 允许连续发出的请求数，默认与每秒请求数相同
+	burst: Option<u32>,
+	// 单个 /write 请求体的大小上限（字节）
+	max_write_size: Option<usize>,
+}
+
+impl LimitsConfig {
+	fn rate_limit(&self) -> Result<Option<RateLimit>, String> {
+		let Some(requests_per_second) = self.requests_per_second else {
+			if self.burst.is_some() {
+				return Err("limits.burst requires limits.requests_per_second".to_string());
+			}
+			return Ok(None);
+		};
+		if !(requests_per_second.is_finite() && requests_per_second > 0.0) {
+			return Err("limits.requests_per_second must be a positive number".to_string());
+		}
+		let burst = self
+			.burst
+			.unwrap_or_else(|| requests_per_second.ceil() as u32)
+			.max(1);
+		Ok(Some(RateLimit {
+			requests_per_second,
+			burst,
+		}))
+	}
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScanConfig {
+	// ICAP 服务的地址，如 icap://127.0.0.1:1344/avscan
+	icap: String,
+	// 检出威胁时只拒绝请求（reject）还是同时把内容移入隔离区（quarantine）
+	#[serde(default)]
+	action: ScanAction,
+	// 超过该大小（字节）的文件不扫描
+	max_size: Option<u64>,
+	// 读取前也扫描文件
+	#[serde(default)]
+	scan_reads: bool,
+	// 扫描器不可用时放行请求
+	#[serde(default)]
+	fail_open: bool,
+}
+
+impl ScanConfig {
+	fn settings(&self) -> Result<ScanSettings, String> {
+		let scanner = IcapScanner::new(&self.icap).map_err(|e| format!("scan.icap: {}", e))?;
+		let mut settings = ScanSettings::new(Arc::new(scanner));
+		settings.action = self.action;
+		settings.max_size = self.max_size;
+		settings.scan_reads = self.scan_reads;
+		settings.fail_open = self.fail_open;
+		Ok(settings)
+	}
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct LogConfig {
+	// 以 JSON 行格式追加写入访问日志的文件，未设置时以文本格式输出到标准错误
+	pub access_log: Option<PathBuf>,
+	// 以哈希链记录所有修改操作的只追加日志文件，未设置时不记录
+	pub audit_log: Option<PathBuf>,
+	level: Option<String>,
+}
+
+fn default_bind() -> SocketAddr {
+	SocketAddr::from(([127, 0, 0, 1], 8080))
+}
+
+fn default_compression() -> bool {
+	true
+}
+
+const DEFAULT_SHARE: &str = "default";
+
+impl Config {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let text = fs::read_to_string(path)
+			.map_err(|e| format!("cannot read config {}: {}", path.display(), e))?;
+		let mut config: Config = toml::from_str(&text)
+			.map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+
+		// 相对路径相对于配置文件所在的目录
+		let base = path.parent().unwrap_or(Path::new(""));
+		for share in config.shares.values_mut() {
+			share.path = base.join(&share.path);
+		}
+		if let Some(tls) = &mut config.tls {
+			tls.cert = base.join(&tls.cert);
+			tls.key = base.join(&tls.key);
+		}
+		if let Some(access_log) = &mut config.log.access_log {
+			*access_log = base.join(&*access_log);
+		}
+		if let Some(audit_log) = &mut config.log.audit_log {
+			*audit_log = base.join(&*audit_log);
+		}
+
+		if config.shares.is_empty() {
+			return Err("config must define at least one share".to_string());
+		}
+		if let Some(name) = &config.default_share {
+			if !config.shares.contains_key(name) {
+				return Err(format!("default share '{}' is not defined", name));
+			}
+		}
+		config.log_level()?;
+		config.limits.rate_limit()?;
+		if let Some(scan) = &config.scan {
+			scan.settings()?;
+		}
+		for (name, share) in &config.shares {
+			if let Some(direct) = &share.direct_reads {
+				direct
+					.settings()
+					.map_err(|e| format!("shares.{}.{}", name, e))?;
+			}
+		}
+		Ok(config)
+	}
+
+	pub fn log_level(&self) -> Result<LevelFilter, String> {
+		match &self.log.level {
+			Some(level) => level
+				.parse()
+				.map_err(|_| format!("invalid log level '{}'", level)),
+			None => Ok(LevelFilter::INFO),
+		}
+	}
+
+	pub(crate) fn settings(&self) -> Settings {
+		let shares = self
+			.shares
+			.iter()
+			.map(|(name, config)| {
+				let mut share = Share::new(name.clone(), config.path.clone());
+				share.token = config.token.clone();
+				share.quota = config.quota;
+				share.symlinks = config.symlinks;
+				share.users = config.users.clone().into_iter().collect();
+				share.retention = config
+					.retention_days
+					.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+				// 已在 load 中校验
+				share.direct_reads = config
+					.direct_reads
+					.as_ref()
+					.and_then(|direct| direct.settings().ok())
+					.map(Arc::new);
+				share
+			})
+			.collect();
+		let mut settings = Settings::new(
+			shares,
+			self.default_share
+				.clone()
+				.unwrap_or_else(|| DEFAULT_SHARE.to_string()),
+		);
+		settings.admin_token = self.auth.admin_token.clone();
+		settings.metrics_token = self.auth.metrics_token.clone();
+		settings.compress_responses = self.compression;
+		settings.trash_retention = self
+			.trash_days
+			.map(|days| Duration::from_secs(days * 24 * 60 * 60));
+		settings.max_versions = self.versions;
+		settings.advisory_locks = self.advisory_locks;
+		// 已在 load 中校验
+		settings.rate_limit = self.limits.rate_limit().unwrap_or_default();
+		settings.max_write_size = self.limits.max_write_size;
+		settings.scan = self.scan.as_ref().and_then(|scan| scan.settings().ok());
+		settings
+	}
+}
+
+// 重新加载所需的启动时状态：配置文件路径、只在重启后生效的设置以及 TLS 证书和日志级别的句柄
+pub struct Reloader {
+	path: PathBuf,
+	bind: SocketAddr,
+	access_log: Option<PathBuf>,
+	audit_log: Option<PathBuf>,
+	tls: Option<RustlsConfig>,
+	// HTTP/3 的 QUIC 端点，证书随 HTTPS 一起重新加载
+	quic: Option<quinn::Endpoint>,
+	log: LogHandle,
+}
+
+impl Reloader {
+	pub fn new(path: PathBuf, config: &Config, tls: Option<RustlsConfig>, log: LogHandle) -> Self {
+		Self {
+			path,
+			bind: config.bind,
+			access_log: config.log.access_log.clone(),
+			audit_log: config.log.audit_log.clone(),
+			tls,
+			quic: None,
+			log,
+		}
+	}
+
+	pub fn with_quic(self, quic: Option<quinn::Endpoint>) -> Self {
+		Self { quic, ..self }
+	}
+}
+
+// 重新读取配置文件并替换设置；任何一步失败时保留原有设置
+pub async fn reload(state: &ServerState) -> Result<(), String> {
+	let reloader = state
+		.reloader
+		.as_ref()
+		.ok_or("server was not started from a config file")?;
+	let config = Config::load(&reloader.path)?;
+	let level = config.log_level()?;
+	let settings = config.settings();
+	let watchers = events::watch(&settings, &state.events)
+		.map_err(|e| format!("watching shares failed: {}", e))?;
+
+	match (&reloader.tls, &config.tls) {
+		// 证书文件可能在原路径上被替换，总是重新读取
+		(Some(rustls), Some(tls)) => rustls
+			.reload_from_pem_file(&tls.cert, &tls.key)
+			.await
+			.map_err(|e| format!("loading TLS certificate failed: {}", e))?,
+		(None, None) => {}
+		_ => eprintln!("[SERVER] reload: enabling or disabling TLS requires a restart"),
+	}
+	if let (Some(quic), Some(rustls)) = (&reloader.quic, &reloader.tls) {
+		quic.set_server_config(Some(quic::server_config(rustls)?));
+	}
+	if config.tls.as_ref().is_some_and(|tls| tls.http3) != reloader.quic.is_some() {
+		eprintln!("[SERVER] reload: enabling or disabling HTTP/3 requires a restart");
+	}
+	if config.bind != reloader.bind {
+		eprintln!("[SERVER] reload: changing bind address requires a restart");
+	}
+	if config.log.access_log != reloader.access_log {
+		eprintln!("[SERVER] reload: changing access log file requires a restart");
+	}
+	if config.log.audit_log != reloader.audit_log {
+		eprintln!("[SERVER] reload: changing audit log file requires a restart");
+	}
+	reloader
+		.log
+		.modify(|filter| *filter = level)
+		.map_err(|e| format!("changing log level failed: {}", e))?;
+
+	eprintln!(
+		"[SERVER] configuration reloaded: {} share(s)",
+		settings.shares.len()
+	);
+	*state.settings.write().unwrap() = Arc::new(settings);
+	*state.watchers.lock().unwrap() = watchers;
+	Ok(())
+}
+
+// 收到 SIGHUP 时重新加载配置
+#[cfg(unix)]
+pub async fn reload_on_sighup(state: Arc<ServerState>) {
+	use tokio::signal::unix::{signal, SignalKind};
+
+	let mut hangups = match signal(SignalKind::hangup()) {
+		Ok(hangups) => hangups,
+		Err(e) => {
+			eprintln!("[ERROR] cannot listen for SIGHUP: {}", e);
+			return;
+		}
+	};
+	while hangups.recv().await.is_some() {
+		if let Err(e) = reload(&state).await {
+			eprintln!("[ERROR] reload failed: {}", e);
+		}
+	}
+}
+
+// /admin 下的路由需要以 auth.admin_token 认证，未设置时全部禁用
+pub fn require_admin(state: &ServerState, headers: &HeaderMap) -> Result<(), ApiError> {
+	let Some(admin_token) = state.settings().admin_token.clone() else {
+		return Err(ApiError::new(
+			StatusCode::FORBIDDEN,
+			"admin_disabled",
+			"set auth.admin_token in the config to enable the /admin routes",
+		));
+	};
+	if bearer_token(headers) != Some(admin_token.as_str()) {
+		return Err(ApiError::new(
+			StatusCode::UNAUTHORIZED,
+			"unauthorized",
+			"missing or invalid admin token",
+		));
+	}
+	Ok(())
+}
+
+// POST /admin/reload - 重新加载配置文件
+pub async fn reload_config(State(state): State<Arc<ServerState>>, headers: HeaderMap) -> Response {
+	if let Err(e) = require_admin(&state, &headers) {
+		return e.into_response();
+	}
+	match reload(&state).await {
+		Ok(()) => StatusCode::NO_CONTENT.into_response(),
+		Err(e) => {
+			eprintln!("[ERROR] reload failed: {}", e);
+			ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, "invalid_config", e).into_response()
+		}
+	}
+}
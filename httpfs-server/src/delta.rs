@@ -0,0 +1,104 @@
+use std::{
+	fs::File,
+	io::{self, Read},
+	path::Path,
+};
+
+use axum::{
+	extract::Query,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{error::ApiError, is_a_directory, Target};
+
+// 未指定时的块大小，以及客户端可以请求的范围
+const DEFAULT_BLOCK_SIZE: usize = 64 * 1024;
+const MIN_BLOCK_SIZE: usize = 1024;
+const MAX_BLOCK_SIZE: usize = 16 * 1024 * 1024;
+
+// 块的强校验和取 SHA-256 的前 16 字节
+const STRONG_LEN: usize = 16;
+
+// rsync 的弱校验和：a 为字节之和，b 为按位置加权的和，各取低 16 位。
+// 客户端在新内容上逐字节滚动计算同样的值，匹配后再比较强校验和
+pub fn weak_checksum(block: &[u8]) -> u32 {
+	let mut a: u32 = 0;
+	let mut b: u32 = 0;
+	let len = block.len() as u32;
+	for (i, &byte) in block.iter().enumerate() {
+		a = a.wrapping_add(byte as u32);
+		b = b.wrapping_add((len - i as u32).wrapping_mul(byte as u32));
+	}
+	(a & 0xffff) | ((b & 0xffff) << 16)
+}
+
+#[derive(Debug, Serialize)]
+pub struct BlockSignature {
+	weak: u32,
+	strong: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignatureResponse {
+	size: u64,
+	block_size: usize,
+	// 按顺序每个块一项，最后一块可能不足 block_size
+	blocks: Vec<BlockSignature>,
+}
+
+fn signature(path: &Path, block_size: usize) -> io::Result<SignatureResponse> {
+	let mut file = File::open(path)?;
+	let size = file.metadata()?.len();
+	let mut blocks = Vec::with_capacity(size.div_ceil(block_size as u64) as usize);
+	let mut buffer = vec![0u8; block_size];
+	loop {
+		// 读满一块，除非到了文件末尾
+		let mut filled = 0;
+		while filled < block_size {
+			let n = file.read(&mut buffer[filled..])?;
+			if n == 0 {
+				break;
+			}
+			filled += n;
+		}
+		if filled == 0 {
+			break;
+		}
+		let block = &buffer[..filled];
+		blocks.push(BlockSignature {
+			weak: weak_checksum(block),
+			strong: hex::encode(&Sha256::digest(block)[..STRONG_LEN]),
+		});
+		if filled < block_size {
+			break;
+		}
+	}
+	Ok(SignatureResponse {
+		size,
+		block_size,
+		blocks,
+	})
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SignatureQuery {
+	block_size: Option<usize>,
+}
+
+// GET /signature/:path?block_size= - 文件按块的弱校验和与强校验和，客户端据此只上传变化的部分
+pub async fn get_signature(target: Target, Query(query): Query<SignatureQuery>) -> Response {
+	if target.real_path.is_dir() {
+		return is_a_directory(&target.path);
+	}
+	let block_size = query
+		.block_size
+		.unwrap_or(DEFAULT_BLOCK_SIZE)
+		.clamp(MIN_BLOCK_SIZE, MAX_BLOCK_SIZE);
+	match signature(&target.real_path, block_size) {
+		Ok(signature) => Json(signature).into_response(),
+		Err(e) => ApiError::io("computing signature failed", &e).into_response(),
+	}
+}
@@ -0,0 +1,125 @@
+use std::io;
+
+use axum::{
+	body::to_bytes,
+	http::{header, StatusCode},
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+
+// 失败响应的 JSON 体：code 是供客户端判断的错误类别，message 是可读的说明，
+// os_error 是服务器上底层系统调用的错误码（如有）
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+	#[serde(skip)]
+	status: StatusCode,
+	code: &'static str,
+	message: String,
+	os_error: Option<i32>,
+	// 附加在错误体中的其他字段（例如上传缺失的区间）
+	#[serde(flatten)]
+	details: Option<serde_json::Value>,
+}
+
+impl ApiError {
+	pub fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+		Self {
+			status,
+			code,
+			message: message.into(),
+			os_error: None,
+			details: None,
+		}
+	}
+
+	// 按 io::ErrorKind 选择状态码和错误类别，message 带上出错的操作
+	pub fn io(context: &str, error: &io::Error) -> Self {
+		let (status, code) = match error.kind() {
+			io::ErrorKind::NotFound => (StatusCode::NOT_FOUND, "not_found"),
+			io::ErrorKind::PermissionDenied => (StatusCode::FORBIDDEN, "permission_denied"),
+			io::ErrorKind::AlreadyExists => (StatusCode::CONFLICT, "already_exists"),
+			io::ErrorKind::DirectoryNotEmpty => (StatusCode::CONFLICT, "directory_not_empty"),
+			io::ErrorKind::NotADirectory => (StatusCode::CONFLICT, "not_a_directory"),
+			io::ErrorKind::IsADirectory => (StatusCode::CONFLICT, "is_a_directory"),
+			io::ErrorKind::StorageFull => (StatusCode::INSUFFICIENT_STORAGE, "disk_full"),
+			io::ErrorKind::FileTooLarge => (StatusCode::PAYLOAD_TOO_LARGE, "file_too_large"),
+			io::ErrorKind::InvalidInput => (StatusCode::BAD_REQUEST, "invalid_input"),
+			_ => (StatusCode::INTERNAL_SERVER_ERROR, "io_error"),
+		};
+		Self {
+			os_error: error.raw_os_error(),
+			..Self::new(status, code, format!("{}: {}", context, error))
+		}
+	}
+
+	pub fn status(&self) -> StatusCode {
+		self.status
+	}
+
+	pub fn with_details(mut self, details: impl Serialize) -> Self {
+		self.details = serde_json::to_value(details).ok();
+		self
+	}
+}
+
+// 没有更具体信息的状态码使用通用的错误类别
+fn default_code(status: StatusCode) -> &'static str {
+	match status {
+		StatusCode::BAD_REQUEST => "bad_request",
+		StatusCode::UNAUTHORIZED => "unauthorized",
+		StatusCode::FORBIDDEN => "forbidden",
+		StatusCode::NOT_FOUND => "not_found",
+		StatusCode::CONFLICT => "conflict",
+		StatusCode::PRECONDITION_FAILED => "precondition_failed",
+		StatusCode::PAYLOAD_TOO_LARGE => "payload_too_large",
+		StatusCode::UNSUPPORTED_MEDIA_TYPE => "unsupported_media_type",
+		StatusCode::RANGE_NOT_SATISFIABLE => "range_not_satisfiable",
+		StatusCode::UNPROCESSABLE_ENTITY => "unprocessable",
+		StatusCode::TOO_MANY_REQUESTS => "rate_limited",
+		StatusCode::INSUFFICIENT_STORAGE => "disk_full",
+		_ if status.is_client_error() => "bad_request",
+		_ => "internal_error",
+	}
+}
+
+impl From<StatusCode> for ApiError {
+	fn from(status: StatusCode) -> Self {
+		Self::new(
+			status,
+			default_code(status),
+			status.canonical_reason().unwrap_or("error"),
+		)
+	}
+}
+
+impl IntoResponse for ApiError {
+	fn into_response(self) -> Response {
+		(self.status, Json(self)).into_response()
+	}
+}
+
+// 错误消息里最多保留的原始响应体长度
+const MAX_FALLBACK_MESSAGE: usize = 4096;
+
+// 把提取器拒绝、路由不存在等没有经过处理函数的错误响应也转换成 JSON 错误体
+pub async fn json_errors(response: Response) -> Response {
+	let status = response.status();
+	let is_json = response
+		.headers()
+		.get(header::CONTENT_TYPE)
+		.is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+	if !(status.is_client_error() || status.is_server_error()) || is_json {
+		return response;
+	}
+
+	let body = to_bytes(response.into_body(), MAX_FALLBACK_MESSAGE)
+		.await
+		.unwrap_or_default();
+	let message = String::from_utf8_lossy(&body).trim().to_string();
+	let mut error = ApiError::from(status);
+	if !message.is_empty() {
+		error.message = message;
+	}
+	error.into_response()
+}
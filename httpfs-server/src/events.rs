@@ -0,0 +1,216 @@
+use std::{convert::Infallible, path::Path, sync::Arc, time::Duration};
+
+use axum::{
+	extract::State,
+	response::{
+		sse::{Event, KeepAlive, Sse},
+		IntoResponse, Response,
+	},
+};
+use notify::{
+	event::{CreateKind, ModifyKind, RemoveKind, RenameMode},
+	EventKind, RecommendedWatcher, RecursiveMode, Watcher,
+};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tokio_stream::{
+	wrappers::{BroadcastStream, WatchStream},
+	StreamExt,
+};
+
+use crate::{
+	is_internal_name, share::Share, users::User, ServerState, Settings, ShareAccess,
+};
+
+// 尚未发送给订阅者的事件数上限，订阅者落后更多时收到 resync 事件
+const EVENT_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+	Create,
+	Modify,
+	Delete,
+	Rename,
+}
+
+// 共享内的一次变化，路径相对于共享根目录，使用 / 分隔
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangeEvent {
+	#[serde(skip)]
+	share: String,
+	kind: ChangeKind,
+	path: String,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	new_path: Option<String>,
+	is_directory: bool,
+}
+
+impl ChangeEvent {
+	pub fn share(&self) -> &str {
+		&self.share
+	}
+
+	// 变化涉及的路径，重命名时为两端
+	pub fn paths(&self) -> impl Iterator<Item = &str> {
+		std::iter::once(self.path.as_str()).chain(self.new_path.as_deref())
+	}
+
+	// 重命名的任一端可见时用户都需要知道这个变化
+	fn visible_to(&self, user: &User) -> bool {
+		user.can_see(&self.path) || self.new_path.as_deref().is_some_and(|path| user.can_see(path))
+	}
+}
+
+pub fn channel() -> broadcast::Sender<ChangeEvent> {
+	broadcast::channel(EVENT_BUFFER).0
+}
+
+// 相对共享根目录的路径；根目录之外或属于服务器内部文件的路径返回 None
+fn relative_path(path: &Path, root: &Path) -> Option<String> {
+	let relative = path.strip_prefix(root).ok()?;
+	let mut parts = Vec::new();
+	for component in relative.components() {
+		let part = component.as_os_str().to_string_lossy();
+		if is_internal_name(&part) {
+			return None;
+		}
+		parts.push(part.into_owned());
+	}
+	(!parts.is_empty()).then(|| parts.join("/"))
+}
+
+fn to_changes(share: &Share, event: notify::Event) -> Vec<ChangeEvent> {
+	let root = &share.root_path;
+	let change = |kind, path: &Path, is_directory| {
+		Some(ChangeEvent {
+			share: share.name.clone(),
+			kind,
+			path: relative_path(path, root)?,
+			new_path: None,
+			is_directory,
+		})
+	};
+	match event.kind {
+		EventKind::Create(kind) => event
+			.paths
+			.iter()
+			.filter_map(|path| {
+				let is_directory = kind == CreateKind::Folder || path.is_dir();
+				change(ChangeKind::Create, path, is_directory)
+			})
+			.collect(),
+		EventKind::Remove(kind) => event
+			.paths
+			.iter()
+			.filter_map(|path| change(ChangeKind::Delete, path, kind == RemoveKind::Folder))
+			.collect(),
+		EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+			let to = &event.paths[1];
+			let is_directory = to.is_dir();
+			match (
+				relative_path(&event.paths[0], root),
+				relative_path(to, root),
+			) {
+				(Some(from), Some(to)) => vec![ChangeEvent {
+					share: share.name.clone(),
+					kind: ChangeKind::Rename,
+					path: from,
+					new_path: Some(to),
+					is_directory,
+				}],
+				// 从内部文件重命名而来（原子写入或上传提交）时无法区分新建和覆盖，按创建处理，
+				// 客户端对已存在的条目同样会刷新
+				(None, Some(_)) => change(ChangeKind::Create, to, is_directory)
+					.into_iter()
+					.collect(),
+				(Some(_), None) => change(ChangeKind::Delete, &event.paths[0], is_directory)
+					.into_iter()
+					.collect(),
+				(None, None) => Vec::new(),
+			}
+		}
+		// 无法配对的重命名按删除和创建处理
+		EventKind::Modify(ModifyKind::Name(RenameMode::From)) => event
+			.paths
+			.iter()
+			.filter_map(|path| change(ChangeKind::Delete, path, false))
+			.collect(),
+		EventKind::Modify(ModifyKind::Name(_)) => event
+			.paths
+			.iter()
+			.filter_map(|path| change(ChangeKind::Create, path, path.is_dir()))
+			.collect(),
+		EventKind::Modify(_) => event
+			.paths
+			.iter()
+			.filter_map(|path| change(ChangeKind::Modify, path, path.is_dir()))
+			.collect(),
+		_ => Vec::new(),
+	}
+}
+
+// 为每个共享的根目录启动递归监视，变化通过 sender 广播；返回的监视器需要一直持有
+pub fn watch(
+	settings: &Settings,
+	sender: &broadcast::Sender<ChangeEvent>,
+) -> notify::Result<Vec<RecommendedWatcher>> {
+	let mut watchers = Vec::new();
+	for share in settings.shares.values() {
+		let root = share.root_path.clone();
+		let share = share.clone();
+		let sender = sender.clone();
+		let mut watcher = notify::recommended_watcher(
+			move |result: notify::Result<notify::Event>| match result {
+				Ok(event) => {
+					for change in to_changes(&share, event) {
+						// 没有订阅者时发送失败，直接丢弃
+						let _ = sender.send(change);
+					}
+				}
+				Err(e) => eprintln!("[SERVER] watching share '{}' failed: {:?}", share.name, e),
+			},
+		)?;
+		watcher.watch(&root, RecursiveMode::Recursive)?;
+		watchers.push(watcher);
+	}
+	Ok(watchers)
+}
+
+// GET /events - 以 Server-Sent Events 推送共享内的变化。事件名为变化类型，数据为 JSON；
+// 订阅者处理过慢而丢失事件时收到 resync 事件，应丢弃所有缓存。管理员预告关闭时收到
+// shutdown_notice 事件（订阅时已有预告则立即收到）。服务器关闭时事件流结束
+pub async fn events(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+) -> Response {
+	let closing = WatchStream::new(state.shutdown.subscribe())
+		.filter(|closing| *closing)
+		.map(|_| None);
+	let stream = BroadcastStream::new(state.events.subscribe()).filter_map(move |item| {
+		let event = match item {
+			Ok(change) if change.share != share.name || !change.visible_to(&user) => return None,
+			Ok(change) => Event::default()
+				.event(match change.kind {
+					ChangeKind::Create => "create",
+					ChangeKind::Modify => "modify",
+					ChangeKind::Delete => "delete",
+					ChangeKind::Rename => "rename",
+				})
+				.json_data(&change)
+				.ok()?,
+			Err(_) => Event::default().event("resync").data("{}"),
+		};
+		Some(Some(Ok::<_, Infallible>(event)))
+	});
+	let notices = WatchStream::new(state.notice.subscribe())
+		.filter_map(|notice| Some(Some(Ok(notice?.event()))));
+	let stream = stream
+		.merge(notices)
+		.merge(closing)
+		.map_while(|event| event);
+	Sse::new(stream)
+		.keep_alive(KeepAlive::new().interval(Duration::from_secs(15)))
+		.into_response()
+}
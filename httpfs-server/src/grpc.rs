@@ -0,0 +1,365 @@
+use std::net::SocketAddr;
+
+use axum::{
+	body::{to_bytes, Body, Bytes},
+	extract::ConnectInfo,
+	http::{header, Method, Request, StatusCode},
+	Router,
+};
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tonic::{metadata::MetadataMap, server::NamedService, Code, Status};
+use tower::ServiceExt;
+
+use crate::{presign::uri_encode, users::USER_HEADER, FileInfo, MAX_BODY_SIZE, MAX_LIST_LIMIT};
+
+pub mod proto {
+	tonic::include_proto!("httpfs.v1");
+}
+
+use proto::{
+	event,
+	storage_server::{Storage, StorageServer},
+	Change, ChangeKind, Event, ListRequest, ListResponse, ReadRequest, ReadResponse, Resync,
+	ShutdownNotice, StatRequest, WatchRequest, WriteRequest, WriteResponse,
+};
+
+// gRPC 形式的存储协议（见 proto/httpfs.proto）。每个调用转换为对 HTTP API 路由的内部请求，
+// 认证、用户权限、配额、WORM、内容扫描、审计、事件和请求限制都与 HTTP API 完全一致；
+// 省下的是网络上的开销：二进制的消息，在一个 HTTP/2 连接上复用所有调用
+
+// 元数据中选择共享的键，未给出时与 HTTP API 一样按令牌选择
+const SHARE_METADATA: &str = "x-httpfs-share";
+// 转发给内部请求的元数据，If-Match 来自 WriteRequest.if_match
+const FORWARDED_METADATA: [&str; 4] = ["authorization", USER_HEADER, "x-request-id", "if-match"];
+// 尚未发送给订阅者的事件数上限
+const WATCH_BUFFER: usize = 256;
+
+#[derive(Clone)]
+struct GrpcStorage {
+	router: Router,
+}
+
+// 调用的路径为 /httpfs.v1.Storage/<方法>，与 HTTP API 的路由不会冲突
+pub fn routes(router: Router) -> Router {
+	let service = StorageServer::new(GrpcStorage { router })
+		.max_decoding_message_size(MAX_BODY_SIZE)
+		.max_encoding_message_size(MAX_BODY_SIZE);
+	let path = format!("/{}/*method", StorageServer::<GrpcStorage>::NAME);
+	Router::new().route_service(&path, service)
+}
+
+// 根目录在 HTTP API 中为 $ROOT
+fn route_path(path: &str) -> String {
+	let path = path.trim_matches('/');
+	if path.is_empty() || path == "." {
+		"$ROOT".to_string()
+	} else {
+		uri_encode(path, true)
+	}
+}
+
+fn status_code(status: StatusCode) -> Code {
+	match status {
+		StatusCode::BAD_REQUEST => Code::InvalidArgument,
+		StatusCode::UNAUTHORIZED => Code::Unauthenticated,
+		StatusCode::FORBIDDEN => Code::PermissionDenied,
+		StatusCode::NOT_FOUND => Code::NotFound,
+		StatusCode::CONFLICT => Code::AlreadyExists,
+		StatusCode::PRECONDITION_FAILED => Code::FailedPrecondition,
+		StatusCode::PAYLOAD_TOO_LARGE
+		| StatusCode::TOO_MANY_REQUESTS
+		| StatusCode::INSUFFICIENT_STORAGE => Code::ResourceExhausted,
+		StatusCode::SERVICE_UNAVAILABLE => Code::Unavailable,
+		StatusCode::NOT_IMPLEMENTED => Code::Unimplemented,
+		_ => Code::Internal,
+	}
+}
+
+// HTTP API 的错误响应转换为 Status，JSON 错误体原样放在 details 中
+fn error_status(status: StatusCode, body: Bytes) -> Status {
+	let message = serde_json::from_slice::<serde_json::Value>(&body)
+		.ok()
+		.and_then(|error| error["message"].as_str().map(str::to_string))
+		.unwrap_or_else(|| status.to_string());
+	Status::with_details(status_code(status), message, body)
+}
+
+fn internal(e: impl std::fmt::Display) -> Status {
+	Status::internal(e.to_string())
+}
+
+impl GrpcStorage {
+	// 以调用的元数据向 HTTP API 发出内部请求，失败的响应转换为 Status
+	async fn call(
+		&self,
+		metadata: &MetadataMap,
+		connect_info: Option<ConnectInfo<SocketAddr>>,
+		method: Method,
+		route: &str,
+		query: &[(&str, String)],
+		body: Body,
+	) -> Result<axum::response::Response, Status> {
+		let mut uri = match metadata
+			.get(SHARE_METADATA)
+			.and_then(|value| value.to_str().ok())
+		{
+			Some(share) => format!("/share/{}/{}", uri_encode(share, false), route),
+			None => format!("/{}", route),
+		};
+		for (index, (name, value)) in query.iter().enumerate() {
+			uri.push(if index == 0 { '?' } else { '&' });
+			uri.push_str(name);
+			uri.push('=');
+			uri.push_str(&uri_encode(value, false));
+		}
+		let mut request = Request::builder().method(method).uri(uri);
+		for name in FORWARDED_METADATA {
+			if let Some(value) = metadata.get(name) {
+				request = request.header(name, value.as_bytes());
+			}
+		}
+		let mut request = request.body(body).map_err(internal)?;
+		// 请求限制和审计按调用者的地址
+		if let Some(connect_info) = connect_info {
+			request.extensions_mut().insert(connect_info);
+		}
+		let response = self
+			.router
+			.clone()
+			.oneshot(request)
+			.await
+			.map_err(internal)?;
+		if response.status().is_success() {
+			return Ok(response);
+		}
+		let status = response.status();
+		let body = to_bytes(response.into_body(), MAX_BODY_SIZE)
+			.await
+			.unwrap_or_default();
+		Err(error_status(status, body))
+	}
+}
+
+fn file_info(info: FileInfo) -> proto::FileInfo {
+	proto::FileInfo {
+		name: info.name,
+		is_directory: info.is_directory,
+		size: info.size,
+		created: info.created,
+		modified: info.modified,
+		accessed: info.accessed,
+		allocated_size: info.allocated_size,
+	}
+}
+
+#[derive(serde::Deserialize)]
+struct ListPage {
+	items: Vec<FileInfo>,
+	next_cursor: Option<String>,
+}
+
+async fn body_bytes(response: axum::response::Response) -> Result<Bytes, Status> {
+	to_bytes(response.into_body(), usize::MAX)
+		.await
+		.map_err(internal)
+}
+
+// /events 的一个 Server-Sent Event 转换为 Watch 的消息，不认识的事件返回 None
+fn watch_event(name: &str, data: &str) -> Option<Event> {
+	let data: serde_json::Value = serde_json::from_str(data).ok()?;
+	let event = match name {
+		"resync" => event::Event::Resync(Resync {}),
+		"shutdown_notice" => event::Event::ShutdownNotice(ShutdownNotice {
+			remaining_secs: data["remaining_secs"].as_u64().unwrap_or(0),
+			message: data["message"].as_str().map(str::to_string),
+		}),
+		kind => {
+			let kind = match kind {
+				"create" => ChangeKind::Create,
+				"modify" => ChangeKind::Modify,
+				"delete" => ChangeKind::Delete,
+				"rename" => ChangeKind::Rename,
+				_ => return None,
+			};
+			event::Event::Change(Change {
+				kind: kind.into(),
+				path: data["path"].as_str()?.to_string(),
+				new_path: data["new_path"].as_str().map(str::to_string),
+				is_directory: data["is_directory"].as_bool().unwrap_or(false),
+			})
+		}
+	};
+	Some(Event { event: Some(event) })
+}
+
+#[tonic::async_trait]
+impl Storage for GrpcStorage {
+	async fn stat(
+		&self,
+		request: tonic::Request<StatRequest>,
+	) -> Result<tonic::Response<proto::FileInfo>, Status> {
+		let connect_info = request.extensions().get().cloned();
+		let path = route_path(&request.get_ref().path);
+		let response = self
+			.call(
+				request.metadata(),
+				connect_info,
+				Method::GET,
+				&format!("info/{}", path),
+				&[],
+				Body::empty(),
+			)
+			.await?;
+		let info: FileInfo =
+			serde_json::from_slice(&body_bytes(response).await?).map_err(internal)?;
+		Ok(tonic::Response::new(file_info(info)))
+	}
+
+	async fn list(
+		&self,
+		request: tonic::Request<ListRequest>,
+	) -> Result<tonic::Response<ListResponse>, Status> {
+		let connect_info = request.extensions().get().cloned();
+		let list = request.get_ref();
+		let mut query = Vec::new();
+		if let Some(cursor) = &list.cursor {
+			query.push(("cursor", cursor.clone()));
+		}
+		// 不带 limit 的 /list 返回旧版的数组而不是分页，总是转发一个上限
+		let limit = list.limit.map_or(MAX_LIST_LIMIT, |limit| limit as usize);
+		query.push(("limit", limit.to_string()));
+		if list.accessible_only {
+			query.push(("accessible", "true".to_string()));
+		}
+		let response = self
+			.call(
+				request.metadata(),
+				connect_info,
+				Method::GET,
+				&format!("list/{}", route_path(&list.path)),
+				&query,
+				Body::empty(),
+			)
+			.await?;
+		let page: ListPage =
+			serde_json::from_slice(&body_bytes(response).await?).map_err(internal)?;
+		Ok(tonic::Response::new(ListResponse {
+			items: page.items.into_iter().map(file_info).collect(),
+			next_cursor: page.next_cursor,
+		}))
+	}
+
+	async fn read(
+		&self,
+		request: tonic::Request<ReadRequest>,
+	) -> Result<tonic::Response<ReadResponse>, Status> {
+		let connect_info = request.extensions().get().cloned();
+		let read = request.get_ref();
+		let mut query = vec![("offset", read.offset.to_string())];
+		if let Some(length) = read.length {
+			query.push(("length", length.to_string()));
+		}
+		if let Some(version) = &read.version {
+			query.push(("version", version.clone()));
+		}
+		let response = self
+			.call(
+				request.metadata(),
+				connect_info,
+				Method::GET,
+				&format!("read/{}", route_path(&read.path)),
+				&query,
+				Body::empty(),
+			)
+			.await?;
+		Ok(tonic::Response::new(ReadResponse {
+			data: body_bytes(response).await?.to_vec(),
+		}))
+	}
+
+	async fn write(
+		&self,
+		request: tonic::Request<WriteRequest>,
+	) -> Result<tonic::Response<WriteResponse>, Status> {
+		let connect_info = request.extensions().get().cloned();
+		let mut metadata = request.metadata().clone();
+		let write = request.into_inner();
+		let query = [
+			("offset", write.offset.to_string()),
+			("append", write.append.to_string()),
+			("atomic", write.atomic.to_string()),
+		];
+		if let Some(etag) = &write.if_match {
+			let value = etag
+				.parse()
+				.map_err(|_| Status::invalid_argument("invalid if_match"))?;
+			metadata.insert("if-match", value);
+		}
+		let response = self
+			.call(
+				&metadata,
+				connect_info,
+				Method::POST,
+				&format!("write/{}", route_path(&write.path)),
+				&query,
+				Body::from(write.data),
+			)
+			.await?;
+		let etag = response
+			.headers()
+			.get(header::ETAG)
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string);
+		Ok(tonic::Response::new(WriteResponse { etag }))
+	}
+
+	type WatchStream = ReceiverStream<Result<Event, Status>>;
+
+	async fn watch(
+		&self,
+		request: tonic::Request<WatchRequest>,
+	) -> Result<tonic::Response<Self::WatchStream>, Status> {
+		let connect_info = request.extensions().get().cloned();
+		let response = self
+			.call(
+				request.metadata(),
+				connect_info,
+				Method::GET,
+				"events",
+				&[],
+				Body::empty(),
+			)
+			.await?;
+		let (sender, receiver) = mpsc::channel(WATCH_BUFFER);
+		tokio::spawn(async move {
+			let mut body = response.into_body().into_data_stream();
+			let (mut buffer, mut name) = (Vec::new(), String::new());
+			// 服务器关闭时事件流结束，订阅者断开后在下一次保活时停止
+			while let Some(Ok(chunk)) = body.next().await {
+				if sender.is_closed() {
+					return;
+				}
+				buffer.extend_from_slice(&chunk);
+				while let Some(end) = buffer.iter().position(|&byte| byte == b'\n') {
+					let line: Vec<u8> = buffer.drain(..=end).collect();
+					let line = String::from_utf8_lossy(&line);
+					let line = line.trim_end_matches(['\r', '\n']);
+					if let Some(value) = line.strip_prefix("event:") {
+						name = value.trim().to_string();
+					} else if let Some(data) = line.strip_prefix("data:") {
+						let Some(event) = watch_event(&name, data.trim()) else {
+							continue;
+						};
+						if sender.send(Ok(event)).await.is_err() {
+							return;
+						}
+					} else if line.is_empty() {
+						name.clear();
+					}
+				}
+			}
+		});
+		Ok(tonic::Response::new(ReceiverStream::new(receiver)))
+	}
+}
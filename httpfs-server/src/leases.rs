@@ -0,0 +1,406 @@
+use std::{
+	collections::{BTreeSet, HashMap},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use axum::{
+	extract::{Path as AxumPath, Query, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, Mutex as AsyncMutex, Notify};
+
+use crate::{
+	error::ApiError, events::ChangeEvent, upload::new_session_id, users::User, ServerState,
+	ShareAccess,
+};
+
+// 客户端会话与租约：客户端建立会话并定期发送心跳，对路径取得读租约或写租约。持有租约期间
+// 客户端可以一直使用缓存的属性，不必等缓存过期后再向服务器确认；另一个客户端请求冲突的租约
+// （读租约与其他客户端的写租约冲突，写租约与任何其他租约冲突）时服务器召回已有的租约，
+// 持有者丢弃缓存后归还。路径被不使用租约的客户端或直接在服务器上修改时同样召回
+
+// 会话在这段时间内没有心跳时结束，持有的租约随之失效
+const SESSION_TTL: Duration = Duration::from_secs(30);
+// 心跳最多等待这么久，期间有召回时立即返回
+const MAX_HEARTBEAT_WAIT: Duration = Duration::from_secs(20);
+// 召回的租约在这段时间内没有归还时强制收回，持有者在下一次心跳时得知
+const RECALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Default)]
+pub struct Leases {
+	sessions: Mutex<HashMap<String, Arc<Session>>>,
+	// 租约归还或会话结束时唤醒等待召回的请求
+	released: Notify,
+}
+
+struct Held {
+	// 客户端请求时给出的路径，召回时原样返回
+	path: String,
+	write: bool,
+}
+
+struct SessionState {
+	last_seen: Instant,
+	// 键为 lease_key
+	held: HashMap<String, Held>,
+	// 已召回、还没有通过心跳告知客户端的路径
+	recalls: BTreeSet<String>,
+}
+
+struct Session {
+	share: String,
+	state: Mutex<SessionState>,
+	// 有新的召回时唤醒进行中的心跳
+	recalled: Notify,
+	// 共享内的变化，心跳时检查是否涉及持有租约的路径
+	changes: AsyncMutex<broadcast::Receiver<ChangeEvent>>,
+}
+
+// Windows 客户端的路径不区分大小写，不同客户端可能以不同的大小写访问同一文件
+fn lease_key(path: &str) -> String {
+	let path = path.trim_matches('/');
+	if path == "$ROOT" || path == "." {
+		String::new()
+	} else {
+		path.to_lowercase()
+	}
+}
+
+fn parent_key(key: &str) -> &str {
+	key.rsplit_once('/').map_or("", |(parent, _)| parent)
+}
+
+fn session_not_found(id: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"session_not_found",
+		format!("client session '{}' does not exist or has expired", id),
+	)
+}
+
+impl Session {
+	fn alive(&self) -> bool {
+		self.state.lock().unwrap().last_seen.elapsed() < SESSION_TTL
+	}
+
+	fn touch(&self) {
+		self.state.lock().unwrap().last_seen = Instant::now();
+	}
+
+	fn has_recalls(&self) -> bool {
+		!self.state.lock().unwrap().recalls.is_empty()
+	}
+
+	// 请求持有者归还租约；revoke 时不再等待，直接收回
+	fn recall(&self, key: &str, revoke: bool) {
+		let mut state = self.state.lock().unwrap();
+		let Some(held) = state.held.get(key) else {
+			return;
+		};
+		let path = held.path.clone();
+		if revoke {
+			state.held.remove(key);
+		}
+		if state.recalls.insert(path) {
+			self.recalled.notify_one();
+		}
+	}
+
+	// 变化涉及持有租约的路径或其所在目录时收回租约；自己持有写租约的路径的变化来自自己
+	fn changed(&self, change: &ChangeEvent) {
+		if change.share() != self.share {
+			return;
+		}
+		for path in change.paths() {
+			let key = lease_key(path);
+			let own_write = self
+				.state
+				.lock()
+				.unwrap()
+				.held
+				.get(&key)
+				.is_some_and(|held| held.write);
+			if !own_write {
+				self.recall(&key, true);
+				self.recall(parent_key(&key), true);
+			}
+		}
+	}
+
+	// 丢失了部分变化时收回所有租约
+	fn recall_all(&self) {
+		let keys: Vec<String> = self.state.lock().unwrap().held.keys().cloned().collect();
+		for key in keys {
+			self.recall(&key, true);
+		}
+	}
+
+	fn drain_changes(&self, changes: &mut broadcast::Receiver<ChangeEvent>) {
+		loop {
+			match changes.try_recv() {
+				Ok(change) => self.changed(&change),
+				Err(broadcast::error::TryRecvError::Lagged(_)) => self.recall_all(),
+				Err(_) => break,
+			}
+		}
+	}
+}
+
+impl Leases {
+	fn open(&self, share: &str, changes: broadcast::Receiver<ChangeEvent>) -> String {
+		let id = new_session_id();
+		let session = Session {
+			share: share.to_string(),
+			state: Mutex::new(SessionState {
+				last_seen: Instant::now(),
+				held: HashMap::new(),
+				recalls: BTreeSet::new(),
+			}),
+			recalled: Notify::new(),
+			changes: AsyncMutex::new(changes),
+		};
+		self.prune();
+		self.sessions
+			.lock()
+			.unwrap()
+			.insert(id.clone(), Arc::new(session));
+		id
+	}
+
+	// 结束超时的会话，等待召回的请求不再等它们归还
+	fn prune(&self) {
+		let mut sessions = self.sessions.lock().unwrap();
+		let before = sessions.len();
+		sessions.retain(|_, session| session.alive());
+		if sessions.len() < before {
+			self.released.notify_waiters();
+		}
+	}
+
+	// 会话只能被创建它的共享访问
+	fn get(&self, id: &str, share: &str) -> Result<Arc<Session>, ApiError> {
+		self.prune();
+		self.sessions
+			.lock()
+			.unwrap()
+			.get(id)
+			.filter(|session| session.share == share)
+			.cloned()
+			.ok_or_else(|| session_not_found(id))
+	}
+
+	fn close(&self, id: &str) {
+		self.sessions.lock().unwrap().remove(id);
+		self.released.notify_waiters();
+	}
+
+	// 其他会话持有的与请求冲突的租约
+	fn conflicts(&self, session: &Arc<Session>, key: &str, write: bool) -> Vec<Arc<Session>> {
+		self.sessions
+			.lock()
+			.unwrap()
+			.values()
+			.filter(|other| !Arc::ptr_eq(other, session) && other.share == session.share)
+			.filter(|other| {
+				let state = other.state.lock().unwrap();
+				state.held.get(key).is_some_and(|held| write || held.write)
+			})
+			.cloned()
+			.collect()
+	}
+
+	// 召回冲突的租约并等待归还，超时后强制收回，然后授予租约
+	async fn acquire(&self, session: &Arc<Session>, path: &str, write: bool) {
+		let key = lease_key(path);
+		let deadline = tokio::time::Instant::now() + RECALL_TIMEOUT;
+		loop {
+			// 先登记等待再检查，检查之后的归还不会错过
+			let released = self.released.notified();
+			tokio::pin!(released);
+			released.as_mut().enable();
+			self.prune();
+			let conflicts = self.conflicts(session, &key, write);
+			let expired = tokio::time::Instant::now() >= deadline;
+			for other in &conflicts {
+				other.recall(&key, expired);
+			}
+			if conflicts.is_empty() || expired {
+				break;
+			}
+			let _ = tokio::time::timeout_at(deadline, released).await;
+		}
+		let mut state = session.state.lock().unwrap();
+		let write = write || state.held.get(&key).is_some_and(|held| held.write);
+		state.recalls.retain(|recalled| lease_key(recalled) != key);
+		state.held.insert(
+			key,
+			Held {
+				path: path.to_string(),
+				write,
+			},
+		);
+	}
+
+	fn release(&self, session: &Session, path: &str) {
+		let key = lease_key(path);
+		let mut state = session.state.lock().unwrap();
+		state.held.remove(&key);
+		state.recalls.retain(|recalled| lease_key(recalled) != key);
+		drop(state);
+		self.released.notify_waiters();
+	}
+}
+
+#[derive(Debug, Serialize)]
+pub struct SessionResponse {
+	session: String,
+	ttl_secs: u64,
+}
+
+// POST /session - 建立客户端会话，之后至少每 ttl_secs 发送一次心跳
+pub async fn open_session(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+) -> Json<SessionResponse> {
+	let session = state.leases.open(&share.name, state.events.subscribe());
+	Json(SessionResponse {
+		session,
+		ttl_secs: SESSION_TTL.as_secs(),
+	})
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeartbeatQuery {
+	// 没有召回时最多等待的秒数
+	#[serde(default)]
+	wait: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct HeartbeatResponse {
+	recalled: Vec<String>,
+}
+
+// POST /session/:session/heartbeat - 保持会话，返回被召回的租约的路径；带 wait 时等到有召回或超时，
+// 客户端可以一直保持一个心跳请求来及时得知召回
+pub async fn heartbeat(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Query(query): Query<HeartbeatQuery>,
+) -> Response {
+	let session = match state.leases.get(&params["session"], &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+	session.touch();
+	let wait = Duration::from_secs(query.wait).min(MAX_HEARTBEAT_WAIT);
+	{
+		let mut changes = session.changes.lock().await;
+		session.drain_changes(&mut changes);
+		let deadline = tokio::time::Instant::now() + wait;
+		let mut closing = state.shutdown.subscribe();
+		while !session.has_recalls() {
+			tokio::select! {
+				_ = session.recalled.notified() => {}
+				change = changes.recv() => match change {
+					Ok(change) => session.changed(&change),
+					Err(broadcast::error::RecvError::Lagged(_)) => session.recall_all(),
+					Err(broadcast::error::RecvError::Closed) => break,
+				},
+				_ = tokio::time::sleep_until(deadline) => break,
+				_ = closing.wait_for(|closing| *closing) => break,
+			}
+		}
+	}
+	session.touch();
+	let recalled = std::mem::take(&mut session.state.lock().unwrap().recalls);
+	Json(HeartbeatResponse {
+		recalled: recalled.into_iter().collect(),
+	})
+	.into_response()
+}
+
+// DELETE /session/:session - 结束会话，归还所有租约
+pub async fn close_session(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+) -> Response {
+	let id = &params["session"];
+	match state.leases.get(id, &share.name) {
+		Ok(_) => {
+			state.leases.close(id);
+			StatusCode::NO_CONTENT.into_response()
+		}
+		Err(error) => error.into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LeaseRequest {
+	session: String,
+	#[serde(default)]
+	write: bool,
+}
+
+#[derive(Debug, Serialize)]
+struct LeaseResponse {
+	path: String,
+	write: bool,
+}
+
+// POST /lease/*path - 取得路径的租约（JSON：session、write），有冲突的租约时等待持有者归还
+pub async fn acquire_lease(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Json(req): Json<LeaseRequest>,
+) -> Response {
+	let path = params.get("path").cloned().unwrap_or_default();
+	let checked = if req.write {
+		user.check_write(&path)
+	} else {
+		user.check_read(&path)
+	};
+	if let Err(error) = checked {
+		return error.into_response();
+	}
+	let session = match state.leases.get(&req.session, &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+	state.leases.acquire(&session, &path, req.write).await;
+	Json(LeaseResponse {
+		path,
+		write: req.write,
+	})
+	.into_response()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ReleaseQuery {
+	session: String,
+}
+
+// DELETE /lease/*path?session= - 归还租约，召回后客户端丢弃缓存再调用
+pub async fn release_lease(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Query(query): Query<ReleaseQuery>,
+) -> Response {
+	let path = params.get("path").cloned().unwrap_or_default();
+	match state.leases.get(&query.session, &share.name) {
+		Ok(session) => {
+			state.leases.release(&session, &path);
+			StatusCode::NO_CONTENT.into_response()
+		}
+		Err(error) => error.into_response(),
+	}
+}
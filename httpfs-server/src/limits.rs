@@ -0,0 +1,152 @@
+use std::{
+	collections::HashMap,
+	net::{IpAddr, Ipv4Addr, SocketAddr},
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use axum::{
+	body::Body,
+	extract::{ConnectInfo, Request, State},
+	http::{header, HeaderValue, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+use tokio_stream::StreamExt;
+
+use crate::{error::ApiError, ServerState};
+
+// 记录的客户端超过该数量时清理已经回满的令牌桶
+const CLEANUP_THRESHOLD: usize = 4096;
+
+// 每个客户端地址的请求速率：平均每秒 requests_per_second 个，最多连续 burst 个
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+	pub requests_per_second: f64,
+	pub burst: u32,
+}
+
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+impl Bucket {
+	fn refill(&mut self, limit: RateLimit, now: Instant) {
+		let elapsed = now.duration_since(self.updated).as_secs_f64();
+		self.tokens = (self.tokens + elapsed * limit.requests_per_second).min(limit.burst as f64);
+		self.updated = now;
+	}
+}
+
+// 按客户端地址的令牌桶，重新加载配置时保留
+#[derive(Default)]
+pub struct RateLimiter {
+	buckets: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+	// 取走一个令牌；令牌用完时返回需要等待的时间
+	pub fn acquire(&self, client: IpAddr, limit: RateLimit) -> Result<(), Duration> {
+		let now = Instant::now();
+		let mut buckets = self.buckets.lock().unwrap();
+		if buckets.len() >= CLEANUP_THRESHOLD {
+			buckets.retain(|_, bucket| {
+				bucket.refill(limit, now);
+				bucket.tokens < limit.burst as f64
+			});
+		}
+		let bucket = buckets.entry(client).or_insert(Bucket {
+			tokens: limit.burst as f64,
+			updated: now,
+		});
+		bucket.refill(limit, now);
+		if bucket.tokens >= 1.0 {
+			bucket.tokens -= 1.0;
+			Ok(())
+		} else {
+			Err(Duration::from_secs_f64(
+				(1.0 - bucket.tokens) / limit.requests_per_second,
+			))
+		}
+	}
+}
+
+// 限制每个客户端的请求速率，超出时返回 429 和 Retry-After
+pub async fn rate_limit(
+	State(state): State<Arc<ServerState>>,
+	request: Request,
+	next: Next,
+) -> Response {
+	let Some(limit) = state.settings().rate_limit else {
+		return next.run(request).await;
+	};
+	// 没有连接信息（例如测试中直接调用路由）时所有请求共用一个令牌桶
+	let client = request
+		.extensions()
+		.get::<ConnectInfo<SocketAddr>>()
+		.map_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED), |info| info.0.ip());
+	match state.limiter.acquire(client, limit) {
+		Ok(()) => next.run(request).await,
+		Err(wait) => {
+			let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+			let mut response = ApiError::new(
+				StatusCode::TOO_MANY_REQUESTS,
+				"rate_limited",
+				format!("too many requests, retry after {} s", retry_after),
+			)
+			.into_response();
+			response
+				.headers_mut()
+				.insert(header::RETRY_AFTER, HeaderValue::from(retry_after));
+			response
+		}
+	}
+}
+
+fn write_too_large(limit: usize) -> Response {
+	ApiError::new(
+		StatusCode::PAYLOAD_TOO_LARGE,
+		"write_too_large",
+		format!("a single write may not exceed {} bytes", limit),
+	)
+	.into_response()
+}
+
+// 限制单个 /write 请求体（解压后）的大小：声明的长度超出时不读取请求体直接返回 413
+pub async fn write_size(
+	State(state): State<Arc<ServerState>>,
+	request: Request,
+	next: Next,
+) -> Response {
+	let Some(limit) = state.settings().max_write_size else {
+		return next.run(request).await;
+	};
+	let declared = request
+		.headers()
+		.get(header::CONTENT_LENGTH)
+		.and_then(|value| value.to_str().ok())
+		.and_then(|value| value.parse::<u64>().ok());
+	if declared.is_some_and(|length| length > limit as u64) {
+		return write_too_large(limit);
+	}
+
+	// 分块传输或压缩上传的请求体边读边检查
+	let (parts, body) = request.into_parts();
+	let mut stream = body.into_data_stream();
+	let mut data = Vec::new();
+	while let Some(chunk) = stream.next().await {
+		let chunk = match chunk {
+			Ok(chunk) => chunk,
+			Err(e) => {
+				return ApiError::new(StatusCode::BAD_REQUEST, "bad_request", e.to_string())
+					.into_response()
+			}
+		};
+		if data.len() + chunk.len() > limit {
+			return write_too_large(limit);
+		}
+		data.extend_from_slice(&chunk);
+	}
+	next.run(Request::from_parts(parts, Body::from(data))).await
+}
@@ -0,0 +1,54 @@
+use std::{
+	collections::HashMap,
+	fs::File,
+	io,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, Weak},
+};
+
+use fs4::fs_std::FileExt;
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+// 记录的锁超过该数量时清理已经没有持有者的条目
+const CLEANUP_THRESHOLD: usize = 1024;
+
+// 按本地路径分配的异步锁：同一文件的写入、截断和上传提交依次执行，不同文件互不影响
+#[derive(Default)]
+pub struct PathLocks {
+	locks: Mutex<HashMap<PathBuf, Weak<AsyncMutex<()>>>>,
+}
+
+impl PathLocks {
+	pub async fn lock(&self, path: &Path) -> OwnedMutexGuard<()> {
+		let key = lock_key(path);
+		let lock = {
+			let mut locks = self.locks.lock().unwrap();
+			if locks.len() >= CLEANUP_THRESHOLD {
+				locks.retain(|_, lock| lock.strong_count() > 0);
+			}
+			match locks.get(&key).and_then(Weak::upgrade) {
+				Some(lock) => lock,
+				None => {
+					let lock = Arc::new(AsyncMutex::new(()));
+					locks.insert(key, Arc::downgrade(&lock));
+					lock
+				}
+			}
+		};
+		lock.lock_owned().await
+	}
+}
+
+// Windows 上的路径不区分大小写
+fn lock_key(path: &Path) -> PathBuf {
+	if cfg!(windows) {
+		PathBuf::from(path.to_string_lossy().to_lowercase())
+	} else {
+		path.to_path_buf()
+	}
+}
+
+// 对打开的文件加操作系统的排他锁，文件关闭时释放，用于与服务器上直接访问共享目录的其他程序协调
+pub fn lock_file(file: &File) -> io::Result<()> {
+	file.lock_exclusive()
+}
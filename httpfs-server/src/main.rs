@@ -0,0 +1,1452 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File, OpenOptions},
+	io::{Read, Seek, SeekFrom, Write},
+	net::SocketAddr,
+	path::{Path, PathBuf},
+	sync::{Arc, Mutex, RwLock},
+	time::Duration,
+};
+
+use axum::{
+	async_trait,
+	body::{Body, Bytes},
+	extract::{DefaultBodyLimit, FromRequestParts, Path as AxumPath, Query, State},
+	http::{header, request::Parts, HeaderMap, Method, StatusCode},
+	middleware,
+	response::{IntoResponse, Response},
+	routing::{delete, get, post, put},
+	Extension, Json, Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use notify::RecommendedWatcher;
+use serde::{Deserialize, Serialize};
+use tokio::{
+	net::TcpListener,
+	sync::{broadcast, watch},
+};
+use tokio_stream::StreamExt;
+use tokio_util::io::ReaderStream;
+use tower_http::decompression::RequestDecompressionLayer;
+
+mod access_log;
+mod atomic;
+mod audit;
+mod batch;
+mod capabilities;
+mod checksum;
+mod chunks;
+mod compression;
+mod conditional;
+mod config;
+mod delta;
+mod error;
+mod events;
+mod grpc;
+mod leases;
+mod limits;
+mod locks;
+mod merge;
+mod metrics;
+mod presign;
+mod quic;
+mod quota;
+mod retention;
+mod scan;
+mod search;
+mod share;
+mod shutdown;
+mod sparse;
+#[cfg(test)]
+mod tests;
+mod times;
+mod trash;
+mod upload;
+mod users;
+mod versions;
+mod xattr;
+
+use crate::{
+	atomic::{is_temp_name, write_atomic},
+	audit::{Action, Actor, AuditLog},
+	checksum::ChecksumCache,
+	compression::Compressible,
+	config::{Config, Reloader},
+	error::ApiError,
+	leases::Leases,
+	limits::{RateLimit, RateLimiter},
+	locks::PathLocks,
+	metrics::Metrics,
+	scan::{ScanCache, ScanSettings},
+	share::Share,
+	upload::UploadRegistry,
+	users::User,
+};
+
+// 重新加载配置文件时整体替换的设置，处理请求时取一份快照
+struct Settings {
+	shares: HashMap<String, Arc<Share>>,
+	default_share: String,
+	// 调用 /admin/reload 所需的令牌，未设置时该接口不可用
+	admin_token: Option<String>,
+	// 访问 /metrics 所需的令牌，未设置时无需认证
+	metrics_token: Option<String>,
+	// 按客户端的 Accept-Encoding 压缩 /read 和 /list 响应
+	compress_responses: bool,
+	// 设置后 /delete 把条目移入回收站，超过保留期限的条目被清除
+	trash_retention: Option<Duration>,
+	// 每个文件保留的历史版本数，0 表示不保留
+	max_versions: usize,
+	// 写入和截断期间对文件加操作系统的排他锁
+	advisory_locks: bool,
+	// 每个客户端地址的请求速率上限
+	rate_limit: Option<RateLimit>,
+	// 单个 /write 请求体的大小上限（字节）
+	max_write_size: Option<usize>,
+	// 写入和读取前的内容扫描
+	scan: Option<ScanSettings>,
+}
+
+impl Settings {
+	fn new(shares: Vec<Share>, default_share: String) -> Self {
+		Self {
+			shares: shares
+				.into_iter()
+				.map(|s| (s.name.clone(), Arc::new(s)))
+				.collect(),
+			default_share,
+			admin_token: None,
+			metrics_token: None,
+			compress_responses: true,
+			trash_retention: None,
+			max_versions: 0,
+			advisory_locks: false,
+			rate_limit: None,
+			max_write_size: None,
+			scan: None,
+		}
+	}
+
+	// 根据 URL 前缀或认证身份确定请求访问的共享
+	fn resolve_share(
+		&self,
+		name: Option<&str>,
+		token: Option<&str>,
+	) -> Result<Arc<Share>, ApiError> {
+		if let Some(name) = name {
+			let share = self.shares.get(name).ok_or_else(|| share_not_found(name))?;
+			if !share.authorize(token) {
+				return Err(unauthorized(name));
+			}
+			return Ok(share.clone());
+		}
+
+		if let Some(token) = token {
+			if let Some(share) = self
+				.shares
+				.values()
+				.find(|s| s.token.as_deref() == Some(token))
+			{
+				return Ok(share.clone());
+			}
+		}
+
+		let share = self
+			.shares
+			.get(&self.default_share)
+			.ok_or_else(|| share_not_found(&self.default_share))?;
+		if !share.authorize(token) {
+			return Err(unauthorized(&self.default_share));
+		}
+		Ok(share.clone())
+	}
+}
+
+struct ServerState {
+	settings: RwLock<Arc<Settings>>,
+	uploads: UploadRegistry,
+	checksums: ChecksumCache,
+	// 读取前扫描的结果
+	scans: ScanCache,
+	// 同一文件的写入、截断和上传提交依次执行
+	locks: PathLocks,
+	limiter: RateLimiter,
+	metrics: Metrics,
+	// 共享内文件变化的广播，/events 的订阅者从中接收
+	events: broadcast::Sender<events::ChangeEvent>,
+	// 各共享根目录的监视器，重新加载配置时随共享一起替换
+	watchers: Mutex<Vec<RecommendedWatcher>>,
+	// 从配置文件启动时用于重新加载配置
+	reloader: Option<Reloader>,
+	// 开始关闭时置为 true，/events 等长连接据此结束
+	shutdown: watch::Sender<bool>,
+	// 管理员预告的关闭
+	notice: watch::Sender<Option<shutdown::ShutdownNotice>>,
+	// 修改操作的审计日志，只在启动时打开
+	audit: AuditLog,
+	// 客户端会话和它们持有的租约
+	leases: Leases,
+}
+
+impl ServerState {
+	fn new(settings: Settings) -> Self {
+		Self {
+			settings: RwLock::new(Arc::new(settings)),
+			uploads: UploadRegistry::default(),
+			checksums: ChecksumCache::default(),
+			scans: ScanCache::default(),
+			locks: PathLocks::default(),
+			limiter: RateLimiter::default(),
+			metrics: Metrics::default(),
+			events: events::channel(),
+			watchers: Mutex::new(Vec::new()),
+			reloader: None,
+			shutdown: watch::channel(false).0,
+			notice: watch::channel(None).0,
+			audit: AuditLog::default(),
+			leases: Leases::default(),
+		}
+	}
+
+	fn settings(&self) -> Arc<Settings> {
+		self.settings.read().unwrap().clone()
+	}
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<&str> {
+	headers
+		.get(header::AUTHORIZATION)
+		.and_then(|v| v.to_str().ok())
+		.and_then(|v| v.strip_prefix("Bearer "))
+}
+
+fn share_not_found(name: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"share_not_found",
+		format!("share '{}' does not exist", name),
+	)
+}
+
+fn unauthorized(name: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::UNAUTHORIZED,
+		"unauthorized",
+		format!("missing or invalid token for share '{}'", name),
+	)
+}
+
+async fn path_params(
+	parts: &mut Parts,
+	state: &Arc<ServerState>,
+) -> Result<HashMap<String, String>, Response> {
+	let AxumPath(params) = AxumPath::<HashMap<String, String>>::from_request_parts(parts, state)
+		.await
+		.map_err(IntoResponse::into_response)?;
+	Ok(params)
+}
+
+// 请求访问的共享（已通过认证）
+struct ShareAccess(Arc<Share>);
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for ShareAccess {
+	type Rejection = Response;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &Arc<ServerState>,
+	) -> Result<Self, Self::Rejection> {
+		let params = path_params(parts, state).await?;
+		let share = state
+			.settings()
+			.resolve_share(
+				params.get("share").map(String::as_str),
+				bearer_token(&parts.headers),
+			)
+			.map_err(IntoResponse::into_response)?;
+		Ok(ShareAccess(share))
+	}
+}
+
+// 请求目标：解析出的共享、共享内的相对路径以及对应的本地路径
+struct Target {
+	share: Arc<Share>,
+	path: String,
+	real_path: PathBuf,
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for Target {
+	type Rejection = Response;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &Arc<ServerState>,
+	) -> Result<Self, Self::Rejection> {
+		let ShareAccess(share) = ShareAccess::from_request_parts(parts, state).await?;
+		let params = path_params(parts, state).await?;
+		let path = params.get("path").cloned().unwrap_or_default();
+		// 按共享中配置的用户权限检查：GET 和 HEAD 是读取，其余方法修改路径
+		let user = User::from_request_parts(parts, state).await?;
+		let checked = match parts.method {
+			Method::GET | Method::HEAD => user.check_read(&path),
+			_ => user.check_write(&path),
+		};
+		checked.map_err(IntoResponse::into_response)?;
+		let real_path = share
+			.get_real_path(&path)
+			.map_err(|status| invalid_path(status, &path).into_response())?;
+		Ok(Target {
+			share,
+			path,
+			real_path,
+		})
+	}
+}
+
+// 路径越出共享根目录时返回 403，名称无法保存在服务器的文件系统中时返回 400，共享根目录本身不可用时返回 404
+fn invalid_path(status: StatusCode, path: &str) -> ApiError {
+	match status {
+		StatusCode::FORBIDDEN => ApiError::new(
+			status,
+			"outside_share",
+			format!("path '{}' resolves outside the share", path),
+		),
+		StatusCode::BAD_REQUEST => ApiError::new(
+			status,
+			"invalid_name",
+			format!("path '{}' contains a name the server cannot store", path),
+		),
+		_ => ApiError::from(status),
+	}
+}
+
+// 服务器内部使用的文件（原子写入临时文件、扩展属性文件、回收站、隔离区、历史版本、上传会话记录），不对客户端展示
+fn is_internal_name(name: &str) -> bool {
+	is_temp_name(name)
+		|| xattr::is_sidecar_name(name)
+		|| trash::is_trash_name(name)
+		|| scan::is_quarantine_name(name)
+		|| versions::is_versions_name(name)
+		|| upload::is_journal_name(name)
+		|| chunks::is_chunks_name(name)
+}
+
+fn lock_if_enabled(state: &ServerState, file: File) -> std::io::Result<File> {
+	if state.settings().advisory_locks {
+		locks::lock_file(&file)?;
+	}
+	Ok(file)
+}
+
+// 文件内容被覆盖前保存历史版本；保存失败不影响本次写入
+fn save_version(state: &ServerState, path: &Path, root: &Path) {
+	if let Err(e) = versions::snapshot(path, root, state.settings().max_versions) {
+		eprintln!("[SERVER] saving version of {:?} failed: {:?}", path, e);
+	}
+}
+
+// 替换文件内容后恢复原来的创建时间；新文件记录创建时间
+fn keep_created(path: &Path, root: &Path, created: Option<u64>) {
+	let result = match created {
+		Some(created) => times::keep_created(path, root, created),
+		None => {
+			times::record_created(path, root);
+			Ok(())
+		}
+	};
+	if let Err(e) = result {
+		eprintln!(
+			"[SERVER] keeping creation time of {:?} failed: {:?}",
+			path, e
+		);
+	}
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct FileInfo {
+	name: String,
+	is_directory: bool,
+	size: u64,
+	created: u64,
+	modified: u64,
+	accessed: u64,
+	// 稀疏文件实际占用的存储，只在小于 size 时给出
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	allocated_size: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ListQuery {
+	// 上一页最后一个条目的名称，从它之后继续列出
+	cursor: Option<String>,
+	limit: Option<usize>,
+	// 只返回能够打开的条目
+	accessible: Option<bool>,
+}
+
+// 分页列目录的响应：next_cursor 为空表示已经是最后一页
+#[derive(Debug, Serialize)]
+struct ListPage {
+	items: Vec<FileInfo>,
+	next_cursor: Option<String>,
+}
+
+// 单页最多返回的条目数
+const MAX_LIST_LIMIT: usize = 10_000;
+
+#[derive(Debug, Deserialize)]
+struct ReadQuery {
+	offset: Option<u64>,
+	length: Option<usize>,
+	// 读取指定的历史版本
+	version: Option<String>,
+	// 客户端可以跟随重定向直接从对象存储读取
+	redirect: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WriteQuery {
+	offset: Option<u64>,
+	append: Option<bool>,
+	// 原子模式：请求体是文件的完整内容，写入临时文件后 rename 替换目标文件
+	atomic: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CreateQuery {
+	is_directory: Option<bool>,
+}
+
+// GET /info/:path - 获取文件/目录信息
+async fn get_info(target: Target, headers: HeaderMap) -> Response {
+	eprintln!("[SERVER] get_info: path='{}'", target.path);
+	let real_path = target.real_path;
+	eprintln!("[SERVER] get_info: real_path={:?}", real_path);
+
+	let metadata = match fs::metadata(&real_path) {
+		Ok(metadata) => metadata,
+		Err(e) => {
+			eprintln!("[SERVER] get_info: failed: {:?}", e);
+			return ApiError::io("stat failed", &e).into_response();
+		}
+	};
+	if conditional::is_not_modified(&headers, &metadata) {
+		return conditional::not_modified(&metadata);
+	}
+
+	match target.share.path_to_file_info(&real_path) {
+		Ok(info) => {
+			eprintln!(
+				"[SERVER] get_info: success, is_directory={}",
+				info.is_directory
+			);
+			conditional::with_validators(&metadata, Json(info))
+		}
+		Err(e) => {
+			eprintln!("[SERVER] get_info: failed: {:?}", e);
+			ApiError::io("stat failed", &e).into_response()
+		}
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct StatBatchRequest {
+	paths: Vec<String>,
+}
+
+// 批量查询中一个路径的结果：成功时带有文件信息，失败时带有与单独请求 /info 相同的错误体
+#[derive(Debug, Serialize)]
+struct StatResult {
+	path: String,
+	#[serde(flatten)]
+	info: Option<FileInfo>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	error: Option<ApiError>,
+}
+
+// 单次批量查询最多包含的路径数
+const MAX_STAT_BATCH: usize = 1000;
+
+// POST /stat_batch - 一次查询多个路径的文件信息，结果按请求中的顺序返回
+async fn stat_batch(
+	ShareAccess(share): ShareAccess,
+	user: User,
+	Json(request): Json<StatBatchRequest>,
+) -> Response {
+	if request.paths.len() > MAX_STAT_BATCH {
+		return ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"invalid_input",
+			format!("at most {} paths per request", MAX_STAT_BATCH),
+		)
+		.into_response();
+	}
+	let results: Vec<StatResult> = request
+		.paths
+		.into_iter()
+		.map(|path| {
+			let info = user
+				.check_read(&path)
+				.and_then(|()| {
+					share
+						.get_real_path(&path)
+						.map_err(|status| invalid_path(status, &path))
+				})
+				.and_then(|real_path| {
+					share
+						.path_to_file_info(&real_path)
+						.map_err(|e| ApiError::io("stat failed", &e))
+				});
+			match info {
+				Ok(info) => StatResult {
+					path,
+					info: Some(info),
+					error: None,
+				},
+				Err(error) => StatResult {
+					path,
+					info: None,
+					error: Some(error),
+				},
+			}
+		})
+		.collect();
+	(Extension(Compressible), Json(results)).into_response()
+}
+
+// GET /list/:path - 列出目录内容
+async fn list_directory(
+	target: Target,
+	user: User,
+	Query(query): Query<ListQuery>,
+) -> Response {
+	eprintln!("[SERVER] list_directory: path='{}', ", target.path);
+	let real_path = target.real_path;
+	eprintln!("[SERVER] list_directory: real_path={:?}", real_path);
+
+	if !real_path.exists() {
+		eprintln!("[SERVER] list_directory: path does not exist");
+		return not_found(&target.path);
+	}
+
+	if !real_path.is_dir() {
+		eprintln!("[SERVER] list_directory: path is not a directory");
+		return ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"not_a_directory",
+			format!("'{}' is not a directory", target.path),
+		)
+		.into_response();
+	}
+
+	let entries = match fs::read_dir(&real_path) {
+		Ok(entries) => entries,
+		Err(e) => {
+			eprintln!("[SERVER] list_directory: read_dir failed: {:?}", e);
+			return ApiError::io("read_dir failed", &e).into_response();
+		}
+	};
+	// 先只收集名称，分页时只对当前页的条目读取元数据
+	let mut names: Vec<String> = entries
+		.flatten()
+		.map(|entry| entry.file_name().to_string_lossy().into_owned())
+		// 隐藏未完成的原子写入临时文件
+		.filter(|name| !is_internal_name(name))
+		// 请求代表的用户只看到自己可以访问的条目
+		.filter(|name| user.can_see(&users::child_path(&target.path, name)))
+		.collect();
+	let accessible_only = query.accessible.unwrap_or(false);
+
+	let Some(limit) = query.limit else {
+		// 未指定 limit 时保持旧行为，一次返回全部条目
+		let items: Vec<FileInfo> = names
+			.iter()
+			.filter(|name| !accessible_only || target.share.is_accessible(&real_path.join(name)))
+			.filter_map(|name| target.share.path_to_file_info(&real_path.join(name)).ok())
+			.collect();
+		eprintln!("[SERVER] list_directory: returning {} items", items.len());
+		return (Extension(Compressible), Json(items)).into_response();
+	};
+	let limit = limit.clamp(1, MAX_LIST_LIMIT);
+
+	// 按名称排序分页，游标是上一页最后的名称，目录在翻页期间变化也不会重复或遗漏未变化的条目
+	if let Some(cursor) = &query.cursor {
+		names.retain(|name| name > cursor);
+	}
+	let has_more = names.len() > limit;
+	if has_more {
+		names.select_nth_unstable(limit);
+		names.truncate(limit);
+	}
+	names.sort_unstable();
+
+	let items: Vec<FileInfo> = names
+		.iter()
+		.filter(|name| !accessible_only || target.share.is_accessible(&real_path.join(name)))
+		.filter_map(|name| target.share.path_to_file_info(&real_path.join(name)).ok())
+		.collect();
+	eprintln!("[SERVER] list_directory: returning {} items", items.len());
+	(
+		Extension(Compressible),
+		Json(ListPage {
+			items,
+			next_cursor: if has_more { names.pop() } else { None },
+		}),
+	)
+		.into_response()
+}
+
+// 文件的偏移和大小不能超过 i64::MAX：系统调用以有符号的 off_t 和 LARGE_INTEGER 表示
+const MAX_FILE_SIZE: u64 = i64::MAX as u64;
+
+// [offset, offset + length) 的结束位置，超出文件大小上限时返回 400
+fn range_end(offset: u64, length: u64) -> Result<u64, ApiError> {
+	offset
+		.checked_add(length)
+		.filter(|&end| end <= MAX_FILE_SIZE)
+		.ok_or_else(|| {
+			ApiError::new(
+				StatusCode::BAD_REQUEST,
+				"invalid_input",
+				"the range is too large",
+			)
+		})
+}
+
+// 从文件的当前位置起发送 length 字节，发送的字节数在发出时计入统计
+fn stream_file(state: Arc<ServerState>, file: File, length: u64) -> Body {
+	let reader = tokio::io::AsyncReadExt::take(tokio::fs::File::from_std(file), length);
+	let chunks = ReaderStream::new(reader).map(move |chunk| {
+		if let Ok(bytes) = &chunk {
+			state.metrics.add_read(bytes.len());
+		}
+		chunk
+	});
+	Body::from_stream(chunks)
+}
+
+// GET /read/:path - 读取文件内容
+async fn read_file(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	headers: HeaderMap,
+	Query(query): Query<ReadQuery>,
+) -> Response {
+	let real_path = match &query.version {
+		Some(id) => match versions::version_path(&target.real_path, &target.share.root_path, id) {
+			Ok(path) => path,
+			Err(error) => return error.into_response(),
+		},
+		None => target.real_path.clone(),
+	};
+	match File::open(&real_path) {
+		Ok(mut file) => {
+			let metadata = match file.metadata() {
+				Ok(metadata) => metadata,
+				Err(e) => return ApiError::io("stat failed", &e).into_response(),
+			};
+			if conditional::is_not_modified(&headers, &metadata) {
+				return conditional::not_modified(&metadata);
+			}
+			if let Err(error) =
+				scan::check_read(&state, &target.share, &target.path, &real_path, &metadata)
+			{
+				return error.into_response();
+			}
+			// 当前内容足够大时交给对象存储，历史版本只保存在本服务器
+			if let Some(direct) = &target.share.direct_reads {
+				if query.redirect.unwrap_or(false)
+					&& query.version.is_none()
+					&& metadata.is_file()
+					&& metadata.len() >= direct.min_size
+				{
+					if let Some(response) = direct.redirect(&target.path) {
+						return response;
+					}
+				}
+			}
+
+			let offset = query.offset.unwrap_or(0);
+			// 未指定长度时读到文件末尾
+			let remaining = metadata.len().saturating_sub(offset);
+			let length = query
+				.length
+				.map_or(remaining, |length| (length as u64).min(remaining));
+
+			if offset > 0 {
+				if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+					return ApiError::io("seek failed", &e).into_response();
+				}
+			}
+
+			// 不超过请求体上限的区间整体读入内存；更大的区间（如不带 length 读取大文件）边读边发送，
+			// 内存占用不随区间长度增长
+			let content = if length <= MAX_BODY_SIZE as u64 {
+				let mut buffer = Vec::with_capacity(length as usize);
+				match file.take(length).read_to_end(&mut buffer) {
+					Ok(n) => state.metrics.add_read(n),
+					Err(e) => return ApiError::io("read failed", &e).into_response(),
+				}
+				Bytes::from(buffer).into_response()
+			} else {
+				(
+					[(header::CONTENT_TYPE, "application/octet-stream")],
+					stream_file(state.clone(), file, length),
+				)
+					.into_response()
+			};
+			let mut response = conditional::with_validators(&metadata, content);
+			if !compression::is_precompressed(&target.real_path) {
+				response.extensions_mut().insert(Compressible);
+			}
+			response
+		}
+		Err(e) => ApiError::io("open failed", &e).into_response(),
+	}
+}
+
+// POST /write/:path - 写入文件内容（atomic=true 时以完整内容原子替换文件）
+// 写操作成功后返回新的 ETag，客户端可以直接用于后续的 If-Match
+fn written(path: &Path) -> Response {
+	match fs::metadata(path) {
+		Ok(metadata) => conditional::with_validators(&metadata, StatusCode::OK),
+		Err(_) => StatusCode::OK.into_response(),
+	}
+}
+
+async fn write_file(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	headers: HeaderMap,
+	Query(query): Query<WriteQuery>,
+	body: Bytes,
+) -> Response {
+	let real_path = target.real_path;
+	let _guard = state.locks.lock(&real_path).await;
+	let metadata = fs::metadata(&real_path).ok();
+
+	if let Err(error) = conditional::check_if_match(&headers, metadata.as_ref()) {
+		return error.into_response();
+	}
+	if let Err(error) = retention::check(&target.share, &target.path, &real_path) {
+		return error.into_response();
+	}
+
+	let old_size = metadata.as_ref().map_or(0, |m| m.len());
+	let append = query.append.unwrap_or(false);
+	let offset = query.offset.unwrap_or(0);
+
+	if query.atomic.unwrap_or(false) {
+		if real_path.is_dir() {
+			return is_a_directory(&target.path);
+		}
+		if let Err(error) = quota::check(&target.share, old_size, body.len() as u64) {
+			return error.into_response();
+		}
+		if let Err(error) = scan::check_content(&state, &target.share, &target.path, &body) {
+			return error.into_response();
+		}
+		save_version(&state, &real_path, &target.share.root_path);
+		// 重命名替换会带来新的创建时间，替换后恢复原文件的创建时间
+		let created = times::created_before_replace(&real_path, &target.share.root_path);
+		return match write_atomic(&real_path, &body) {
+			Ok(_) => {
+				keep_created(&real_path, &target.share.root_path, created);
+				retention::commit(&target.share, &real_path);
+				state.metrics.add_written(body.len());
+				state.audit.record(&actor, Action::Write, &target.path, None);
+				written(&real_path)
+			}
+			Err(e) => {
+				eprintln!("[SERVER] write_file: atomic write failed: {:?}", e);
+				ApiError::io("atomic write failed", &e).into_response()
+			}
+		};
+	}
+
+	// 写入后文件的大小：追加时在末尾增长，否则只有写到原末尾之后的部分才增加用量
+	let end = match range_end(if append { old_size } else { offset }, body.len() as u64) {
+		Ok(end) => end,
+		Err(error) => return error.into_response(),
+	};
+	let new_size = old_size.max(end);
+	if let Err(error) = quota::check(&target.share, old_size, new_size) {
+		return error.into_response();
+	}
+	// 只有覆盖已有内容的写入才保存版本，追加和扩展不会丢失数据
+	if !append && offset < old_size {
+		save_version(&state, &real_path, &target.share.root_path);
+	}
+
+	let mut opts = OpenOptions::new();
+	opts.write(true);
+
+	if append {
+		opts.append(true);
+	} else {
+		opts.create(true);
+	}
+
+	match opts
+		.open(&real_path)
+		.and_then(|file| lock_if_enabled(&state, file))
+	{
+		Ok(mut file) => {
+			if offset > 0 && !append {
+				if let Err(e) = file.seek(SeekFrom::Start(offset)) {
+					return ApiError::io("seek failed", &e).into_response();
+				}
+			}
+
+			match file.write_all(&body) {
+				Ok(_) => {
+					if metadata.is_none() {
+						times::record_created(&real_path, &target.share.root_path);
+					}
+					state.metrics.add_written(body.len());
+					state.audit.record(&actor, Action::Write, &target.path, None);
+					written(&real_path)
+				}
+				Err(e) => ApiError::io("write failed", &e).into_response(),
+			}
+		}
+		Err(e) => ApiError::io("open failed", &e).into_response(),
+	}
+}
+
+// PUT /create/:path - 创建文件或目录
+async fn create_file(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	Query(query): Query<CreateQuery>,
+) -> Response {
+	let real_path = target.real_path;
+	let is_directory = query.is_directory.unwrap_or(false);
+
+	if real_path.exists() {
+		return already_exists(&target.path);
+	}
+
+	if let Err(error) = quota::check_room(&target.share) {
+		return error.into_response();
+	}
+
+	let result = if is_directory {
+		fs::create_dir_all(&real_path).map_err(|e| ApiError::io("create_dir failed", &e))
+	} else {
+		// Create parent directories if needed
+		if let Some(parent) = real_path.parent() {
+			let _ = fs::create_dir_all(parent);
+		}
+
+		File::create(&real_path)
+			.map(drop)
+			.map_err(|e| ApiError::io("create failed", &e))
+	};
+	match result {
+		Ok(_) => {
+			times::record_created(&real_path, &target.share.root_path);
+			let action = if is_directory {
+				Action::Mkdir
+			} else {
+				Action::Create
+			};
+			state.audit.record(&actor, action, &target.path, None);
+			StatusCode::CREATED.into_response()
+		}
+		Err(error) => error.into_response(),
+	}
+}
+
+// DELETE /delete/:path - 删除文件或目录
+#[derive(Debug, Deserialize)]
+struct DeleteQuery {
+	// 递归删除非空目录；否则删除非空目录返回 409
+	recursive: Option<bool>,
+	// 只检查能否删除，不实际删除
+	dry_run: Option<bool>,
+}
+
+async fn delete_path(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	headers: HeaderMap,
+	Query(query): Query<DeleteQuery>,
+) -> Response {
+	let real_path = target.real_path;
+
+	// 共享根目录本身不能被删除
+	if real_path == target.share.root_path {
+		return share_root();
+	}
+
+	let metadata = match fs::symlink_metadata(&real_path) {
+		Ok(metadata) => metadata,
+		Err(e) => return ApiError::io("stat failed", &e).into_response(),
+	};
+	if let Err(error) = conditional::check_if_match(&headers, Some(&metadata)) {
+		return error.into_response();
+	}
+	if let Err(error) = retention::check_tree(&target.share, &target.path, &real_path) {
+		return error.into_response();
+	}
+	let recursive = query.recursive.unwrap_or(false);
+
+	if metadata.is_dir() && !recursive {
+		match fs::read_dir(&real_path) {
+			Ok(entries) => {
+				let mut entries = entries.flatten();
+				if entries.any(|entry| !is_internal_name(&entry.file_name().to_string_lossy())) {
+					return directory_not_empty(&target.path);
+				}
+			}
+			Err(e) => return ApiError::io("read_dir failed", &e).into_response(),
+		}
+	}
+
+	if query.dry_run.unwrap_or(false) {
+		return StatusCode::OK.into_response();
+	}
+
+	let root = &target.share.root_path;
+	if let Some(retention) = state.settings().trash_retention {
+		trash::purge(root, retention);
+		return match trash::move_to_trash(&real_path, root) {
+			Ok(_) => {
+				state.audit.record(&actor, Action::Delete, &target.path, None);
+				StatusCode::OK.into_response()
+			}
+			Err(e) => ApiError::io("moving to trash failed", &e).into_response(),
+		};
+	}
+
+	let result = if !metadata.is_dir() {
+		fs::remove_file(&real_path)
+	} else if recursive {
+		fs::remove_dir_all(&real_path)
+	} else {
+		// 目录中只剩下残留的内部文件时先清理掉
+		if let Ok(entries) = fs::read_dir(&real_path) {
+			for entry in entries.flatten() {
+				if is_internal_name(&entry.file_name().to_string_lossy()) {
+					let _ = fs::remove_file(entry.path());
+				}
+			}
+		}
+		fs::remove_dir(&real_path)
+	};
+
+	match result {
+		Ok(_) => {
+			xattr::remove_for(&real_path, root);
+			state.audit.record(&actor, Action::Delete, &target.path, None);
+			StatusCode::OK.into_response()
+		}
+		// 检查之后目录里又出现了新文件时 io::Error 同样映射为 directory_not_empty
+		Err(e) => ApiError::io("delete failed", &e).into_response(),
+	}
+}
+
+// POST /move/:path - 移动/重命名文件或目录
+#[derive(Debug, Deserialize)]
+struct MoveRequest {
+	new_path: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveQuery {
+	// 目标已存在时覆盖：文件直接替换，目录只能替换空目录
+	replace: Option<bool>,
+	// 目标是已存在的目录时把源目录内容合并进去
+	merge: Option<bool>,
+}
+
+async fn move_path(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	user: User,
+	actor: Actor,
+	Query(query): Query<MoveQuery>,
+	Json(req): Json<MoveRequest>,
+) -> Response {
+	if let Err(error) = user.check_write(&req.new_path) {
+		return error.into_response();
+	}
+	let old_path = target.real_path;
+	let new_path = match target.share.get_real_path(&req.new_path) {
+		Ok(path) => path,
+		Err(status) => return invalid_path(status, &req.new_path).into_response(),
+	};
+
+	let old_meta = match fs::symlink_metadata(&old_path) {
+		Ok(metadata) => metadata,
+		Err(e) => return ApiError::io("stat failed", &e).into_response(),
+	};
+	if old_path == target.share.root_path || new_path == target.share.root_path {
+		return share_root();
+	}
+	if new_path == old_path {
+		return StatusCode::OK.into_response();
+	}
+	// 保留期限内的文件不能移走，也不能被替换
+	let retained = retention::check_tree(&target.share, &target.path, &old_path)
+		.and_then(|()| retention::check(&target.share, &req.new_path, &new_path));
+	if let Err(error) = retained {
+		return error.into_response();
+	}
+	// 不能把目录移动到它自己的子目录中
+	if old_meta.is_dir() && new_path.starts_with(&old_path) {
+		return ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"move_into_self",
+			format!("cannot move '{}' into itself", target.path),
+		)
+		.into_response();
+	}
+	if !new_path.parent().is_some_and(|parent| parent.is_dir()) {
+		return ApiError::new(
+			StatusCode::NOT_FOUND,
+			"parent_not_found",
+			format!("parent directory of '{}' does not exist", req.new_path),
+		)
+		.into_response();
+	}
+
+	let replace = query.replace.unwrap_or(false);
+	if let Ok(new_meta) = fs::symlink_metadata(&new_path) {
+		if query.merge.unwrap_or(false) && old_meta.is_dir() && new_meta.is_dir() {
+			return match merge::has_conflicts(&old_path, &new_path, replace) {
+				Ok(true) => ApiError::new(
+					StatusCode::CONFLICT,
+					"merge_conflict",
+					format!(
+						"merging into '{}' would overwrite existing entries",
+						req.new_path
+					),
+				)
+				.into_response(),
+				Ok(false) => match merge::merge_dir(&old_path, &new_path) {
+					Ok(_) => {
+						// 合并后保留目标目录自身的属性
+						xattr::remove_for(&old_path, &target.share.root_path);
+						state.audit.record(
+							&actor,
+							Action::Rename,
+							&target.path,
+							Some(&req.new_path),
+						);
+						StatusCode::OK.into_response()
+					}
+					Err(e) => {
+						eprintln!("[SERVER] move_path: merge failed: {:?}", e);
+						ApiError::io("merge failed", &e).into_response()
+					}
+				},
+				Err(e) => ApiError::io("merge check failed", &e).into_response(),
+			};
+		}
+		if !replace {
+			return already_exists(&req.new_path);
+		}
+		if old_meta.is_dir() != new_meta.is_dir() {
+			return ApiError::new(
+				StatusCode::CONFLICT,
+				"type_mismatch",
+				format!(
+					"cannot replace '{}' with a different kind of entry",
+					req.new_path
+				),
+			)
+			.into_response();
+		}
+		if new_meta.is_dir() {
+			// 只有空目录可以被替换
+			if let Err(e) = fs::remove_dir(&new_path) {
+				return ApiError::io("replace failed", &e).into_response();
+			}
+		}
+	}
+
+	match fs::rename(&old_path, &new_path) {
+		Ok(_) => {
+			xattr::move_for(&old_path, &new_path, &target.share.root_path);
+			versions::move_for(&old_path, &new_path, &target.share.root_path);
+			state.audit.record(&actor, Action::Rename, &target.path, Some(&req.new_path));
+			StatusCode::OK.into_response()
+		}
+		Err(e) => ApiError::io("rename failed", &e).into_response(),
+	}
+}
+
+// POST /truncate/:path - 设置文件大小
+#[derive(Debug, Deserialize)]
+struct TruncateRequest {
+	size: u64,
+}
+
+async fn truncate_file(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	headers: HeaderMap,
+	Json(req): Json<TruncateRequest>,
+) -> Response {
+	let real_path = target.real_path;
+	let _guard = state.locks.lock(&real_path).await;
+	let metadata = fs::metadata(&real_path).ok();
+
+	if let Err(error) = conditional::check_if_match(&headers, metadata.as_ref()) {
+		return error.into_response();
+	}
+	if let Err(error) = retention::check(&target.share, &target.path, &real_path) {
+		return error.into_response();
+	}
+
+	if let Err(error) = range_end(req.size, 0) {
+		return error.into_response();
+	}
+	let old_size = metadata.as_ref().map_or(0, |m| m.len());
+	if let Err(error) = quota::check(&target.share, old_size, req.size) {
+		return error.into_response();
+	}
+	if metadata.is_some() && req.size != old_size {
+		save_version(&state, &real_path, &target.share.root_path);
+	}
+
+	let file = OpenOptions::new()
+		.write(true)
+		.open(&real_path)
+		.and_then(|file| lock_if_enabled(&state, file));
+	match file {
+		Ok(file) => match file.set_len(req.size) {
+			Ok(_) => {
+				state.audit.record(&actor, Action::Truncate, &target.path, None);
+				written(&real_path)
+			}
+			Err(e) => ApiError::io("set_len failed", &e).into_response(),
+		},
+		Err(e) => ApiError::io("open failed", &e).into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+struct ZeroRequest {
+	offset: u64,
+	length: u64,
+}
+
+// POST /zero/:path - 把文件的一段置为 0，超出末尾时扩展文件；
+// 文件系统支持稀疏文件时这段不再占用存储
+async fn zero_range(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	headers: HeaderMap,
+	Json(req): Json<ZeroRequest>,
+) -> Response {
+	let real_path = target.real_path;
+	let _guard = state.locks.lock(&real_path).await;
+	let metadata = match fs::metadata(&real_path) {
+		Ok(metadata) => metadata,
+		Err(e) => return ApiError::io("stat failed", &e).into_response(),
+	};
+
+	if let Err(error) = conditional::check_if_match(&headers, Some(&metadata)) {
+		return error.into_response();
+	}
+	if let Err(error) = retention::check(&target.share, &target.path, &real_path) {
+		return error.into_response();
+	}
+
+	let end = match range_end(req.offset, req.length) {
+		Ok(end) => end,
+		Err(error) => return error.into_response(),
+	};
+	// 与截断相同按长度检查配额；空洞实际不占用存储，用量按占用的存储统计
+	if let Err(error) = quota::check(&target.share, metadata.len(), end) {
+		return error.into_response();
+	}
+	if req.length > 0 {
+		save_version(&state, &real_path, &target.share.root_path);
+	}
+
+	let file = OpenOptions::new()
+		.write(true)
+		.open(&real_path)
+		.and_then(|file| lock_if_enabled(&state, file));
+	match file {
+		Ok(file) => match sparse::zero_range(&file, req.offset, req.length) {
+			Ok(()) => {
+				state.audit.record(&actor, Action::Write, &target.path, None);
+				written(&real_path)
+			}
+			Err(e) => ApiError::io("zeroing failed", &e).into_response(),
+		},
+		Err(e) => ApiError::io("open failed", &e).into_response(),
+	}
+}
+
+fn not_found(path: &str) -> Response {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"not_found",
+		format!("'{}' does not exist", path),
+	)
+	.into_response()
+}
+
+fn already_exists(path: &str) -> Response {
+	ApiError::new(
+		StatusCode::CONFLICT,
+		"already_exists",
+		format!("'{}' already exists", path),
+	)
+	.into_response()
+}
+
+fn is_a_directory(path: &str) -> Response {
+	ApiError::new(
+		StatusCode::CONFLICT,
+		"is_a_directory",
+		format!("'{}' is a directory", path),
+	)
+	.into_response()
+}
+
+fn directory_not_empty(path: &str) -> Response {
+	ApiError::new(
+		StatusCode::CONFLICT,
+		"directory_not_empty",
+		format!("directory '{}' is not empty", path),
+	)
+	.into_response()
+}
+
+fn share_root() -> Response {
+	ApiError::new(
+		StatusCode::FORBIDDEN,
+		"share_root",
+		"the share root cannot be deleted or moved",
+	)
+	.into_response()
+}
+
+// 每个共享都挂载同一组文件操作路由
+fn fs_routes(state: &Arc<ServerState>) -> Router<Arc<ServerState>> {
+	Router::new()
+		.route("/info/*path", get(get_info))
+		.route("/stat_batch", post(stat_batch))
+		.route("/batch", post(batch::commit_batch))
+		.route("/list/*path", get(list_directory))
+		.route("/read/*path", get(read_file))
+		.route(
+			"/write/*path",
+			post(write_file).route_layer(middleware::from_fn_with_state(
+				state.clone(),
+				limits::write_size,
+			)),
+		)
+		.route("/create/*path", put(create_file))
+		.route("/delete/*path", delete(delete_path))
+		.route("/move/*path", post(move_path))
+		.route("/truncate/*path", post(truncate_file))
+		.route("/zero/*path", post(zero_range))
+		.route("/times/*path", post(times::set_times))
+		.route("/search", get(search::search))
+		.route("/space", get(quota::get_space))
+		.route(
+			"/xattr/*path",
+			get(xattr::get_xattr)
+				.put(xattr::put_xattr)
+				.delete(xattr::delete_xattr),
+		)
+		.route("/checksum/*path", get(checksum::get_checksum))
+		.route("/signature/*path", get(delta::get_signature))
+		.route("/events", get(events::events))
+		.route("/versions/*path", get(versions::list_versions))
+		.route("/trash/list", get(trash::list_trash))
+		.route("/trash/restore", post(trash::restore_trash))
+		.route("/chunks/exists", post(chunks::chunks_exist))
+		.route("/chunks/gc", post(chunks::gc_chunks))
+		.route(
+			"/chunks/:hash",
+			get(chunks::get_chunk).put(chunks::put_chunk),
+		)
+		.route("/upload/start", post(upload::start_upload))
+		.route(
+			"/upload/:session",
+			get(upload::upload_status).delete(upload::abort_upload),
+		)
+		.route("/upload/:session/chunk", put(upload::upload_chunk))
+		.route("/upload/:session/copy", post(upload::copy_ranges))
+		.route("/upload/:session/commit", post(upload::commit_upload))
+		.route("/audit", get(audit::query_audit))
+		.route("/capabilities", get(capabilities::get_capabilities))
+		.route("/session", post(leases::open_session))
+		.route("/session/:session", delete(leases::close_session))
+		.route("/session/:session/heartbeat", post(leases::heartbeat))
+		.route(
+			"/lease/*path",
+			post(leases::acquire_lease).delete(leases::release_lease),
+		)
+}
+
+// 单个请求体的上限：需要容纳客户端的整文件原子写入和上传分块
+const MAX_BODY_SIZE: usize = 64 * 1024 * 1024;
+
+// 无前缀的路由访问默认共享（或令牌对应的共享），/share/:share/... 访问指定共享
+fn build_router(state: Arc<ServerState>) -> Router {
+	let router = access_log::trace(
+		Router::new()
+			.merge(fs_routes(&state))
+			.nest("/share/:share", fs_routes(&state))
+			.route("/admin/reload", post(config::reload_config))
+			.route("/admin/shutdown_notice", post(shutdown::announce))
+			.route("/metrics", get(metrics::get_metrics))
+			.layer(middleware::from_fn_with_state(
+				state.clone(),
+				limits::rate_limit,
+			))
+			.layer(middleware::from_fn_with_state(
+				state.clone(),
+				metrics::track,
+			)),
+	);
+	// 压缩层始终存在，是否压缩由当前设置决定，重新加载配置后立即生效
+	let router = router
+		.layer(middleware::map_response_with_state(
+			state.clone(),
+			compression::apply_setting,
+		))
+		.layer(compression::layer());
+	// 请求体可以用 gzip/zstd 压缩上传，大小限制作用于解压后的内容
+	let router = router
+		.layer(RequestDecompressionLayer::new())
+		.layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+		.layer(middleware::map_response(error::json_errors));
+	let router = access_log::request_ids(router).with_state(state);
+	// gRPC 调用转换为对上面的路由的内部请求
+	grpc::routes(router.clone()).merge(router)
+}
+
+pub async fn run_server(
+	config_path: PathBuf,
+	config: Config,
+) -> Result<(), Box<dyn std::error::Error>> {
+	let log = access_log::init(config.log.access_log.as_deref(), config.log_level()?)?;
+	let settings = config.settings();
+	for share in settings.shares.values() {
+		println!(
+			"Serving share '{}' from: {}",
+			share.name,
+			share.root_path.display()
+		);
+	}
+
+	let tls = match &config.tls {
+		Some(tls) => Some(RustlsConfig::from_pem_file(&tls.cert, &tls.key).await?),
+		None => None,
+	};
+	let quic = match (&tls, &config.tls) {
+		(Some(rustls), Some(tls)) if tls.http3 => Some(quic::bind(config.bind, rustls)?),
+		_ => None,
+	};
+	let mut state = ServerState::new(settings);
+	if let Some(path) = &config.log.audit_log {
+		state.audit = AuditLog::open(path)?;
+	}
+	state.reloader =
+		Some(Reloader::new(config_path, &config, tls.clone(), log).with_quic(quic.clone()));
+	*state.watchers.lock().unwrap() = events::watch(&state.settings(), &state.events)?;
+	let restored = state.uploads.restore(&state.settings());
+	if restored > 0 {
+		println!("Restored {} unfinished upload session(s)", restored);
+	}
+	let state = Arc::new(state);
+	#[cfg(unix)]
+	tokio::spawn(config::reload_on_sighup(state.clone()));
+
+	let app = build_router(state.clone());
+	if let Some(endpoint) = quic {
+		println!("HTTP/3 (experimental) listening on udp://{}", config.bind);
+		tokio::spawn(quic::serve(endpoint, app.clone(), state.clone()));
+	}
+
+	// 收到关闭信号后停止接受新连接，等待进行中的请求完成，超时后不再等待
+	let mut closing = state.shutdown.subscribe();
+	let deadline = async move {
+		let _ = closing.wait_for(|closing| *closing).await;
+		tokio::time::sleep(shutdown::DRAIN_TIMEOUT).await;
+	};
+	let served = match tls {
+		Some(tls) => {
+			println!("HTTP Storage Server listening on https://{}", config.bind);
+			let handle = axum_server::Handle::new();
+			tokio::spawn({
+				let (state, handle) = (state.clone(), handle.clone());
+				async move {
+					shutdown::signal(&state).await;
+					handle.graceful_shutdown(None);
+				}
+			});
+			let server = axum_server::bind_rustls(config.bind, tls)
+				.handle(handle)
+				.serve(app.into_make_service_with_connect_info::<SocketAddr>());
+			tokio::select! {
+				result = server => Some(result),
+				_ = deadline => None,
+			}
+		}
+		None => {
+			println!("HTTP Storage Server listening on http://{}", config.bind);
+			let listener = TcpListener::bind(config.bind).await?;
+			let server = axum::serve(
+				listener,
+				app.into_make_service_with_connect_info::<SocketAddr>(),
+			)
+			.with_graceful_shutdown({
+				let state = state.clone();
+				async move { shutdown::signal(&state).await }
+			});
+			tokio::select! {
+				result = server => Some(result),
+				_ = deadline => None,
+			}
+		}
+	};
+	match served {
+		Some(result) => result?,
+		None => {
+			eprintln!("[SERVER] requests still running after the drain timeout, exiting anyway")
+		}
+	}
+
+	shutdown::flush(&state);
+	println!("HTTP Storage Server stopped");
+	Ok(())
+}
+
+const DEFAULT_CONFIG: &str = "httpfs-server.toml";
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+	let mut config_path = PathBuf::from(DEFAULT_CONFIG);
+
+	let mut args = std::env::args().skip(1);
+	while let Some(arg) = args.next() {
+		match arg.as_str() {
+			"-c" | "--config" => {
+				config_path = PathBuf::from(args.next().ok_or("--config expects a file path")?);
+			}
+			_ => return Err(format!("unexpected argument '{}'", arg).into()),
+		}
+	}
+
+	let config = Config::load(&config_path)?;
+	run_server(config_path, config).await
+}
@@ -0,0 +1,41 @@
+use std::{fs, io, path::Path};
+
+// 检查把 src 目录合并到 dst 目录时是否存在无法处理的冲突：
+// 同名的目录会递归合并；同名文件只有在允许替换时才能覆盖，文件与目录互相覆盖总是冲突
+pub fn has_conflicts(src: &Path, dst: &Path, replace: bool) -> io::Result<bool> {
+	for entry in fs::read_dir(src)? {
+		let entry = entry?;
+		let to = dst.join(entry.file_name());
+		let to_meta = match fs::symlink_metadata(&to) {
+			Ok(meta) => meta,
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+		let from_is_dir = entry.file_type()?.is_dir();
+		if from_is_dir && to_meta.is_dir() {
+			if has_conflicts(&entry.path(), &to, replace)? {
+				return Ok(true);
+			}
+		} else if !replace || from_is_dir || to_meta.is_dir() {
+			return Ok(true);
+		}
+	}
+	Ok(false)
+}
+
+// 把 src 目录的内容移动到 dst 目录中，完成后删除 src；调用前应先用 has_conflicts 检查
+pub fn merge_dir(src: &Path, dst: &Path) -> io::Result<()> {
+	for entry in fs::read_dir(src)? {
+		let entry = entry?;
+		let to = dst.join(entry.file_name());
+		let to_is_dir = fs::symlink_metadata(&to)
+			.map(|m| m.is_dir())
+			.unwrap_or(false);
+		if entry.file_type()?.is_dir() && to_is_dir {
+			merge_dir(&entry.path(), &to)?;
+		} else {
+			fs::rename(entry.path(), &to)?;
+		}
+	}
+	fs::remove_dir(src)
+}
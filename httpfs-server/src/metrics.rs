@@ -0,0 +1,260 @@
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::{
+		atomic::{AtomicI64, AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::Instant,
+};
+
+use axum::{
+	extract::{MatchedPath, Request, State},
+	http::{header, HeaderMap, StatusCode},
+	middleware::Next,
+	response::{IntoResponse, Response},
+};
+
+use crate::{bearer_token, error::ApiError, ServerState};
+
+// 请求耗时直方图的桶上限（秒）
+const LATENCY_BUCKETS: [f64; 11] = [
+	0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Default)]
+struct Histogram {
+	buckets: [u64; LATENCY_BUCKETS.len()],
+	count: u64,
+	sum: f64,
+}
+
+impl Histogram {
+	fn observe(&mut self, value: f64) {
+		for (bucket, bound) in self.buckets.iter_mut().zip(LATENCY_BUCKETS) {
+			if value <= bound {
+				*bucket += 1;
+			}
+		}
+		self.count += 1;
+		self.sum += value;
+	}
+}
+
+// 服务器运行以来的统计，以 Prometheus 文本格式从 /metrics 导出；重新加载配置时保留
+#[derive(Default)]
+pub struct Metrics {
+	// 按（方法、路由、状态码）统计的请求数
+	requests: Mutex<BTreeMap<(String, String, u16), u64>>,
+	// 按路由统计的耗时
+	latencies: Mutex<BTreeMap<String, Histogram>>,
+	in_flight: AtomicI64,
+	bytes_read: AtomicU64,
+	bytes_written: AtomicU64,
+}
+
+impl Metrics {
+	// 从文件读出并返回给客户端的字节数
+	pub fn add_read(&self, bytes: usize) {
+		self.bytes_read.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	// 客户端写入文件的字节数（/write 和上传分块）
+	pub fn add_written(&self, bytes: usize) {
+		self.bytes_written
+			.fetch_add(bytes as u64, Ordering::Relaxed);
+	}
+
+	fn record(&self, method: String, route: String, status: u16, seconds: f64) {
+		*self
+			.requests
+			.lock()
+			.unwrap()
+			.entry((method, route.clone(), status))
+			.or_default() += 1;
+		self.latencies
+			.lock()
+			.unwrap()
+			.entry(route)
+			.or_default()
+			.observe(seconds);
+	}
+}
+
+// 处理中的请求计数，客户端断开导致请求被取消时也会减回去
+struct InFlight<'a>(&'a AtomicI64);
+
+impl<'a> InFlight<'a> {
+	fn new(counter: &'a AtomicI64) -> Self {
+		counter.fetch_add(1, Ordering::Relaxed);
+		Self(counter)
+	}
+}
+
+impl Drop for InFlight<'_> {
+	fn drop(&mut self) {
+		self.0.fetch_sub(1, Ordering::Relaxed);
+	}
+}
+
+// 统计每个请求的方法、路由、状态码和耗时；路由使用匹配到的模式（如 /read/*path），避免按具体路径产生大量标签
+pub async fn track(
+	State(state): State<Arc<ServerState>>,
+	request: Request,
+	next: Next,
+) -> Response {
+	let method = request.method().to_string();
+	let route = request
+		.extensions()
+		.get::<MatchedPath>()
+		.map_or("unmatched", MatchedPath::as_str)
+		.to_string();
+	let started = Instant::now();
+	let in_flight = InFlight::new(&state.metrics.in_flight);
+	let response = next.run(request).await;
+	drop(in_flight);
+	state.metrics.record(
+		method,
+		route,
+		response.status().as_u16(),
+		started.elapsed().as_secs_f64(),
+	);
+	response
+}
+
+// 标签值中的反斜杠、双引号和换行需要转义
+fn label(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+fn header(out: &mut String, name: &str, kind: &str, help: &str) {
+	let _ = writeln!(out, "# HELP {} {}", name, help);
+	let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn render(state: &ServerState) -> String {
+	let metrics = &state.metrics;
+	let (checksum_hits, checksum_misses) = state.checksums.stats();
+	let mut out = String::new();
+
+	header(
+		&mut out,
+		"httpfs_requests_total",
+		"counter",
+		"HTTP requests by method, route and status.",
+	);
+	for ((method, route, status), count) in metrics.requests.lock().unwrap().iter() {
+		let _ = writeln!(
+			out,
+			"httpfs_requests_total{{method=\"{}\",route=\"{}\",status=\"{}\"}} {}",
+			method,
+			label(route),
+			status,
+			count
+		);
+	}
+
+	header(
+		&mut out,
+		"httpfs_request_duration_seconds",
+		"histogram",
+		"Request latency by route.",
+	);
+	for (route, histogram) in metrics.latencies.lock().unwrap().iter() {
+		let route = label(route);
+		for (count, bound) in histogram.buckets.iter().zip(LATENCY_BUCKETS) {
+			let _ = writeln!(
+				out,
+				"httpfs_request_duration_seconds_bucket{{route=\"{}\",le=\"{}\"}} {}",
+				route, bound, count
+			);
+		}
+		let _ = writeln!(
+			out,
+			"httpfs_request_duration_seconds_bucket{{route=\"{}\",le=\"+Inf\"}} {}",
+			route, histogram.count
+		);
+		let _ = writeln!(
+			out,
+			"httpfs_request_duration_seconds_sum{{route=\"{}\"}} {}",
+			route, histogram.sum
+		);
+		let _ = writeln!(
+			out,
+			"httpfs_request_duration_seconds_count{{route=\"{}\"}} {}",
+			route, histogram.count
+		);
+	}
+
+	let counters = [
+		(
+			"httpfs_read_bytes_total",
+			"Bytes of file content returned to clients.",
+			metrics.bytes_read.load(Ordering::Relaxed),
+		),
+		(
+			"httpfs_written_bytes_total",
+			"Bytes of file content written by clients.",
+			metrics.bytes_written.load(Ordering::Relaxed),
+		),
+		(
+			"httpfs_checksum_cache_hits_total",
+			"Checksum requests answered from the digest cache.",
+			checksum_hits,
+		),
+		(
+			"httpfs_checksum_cache_misses_total",
+			"Checksum requests that had to hash the file.",
+			checksum_misses,
+		),
+	];
+	for (name, help, value) in counters {
+		header(&mut out, name, "counter", help);
+		let _ = writeln!(out, "{} {}", name, value);
+	}
+
+	let gauges = [
+		(
+			"httpfs_in_flight_requests",
+			"Requests currently being handled.",
+			metrics.in_flight.load(Ordering::Relaxed) as u64,
+		),
+		(
+			"httpfs_upload_sessions",
+			"Open chunked upload sessions.",
+			state.uploads.count() as u64,
+		),
+		(
+			"httpfs_event_subscribers",
+			"Clients subscribed to /events.",
+			state.events.receiver_count() as u64,
+		),
+	];
+	for (name, help, value) in gauges {
+		header(&mut out, name, "gauge", help);
+		let _ = writeln!(out, "{} {}", name, value);
+	}
+	out
+}
+
+// GET /metrics - Prometheus 文本格式的统计；设置 auth.metrics_token 后需要以该令牌认证
+pub async fn get_metrics(State(state): State<Arc<ServerState>>, headers: HeaderMap) -> Response {
+	if let Some(token) = &state.settings().metrics_token {
+		if bearer_token(&headers) != Some(token.as_str()) {
+			return ApiError::new(
+				StatusCode::UNAUTHORIZED,
+				"unauthorized",
+				"missing or invalid metrics token",
+			)
+			.into_response();
+		}
+	}
+	(
+		[(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+		render(&state),
+	)
+		.into_response()
+}
@@ -0,0 +1,210 @@
+use std::{
+	fmt,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	http::{header, HeaderValue, StatusCode},
+	response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+
+// 共享的内容同时保存在对象存储中（共享目录是桶的本地副本或网关挂载）时，/read?redirect=true
+// 对足够大的文件返回 307，Location 为预签名的对象 URL，客户端直接从对象存储读取，
+// 大量数据不再经过本服务器。签名使用 S3 的 SigV4 查询参数形式，只覆盖 Host 头
+pub const EXPIRES_HEADER: &str = "x-httpfs-url-expires-in";
+// SigV4 预签名 URL 允许的最长有效期
+pub const MAX_EXPIRES: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+const DEFAULT_EXPIRES: Duration = Duration::from_secs(5 * 60);
+// 默认只重定向至少这么大的文件，小文件直接返回比多一次往返更快
+const DEFAULT_MIN_SIZE: u64 = 1024 * 1024;
+const HMAC_BLOCK: usize = 64;
+
+#[derive(Clone)]
+pub struct DirectReads {
+	// 对象存储的地址，如 https://s3.eu-west-1.amazonaws.com，使用路径形式的 URL
+	pub endpoint: String,
+	pub bucket: String,
+	// 共享根目录在桶中对应的键前缀
+	pub prefix: String,
+	pub region: String,
+	pub access_key: String,
+	pub secret_key: String,
+	pub expires: Duration,
+	pub min_size: u64,
+}
+
+// 打印配置时不显示密钥
+impl fmt::Debug for DirectReads {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		f.debug_struct("DirectReads")
+			.field("endpoint", &self.endpoint)
+			.field("bucket", &self.bucket)
+			.field("prefix", &self.prefix)
+			.field("region", &self.region)
+			.field("access_key", &self.access_key)
+			.field("expires", &self.expires)
+			.field("min_size", &self.min_size)
+			.finish_non_exhaustive()
+	}
+}
+
+impl DirectReads {
+	pub fn new(endpoint: &str, bucket: String, access_key: String, secret_key: String) -> Self {
+		Self {
+			endpoint: endpoint.trim_end_matches('/').to_string(),
+			bucket,
+			prefix: String::new(),
+			region: "us-east-1".to_string(),
+			access_key,
+			secret_key,
+			expires: DEFAULT_EXPIRES,
+			min_size: DEFAULT_MIN_SIZE,
+		}
+	}
+
+	// 共享内路径对应的对象在 now 时签名的 GET URL
+	pub fn presign(&self, path: &str, now: SystemTime) -> Result<String, String> {
+		let (scheme, host) = self
+			.endpoint
+			.split_once("://")
+			.ok_or_else(|| format!("'{}' is not a URL", self.endpoint))?;
+		let host = host.split('/').next().unwrap_or_default();
+		let key = format!("{}{}", self.prefix, path.trim_start_matches('/'));
+		let canonical_uri = format!(
+			"/{}/{}",
+			uri_encode(&self.bucket, false),
+			uri_encode(&key, true)
+		);
+
+		let secs = now
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or(0);
+		let (date, timestamp) = amz_date(secs);
+		let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+		let credential = format!("{}/{}", self.access_key, scope);
+		let expires = self.expires.as_secs().to_string();
+		// 查询参数按名称排序
+		let query = [
+			("X-Amz-Algorithm", "AWS4-HMAC-SHA256"),
+			("X-Amz-Credential", credential.as_str()),
+			("X-Amz-Date", timestamp.as_str()),
+			("X-Amz-Expires", expires.as_str()),
+			("X-Amz-SignedHeaders", "host"),
+		]
+		.iter()
+		.map(|(name, value)| format!("{}={}", name, uri_encode(value, false)))
+		.collect::<Vec<_>>()
+		.join("&");
+
+		let canonical_request = format!(
+			"GET\n{}\n{}\nhost:{}\n\nhost\nUNSIGNED-PAYLOAD",
+			canonical_uri, query, host
+		);
+		let string_to_sign = format!(
+			"AWS4-HMAC-SHA256\n{}\n{}\n{}",
+			timestamp,
+			scope,
+			hex::encode(Sha256::digest(canonical_request.as_bytes()))
+		);
+		let mut signing_key = hmac(format!("AWS4{}", self.secret_key).as_bytes(), &date);
+		for part in [self.region.as_str(), "s3", "aws4_request"] {
+			signing_key = hmac(&signing_key, part);
+		}
+		let signature = hex::encode(hmac(&signing_key, &string_to_sign));
+		Ok(format!(
+			"{}://{}{}?{}&X-Amz-Signature={}",
+			scheme, host, canonical_uri, query, signature
+		))
+	}
+
+	// 把读取重定向到签名 URL；有效期随响应返回，客户端在到期前可以重复使用
+	pub fn redirect(&self, path: &str) -> Option<Response> {
+		let url = match self.presign(path, SystemTime::now()) {
+			Ok(url) => url,
+			Err(e) => {
+				eprintln!("[SERVER] presigning {} failed: {}", path, e);
+				return None;
+			}
+		};
+		let location = HeaderValue::from_str(&url).ok()?;
+		Some(
+			(
+				StatusCode::TEMPORARY_REDIRECT,
+				[
+					(header::LOCATION, location),
+					(
+						header::HeaderName::from_static(EXPIRES_HEADER),
+						HeaderValue::from(self.expires.as_secs()),
+					),
+					(header::CACHE_CONTROL, HeaderValue::from_static("no-store")),
+				],
+			)
+				.into_response(),
+		)
+	}
+}
+
+// RFC 3986 的非保留字符之外都按 %XX 编码，键中的 / 保留
+pub fn uri_encode(text: &str, keep_slash: bool) -> String {
+	let mut encoded = String::with_capacity(text.len());
+	for byte in text.bytes() {
+		match byte {
+			b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+				encoded.push(byte as char)
+			}
+			b'/' if keep_slash => encoded.push('/'),
+			_ => encoded.push_str(&format!("%{:02X}", byte)),
+		}
+	}
+	encoded
+}
+
+// 1970-01-01 以来的天数换算为公历日期
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+	let z = days + 719468;
+	let era = z.div_euclid(146097);
+	let doe = z.rem_euclid(146097);
+	let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+	let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+	let mp = (5 * doy + 2) / 153;
+	let day = doy - (153 * mp + 2) / 5 + 1;
+	let month = if mp < 10 { mp + 3 } else { mp - 9 };
+	(yoe + era * 400 + i64::from(month <= 2), month, day)
+}
+
+// 签名使用的日期（20240102）和时间（20240102T030405Z）
+fn amz_date(secs: u64) -> (String, String) {
+	let (year, month, day) = civil_from_days((secs / 86400) as i64);
+	let date = format!("{:04}{:02}{:02}", year, month, day);
+	let time = secs % 86400;
+	let timestamp = format!(
+		"{}T{:02}{:02}{:02}Z",
+		date,
+		time / 3600,
+		time / 60 % 60,
+		time % 60
+	);
+	(date, timestamp)
+}
+
+// RFC 2104 的 HMAC-SHA256
+fn hmac(key: &[u8], data: &str) -> Vec<u8> {
+	let mut block = [0u8; HMAC_BLOCK];
+	if key.len() > HMAC_BLOCK {
+		block[..32].copy_from_slice(&Sha256::digest(key));
+	} else {
+		block[..key.len()].copy_from_slice(key);
+	}
+	let pad = |byte: u8| block.iter().map(|b| b ^ byte).collect::<Vec<_>>();
+	let inner = Sha256::new()
+		.chain_update(pad(0x36))
+		.chain_update(data.as_bytes())
+		.finalize();
+	Sha256::new()
+		.chain_update(pad(0x5c))
+		.chain_update(inner)
+		.finalize()
+		.to_vec()
+}
@@ -0,0 +1,118 @@
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::{
+	body::{Body, Bytes},
+	extract::ConnectInfo,
+	http::{Request, Response, StatusCode},
+	Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use bytes::{Buf, BytesMut};
+use h3::server::RequestStream;
+use quinn::{crypto::rustls::QuicServerConfig, Endpoint, ServerConfig};
+use tokio_stream::StreamExt;
+use tower::ServiceExt;
+
+use crate::{ServerState, MAX_BODY_SIZE};
+
+// 实验性的 HTTP/3：与 HTTPS 相同的端口上以 UDP 接受 QUIC 连接，请求交给同一个路由处理。
+// QUIC 的各个请求在连接上独立传输，丢包只影响所在的请求，不会像 TCP 那样阻塞整个连接，
+// 在丢包较多的无线网络和广域网上吞吐更稳定
+
+// 与 HTTPS 使用同一份证书，只把 ALPN 换成 h3
+pub fn server_config(tls: &RustlsConfig) -> Result<ServerConfig, String> {
+	let mut crypto = (*tls.get_inner()).clone();
+	crypto.alpn_protocols = vec![b"h3".to_vec()];
+	let crypto = QuicServerConfig::try_from(crypto).map_err(|e| e.to_string())?;
+	Ok(ServerConfig::with_crypto(Arc::new(crypto)))
+}
+
+pub fn bind(addr: SocketAddr, tls: &RustlsConfig) -> Result<Endpoint, Box<dyn std::error::Error>> {
+	Ok(Endpoint::server(server_config(tls)?, addr)?)
+}
+
+// 接受连接直到开始关闭；之后不再接受新连接，已有的请求照常完成
+pub async fn serve(endpoint: Endpoint, app: Router, state: Arc<ServerState>) {
+	let mut closing = state.shutdown.subscribe();
+	loop {
+		let incoming = tokio::select! {
+			incoming = endpoint.accept() => incoming,
+			_ = closing.wait_for(|closing| *closing) => None,
+		};
+		let Some(incoming) = incoming else {
+			break;
+		};
+		let app = app.clone();
+		tokio::spawn(async move {
+			if let Err(e) = connection(incoming, app).await {
+				eprintln!("[SERVER] HTTP/3 connection failed: {}", e);
+			}
+		});
+	}
+	endpoint.set_server_config(None);
+}
+
+async fn connection(
+	incoming: quinn::Incoming,
+	app: Router,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+	let connection = incoming.await?;
+	let remote = connection.remote_address();
+	let mut connection =
+		h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(connection)).await?;
+	// 客户端关闭连接时 accept 返回 None 或连接错误
+	while let Ok(Some(resolver)) = connection.accept().await {
+		let app = app.clone();
+		tokio::spawn(async move {
+			let result = async {
+				let (request, stream) = resolver.resolve_request().await?;
+				respond(request, stream, app, remote).await
+			};
+			if let Err(e) = result.await {
+				eprintln!("[SERVER] HTTP/3 request from {} failed: {}", remote, e);
+			}
+		});
+	}
+	Ok(())
+}
+
+async fn respond(
+	request: Request<()>,
+	mut stream: RequestStream<h3_quinn::BidiStream<Bytes>, Bytes>,
+	app: Router,
+	remote: SocketAddr,
+) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+	// 请求体先完整接收，超过上限时与 HTTP/1.1 和 HTTP/2 一样返回 413
+	let mut body = BytesMut::new();
+	let mut too_large = false;
+	while let Some(mut chunk) = stream.recv_data().await? {
+		if body.len() + chunk.remaining() > MAX_BODY_SIZE {
+			too_large = true;
+			break;
+		}
+		body.extend_from_slice(&chunk.copy_to_bytes(chunk.remaining()));
+	}
+	let response = if too_large {
+		Response::builder()
+			.status(StatusCode::PAYLOAD_TOO_LARGE)
+			.body(Body::empty())?
+	} else {
+		let (parts, ()) = request.into_parts();
+		let mut request = Request::from_parts(parts, Body::from(body.freeze()));
+		// 请求限制和审计按客户端的地址
+		request.extensions_mut().insert(ConnectInfo(remote));
+		app.oneshot(request).await?
+	};
+
+	let (parts, body) = response.into_parts();
+	stream
+		.send_response(Response::from_parts(parts, ()))
+		.await?;
+	// /events 等长时间的响应边产生边发送
+	let mut body = body.into_data_stream();
+	while let Some(chunk) = body.next().await {
+		stream.send_data(chunk?).await?;
+	}
+	stream.finish().await?;
+	Ok(())
+}
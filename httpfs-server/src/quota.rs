@@ -0,0 +1,116 @@
+use std::{fs, io, path::Path};
+
+use axum::{
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+
+use crate::{error::ApiError, scan, share::Share, sparse, trash, versions, ShareAccess};
+
+// 统计目录下所有文件占用的字节数，稀疏文件的空洞不计入。不跟随符号链接；服务器内部文件（临时文件、
+// 扩展属性文件）同样占用磁盘，也计入用量，但回收站中的条目、被隔离的文件和历史版本不计入
+pub fn usage(dir: &Path) -> io::Result<u64> {
+	let mut total = 0;
+	for entry in fs::read_dir(dir)? {
+		let entry = entry?;
+		let name = entry.file_name();
+		let name = name.to_string_lossy();
+		if trash::is_trash_name(&name)
+			|| scan::is_quarantine_name(&name)
+			|| versions::is_versions_name(&name)
+		{
+			continue;
+		}
+		let metadata = match entry.metadata() {
+			Ok(metadata) => metadata,
+			// 遍历期间被删除的条目直接跳过
+			Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+			Err(e) => return Err(e),
+		};
+		if metadata.is_dir() {
+			total += usage(&entry.path())?;
+		} else if metadata.is_file() {
+			total += sparse::stored_len(&entry.path(), &metadata);
+		}
+	}
+	Ok(total)
+}
+
+fn quota_exceeded(quota: u64, used: u64, requested: u64) -> ApiError {
+	ApiError::new(
+		StatusCode::INSUFFICIENT_STORAGE,
+		"disk_full",
+		format!(
+			"share quota of {} bytes exceeded ({} bytes used, {} more requested)",
+			quota, used, requested
+		),
+	)
+}
+
+// 检查把一个文件从 old_size 改为 new_size 后共享是否仍在配额之内，未设置配额时总是通过
+pub fn check(share: &Share, old_size: u64, new_size: u64) -> Result<(), ApiError> {
+	let Some(quota) = share.quota else {
+		return Ok(());
+	};
+	if new_size <= old_size {
+		return Ok(());
+	}
+	let used = usage(&share.root_path).map_err(|e| ApiError::io("computing usage failed", &e))?;
+	let requested = new_size - old_size;
+	if used.saturating_add(requested) > quota {
+		return Err(quota_exceeded(quota, used, requested));
+	}
+	Ok(())
+}
+
+// 创建新条目前检查配额是否已经用完
+pub fn check_room(share: &Share) -> Result<(), ApiError> {
+	let Some(quota) = share.quota else {
+		return Ok(());
+	};
+	let used = usage(&share.root_path).map_err(|e| ApiError::io("computing usage failed", &e))?;
+	if used >= quota {
+		return Err(quota_exceeded(quota, used, 0));
+	}
+	Ok(())
+}
+
+#[derive(Debug, Serialize)]
+pub struct SpaceResponse {
+	total: u64,
+	used: u64,
+	available: u64,
+	quota: Option<u64>,
+}
+
+fn space(share: &Share) -> io::Result<SpaceResponse> {
+	let stats = fs4::statvfs(&share.root_path)?;
+	Ok(match share.quota {
+		// 设置了配额时按配额报告，剩余空间同时受底层磁盘限制
+		Some(quota) => {
+			let used = usage(&share.root_path)?;
+			SpaceResponse {
+				total: quota,
+				used,
+				available: quota.saturating_sub(used).min(stats.available_space()),
+				quota: Some(quota),
+			}
+		}
+		None => SpaceResponse {
+			total: stats.total_space(),
+			used: stats.total_space().saturating_sub(stats.free_space()),
+			available: stats.available_space(),
+			quota: None,
+		},
+	})
+}
+
+// GET /space - 共享的总容量、已用和可用字节数
+pub async fn get_space(ShareAccess(share): ShareAccess) -> Response {
+	match space(&share) {
+		Ok(space) => Json(space).into_response(),
+		Err(e) => ApiError::io("querying disk space failed", &e).into_response(),
+	}
+}
@@ -0,0 +1,96 @@
+use std::{
+	fs::{self, Metadata},
+	path::Path,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::StatusCode;
+
+use crate::{error::ApiError, is_internal_name, share::Share, xattr};
+
+// 一次写入多次读取（WORM）的共享：有内容的文件即为已提交，在保留期限内不能修改、删除、重命名，
+// 也不能修改时间戳和扩展属性。空文件尚未提交，客户端先创建空文件再写入完整内容。
+// 保留期限在提交完整内容时记录在属性文件中；没有记录的文件（按偏移写入或绕过服务器放入的）
+// 从修改时间起计算，已提交的文件无法再修改时间，因此不能借此缩短期限
+
+fn to_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+fn retained(path: &str, until: u64) -> ApiError {
+	let until_date = httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(until));
+	ApiError::new(
+		StatusCode::FORBIDDEN,
+		"retained",
+		format!("'{}' is retained until {}", path, until_date),
+	)
+	.with_details(serde_json::json!({ "retained_until": until }))
+}
+
+// 已提交的文件保留到何时（Unix 秒），期限已过或尚未提交时返回 None
+fn retained_until(
+	retention: Duration,
+	real_path: &Path,
+	root: &Path,
+	metadata: &Metadata,
+) -> Option<u64> {
+	if !metadata.is_file() || metadata.len() == 0 {
+		return None;
+	}
+	let until = xattr::retained_until(real_path, root).unwrap_or_else(|| {
+		let modified = metadata.modified().map(to_secs).unwrap_or(0);
+		modified.saturating_add(retention.as_secs())
+	});
+	(until > to_secs(SystemTime::now())).then_some(until)
+}
+
+// 修改、删除或重命名一个文件之前检查；记录保留期限的属性文件由 Share::get_real_path 拒绝，客户端无法直接修改
+pub fn check(share: &Share, path: &str, real_path: &Path) -> Result<(), ApiError> {
+	let Some(retention) = share.retention else {
+		return Ok(());
+	};
+	let Ok(metadata) = fs::symlink_metadata(real_path) else {
+		return Ok(());
+	};
+	match retained_until(retention, real_path, &share.root_path, &metadata) {
+		Some(until) => Err(retained(path, until)),
+		None => Ok(()),
+	}
+}
+
+// 删除或移动目录之前检查其中所有的文件，报告第一个仍在保留期限内的文件
+pub fn check_tree(share: &Share, path: &str, real_path: &Path) -> Result<(), ApiError> {
+	if share.retention.is_none() || !real_path.is_dir() {
+		return check(share, path, real_path);
+	}
+	let entries = fs::read_dir(real_path).map_err(|e| ApiError::io("read_dir failed", &e))?;
+	for entry in entries.flatten() {
+		let name = entry.file_name().to_string_lossy().into_owned();
+		// 目录被删除或移动时它的内部文件随之处理
+		if is_internal_name(&name) {
+			continue;
+		}
+		let child = format!("{}/{}", path.trim_end_matches('/'), name);
+		check_tree(share, &child, &entry.path())?;
+	}
+	Ok(())
+}
+
+// 完整内容写入（原子写入、批量提交、上传提交）成功后记录保留期限，从这时起文件不能再修改
+pub fn commit(share: &Share, real_path: &Path) {
+	let Some(retention) = share.retention else {
+		return;
+	};
+	if !fs::metadata(real_path).is_ok_and(|metadata| metadata.len() > 0) {
+		return;
+	}
+	let until = to_secs(SystemTime::now()).saturating_add(retention.as_secs());
+	if let Err(e) = xattr::set_retained_until(real_path, &share.root_path, until) {
+		eprintln!(
+			"[SERVER] recording retention of {:?} failed: {:?}",
+			real_path, e
+		);
+	}
+}
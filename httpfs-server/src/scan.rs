@@ -0,0 +1,436 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File, Metadata},
+	io::{self, BufRead, BufReader, Read, Write},
+	net::{TcpStream, ToSocketAddrs},
+	path::{Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::http::StatusCode;
+use serde::{Deserialize, Serialize};
+
+use crate::{error::ApiError, share::Share, ServerState};
+
+// 隔离区位于共享根目录下，结构与回收站相同：每个被隔离的文件占用一个子目录，
+// info.json 记录原路径、检出的威胁和时间，data 是文件内容
+pub const QUARANTINE_DIR: &str = ".httpfs-quarantine";
+const INFO_FILE: &str = "info.json";
+const DATA_NAME: &str = "data";
+// 缓存的扫描结果超过该值时整体清空
+const MAX_CACHED_VERDICTS: usize = 10_000;
+const DEFAULT_ICAP_PORT: u16 = 1344;
+const ICAP_TIMEOUT: Duration = Duration::from_secs(60);
+// 向 ICAP 服务器发送内容时每块的大小
+const SCAN_CHUNK: usize = 64 * 1024;
+
+pub fn is_quarantine_name(name: &str) -> bool {
+	name == QUARANTINE_DIR
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+	Clean,
+	// 检出的威胁名称
+	Infected(String),
+}
+
+// 内容扫描器：检查一个文件的完整内容，name 是共享内的路径，只用于扫描器的报告
+pub trait Scanner: Send + Sync {
+	fn scan(&self, name: &str, size: u64, content: &mut dyn Read) -> io::Result<Verdict>;
+}
+
+// 检出威胁后的处理：reject 只拒绝请求，quarantine 另外把内容移入隔离区
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ScanAction {
+	#[default]
+	Reject,
+	Quarantine,
+}
+
+pub struct ScanSettings {
+	pub scanner: Arc<dyn Scanner>,
+	pub action: ScanAction,
+	// 超过该大小（字节）的文件不扫描
+	pub max_size: Option<u64>,
+	// 读取前也扫描文件，结果按文件的大小和修改时间缓存
+	pub scan_reads: bool,
+	// 扫描器不可用时放行，默认拒绝请求
+	pub fail_open: bool,
+}
+
+impl ScanSettings {
+	pub fn new(scanner: Arc<dyn Scanner>) -> Self {
+		Self {
+			scanner,
+			action: ScanAction::Reject,
+			max_size: None,
+			scan_reads: false,
+			fail_open: false,
+		}
+	}
+}
+
+fn infected(path: &str, threat: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::FORBIDDEN,
+		"virus_infected",
+		format!("'{}' contains {}", path, threat),
+	)
+}
+
+// 扫描一份内容：干净或不需扫描时返回 None，检出威胁时返回威胁名称
+fn run(
+	settings: &ScanSettings,
+	path: &str,
+	size: u64,
+	content: &mut dyn Read,
+) -> Result<Option<String>, ApiError> {
+	if settings.max_size.is_some_and(|max_size| size > max_size) {
+		return Ok(None);
+	}
+	match settings.scanner.scan(path, size, content) {
+		Ok(Verdict::Clean) => Ok(None),
+		Ok(Verdict::Infected(threat)) => {
+			eprintln!("[SERVER] scan: '{}' contains {}", path, threat);
+			Ok(Some(threat))
+		}
+		Err(e) if settings.fail_open => {
+			eprintln!(
+				"[SERVER] scan: scanning '{}' failed, allowing it: {:?}",
+				path, e
+			);
+			Ok(None)
+		}
+		Err(e) => {
+			eprintln!("[SERVER] scan: scanning '{}' failed: {:?}", path, e);
+			Err(ApiError::new(
+				StatusCode::SERVICE_UNAVAILABLE,
+				"scan_failed",
+				format!("the content scanner is unavailable: {}", e),
+			))
+		}
+	}
+}
+
+// 写入完整内容（原子写入、批量提交）之前扫描；检出威胁时拒绝写入，需要时把内容存入隔离区
+pub fn check_content(
+	state: &ServerState,
+	share: &Share,
+	path: &str,
+	data: &[u8],
+) -> Result<(), ApiError> {
+	let settings = state.settings();
+	let Some(scan) = &settings.scan else {
+		return Ok(());
+	};
+	let Some(threat) = run(scan, path, data.len() as u64, &mut &data[..])? else {
+		return Ok(());
+	};
+	if scan.action == ScanAction::Quarantine {
+		let result = quarantine(&share.root_path, path, &threat, |dest| {
+			fs::write(dest, data)
+		});
+		if let Err(e) = result {
+			eprintln!("[SERVER] scan: quarantining '{}' failed: {:?}", path, e);
+		}
+	}
+	Err(infected(path, &threat))
+}
+
+// 提交上传或读取之前扫描磁盘上的文件；检出威胁时需要时把文件本身移入隔离区
+pub fn check_file(
+	state: &ServerState,
+	share: &Share,
+	path: &str,
+	real_path: &Path,
+) -> Result<(), ApiError> {
+	let settings = state.settings();
+	let Some(scan) = &settings.scan else {
+		return Ok(());
+	};
+	let threat = scan_file(scan, path, real_path)?;
+	isolate(scan, share, path, real_path, threat)
+}
+
+// 读取之前扫描，未开启 scan_reads 时不扫描；同一内容的结果被缓存，只有变化后才重新扫描
+pub fn check_read(
+	state: &ServerState,
+	share: &Share,
+	path: &str,
+	real_path: &Path,
+	metadata: &Metadata,
+) -> Result<(), ApiError> {
+	let settings = state.settings();
+	let Some(scan) = settings.scan.as_ref().filter(|scan| scan.scan_reads) else {
+		return Ok(());
+	};
+	let threat = match state.scans.get(real_path, metadata) {
+		Some(threat) => threat,
+		None => {
+			let threat = scan_file(scan, path, real_path)?;
+			state.scans.insert(real_path, metadata, threat.clone());
+			threat
+		}
+	};
+	isolate(scan, share, path, real_path, threat)
+}
+
+fn scan_file(
+	scan: &ScanSettings,
+	path: &str,
+	real_path: &Path,
+) -> Result<Option<String>, ApiError> {
+	let mut file = File::open(real_path).map_err(|e| ApiError::io("open failed", &e))?;
+	let size = file
+		.metadata()
+		.map_err(|e| ApiError::io("stat failed", &e))?
+		.len();
+	run(scan, path, size, &mut file)
+}
+
+fn isolate(
+	scan: &ScanSettings,
+	share: &Share,
+	path: &str,
+	real_path: &Path,
+	threat: Option<String>,
+) -> Result<(), ApiError> {
+	let Some(threat) = threat else {
+		return Ok(());
+	};
+	if scan.action == ScanAction::Quarantine {
+		let result = quarantine(&share.root_path, path, &threat, |dest| {
+			fs::rename(real_path, dest)
+		});
+		if let Err(e) = result {
+			eprintln!("[SERVER] scan: quarantining '{}' failed: {:?}", path, e);
+		}
+	}
+	Err(infected(path, &threat))
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct QuarantineEntry {
+	// 相对共享根目录的路径
+	path: String,
+	threat: String,
+	time: u64,
+}
+
+fn new_entry_id() -> String {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_nanos())
+		.unwrap_or(0);
+	format!("{}-{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+// 在隔离区中新建一个条目，store 把内容放到给出的路径上
+fn quarantine(
+	root: &Path,
+	path: &str,
+	threat: &str,
+	store: impl FnOnce(&Path) -> io::Result<()>,
+) -> io::Result<()> {
+	let entry = QuarantineEntry {
+		path: path.trim_matches('/').to_string(),
+		threat: threat.to_string(),
+		time: SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_secs())
+			.unwrap_or(0),
+	};
+	let entry_dir = root.join(QUARANTINE_DIR).join(new_entry_id());
+	fs::create_dir_all(&entry_dir)?;
+	let result = serde_json::to_vec(&entry)
+		.map_err(io::Error::other)
+		.and_then(|info| fs::write(entry_dir.join(INFO_FILE), info))
+		.and_then(|_| store(&entry_dir.join(DATA_NAME)));
+	if result.is_err() {
+		let _ = fs::remove_dir_all(&entry_dir);
+	}
+	result
+}
+
+struct CachedVerdict {
+	size: u64,
+	modified: SystemTime,
+	threat: Option<String>,
+}
+
+// 按路径缓存读取前的扫描结果，文件大小或修改时间变化后失效
+#[derive(Default)]
+pub struct ScanCache {
+	verdicts: Mutex<HashMap<PathBuf, CachedVerdict>>,
+}
+
+impl ScanCache {
+	fn get(&self, path: &Path, metadata: &Metadata) -> Option<Option<String>> {
+		let modified = metadata.modified().ok()?;
+		let verdicts = self.verdicts.lock().unwrap();
+		let cached = verdicts.get(path)?;
+		(cached.size == metadata.len() && cached.modified == modified)
+			.then(|| cached.threat.clone())
+	}
+
+	fn insert(&self, path: &Path, metadata: &Metadata, threat: Option<String>) {
+		let Ok(modified) = metadata.modified() else {
+			return;
+		};
+		let mut verdicts = self.verdicts.lock().unwrap();
+		if verdicts.len() >= MAX_CACHED_VERDICTS {
+			verdicts.clear();
+		}
+		verdicts.insert(
+			path.to_path_buf(),
+			CachedVerdict {
+				size: metadata.len(),
+				modified,
+				threat,
+			},
+		);
+	}
+}
+
+// 通过 ICAP（RFC 3507）的 RESPMOD 把内容交给防病毒网关扫描，如 c-icap 加 ClamAV 或商用的 ICAP 服务。
+// 服务器回答 204 表示内容未被修改；200 且带有 X-Infection-Found 或 X-Virus-ID 时表示检出威胁
+pub struct IcapScanner {
+	host: String,
+	port: u16,
+	service: String,
+}
+
+impl IcapScanner {
+	// 地址形如 icap://host[:port]/service，端口默认为 1344
+	pub fn new(url: &str) -> Result<Self, String> {
+		let invalid = || format!("invalid ICAP URL '{}'", url);
+		let rest = url.strip_prefix("icap://").ok_or_else(invalid)?;
+		let (authority, service) = rest.split_once('/').unwrap_or((rest, ""));
+		let (host, port) = match authority.rsplit_once(':') {
+			Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+			None => (authority, DEFAULT_ICAP_PORT),
+		};
+		if host.is_empty() {
+			return Err(invalid());
+		}
+		Ok(Self {
+			host: host.to_string(),
+			port,
+			service: service.to_string(),
+		})
+	}
+
+	fn connect(&self) -> io::Result<TcpStream> {
+		let address = (self.host.as_str(), self.port)
+			.to_socket_addrs()?
+			.next()
+			.ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "ICAP host not found"))?;
+		let stream = TcpStream::connect_timeout(&address, ICAP_TIMEOUT)?;
+		stream.set_read_timeout(Some(ICAP_TIMEOUT))?;
+		stream.set_write_timeout(Some(ICAP_TIMEOUT))?;
+		Ok(stream)
+	}
+}
+
+// 封装的 HTTP 请求行中的路径只保留不需转义的字符
+fn encode_path(name: &str) -> String {
+	let mut encoded = String::with_capacity(name.len() + 1);
+	encoded.push('/');
+	for &byte in name.trim_start_matches('/').as_bytes() {
+		if byte.is_ascii_alphanumeric() || b"-._~/".contains(&byte) {
+			encoded.push(byte as char);
+		} else {
+			encoded.push_str(&format!("%{:02X}", byte));
+		}
+	}
+	encoded
+}
+
+// 从 X-Infection-Found（"Type=0; Resolution=2; Threat=Eicar-Test-Signature;"）中取出威胁名称
+fn threat_name(infection_found: &str) -> Option<&str> {
+	infection_found
+		.split(';')
+		.filter_map(|field| field.trim().split_once('='))
+		.find(|(key, _)| key.eq_ignore_ascii_case("threat"))
+		.map(|(_, value)| value.trim())
+}
+
+impl Scanner for IcapScanner {
+	fn scan(&self, name: &str, size: u64, content: &mut dyn Read) -> io::Result<Verdict> {
+		let mut stream = self.connect()?;
+		// 封装一个对 GET 请求的响应，扫描器在报告中显示请求的路径
+		let request_header = format!("GET {} HTTP/1.1\r\nHost: httpfs\r\n\r\n", encode_path(name));
+		let response_header = format!(
+			"HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\n\r\n",
+			size
+		);
+		let header = format!(
+			"RESPMOD icap://{}:{}/{} ICAP/1.0\r\nHost: {}\r\nAllow: 204\r\nConnection: close\r\n\
+			 Encapsulated: req-hdr=0, res-hdr={}, res-body={}\r\n\r\n{}{}",
+			self.host,
+			self.port,
+			self.service,
+			self.host,
+			request_header.len(),
+			request_header.len() + response_header.len(),
+			request_header,
+			response_header
+		);
+		let mut writer = io::BufWriter::new(&stream);
+		writer.write_all(header.as_bytes())?;
+		// 内容以分块编码发送
+		let mut buffer = vec![0u8; SCAN_CHUNK];
+		loop {
+			let n = content.read(&mut buffer)?;
+			if n == 0 {
+				break;
+			}
+			write!(writer, "{:x}\r\n", n)?;
+			writer.write_all(&buffer[..n])?;
+			writer.write_all(b"\r\n")?;
+		}
+		writer.write_all(b"0\r\n\r\n")?;
+		writer.flush()?;
+		drop(writer);
+
+		let mut reader = BufReader::new(&mut stream);
+		let mut line = String::new();
+		reader.read_line(&mut line)?;
+		let status: u16 = line
+			.split_whitespace()
+			.nth(1)
+			.and_then(|status| status.parse().ok())
+			.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "invalid ICAP response"))?;
+		let mut threat = None;
+		loop {
+			line.clear();
+			if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+				break;
+			}
+			let Some((key, value)) = line.split_once(':') else {
+				continue;
+			};
+			let value = value.trim();
+			if key.eq_ignore_ascii_case("X-Infection-Found") {
+				threat = Some(threat_name(value).unwrap_or(value).to_string());
+			} else if key.eq_ignore_ascii_case("X-Virus-ID") && threat.is_none() {
+				threat = Some(value.to_string());
+			}
+		}
+		match (status, threat) {
+			(200 | 204, Some(threat)) => Ok(Verdict::Infected(threat)),
+			(200 | 204, None) => Ok(Verdict::Clean),
+			(status, _) => Err(io::Error::other(format!(
+				"ICAP server returned status {}",
+				status
+			))),
+		}
+	}
+}
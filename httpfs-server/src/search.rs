@@ -0,0 +1,178 @@
+use std::{
+	collections::VecDeque,
+	fs::{self, File},
+	io::Read,
+	path::Path,
+};
+
+use axum::{
+	extract::Query,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{invalid_path, is_internal_name, not_found, users::User, FileInfo, ShareAccess};
+
+// 默认与最大返回结果数
+const DEFAULT_SEARCH_LIMIT: usize = 1000;
+const MAX_SEARCH_LIMIT: usize = 10_000;
+// 内容搜索只检查不超过该大小的文件
+const MAX_CONTENT_SEARCH_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+	// 文件名通配符（`*` 匹配任意字符序列，`?` 匹配单个字符），不区分大小写
+	q: String,
+	// 搜索起点目录，默认为共享根目录
+	path: Option<String>,
+	recursive: Option<bool>,
+	// 同时在文件内容中查找 q（按字面文本，不区分 ASCII 大小写）
+	content: Option<bool>,
+	limit: Option<usize>,
+	// 只返回能够打开的条目
+	accessible: Option<bool>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchHit {
+	// 相对于共享根目录的路径，使用 `/` 分隔
+	path: String,
+	#[serde(flatten)]
+	info: FileInfo,
+}
+
+// truncated 表示结果数达到 limit，还有未返回的匹配项
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+	hits: Vec<SearchHit>,
+	truncated: bool,
+}
+
+// 通配符匹配，`*` 回溯时只需记住最近一个星号的位置
+pub fn wildcard_match(pattern: &str, name: &str) -> bool {
+	let pattern: Vec<char> = pattern.to_lowercase().chars().collect();
+	let name: Vec<char> = name.to_lowercase().chars().collect();
+	let (mut p, mut n) = (0, 0);
+	let mut star: Option<(usize, usize)> = None;
+	while n < name.len() {
+		if p < pattern.len() && (pattern[p] == '?' || pattern[p] == name[n]) {
+			p += 1;
+			n += 1;
+		} else if p < pattern.len() && pattern[p] == '*' {
+			star = Some((p, n));
+			p += 1;
+		} else if let Some((star_p, star_n)) = star {
+			p = star_p + 1;
+			n = star_n + 1;
+			star = Some((star_p, star_n + 1));
+		} else {
+			return false;
+		}
+	}
+	pattern[p..].iter().all(|&c| c == '*')
+}
+
+fn content_contains(path: &Path, needle: &[u8]) -> bool {
+	if needle.is_empty() {
+		return false;
+	}
+	let mut data = Vec::new();
+	let read =
+		File::open(path).and_then(|file| file.take(MAX_CONTENT_SEARCH_SIZE).read_to_end(&mut data));
+	read.is_ok()
+		&& data
+			.windows(needle.len())
+			.any(|window| window.eq_ignore_ascii_case(needle))
+}
+
+// GET /search?q=&path=&recursive=&content=&limit= - 按文件名（及可选的内容）搜索
+pub async fn search(
+	ShareAccess(share): ShareAccess,
+	user: User,
+	Query(query): Query<SearchQuery>,
+) -> Response {
+	let start_path = query.path.as_deref().unwrap_or("$ROOT");
+	if let Err(error) = user.check_read(start_path) {
+		return error.into_response();
+	}
+	let start = match share.get_real_path(start_path) {
+		Ok(path) => path,
+		Err(status) => return invalid_path(status, start_path).into_response(),
+	};
+	if !start.is_dir() {
+		return not_found(start_path);
+	}
+	let root = share.root_path.clone();
+
+	let recursive = query.recursive.unwrap_or(false);
+	let content = query.content.unwrap_or(false);
+	let accessible_only = query.accessible.unwrap_or(false);
+	let limit = query
+		.limit
+		.unwrap_or(DEFAULT_SEARCH_LIMIT)
+		.clamp(1, MAX_SEARCH_LIMIT);
+
+	let mut hits = Vec::new();
+	let mut truncated = false;
+	let mut pending = VecDeque::from([start]);
+	'walk: while let Some(dir) = pending.pop_front() {
+		let Ok(entries) = fs::read_dir(&dir) else {
+			continue;
+		};
+		let mut entries: Vec<_> = entries.flatten().collect();
+		entries.sort_by_key(|entry| entry.file_name());
+		for entry in entries {
+			let name = entry.file_name().to_string_lossy().into_owned();
+			if is_internal_name(&name) {
+				continue;
+			}
+			let Ok(file_type) = entry.file_type() else {
+				continue;
+			};
+			let path = entry.path();
+			let relative = path
+				.strip_prefix(&root)
+				.unwrap_or(&path)
+				.components()
+				.map(|c| c.as_os_str().to_string_lossy())
+				.collect::<Vec<_>>()
+				.join("/");
+			// 用户看不到的条目既不返回也不进入，更不检查内容
+			if !user.can_see(&relative) {
+				continue;
+			}
+			// 不跟随符号链接进入目录，避免离开共享根目录或陷入循环
+			if recursive && file_type.is_dir() {
+				pending.push_back(path.clone());
+			}
+
+			let matched = wildcard_match(&query.q, &name)
+				|| (content
+					&& file_type.is_file()
+					&& user.can_read(&relative)
+					&& content_contains(&path, query.q.as_bytes()));
+			if !matched || (accessible_only && !share.is_accessible(&path)) {
+				continue;
+			}
+			if hits.len() == limit {
+				truncated = true;
+				break 'walk;
+			}
+			let Ok(info) = share.path_to_file_info(&path) else {
+				continue;
+			};
+			hits.push(SearchHit {
+				path: relative,
+				info,
+			});
+		}
+	}
+
+	eprintln!(
+		"[SERVER] search: q='{}', returning {} hits",
+		query.q,
+		hits.len()
+	);
+	Json(SearchResponse { hits, truncated }).into_response()
+}
@@ -0,0 +1,299 @@
+use std::{
+	collections::HashMap,
+	fs, io,
+	path::{Component, Path, PathBuf},
+	sync::Arc,
+	time::Duration,
+};
+
+use axum::http::StatusCode;
+use serde::Deserialize;
+
+use crate::{is_internal_name, presign::DirectReads, sparse, times, users::UserAccess, FileInfo};
+
+// 共享内符号链接的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SymlinkPolicy {
+	// 跟随符号链接，但解析后的路径必须仍位于共享根目录之内
+	#[default]
+	WithinRoot,
+	// 拒绝经过任何符号链接的路径，符号链接也不出现在目录列表中
+	Deny,
+	// 跟随符号链接，不限制它指向的位置
+	Follow,
+}
+
+// 设备名（带扩展名也一样）打开的是设备而不是共享中的文件
+const RESERVED_NAMES: [&str; 22] = [
+	"CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+	"COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+// Windows 能否以这个名称保存文件：末尾的点和空格会被去掉，与另一个名称指向同一个文件；
+// : 打开的是备用数据流；设备名和 <>"|?* 以及控制字符也不能使用
+pub fn is_storable_on_windows(name: &std::ffi::OsStr) -> bool {
+	let Some(name) = name.to_str() else {
+		return false;
+	};
+	let stem = name
+		.split('.')
+		.next()
+		.unwrap_or_default()
+		.trim_end_matches(' ');
+	!name.ends_with(['.', ' '])
+		&& !name
+			.chars()
+			.any(|c| c.is_control() || "<>:\"|?*".contains(c))
+		&& !RESERVED_NAMES
+			.iter()
+			.any(|reserved| stem.eq_ignore_ascii_case(reserved))
+}
+
+// 一个共享目录：名称、根路径、可选的访问令牌、可选的容量配额（字节）、符号链接的处理方式、
+// 持有令牌的客户端可以代表的用户和他们各自的权限、一次写入多次读取（WORM）共享的保留期限，
+// 以及内容同时保存在对象存储中时直接读取对象的签名设置
+#[derive(Debug, Clone)]
+pub struct Share {
+	pub name: String,
+	pub root_path: PathBuf,
+	pub token: Option<String>,
+	pub quota: Option<u64>,
+	pub symlinks: SymlinkPolicy,
+	pub users: HashMap<String, UserAccess>,
+	pub retention: Option<Duration>,
+	pub direct_reads: Option<Arc<DirectReads>>,
+}
+
+impl Share {
+	pub fn new(name: String, root_path: PathBuf) -> Self {
+		Self {
+			name,
+			root_path,
+			token: None,
+			quota: None,
+			symlinks: SymlinkPolicy::default(),
+			users: HashMap::new(),
+			retention: None,
+			direct_reads: None,
+		}
+	}
+
+	// 未设置令牌的共享允许匿名访问
+	pub fn authorize(&self, token: Option<&str>) -> bool {
+		match &self.token {
+			Some(expected) => token == Some(expected.as_str()),
+			None => true,
+		}
+	}
+
+	// 将客户端路径映射到共享根目录下，任何解析到根目录之外（或经过被拒绝的符号链接）的路径都返回 403，
+	// 指向服务器内部文件的路径返回 404
+	pub fn get_real_path(&self, path: &str) -> Result<PathBuf, StatusCode> {
+		let normalized = path.trim_start_matches('/');
+		// 处理根目录：如果是 "$ROOT", "." 或空字符串，返回 root_path
+		if normalized.is_empty() || normalized == "." || normalized == "$ROOT" {
+			return Ok(self.root_path.clone());
+		}
+
+		// 先做词法规范化：拒绝绝对路径、盘符以及越过根目录的 ".."
+		let mut relative = PathBuf::new();
+		for component in Path::new(normalized).components() {
+			match component {
+				// Windows 上无法原样保存的名称不能映射到别的文件或设备上
+				Component::Normal(part) if cfg!(windows) && !is_storable_on_windows(part) => {
+					return Err(StatusCode::BAD_REQUEST)
+				}
+				// 服务器内部文件（隔离区、回收站、历史版本、扩展属性文件等）不能通过路径直接访问
+				Component::Normal(part) if is_internal_name(&part.to_string_lossy()) => {
+					return Err(StatusCode::NOT_FOUND)
+				}
+				Component::Normal(part) => relative.push(part),
+				Component::CurDir => {}
+				Component::ParentDir => {
+					if !relative.pop() {
+						return Err(StatusCode::FORBIDDEN);
+					}
+				}
+				Component::RootDir | Component::Prefix(_) => return Err(StatusCode::FORBIDDEN),
+			}
+		}
+		let real_path = self.root_path.join(&relative);
+
+		match self.symlinks {
+			SymlinkPolicy::WithinRoot => {}
+			SymlinkPolicy::Follow => return Ok(real_path),
+			// 逐级检查已存在的部分，其中不能有符号链接
+			SymlinkPolicy::Deny => {
+				let mut current = self.root_path.clone();
+				for part in relative.components() {
+					current.push(part);
+					match fs::symlink_metadata(&current) {
+						Ok(metadata) if metadata.file_type().is_symlink() => {
+							return Err(StatusCode::FORBIDDEN)
+						}
+						Ok(_) => {}
+						Err(_) => break,
+					}
+				}
+				return Ok(real_path);
+			}
+		}
+
+		// 再逐级解析其中的符号链接（包括目标不存在的链接，写入时会创建其目标），
+		// 确认每一级都仍位于根目录之内
+		let root = fs::canonicalize(&self.root_path).map_err(|_| StatusCode::NOT_FOUND)?;
+		resolve_within(&root, &relative)
+			.map(|_| real_path)
+			.ok_or(StatusCode::FORBIDDEN)
+	}
+
+	// 列目录和搜索时，不允许访问的符号链接返回错误而不是其目标的信息
+	fn check_symlink(&self, path: &Path) -> io::Result<()> {
+		// 共享根目录本身由配置指定，可以是符号链接
+		if path == self.root_path || !fs::symlink_metadata(path)?.file_type().is_symlink() {
+			return Ok(());
+		}
+		let allowed = match self.symlinks {
+			SymlinkPolicy::Follow => true,
+			SymlinkPolicy::Deny => false,
+			SymlinkPolicy::WithinRoot => {
+				let root = fs::canonicalize(&self.root_path)?;
+				fs::canonicalize(path).is_ok_and(|resolved| resolved.starts_with(root))
+			}
+		};
+		if allowed {
+			Ok(())
+		} else {
+			Err(io::Error::new(
+				io::ErrorKind::PermissionDenied,
+				"symbolic link is not allowed",
+			))
+		}
+	}
+
+	// 访问权限枚举：服务器能否打开该条目（目录能列出，文件能读取），不能时不出现在列表和搜索结果中
+	pub fn is_accessible(&self, path: &Path) -> bool {
+		if self.check_symlink(path).is_err() {
+			return false;
+		}
+		if path.is_dir() {
+			fs::read_dir(path).is_ok()
+		} else {
+			fs::File::open(path).is_ok()
+		}
+	}
+
+	pub fn path_to_file_info(&self, path: &Path) -> Result<FileInfo, std::io::Error> {
+		self.check_symlink(path)?;
+		let metadata = fs::metadata(path)?;
+		let name = path
+			.file_name()
+			.and_then(|n| n.to_str())
+			.map(|s| s.to_string())
+			.unwrap_or_else(|| {
+				// 根目录使用 "." 作为名称
+				".".to_string()
+			});
+
+		Ok(FileInfo {
+			name,
+			is_directory: metadata.is_dir(),
+			size: metadata.len(),
+			created: times::created(path, &self.root_path, &metadata),
+			modified: metadata
+				.modified()
+				.ok()
+				.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+				.map(|d| d.as_secs())
+				.unwrap_or(0),
+			accessed: metadata
+				.accessed()
+				.ok()
+				.and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+				.map(|d| d.as_secs())
+				.unwrap_or(0),
+			allocated_size: Some(sparse::stored_len(path, &metadata))
+				.filter(|&stored| metadata.is_file() && stored < metadata.len()),
+		})
+	}
+}
+
+// 解析符号链接的次数上限，超过时视为链接循环
+const MAX_SYMLINK_FOLLOWS: usize = 40;
+
+// 从（已规范化的）根目录出发逐级解析 relative：符号链接以 read_link 读取目标后继续解析，
+// 目标不存在也照样检查；任何一级到达根目录之外时返回 None，否则返回解析后的路径
+fn resolve_within(root: &Path, relative: &Path) -> Option<PathBuf> {
+	let mut pending: Vec<PathBuf> = relative
+		.components()
+		.rev()
+		.map(|c| PathBuf::from(c.as_os_str()))
+		.collect();
+	let mut current = root.to_path_buf();
+	let mut follows = 0;
+	while let Some(part) = pending.pop() {
+		match Path::new(&part).components().next() {
+			Some(Component::Normal(name)) => {
+				let next = current.join(name);
+				match fs::symlink_metadata(&next) {
+					Ok(metadata) if metadata.file_type().is_symlink() => {
+						follows += 1;
+						if follows > MAX_SYMLINK_FOLLOWS {
+							return None;
+						}
+						let target = fs::read_link(&next).ok()?;
+						let target = if target.is_absolute() {
+							// 绝对路径的目标从根目录重新解析其在根目录之下的部分
+							let target = canonicalize_existing(&target)?;
+							let inside = target.strip_prefix(root).ok()?.to_path_buf();
+							current = root.to_path_buf();
+							inside
+						} else {
+							target
+						};
+						pending.extend(
+							target
+								.components()
+								.rev()
+								.map(|c| PathBuf::from(c.as_os_str())),
+						);
+					}
+					_ => current = next,
+				}
+			}
+			Some(Component::ParentDir) => {
+				if current == root {
+					return None;
+				}
+				current.pop();
+			}
+			Some(Component::CurDir) | None => {}
+			Some(Component::RootDir | Component::Prefix(_)) => return None,
+		}
+	}
+	Some(current)
+}
+
+// 规范化 path 中已存在的最深一级祖先，其余不存在的部分按词法拼接
+fn canonicalize_existing(path: &Path) -> Option<PathBuf> {
+	let mut missing = Vec::new();
+	let mut existing = path;
+	loop {
+		if let Ok(resolved) = fs::canonicalize(existing) {
+			let mut result = resolved;
+			for part in missing.iter().rev() {
+				match part {
+					Component::ParentDir => {
+						result.pop();
+					}
+					Component::Normal(name) => result.push(name),
+					_ => {}
+				}
+			}
+			return Some(result);
+		}
+		missing.push(existing.components().next_back()?);
+		existing = existing.parent()?;
+	}
+}
@@ -0,0 +1,141 @@
+use std::{
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	extract::State,
+	http::HeaderMap,
+	response::{sse::Event, IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{config, ServerState};
+
+// 开始关闭后等待进行中的请求完成的最长时间
+pub const DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+// 等待 Ctrl-C（Unix 上还有 SIGTERM），然后通知长连接结束
+pub async fn signal(state: &ServerState) {
+	#[cfg(unix)]
+	{
+		use tokio::signal::unix::{signal, SignalKind};
+
+		let mut terminate = signal(SignalKind::terminate()).ok();
+		let terminated = async {
+			match &mut terminate {
+				Some(terminate) => {
+					terminate.recv().await;
+				}
+				None => std::future::pending().await,
+			}
+		};
+		tokio::select! {
+			_ = tokio::signal::ctrl_c() => {}
+			_ = terminated => {}
+		}
+	}
+	#[cfg(not(unix))]
+	let _ = tokio::signal::ctrl_c().await;
+
+	println!("Shutting down, waiting for in-flight requests");
+	state.shutdown.send_replace(true);
+}
+
+// 管理员预告的关闭，通过 /events 推送给所有共享的订阅者，之后订阅的客户端也会收到
+#[derive(Debug, Clone)]
+pub struct ShutdownNotice {
+	shutdown_at: SystemTime,
+	message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NoticeEvent<'a> {
+	// 距离计划关闭的秒数，按发送时计算，避免依赖客户端的时钟
+	remaining_secs: u64,
+	shutdown_at: u64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	message: Option<&'a str>,
+}
+
+impl ShutdownNotice {
+	pub fn event(&self) -> Event {
+		let remaining = self
+			.shutdown_at
+			.duration_since(SystemTime::now())
+			.unwrap_or_default();
+		let data = NoticeEvent {
+			remaining_secs: remaining.as_secs(),
+			shutdown_at: unix_secs(self.shutdown_at),
+			message: self.message.as_deref(),
+		};
+		Event::default()
+			.event("shutdown_notice")
+			.json_data(data)
+			.unwrap_or_default()
+	}
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.unwrap_or_default()
+		.as_secs()
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NoticeRequest {
+	// 距离关闭的秒数，默认立即
+	#[serde(default)]
+	delay_secs: u64,
+	message: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct NoticeResponse {
+	shutdown_at: u64,
+	// 收到通知的 /events 订阅数
+	subscribers: usize,
+}
+
+// POST /admin/shutdown_notice - 预告服务器将在 delay_secs 秒后关闭，客户端据此完成写入并卸载；
+// 再次调用会替换之前的通知。服务器本身不会因此关闭
+pub async fn announce(
+	State(state): State<Arc<ServerState>>,
+	headers: HeaderMap,
+	Json(request): Json<NoticeRequest>,
+) -> Response {
+	if let Err(e) = config::require_admin(&state, &headers) {
+		return e.into_response();
+	}
+	let shutdown_at = SystemTime::now() + Duration::from_secs(request.delay_secs);
+	println!(
+		"Shutdown announced in {} seconds{}",
+		request.delay_secs,
+		request
+			.message
+			.as_deref()
+			.map(|message| format!(": {}", message))
+			.unwrap_or_default()
+	);
+	state.notice.send_replace(Some(ShutdownNotice {
+		shutdown_at,
+		message: request.message,
+	}));
+	Json(NoticeResponse {
+		shutdown_at: unix_secs(shutdown_at),
+		subscribers: state.notice.receiver_count(),
+	})
+	.into_response()
+}
+
+// 所有请求结束后保存需要跨重启保留的状态并停止监视共享
+pub fn flush(state: &ServerState) {
+	match state.uploads.save(&state.settings()) {
+		Ok(0) => {}
+		Ok(saved) => println!("Saved {} unfinished upload session(s)", saved),
+		Err(e) => eprintln!("[ERROR] saving upload sessions failed: {}", e),
+	}
+	state.watchers.lock().unwrap().clear();
+}
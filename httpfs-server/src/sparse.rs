@@ -0,0 +1,169 @@
+use std::{
+	fs::{File, Metadata},
+	io::{self, Seek, SeekFrom, Write},
+	path::Path,
+};
+
+// 不能打洞时每次写入 0 的长度
+const ZERO_CHUNK: u64 = 1024 * 1024;
+
+// 文件实际占用的存储：稀疏文件的空洞不占空间。按块分配的零头不计入，
+// 因此不会大于文件长度；无法取得时为文件长度
+pub fn stored_len(path: &Path, metadata: &Metadata) -> u64 {
+	allocated(path, metadata).min(metadata.len())
+}
+
+#[cfg(unix)]
+fn allocated(_path: &Path, metadata: &Metadata) -> u64 {
+	use std::os::unix::fs::MetadataExt;
+
+	metadata.blocks() * 512
+}
+
+// NTFS 上为稀疏或压缩后实际占用的长度
+#[cfg(windows)]
+fn allocated(path: &Path, metadata: &Metadata) -> u64 {
+	use std::os::windows::ffi::OsStrExt;
+	use winapi::um::{
+		errhandlingapi::GetLastError,
+		fileapi::{GetCompressedFileSizeW, INVALID_FILE_SIZE},
+	};
+
+	let wide: Vec<u16> = path.as_os_str().encode_wide().chain(Some(0)).collect();
+	let mut high = 0;
+	let low = unsafe { GetCompressedFileSizeW(wide.as_ptr(), &mut high) };
+	if low == INVALID_FILE_SIZE && unsafe { GetLastError() } != 0 {
+		return metadata.len();
+	}
+	(high as u64) << 32 | low as u64
+}
+
+#[cfg(not(any(unix, windows)))]
+fn allocated(_path: &Path, metadata: &Metadata) -> u64 {
+	metadata.len()
+}
+
+// 把 [offset, offset + length) 置为 0，范围超出文件末尾时扩展文件。
+// 文件标记为稀疏（Windows）后扩展出的部分不占空间，文件内的部分打洞释放空间；
+// 文件系统不支持时写入 0，结果相同只是仍然占用空间
+pub fn zero_range(file: &File, offset: u64, length: u64) -> io::Result<()> {
+	let end = offset
+		.checked_add(length)
+		.ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "the range is too large"))?;
+	let size = file.metadata()?.len();
+	let sparse = set_sparse(file)?;
+	if end > size {
+		file.set_len(end)?;
+	}
+	let hole_end = end.min(size);
+	if offset >= hole_end {
+		return Ok(());
+	}
+	if sparse && punch_hole(file, offset, hole_end - offset)? {
+		return Ok(());
+	}
+	write_zeros(file, offset, hole_end - offset)
+}
+
+// Windows 上只有标记为稀疏的文件才能打洞，FAT 等文件系统不支持时返回 false
+#[cfg(windows)]
+fn set_sparse(file: &File) -> io::Result<bool> {
+	use std::{os::windows::io::AsRawHandle, ptr::null_mut};
+	use winapi::{
+		shared::winerror::ERROR_INVALID_FUNCTION,
+		um::{ioapiset::DeviceIoControl, winioctl::FSCTL_SET_SPARSE},
+	};
+
+	let mut returned = 0;
+	let ok = unsafe {
+		DeviceIoControl(
+			file.as_raw_handle() as _,
+			FSCTL_SET_SPARSE,
+			null_mut(),
+			0,
+			null_mut(),
+			0,
+			&mut returned,
+			null_mut(),
+		)
+	};
+	if ok != 0 {
+		return Ok(true);
+	}
+	let e = io::Error::last_os_error();
+	match e.raw_os_error() {
+		Some(code) if code as u32 == ERROR_INVALID_FUNCTION => Ok(false),
+		_ => Err(e),
+	}
+}
+
+#[cfg(not(windows))]
+fn set_sparse(_file: &File) -> io::Result<bool> {
+	Ok(true)
+}
+
+#[cfg(windows)]
+fn punch_hole(file: &File, offset: u64, length: u64) -> io::Result<bool> {
+	use std::{os::windows::io::AsRawHandle, ptr::null_mut};
+	use winapi::um::{ioapiset::DeviceIoControl, winioctl::FSCTL_SET_ZERO_DATA};
+
+	// FILE_ZERO_DATA_INFORMATION：起始偏移和结束偏移（不含）
+	let range = [offset as i64, (offset + length) as i64];
+	let mut returned = 0;
+	let ok = unsafe {
+		DeviceIoControl(
+			file.as_raw_handle() as _,
+			FSCTL_SET_ZERO_DATA,
+			range.as_ptr() as _,
+			16,
+			null_mut(),
+			0,
+			&mut returned,
+			null_mut(),
+		)
+	};
+	match ok {
+		0 => Err(io::Error::last_os_error()),
+		_ => Ok(true),
+	}
+}
+
+// 文件长度不变；文件系统不支持打洞（EOPNOTSUPP）时返回 false
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, offset: u64, length: u64) -> io::Result<bool> {
+	use std::os::unix::io::AsRawFd;
+
+	let result = unsafe {
+		libc::fallocate(
+			file.as_raw_fd(),
+			libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+			offset as libc::off_t,
+			length as libc::off_t,
+		)
+	};
+	if result == 0 {
+		return Ok(true);
+	}
+	let e = io::Error::last_os_error();
+	match e.raw_os_error() {
+		Some(libc::EOPNOTSUPP) => Ok(false),
+		_ => Err(e),
+	}
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn punch_hole(_file: &File, _offset: u64, _length: u64) -> io::Result<bool> {
+	Ok(false)
+}
+
+fn write_zeros(mut file: &File, offset: u64, length: u64) -> io::Result<()> {
+	let zeros = vec![0; ZERO_CHUNK.min(length) as usize];
+	file.seek(SeekFrom::Start(offset))?;
+	let mut remaining = length;
+	while remaining > 0 {
+		let chunk = remaining.min(ZERO_CHUNK);
+		file.write_all(&zeros[..chunk as usize])?;
+		remaining -= chunk;
+	}
+	Ok(())
+}
@@ -0,0 +1,146 @@
+use std::{
+	fs::{self, FileTimes, Metadata, OpenOptions},
+	io,
+	path::Path,
+	sync::Arc,
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Deserialize;
+
+use crate::{
+	audit::{Action, Actor},
+	error::ApiError,
+	retention, xattr, ServerState, Target,
+};
+
+fn to_secs(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+fn from_secs(secs: u64) -> SystemTime {
+	UNIX_EPOCH + Duration::from_secs(secs)
+}
+
+// 文件的创建时间（秒）：优先使用服务器记录的值，其次是文件系统提供的值，
+// 两者都没有时（例如部分 Linux 文件系统）使用修改时间
+pub fn created(path: &Path, root: &Path, metadata: &Metadata) -> u64 {
+	xattr::created(path, root)
+		.or_else(|| metadata.created().ok().map(to_secs))
+		.unwrap_or_else(|| metadata.modified().map(to_secs).unwrap_or(0))
+}
+
+// 替换文件内容前取得的创建时间
+pub fn created_before_replace(path: &Path, root: &Path) -> Option<u64> {
+	let metadata = fs::metadata(path).ok()?;
+	Some(created(path, root, &metadata))
+}
+
+// 设置创建时间：Windows 上直接写入文件系统；文件系统上的值与之不同（无法设置或不支持）时记录在属性文件中
+pub fn keep_created(path: &Path, root: &Path, created: u64) -> io::Result<()> {
+	#[cfg(windows)]
+	{
+		use std::os::windows::fs::FileTimesExt;
+
+		let times = FileTimes::new().set_created(from_secs(created));
+		let _ = open_for_times(path).and_then(|file| file.set_times(times));
+	}
+	let on_disk = fs::metadata(path)?.created().ok().map(to_secs);
+	xattr::set_created(path, root, (on_disk != Some(created)).then_some(created))
+}
+
+// 新建的文件或目录：文件系统不提供创建时间时记录当前时间，之后的写入不会改变它
+pub fn record_created(path: &Path, root: &Path) {
+	let Ok(metadata) = fs::metadata(path) else {
+		return;
+	};
+	if metadata.created().is_err() {
+		let now = to_secs(SystemTime::now());
+		if let Err(e) = xattr::set_created(path, root, Some(now)) {
+			eprintln!(
+				"[SERVER] recording creation time of {:?} failed: {:?}",
+				path, e
+			);
+		}
+	}
+}
+
+// 只为修改时间戳打开文件或目录
+fn open_for_times(path: &Path) -> io::Result<fs::File> {
+	let mut options = OpenOptions::new();
+	#[cfg(windows)]
+	{
+		use std::os::windows::fs::OpenOptionsExt;
+
+		// FILE_WRITE_ATTRIBUTES；FILE_FLAG_BACKUP_SEMANTICS 用于打开目录
+		options.access_mode(0x100).custom_flags(0x0200_0000);
+	}
+	#[cfg(not(windows))]
+	options.read(true);
+	options.open(path)
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimesRequest {
+	created: Option<u64>,
+	accessed: Option<u64>,
+	modified: Option<u64>,
+}
+
+impl TimesRequest {
+	pub fn is_empty(&self) -> bool {
+		self.created.is_none() && self.accessed.is_none() && self.modified.is_none()
+	}
+
+	// 设置请求中给出的时间，/batch 中的文件提交后也这样设置
+	pub fn apply(&self, real_path: &Path, root: &Path) -> Result<(), ApiError> {
+		if self.accessed.is_some() || self.modified.is_some() {
+			let mut times = FileTimes::new();
+			if let Some(accessed) = self.accessed {
+				times = times.set_accessed(from_secs(accessed));
+			}
+			if let Some(modified) = self.modified {
+				times = times.set_modified(from_secs(modified));
+			}
+			open_for_times(real_path)
+				.and_then(|file| file.set_times(times))
+				.map_err(|e| ApiError::io("setting times failed", &e))?;
+		} else {
+			fs::symlink_metadata(real_path).map_err(|e| ApiError::io("stat failed", &e))?;
+		}
+
+		if let Some(created) = self.created {
+			keep_created(real_path, root, created)
+				.map_err(|e| ApiError::io("setting creation time failed", &e))?;
+		}
+		Ok(())
+	}
+}
+
+// POST /times/:path - 设置创建、访问和修改时间（秒），未给出的时间保持不变
+pub async fn set_times(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	Json(req): Json<TimesRequest>,
+) -> Response {
+	// 已提交的文件不能修改时间，保留期限从修改时间起计算时借此缩短期限
+	if let Err(error) = retention::check(&target.share, &target.path, &target.real_path) {
+		return error.into_response();
+	}
+	match req.apply(&target.real_path, &target.share.root_path) {
+		Ok(_) => {
+			state.audit.record(&actor, Action::SetTimes, &target.path, None);
+			StatusCode::OK.into_response()
+		}
+		Err(error) => error.into_response(),
+	}
+}
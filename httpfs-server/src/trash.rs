@@ -0,0 +1,246 @@
+use std::{
+	fs, io,
+	path::{Component, Path, PathBuf},
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc,
+	},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	extract::State,
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	already_exists,
+	audit::{Action, Actor},
+	error::ApiError,
+	invalid_path, quota,
+	share::Share,
+	users::User,
+	xattr, ServerState, ShareAccess,
+};
+
+// 回收站目录位于共享根目录下，每个被删除的条目占用其中一个子目录：
+// info.json 记录原路径和删除时间，data 是被移入的文件或目录本身
+pub const TRASH_DIR: &str = ".httpfs-trash";
+const INFO_FILE: &str = "info.json";
+const DATA_NAME: &str = "data";
+
+pub fn is_trash_name(name: &str) -> bool {
+	name == TRASH_DIR
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TrashEntry {
+	id: String,
+	// 删除前相对共享根目录的路径
+	path: String,
+	is_directory: bool,
+	size: u64,
+	deleted: u64,
+}
+
+fn now_secs() -> u64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_secs())
+		.unwrap_or(0)
+}
+
+fn new_entry_id() -> String {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	let nanos = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|d| d.as_nanos())
+		.unwrap_or(0);
+	format!("{}-{}", nanos, COUNTER.fetch_add(1, Ordering::Relaxed))
+}
+
+fn relative_path(path: &Path, root: &Path) -> String {
+	path.strip_prefix(root)
+		.unwrap_or(path)
+		.components()
+		.map(|c| c.as_os_str().to_string_lossy())
+		.collect::<Vec<_>>()
+		.join("/")
+}
+
+// 把文件或目录（连同它的扩展属性）移入回收站，代替直接删除
+pub fn move_to_trash(path: &Path, root: &Path) -> io::Result<()> {
+	let metadata = fs::symlink_metadata(path)?;
+	let entry = TrashEntry {
+		id: new_entry_id(),
+		path: relative_path(path, root),
+		is_directory: metadata.is_dir(),
+		size: if metadata.is_dir() {
+			quota::usage(path).unwrap_or(0)
+		} else {
+			metadata.len()
+		},
+		deleted: now_secs(),
+	};
+	let entry_dir = root.join(TRASH_DIR).join(&entry.id);
+	fs::create_dir_all(&entry_dir)?;
+	let data = entry_dir.join(DATA_NAME);
+	let result = serde_json::to_vec(&entry)
+		.map_err(io::Error::other)
+		.and_then(|info| fs::write(entry_dir.join(INFO_FILE), info))
+		.and_then(|_| fs::rename(path, &data));
+	if let Err(e) = result {
+		let _ = fs::remove_dir_all(&entry_dir);
+		return Err(e);
+	}
+	xattr::move_for(path, &data, root);
+	Ok(())
+}
+
+fn load_entry(entry_dir: &Path) -> io::Result<TrashEntry> {
+	let info = fs::read(entry_dir.join(INFO_FILE))?;
+	let mut entry: TrashEntry = serde_json::from_slice(&info).map_err(io::Error::other)?;
+	// 条目 ID 以目录名为准
+	entry.id = entry_dir
+		.file_name()
+		.map(|n| n.to_string_lossy().into_owned())
+		.unwrap_or_default();
+	Ok(entry)
+}
+
+// 列出回收站中的条目，最近删除的在前
+fn list(root: &Path) -> io::Result<Vec<TrashEntry>> {
+	let entries = match fs::read_dir(root.join(TRASH_DIR)) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+	let mut items: Vec<TrashEntry> = entries
+		.flatten()
+		.filter_map(|entry| load_entry(&entry.path()).ok())
+		.collect();
+	items.sort_by(|a, b| b.deleted.cmp(&a.deleted).then_with(|| b.id.cmp(&a.id)));
+	Ok(items)
+}
+
+// 清除超过保留期限的条目；没有 info.json 的目录可能正在写入，保留不动
+pub fn purge(root: &Path, retention: Duration) {
+	let Ok(items) = list(root) else {
+		return;
+	};
+	let now = now_secs();
+	for item in items {
+		if item.deleted.saturating_add(retention.as_secs()) <= now {
+			let _ = fs::remove_dir_all(root.join(TRASH_DIR).join(&item.id));
+		}
+	}
+}
+
+// GET /trash/list - 列出回收站中的条目
+pub async fn list_trash(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+) -> Response {
+	if let Some(retention) = state.settings().trash_retention {
+		purge(&share.root_path, retention);
+	}
+	match list(&share.root_path) {
+		// 用户只能看到从自己可以访问的路径删除的条目
+		Ok(mut items) => {
+			items.retain(|item| user.can_read(&item.path));
+			Json(items).into_response()
+		}
+		Err(e) => ApiError::io("reading trash failed", &e).into_response(),
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct RestoreRequest {
+	id: String,
+	// 恢复到的路径，默认恢复到原路径
+	path: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RestoreResponse {
+	path: String,
+}
+
+fn entry_not_found(id: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"trash_entry_not_found",
+		format!("trash entry '{}' does not exist", id),
+	)
+}
+
+fn entry_dir(share: &Share, id: &str) -> Result<PathBuf, ApiError> {
+	// ID 只能是回收站下的一级目录名
+	let mut components = Path::new(id).components();
+	match (components.next(), components.next()) {
+		(Some(Component::Normal(_)), None) => {}
+		_ => return Err(entry_not_found(id)),
+	}
+	let dir = share.root_path.join(TRASH_DIR).join(id);
+	if !dir.join(INFO_FILE).is_file() {
+		return Err(entry_not_found(id));
+	}
+	Ok(dir)
+}
+
+// POST /trash/restore - 把回收站中的条目移回原路径（或指定的路径）
+pub async fn restore_trash(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	actor: Actor,
+	Json(req): Json<RestoreRequest>,
+) -> Response {
+	let dir = match entry_dir(&share, &req.id) {
+		Ok(dir) => dir,
+		Err(error) => return error.into_response(),
+	};
+	let entry = match load_entry(&dir) {
+		Ok(entry) => entry,
+		Err(e) => return ApiError::io("reading trash entry failed", &e).into_response(),
+	};
+	if let Err(error) = user.check_write(&entry.path) {
+		return error.into_response();
+	}
+	let path = req.path.unwrap_or(entry.path);
+	if let Err(error) = user.check_write(&path) {
+		return error.into_response();
+	}
+	let target = match share.get_real_path(&path) {
+		Ok(target) => target,
+		Err(status) => return invalid_path(status, &path).into_response(),
+	};
+	if target == share.root_path {
+		return invalid_path(StatusCode::FORBIDDEN, &path).into_response();
+	}
+	if fs::symlink_metadata(&target).is_ok() {
+		return already_exists(&path);
+	}
+	// 回收站不计入配额，恢复的内容重新占用配额
+	if let Err(error) = quota::check(&share, 0, entry.size) {
+		return error.into_response();
+	}
+
+	if let Some(parent) = target.parent() {
+		if let Err(e) = fs::create_dir_all(parent) {
+			return ApiError::io("create_dir failed", &e).into_response();
+		}
+	}
+	let data = dir.join(DATA_NAME);
+	if let Err(e) = fs::rename(&data, &target) {
+		return ApiError::io("restore failed", &e).into_response();
+	}
+	xattr::move_for(&data, &target, &share.root_path);
+	let _ = fs::remove_dir_all(&dir);
+	state.audit.record(&actor, Action::Restore, &path, None);
+	Json(RestoreResponse { path }).into_response()
+}
@@ -0,0 +1,522 @@
+use std::{
+	collections::HashMap,
+	fs::{self, File, OpenOptions},
+	io::{self, Read, Seek, SeekFrom, Write},
+	path::PathBuf,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	body::Bytes,
+	extract::{Path as AxumPath, Query, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::{
+	atomic::temp_path_for,
+	audit::{Action, Actor},
+	checksum::sha256_file,
+	error::ApiError,
+	invalid_path, is_a_directory, keep_created, quota, retention, save_version, scan, times,
+	users::User,
+	ServerState, Settings, ShareAccess,
+};
+
+// 超过该时间没有任何活动的上传会话会被清理
+const SESSION_IDLE_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+// 服务器退出时未完成的上传会话记录在共享根目录下的该文件中，重启后恢复
+const JOURNAL_NAME: &str = ".httpfs-uploads.json";
+
+pub fn is_journal_name(name: &str) -> bool {
+	name == JOURNAL_NAME
+}
+
+// 一个可续传的分块上传会话：数据写入目标目录下的临时文件，提交时校验后 rename
+#[derive(Serialize, Deserialize)]
+struct UploadSession {
+	share: String,
+	// 相对共享根目录的目标路径，用于审计日志；旧版本的会话记录中没有
+	#[serde(default)]
+	path: String,
+	target: PathBuf,
+	temp_path: PathBuf,
+	size: u64,
+	// 已接收的字节区间 [start, end)，按起点排序且互不重叠
+	received: Vec<(u64, u64)>,
+	// 恢复的会话从重启时开始计算闲置时间
+	#[serde(skip, default = "Instant::now")]
+	last_active: Instant,
+}
+
+impl UploadSession {
+	fn mark_received(&mut self, start: u64, end: u64) {
+		self.received.push((start, end));
+		self.received.sort_unstable();
+		let mut merged: Vec<(u64, u64)> = Vec::with_capacity(self.received.len());
+		for &(s, e) in &self.received {
+			match merged.last_mut() {
+				Some(last) if s <= last.1 => last.1 = last.1.max(e),
+				_ => merged.push((s, e)),
+			}
+		}
+		self.received = merged;
+	}
+
+	fn is_complete(&self) -> bool {
+		self.size == 0 || self.received == [(0, self.size)]
+	}
+}
+
+#[derive(Default)]
+pub struct UploadRegistry {
+	sessions: Mutex<HashMap<String, Arc<Mutex<UploadSession>>>>,
+}
+
+impl UploadRegistry {
+	fn insert(&self, id: String, session: UploadSession) {
+		let mut sessions = self.sessions.lock().unwrap();
+		// 顺便清理闲置过久的会话
+		sessions.retain(|_, s| {
+			let s = s.lock().unwrap();
+			let alive = s.last_active.elapsed() < SESSION_IDLE_TIMEOUT;
+			if !alive {
+				let _ = fs::remove_file(&s.temp_path);
+			}
+			alive
+		});
+		sessions.insert(id, Arc::new(Mutex::new(session)));
+	}
+
+	// 会话只能被创建它的共享访问
+	fn get(&self, id: &str, share: &str) -> Result<Arc<Mutex<UploadSession>>, ApiError> {
+		let not_found = || {
+			ApiError::new(
+				StatusCode::NOT_FOUND,
+				"session_not_found",
+				format!("upload session '{}' does not exist", id),
+			)
+		};
+		let session = self
+			.sessions
+			.lock()
+			.unwrap()
+			.get(id)
+			.cloned()
+			.ok_or_else(not_found)?;
+		if session.lock().unwrap().share != share {
+			return Err(not_found());
+		}
+		Ok(session)
+	}
+
+	pub fn count(&self) -> usize {
+		self.sessions.lock().unwrap().len()
+	}
+
+	fn remove(&self, id: &str) {
+		self.sessions.lock().unwrap().remove(id);
+	}
+
+	// 把未完成的会话按共享写入各自根目录下的记录文件，返回保存的会话数
+	pub fn save(&self, settings: &Settings) -> io::Result<usize> {
+		let sessions = self.sessions.lock().unwrap();
+		let mut saved = 0;
+		for share in settings.shares.values() {
+			let guards: Vec<_> = sessions
+				.iter()
+				.map(|(id, session)| (id, session.lock().unwrap()))
+				.filter(|(_, session)| session.share == share.name)
+				.collect();
+			if guards.is_empty() {
+				continue;
+			}
+			let journal: HashMap<&String, &UploadSession> = guards
+				.iter()
+				.map(|(id, session)| (*id, &**session))
+				.collect();
+			let data = serde_json::to_vec(&journal).map_err(io::Error::other)?;
+			fs::write(share.root_path.join(JOURNAL_NAME), data)?;
+			saved += journal.len();
+		}
+		Ok(saved)
+	}
+
+	// 读取并删除各共享的记录文件，恢复临时文件仍然存在的会话，返回恢复的会话数
+	pub fn restore(&self, settings: &Settings) -> usize {
+		let mut restored = 0;
+		for share in settings.shares.values() {
+			let path = share.root_path.join(JOURNAL_NAME);
+			let Ok(data) = fs::read(&path) else {
+				continue;
+			};
+			let _ = fs::remove_file(&path);
+			let journal: HashMap<String, UploadSession> = match serde_json::from_slice(&data) {
+				Ok(journal) => journal,
+				Err(e) => {
+					eprintln!("[SERVER] ignoring corrupt upload journal {:?}: {}", path, e);
+					continue;
+				}
+			};
+			let mut sessions = self.sessions.lock().unwrap();
+			for (id, session) in journal {
+				if session.share == share.name && session.temp_path.is_file() {
+					sessions.insert(id, Arc::new(Mutex::new(session)));
+					restored += 1;
+				}
+			}
+		}
+		restored
+	}
+}
+
+pub fn new_session_id() -> String {
+	static COUNTER: AtomicU64 = AtomicU64::new(0);
+	let mut hasher = Sha256::new();
+	hasher.update(std::process::id().to_le_bytes());
+	hasher.update(
+		SystemTime::now()
+			.duration_since(UNIX_EPOCH)
+			.map(|d| d.as_nanos())
+			.unwrap_or(0)
+			.to_le_bytes(),
+	);
+	hasher.update(COUNTER.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+	hex::encode(&hasher.finalize()[..16])
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StartRequest {
+	path: String,
+	size: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StartResponse {
+	session: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+	size: u64,
+	received: Vec<(u64, u64)>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChunkQuery {
+	offset: u64,
+	sha256: Option<String>,
+}
+
+// 从目标文件的 source 处复制 length 字节到上传内容的 offset 处
+#[derive(Debug, Deserialize)]
+pub struct CopyRange {
+	source: u64,
+	offset: u64,
+	length: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CopyRequest {
+	ranges: Vec<CopyRange>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CommitRequest {
+	sha256: Option<String>,
+}
+
+fn checksum_mismatch(what: &str) -> Response {
+	ApiError::new(
+		StatusCode::UNPROCESSABLE_ENTITY,
+		"checksum_mismatch",
+		format!("{} sha256 does not match", what),
+	)
+	.into_response()
+}
+
+// POST /upload/start - 创建上传会话
+pub async fn start_upload(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	user: User,
+	Json(req): Json<StartRequest>,
+) -> Response {
+	if let Err(error) = user.check_write(&req.path) {
+		return error.into_response();
+	}
+	let target = match share.get_real_path(&req.path) {
+		Ok(path) => path,
+		Err(status) => return invalid_path(status, &req.path).into_response(),
+	};
+	if target.is_dir() {
+		return is_a_directory(&req.path);
+	}
+	// 提交时还会再检查一次，这里先避免上传注定被拒绝的内容
+	if let Err(error) = retention::check(&share, &req.path, &target) {
+		return error.into_response();
+	}
+	// 临时文件按声明的大小预先分配，进行中的上传也计入共享用量
+	let old_size = fs::metadata(&target).map_or(0, |m| m.len());
+	if let Err(error) = quota::check(&share, old_size, req.size) {
+		return error.into_response();
+	}
+
+	let temp_path = temp_path_for(&target);
+	if let Err(e) = File::create(&temp_path).and_then(|f| f.set_len(req.size)) {
+		eprintln!("[SERVER] start_upload: failed to create temp file: {:?}", e);
+		return ApiError::io("creating temp file failed", &e).into_response();
+	}
+
+	let id = new_session_id();
+	state.uploads.insert(
+		id.clone(),
+		UploadSession {
+			share: share.name.clone(),
+			path: req.path.clone(),
+			target,
+			temp_path,
+			size: req.size,
+			received: Vec::new(),
+			last_active: Instant::now(),
+		},
+	);
+	(StatusCode::CREATED, Json(StartResponse { session: id })).into_response()
+}
+
+// GET /upload/:session - 查询已接收的区间，用于断点续传
+pub async fn upload_status(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+) -> Response {
+	let session = match state.uploads.get(&params["session"], &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+	let session = session.lock().unwrap();
+	Json(StatusResponse {
+		size: session.size,
+		received: session.received.clone(),
+	})
+	.into_response()
+}
+
+// PUT /upload/:session/chunk - 写入一个分块，可附带 sha256 校验
+pub async fn upload_chunk(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Query(query): Query<ChunkQuery>,
+	body: Bytes,
+) -> Response {
+	let session = match state.uploads.get(&params["session"], &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+
+	if let Some(expected) = &query.sha256 {
+		if !hex::encode(Sha256::digest(&body)).eq_ignore_ascii_case(expected) {
+			return checksum_mismatch("chunk");
+		}
+	}
+
+	let mut session = session.lock().unwrap();
+	let end = query.offset + body.len() as u64;
+	if end > session.size {
+		return ApiError::new(
+			StatusCode::RANGE_NOT_SATISFIABLE,
+			"range_not_satisfiable",
+			format!(
+				"chunk ends at {} beyond the declared size {}",
+				end, session.size
+			),
+		)
+		.into_response();
+	}
+
+	let result = OpenOptions::new()
+		.write(true)
+		.open(&session.temp_path)
+		.and_then(|mut file| {
+			file.seek(SeekFrom::Start(query.offset))?;
+			file.write_all(&body)
+		});
+	match result {
+		Ok(_) => {
+			session.mark_received(query.offset, end);
+			session.last_active = Instant::now();
+			state.metrics.add_written(body.len());
+			StatusCode::OK.into_response()
+		}
+		Err(e) => {
+			eprintln!("[SERVER] upload_chunk: write failed: {:?}", e);
+			ApiError::io("write failed", &e).into_response()
+		}
+	}
+}
+
+// POST /upload/:session/copy - 把目标文件现有内容中没有变化的部分复制到上传内容中，
+// 客户端按 /signature 比较后只需上传其余部分；复制时目标文件已变化会在提交时的 sha256 校验中发现
+pub async fn copy_ranges(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Json(req): Json<CopyRequest>,
+) -> Response {
+	let session = match state.uploads.get(&params["session"], &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+	let mut session = session.lock().unwrap();
+	if let Some(range) = req.ranges.iter().find(|r| r.offset + r.length > session.size) {
+		return ApiError::new(
+			StatusCode::RANGE_NOT_SATISFIABLE,
+			"range_not_satisfiable",
+			format!(
+				"copy ends at {} beyond the declared size {}",
+				range.offset + range.length,
+				session.size
+			),
+		)
+		.into_response();
+	}
+
+	let result = (|| {
+		let mut source = File::open(&session.target)?;
+		let mut temp = OpenOptions::new().write(true).open(&session.temp_path)?;
+		let source_size = source.metadata()?.len();
+		for range in &req.ranges {
+			if range.source + range.length > source_size {
+				return Err(io::Error::new(
+					io::ErrorKind::InvalidInput,
+					format!(
+						"copy source ends at {} beyond the file size {}",
+						range.source + range.length,
+						source_size
+					),
+				));
+			}
+			source.seek(SeekFrom::Start(range.source))?;
+			temp.seek(SeekFrom::Start(range.offset))?;
+			io::copy(&mut (&mut source).take(range.length), &mut temp)?;
+		}
+		Ok(())
+	})();
+	match result {
+		Ok(_) => {
+			for range in &req.ranges {
+				session.mark_received(range.offset, range.offset + range.length);
+			}
+			session.last_active = Instant::now();
+			StatusCode::OK.into_response()
+		}
+		Err(e) => {
+			eprintln!("[SERVER] copy_ranges: copy failed: {:?}", e);
+			ApiError::io("copy failed", &e).into_response()
+		}
+	}
+}
+
+// POST /upload/:session/commit - 校验完整性后原子替换目标文件
+pub async fn commit_upload(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	actor: Actor,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+	Json(req): Json<CommitRequest>,
+) -> Response {
+	let id = &params["session"];
+	let session = match state.uploads.get(id, &share.name) {
+		Ok(session) => session,
+		Err(error) => return error.into_response(),
+	};
+	// 先取得目标文件的锁，等待期间不能持有会话的锁
+	let target = session.lock().unwrap().target.clone();
+	let _guard = state.locks.lock(&target).await;
+	let session = session.lock().unwrap();
+
+	// 错误体中附带已接收的区间，客户端据此补传
+	if !session.is_complete() {
+		return ApiError::new(
+			StatusCode::CONFLICT,
+			"upload_incomplete",
+			"some chunks have not been received",
+		)
+		.with_details(StatusResponse {
+			size: session.size,
+			received: session.received.clone(),
+		})
+		.into_response();
+	}
+
+	if let Some(expected) = &req.sha256 {
+		match sha256_file(&session.temp_path) {
+			Ok(actual) if actual.eq_ignore_ascii_case(expected) => {}
+			Ok(_) => {
+				// 内容已损坏，只能整体重传
+				let _ = fs::remove_file(&session.temp_path);
+				state.uploads.remove(id);
+				return checksum_mismatch("file");
+			}
+			Err(e) => return ApiError::io("hashing failed", &e).into_response(),
+		}
+	}
+
+	if let Err(error) = retention::check(&share, &session.path, &session.target) {
+		return error.into_response();
+	}
+	if let Err(error) = scan::check_file(&state, &share, &session.path, &session.temp_path) {
+		// 检出威胁时放弃会话（隔离时临时文件已被移走）；扫描器不可用时保留，客户端可以稍后重试提交
+		if error.status() == StatusCode::FORBIDDEN {
+			let _ = fs::remove_file(&session.temp_path);
+			state.uploads.remove(id);
+		}
+		return error.into_response();
+	}
+
+	save_version(&state, &session.target, &share.root_path);
+	let created = times::created_before_replace(&session.target, &share.root_path);
+	let result = File::open(&session.temp_path)
+		.and_then(|f| f.sync_all())
+		.and_then(|_| fs::rename(&session.temp_path, &session.target));
+	state.uploads.remove(id);
+	match result {
+		Ok(_) => {
+			keep_created(&session.target, &share.root_path, created);
+			retention::commit(&share, &session.target);
+			state.audit.record(&actor, Action::Write, &session.path, None);
+			StatusCode::OK.into_response()
+		}
+		Err(e) => {
+			eprintln!("[SERVER] commit_upload: rename failed: {:?}", e);
+			let _ = fs::remove_file(&session.temp_path);
+			ApiError::io("rename failed", &e).into_response()
+		}
+	}
+}
+
+// DELETE /upload/:session - 放弃上传会话
+pub async fn abort_upload(
+	State(state): State<Arc<ServerState>>,
+	ShareAccess(share): ShareAccess,
+	AxumPath(params): AxumPath<HashMap<String, String>>,
+) -> Response {
+	let id = &params["session"];
+	match state.uploads.get(id, &share.name) {
+		Ok(session) => {
+			let _ = fs::remove_file(&session.lock().unwrap().temp_path);
+			state.uploads.remove(id);
+			StatusCode::OK.into_response()
+		}
+		Err(error) => error.into_response(),
+	}
+}
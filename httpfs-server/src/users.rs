@@ -0,0 +1,182 @@
+use std::{
+	path::{Component, Path},
+	sync::Arc,
+};
+
+use axum::{
+	async_trait,
+	extract::FromRequestParts,
+	http::{request::Parts, StatusCode},
+	response::{IntoResponse, Response},
+};
+use serde::Deserialize;
+
+use crate::{error::ApiError, ServerState, ShareAccess};
+
+// 客户端代表的用户（例如多用户终端服务器上发起请求的 Windows 用户映射成的名称）
+pub const USER_HEADER: &str = "x-httpfs-user";
+
+// 共享中一个用户的权限
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct UserAccess {
+	// 只能读取
+	#[serde(default)]
+	pub read_only: bool,
+	// 可以访问的目录或文件（相对共享根目录），为空时可以访问整个共享
+	#[serde(default)]
+	pub paths: Vec<String>,
+}
+
+// 共享内路径的统一写法：与 Share::get_real_path 一样按组成部分展开 "." 和 ".."，
+// 以 / 分隔，根目录为空字符串；越过根目录的 ".." 停在根目录，这样的路径随后会被 get_real_path 拒绝
+fn normalize(path: &str) -> String {
+	let path = path.trim_matches('/');
+	if path == "$ROOT" {
+		return String::new();
+	}
+	let mut parts = Vec::new();
+	for component in Path::new(path).components() {
+		match component {
+			Component::Normal(part) => parts.push(part.to_string_lossy()),
+			Component::ParentDir => {
+				parts.pop();
+			}
+			Component::CurDir | Component::RootDir | Component::Prefix(_) => {}
+		}
+	}
+	parts.join("/")
+}
+
+// 目录中条目的路径
+pub fn child_path(dir: &str, name: &str) -> String {
+	match normalize(dir).as_str() {
+		"" => name.to_string(),
+		dir => format!("{}/{}", dir, name),
+	}
+}
+
+// path 是否为 dir 或在 dir 之下
+fn is_within(path: &str, dir: &str) -> bool {
+	dir.is_empty()
+		|| path == dir
+		|| path
+			.strip_prefix(dir)
+			.is_some_and(|rest| rest.starts_with('/'))
+}
+
+impl UserAccess {
+	pub fn can_read(&self, path: &str) -> bool {
+		let path = normalize(path);
+		self.paths.is_empty()
+			|| self
+				.paths
+				.iter()
+				.any(|allowed| is_within(&path, &normalize(allowed)))
+	}
+
+	// 可以访问的路径及通往它们的目录出现在列表中
+	pub fn can_see(&self, path: &str) -> bool {
+		let path = normalize(path);
+		self.can_read(&path)
+			|| self
+				.paths
+				.iter()
+				.any(|allowed| is_within(&normalize(allowed), &path))
+	}
+
+	pub fn can_write(&self, path: &str) -> bool {
+		!self.read_only && self.can_read(path)
+	}
+}
+
+// 请求代表的用户及其权限；没有给出用户或共享没有配置用户时为 None，按令牌本身的权限访问整个共享
+pub struct User(pub Option<(String, UserAccess)>);
+
+impl User {
+	pub fn can_see(&self, path: &str) -> bool {
+		self.0.as_ref().is_none_or(|(_, access)| access.can_see(path))
+	}
+
+	pub fn can_read(&self, path: &str) -> bool {
+		self.0.as_ref().is_none_or(|(_, access)| access.can_read(path))
+	}
+
+	pub fn check_write(&self, path: &str) -> Result<(), ApiError> {
+		match &self.0 {
+			Some((name, access)) if !access.can_write(path) => {
+				Err(access_denied(name, path))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	// 读取只要求能看到路径：列出通往可访问路径的目录、查询它们的信息
+	pub fn check_read(&self, path: &str) -> Result<(), ApiError> {
+		match &self.0 {
+			Some((name, access)) if !access.can_see(path) => {
+				Err(access_denied(name, path))
+			}
+			_ => Ok(()),
+		}
+	}
+
+	// 不针对某个路径的修改（上传内容分块等）要求用户可以写入
+	pub fn check_writer(&self) -> Result<(), ApiError> {
+		match &self.0 {
+			Some((name, access)) if access.read_only => Err(access_denied(name, "")),
+			_ => Ok(()),
+		}
+	}
+
+	// 影响整个共享的操作只允许令牌本身
+	pub fn check_unrestricted(&self) -> Result<(), ApiError> {
+		match &self.0 {
+			Some((name, _)) => Err(ApiError::new(
+				StatusCode::FORBIDDEN,
+				"access_denied",
+				format!("user '{}' may not perform this operation", name),
+			)),
+			None => Ok(()),
+		}
+	}
+}
+
+fn access_denied(user: &str, path: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::FORBIDDEN,
+		"access_denied",
+		format!("user '{}' has no access to '{}'", user, normalize(path)),
+	)
+}
+
+#[async_trait]
+impl FromRequestParts<Arc<ServerState>> for User {
+	type Rejection = Response;
+
+	async fn from_request_parts(
+		parts: &mut Parts,
+		state: &Arc<ServerState>,
+	) -> Result<Self, Self::Rejection> {
+		let ShareAccess(share) = ShareAccess::from_request_parts(parts, state).await?;
+		let Some(name) = parts.headers.get(USER_HEADER) else {
+			return Ok(User(None));
+		};
+		if share.users.is_empty() {
+			return Ok(User(None));
+		}
+		let name = name.to_str().unwrap_or_default();
+		match share.users.get(name) {
+			Some(access) => Ok(User(Some((name.to_string(), access.clone())))),
+			None => Err(ApiError::new(
+				StatusCode::FORBIDDEN,
+				"unknown_user",
+				format!(
+					"user '{}' is not configured for share '{}'",
+					name, share.name
+				),
+			)
+			.into_response()),
+		}
+	}
+}
@@ -0,0 +1,175 @@
+use std::{
+	fs::{self, File},
+	io,
+	path::{Component, Path, PathBuf},
+	sync::atomic::{AtomicU32, Ordering},
+	time::{SystemTime, UNIX_EPOCH},
+};
+
+use axum::{
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::Serialize;
+
+use crate::{error::ApiError, not_found, Target};
+
+// 历史版本保存在共享根目录下的镜像目录中：文件 a/b.txt 的版本位于
+// .httpfs-versions/a/b.txt/ 下，每个版本一个文件，文件名即版本 ID
+// 客户端只能通过 /versions 和 ?version= 访问，不能按路径读写这个目录
+pub const VERSIONS_DIR: &str = ".httpfs-versions";
+
+pub fn is_versions_name(name: &str) -> bool {
+	name == VERSIONS_DIR
+}
+
+fn versions_dir(path: &Path, root: &Path) -> Option<PathBuf> {
+	let relative = path.strip_prefix(root).ok()?;
+	if relative.as_os_str().is_empty() {
+		return None;
+	}
+	Some(root.join(VERSIONS_DIR).join(relative))
+}
+
+// 版本 ID 由保存时间生成，按字符串排序即按时间排序
+fn new_version_id() -> String {
+	static COUNTER: AtomicU32 = AtomicU32::new(0);
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default();
+	format!(
+		"{}.{:09}.{:04}",
+		now.as_secs(),
+		now.subsec_nanos(),
+		COUNTER.fetch_add(1, Ordering::Relaxed) % 10000
+	)
+}
+
+// 在文件被覆盖或截断之前保存一份副本，只保留最新的 keep 个版本。
+// keep 为 0 或文件不存在时什么都不做
+pub fn snapshot(path: &Path, root: &Path, keep: usize) -> io::Result<()> {
+	if keep == 0 {
+		return Ok(());
+	}
+	let metadata = match fs::symlink_metadata(path) {
+		Ok(metadata) if metadata.is_file() => metadata,
+		Ok(_) => return Ok(()),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+		Err(e) => return Err(e),
+	};
+	let Some(dir) = versions_dir(path, root) else {
+		return Ok(());
+	};
+	fs::create_dir_all(&dir)?;
+	let version = dir.join(new_version_id());
+	fs::copy(path, &version)?;
+	// 版本的修改时间保持为被覆盖前的修改时间
+	if let Ok(modified) = metadata.modified() {
+		let _ = File::options()
+			.write(true)
+			.open(&version)
+			.and_then(|f| f.set_modified(modified));
+	}
+
+	let mut ids = version_ids(&dir)?;
+	if ids.len() > keep {
+		ids.sort_unstable();
+		for id in &ids[..ids.len() - keep] {
+			let _ = fs::remove_file(dir.join(id));
+		}
+	}
+	Ok(())
+}
+
+// 文件移动后让历史版本跟随移动；目标上原有的版本被丢弃
+pub fn move_for(old_path: &Path, new_path: &Path, root: &Path) {
+	let (Some(old_dir), Some(new_dir)) =
+		(versions_dir(old_path, root), versions_dir(new_path, root))
+	else {
+		return;
+	};
+	if !old_dir.is_dir() {
+		return;
+	}
+	let _ = fs::remove_dir_all(&new_dir);
+	if let Some(parent) = new_dir.parent() {
+		let _ = fs::create_dir_all(parent);
+	}
+	let _ = fs::rename(old_dir, new_dir);
+}
+
+// 版本目录中只有普通文件是版本，子目录属于同名路径下的其他文件
+fn version_ids(dir: &Path) -> io::Result<Vec<String>> {
+	let entries = match fs::read_dir(dir) {
+		Ok(entries) => entries,
+		Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+		Err(e) => return Err(e),
+	};
+	Ok(entries
+		.flatten()
+		.filter(|entry| entry.file_type().is_ok_and(|t| t.is_file()))
+		.map(|entry| entry.file_name().to_string_lossy().into_owned())
+		.collect())
+}
+
+fn version_not_found(id: &str) -> ApiError {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"version_not_found",
+		format!("version '{}' does not exist", id),
+	)
+}
+
+// 指定版本的存放路径，ID 只能是版本目录下的一级文件名
+pub fn version_path(path: &Path, root: &Path, id: &str) -> Result<PathBuf, ApiError> {
+	let mut components = Path::new(id).components();
+	let (Some(Component::Normal(_)), None) = (components.next(), components.next()) else {
+		return Err(version_not_found(id));
+	};
+	let version = versions_dir(path, root)
+		.map(|dir| dir.join(id))
+		.filter(|version| version.is_file())
+		.ok_or_else(|| version_not_found(id))?;
+	Ok(version)
+}
+
+#[derive(Debug, Serialize)]
+pub struct VersionInfo {
+	id: String,
+	size: u64,
+	modified: u64,
+}
+
+// GET /versions/:path - 列出文件的历史版本，最新的在前
+pub async fn list_versions(target: Target) -> Response {
+	let Some(dir) = versions_dir(&target.real_path, &target.share.root_path) else {
+		return Json(Vec::<VersionInfo>::new()).into_response();
+	};
+	let mut ids = match version_ids(&dir) {
+		Ok(ids) => ids,
+		Err(e) => return ApiError::io("reading versions failed", &e).into_response(),
+	};
+	// 文件已被删除时仍然可以列出它的版本
+	if ids.is_empty() && !target.real_path.exists() {
+		return not_found(&target.path);
+	}
+	ids.sort_unstable_by(|a, b| b.cmp(a));
+	let versions: Vec<VersionInfo> = ids
+		.into_iter()
+		.filter_map(|id| {
+			let metadata = fs::metadata(dir.join(&id)).ok()?;
+			Some(VersionInfo {
+				id,
+				size: metadata.len(),
+				modified: metadata
+					.modified()
+					.ok()
+					.and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+					.map(|d| d.as_secs())
+					.unwrap_or(0),
+			})
+		})
+		.collect();
+	Json(versions).into_response()
+}
@@ -0,0 +1,292 @@
+use std::{
+	collections::BTreeMap,
+	fs, io,
+	path::{Path, PathBuf},
+	sync::Arc,
+};
+
+use axum::{
+	body::Bytes,
+	extract::{Query, State},
+	http::StatusCode,
+	response::{IntoResponse, Response},
+	Json,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+	atomic::write_atomic,
+	audit::{Action, Actor},
+	error::ApiError,
+	retention, ServerState, Target,
+};
+
+// 扩展属性保存在同目录下的 `.{name}.httpfs-xattr` 文件中（JSON，值为十六进制），列目录时隐藏，
+// 也不能按路径访问
+const SIDECAR_SUFFIX: &str = ".httpfs-xattr";
+
+// 服务器记录的创建时间和保留期限也保存在属性文件中。名称含有冒号，不会与客户端（备用数据流名称）的属性冲突，
+// 也不会出现在属性列表中
+const CREATED_KEY: &str = "httpfs:created";
+const RETAIN_KEY: &str = "httpfs:retain_until";
+
+const MAX_NAME_LEN: usize = 255;
+const MAX_VALUE_SIZE: usize = 64 * 1024;
+
+pub fn is_sidecar_name(name: &str) -> bool {
+	name.starts_with('.') && name.ends_with(SIDECAR_SUFFIX)
+}
+
+// 共享根目录没有父目录可以存放属性文件，返回 None
+fn sidecar_path(path: &Path, root: &Path) -> Option<PathBuf> {
+	if path == root {
+		return None;
+	}
+	let name = path.file_name()?.to_string_lossy();
+	Some(path.with_file_name(format!(".{}{}", name, SIDECAR_SUFFIX)))
+}
+
+fn load(sidecar: &Path) -> io::Result<BTreeMap<String, String>> {
+	match fs::read(sidecar) {
+		Ok(data) => serde_json::from_slice(&data).map_err(io::Error::other),
+		Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(BTreeMap::new()),
+		Err(e) => Err(e),
+	}
+}
+
+fn store(sidecar: &Path, attrs: &BTreeMap<String, String>) -> io::Result<()> {
+	if attrs.is_empty() {
+		return match fs::remove_file(sidecar) {
+			Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+			_ => Ok(()),
+		};
+	}
+	write_atomic(
+		sidecar,
+		&serde_json::to_vec(attrs).map_err(io::Error::other)?,
+	)
+}
+
+// 服务器自己记录的属性名称含有冒号，客户端不能设置，也不会看到
+fn is_internal_key(name: &str) -> bool {
+	name.contains(':')
+}
+
+fn get_u64(path: &Path, root: &Path, key: &str) -> Option<u64> {
+	let attrs = load(&sidecar_path(path, root)?).ok()?;
+	let value = hex::decode(attrs.get(key)?).ok()?;
+	Some(u64::from_le_bytes(value.try_into().ok()?))
+}
+
+// None 时清除记录；共享根目录没有属性文件，不记录
+fn set_u64(path: &Path, root: &Path, key: &str, value: Option<u64>) -> io::Result<()> {
+	let Some(sidecar) = sidecar_path(path, root) else {
+		return Ok(());
+	};
+	let mut attrs = load(&sidecar)?;
+	let value = value.map(|value| hex::encode(value.to_le_bytes()));
+	if attrs.get(key) == value.as_ref() {
+		return Ok(());
+	}
+	match value {
+		Some(value) => attrs.insert(key.to_string(), value),
+		None => attrs.remove(key),
+	};
+	store(&sidecar, &attrs)
+}
+
+// 服务器记录的创建时间（秒）
+pub fn created(path: &Path, root: &Path) -> Option<u64> {
+	get_u64(path, root, CREATED_KEY)
+}
+
+// 记录创建时间，None 时清除记录
+pub fn set_created(path: &Path, root: &Path, created: Option<u64>) -> io::Result<()> {
+	set_u64(path, root, CREATED_KEY, created)
+}
+
+// WORM 共享中记录的保留期限（Unix 秒）
+pub fn retained_until(path: &Path, root: &Path) -> Option<u64> {
+	get_u64(path, root, RETAIN_KEY)
+}
+
+pub fn set_retained_until(path: &Path, root: &Path, until: u64) -> io::Result<()> {
+	set_u64(path, root, RETAIN_KEY, Some(until))
+}
+
+// 删除文件或目录后一并删除它的属性文件
+pub fn remove_for(path: &Path, root: &Path) {
+	if let Some(sidecar) = sidecar_path(path, root) {
+		let _ = fs::remove_file(sidecar);
+	}
+}
+
+// 移动文件或目录后让属性跟随移动；源没有属性时清除目标上残留的属性
+pub fn move_for(old_path: &Path, new_path: &Path, root: &Path) {
+	let (Some(old_sidecar), Some(new_sidecar)) =
+		(sidecar_path(old_path, root), sidecar_path(new_path, root))
+	else {
+		return;
+	};
+	if old_sidecar.exists() {
+		let _ = fs::rename(old_sidecar, new_sidecar);
+	} else {
+		let _ = fs::remove_file(new_sidecar);
+	}
+}
+
+#[derive(Debug, Deserialize)]
+pub struct XattrQuery {
+	name: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct XattrEntry {
+	name: String,
+	size: usize,
+}
+
+// 校验目标存在且可以拥有扩展属性，返回属性文件路径
+fn target_sidecar(target: &Target) -> Result<PathBuf, ApiError> {
+	if let Err(e) = fs::symlink_metadata(&target.real_path) {
+		return Err(ApiError::io("stat failed", &e));
+	}
+	sidecar_path(&target.real_path, &target.share.root_path).ok_or_else(|| {
+		ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"share_root",
+			"the share root cannot have extended attributes",
+		)
+	})
+}
+
+fn attr_name(query: XattrQuery) -> Result<String, ApiError> {
+	match query.name {
+		Some(name) if !name.is_empty() && name.len() <= MAX_NAME_LEN && !is_internal_key(&name) => {
+			Ok(name)
+		}
+		_ => Err(ApiError::new(
+			StatusCode::BAD_REQUEST,
+			"invalid_name",
+			format!(
+				"attribute name must be 1 to {} bytes without ':'",
+				MAX_NAME_LEN
+			),
+		)),
+	}
+}
+
+fn attr_not_found(name: &str) -> Response {
+	ApiError::new(
+		StatusCode::NOT_FOUND,
+		"xattr_not_found",
+		format!("attribute '{}' does not exist", name),
+	)
+	.into_response()
+}
+
+// GET /xattr/:path - 不带 name 时列出属性名及大小，带 ?name= 时返回属性值
+pub async fn get_xattr(target: Target, Query(query): Query<XattrQuery>) -> Response {
+	let sidecar = match target_sidecar(&target) {
+		Ok(sidecar) => sidecar,
+		Err(error) => return error.into_response(),
+	};
+	let attrs = match load(&sidecar) {
+		Ok(attrs) => attrs,
+		Err(e) => {
+			eprintln!("[SERVER] get_xattr: failed to load {:?}: {:?}", sidecar, e);
+			return ApiError::io("loading attributes failed", &e).into_response();
+		}
+	};
+
+	let Some(name) = query.name else {
+		let entries: Vec<XattrEntry> = attrs
+			.iter()
+			.filter(|(name, _)| !is_internal_key(name))
+			.map(|(name, value)| XattrEntry {
+				name: name.clone(),
+				size: value.len() / 2,
+			})
+			.collect();
+		return Json(entries).into_response();
+	};
+	match attrs
+		.get(&name)
+		.filter(|_| !is_internal_key(&name))
+		.and_then(|value| hex::decode(value).ok())
+	{
+		Some(value) => value.into_response(),
+		None => attr_not_found(&name),
+	}
+}
+
+// PUT /xattr/:path?name= - 设置属性值（请求体为原始字节）
+pub async fn put_xattr(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	Query(query): Query<XattrQuery>,
+	body: Bytes,
+) -> Response {
+	let checked = retention::check(&target.share, &target.path, &target.real_path)
+		.and_then(|()| target_sidecar(&target))
+		.and_then(|s| Ok((s, attr_name(query)?)));
+	let (sidecar, name) = match checked {
+		Ok(result) => result,
+		Err(error) => return error.into_response(),
+	};
+	if body.len() > MAX_VALUE_SIZE {
+		return ApiError::new(
+			StatusCode::PAYLOAD_TOO_LARGE,
+			"payload_too_large",
+			format!("attribute values are limited to {} bytes", MAX_VALUE_SIZE),
+		)
+		.into_response();
+	}
+
+	let result = load(&sidecar).and_then(|mut attrs| {
+		attrs.insert(name, hex::encode(&body));
+		store(&sidecar, &attrs)
+	});
+	match result {
+		Ok(_) => {
+			state.audit.record(&actor, Action::SetXattr, &target.path, None);
+			StatusCode::OK.into_response()
+		}
+		Err(e) => {
+			eprintln!("[SERVER] put_xattr: failed to store {:?}: {:?}", sidecar, e);
+			ApiError::io("storing attributes failed", &e).into_response()
+		}
+	}
+}
+
+// DELETE /xattr/:path?name= - 删除属性
+pub async fn delete_xattr(
+	State(state): State<Arc<ServerState>>,
+	target: Target,
+	actor: Actor,
+	Query(query): Query<XattrQuery>,
+) -> Response {
+	let checked = retention::check(&target.share, &target.path, &target.real_path)
+		.and_then(|()| target_sidecar(&target))
+		.and_then(|s| Ok((s, attr_name(query)?)));
+	let (sidecar, name) = match checked {
+		Ok(result) => result,
+		Err(error) => return error.into_response(),
+	};
+
+	let mut attrs = match load(&sidecar) {
+		Ok(attrs) => attrs,
+		Err(e) => return ApiError::io("loading attributes failed", &e).into_response(),
+	};
+	if attrs.remove(&name).is_none() {
+		return attr_not_found(&name);
+	}
+	match store(&sidecar, &attrs) {
+		Ok(_) => {
+			state.audit.record(&actor, Action::RemoveXattr, &target.path, None);
+			StatusCode::OK.into_response()
+		}
+		Err(e) => ApiError::io("storing attributes failed", &e).into_response(),
+	}
+}